@@ -0,0 +1,56 @@
+; Everything a file declares. Each match captures the whole declaration as `@definition.<kind>`
+; and the name being declared as `@name`.
+
+(namespace_definition
+  name: (namespace_name) @name) @definition.namespace
+
+; `namespace { ... }` is the global namespace
+(namespace_definition
+  !name
+  body: (compound_statement)) @definition.namespace
+
+(class_declaration
+  name: (name) @name) @definition.class
+
+(interface_declaration
+  name: (name) @name) @definition.interface
+
+(trait_declaration
+  name: (name) @name) @definition.trait
+
+(enum_declaration
+  name: (name) @name) @definition.enum
+
+(function_definition
+  name: (name) @name) @definition.function
+
+(method_declaration
+  name: (name) @name) @definition.method
+
+(property_element
+  name: (variable_name) @name) @definition.property
+
+(const_element
+  .
+  (name) @name) @definition.constant
+
+(enum_case
+  name: (name) @name) @definition.enum_case
+
+; only the parameters of named functions, not those of closures inside them
+
+(function_definition
+  parameters: (formal_parameters
+    [
+      (simple_parameter name: (variable_name) @name)
+      (variadic_parameter name: (variable_name) @name)
+      (property_promotion_parameter name: (variable_name) @name)
+    ] @definition.parameter))
+
+(method_declaration
+  parameters: (formal_parameters
+    [
+      (simple_parameter name: (variable_name) @name)
+      (variadic_parameter name: (variable_name) @name)
+      (property_promotion_parameter name: (variable_name) @name)
+    ] @definition.parameter))
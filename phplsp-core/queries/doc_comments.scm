@@ -0,0 +1,19 @@
+; Doc comments, captured as `@doc`, directly in front of the declaration they document,
+; captured as `@definition`.
+
+(
+  (comment) @doc
+  .
+  [
+    (class_declaration)
+    (interface_declaration)
+    (trait_declaration)
+    (enum_declaration)
+    (function_definition)
+    (method_declaration)
+    (property_declaration)
+    (const_declaration)
+    (enum_case)
+  ] @definition
+  (#match? @doc "^/\\*\\*")
+)
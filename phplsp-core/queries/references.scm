@@ -0,0 +1,108 @@
+; Names that refer to something declared elsewhere, captured as `@reference.<kind>`. When a name
+; matches more than one pattern, the first pattern wins.
+;
+; `use` statements are captured as `@import`, since they change how later names are resolved.
+; Doc comments are captured as `@doc`, since their tags can name things too.
+
+(namespace_use_declaration) @import
+
+((comment) @doc
+  (#match? @doc "^/\\*\\*"))
+
+; classes
+
+(named_type
+  [(name) (qualified_name)] @reference.class)
+
+(object_creation_expression
+  [(name) (qualified_name)] @reference.class)
+
+(base_clause
+  [(name) (qualified_name)] @reference.class)
+
+(class_interface_clause
+  [(name) (qualified_name)] @reference.class)
+
+(use_declaration
+  [(name) (qualified_name)] @reference.class)
+
+(binary_expression
+  operator: "instanceof"
+  right: [(name) (qualified_name)] @reference.class)
+
+(scoped_call_expression
+  scope: [(name) (qualified_name)] @reference.class)
+
+(scoped_property_access_expression
+  scope: [(name) (qualified_name)] @reference.class)
+
+(class_constant_access_expression
+  .
+  [(name) (qualified_name)] @reference.class)
+
+; members
+
+(member_call_expression
+  name: (name) @reference.member)
+
+(member_access_expression
+  name: (name) @reference.member)
+
+(nullsafe_member_call_expression
+  name: (name) @reference.member)
+
+(nullsafe_member_access_expression
+  name: (name) @reference.member)
+
+(scoped_call_expression
+  name: (name) @reference.member)
+
+(scoped_property_access_expression
+  name: (variable_name) @reference.member)
+
+((class_constant_access_expression
+  .
+  (_)
+  .
+  (name) @reference.member)
+  (#not-eq? @reference.member "class"))
+
+; functions
+
+(function_call_expression
+  function: [(name) (qualified_name)] @reference.function)
+
+; constants, where a bare name can't be anything else
+
+(argument
+  [(name) (qualified_name)] @reference.constant)
+
+(array_element_initializer
+  [(name) (qualified_name)] @reference.constant)
+
+(assignment_expression
+  [(name) (qualified_name)] @reference.constant)
+
+(binary_expression
+  [(name) (qualified_name)] @reference.constant)
+
+(conditional_expression
+  [(name) (qualified_name)] @reference.constant)
+
+(echo_statement
+  [(name) (qualified_name)] @reference.constant)
+
+(expression_statement
+  [(name) (qualified_name)] @reference.constant)
+
+(match_condition_list
+  [(name) (qualified_name)] @reference.constant)
+
+(parenthesized_expression
+  [(name) (qualified_name)] @reference.constant)
+
+(return_statement
+  [(name) (qualified_name)] @reference.constant)
+
+(unary_op_expression
+  [(name) (qualified_name)] @reference.constant)
@@ -0,0 +1,114 @@
+use lsp_types::{Diagnostic, DocumentSymbol, Range};
+
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::diagnostics::diagnostics;
+use crate::document_symbols::document_symbols;
+use crate::file::{php_parser, Snapshot};
+use crate::symbols::{file_symbols, Declaration, Reference};
+
+/**
+ * Everything we know about a single file, without looking at any other file.
+ *
+ * The output only depends on the source and the configuration: everything is sorted by where it
+ * starts in the file, so that it can be compared between runs and checked in as a golden file.
+ */
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalysisResult {
+    pub declarations: Vec<Declaration>,
+    pub references: Vec<Reference>,
+    /// Namespaces, class-likes and functions, nested the way they are in the file.
+    pub scopes: Vec<DocumentSymbol>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+fn range_key(range: &Range) -> (u32, u32, u32, u32) {
+    (
+        range.start.line,
+        range.start.character,
+        range.end.line,
+        range.end.character,
+    )
+}
+
+fn sort_scopes(scopes: &mut [DocumentSymbol]) {
+    scopes.sort_by(|a, b| {
+        range_key(&a.range)
+            .cmp(&range_key(&b.range))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    for scope in scopes {
+        if let Some(children) = &mut scope.children {
+            sort_scopes(children);
+        }
+    }
+}
+
+/// Analyze the source of one PHP file.
+pub fn analyze_file(source: &str, config: &Config) -> AnalysisResult {
+    let snapshot =
+        Snapshot::parse(&mut php_parser(), source).expect("parsing without a timeout never fails");
+    let root_node = snapshot.root_node();
+
+    let symbols = file_symbols(&root_node, source);
+    let mut declarations = symbols.declarations;
+    declarations.sort_by(|a, b| {
+        range_key(&a.range)
+            .cmp(&range_key(&b.range))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    let mut references = symbols.references;
+    references.sort_by(|a, b| {
+        range_key(&a.range)
+            .cmp(&range_key(&b.range))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+
+    let mut scopes = document_symbols(&root_node, source);
+    sort_scopes(&mut scopes);
+
+    let mut diagnostics = diagnostics(&root_node, source, config);
+    diagnostics.sort_by(|a, b| {
+        range_key(&a.range)
+            .cmp(&range_key(&b.range))
+            .then_with(|| a.message.cmp(&b.message))
+    });
+
+    AnalysisResult {
+        declarations,
+        references,
+        scopes,
+        diagnostics,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::analyze_file;
+    use crate::config::Config;
+
+    #[test]
+    fn test_deterministic() {
+        let source = "<?php
+namespace App;
+
+class A {
+    public function b(): B { return new B(); }
+}
+";
+        let config = Config::default();
+        let first = analyze_file(source, &config);
+        assert_eq!(first, analyze_file(source, &config));
+        assert_eq!(
+            vec!["App\\A", "App\\A::b"],
+            first
+                .declarations
+                .iter()
+                .map(|declaration| declaration.name.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!("App", first.scopes[0].name);
+    }
+}
@@ -0,0 +1,171 @@
+//! What users tell us about their workspace in `.phplsp/overrides.json`, for what we can't work
+//! out ourselves: the types of symbols that depend on how they're called, and diagnostics that
+//! some files should go without.
+//!
+//! ```json
+//! {
+//!     "types": {
+//!         "App\\Container::get": "class-of-argument:0",
+//!         "App\\Order::$items": "App\\Item[]"
+//!     },
+//!     "ignore": {
+//!         "src/Legacy/*": ["null-access", "property-type"],
+//!         "tests/fixtures/*": ["*"]
+//!     },
+//!     "rules": {
+//!         "dead-store": "off",
+//!         "null-access": "error"
+//!     }
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::config::RuleSetting;
+use crate::unused_api::matches;
+
+/// Where the annotations of a workspace folder are, relative to it.
+pub const ANNOTATIONS_FILE: &str = ".phplsp/overrides.json";
+
+/// A type that a symbol is pinned to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PinnedType<'a> {
+    /// A type as it's written in PHP, with fully qualified class names.
+    Written(&'a str),
+    /// An object of the class that an argument names, like `User::class` or `'App\User'`, by
+    /// the argument's position.
+    ArgumentClass(usize),
+}
+
+/// The annotations of a workspace folder.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Annotations {
+    /// The types that functions and methods return and that properties have, by their fully
+    /// qualified names, as a type or as `class-of-argument:N`.
+    pub types: HashMap<String, String>,
+    /// Codes of diagnostics not to report in files matching a pattern of paths relative to the
+    /// workspace folder, where `*` is anything and a code of `*` is every diagnostic.
+    pub ignore: HashMap<String, Vec<String>>,
+    /// What the diagnostics of rules are reported as in the workspace folder, by their code, or
+    /// `off` not to report them, over what the client's settings say.
+    pub rules: HashMap<String, RuleSetting>,
+    /// The workspace folder that the annotations are for.
+    #[serde(skip)]
+    pub root: PathBuf,
+}
+
+impl Annotations {
+    /// Parse the annotations of a workspace folder.
+    pub fn parse(root: &Path, contents: &str) -> serde_json::Result<Self> {
+        let annotations: Self = serde_json::from_str(contents)?;
+        Ok(Self {
+            root: root.to_path_buf(),
+            ..annotations
+        })
+    }
+
+    /// Read the annotations of a workspace folder, if it has any.
+    pub fn load(root: &Path) -> Result<Option<Self>, String> {
+        let path = root.join(ANNOTATIONS_FILE);
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("could not read `{}`: {}", path.display(), e)),
+        };
+        Self::parse(root, &contents)
+            .map(Some)
+            .map_err(|e| format!("invalid `{}`: {}", path.display(), e))
+    }
+
+    /// The type that a function, method or property is pinned to, if any.
+    pub fn pinned_type(&self, name: &str) -> Option<PinnedType<'_>> {
+        let name = name.trim_start_matches('\\');
+        let (_, written) = self
+            .types
+            .iter()
+            .find(|(pinned, _)| pinned.trim_start_matches('\\').eq_ignore_ascii_case(name))?;
+        let written = written.trim();
+        match written.strip_prefix("class-of-argument:") {
+            Some(position) => position.trim().parse().ok().map(PinnedType::ArgumentClass),
+            None => Some(PinnedType::Written(written)),
+        }
+    }
+
+    /// What the diagnostics with a code are reported as in a file, if it's in the workspace folder.
+    pub fn rule_setting(&self, path: &Path, code: &str) -> Option<RuleSetting> {
+        path.starts_with(&self.root)
+            .then(|| self.rules.get(code).copied())
+            .flatten()
+    }
+
+    /// Whether diagnostics with a code aren't to be reported in a file of the workspace folder.
+    pub fn is_ignored(&self, path: &Path, code: &str) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return false;
+        };
+        let relative = relative.to_string_lossy().replace('\\', "/");
+        self.ignore.iter().any(|(pattern, codes)| {
+            matches(pattern.trim_start_matches("./"), &relative)
+                && codes
+                    .iter()
+                    .any(|ignored| ignored == "*" || ignored == code)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::{Annotations, PinnedType};
+    use crate::config::RuleSetting;
+
+    #[test]
+    fn test_annotations() {
+        let annotations = Annotations::parse(
+            Path::new("/project"),
+            r#"{
+                "types": {
+                    "\\App\\Container::get": "class-of-argument:0",
+                    "App\\Order::$items": "App\\Item[]"
+                },
+                "ignore": {
+                    "src/Legacy/*": ["null-access"],
+                    "./tests/fixtures/*": ["*"]
+                },
+                "rules": { "dead-store": "off" }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            Some(PinnedType::ArgumentClass(0)),
+            annotations.pinned_type("App\\container::GET")
+        );
+        assert_eq!(
+            Some(PinnedType::Written("App\\Item[]")),
+            annotations.pinned_type("App\\Order::$items")
+        );
+        assert_eq!(None, annotations.pinned_type("App\\Order::total"));
+
+        let ignored = |path: &str, code: &str| annotations.is_ignored(Path::new(path), code);
+        assert!(ignored("/project/src/Legacy/Old/User.php", "null-access"));
+        assert!(!ignored("/project/src/Legacy/User.php", "property-type"));
+        assert!(!ignored("/project/src/User.php", "null-access"));
+        assert!(ignored(
+            "/project/tests/fixtures/broken.php",
+            "syntax-error"
+        ));
+        assert!(!ignored("/other/src/Legacy/User.php", "null-access"));
+
+        let setting = |path: &str| annotations.rule_setting(Path::new(path), "dead-store");
+        assert_eq!(Some(RuleSetting::Off), setting("/project/src/User.php"));
+        assert_eq!(None, setting("/other/src/User.php"));
+    }
+}
@@ -0,0 +1,481 @@
+//! Calls that pass fewer arguments than their callee requires, more than it takes, or arguments
+//! of types that its parameters don't take.
+
+use lsp_types::*;
+use tree_sitter::Node;
+
+use crate::file::to_range;
+use crate::index::WorkspaceIndex;
+use crate::null_access::is_guarded;
+use crate::resolve::Resolver;
+use crate::return_types::{is_checked, is_known};
+use crate::symbols::{Declaration, FileSymbols};
+use crate::types::{Type, TypeInference};
+
+const CALLS: &[&str] = &[
+    "function_call_expression",
+    "member_call_expression",
+    "nullsafe_member_call_expression",
+    "scoped_call_expression",
+    "object_creation_expression",
+];
+
+fn plural(count: usize) -> String {
+    match count {
+        1 => "1 argument".to_string(),
+        count => format!("{} arguments", count),
+    }
+}
+
+/// Scalars, which PHP converts into each other when passed or assigned, unless the file has
+/// `strict_types`.
+const SCALARS: &[Type] = &[Type::Int, Type::Float, Type::String, Type::Bool];
+
+fn diagnostic(
+    range: Range,
+    code: &str,
+    severity: DiagnosticSeverity,
+    message: String,
+) -> Diagnostic {
+    Diagnostic {
+        range,
+        severity: Some(severity),
+        code: Some(NumberOrString::String(code.to_string())),
+        source: Some("phplsp".to_string()),
+        message,
+        ..Diagnostic::default()
+    }
+}
+
+/// The `arguments` of a call, which constructor calls don't have a field for.
+fn arguments<'tree>(call: &Node<'tree>) -> Option<Node<'tree>> {
+    if let Some(arguments) = call.child_by_field_name("arguments") {
+        return Some(arguments);
+    }
+    let mut cursor = call.walk();
+    let arguments = call
+        .named_children(&mut cursor)
+        .find(|child| child.kind() == "arguments");
+    arguments
+}
+
+fn check_call(call: &Node, file_contents: &str, callee: &Declaration) -> Option<Diagnostic> {
+    let (required, most) = callee.arity();
+    let arguments = arguments(call);
+    let mut positional = vec![];
+    let mut named = vec![];
+    if let Some(arguments) = arguments {
+        let mut cursor = arguments.walk();
+        for argument in arguments.named_children(&mut cursor) {
+            // spreading and `f(...)` pass what we can't count
+            if argument.kind() == "variadic_placeholder"
+                || argument
+                    .named_child(0)
+                    .is_some_and(|value| value.kind() == "variadic_unpacking")
+            {
+                return None;
+            }
+            match argument.child_by_field_name("name") {
+                Some(name) => named.push(&file_contents[name.byte_range()]),
+                None => positional.push(argument),
+            }
+        }
+    }
+
+    let name = match call.kind() {
+        "object_creation_expression" => callee.class_name().unwrap_or_default(),
+        _ => callee.short_name(),
+    };
+    let name = name.rsplit('\\').next().unwrap_or(name);
+    let at = arguments.unwrap_or(*call);
+    if positional.len() < required {
+        let parameters = callee.parameters();
+        let missing = parameters
+            .iter()
+            .take(required)
+            .skip(positional.len())
+            .find(|parameter| !named.contains(&parameter.trim_start_matches('$')))?;
+        let message = match named.is_empty() {
+            true => format!(
+                "`{}` takes at least {}, but is given {}",
+                name,
+                plural(required),
+                positional.len()
+            ),
+            false => format!("`{}` is called without `{}`", name, missing),
+        };
+        return Some(diagnostic(
+            to_range(&at.range()),
+            "argument-count",
+            DiagnosticSeverity::ERROR,
+            message,
+        ));
+    }
+
+    let most = most?;
+    let (first, last) = (positional.get(most)?, positional.last()?);
+    Some(diagnostic(
+        Range::new(to_range(&first.range()).start, to_range(&last.range()).end),
+        "argument-count",
+        DiagnosticSeverity::WARNING,
+        format!(
+            "`{}` takes at most {}, but is given {}",
+            name,
+            plural(most),
+            positional.len()
+        ),
+    ))
+}
+
+fn calls<'tree>(node: &Node<'tree>, found: &mut Vec<Node<'tree>>) {
+    if CALLS.contains(&node.kind()) {
+        found.push(*node);
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        calls(&child, found);
+    }
+}
+
+/**
+ * Whether a value of one type can be passed or assigned where another is expected. Unless the
+ * file is `strict`, scalars go where other scalars are expected, since PHP converts them.
+ */
+pub(crate) fn accepts(types: &TypeInference, expected: &Type, actual: &Type, strict: bool) -> bool {
+    if types.accepts(expected, actual) {
+        return true;
+    }
+    let members = match expected {
+        Type::Union(members) => members.as_slice(),
+        ty => std::slice::from_ref(ty),
+    };
+    let coerced = !strict && members.iter().any(|member| SCALARS.contains(member));
+    coerced
+        && types.accepts(
+            &Type::union(members.iter().cloned().chain(SCALARS.iter().cloned())),
+            actual,
+        )
+}
+
+/// Whether a file has `declare(strict_types=1)`, so that scalars aren't converted when passed.
+pub(crate) fn is_strict(root_node: &Node, file_contents: &str) -> bool {
+    let mut cursor = root_node.walk();
+    let strict = root_node
+        .named_children(&mut cursor)
+        .filter(|statement| statement.kind() == "declare_statement")
+        .any(|statement| {
+            let mut cursor = statement.walk();
+            let strict = statement
+                .named_children(&mut cursor)
+                .filter(|directive| directive.kind() == "declare_directive")
+                .any(|directive| {
+                    let text: String = file_contents[directive.byte_range()]
+                        .split_whitespace()
+                        .collect();
+                    text.eq_ignore_ascii_case("strict_types=1")
+                });
+            strict
+        });
+    strict
+}
+
+struct ArgumentChecker<'a> {
+    contents: &'a str,
+    index: &'a WorkspaceIndex,
+    types: TypeInference<'a>,
+    strict: bool,
+}
+
+impl ArgumentChecker<'_> {
+    /// The type that a parameter takes, if it's one we can check arguments against.
+    fn parameter_type(&self, callee: &Declaration, parameter: usize) -> Option<Type> {
+        let written = callee.parameter_type(parameter)?;
+        if !is_checked(written) || callee.is_by_reference(parameter) {
+            return None;
+        }
+        let ty = Type::parse(written, callee.namespace(), callee.class_name())?;
+        if !is_known(self.index, &ty) {
+            return None;
+        }
+        // a default of `null` makes a parameter take `null`
+        let takes_null = callee
+            .parameter_default(parameter)
+            .is_some_and(|default| default.eq_ignore_ascii_case("null"));
+        Some(match takes_null {
+            true => Type::union([ty, Type::Null]),
+            false => ty,
+        })
+    }
+
+    fn check_call(&self, call: &Node, callee: &Declaration, diagnostics: &mut Vec<Diagnostic>) {
+        let Some(arguments) = arguments(call) else {
+            return;
+        };
+        let parameters = callee.parameters();
+        let variadic = parameters
+            .last()
+            .is_some_and(|last| last.starts_with("..."));
+        let mut cursor = arguments.walk();
+        let positional = arguments
+            .named_children(&mut cursor)
+            .filter(|argument| argument.kind() == "argument");
+        for (i, argument) in positional.enumerate() {
+            let Some(value) = argument.named_child(argument.named_child_count().saturating_sub(1))
+            else {
+                continue;
+            };
+            if value.kind() == "variadic_unpacking" {
+                return;
+            }
+            let index = match argument.child_by_field_name("name") {
+                Some(name) => {
+                    let name = &self.contents[name.byte_range()];
+                    parameters.iter().position(|parameter| {
+                        parameter.trim_start_matches("...").trim_start_matches('$') == name
+                    })
+                }
+                None if variadic => Some(i.min(parameters.len() - 1)),
+                None => Some(i),
+            };
+            let Some(index) = index else {
+                continue;
+            };
+            let (Some(expected), Some(mut actual)) = (
+                self.parameter_type(callee, index),
+                self.types
+                    .expression(&value)
+                    .filter(|actual| is_known(self.index, actual)),
+            ) else {
+                continue;
+            };
+            // a condition or an early `return` may have ruled `null` out
+            let nullable = matches!(&actual, Type::Union(members) if members.contains(&Type::Null));
+            if nullable && is_guarded(&value, &self.contents[value.byte_range()], self.contents) {
+                actual = actual.without_null();
+            }
+            if !accepts(&self.types, &expected, &actual, self.strict) {
+                diagnostics.push(diagnostic(
+                    to_range(&value.range()),
+                    "argument-type",
+                    DiagnosticSeverity::ERROR,
+                    format!(
+                        "`{}` of `{}` takes `{}`, but is given `{}`",
+                        parameters[index].trim_start_matches("..."),
+                        callee.short_name(),
+                        expected,
+                        actual
+                    ),
+                ));
+            }
+        }
+    }
+}
+
+/**
+ * Report calls of functions, methods and constructors that don't pass every parameter without a
+ * default, which PHP throws an `ArgumentCountError` for, and calls that pass more than a function
+ * that isn't variadic takes, which PHP only throws for with its own functions.
+ */
+pub fn argument_counts(
+    root_node: &Node,
+    file_contents: &str,
+    symbols: &FileSymbols,
+    index: &WorkspaceIndex,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let resolver = Resolver::new(file_contents, symbols, index);
+    let types = TypeInference::new(file_contents, &resolver);
+    let mut found = vec![];
+    calls(root_node, &mut found);
+    for call in found {
+        if let Some(diagnostic) = types
+            .callee(&call)
+            .and_then(|callee| check_call(&call, file_contents, callee))
+        {
+            diagnostics.push(diagnostic);
+        }
+    }
+}
+
+/**
+ * Report arguments of types that the parameters they're passed to don't take. A default of
+ * `null` lets a parameter take `null`, and scalars can be passed as other scalars unless the file
+ * declares `strict_types`, since PHP converts them. Arguments aren't taken to be `null` where
+ * something rules it out, as for [`crate::null_access::null_accesses`]. Only parameters whose
+ * classes are all indexed are checked, and not those passed by reference.
+ */
+pub fn argument_types(
+    root_node: &Node,
+    file_contents: &str,
+    symbols: &FileSymbols,
+    index: &WorkspaceIndex,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let resolver = Resolver::new(file_contents, symbols, index);
+    let checker = ArgumentChecker {
+        contents: file_contents,
+        index,
+        types: TypeInference::new(file_contents, &resolver),
+        strict: is_strict(root_node, file_contents),
+    };
+    let mut found = vec![];
+    calls(root_node, &mut found);
+    for call in found {
+        if let Some(callee) = checker.types.callee(&call) {
+            checker.check_call(&call, callee, diagnostics);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::Url;
+
+    use super::{argument_counts, argument_types};
+    use crate::file::php_parser;
+    use crate::index::WorkspaceIndex;
+    use crate::symbols::file_symbols;
+
+    #[test]
+    fn test_argument_counts() {
+        let source = "<?php
+function greet(string $name, string $greeting = 'Hello') {}
+function total(int ...$numbers) {}
+class Point { public function __construct(int $x, int $y) {} }
+greet();
+greet('a', 'b', 'c');
+greet(greeting: 'Hi');
+greet(...$args);
+total(1, 2, 3);
+new Point(1);
+$p = new Point(1, 2);
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(&Url::parse("file:///a.php").unwrap(), symbols.clone());
+        let mut diagnostics = vec![];
+        argument_counts(
+            &tree.root_node(),
+            source,
+            &symbols,
+            &index,
+            &mut diagnostics,
+        );
+
+        let found: Vec<(u32, u32, String)> = diagnostics
+            .into_iter()
+            .map(|diagnostic| {
+                (
+                    diagnostic.range.start.line,
+                    diagnostic.range.start.character,
+                    diagnostic.message,
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                (
+                    4,
+                    5,
+                    "`greet` takes at least 1 argument, but is given 0".to_string()
+                ),
+                (
+                    5,
+                    16,
+                    "`greet` takes at most 2 arguments, but is given 3".to_string()
+                ),
+                (6, 5, "`greet` is called without `$name`".to_string()),
+                (
+                    9,
+                    9,
+                    "`Point` takes at least 2 arguments, but is given 1".to_string()
+                ),
+            ],
+            found
+        );
+    }
+
+    #[test]
+    fn test_argument_types() {
+        let source = "<?php
+declare(strict_types=1);
+class Animal {}
+class Dog extends Animal {}
+class Car {}
+function adopt(Animal $animal, ?string $name, int $age = null, float ...$weights) {}
+adopt(new Dog(), null, null, 1, 2.5);
+adopt(new Car(), 'Rex');
+adopt(new Dog(), 5, weights: 'heavy');
+adopt(name: 'Rex', animal: new Dog(), age: 'old');
+function sum(array $values) {}
+function total(int ...$nums) { return sum($nums); }
+function length(string $s): int {}
+function measure(?string $a, ?string $b) {
+    if ($a !== null) { length($a); }
+    if ($b === null) { throw new Exception(); }
+    length($b);
+    length($a);
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(&Url::parse("file:///a.php").unwrap(), symbols.clone());
+        let mut diagnostics = vec![];
+        argument_types(
+            &tree.root_node(),
+            source,
+            &symbols,
+            &index,
+            &mut diagnostics,
+        );
+
+        let found: Vec<(u32, String)> = diagnostics
+            .into_iter()
+            .map(|diagnostic| (diagnostic.range.start.line, diagnostic.message))
+            .collect();
+        assert_eq!(
+            vec![
+                (
+                    7,
+                    "`$animal` of `adopt` takes `Animal`, but is given `Car`".to_string()
+                ),
+                (
+                    8,
+                    "`$name` of `adopt` takes `?string`, but is given `int`".to_string()
+                ),
+                (
+                    8,
+                    "`$weights` of `adopt` takes `float`, but is given `string`".to_string()
+                ),
+                (
+                    9,
+                    "`$age` of `adopt` takes `?int`, but is given `string`".to_string()
+                ),
+                (
+                    17,
+                    "`$s` of `length` takes `string`, but is given `?string`".to_string()
+                ),
+            ],
+            found
+        );
+
+        // without `strict_types`, scalars are converted, but `null` isn't
+        let source = source.replace("declare(strict_types=1);", "");
+        let tree = php_parser().parse(&source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), &source);
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(&Url::parse("file:///a.php").unwrap(), symbols.clone());
+        let mut diagnostics = vec![];
+        argument_types(
+            &tree.root_node(),
+            &source,
+            &symbols,
+            &index,
+            &mut diagnostics,
+        );
+        assert_eq!(2, diagnostics.len());
+    }
+}
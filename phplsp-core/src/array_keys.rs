@@ -0,0 +1,305 @@
+//! Keys read from arrays whose keys are known, like those with an `#[ArrayShape]` or the matches
+//! of a literal pattern, that aren't among them: usually a typo, which PHP only warns about when
+//! the code runs, going on with `null`.
+
+use lsp_types::*;
+use tree_sitter::Node;
+
+use crate::file::to_range;
+use crate::index::WorkspaceIndex;
+use crate::preg::literal_chars;
+use crate::resolve::Resolver;
+use crate::symbols::FileSymbols;
+use crate::types::{Type, TypeInference};
+
+/// Functions that check whether a key is there before it's read.
+const GUARDS: &[&str] = &["isset", "empty", "array_key_exists", "key_exists"];
+
+/// The key of a subscript or of a destructured element, if it's a literal one.
+pub(crate) fn literal_key(key: &Node, file_contents: &str) -> Option<String> {
+    match key.kind() {
+        "integer" => Some(file_contents[key.byte_range()].to_string()),
+        _ => Some(
+            literal_chars(key, file_contents)?
+                .iter()
+                .map(|c| c.c)
+                .collect(),
+        ),
+    }
+}
+
+/// The whole of the subscripts that a subscript starts, like `$a['b']['c']` for `$a['b']`.
+fn outermost<'tree>(subscript: &Node<'tree>) -> Node<'tree> {
+    let mut node = *subscript;
+    while let Some(parent) = node.parent().filter(|parent| {
+        parent.kind() == "parenthesized_expression"
+            || (parent.kind() == "subscript_expression"
+                && parent
+                    .named_child(0)
+                    .is_some_and(|array| array.id() == node.id()))
+    }) {
+        node = parent;
+    }
+    node
+}
+
+/// Whether a node is a field of its parent, like the `left` of an assignment.
+fn is_field(node: &Node, parent: &Node, field: &str) -> bool {
+    parent
+        .child_by_field_name(field)
+        .is_some_and(|child| child.id() == node.id())
+}
+
+/// Whether a node is a call to one of the `GUARDS`.
+fn is_guard(node: &Node, file_contents: &str) -> bool {
+    node.kind() == "function_call_expression"
+        && node
+            .child_by_field_name("function")
+            .is_some_and(|function| {
+                GUARDS
+                    .iter()
+                    .any(|guard| guard.eq_ignore_ascii_case(&file_contents[function.byte_range()]))
+            })
+}
+
+/// Whether a condition checks for a key of an array with one of the `GUARDS`.
+fn has_guard(condition: &Node, array: &str, key: &str, file_contents: &str) -> bool {
+    if is_guard(condition, file_contents) {
+        let arguments = condition
+            .child_by_field_name("arguments")
+            .map_or("", |arguments| &file_contents[arguments.byte_range()]);
+        if arguments.contains(array) && arguments.contains(key) {
+            return true;
+        }
+    }
+
+    let mut cursor = condition.walk();
+    let found = condition
+        .named_children(&mut cursor)
+        .any(|child| has_guard(&child, array, key, file_contents));
+    found
+}
+
+/**
+ * Whether a subscript isn't a read that needs its key to be there: one that's written to, taken
+ * by reference or `unset`, one that `isset`, `empty` or `??` check first, and one in a branch that
+ * only runs if such a check found the key.
+ */
+fn is_guarded(subscript: &Node, array: &Node, key: &Node, file_contents: &str) -> bool {
+    let outer = outermost(subscript);
+    let Some(parent) = outer.parent() else {
+        return false;
+    };
+    let checked = match parent.kind() {
+        "assignment_expression" | "augmented_assignment_expression" => {
+            is_field(&outer, &parent, "left")
+        }
+        "binary_expression" => {
+            is_field(&outer, &parent, "left")
+                && parent
+                    .child_by_field_name("operator")
+                    .is_some_and(|operator| &file_contents[operator.byte_range()] == "??")
+        }
+        "unset_statement" | "by_ref" | "reference_assignment_expression" => true,
+        _ => false,
+    };
+    if checked {
+        return true;
+    }
+
+    let array = &file_contents[array.byte_range()];
+    let key = &file_contents[key.byte_range()];
+    let mut node = *subscript;
+    while let Some(parent) = node.parent() {
+        if is_guard(&parent, file_contents) {
+            return true;
+        }
+        let condition = match parent.kind() {
+            "if_statement" | "conditional_expression" if is_field(&node, &parent, "body") => {
+                parent.child_by_field_name("condition")
+            }
+            "binary_expression" if is_field(&node, &parent, "right") => parent
+                .child_by_field_name("operator")
+                .filter(|operator| {
+                    matches!(
+                        file_contents[operator.byte_range()].to_lowercase().as_str(),
+                        "&&" | "and"
+                    )
+                })
+                .and_then(|_| parent.child_by_field_name("left")),
+            _ => None,
+        };
+        if condition.is_some_and(|condition| has_guard(&condition, array, key, file_contents)) {
+            return true;
+        }
+        node = parent;
+    }
+    false
+}
+
+fn walk(
+    node: &Node,
+    file_contents: &str,
+    types: &TypeInference,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    if node.kind() == "subscript_expression" {
+        let array = node.named_child(0);
+        let key = node
+            .named_child(1)
+            .and_then(|key| Some((key, literal_key(&key, file_contents)?)));
+        if let (Some(array), Some((key, name))) = (array, key) {
+            let shape = match types.expression(&array) {
+                Some(Type::Shape(entries)) => Some(entries),
+                _ => None,
+            };
+            let missing = shape.filter(|entries| !entries.iter().any(|(known, _)| *known == name));
+            if let Some(entries) =
+                missing.filter(|_| !is_guarded(node, &array, &key, file_contents))
+            {
+                // what `preg_match` gave a variable has the groups of its pattern as keys
+                let from_pattern = array.kind() == "variable_name"
+                    && types
+                        .binding(&array)
+                        .is_some_and(|binding| binding.kind() == "argument");
+                let (code, message) = match from_pattern {
+                    true => (
+                        "undefined-group",
+                        format!("the pattern has no group `{}`", name),
+                    ),
+                    false => (
+                        "undefined-key",
+                        format!(
+                            "`{}` has no key `{}`; its keys are {}",
+                            &file_contents[array.byte_range()],
+                            name,
+                            entries
+                                .iter()
+                                .map(|(known, _)| format!("`{}`", known))
+                                .collect::<Vec<String>>()
+                                .join(", ")
+                        ),
+                    ),
+                };
+                diagnostics.push(Diagnostic {
+                    range: to_range(&key.range()),
+                    severity: Some(DiagnosticSeverity::WARNING),
+                    code: Some(NumberOrString::String(code.to_string())),
+                    source: Some("phplsp".to_string()),
+                    message,
+                    ..Diagnostic::default()
+                });
+            }
+        }
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        walk(&child, file_contents, types, diagnostics);
+    }
+}
+
+/**
+ * Report literal keys read from arrays whose keys are known that aren't one of them, like
+ * `$config['hots']` for a parameter with `#[ArrayShape(['host' => 'string'])]`, or
+ * `$matches['yaer']` for the matches of `/(?<year>\d+)/`, which has no such group. Reads that
+ * check for the key first, with `isset`, `empty`, `array_key_exists` or `??`, are left alone.
+ */
+pub fn undefined_keys(
+    root_node: &Node,
+    file_contents: &str,
+    symbols: &FileSymbols,
+    index: &WorkspaceIndex,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let resolver = Resolver::new(file_contents, symbols, index);
+    let types = TypeInference::new(file_contents, &resolver);
+    walk(root_node, file_contents, &types, diagnostics);
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::*;
+
+    use super::undefined_keys;
+    use crate::file::php_parser;
+    use crate::index::WorkspaceIndex;
+    use crate::symbols::file_symbols;
+
+    fn undefined(source: &str) -> Vec<(Position, String)> {
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(&Url::parse("file:///a.php").unwrap(), symbols.clone());
+        let mut diagnostics = vec![];
+        undefined_keys(
+            &tree.root_node(),
+            source,
+            &symbols,
+            &index,
+            &mut diagnostics,
+        );
+        diagnostics
+            .into_iter()
+            .map(|diagnostic| (diagnostic.range.start, diagnostic.message))
+            .collect()
+    }
+
+    #[test]
+    fn test_group_diagnostics() {
+        let source = "<?php
+if (preg_match('/(?<year>\\d{4})/', $date, $matches)) {
+    echo $matches['year'], $matches[1], $matches['yaer'], $matches[2];
+}
+$matches = ['yaer' => 1];
+echo $matches['yaer'];
+";
+        assert_eq!(
+            vec![
+                (
+                    Position::new(2, 49),
+                    "the pattern has no group `yaer`".to_string()
+                ),
+                (
+                    Position::new(2, 67),
+                    "the pattern has no group `2`".to_string()
+                ),
+            ],
+            undefined(source)
+        );
+    }
+
+    #[test]
+    fn test_undefined_keys() {
+        let source = "<?php
+class Db {
+    #[ArrayShape(['host' => 'string', 'port' => 'int'])]
+    public function config(): array {}
+    public function connect(#[ArrayShape(['host' => 'string'])] array $options) {
+        $host = $options['hots'];
+        $port = $options['port'] ?? 3306;
+        $options['port'] = 1;
+        if (isset($options['user'])) {
+            $user = $options['user'];
+        }
+        $name = array_key_exists('name', $options) ? $options['name'] : '';
+        $timeout = $this->config()['timeout'];
+    }
+}
+";
+        assert_eq!(
+            vec![
+                (
+                    Position::new(5, 25),
+                    "`$options` has no key `hots`; its keys are `host`".to_string()
+                ),
+                (
+                    Position::new(12, 35),
+                    "`$this->config()` has no key `timeout`; its keys are `host`, `port`"
+                        .to_string()
+                ),
+            ],
+            undefined(source)
+        );
+    }
+}
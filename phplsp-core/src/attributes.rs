@@ -0,0 +1,293 @@
+//! Reading attributes as they are written, for the JetBrains attributes that many codebases and
+//! stubs use to say more than PHP's types can: `#[ArrayShape]`, `#[Pure]` and `#[ExpectedValues]`.
+//!
+//! Attributes are only matched on the last segment of their name, since we don't know what the
+//! file they're written in imports.
+
+/**
+ * Split a list on the commas that aren't inside brackets or strings, trimming every item and
+ * dropping the empty item after a trailing comma.
+ */
+pub fn split_top_level(text: &str) -> Vec<&str> {
+    let mut items = vec![];
+    let mut depth = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+
+        match (quote, c) {
+            (Some(_), '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '(' | '[' | '{') => depth += 1,
+            (None, ')' | ']' | '}') => depth -= 1,
+            (None, ',') if depth == 0 => {
+                items.push(text[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(text[start..].trim());
+
+    items.retain(|item| !item.is_empty());
+    items
+}
+
+/// Where the bracket that closes the one `text` starts with is, skipping over strings.
+fn closing_bracket(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+
+        match (quote, c) {
+            (Some(_), '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '(' | '[') => depth += 1,
+            (None, ')' | ']') => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// The attributes that a parameter or declaration starts with, and what's written after them.
+pub fn strip_attributes(text: &str) -> (Vec<&str>, &str) {
+    let mut attributes = vec![];
+    let mut rest = text.trim_start();
+    while let Some(group) = rest.strip_prefix('#') {
+        let Some(end) = closing_bracket(group) else {
+            break;
+        };
+        attributes.extend(split_top_level(&group[1..end]));
+        rest = group[end + 1..].trim_start();
+    }
+
+    (attributes, rest)
+}
+
+/**
+ * The arguments of the first attribute with a name, without their parentheses. Attributes
+ * without arguments have empty ones.
+ */
+pub fn attribute_arguments<'a>(
+    attributes: impl IntoIterator<Item = &'a str>,
+    name: &str,
+) -> Option<&'a str> {
+    attributes.into_iter().find_map(|attribute| {
+        let (attribute_name, arguments) = match attribute.split_once('(') {
+            Some((attribute_name, arguments)) => (attribute_name, arguments.strip_suffix(')')?),
+            None => (attribute, ""),
+        };
+        let short_name = attribute_name.trim().rsplit('\\').next()?;
+        short_name
+            .eq_ignore_ascii_case(name)
+            .then_some(arguments.trim())
+    })
+}
+
+/// The name of a named argument like `values: [1, 2]`.
+fn argument_name(argument: &str) -> Option<&str> {
+    let end = argument.find(|c: char| !(c.is_alphanumeric() || c == '_'))?;
+    let rest = argument[end..].trim_start();
+    (end > 0 && rest.starts_with(':') && !rest.starts_with("::")).then_some(&argument[..end])
+}
+
+/// The value of a named argument, or else of the positional argument at an index.
+fn argument<'a>(arguments: &'a str, name: &str, position: usize) -> Option<&'a str> {
+    let arguments = split_top_level(arguments);
+    if let Some(named) = arguments
+        .iter()
+        .find(|argument| argument_name(argument) == Some(name))
+    {
+        return named.split_once(':').map(|(_, value)| value.trim());
+    }
+
+    arguments
+        .get(position)
+        .filter(|argument| argument_name(argument).is_none())
+        .copied()
+}
+
+/// The elements of an array literal written with brackets, with their keys if they have any.
+fn array_elements(array: &str) -> Option<Vec<(Option<&str>, &str)>> {
+    let inner = array.trim().strip_prefix('[')?.strip_suffix(']')?;
+    Some(
+        split_top_level(inner)
+            .into_iter()
+            .map(|element| match split_arrow(element) {
+                Some((key, value)) => (Some(key), value),
+                None => (None, element),
+            })
+            .collect(),
+    )
+}
+
+/// `key => value`, splitting on the first arrow that isn't in a string.
+fn split_arrow(element: &str) -> Option<(&str, &str)> {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in element.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+
+        match (quote, c) {
+            (Some(_), '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '=') if element[i..].starts_with("=>") => {
+                return Some((element[..i].trim(), element[i + 2..].trim()));
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// The contents of a string literal, or the literal itself if it isn't one.
+fn unquote(literal: &str) -> &str {
+    literal
+        .strip_prefix('\'')
+        .and_then(|literal| literal.strip_suffix('\''))
+        .or_else(|| {
+            literal
+                .strip_prefix('"')
+                .and_then(|literal| literal.strip_suffix('"'))
+        })
+        .unwrap_or(literal)
+}
+
+/**
+ * What a `#[Deprecated]` attribute says, from its reason, or message for PHP's own, and the
+ * replacement that JetBrains' takes.
+ */
+pub fn deprecation(arguments: &str) -> Option<String> {
+    let reason = argument(arguments, "reason", 0)
+        .or_else(|| argument(arguments, "message", 0))
+        .map(unquote)
+        .filter(|reason| !reason.is_empty());
+    // only by name, since the second argument of PHP's own is `since`
+    let replacement = argument(arguments, "replacement", usize::MAX).map(unquote);
+    match (reason, replacement) {
+        (Some(reason), Some(replacement)) => {
+            Some(format!("{}, use `{}` instead", reason, replacement))
+        }
+        (Some(reason), None) => Some(reason.to_string()),
+        (None, Some(replacement)) => Some(format!("use `{}` instead", replacement)),
+        (None, None) => None,
+    }
+}
+
+/**
+ * The keys and value types of `#[ArrayShape(['id' => 'int', 'user' => User::class])]`, with the
+ * types as they are written. Nested shapes are only known to be arrays.
+ */
+pub fn array_shape(arguments: &str) -> Option<Vec<(String, String)>> {
+    let shape = argument(arguments, "shape", 0)?;
+    array_elements(shape)?
+        .into_iter()
+        .map(|(key, value)| {
+            let ty = match value.strip_suffix("::class") {
+                Some(class) => class,
+                None if value.starts_with('[') => "array",
+                None => unquote(value),
+            };
+            Some((unquote(key?).to_string(), ty.to_string()))
+        })
+        .collect()
+}
+
+/// The values of `#[ExpectedValues([1, 2])]` or `#[ExpectedValues(values: [...])]`, as written.
+pub fn expected_values(arguments: &str) -> Option<Vec<String>> {
+    let values = argument(arguments, "values", 0)?;
+    Some(
+        array_elements(values)?
+            .into_iter()
+            .map(|(_, value)| value.to_string())
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::{array_shape, attribute_arguments, deprecation, expected_values, strip_attributes};
+
+    #[test]
+    fn test_strip_attributes() {
+        let (attributes, rest) =
+            strip_attributes("#[ExpectedValues(values: [1, ']'])] #[A, \\B\\C(2)] int $mode = 1");
+        assert_eq!(
+            vec!["ExpectedValues(values: [1, ']'])", "A", "\\B\\C(2)"],
+            attributes
+        );
+        assert_eq!("int $mode = 1", rest);
+
+        assert_eq!(Some(""), attribute_arguments(attributes.clone(), "a"));
+        assert_eq!(Some("2"), attribute_arguments(attributes.clone(), "C"));
+        assert_eq!(None, attribute_arguments(attributes, "Pure"));
+    }
+
+    #[test]
+    fn test_arguments() {
+        assert_eq!(
+            Some(vec![
+                ("id".to_string(), "int".to_string()),
+                ("tags".to_string(), "string[]".to_string()),
+                ("owner".to_string(), "User".to_string()),
+                ("meta".to_string(), "array".to_string()),
+            ]),
+            array_shape("['id' => 'int', \"tags\" => 'string[]', 'owner' => User::class, 'meta' => ['a' => 'int']]")
+        );
+        assert_eq!(
+            Some(vec![("a".to_string(), "int".to_string())]),
+            array_shape("shape: ['a' => 'int']")
+        );
+        assert_eq!(None, array_shape("[1 => 'int', 'x']"));
+
+        assert_eq!(
+            Some(vec!["SORT_ASC".to_string(), "'desc'".to_string()]),
+            expected_values("values: [SORT_ASC, 'desc']")
+        );
+        assert_eq!(
+            Some(vec!["1".to_string(), "2".to_string()]),
+            expected_values("[1, 2]")
+        );
+
+        assert_eq!(
+            Some("too slow, use `fetch()` instead".to_string()),
+            deprecation("reason: 'too slow', replacement: 'fetch()'")
+        );
+        assert_eq!(
+            Some("use `%class%->fetch()` instead".to_string()),
+            deprecation("replacement: \"%class%->fetch()\"")
+        );
+        assert_eq!(
+            Some("Use fetch()".to_string()),
+            deprecation("'Use fetch()', since: '8.4'")
+        );
+        assert_eq!(None, deprecation(""));
+    }
+}
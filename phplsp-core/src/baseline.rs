@@ -0,0 +1,174 @@
+//! Diagnostics that a workspace already had when it took up the server, in `.phplsp/baseline.json`,
+//! so that only new ones are reported. `phplsp --generate-baseline` writes it, counting the
+//! diagnostics of each file by code:
+//!
+//! ```json
+//! {
+//!     "src/Legacy/Order.php": {
+//!         "dead-store": 1,
+//!         "null-access": 3
+//!     }
+//! }
+//! ```
+
+use lsp_types::*;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Where the baseline of a workspace folder is, relative to it.
+pub const BASELINE_FILE: &str = ".phplsp/baseline.json";
+
+/// How many diagnostics of each code the files of a workspace folder had.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Baseline {
+    /// Counts by code, by paths relative to the workspace folder.
+    pub files: BTreeMap<String, BTreeMap<String, usize>>,
+    /// The workspace folder that the baseline is for.
+    pub root: PathBuf,
+}
+
+impl Baseline {
+    pub fn new(root: &Path) -> Self {
+        Self {
+            files: BTreeMap::new(),
+            root: root.to_path_buf(),
+        }
+    }
+
+    /// Parse the baseline of a workspace folder.
+    pub fn parse(root: &Path, contents: &str) -> serde_json::Result<Self> {
+        Ok(Self {
+            files: serde_json::from_str(contents)?,
+            root: root.to_path_buf(),
+        })
+    }
+
+    /// Read the baseline of a workspace folder, if it has one.
+    pub fn load(root: &Path) -> Result<Option<Self>, String> {
+        let path = root.join(BASELINE_FILE);
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("could not read `{}`: {}", path.display(), e)),
+        };
+        Self::parse(root, &contents)
+            .map(Some)
+            .map_err(|e| format!("invalid `{}`: {}", path.display(), e))
+    }
+
+    /// Write the baseline to its workspace folder, replacing any it had.
+    pub fn save(&self) -> io::Result<()> {
+        let path = self.root.join(BASELINE_FILE);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string_pretty(&self.files).expect("counts are serializable");
+        fs::write(path, json + "\n")
+    }
+
+    /// A path relative to the workspace folder, with `/` between its parts.
+    fn relative(&self, path: &Path) -> Option<String> {
+        let relative = path.strip_prefix(&self.root).ok()?;
+        Some(relative.to_string_lossy().replace('\\', "/"))
+    }
+
+    /// Count the diagnostics of a file of the workspace folder.
+    pub fn add(&mut self, path: &Path, diagnostics: &[Diagnostic]) {
+        let Some(relative) = self.relative(path) else {
+            return;
+        };
+        for code in diagnostics.iter().filter_map(code) {
+            *self
+                .files
+                .entry(relative.clone())
+                .or_default()
+                .entry(code.to_string())
+                .or_default() += 1;
+        }
+    }
+
+    /**
+     * Drop the diagnostics of a file that it already had. Where it has more of a code than it
+     * had, there's no telling which are new, so all of them are kept.
+     */
+    pub fn drop_known(&self, path: &Path, diagnostics: &mut Vec<Diagnostic>) {
+        let Some(counts) = self
+            .relative(path)
+            .and_then(|relative| self.files.get(&relative))
+        else {
+            return;
+        };
+        let mut found: BTreeMap<&str, usize> = BTreeMap::new();
+        for code in diagnostics.iter().filter_map(code) {
+            *found.entry(code).or_default() += 1;
+        }
+        let known: Vec<String> = found
+            .into_iter()
+            .filter(|(code, count)| counts.get(*code).is_some_and(|known| count <= known))
+            .map(|(code, _)| code.to_string())
+            .collect();
+        diagnostics.retain(|diagnostic| {
+            code(diagnostic).is_none_or(|code| !known.iter().any(|known| known == code))
+        });
+    }
+}
+
+fn code(diagnostic: &Diagnostic) -> Option<&str> {
+    match &diagnostic.code {
+        Some(NumberOrString::String(code)) => Some(code),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::*;
+
+    use std::path::Path;
+
+    use super::Baseline;
+
+    fn diagnostic(line: u32, code: &str) -> Diagnostic {
+        Diagnostic {
+            range: Range::new(Position::new(line, 0), Position::new(line, 1)),
+            code: Some(NumberOrString::String(code.to_string())),
+            ..Diagnostic::default()
+        }
+    }
+
+    #[test]
+    fn test_baseline() {
+        let root = Path::new("/project");
+        let path = root.join("src/Order.php");
+        let mut baseline = Baseline::new(root);
+        baseline.add(
+            &path,
+            &[
+                diagnostic(1, "null-access"),
+                diagnostic(2, "null-access"),
+                diagnostic(3, "dead-store"),
+            ],
+        );
+        baseline.add(Path::new("/elsewhere/A.php"), &[diagnostic(1, "naming")]);
+        let json = serde_json::to_string(&baseline.files).unwrap();
+        assert_eq!(
+            r#"{"src/Order.php":{"dead-store":1,"null-access":2}}"#,
+            json
+        );
+        let baseline = Baseline::parse(root, &json).unwrap();
+
+        let mut diagnostics = vec![
+            diagnostic(1, "null-access"),
+            diagnostic(5, "dead-store"),
+            diagnostic(6, "dead-store"),
+            diagnostic(7, "unused-variable"),
+        ];
+        baseline.drop_known(&path, &mut diagnostics);
+        let lines: Vec<u32> = diagnostics.iter().map(|d| d.range.start.line).collect();
+        // a second `dead-store` could be either of them
+        assert_eq!(vec![5, 6, 7], lines);
+    }
+}
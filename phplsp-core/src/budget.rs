@@ -0,0 +1,186 @@
+//! A bound on the time spent working out the types of any one function, since generated code can
+//! make unions grow without end. Functions that go over it are taken as `mixed` throughout, their
+//! checks of types are skipped, and a hint says so.
+
+use lsp_types::*;
+use tree_sitter::Node;
+
+use std::time::{Duration, Instant};
+
+use crate::file::to_range;
+use crate::index::WorkspaceIndex;
+use crate::resolve::Resolver;
+use crate::symbols::FileSymbols;
+use crate::types::TypeInference;
+
+/// How long the types of a function can take to work out.
+pub const FUNCTION_BUDGET: Duration = Duration::from_millis(250);
+
+/// Node kinds of the functions that each get a budget. Closures share that of their function.
+const FUNCTIONS: &[&str] = &["function_definition", "method_declaration"];
+
+/// Codes of the diagnostics that are only as right as the types they're worked out from.
+const TYPE_CODES: &[&str] = &[
+    "undefined-method",
+    "undefined-property",
+    "argument-type",
+    "return-type",
+    "property-type",
+    "reference-type",
+    "generator",
+    "foreach-type",
+    "foreach-destructuring",
+    "null-access",
+    "constant-condition",
+    "undefined-key",
+    "option-type",
+    "format-type",
+];
+
+/// Work out the type of every expression under a node, until the deadline passes.
+fn infer_all(node: &Node, inference: &TypeInference) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        if inference.is_out_of_time() {
+            return;
+        }
+        inference.expression(&child);
+        infer_all(&child, inference);
+    }
+}
+
+fn over_budget<'tree>(
+    node: &Node<'tree>,
+    file_contents: &str,
+    resolver: &Resolver,
+    budget: Duration,
+    found: &mut Vec<Node<'tree>>,
+) {
+    if FUNCTIONS.contains(&node.kind()) {
+        let inference =
+            TypeInference::new(file_contents, resolver).with_deadline(Instant::now() + budget);
+        if let Some(body) = node.child_by_field_name("body") {
+            infer_all(&body, &inference);
+        }
+        if inference.is_out_of_time() {
+            found.push(*node);
+        }
+        return;
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        over_budget(&child, file_contents, resolver, budget, found);
+    }
+}
+
+/**
+ * Drop the diagnostics of types in the functions whose types take longer than a budget to work
+ * out, and hint at each of them that its analysis was cut short.
+ */
+pub fn truncate_slow_functions(
+    root_node: &Node,
+    file_contents: &str,
+    symbols: &FileSymbols,
+    index: &WorkspaceIndex,
+    budget: Duration,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let resolver = Resolver::new(file_contents, symbols, index);
+    let mut slow = vec![];
+    over_budget(root_node, file_contents, &resolver, budget, &mut slow);
+
+    for function in slow {
+        let range = to_range(&function.range());
+        diagnostics.retain(|diagnostic| {
+            let within = range.start <= diagnostic.range.start && diagnostic.range.end <= range.end;
+            let of_types = matches!(
+                &diagnostic.code,
+                Some(NumberOrString::String(code)) if TYPE_CODES.contains(&code.as_str())
+            );
+            !(within && of_types)
+        });
+
+        let name = function.child_by_field_name("name").unwrap_or(function);
+        diagnostics.push(Diagnostic {
+            range: to_range(&name.range()),
+            severity: Some(DiagnosticSeverity::HINT),
+            code: Some(NumberOrString::String("analysis-truncated".to_string())),
+            source: Some("phplsp".to_string()),
+            message: format!(
+                "working out the types of `{}` took over {}ms, so they're all taken as `mixed` \
+                and aren't checked",
+                &file_contents[name.byte_range()],
+                budget.as_millis()
+            ),
+            ..Diagnostic::default()
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::*;
+
+    use std::time::Duration;
+
+    use super::truncate_slow_functions;
+    use crate::file::php_parser;
+    use crate::index::WorkspaceIndex;
+    use crate::null_access::null_accesses;
+    use crate::symbols::file_symbols;
+
+    #[test]
+    fn test_truncate_slow_functions() {
+        let source = "<?php
+class User {
+    public function save(): void {}
+}
+function find(int $id): ?User {}
+function show() {
+    find(1)->save();
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(&Url::parse("file:///a.php").unwrap(), symbols.clone());
+        let found = |budget| {
+            let mut diagnostics = vec![];
+            null_accesses(
+                &tree.root_node(),
+                source,
+                &symbols,
+                &index,
+                &mut diagnostics,
+            );
+            truncate_slow_functions(
+                &tree.root_node(),
+                source,
+                &symbols,
+                &index,
+                budget,
+                &mut diagnostics,
+            );
+            diagnostics
+                .into_iter()
+                .map(|diagnostic| (diagnostic.range.start.line, diagnostic.code))
+                .collect::<Vec<_>>()
+        };
+        let code = |code: &str| Some(NumberOrString::String(code.to_string()));
+
+        assert_eq!(
+            vec![(6, code("null-access"))],
+            found(Duration::from_secs(60))
+        );
+        // with no time at all, every function is cut short
+        assert_eq!(
+            vec![
+                (2, code("analysis-truncated")),
+                (4, code("analysis-truncated")),
+                (5, code("analysis-truncated")),
+            ],
+            found(Duration::ZERO)
+        );
+    }
+}
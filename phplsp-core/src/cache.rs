@@ -0,0 +1,217 @@
+use lsp_types::Url;
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::file::php_language;
+use crate::symbols::FileSymbols;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct CachedFile {
+    modified: SystemTime,
+    is_vendor: bool,
+    symbols: FileSymbols,
+}
+
+/**
+ * The version of what's cached: bump it whenever [`FileSymbols`] or what's found of them changes,
+ * since releases don't always bump the version of phplsp.
+ */
+const CACHE_FORMAT: u32 = 1;
+
+/**
+ * What made a cache: the format of what's in it, this version of phplsp and the PHP grammar it
+ * parses with. Symbols from another version may be missing what this one finds, so caches of
+ * other versions aren't used.
+ */
+fn cache_version() -> String {
+    let language = php_language();
+    format!(
+        "{}:{}+grammar{}.{}.{}",
+        CACHE_FORMAT,
+        env!("CARGO_PKG_VERSION"),
+        language.version(),
+        language.node_kind_count(),
+        language.field_count()
+    )
+}
+
+/**
+ * Symbols of every file in a workspace folder, kept on disk between runs.
+ *
+ * Symbols are all that the index keeps of files that aren't loaded, so with these we only have to
+ * parse the files that changed since the last run.
+ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexCache {
+    version: String,
+    files: HashMap<Url, CachedFile>,
+}
+
+impl Default for IndexCache {
+    fn default() -> Self {
+        Self {
+            version: cache_version(),
+            files: HashMap::new(),
+        }
+    }
+}
+
+impl IndexCache {
+    /**
+     * A missing or unreadable cache is the same as an empty one. Caches that are corrupt or from
+     * another version are removed, to be saved again from scratch after indexing.
+     */
+    pub fn load(path: &Path) -> Self {
+        let Ok(bytes) = fs::read(path) else {
+            return Self::default();
+        };
+        match serde_json::from_slice::<Self>(&bytes) {
+            Ok(cache) if cache.version == cache_version() => cache,
+            _ => {
+                let _ = fs::remove_file(path);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // write somewhere else first so that a concurrent load never sees half a cache
+        let partial = path.with_extension("partial");
+        fs::write(&partial, serde_json::to_vec(self)?)?;
+        fs::rename(partial, path)
+    }
+
+    /// Symbols of a file, unless it was modified since they were cached.
+    pub fn get(&self, uri: &Url, modified: SystemTime) -> Option<&FileSymbols> {
+        self.files
+            .get(uri)
+            .filter(|file| file.modified == modified)
+            .map(|file| &file.symbols)
+    }
+
+    pub fn insert(
+        &mut self,
+        uri: Url,
+        modified: SystemTime,
+        is_vendor: bool,
+        symbols: FileSymbols,
+    ) {
+        self.files.insert(
+            uri,
+            CachedFile {
+                modified,
+                is_vendor,
+                symbols,
+            },
+        );
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+/**
+ * A 64-bit FNV-1a hash, which unlike the hashers of the standard library is the same from one
+ * build to the next.
+ */
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/**
+ * Where the cache of a workspace folder is kept: `$XDG_CACHE_HOME/phplsp`, or `~/.cache/phplsp`,
+ * in a file named after a hash of the folder's path.
+ */
+pub fn cache_path(folder: &Path) -> Option<PathBuf> {
+    let cache_home = env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+
+    let hash = fnv1a(folder.as_os_str().as_encoded_bytes());
+    Some(
+        cache_home
+            .join("phplsp")
+            .join(format!("{:016x}.json", hash)),
+    )
+}
+
+/// Remove the cache of a workspace folder, if it has one.
+pub fn clear_cache(folder: &Path) -> io::Result<()> {
+    let Some(path) = cache_path(folder) else {
+        return Ok(());
+    };
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::time::{Duration, SystemTime};
+
+    use lsp_types::Url;
+
+    use super::{fnv1a, IndexCache};
+    use crate::file::php_parser;
+    use crate::symbols::file_symbols;
+
+    #[test]
+    fn test_round_trip() {
+        let source = "<?php namespace App; class User { public function name(): string {} }";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let uri = Url::parse("file:///project/User.php").unwrap();
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+        let mut cache = IndexCache::default();
+        cache.insert(uri.clone(), modified, false, symbols.clone());
+
+        let path = std::env::temp_dir().join("phplsp-test-cache-round-trip/cache.json");
+        cache.save(&path).unwrap();
+        let loaded = IndexCache::load(&path);
+        assert_eq!(cache, loaded);
+        assert_eq!(Some(&symbols), loaded.get(&uri, modified));
+        assert_eq!(None, loaded.get(&uri, modified + Duration::from_secs(1)));
+
+        // a cache from something else entirely is ignored
+        fs::write(&path, "[]").unwrap();
+        assert!(IndexCache::load(&path).is_empty());
+        assert!(!path.exists());
+
+        // and so is one from another version
+        let mut old = cache.clone();
+        old.version = "0:0.0.0+grammar0.0.0".to_string();
+        old.save(&path).unwrap();
+        assert!(IndexCache::load(&path).is_empty());
+        assert!(!path.exists());
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_fnv1a() {
+        // cache files are named with it, so it can't change between builds
+        assert_eq!(0xcbf29ce484222325, fnv1a(b""));
+        assert_eq!(0xaf63dc4c8601ec8c, fnv1a(b"a"));
+        assert_eq!(0x85944171f73967e8, fnv1a(b"foobar"));
+    }
+}
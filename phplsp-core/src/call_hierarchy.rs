@@ -0,0 +1,348 @@
+use lsp_types::*;
+use serde::{Deserialize, Serialize};
+use tree_sitter::Node;
+
+use crate::file::{to_point, to_position};
+use crate::index::WorkspaceIndex;
+use crate::resolve::Resolver;
+use crate::symbols::{Declaration, DeclarationKind, FileSymbols};
+use crate::types::TypeInference;
+
+/// Node kinds that call a function, method or constructor.
+const CALLS: &[&str] = &[
+    "function_call_expression",
+    "member_call_expression",
+    "nullsafe_member_call_expression",
+    "scoped_call_expression",
+    "object_creation_expression",
+];
+
+/// Node kinds whose calls are made by themselves, and not by whatever they're declared in.
+const CALLERS: &[&str] = &["function_definition", "method_declaration"];
+
+/// What an item needs to find its declaration again when the client asks for its calls.
+#[derive(Debug, Serialize, Deserialize)]
+struct CallHierarchyData {
+    name: String,
+}
+
+fn is_callable(declaration: &Declaration) -> bool {
+    matches!(
+        declaration.kind,
+        DeclarationKind::Function | DeclarationKind::Method
+    )
+}
+
+/// An item for a function or method, spanning its name.
+pub fn call_hierarchy_item(uri: &Url, declaration: &Declaration) -> CallHierarchyItem {
+    CallHierarchyItem {
+        name: declaration.short_name().to_string(),
+        kind: match declaration.member_name() {
+            Some(name) if name.eq_ignore_ascii_case("__construct") => SymbolKind::CONSTRUCTOR,
+            _ => declaration.kind.into(),
+        },
+        tags: declaration.deprecated.then(|| vec![SymbolTag::DEPRECATED]),
+        detail: declaration.container_name().map(str::to_string),
+        uri: uri.clone(),
+        range: declaration.range,
+        selection_range: declaration.range,
+        data: serde_json::to_value(CallHierarchyData {
+            name: declaration.name.clone(),
+        })
+        .ok(),
+    }
+}
+
+/// The function or method an item from [`call_hierarchy_item`] is for, and where it's declared.
+pub fn item_declaration<'a>(
+    item: &CallHierarchyItem,
+    index: &'a WorkspaceIndex,
+) -> Option<(&'a Url, &'a Declaration)> {
+    let data: CallHierarchyData = serde_json::from_value(item.data.clone()?).ok()?;
+    index
+        .locate(&data.name)
+        .filter(|(_, declaration)| is_callable(declaration))
+}
+
+/// The item for the function or method that the name at a position declares or calls.
+pub fn prepare_call_hierarchy(
+    root_node: &Node,
+    file_contents: &str,
+    symbols: &FileSymbols,
+    index: &WorkspaceIndex,
+    position: &Position,
+) -> Option<CallHierarchyItem> {
+    let resolver = Resolver::new(file_contents, symbols, index);
+    let declaration = resolver.target(root_node, position)?;
+    let (uri, declaration) = index.locate(&declaration.name)?;
+
+    is_callable(declaration).then(|| call_hierarchy_item(uri, declaration))
+}
+
+/// The node that names what a call calls.
+fn called_name<'tree>(call: &Node<'tree>) -> Option<Node<'tree>> {
+    match call.kind() {
+        "function_call_expression" => call.child_by_field_name("function"),
+        "object_creation_expression" => {
+            let mut cursor = call.walk();
+            let class = call
+                .named_children(&mut cursor)
+                .find(|child| matches!(child.kind(), "name" | "qualified_name"));
+            class
+        }
+        _ => call.child_by_field_name("name"),
+    }
+}
+
+/// The call whose callee is named by the node at a position, if that is a call at all.
+pub(crate) fn call_at<'tree>(root_node: &Node<'tree>, position: &Position) -> Option<Node<'tree>> {
+    let point = to_point(position);
+    let mut current = root_node.descendant_for_point_range(point, point);
+    // names of functions can be qualified, and methods can be named by a `name` in a variable
+    for _ in 0..4 {
+        let node = current?;
+        if CALLS.contains(&node.kind()) {
+            return called_name(&node)
+                .filter(|name| name.start_position() == point)
+                .map(|_| node);
+        }
+        current = node.parent();
+    }
+
+    None
+}
+
+/// The item of whatever makes calls from a node: a function, a method, or else the whole file.
+fn caller_item(node: &Node, uri: &Url, resolver: &Resolver) -> CallHierarchyItem {
+    let mut current = node.parent();
+    while let Some(parent) = current {
+        if CALLERS.contains(&parent.kind()) {
+            let declaration = parent
+                .child_by_field_name("name")
+                .and_then(|name| resolver.declaration_at(&to_position(&name.start_position())));
+            if let Some(declaration) = declaration {
+                return call_hierarchy_item(uri, declaration);
+            }
+        }
+        current = parent.parent();
+    }
+
+    let name = uri
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .unwrap_or_default();
+    let start = Range::default();
+    CallHierarchyItem {
+        name: name.to_string(),
+        kind: SymbolKind::FILE,
+        tags: None,
+        detail: None,
+        uri: uri.clone(),
+        range: start,
+        selection_range: start,
+        data: None,
+    }
+}
+
+/**
+ * The calls to a function or method in one file, grouped by the function or method they're made
+ * from. Calls made outside of any are made by the file.
+ *
+ * Methods are referenced by their name alone, so calls that we can tell are to a method of
+ * another class are left out, but calls whose class we can't tell are kept.
+ */
+pub fn incoming_calls(
+    root_node: &Node,
+    file_contents: &str,
+    uri: &Url,
+    symbols: &FileSymbols,
+    index: &WorkspaceIndex,
+    callee: &Declaration,
+) -> Vec<CallHierarchyIncomingCall> {
+    let resolver = Resolver::new(file_contents, symbols, index);
+    let types = TypeInference::new(file_contents, &resolver);
+
+    let mut calls: Vec<CallHierarchyIncomingCall> = vec![];
+    let references = index
+        .references(callee)
+        .into_iter()
+        .filter(|(reference_uri, _)| *reference_uri == uri);
+    for (_, reference) in references {
+        let Some(call) = call_at(root_node, &reference.range.start) else {
+            continue;
+        };
+        if types
+            .callee(&call)
+            .is_some_and(|called| !called.name.eq_ignore_ascii_case(&callee.name))
+        {
+            continue;
+        }
+
+        let from = caller_item(&call, uri, &resolver);
+        match calls.iter_mut().find(|incoming| incoming.from == from) {
+            Some(incoming) => incoming.from_ranges.push(reference.range),
+            None => calls.push(CallHierarchyIncomingCall {
+                from,
+                from_ranges: vec![reference.range],
+            }),
+        }
+    }
+
+    calls
+}
+
+fn find_calls<'tree>(node: &Node<'tree>, calls: &mut Vec<Node<'tree>>) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        // these make their own calls
+        if CALLERS.contains(&child.kind()) || child.kind() == "class_declaration" {
+            continue;
+        }
+        if CALLS.contains(&child.kind()) {
+            calls.push(child);
+        }
+        find_calls(&child, calls);
+    }
+}
+
+/**
+ * The functions and methods that a function or method declared in this file calls, in the order
+ * they're first called.
+ *
+ * Calls in closures count as calls of the function they're in. Only calls whose callee we can
+ * find are included.
+ */
+pub fn outgoing_calls(
+    root_node: &Node,
+    file_contents: &str,
+    symbols: &FileSymbols,
+    index: &WorkspaceIndex,
+    caller: &Declaration,
+) -> Vec<CallHierarchyOutgoingCall> {
+    let start = to_point(&caller.range.start);
+    let Some(body) = root_node
+        .descendant_for_point_range(start, start)
+        .and_then(|name| name.parent())
+        .filter(|caller| CALLERS.contains(&caller.kind()))
+        .and_then(|caller| caller.child_by_field_name("body"))
+    else {
+        return vec![];
+    };
+
+    let resolver = Resolver::new(file_contents, symbols, index);
+    let types = TypeInference::new(file_contents, &resolver);
+    let mut call_nodes = vec![];
+    find_calls(&body, &mut call_nodes);
+
+    let mut calls: Vec<CallHierarchyOutgoingCall> = vec![];
+    for call in call_nodes {
+        let (Some(callee), Some(name)) = (types.callee(&call), called_name(&call)) else {
+            continue;
+        };
+        let Some((uri, callee)) = index.locate(&callee.name) else {
+            continue;
+        };
+        let range = Range {
+            start: to_position(&name.start_position()),
+            end: to_position(&name.end_position()),
+        };
+
+        match calls
+            .iter_mut()
+            .find(|outgoing| outgoing.to.uri == *uri && outgoing.to.range == callee.range)
+        {
+            Some(outgoing) => outgoing.from_ranges.push(range),
+            None => calls.push(CallHierarchyOutgoingCall {
+                to: call_hierarchy_item(uri, callee),
+                from_ranges: vec![range],
+            }),
+        }
+    }
+
+    calls
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::*;
+
+    use super::{incoming_calls, item_declaration, outgoing_calls, prepare_call_hierarchy};
+    use crate::file::php_parser;
+    use crate::index::WorkspaceIndex;
+    use crate::symbols::file_symbols;
+
+    const SOURCE: &str = "<?php
+namespace App;
+function helper() {}
+class Greeter {
+    public function greet() {
+        helper();
+        $f = function () { return $this->loud(); };
+        return $this->loud();
+    }
+    public function loud() {}
+}
+class Other {
+    public function loud() {}
+}
+(new Greeter)->greet();
+(new Other)->loud();
+helper();
+";
+
+    #[test]
+    fn test_calls() {
+        let tree = php_parser().parse(SOURCE, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), SOURCE);
+        let uri = Url::parse("file:///project/Greeter.php").unwrap();
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(&uri, symbols.clone());
+
+        // on the call to `loud` in `greet`
+        let position = Position {
+            line: 7,
+            character: 23,
+        };
+        let item =
+            prepare_call_hierarchy(&tree.root_node(), SOURCE, &symbols, &index, &position).unwrap();
+        assert_eq!("loud", item.name);
+        assert_eq!(Some("App\\Greeter"), item.detail.as_deref());
+        let (_, loud) = item_declaration(&item, &index).unwrap();
+
+        let incoming = incoming_calls(&tree.root_node(), SOURCE, &uri, &symbols, &index, loud);
+        assert_eq!(
+            vec![("greet".to_string(), 2)],
+            incoming
+                .into_iter()
+                .map(|call| (call.from.name, call.from_ranges.len()))
+                .collect::<Vec<_>>()
+        );
+
+        let helper = index.find_declaration("App\\helper").unwrap();
+        let incoming = incoming_calls(&tree.root_node(), SOURCE, &uri, &symbols, &index, helper);
+        assert_eq!(
+            vec![("greet".to_string(), 1), ("Greeter.php".to_string(), 1)],
+            incoming
+                .into_iter()
+                .map(|call| (call.from.name, call.from_ranges.len()))
+                .collect::<Vec<_>>()
+        );
+
+        let greet = index.find_declaration("App\\Greeter::greet").unwrap();
+        let outgoing = outgoing_calls(&tree.root_node(), SOURCE, &symbols, &index, greet);
+        assert_eq!(
+            vec![
+                ("App".to_string(), "helper".to_string(), 1),
+                ("App\\Greeter".to_string(), "loud".to_string(), 2)
+            ],
+            outgoing
+                .into_iter()
+                .map(|call| (
+                    call.to.detail.unwrap(),
+                    call.to.name,
+                    call.from_ranges.len()
+                ))
+                .collect::<Vec<_>>()
+        );
+    }
+}
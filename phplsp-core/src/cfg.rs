@@ -0,0 +1,710 @@
+//! Control flow graphs of functions, for rules that need to know which statements can run after
+//! which: unreachable code, values that are never read, and functions that don't always return.
+//!
+//! Expressions are treated as if they run all at once, except that `throw`, `exit` and the arms
+//! of a `match` can be statements of their own.
+
+use tree_sitter::Node;
+
+use std::collections::HashMap;
+
+/// Index of a block in [`ControlFlowGraph::blocks`].
+pub type BlockId = usize;
+
+/// Statements that always run one after another.
+#[derive(Debug, Default)]
+pub struct BasicBlock<'tree> {
+    /**
+     * What runs, in order: statements that don't branch, and the conditions of those that do.
+     * Statements that branch are left out, since their parts are in other blocks.
+     */
+    pub statements: Vec<Node<'tree>>,
+    /// Blocks that can run next.
+    pub successors: Vec<BlockId>,
+}
+
+#[derive(Debug)]
+pub struct ControlFlowGraph<'tree> {
+    pub blocks: Vec<BasicBlock<'tree>>,
+    /// The block that the body of the function ends with, which falls off its end.
+    pub end: BlockId,
+    /// Each `case` and `default` of a `switch`, and the block it ends with, which falls through.
+    pub cases: Vec<(Node<'tree>, BlockId)>,
+}
+
+/// Where `break` and `continue` go from inside a loop or a `switch`.
+struct Loop {
+    after: BlockId,
+    next: BlockId,
+}
+
+struct Builder<'a, 'tree> {
+    contents: &'a str,
+    blocks: Vec<BasicBlock<'tree>>,
+    current: BlockId,
+    /// Innermost last.
+    loops: Vec<Loop>,
+    /// The `catch` and `finally` blocks of each `try` that we're in, innermost last.
+    handlers: Vec<Vec<BlockId>>,
+    labels: HashMap<&'a str, BlockId>,
+    gotos: Vec<(BlockId, &'a str)>,
+    cases: Vec<(Node<'tree>, BlockId)>,
+    /// Whether a call never returns, like one of a function whose return type is `never`.
+    never_returns: &'a dyn Fn(&Node) -> bool,
+}
+
+/// Whether a condition is always true, like in `while (true)`.
+fn always_true(condition: &Node, contents: &str) -> bool {
+    let text = contents[condition.byte_range()]
+        .trim_start_matches('(')
+        .trim_end_matches(')')
+        .trim();
+    text.eq_ignore_ascii_case("true") || text == "1"
+}
+
+/// Whether an expression ends the script, like `exit(1)` or `die`.
+pub(crate) fn is_exit(expression: &Node, contents: &str) -> bool {
+    let name = match expression.kind() {
+        "exit_statement" => return true,
+        "name" => Some(*expression),
+        "function_call_expression" => expression.child_by_field_name("function"),
+        _ => None,
+    };
+    name.is_some_and(|name| {
+        let name = &contents[name.byte_range()];
+        name.eq_ignore_ascii_case("exit") || name.eq_ignore_ascii_case("die")
+    })
+}
+
+/// The `match` that a statement's expression is, or assigns.
+fn match_in<'tree>(expression: &Node<'tree>) -> Option<Node<'tree>> {
+    match expression.kind() {
+        "match_expression" => Some(*expression),
+        "assignment_expression" | "augmented_assignment_expression" => expression
+            .child_by_field_name("right")
+            .filter(|right| right.kind() == "match_expression"),
+        _ => None,
+    }
+}
+
+impl<'a, 'tree> Builder<'a, 'tree> {
+    /// A new block, which can throw to the handlers of the `try` it's in.
+    fn block(&mut self) -> BlockId {
+        let successors = self.handlers.last().cloned().unwrap_or_default();
+        self.blocks.push(BasicBlock {
+            statements: vec![],
+            successors,
+        });
+        self.blocks.len() - 1
+    }
+
+    fn edge(&mut self, from: BlockId, to: BlockId) {
+        if !self.blocks[from].successors.contains(&to) {
+            self.blocks[from].successors.push(to);
+        }
+    }
+
+    /// Whether anything built so far leads to a block.
+    fn reachable(&self, block: BlockId) -> bool {
+        let mut seen = vec![false; self.blocks.len()];
+        let mut stack = vec![ControlFlowGraph::ENTRY];
+        while let Some(next) = stack.pop() {
+            if next == block {
+                return true;
+            }
+            if !std::mem::replace(&mut seen[next], true) {
+                stack.extend(&self.blocks[next].successors);
+            }
+        }
+        false
+    }
+
+    fn push(&mut self, node: Node<'tree>) {
+        self.blocks[self.current].statements.push(node);
+    }
+
+    /// Continue in a block that nothing leads to, after a jump.
+    fn detach(&mut self) {
+        self.current = self.block();
+    }
+
+    /// Continue in a new block that follows the current one.
+    fn next(&mut self) -> BlockId {
+        let next = self.block();
+        self.edge(self.current, next);
+        self.current = next;
+        next
+    }
+
+    fn statements(&mut self, node: &Node<'tree>) {
+        let mut cursor = node.walk();
+        for child in node.named_children(&mut cursor) {
+            if child.kind() != "comment" {
+                self.statement(&child);
+            }
+        }
+    }
+
+    fn statement(&mut self, node: &Node<'tree>) {
+        match node.kind() {
+            "compound_statement" | "colon_block" => self.statements(node),
+            "expression_statement" => {
+                let Some(expression) = node.named_child(0) else {
+                    return self.push(*node);
+                };
+                if let Some(match_expression) = match_in(&expression) {
+                    self.match_expression(&match_expression);
+                }
+                self.push(*node);
+                if expression.kind() == "throw_expression"
+                    || is_exit(&expression, self.contents)
+                    || (self.never_returns)(&expression)
+                {
+                    self.detach();
+                }
+            }
+            "exit_statement" => {
+                self.push(*node);
+                self.detach();
+            }
+            "return_statement" => {
+                let value = node.named_child(0);
+                if let Some(match_expression) = value.as_ref().and_then(match_in) {
+                    self.match_expression(&match_expression);
+                }
+                self.push(*node);
+                self.edge(self.current, ControlFlowGraph::EXIT);
+                self.detach();
+            }
+            "if_statement" => self.if_statement(node),
+            "while_statement" => self.while_statement(node),
+            "do_statement" => self.do_statement(node),
+            "for_statement" => self.for_statement(node),
+            "foreach_statement" => self.foreach_statement(node),
+            "switch_statement" => self.switch_statement(node),
+            "try_statement" => self.try_statement(node),
+            "break_statement" | "continue_statement" => {
+                let levels = node
+                    .named_child(0)
+                    .and_then(|levels| self.contents[levels.byte_range()].parse().ok())
+                    .unwrap_or(1usize)
+                    .max(1);
+                let target = self
+                    .loops
+                    .len()
+                    .checked_sub(levels)
+                    .map(|i| &self.loops[i])
+                    .map(|target| match node.kind() {
+                        "break_statement" => target.after,
+                        _ => target.next,
+                    });
+                self.push(*node);
+                if let Some(target) = target {
+                    self.edge(self.current, target);
+                }
+                self.detach();
+            }
+            "goto_statement" => {
+                self.push(*node);
+                if let Some(label) = node.named_child(0) {
+                    self.gotos
+                        .push((self.current, &self.contents[label.byte_range()]));
+                }
+                self.detach();
+            }
+            "named_label_statement" => {
+                let label = self.next();
+                if let Some(name) = node.named_child(0) {
+                    self.labels.insert(&self.contents[name.byte_range()], label);
+                }
+                self.push(*node);
+            }
+            _ => self.push(*node),
+        }
+    }
+
+    fn if_statement(&mut self, node: &Node<'tree>) {
+        if let Some(condition) = node.child_by_field_name("condition") {
+            self.push(condition);
+        }
+        let mut condition = self.current;
+        let mut ends = vec![];
+        if let Some(body) = node.child_by_field_name("body") {
+            self.next();
+            self.statement(&body);
+            ends.push(self.current);
+        }
+
+        let mut has_else = false;
+        let mut cursor = node.walk();
+        for alternative in node.children_by_field_name("alternative", &mut cursor) {
+            self.current = condition;
+            if alternative.kind() == "else_if_clause" {
+                condition = self.next();
+                if let Some(condition) = alternative.child_by_field_name("condition") {
+                    self.push(condition);
+                }
+            } else {
+                has_else = true;
+            }
+            if let Some(body) = alternative.child_by_field_name("body") {
+                self.next();
+                self.statement(&body);
+                ends.push(self.current);
+            }
+        }
+        if !has_else {
+            ends.push(condition);
+        }
+
+        let after = self.block();
+        for end in ends {
+            self.edge(end, after);
+        }
+        self.current = after;
+    }
+
+    fn loop_body(&mut self, body: Option<Node<'tree>>, after: BlockId, next: BlockId) {
+        self.loops.push(Loop { after, next });
+        if let Some(body) = body {
+            self.statement(&body);
+        }
+        self.loops.pop();
+        self.edge(self.current, next);
+    }
+
+    fn while_statement(&mut self, node: &Node<'tree>) {
+        let header = self.next();
+        let condition = node.child_by_field_name("condition");
+        if let Some(condition) = condition {
+            self.push(condition);
+        }
+        let after = self.block();
+        if !condition.is_some_and(|condition| always_true(&condition, self.contents)) {
+            self.edge(header, after);
+        }
+
+        self.next();
+        self.loop_body(node.child_by_field_name("body"), after, header);
+        self.current = after;
+    }
+
+    fn do_statement(&mut self, node: &Node<'tree>) {
+        let body = self.next();
+        let header = self.block();
+        let after = self.block();
+        self.loop_body(node.child_by_field_name("body"), after, header);
+
+        self.current = header;
+        let condition = node.child_by_field_name("condition");
+        if let Some(condition) = condition {
+            self.push(condition);
+        }
+        self.edge(header, body);
+        if !condition.is_some_and(|condition| always_true(&condition, self.contents)) {
+            self.edge(header, after);
+        }
+        self.current = after;
+    }
+
+    fn for_statement(&mut self, node: &Node<'tree>) {
+        let mut cursor = node.walk();
+        for initialize in node.children_by_field_name("initialize", &mut cursor) {
+            self.push(initialize);
+        }
+        let header = self.next();
+        let mut cursor = node.walk();
+        let conditions: Vec<Node> = node
+            .children_by_field_name("condition", &mut cursor)
+            .collect();
+        for condition in &conditions {
+            self.push(*condition);
+        }
+        let update = self.block();
+        let after = self.block();
+        // the last condition is the one that decides
+        if conditions
+            .last()
+            .is_some_and(|condition| !always_true(condition, self.contents))
+        {
+            self.edge(header, after);
+        }
+
+        self.next();
+        self.loop_body(node.child_by_field_name("body"), after, update);
+        self.current = update;
+        let mut cursor = node.walk();
+        for update in node.children_by_field_name("update", &mut cursor) {
+            self.push(update);
+        }
+        self.edge(update, header);
+        self.current = after;
+    }
+
+    fn foreach_statement(&mut self, node: &Node<'tree>) {
+        if let Some(iterated) = node.named_child(0) {
+            self.push(iterated);
+        }
+        let header = self.next();
+        if let Some(value) = node
+            .named_child(1)
+            .filter(|value| value.kind() != "compound_statement")
+        {
+            self.push(value);
+        }
+        let after = self.block();
+        self.edge(header, after);
+
+        self.next();
+        self.loop_body(node.child_by_field_name("body"), after, header);
+        self.current = after;
+    }
+
+    fn switch_statement(&mut self, node: &Node<'tree>) {
+        if let Some(condition) = node.child_by_field_name("condition") {
+            self.push(condition);
+        }
+        let dispatch = self.current;
+        let after = self.block();
+        // `continue` acts like `break` in a `switch`
+        self.loops.push(Loop { after, next: after });
+
+        let mut has_default = false;
+        let mut previous = None;
+        if let Some(body) = node.child_by_field_name("body") {
+            let mut cursor = body.walk();
+            for case in body.named_children(&mut cursor) {
+                if !matches!(case.kind(), "case_statement" | "default_statement") {
+                    continue;
+                }
+                has_default |= case.kind() == "default_statement";
+                self.current = dispatch;
+                if let Some(value) = case.child_by_field_name("value") {
+                    self.push(value);
+                }
+                let block = self.next();
+                // cases without a `break` fall through to the next one
+                if let Some(previous) = previous {
+                    self.edge(previous, block);
+                }
+
+                let mut cursor = case.walk();
+                let colon = case.children(&mut cursor).position(|child| {
+                    child.kind() == ":" || (child.kind() == ";" && !child.is_named())
+                });
+                let mut cursor = case.walk();
+                let statements: Vec<Node> = case
+                    .children(&mut cursor)
+                    .skip(colon.map_or(0, |colon| colon + 1))
+                    .filter(|child| child.is_named() && child.kind() != "comment")
+                    .collect();
+                for statement in statements {
+                    self.statement(&statement);
+                }
+                self.cases.push((case, self.current));
+                previous = Some(self.current);
+            }
+        }
+
+        self.loops.pop();
+        if let Some(previous) = previous {
+            self.edge(previous, after);
+        }
+        if !has_default {
+            self.edge(dispatch, after);
+        }
+        self.current = after;
+    }
+
+    fn match_expression(&mut self, node: &Node<'tree>) {
+        if let Some(condition) = node.child_by_field_name("condition") {
+            self.push(condition);
+        }
+        let dispatch = self.current;
+        let after = self.block();
+
+        if let Some(body) = node.child_by_field_name("body") {
+            let mut cursor = body.walk();
+            for arm in body.named_children(&mut cursor) {
+                if arm.kind() == "comment" {
+                    continue;
+                }
+                self.current = dispatch;
+                if let Some(conditions) = arm.child_by_field_name("conditional_expressions") {
+                    self.push(conditions);
+                }
+                self.next();
+                let Some(value) = arm.child_by_field_name("return_expression") else {
+                    continue;
+                };
+                self.push(value);
+                if value.kind() != "throw_expression" {
+                    self.edge(self.current, after);
+                }
+            }
+        }
+
+        self.current = after;
+    }
+
+    fn try_statement(&mut self, node: &Node<'tree>) {
+        let mut cursor = node.walk();
+        let clauses: Vec<Node> = node
+            .named_children(&mut cursor)
+            .filter(|clause| matches!(clause.kind(), "catch_clause" | "finally_clause"))
+            .collect();
+        // handlers throw to the handlers outside of this `try`
+        let handlers: Vec<BlockId> = clauses.iter().map(|_| self.block()).collect();
+
+        self.handlers.push(handlers.clone());
+        self.next();
+        if let Some(body) = node.child_by_field_name("body") {
+            self.statement(&body);
+        }
+        self.handlers.pop();
+
+        let after = self.block();
+        let finally = clauses
+            .iter()
+            .position(|clause| clause.kind() == "finally_clause")
+            .map(|i| handlers[i]);
+        let mut ends = vec![self.current];
+        for (clause, handler) in clauses.iter().zip(&handlers) {
+            if clause.kind() == "finally_clause" {
+                continue;
+            }
+            self.current = *handler;
+            if let Some(body) = clause.child_by_field_name("body") {
+                self.statement(&body);
+            }
+            ends.push(self.current);
+        }
+
+        // gotos aren't linked yet, but they don't jump to the end of a `try`
+        let completes = ends.iter().any(|end| self.reachable(*end));
+        let end = finally.unwrap_or(after);
+        for end_block in ends {
+            self.edge(end_block, end);
+        }
+        if let (Some(finally), Some(clause)) = (
+            finally,
+            clauses
+                .iter()
+                .find(|clause| clause.kind() == "finally_clause"),
+        ) {
+            self.current = finally;
+            if let Some(body) = clause.child_by_field_name("body") {
+                self.statement(&body);
+            }
+            // what threw or returned before the `finally` leaves the function after it
+            if completes {
+                self.edge(self.current, after);
+            }
+            self.edge(self.current, ControlFlowGraph::EXIT);
+        }
+        self.current = after;
+    }
+}
+
+impl<'tree> ControlFlowGraph<'tree> {
+    /// The block that everything starts in.
+    pub const ENTRY: BlockId = 0;
+    /// The block that `return` and falling off the end of the function go to.
+    pub const EXIT: BlockId = 1;
+
+    /**
+     * The graph of a function, method or closure, or of the statements of a file.
+     *
+     * `return` goes straight to the exit without running any `finally`, and everything in a
+     * `try` may throw to any of its `catch` blocks. A `finally` goes on to what follows its `try`
+     * only if the `try` or one of its `catch` blocks can finish, and otherwise to the exit. Statements outside of a `try` that throw
+     * leave the function without going anywhere, as does `exit`.
+     */
+    pub fn new(function: &Node<'tree>, file_contents: &str) -> Self {
+        Self::with_never_returning(function, file_contents, &|_| false)
+    }
+
+    /// The graph of a function in which statements that are calls can also never return.
+    pub fn with_never_returning(
+        function: &Node<'tree>,
+        file_contents: &str,
+        never_returns: &dyn Fn(&Node) -> bool,
+    ) -> Self {
+        let mut builder = Builder {
+            contents: file_contents,
+            blocks: vec![BasicBlock::default(), BasicBlock::default()],
+            current: Self::ENTRY,
+            loops: vec![],
+            handlers: vec![],
+            labels: HashMap::new(),
+            gotos: vec![],
+            cases: vec![],
+            never_returns,
+        };
+        builder.next();
+
+        match function.child_by_field_name("body") {
+            // arrow functions have an expression for a body
+            Some(body) if function.kind() == "arrow_function" => {
+                builder.push(body);
+                builder.edge(builder.current, Self::EXIT);
+            }
+            Some(body) => builder.statement(&body),
+            None => builder.statements(function),
+        }
+        let end = builder.current;
+        builder.edge(end, Self::EXIT);
+
+        for (from, label) in std::mem::take(&mut builder.gotos) {
+            if let Some(to) = builder.labels.get(label) {
+                builder.edge(from, *to);
+            }
+        }
+
+        Self {
+            blocks: builder.blocks,
+            end,
+            cases: builder.cases,
+        }
+    }
+
+    /// Blocks that can run right before a block.
+    pub fn predecessors(&self, block: BlockId) -> Vec<BlockId> {
+        (0..self.blocks.len())
+            .filter(|i| self.blocks[*i].successors.contains(&block))
+            .collect()
+    }
+
+    /// Which blocks can be run at all, by their index.
+    pub fn reachable(&self) -> Vec<bool> {
+        let mut reachable = vec![false; self.blocks.len()];
+        let mut stack = vec![Self::ENTRY];
+        while let Some(block) = stack.pop() {
+            if std::mem::replace(&mut reachable[block], true) {
+                continue;
+            }
+            stack.extend(&self.blocks[block].successors);
+        }
+        reachable
+    }
+
+    /// Whether running the function can get to the end of its body, without a `return`.
+    pub fn falls_off_end(&self) -> bool {
+        self.reachable()[self.end]
+    }
+
+    /// The block that a statement or condition, or anything in one, runs in.
+    pub fn block_of(&self, node: &Node) -> Option<BlockId> {
+        self.blocks.iter().position(|block| {
+            block.statements.iter().any(|statement| {
+                statement.start_byte() <= node.start_byte()
+                    && node.end_byte() <= statement.end_byte()
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ControlFlowGraph;
+    use crate::file::php_parser;
+
+    /// Whether each function falls off its end, and the statements it can never run.
+    fn analyze(source: &str) -> Vec<(bool, Vec<String>)> {
+        let tree = php_parser().parse(source, None).unwrap();
+        let root = tree.root_node();
+        let mut cursor = root.walk();
+        root.named_children(&mut cursor)
+            .filter(|node| node.kind() == "function_definition")
+            .map(|function| {
+                let cfg = ControlFlowGraph::new(&function, source);
+                let reachable = cfg.reachable();
+                let unreachable = cfg
+                    .blocks
+                    .iter()
+                    .zip(reachable)
+                    .filter(|(_, reachable)| !reachable)
+                    .flat_map(|(block, _)| &block.statements)
+                    .map(|statement| source[statement.byte_range()].to_string())
+                    .collect();
+                (cfg.falls_off_end(), unreachable)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_control_flow() {
+        let source = "<?php
+function branches($a) {
+    if ($a) { return 1; } elseif ($b) { return 2; } else { throw new E(); }
+    a();
+}
+function loops($a) {
+    while (true) {
+        if ($a) break;
+        continue;
+        b();
+    }
+    foreach ($a as $b) { return 1; }
+    for (;;) {}
+}
+function handlers() {
+    try {
+        return a();
+    } catch (E $e) {
+        b();
+    } finally {
+        c();
+    }
+}
+function cleanup() {
+    try {
+        return a();
+    } finally {
+        c();
+    }
+    g();
+}
+function cases($a) {
+    switch ($a) {
+        case 1:
+            x();
+        case 2:
+            return 1;
+        default:
+            exit(1);
+    }
+    d();
+}
+function jumps($a) {
+    goto end;
+    e();
+    end:
+    $b = match ($a) { 1 => 2, default => throw new E() };
+    return $b;
+}
+function arms($a) {
+    $b = match ($a) { default => throw new E() };
+    f();
+}
+";
+        assert_eq!(
+            vec![
+                (false, vec!["a();".to_string()]),
+                (false, vec!["b();".to_string()]),
+                (true, vec![]),
+                (false, vec!["g();".to_string()]),
+                (false, vec!["d();".to_string()]),
+                (false, vec!["e();".to_string()]),
+                (
+                    false,
+                    vec![
+                        "$b = match ($a) { default => throw new E() };".to_string(),
+                        "f();".to_string()
+                    ]
+                ),
+            ],
+            analyze(source)
+        );
+    }
+}
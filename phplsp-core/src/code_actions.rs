@@ -0,0 +1,141 @@
+use lsp_types::*;
+use serde::{Deserialize, Serialize};
+
+/**
+ * How to fix what a diagnostic reports. Fixes are found along with their diagnostics and kept in
+ * their `data`, which clients send back when they ask for code actions, so the file doesn't have
+ * to be analyzed again.
+ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuickFix {
+    pub title: String,
+    pub edits: Vec<TextEdit>,
+    /// A symbol to rename along with everything that refers to it, in any file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rename: Option<SymbolRename>,
+}
+
+/**
+ * Renaming a declaration everywhere. Finding its references needs the workspace index, so that's
+ * left until the fix is asked for.
+ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SymbolRename {
+    /// Fully qualified name of the declaration.
+    pub name: String,
+    /// What to call it instead, without its namespace or class.
+    pub new_name: String,
+}
+
+impl QuickFix {
+    pub fn attach(&self, diagnostic: &mut Diagnostic) {
+        diagnostic.data = serde_json::to_value(self).ok();
+    }
+
+    /// Attach several ways of fixing a diagnostic, which the user picks from.
+    pub fn attach_all(fixes: &[QuickFix], diagnostic: &mut Diagnostic) {
+        diagnostic.data = serde_json::to_value(fixes).ok();
+    }
+
+    /// The fix of a diagnostic, or the first of them if there are several.
+    pub fn of(diagnostic: &Diagnostic) -> Option<Self> {
+        Self::all(diagnostic).into_iter().next()
+    }
+
+    /// Every fix of a diagnostic.
+    pub fn all(diagnostic: &Diagnostic) -> Vec<Self> {
+        let Some(data) = diagnostic.data.clone() else {
+            return vec![];
+        };
+        match data {
+            serde_json::Value::Array(_) => serde_json::from_value(data).unwrap_or_default(),
+            _ => serde_json::from_value(data).map_or(vec![], |fix| vec![fix]),
+        }
+    }
+}
+
+/**
+ * Quick fixes for the diagnostics in a file that have any. `edit` puts together what each fix
+ * changes, renames included, and fixes that it finds nothing to change for are left out. A fix is
+ * preferred when it's the only one of its diagnostic.
+ */
+pub fn quick_fixes(
+    diagnostics: &[Diagnostic],
+    mut edit: impl FnMut(&QuickFix) -> Option<WorkspaceEdit>,
+) -> Vec<CodeActionOrCommand> {
+    diagnostics
+        .iter()
+        .flat_map(|diagnostic| {
+            let fixes = QuickFix::all(diagnostic);
+            let preferred = fixes.len() == 1;
+            fixes
+                .into_iter()
+                .filter_map(|fix| {
+                    let edit = edit(&fix)?;
+                    Some(CodeActionOrCommand::CodeAction(CodeAction {
+                        title: fix.title,
+                        kind: Some(CodeActionKind::QUICKFIX),
+                        diagnostics: Some(vec![diagnostic.clone()]),
+                        edit: Some(edit),
+                        is_preferred: Some(preferred),
+                        ..CodeAction::default()
+                    }))
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::*;
+
+    use super::quick_fixes;
+    use crate::config::Config;
+    use crate::diagnostics::diagnostics;
+    use crate::edits::WorkspaceEditBuilder;
+    use crate::file::php_parser;
+
+    #[test]
+    fn test_quick_fixes() {
+        let source = "<?php
+function f() {
+    $a = 1;
+    $a = 2;
+    return $a;
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let uri = Url::parse("file:///project/f.php").unwrap();
+        let diagnostics = diagnostics(&tree.root_node(), source, &Config::default());
+
+        let actions = quick_fixes(&diagnostics, |fix| {
+            assert!(fix.rename.is_none());
+            let mut builder = WorkspaceEditBuilder::new();
+            builder.edit(&uri, fix.edits.clone());
+            builder.build(|_| Some((None, source.into())), false).ok()
+        });
+        assert_eq!(1, actions.len());
+        let CodeActionOrCommand::CodeAction(action) = &actions[0] else {
+            panic!("expected a code action");
+        };
+        assert_eq!("Remove the assignment to `$a`", action.title);
+        let edits = &action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+        assert_eq!(
+            vec![TextEdit {
+                range: Range {
+                    start: Position {
+                        line: 2,
+                        character: 0
+                    },
+                    end: Position {
+                        line: 3,
+                        character: 0
+                    },
+                },
+                new_text: String::new(),
+            }],
+            *edits
+        );
+    }
+}
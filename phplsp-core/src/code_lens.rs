@@ -0,0 +1,322 @@
+use lsp_types::{CodeLens, Command, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tree_sitter::Node;
+
+use std::collections::HashMap;
+
+use crate::file::to_position;
+use crate::index::WorkspaceIndex;
+use crate::resolve::key;
+use crate::symbols::{Declaration, DeclarationKind, FileSymbols};
+use crate::tags::{definitions, doc_comments, DefinitionKind};
+
+/**
+ * The command of the lenses that run tests. Its arguments are what to pass to `phpunit`; we
+ * don't run anything ourselves, so clients have to handle it, for example with a terminal task.
+ */
+pub const RUN_TEST_COMMAND: &str = "phplsp.runTest";
+
+/// What a code lens needs to find its declaration again when it's resolved.
+#[derive(Debug, Serialize, Deserialize)]
+struct CodeLensData {
+    uri: Url,
+    name: String,
+}
+
+fn has_modifier(node: &Node, modifier: &str) -> bool {
+    let mut cursor = node.walk();
+    let has = node
+        .children(&mut cursor)
+        .any(|child| child.kind() == modifier);
+    has
+}
+
+/// Whether a class is a test case that can be run, going by the name of the class it extends.
+fn is_test_case(class: &Node, file_contents: &str) -> bool {
+    let mut cursor = class.walk();
+    let Some(base) = class
+        .children(&mut cursor)
+        .find(|child| child.kind() == "base_clause")
+        .and_then(|base| base.named_child(0))
+    else {
+        return false;
+    };
+
+    file_contents[base.byte_range()].ends_with("TestCase")
+        && !has_modifier(class, "abstract_modifier")
+}
+
+/// Whether a method is a test: named `test...`, or marked with `#[Test]` or `@test`.
+fn is_test(method: &Node, doc: Option<&Node>, file_contents: &str) -> bool {
+    let public = {
+        let mut cursor = method.walk();
+        let visibility = method
+            .children(&mut cursor)
+            .find(|child| child.kind() == "visibility_modifier");
+        visibility.is_none_or(|visibility| &file_contents[visibility.byte_range()] == "public")
+    };
+    if !public || has_modifier(method, "static_modifier") {
+        return false;
+    }
+
+    let named_test = method
+        .child_by_field_name("name")
+        .is_some_and(|name| file_contents[name.byte_range()].starts_with("test"));
+    let has_attribute = method
+        .child_by_field_name("attributes")
+        .is_some_and(|attributes| {
+            let mut attributes = vec![attributes];
+            let mut found = false;
+            while let Some(node) = attributes.pop() {
+                if node.kind() == "attribute" {
+                    let name = node
+                        .named_child(0)
+                        .map(|name| &file_contents[name.byte_range()]);
+                    found |= name.is_some_and(|name| name.rsplit('\\').next() == Some("Test"));
+                    continue;
+                }
+                let mut cursor = node.walk();
+                attributes.extend(node.named_children(&mut cursor));
+            }
+            found
+        });
+    let documented = doc.is_some_and(|doc| {
+        file_contents[doc.byte_range()]
+            .split_whitespace()
+            .any(|word| word.trim_start_matches('*') == "@test")
+    });
+
+    named_test || has_attribute || documented
+}
+
+/// A lens that runs the tests matching a `phpunit --filter`.
+fn run_test_lens(declaration: &Declaration, title: &str, filter: String, uri: &Url) -> CodeLens {
+    let path = match uri.to_file_path() {
+        Ok(path) => path.to_string_lossy().into_owned(),
+        Err(_) => uri.to_string(),
+    };
+
+    CodeLens {
+        range: declaration.range,
+        command: Some(Command {
+            title: title.to_string(),
+            command: RUN_TEST_COMMAND.to_string(),
+            arguments: Some(vec![
+                Value::String("--filter".to_string()),
+                Value::String(filter),
+                Value::String(path),
+            ]),
+        }),
+        data: None,
+    }
+}
+
+/// "Run class" and "Run test" lenses on PHPUnit test cases and their tests.
+fn test_lenses(
+    root_node: &Node,
+    file_contents: &str,
+    uri: &Url,
+    symbols: &FileSymbols,
+) -> Vec<CodeLens> {
+    let declarations: HashMap<(u32, u32), &Declaration> = symbols
+        .declarations
+        .iter()
+        .map(|declaration| (key(&declaration.range.start), declaration))
+        .collect();
+    let docs = doc_comments(root_node, file_contents);
+    // test names are matched as regular expressions
+    let escape = |name: &str| name.replace('\\', "\\\\");
+
+    let mut lenses = vec![];
+    for definition in definitions(root_node, file_contents) {
+        let Some(declaration) = definition
+            .name
+            .and_then(|name| declarations.get(&key(&to_position(&name.start_position()))))
+        else {
+            continue;
+        };
+
+        match definition.kind {
+            DefinitionKind::Class if is_test_case(&definition.node, file_contents) => {
+                let filter = format!("/^{}::/", escape(&declaration.name));
+                lenses.push(run_test_lens(declaration, "Run class", filter, uri));
+            }
+            DefinitionKind::Method => {
+                let class = definition
+                    .node
+                    .parent()
+                    .and_then(|body| body.parent())
+                    .filter(|class| class.kind() == "class_declaration");
+                let (Some(class), Some(class_name), Some(method)) =
+                    (class, declaration.class_name(), declaration.member_name())
+                else {
+                    continue;
+                };
+                if is_test_case(&class, file_contents)
+                    && is_test(
+                        &definition.node,
+                        docs.get(&definition.node.id()),
+                        file_contents,
+                    )
+                {
+                    let filter =
+                        format!("/^{}::{}( with data set .*)?$/", escape(class_name), method);
+                    lenses.push(run_test_lens(declaration, "Run test", filter, uri));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    lenses
+}
+
+/**
+ * Code lenses for a file: "Run class" and "Run test" on PHPUnit tests, and an unresolved lens
+ * above every class-like, method and function for its number of references.
+ *
+ * Counting references needs the whole index, so that is left to [`resolve_code_lens`], which
+ * clients only call for the lenses they show.
+ */
+pub fn code_lenses(
+    root_node: &Node,
+    file_contents: &str,
+    uri: &Url,
+    symbols: &FileSymbols,
+) -> Vec<CodeLens> {
+    let references = symbols
+        .declarations
+        .iter()
+        .filter(|declaration| {
+            matches!(
+                declaration.kind,
+                DeclarationKind::Class
+                    | DeclarationKind::Interface
+                    | DeclarationKind::Trait
+                    | DeclarationKind::Enum
+                    | DeclarationKind::Function
+                    | DeclarationKind::Method
+            )
+        })
+        .map(|declaration| CodeLens {
+            range: declaration.range,
+            command: None,
+            data: serde_json::to_value(CodeLensData {
+                uri: uri.clone(),
+                name: declaration.name.clone(),
+            })
+            .ok(),
+        });
+
+    let mut lenses = test_lenses(root_node, file_contents, uri, symbols);
+    lenses.extend(references);
+    lenses.sort_by_key(|lens| key(&lens.range.start));
+    lenses
+}
+
+/// Fill in the number of references of a lens from [`code_lenses`].
+pub fn resolve_code_lens(mut lens: CodeLens, index: &WorkspaceIndex) -> CodeLens {
+    let count = lens
+        .data
+        .take()
+        .and_then(|data| serde_json::from_value::<CodeLensData>(data).ok())
+        .and_then(|data| index.find_declaration(&data.name))
+        .map_or(0, |declaration| index.references(declaration).len());
+
+    lens.command = Some(Command {
+        title: match count {
+            1 => "1 reference".to_string(),
+            _ => format!("{} references", count),
+        },
+        // only there to be shown
+        command: String::new(),
+        arguments: None,
+    });
+    lens
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::Url;
+
+    use super::{code_lenses, resolve_code_lens, RUN_TEST_COMMAND};
+    use crate::file::php_parser;
+    use crate::index::WorkspaceIndex;
+    use crate::symbols::file_symbols;
+
+    #[test]
+    fn test_reference_counts() {
+        let source = "<?php
+class Greeter {
+    const LOUD = true;
+    public function greet() { return helper(); }
+}
+function helper() {}
+(new Greeter)->greet();
+helper();
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let uri = Url::parse("file:///project/Greeter.php").unwrap();
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(&uri, symbols.clone());
+
+        let titles: Vec<String> = code_lenses(&tree.root_node(), source, &uri, &symbols)
+            .into_iter()
+            .map(|lens| resolve_code_lens(lens, &index).command.unwrap().title)
+            .collect();
+        assert_eq!(vec!["1 reference", "1 reference", "2 references"], titles);
+    }
+
+    #[test]
+    fn test_phpunit() {
+        let source = "<?php
+namespace Tests;
+use PHPUnit\\Framework\\TestCase;
+final class FooTest extends TestCase {
+    public function testAdds() {}
+    #[Test]
+    public function subtracts() {}
+    /** @test */
+    public function multiplies() {}
+    private function testHelper() {}
+    public function setUp(): void {}
+}
+abstract class BaseTest extends TestCase {
+    public function testNothing() {}
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let uri = Url::parse("file:///project/tests/FooTest.php").unwrap();
+
+        let runs: Vec<(String, String)> = code_lenses(&tree.root_node(), source, &uri, &symbols)
+            .into_iter()
+            .filter_map(|lens| lens.command)
+            .filter(|command| command.command == RUN_TEST_COMMAND)
+            .map(|command| {
+                let arguments = command.arguments.unwrap();
+                (command.title, arguments[1].as_str().unwrap().to_string())
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                ("Run class".to_string(), "/^Tests\\\\FooTest::/".to_string()),
+                (
+                    "Run test".to_string(),
+                    "/^Tests\\\\FooTest::testAdds( with data set .*)?$/".to_string()
+                ),
+                (
+                    "Run test".to_string(),
+                    "/^Tests\\\\FooTest::subtracts( with data set .*)?$/".to_string()
+                ),
+                (
+                    "Run test".to_string(),
+                    "/^Tests\\\\FooTest::multiplies( with data set .*)?$/".to_string()
+                ),
+            ],
+            runs
+        );
+    }
+}
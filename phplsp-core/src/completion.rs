@@ -0,0 +1,638 @@
+use lsp_types::*;
+use tree_sitter::{Node, Point};
+
+use std::collections::HashMap;
+
+use crate::file::{to_point, LineIndex, PositionEncoding};
+use crate::index::WorkspaceIndex;
+use crate::resolve::Resolver;
+use crate::symbols::{DeclarationKind, FileSymbols};
+use crate::types::{Type, TypeInference};
+
+/// Most declarations from the index that we offer at once.
+const COMPLETION_LIMIT: usize = 128;
+
+/// Constructors with at least this many parameters get a completion that passes all of them.
+const NAMED_ARGUMENTS_MIN: usize = 3;
+
+/// Node kinds whose variables are their own. Arrow functions see the variables around them.
+const SCOPES: &[&str] = &[
+    "function_definition",
+    "method_declaration",
+    "anonymous_function",
+    "program",
+];
+
+/// Nodes in a scope whose variables are not part of it.
+const OTHER_SCOPES: &[&str] = &[
+    "function_definition",
+    "method_declaration",
+    "anonymous_function",
+    "class_declaration",
+];
+
+/// Where the name being typed before a column starts: a name, maybe qualified, or a variable.
+fn prefix_start(line: &str) -> usize {
+    let start = line
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| c.is_alphanumeric() || *c == '_' || *c == '\\')
+        .last()
+        .map_or(line.len(), |(i, _)| i);
+    match line[..start].ends_with('$') {
+        true => start - 1,
+        false => start,
+    }
+}
+
+/// How well a candidate of some type fits where it's going, best first.
+fn tier(types: &TypeInference, expected: Option<&Type>, ty: Option<&Type>) -> u8 {
+    match (expected, ty) {
+        (Some(expected), Some(ty)) if types.accepts(expected, ty) => 0,
+        (Some(_), Some(_)) => 2,
+        _ => 1,
+    }
+}
+
+/// The variables used in a scope before a byte, with the last node that names each of them.
+fn variables<'tree>(
+    node: &Node<'tree>,
+    file_contents: &'tree str,
+    before: usize,
+    found: &mut HashMap<&'tree str, Node<'tree>>,
+) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        if child.start_byte() >= before {
+            break;
+        }
+        if OTHER_SCOPES.contains(&child.kind()) {
+            continue;
+        }
+        if child.kind() == "variable_name" && child.end_byte() < before {
+            found.insert(&file_contents[child.byte_range()], child);
+        }
+        variables(&child, file_contents, before, found);
+    }
+}
+
+/// The type a variable has after the node that last names it.
+fn variable_type(types: &TypeInference, variable: &Node) -> Option<Type> {
+    let assignment = variable
+        .parent()
+        .filter(|parent| parent.kind() == "assignment_expression")
+        .filter(|parent| parent.child_by_field_name("left") == Some(*variable));
+    match assignment {
+        Some(assignment) => types.expression(&assignment.child_by_field_name("right")?),
+        None => types.expression(variable),
+    }
+}
+
+/**
+ * The type expected of a value at a point, which is where the name being typed starts or where
+ * one would be typed.
+ */
+fn expected_at(types: &TypeInference, root_node: &Node, point: Point, typed: bool) -> Option<Type> {
+    let mut node = root_node.descendant_for_point_range(point, point)?;
+    if !typed {
+        if !node.is_named() {
+            node = node.parent()?;
+        }
+        return match node.kind() {
+            "arguments" => {
+                let mut cursor = node.walk();
+                let index = node
+                    .named_children(&mut cursor)
+                    .filter(|argument| argument.end_position() <= point)
+                    .count();
+                types.parameter_type(&node.parent()?, index)
+            }
+            "return_statement" => types.enclosing_return(&node),
+            _ => None,
+        };
+    }
+
+    // from the name to the whole expression it starts, but not the argument it's passed as
+    while let Some(parent) = node.parent() {
+        if parent.byte_range() != node.byte_range() || parent.kind() == "argument" {
+            break;
+        }
+        node = parent;
+    }
+    types.expected(&node)
+}
+
+/// A value of a type to start an argument off with.
+fn placeholder(ty: Option<&Type>) -> String {
+    match ty {
+        Some(Type::Int) => "0".to_string(),
+        Some(Type::Float) => "0.0".to_string(),
+        Some(Type::String) => "''".to_string(),
+        Some(Type::Bool) => "false".to_string(),
+        Some(Type::Array(_) | Type::Shape(_)) => "[]".to_string(),
+        Some(ty @ Type::Class(_)) => format!("new {}()", ty),
+        Some(Type::Union(members)) if !members.contains(&Type::Null) => {
+            placeholder(members.first())
+        }
+        _ => "null".to_string(),
+    }
+}
+
+/// Text that a snippet inserts as it is.
+fn escape_snippet(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('$', "\\$")
+        .replace('}', "\\}")
+}
+
+/**
+ * A snippet for `new Class(` with nothing between the parentheses yet that passes every
+ * parameter of the constructor by name, one per line, starting with a value of its type.
+ */
+fn named_arguments(
+    types: &TypeInference,
+    root_node: &Node,
+    point: Point,
+    indentation: &str,
+) -> Option<CompletionItem> {
+    let mut arguments = root_node.descendant_for_point_range(point, point)?;
+    if !arguments.is_named() {
+        arguments = arguments.parent()?;
+    }
+    let creation = arguments
+        .parent()
+        .filter(|creation| creation.kind() == "object_creation_expression")?;
+    if arguments.kind() != "arguments" || arguments.named_child_count() > 0 {
+        return None;
+    }
+
+    let constructor = types.callee(&creation)?;
+    let parameters = constructor.parameters();
+    // variadic parameters can't be passed by name
+    if parameters
+        .iter()
+        .any(|parameter| parameter.starts_with("..."))
+        || parameters.len() < NAMED_ARGUMENTS_MIN
+    {
+        return None;
+    }
+
+    let mut snippet = "\n".to_string();
+    for (i, parameter) in parameters.iter().enumerate() {
+        let ty = constructor
+            .parameter_type(i)
+            .and_then(|ty| Type::parse(ty, constructor.namespace(), constructor.class_name()));
+        snippet.push_str(&format!(
+            "{}    {}: ${{{}:{}}},\n",
+            indentation,
+            parameter.trim_start_matches('$'),
+            i + 1,
+            escape_snippet(&placeholder(ty.as_ref()))
+        ));
+    }
+    snippet.push_str(indentation);
+
+    Some(CompletionItem {
+        label: "Fill all named arguments".to_string(),
+        kind: Some(CompletionItemKind::SNIPPET),
+        detail: Some(constructor.signature.clone()),
+        preselect: Some(true),
+        sort_text: Some("0".to_string()),
+        insert_text: Some(snippet),
+        insert_text_format: Some(InsertTextFormat::SNIPPET),
+        insert_text_mode: Some(InsertTextMode::AS_IS),
+        ..CompletionItem::default()
+    })
+}
+
+/// Constants, enum cases and static methods and properties of a class, and of its ancestors.
+fn static_members(resolver: &Resolver, class: &Node, prefix: &str) -> Vec<CompletionItem> {
+    let Some(class) = resolver.accessed_class(class) else {
+        return vec![];
+    };
+    resolver
+        .members(class)
+        .into_iter()
+        .filter(|member| {
+            member
+                .short_name()
+                .trim_start_matches('$')
+                .to_lowercase()
+                .starts_with(&prefix.trim_start_matches('$').to_lowercase())
+        })
+        .filter_map(|member| {
+            let kind = match member.kind {
+                DeclarationKind::Constant => CompletionItemKind::CONSTANT,
+                DeclarationKind::EnumCase => CompletionItemKind::ENUM_MEMBER,
+                DeclarationKind::Method if member.has_modifier("static") => {
+                    CompletionItemKind::METHOD
+                }
+                DeclarationKind::Property if member.has_modifier("static") => {
+                    CompletionItemKind::PROPERTY
+                }
+                _ => return None,
+            };
+            Some(CompletionItem {
+                label: member.short_name().to_string(),
+                kind: Some(kind),
+                detail: Some(member.signature.clone()),
+                ..CompletionItem::default()
+            })
+        })
+        .collect()
+}
+
+/**
+ * The keys of the array shape of the variable that `before` ends with the `[` of, with the quote
+ * that was typed after it, if any.
+ */
+fn shape_keys(
+    types: &TypeInference,
+    root_node: &Node,
+    before: &str,
+    line: u32,
+    prefix: &str,
+) -> Option<Vec<CompletionItem>> {
+    let (before, quote) = match before.strip_suffix(['\'', '"']) {
+        Some(rest) => (rest, before.chars().last()),
+        None => (before, None),
+    };
+    let variable = before.strip_suffix('[')?.trim_end();
+    let start = prefix_start(variable);
+    if !variable[start..].starts_with('$') {
+        return None;
+    }
+    let point = to_point(&Position::new(line, start as u32));
+    let mut variable = root_node.descendant_for_point_range(point, point)?;
+    while variable.kind() != "variable_name" {
+        variable = variable.parent()?;
+    }
+    let Some(Type::Shape(entries)) = types.expression(&variable) else {
+        return None;
+    };
+
+    let items = entries
+        .into_iter()
+        .filter(|(key, _)| key.starts_with(prefix))
+        .map(|(key, ty)| {
+            let numeric = key.parse::<i64>().is_ok();
+            let insert_text = match (quote, numeric) {
+                (None, false) => Some(format!("'{}'", key)),
+                _ => None,
+            };
+            CompletionItem {
+                label: key,
+                kind: Some(CompletionItemKind::FIELD),
+                label_details: Some(CompletionItemLabelDetails {
+                    detail: None,
+                    description: Some(ty.to_string()),
+                }),
+                insert_text,
+                ..CompletionItem::default()
+            }
+        })
+        .collect();
+    Some(items)
+}
+
+/**
+ * Variables in scope and functions, classes and constants from the index that could complete the
+ * name before a position.
+ *
+ * Where a value of some type is expected, like an argument of a function whose parameter has a
+ * type, candidates of that type are ranked first, and those of some other type last, with the
+ * type that was expected next to them. Constructors with many parameters can have them all
+ * filled in by name.
+ *
+ * After `::`, the constants, enum cases and static members of the class come instead, including
+ * those it inherits, like constants of the interfaces it implements.
+ *
+ * After the `[` of an array whose keys are known, like the matches of a literal pattern, those
+ * keys come instead.
+ */
+pub fn completions(
+    root_node: &Node,
+    file_contents: &str,
+    symbols: &FileSymbols,
+    index: &WorkspaceIndex,
+    position: &Position,
+) -> Vec<CompletionItem> {
+    let Some(line) = file_contents.lines().nth(position.line as usize) else {
+        return vec![];
+    };
+    let mut column = (position.character as usize).min(line.len());
+    while !line.is_char_boundary(column) {
+        column -= 1;
+    }
+    let start = prefix_start(&line[..column]);
+    let prefix = &line[start..column];
+    let resolver = Resolver::new(file_contents, symbols, index);
+    let before = line[..start].trim_end();
+    if let Some(scope) = before.strip_suffix("::") {
+        let scope = scope.trim_end();
+        let point = to_point(&Position::new(position.line, prefix_start(scope) as u32));
+        return root_node
+            .descendant_for_point_range(point, point)
+            .map(|class| static_members(&resolver, &class, prefix))
+            .unwrap_or_default();
+    }
+    // members of objects need the class of what comes before them, which we don't look into yet
+    if before.ends_with("->") {
+        return vec![];
+    }
+
+    let types = TypeInference::new(file_contents, &resolver);
+    if let Some(keys) = shape_keys(&types, root_node, before, position.line, prefix) {
+        return keys;
+    }
+    let point = to_point(&Position::new(position.line, start as u32));
+    let expected = expected_at(&types, root_node, point, !prefix.is_empty())
+        .filter(|expected| *expected != Type::Void);
+
+    let mut candidates: Vec<(String, CompletionItemKind, Option<String>, Option<Type>)> = vec![];
+    if prefix.is_empty() || prefix.starts_with('$') {
+        let Some(mut scope) = root_node.descendant_for_point_range(point, point) else {
+            return vec![];
+        };
+        while !SCOPES.contains(&scope.kind()) {
+            match scope.parent() {
+                Some(parent) => scope = parent,
+                None => break,
+            }
+        }
+
+        let offset = LineIndex::new(file_contents).offset(
+            Position::new(position.line, start as u32),
+            PositionEncoding::Utf8,
+        );
+        let mut found = HashMap::new();
+        variables(&scope, file_contents, offset, &mut found);
+        let mut names: Vec<&str> = found.keys().copied().collect();
+        names.sort();
+        for name in names {
+            let ty = variable_type(&types, &found[name]);
+            candidates.push((name.to_string(), CompletionItemKind::VARIABLE, None, ty));
+        }
+    }
+    if !prefix.starts_with('$') {
+        for (_, declaration) in index.search(prefix.trim_start_matches('\\'), COMPLETION_LIMIT) {
+            let (kind, ty) = match declaration.kind {
+                _ if declaration.class_name().is_some() => continue,
+                DeclarationKind::Function => (
+                    CompletionItemKind::FUNCTION,
+                    declaration.return_type().and_then(|return_type| {
+                        Type::parse(return_type, declaration.namespace(), None)
+                    }),
+                ),
+                DeclarationKind::Constant => (CompletionItemKind::CONSTANT, None),
+                DeclarationKind::Interface => (CompletionItemKind::INTERFACE, None),
+                DeclarationKind::Enum => (CompletionItemKind::ENUM, None),
+                _ => (CompletionItemKind::CLASS, None),
+            };
+            candidates.push((
+                declaration.short_name().to_string(),
+                kind,
+                Some(declaration.signature.clone()),
+                ty,
+            ));
+        }
+    }
+
+    let indentation = &line[..line.len() - line.trim_start().len()];
+    let named = match prefix.is_empty() {
+        true => named_arguments(&types, root_node, point, indentation),
+        false => None,
+    };
+    let ranked = candidates
+        .into_iter()
+        .enumerate()
+        .map(|(i, (label, kind, detail, ty))| {
+            let tier = tier(&types, expected.as_ref(), ty.as_ref());
+            let description = ty.map(|ty| match (tier, &expected) {
+                (2, Some(expected)) => format!("{}, but {} is expected", ty, expected),
+                _ => ty.to_string(),
+            });
+            CompletionItem {
+                label,
+                kind: Some(kind),
+                detail,
+                label_details: description.map(|description| CompletionItemLabelDetails {
+                    detail: None,
+                    description: Some(description),
+                }),
+                sort_text: Some(format!("{}{:04}", tier, i)),
+                ..CompletionItem::default()
+            }
+        });
+    named.into_iter().chain(ranked).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::*;
+
+    use super::completions;
+    use crate::file::php_parser;
+    use crate::index::WorkspaceIndex;
+    use crate::symbols::file_symbols;
+
+    #[test]
+    fn test_completions() {
+        let source = "<?php
+function total(int $count): float {}
+function label(): string {}
+function greet(string $name) {}
+function f(int $a) {
+    $s = 'x';
+    greet(\x20);
+    return $
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(&Url::parse("file:///A.php").unwrap(), symbols.clone());
+        let ranked = |line, character| {
+            let mut items = completions(
+                &tree.root_node(),
+                source,
+                &symbols,
+                &index,
+                &Position::new(line, character),
+            );
+            items.sort_by(|a, b| a.sort_text.cmp(&b.sort_text));
+            items
+                .into_iter()
+                .map(|item| {
+                    let description = item
+                        .label_details
+                        .and_then(|details| details.description)
+                        .unwrap_or_default();
+                    format!("{} {}", item.label, description).trim().to_string()
+                })
+                .collect::<Vec<String>>()
+        };
+
+        // strings first when greeting, and ints last
+        assert_eq!(
+            vec![
+                "$s string",
+                "label string",
+                "f",
+                "greet",
+                "$a int, but string is expected",
+                "total float, but string is expected",
+            ],
+            ranked(6, 10)
+        );
+        // the function has no return type, so nothing is preferred
+        assert_eq!(vec!["$a int", "$s string"], ranked(7, 12));
+    }
+
+    #[test]
+    fn test_named_arguments() {
+        let source = "<?php
+namespace App;
+class Money {}
+class Order {
+    public function __construct(
+        private int $id,
+        private ?string $note,
+        private Money $total,
+        private array $lines = [],
+    ) {}
+}
+class Point {
+    public function __construct(int $x, int $y) {}
+}
+function f() {
+    $order = new Order();
+    $point = new Point();
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(&Url::parse("file:///A.php").unwrap(), symbols.clone());
+        let first = |line, character| {
+            completions(
+                &tree.root_node(),
+                source,
+                &symbols,
+                &index,
+                &Position::new(line, character),
+            )
+            .into_iter()
+            .next()
+            .filter(|item| item.kind == Some(CompletionItemKind::SNIPPET))
+            .and_then(|item| item.insert_text)
+        };
+
+        assert_eq!(
+            Some(
+                "
+        id: ${1:0},
+        note: ${2:null},
+        total: ${3:new Money()},
+        lines: ${4:[]},
+    "
+                .to_string()
+            ),
+            first(15, 23)
+        );
+        // too few parameters to bother
+        assert_eq!(None, first(16, 23));
+    }
+
+    #[test]
+    fn test_static_members() {
+        let source = "<?php
+interface HasLimit { const LIMIT = 10; }
+class Repo implements HasLimit {
+    final public const TABLE = 'repos';
+    public static $cache = [];
+    public static function find() {}
+    public function save() {}
+    function f() {
+        self::
+    }
+}
+Repo::L
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(&Url::parse("file:///A.php").unwrap(), symbols.clone());
+        let labels = |line, character| {
+            completions(
+                &tree.root_node(),
+                source,
+                &symbols,
+                &index,
+                &Position::new(line, character),
+            )
+            .into_iter()
+            .map(|item| (item.label, item.detail.unwrap_or_default()))
+            .collect::<Vec<(String, String)>>()
+        };
+
+        assert_eq!(
+            vec![
+                (
+                    "TABLE".to_string(),
+                    "final public const TABLE = 'repos'".to_string()
+                ),
+                (
+                    "$cache".to_string(),
+                    "public static $cache = [];".to_string()
+                ),
+                (
+                    "find".to_string(),
+                    "public static function find()".to_string()
+                ),
+                ("LIMIT".to_string(), "const LIMIT = 10".to_string()),
+            ],
+            labels(8, 14)
+        );
+        assert_eq!(
+            vec![("LIMIT".to_string(), "const LIMIT = 10".to_string())],
+            labels(11, 7)
+        );
+    }
+
+    #[test]
+    fn test_shape_keys() {
+        let source = "<?php
+if (preg_match('/(?<year>\\d{4})-(\\d+)/', $date, $matches)) {
+    $matches[
+    $matches['y
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let index = WorkspaceIndex::default();
+        let keys = |line, character| {
+            completions(
+                &tree.root_node(),
+                source,
+                &symbols,
+                &index,
+                &Position::new(line, character),
+            )
+            .into_iter()
+            .map(|item| (item.label, item.insert_text))
+            .collect::<Vec<(String, Option<String>)>>()
+        };
+
+        assert_eq!(
+            vec![
+                ("0".to_string(), None),
+                ("year".to_string(), Some("'year'".to_string())),
+                ("1".to_string(), None),
+                ("2".to_string(), None),
+            ],
+            keys(2, 13)
+        );
+        assert_eq!(vec![("year".to_string(), None)], keys(3, 15));
+    }
+}
@@ -0,0 +1,654 @@
+//! Editing `composer.json`: its autoload configuration is checked against the directories it
+//! names, and packages that `composer.lock` has can be completed where packages are required.
+
+use lsp_types::*;
+
+use std::ops::Range as Span;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::file::{is_within, LineIndex, PositionEncoding};
+use crate::php_namespace::PhpNamespace;
+
+pub const COMPOSER_FILE: &str = "composer.json";
+pub const LOCK_FILE: &str = "composer.lock";
+
+/// The sections of `composer.json` that configure autoloading.
+const AUTOLOAD_SECTIONS: &[&str] = &["autoload", "autoload-dev"];
+
+/// The sections of `composer.json` whose keys are package names.
+const PACKAGE_SECTIONS: &[&str] = &[
+    "require",
+    "require-dev",
+    "conflict",
+    "replace",
+    "provide",
+    "suggest",
+];
+
+pub fn is_composer_file(uri: &Url) -> bool {
+    uri.path().rsplit('/').next() == Some(COMPOSER_FILE)
+}
+
+#[derive(Debug)]
+enum JsonValue {
+    Null,
+    Bool,
+    Number,
+    String(String),
+    Array(Vec<Json>),
+    /// Members by their keys, which may not have a value yet while the file is being edited.
+    Object(Vec<(Json, Option<Json>)>),
+}
+
+/// A JSON value, and where it is in the text.
+#[derive(Debug)]
+struct Json {
+    value: JsonValue,
+    span: Span<usize>,
+}
+
+impl Json {
+    fn get(&self, key: &str) -> Option<&Json> {
+        match &self.value {
+            JsonValue::Object(members) => members
+                .iter()
+                .find(|(name, _)| name.as_str() == Some(key))
+                .and_then(|(_, value)| value.as_ref()),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match &self.value {
+            JsonValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn members(&self) -> &[(Json, Option<Json>)] {
+        match &self.value {
+            JsonValue::Object(members) => members,
+            _ => &[],
+        }
+    }
+}
+
+/**
+ * Reads JSON with where each value is, which `serde_json` doesn't keep. Text that isn't JSON ends
+ * the values it's in rather than failing, so that files being edited can still be completed in.
+ */
+struct JsonParser<'a> {
+    text: &'a str,
+    at: usize,
+}
+
+impl JsonParser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.at).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+            self.at += 1;
+        }
+    }
+
+    fn value(&mut self) -> Option<Json> {
+        self.skip_whitespace();
+        let start = self.at;
+        let value = match self.peek()? {
+            b'{' => self.object(),
+            b'[' => self.array(),
+            b'"' => JsonValue::String(self.string()),
+            _ => {
+                let length = self.text[start..]
+                    .find(|c: char| !c.is_ascii_alphanumeric() && !"+-.".contains(c))
+                    .unwrap_or(self.text.len() - start);
+                if length == 0 {
+                    return None;
+                }
+                self.at += length;
+                match &self.text[start..self.at] {
+                    "null" => JsonValue::Null,
+                    "true" | "false" => JsonValue::Bool,
+                    _ => JsonValue::Number,
+                }
+            }
+        };
+        Some(Json {
+            value,
+            span: start..self.at,
+        })
+    }
+
+    /// A string from its opening quote. One that isn't closed ends with its line.
+    fn string(&mut self) -> String {
+        self.at += 1;
+        let mut value = String::new();
+        let mut chars = self.text[self.at..].char_indices();
+        let length = loop {
+            match chars.next() {
+                None => break self.text.len() - self.at,
+                Some((i, '"')) => break i + 1,
+                Some((i, '\n')) => break i,
+                Some((_, '\\')) => match chars.next() {
+                    Some((_, 'n')) => value.push('\n'),
+                    Some((_, 't')) => value.push('\t'),
+                    Some((_, 'r')) => value.push('\r'),
+                    Some((_, 'b')) => value.push('\u{8}'),
+                    Some((_, 'f')) => value.push('\u{c}'),
+                    Some((i, 'u')) => {
+                        let hex = self.text.get(self.at + i + 1..self.at + i + 5);
+                        let c = hex
+                            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                            .and_then(char::from_u32);
+                        value.push(c.unwrap_or(char::REPLACEMENT_CHARACTER));
+                        chars.nth(3);
+                    }
+                    Some((_, c)) => value.push(c),
+                    None => break self.text.len() - self.at,
+                },
+                Some((_, c)) => value.push(c),
+            }
+        };
+        self.at += length;
+        value
+    }
+
+    fn object(&mut self) -> JsonValue {
+        self.at += 1;
+        let mut members = vec![];
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b'}') => {
+                    self.at += 1;
+                    break;
+                }
+                Some(b',') => self.at += 1,
+                Some(b'"') => {
+                    let start = self.at;
+                    let key = Json {
+                        value: JsonValue::String(self.string()),
+                        span: start..self.at,
+                    };
+                    self.skip_whitespace();
+                    let value = match self.peek() {
+                        Some(b':') => {
+                            self.at += 1;
+                            self.value()
+                        }
+                        _ => None,
+                    };
+                    members.push((key, value));
+                }
+                _ => break,
+            }
+        }
+        JsonValue::Object(members)
+    }
+
+    fn array(&mut self) -> JsonValue {
+        self.at += 1;
+        let mut values = vec![];
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b']') => {
+                    self.at += 1;
+                    break;
+                }
+                Some(b',') => self.at += 1,
+                _ => match self.value() {
+                    Some(value) => values.push(value),
+                    None => break,
+                },
+            }
+        }
+        JsonValue::Array(values)
+    }
+}
+
+fn parse(text: &str) -> Option<Json> {
+    JsonParser { text, at: 0 }.value()
+}
+
+fn to_position(text: &str, offset: usize) -> Position {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    Position::new(
+        before.matches('\n').count() as u32,
+        (offset - line_start) as u32,
+    )
+}
+
+fn to_range(text: &str, span: &Span<usize>) -> Range {
+    Range::new(to_position(text, span.start), to_position(text, span.end))
+}
+
+fn diagnostic(
+    range: Range,
+    severity: DiagnosticSeverity,
+    code: &str,
+    message: String,
+) -> Diagnostic {
+    Diagnostic {
+        range,
+        severity: Some(severity),
+        code: Some(NumberOrString::String(code.to_string())),
+        source: Some("phplsp".to_string()),
+        message,
+        ..Diagnostic::default()
+    }
+}
+
+/// Whether a namespace prefix is names separated by `\`, leaving out the `\` at its end.
+fn is_namespace_prefix(prefix: &str) -> bool {
+    prefix
+        .strip_suffix('\\')
+        .unwrap_or(prefix)
+        .split('\\')
+        .all(|name| {
+            let mut chars = name.chars();
+            chars
+                .next()
+                .is_some_and(|c| c.is_alphabetic() || c == '_' || !c.is_ascii())
+                && chars.all(|c| c.is_alphanumeric() || c == '_' || !c.is_ascii())
+        })
+}
+
+/// The strings of a value that's a string or an array of strings.
+fn strings(value: &Json) -> Option<Vec<&Json>> {
+    match &value.value {
+        JsonValue::String(_) => Some(vec![value]),
+        JsonValue::Array(values) => values
+            .iter()
+            .map(|value| value.as_str().map(|_| value))
+            .collect(),
+        _ => None,
+    }
+}
+
+struct Checker<'a> {
+    text: &'a str,
+    dir: &'a Path,
+    readable: Option<&'a [PathBuf]>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker<'_> {
+    /// Whether a path is there, taking what we may not look at to be.
+    fn exists(&self, path: &Path, exists: fn(&Path) -> bool) -> bool {
+        let readable = self
+            .readable
+            .is_none_or(|readable| is_within(path, readable));
+        !readable || exists(path)
+    }
+
+    fn error(&mut self, span: &Span<usize>, message: String) {
+        self.diagnostics.push(diagnostic(
+            to_range(self.text, span),
+            DiagnosticSeverity::ERROR,
+            "invalid-autoload",
+            message,
+        ));
+    }
+
+    fn missing(&mut self, span: &Span<usize>, message: String) {
+        self.diagnostics.push(diagnostic(
+            to_range(self.text, span),
+            DiagnosticSeverity::WARNING,
+            "missing-autoload-path",
+            message,
+        ));
+    }
+
+    /// `"psr-4": { "App\\": "src/" }`, and `psr-0`, whose prefixes needn't end with `\`.
+    fn namespaces(&mut self, kind: &str, namespaces: &Json) {
+        if !matches!(namespaces.value, JsonValue::Object(_)) {
+            self.error(
+                &namespaces.span,
+                format!("`{}` expects an object of namespace prefixes", kind),
+            );
+            return;
+        }
+        for (key, value) in namespaces.members() {
+            let prefix = key.as_str().unwrap_or_default();
+            if kind == "psr-4" && !prefix.is_empty() && !prefix.ends_with('\\') {
+                self.error(
+                    &key.span,
+                    format!("PSR-4 prefix `{}` must end with `\\`", prefix),
+                );
+            } else if !prefix.is_empty() && !is_namespace_prefix(prefix) {
+                self.error(&key.span, format!("`{}` is not a namespace prefix", prefix));
+            }
+
+            let Some(value) = value else {
+                continue;
+            };
+            let Some(dirs) = strings(value) else {
+                self.error(
+                    &value.span,
+                    "expected a directory or an array of directories".to_string(),
+                );
+                continue;
+            };
+            for dir in dirs {
+                let name = dir.as_str().unwrap_or_default();
+                if !self.exists(&self.dir.join(name), Path::is_dir) {
+                    self.missing(&dir.span, format!("directory `{}` doesn't exist", name));
+                }
+            }
+        }
+    }
+
+    /// `"classmap": ["lib/"]` and `"files": ["helpers.php"]`.
+    fn paths(&mut self, kind: &str, paths: &Json) {
+        let Some(paths) = strings(paths).filter(|_| matches!(paths.value, JsonValue::Array(_)))
+        else {
+            self.error(&paths.span, format!("`{}` expects an array of paths", kind));
+            return;
+        };
+        for path in paths {
+            let name = path.as_str().unwrap_or_default();
+            let found = self.dir.join(name);
+            match kind {
+                "files" if !self.exists(&found, Path::is_file) => {
+                    self.missing(&path.span, format!("file `{}` doesn't exist", name))
+                }
+                // classmaps can have wildcards
+                "classmap" if !name.contains('*') && !self.exists(&found, Path::exists) => {
+                    self.missing(&path.span, format!("`{}` doesn't exist", name))
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/**
+ * Report syntax errors in a `composer.json`, and autoload configuration that composer would
+ * refuse or that points at directories and files that don't exist. Paths are relative to `dir`,
+ * the directory of the file. Only paths in the `readable` directories are looked for, if there are
+ * any.
+ */
+pub fn composer_diagnostics(
+    contents: &str,
+    dir: &Path,
+    readable: Option<&[PathBuf]>,
+) -> Vec<Diagnostic> {
+    if let Err(e) = serde_json::from_str::<serde_json::Value>(contents) {
+        let at = Position::new(
+            e.line().saturating_sub(1) as u32,
+            e.column().saturating_sub(1) as u32,
+        );
+        return vec![diagnostic(
+            Range::new(at, at),
+            DiagnosticSeverity::ERROR,
+            "invalid-json",
+            e.to_string(),
+        )];
+    }
+
+    let Some(root) = parse(contents) else {
+        return vec![];
+    };
+    let mut checker = Checker {
+        text: contents,
+        dir,
+        readable,
+        diagnostics: vec![],
+    };
+    for section in AUTOLOAD_SECTIONS {
+        let Some(autoload) = root.get(section) else {
+            continue;
+        };
+        if !matches!(autoload.value, JsonValue::Object(_)) {
+            checker.error(&autoload.span, format!("`{}` expects an object", section));
+            continue;
+        }
+        for (key, value) in autoload.members() {
+            let Some(value) = value else {
+                continue;
+            };
+            match key.as_str() {
+                Some(kind @ ("psr-4" | "psr-0")) => checker.namespaces(kind, value),
+                Some(kind @ ("classmap" | "files")) => checker.paths(kind, value),
+                _ => {}
+            }
+        }
+    }
+    checker.diagnostics
+}
+
+/// The names and versions of the packages in a `composer.lock`, including those only for development.
+pub fn locked_packages(lock: &str) -> Vec<(String, String)> {
+    let Ok(lock) = serde_json::from_str::<serde_json::Value>(lock) else {
+        return vec![];
+    };
+    ["packages", "packages-dev"]
+        .iter()
+        .filter_map(|section| lock[section].as_array())
+        .flatten()
+        .filter_map(|package| {
+            let name = package["name"].as_str()?;
+            let version = package["version"].as_str().unwrap_or_default();
+            Some((name.to_string(), version.to_string()))
+        })
+        .collect()
+}
+
+/**
+ * Packages to complete the name of a package with, where one is being written in `require` or
+ * another section of a `composer.json` whose keys are packages. Those that the section already
+ * has are left out.
+ */
+pub fn package_completions(
+    contents: &str,
+    position: &Position,
+    packages: &[(String, String)],
+) -> Vec<CompletionItem> {
+    let Some(root) = parse(contents) else {
+        return vec![];
+    };
+    let offset = LineIndex::new(contents).offset(*position, PositionEncoding::Utf8);
+    for section in PACKAGE_SECTIONS {
+        let Some(required) = root.get(section) else {
+            continue;
+        };
+        for (key, _) in required.members() {
+            // between the quotes, or after the opening one of a key that isn't closed yet
+            let start = key.span.start + 1;
+            let closed = key.span.len() > 1 && contents[key.span.clone()].ends_with('"');
+            let end = key.span.end - closed as usize;
+            if !(start..=end).contains(&offset) {
+                continue;
+            }
+
+            let range = to_range(contents, &(start..end));
+            return packages
+                .iter()
+                .filter(|(name, _)| {
+                    required
+                        .members()
+                        .iter()
+                        .all(|(other, _)| other.span == key.span || other.as_str() != Some(name))
+                })
+                .map(|(name, version)| CompletionItem {
+                    label: name.clone(),
+                    kind: Some(CompletionItemKind::MODULE),
+                    detail: Some(version.clone()),
+                    text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                        range,
+                        new_text: name.clone(),
+                    })),
+                    ..CompletionItem::default()
+                })
+                .collect();
+        }
+    }
+    vec![]
+}
+
+/// The directories that a PSR-4 or PSR-0 prefix maps to, relative to `composer.json`.
+fn prefix_dirs(dirs: &serde_json::Value) -> Vec<&str> {
+    match dirs {
+        serde_json::Value::String(dir) => vec![dir],
+        serde_json::Value::Array(dirs) => dirs.iter().filter_map(|dir| dir.as_str()).collect(),
+        _ => vec![],
+    }
+}
+
+/**
+ * The directories that the `autoload` of a `composer.json` in a directory puts the classes of each
+ * namespace in, the way PSR-4 maps them. The directories of PSR-0 are of the whole namespace, so
+ * it's the directory of the namespace in them, and its prefixes of class names like `Twig_` are
+ * left out. What `files` and `classmap` autoload is indexed like the rest of the workspace, so
+ * they map nothing.
+ */
+pub fn autoload_namespaces(
+    root: &Path,
+    composer: &serde_json::Value,
+) -> Vec<(PhpNamespace, Vec<PathBuf>)> {
+    let autoload = &composer["autoload"];
+    let mut namespaces = vec![];
+    if let Some(psr4) = autoload["psr-4"].as_object() {
+        for (prefix, dirs) in psr4 {
+            let Ok(namespace) = PhpNamespace::from_str(prefix);
+            let dirs = prefix_dirs(dirs).into_iter().map(|dir| root.join(dir));
+            namespaces.push((namespace, dirs.collect()));
+        }
+    }
+    if let Some(psr0) = autoload["psr-0"].as_object() {
+        for (prefix, dirs) in psr0 {
+            if prefix.ends_with('_') {
+                continue;
+            }
+            let Ok(namespace) = PhpNamespace::from_str(prefix);
+            let within: PathBuf = prefix.split('\\').filter(|part| !part.is_empty()).collect();
+            let dirs = prefix_dirs(dirs)
+                .into_iter()
+                .map(|dir| root.join(dir).join(&within));
+            namespaces.push((namespace, dirs.collect()));
+        }
+    }
+    namespaces
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::*;
+
+    use std::fs;
+    use std::path::Path;
+    use std::str::FromStr;
+
+    use super::{autoload_namespaces, composer_diagnostics, locked_packages, package_completions};
+    use crate::php_namespace::PhpNamespace;
+
+    #[test]
+    fn test_autoload_namespaces() {
+        let composer = serde_json::json!({
+            "autoload": {
+                "psr-4": { "App\\": "src/" },
+                "psr-0": {
+                    "Legacy\\Models\\": ["lib/", "old/"],
+                    "Twig_": "vendor/twig/lib/"
+                },
+                "files": ["helpers.php"]
+            }
+        });
+        let root = Path::new("/project");
+        let namespace = |name: &str| PhpNamespace::from_str(name).unwrap();
+        assert_eq!(
+            vec![
+                (namespace("App"), vec![root.join("src/")]),
+                (
+                    namespace("Legacy\\Models"),
+                    vec![
+                        root.join("lib/Legacy/Models"),
+                        root.join("old/Legacy/Models")
+                    ]
+                ),
+            ],
+            autoload_namespaces(root, &composer)
+        );
+    }
+
+    #[test]
+    fn test_composer_diagnostics() {
+        let root = std::env::temp_dir().join("phplsp-test-composer");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("helpers.php"), "<?php\n").unwrap();
+
+        let contents = r#"{
+    "autoload": {
+        "psr-4": {
+            "App\\": "src/",
+            "Lib": "lib/",
+            "1Bad\\": ["src/", 3]
+        },
+        "files": ["helpers.php", "missing.php"]
+    },
+    "autoload-dev": { "classmap": ["tests/", "build/*.php"] }
+}"#;
+        let found: Vec<(u32, String)> = composer_diagnostics(contents, &root, None)
+            .into_iter()
+            .map(|diagnostic| (diagnostic.range.start.line, diagnostic.message))
+            .collect();
+        assert_eq!(
+            vec![
+                (4, "PSR-4 prefix `Lib` must end with `\\`".to_string()),
+                (4, "directory `lib/` doesn't exist".to_string()),
+                (5, "`1Bad\\` is not a namespace prefix".to_string()),
+                (
+                    5,
+                    "expected a directory or an array of directories".to_string()
+                ),
+                (7, "file `missing.php` doesn't exist".to_string()),
+                (9, "`tests/` doesn't exist".to_string()),
+            ],
+            found
+        );
+
+        // what's outside of the readable directories isn't looked for
+        let restricted = composer_diagnostics(contents, &root, Some(&[root.join("src")]));
+        assert!(restricted
+            .iter()
+            .all(|diagnostic| !diagnostic.message.contains("doesn't exist")));
+
+        let broken =
+            composer_diagnostics("{\n  \"name\": \"acme/app\"\n  \"type\": 1\n}", &root, None);
+        assert_eq!(1, broken.len());
+        assert_eq!(Position::new(2, 2), broken[0].range.start);
+    }
+
+    #[test]
+    fn test_package_completions() {
+        let lock = r#"{
+    "packages": [{ "name": "monolog/monolog", "version": "3.5.0" }],
+    "packages-dev": [{ "name": "phpunit/phpunit", "version": "10.5.1" }, { "name": "psr/log", "version": "3.0.0" }]
+}"#;
+        let packages = locked_packages(lock);
+        let contents =
+            "{\n    \"require\": {\n        \"psr/log\": \"^3.0\",\n        \"mono\n    }\n}";
+
+        let completions = package_completions(contents, &Position::new(3, 12), &packages);
+        let labels: Vec<&str> = completions
+            .iter()
+            .map(|completion| completion.label.as_str())
+            .collect();
+        assert_eq!(vec!["monolog/monolog", "phpunit/phpunit"], labels);
+        assert_eq!(
+            Some(CompletionTextEdit::Edit(TextEdit {
+                range: Range::new(Position::new(3, 9), Position::new(3, 13)),
+                new_text: "monolog/monolog".to_string(),
+            })),
+            completions[0].text_edit
+        );
+
+        assert!(package_completions(contents, &Position::new(2, 20), &packages).is_empty());
+    }
+}
@@ -0,0 +1,320 @@
+//! Conditions and operands that are almost always a mistake: copied where they shouldn't be, or
+//! assigning where they were meant to compare.
+
+use lsp_types::*;
+use tree_sitter::Node;
+
+use crate::code_actions::QuickFix;
+use crate::dead_stores::is_pure;
+use crate::file::to_range;
+
+/**
+ * Operators that are always the same, or always the same as their operand, when both sides are:
+ * `$a == $a` is always true, `$a - $a` always 0, and `$a ?? $a` always `$a`. Operators like `+`
+ * and `.` are left out, since doubling something that way is fine.
+ */
+const SUSPICIOUS: &[&str] = &[
+    "==", "===", "!=", "!==", "<>", "<", ">", "<=", ">=", "<=>", "&&", "||", "and", "or", "xor",
+    "??", "-", "/", "%", "&", "|", "^",
+];
+
+/// The tokens of a node, which are what two nodes are compared by, leaving out comments.
+fn tokens<'a>(node: &Node, file_contents: &'a str, tokens_so_far: &mut Vec<&'a str>) {
+    if node.kind() == "comment" {
+        return;
+    }
+    if node.child_count() == 0 {
+        tokens_so_far.push(&file_contents[node.byte_range()]);
+        return;
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        tokens(&child, file_contents, tokens_so_far);
+    }
+}
+
+/// Whether two expressions are written the same, other than how they're spaced and commented.
+fn is_same(a: &Node, b: &Node, file_contents: &str) -> bool {
+    let (mut a_tokens, mut b_tokens) = (vec![], vec![]);
+    tokens(a, file_contents, &mut a_tokens);
+    tokens(b, file_contents, &mut b_tokens);
+    a_tokens == b_tokens
+}
+
+/// The conditions of an `if` and of the `elseif`s and `else if`s that follow it, in order.
+fn chain<'a>(if_statement: &Node<'a>) -> Vec<Node<'a>> {
+    let mut conditions = vec![];
+    let mut statement = Some(*if_statement);
+    while let Some(current) = statement.take() {
+        conditions.extend(current.child_by_field_name("condition"));
+        let mut cursor = current.walk();
+        for alternative in current.children_by_field_name("alternative", &mut cursor) {
+            match alternative.kind() {
+                "else_if_clause" => conditions.extend(alternative.child_by_field_name("condition")),
+                "else_clause" => {
+                    statement = alternative
+                        .child_by_field_name("body")
+                        .filter(|body| body.kind() == "if_statement");
+                }
+                _ => {}
+            }
+        }
+    }
+
+    conditions
+}
+
+fn warning(node: &Node, code: &str, message: String) -> Diagnostic {
+    Diagnostic {
+        range: to_range(&node.range()),
+        severity: Some(DiagnosticSeverity::WARNING),
+        code: Some(NumberOrString::String(code.to_string())),
+        source: Some("phplsp".to_string()),
+        message,
+        ..Diagnostic::default()
+    }
+}
+
+/**
+ * Report conditions of an `if`/`elseif` chain that are the same as one before them, whose branch
+ * can never run, and binary operators with the same expression on both sides, like `$a == $a`.
+ * Expressions that could have side effects, like calls, are left alone, since they needn't give
+ * the same value twice.
+ */
+pub fn duplicate_conditions(node: &Node, file_contents: &str, diagnostics: &mut Vec<Diagnostic>) {
+    match node.kind() {
+        // an `else if` is checked with the `if` it follows
+        "if_statement"
+            if node
+                .parent()
+                .is_none_or(|parent| parent.kind() != "else_clause") =>
+        {
+            let conditions = chain(node);
+            for (i, condition) in conditions.iter().enumerate() {
+                if !is_pure(condition, file_contents) {
+                    continue;
+                }
+                if let Some(earlier) = conditions[..i]
+                    .iter()
+                    .find(|earlier| is_same(earlier, condition, file_contents))
+                {
+                    diagnostics.push(warning(
+                        condition,
+                        "duplicate-condition",
+                        format!(
+                            "this condition is the same as the one on line {}, so this branch never runs",
+                            earlier.start_position().row + 1
+                        ),
+                    ));
+                }
+            }
+        }
+        "binary_expression" => {
+            let operator = node
+                .child_by_field_name("operator")
+                .map(|operator| &file_contents[operator.byte_range()]);
+            if let (Some(operator), Some(left), Some(right)) = (
+                operator,
+                node.child_by_field_name("left"),
+                node.child_by_field_name("right"),
+            ) {
+                if SUSPICIOUS
+                    .iter()
+                    .any(|suspicious| suspicious.eq_ignore_ascii_case(operator))
+                    && is_pure(&left, file_contents)
+                    && is_same(&left, &right, file_contents)
+                {
+                    diagnostics.push(warning(
+                        node,
+                        "identical-operands",
+                        format!("both sides of `{}` are the same", operator),
+                    ));
+                }
+            }
+        }
+        _ => {}
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        duplicate_conditions(&child, file_contents, diagnostics);
+    }
+}
+
+/**
+ * Report `if` and `elseif` conditions that are an assignment, like `if ($a = b())`, which is
+ * usually `==` with an `=` missing, with fixes to compare instead or to wrap the assignment in
+ * another pair of parentheses, which says it's meant. `while` loops are left alone, since
+ * `while ($row = next())` is how lists are read.
+ */
+pub fn assignments_in_conditions(
+    node: &Node,
+    file_contents: &str,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let assignment = match node.kind() {
+        "if_statement" | "else_if_clause" => node
+            .child_by_field_name("condition")
+            .filter(|condition| condition.kind() == "parenthesized_expression")
+            .and_then(|condition| condition.named_child(0))
+            .filter(|assignment| assignment.kind() == "assignment_expression"),
+        _ => None,
+    };
+    let operator = assignment.and_then(|assignment| {
+        let mut cursor = assignment.walk();
+        let operator = assignment
+            .children(&mut cursor)
+            .find(|child| child.kind() == "=");
+        operator
+    });
+    if let (Some(assignment), Some(operator)) = (assignment, operator) {
+        let range = to_range(&assignment.range());
+        let left = assignment
+            .child_by_field_name("left")
+            .map_or("", |left| &file_contents[left.byte_range()]);
+        let mut diagnostic = Diagnostic {
+            range,
+            severity: Some(DiagnosticSeverity::WARNING),
+            code: Some(NumberOrString::String(
+                "assignment-in-condition".to_string(),
+            )),
+            source: Some("phplsp".to_string()),
+            message: format!(
+                "this assigns to `{}` rather than comparing; use `==` to compare, or wrap the assignment in parentheses if it's meant",
+                left
+            ),
+            ..Diagnostic::default()
+        };
+        let fixes = [
+            QuickFix {
+                title: "Compare with `==` instead".to_string(),
+                edits: vec![TextEdit {
+                    range: to_range(&operator.range()),
+                    new_text: "==".to_string(),
+                }],
+                rename: None,
+            },
+            QuickFix {
+                title: "Wrap the assignment in parentheses".to_string(),
+                edits: vec![
+                    TextEdit {
+                        range: Range::new(range.start, range.start),
+                        new_text: "(".to_string(),
+                    },
+                    TextEdit {
+                        range: Range::new(range.end, range.end),
+                        new_text: ")".to_string(),
+                    },
+                ],
+                rename: None,
+            },
+        ];
+        QuickFix::attach_all(&fixes, &mut diagnostic);
+        diagnostics.push(diagnostic);
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        assignments_in_conditions(&child, file_contents, diagnostics);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::*;
+
+    use super::{assignments_in_conditions, duplicate_conditions};
+    use crate::code_actions::QuickFix;
+    use crate::file::php_parser;
+
+    #[test]
+    fn test_duplicate_conditions() {
+        let source = "<?php
+function f($a, $b, $x) {
+    if ($a == 1) {
+    } elseif ($b) {
+    } elseif ($a  ==  /* one */ 1) {
+    } else if ($b) {
+    } else {
+    }
+    if (rand() > 5) {
+    } elseif (rand() > 5) {
+    }
+    $y = $x ?? $x;
+    $z = $a + $a;
+    $w = $a->b === $a->c;
+    $v = g() - g();
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let mut diagnostics = vec![];
+        duplicate_conditions(&tree.root_node(), source, &mut diagnostics);
+
+        let found: Vec<(u32, String)> = diagnostics
+            .into_iter()
+            .map(|diagnostic| (diagnostic.range.start.line, diagnostic.message))
+            .collect();
+        assert_eq!(
+            vec![
+                (
+                    4,
+                    "this condition is the same as the one on line 3, so this branch never runs"
+                        .to_string()
+                ),
+                (
+                    5,
+                    "this condition is the same as the one on line 4, so this branch never runs"
+                        .to_string()
+                ),
+                (11, "both sides of `??` are the same".to_string()),
+            ],
+            found
+        );
+    }
+
+    #[test]
+    fn test_assignments_in_conditions() {
+        let source = "<?php
+if ($a = f()) {
+} elseif (($b = g())) {
+} elseif ($c == h()) {
+}
+while ($row = next()) {
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let mut diagnostics = vec![];
+        assignments_in_conditions(&tree.root_node(), source, &mut diagnostics);
+
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(
+            Range::new(Position::new(1, 4), Position::new(1, 12)),
+            diagnostics[0].range
+        );
+        let fixes: Vec<(String, Vec<TextEdit>)> = QuickFix::all(&diagnostics[0])
+            .into_iter()
+            .map(|fix| (fix.title, fix.edits))
+            .collect();
+        let insert = |character, text: &str| TextEdit {
+            range: Range::new(Position::new(1, character), Position::new(1, character)),
+            new_text: text.to_string(),
+        };
+        assert_eq!(
+            vec![
+                (
+                    "Compare with `==` instead".to_string(),
+                    vec![TextEdit {
+                        range: Range::new(Position::new(1, 7), Position::new(1, 8)),
+                        new_text: "==".to_string(),
+                    }]
+                ),
+                (
+                    "Wrap the assignment in parentheses".to_string(),
+                    vec![insert(4, "("), insert(12, ")")]
+                ),
+            ],
+            fixes
+        );
+    }
+}
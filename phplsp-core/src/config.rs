@@ -0,0 +1,397 @@
+use lsp_types::DiagnosticSeverity;
+use serde::Deserialize;
+
+use std::collections::HashMap;
+use std::fmt;
+
+/**
+ * Settings that the client passes to us through `initializationOptions`.
+ *
+ * Anything left out keeps its default value.
+ */
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Config {
+    /// Megabytes the workspace index may use before the parse trees of the least recently used
+    /// files are evicted.
+    pub memory_budget_mb: usize,
+    /// Which arguments get an inlay hint with the name of their parameter.
+    pub parameter_name_hints: ParameterNameHints,
+    /// Show inlay hints with the types of variables, closures and `foreach` values.
+    pub type_hints: bool,
+    /// Limits on the size and complexity of functions and methods.
+    pub metrics: MetricLimits,
+    /// Conventions for how classes, methods, variables and constants are named.
+    pub naming: NamingRules,
+    /// What quick fixes declare methods and properties as, when they don't say.
+    pub default_visibility: Visibility,
+    /// Language features that a team may not want used.
+    pub usage: UsageRules,
+    /**
+     * Methods without a doc comment that override a documented one with parameters of other
+     * names, which callers using named arguments have to know about. Not checked unless set.
+     */
+    pub undocumented_overrides: Option<UsageRule>,
+    /// Methods that override another without saying so with `#[\Override]`. Not checked unless set.
+    pub missing_override: Option<UsageRule>,
+    /**
+     * How strict to be, from 0, which only reports what PHP refuses, up to 8, which reports
+     * everything, like no level does. Rules that are off unless they're set stay that way.
+     */
+    pub level: Option<u8>,
+    /// What the diagnostics of rules are reported as, by their code, or `off` not to report them.
+    pub rules: HashMap<String, RuleSetting>,
+    /// The version of PHP that the code has to run on. Syntax that's newer than it is reported.
+    pub php_version: PhpVersion,
+    /**
+     * Classes, functions and methods that are used in ways we can't see, like controllers that
+     * routes name in strings or config files, so they are never reported as unused public API.
+     * Fully qualified names, where `*` stands for anything, like `App\Controller\*`.
+     */
+    pub entry_points: Vec<String>,
+    /// Which blocks get closed for us when their `{` is typed at the end of a line.
+    pub auto_close: AutoClose,
+    /**
+     * Functions that the `disable_functions` of the php.ini the code runs with turns off, which
+     * fail when they're called. Entries can list several separated by commas, like php.ini does.
+     */
+    pub disabled_functions: Vec<String>,
+    /**
+     * Restricted mode, for workspaces that aren't trusted: nothing that the project ships is ever
+     * run, no file outside the workspace folders and the stubs is read, and no file is written,
+     * so the index isn't cached and commands that write files are turned off.
+     */
+    pub restricted: bool,
+}
+
+/// A version of PHP, like `"8.2"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(try_from = "String")]
+pub struct PhpVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl PhpVersion {
+    pub const fn new(major: u32, minor: u32) -> Self {
+        Self { major, minor }
+    }
+}
+
+/// The latest version we know the syntax of.
+impl Default for PhpVersion {
+    fn default() -> Self {
+        Self::new(8, 4)
+    }
+}
+
+impl TryFrom<String> for PhpVersion {
+    type Error = String;
+
+    fn try_from(version: String) -> Result<Self, Self::Error> {
+        let mut parts = version.split('.').map(str::parse::<u32>);
+        match (parts.next(), parts.next()) {
+            (Some(Ok(major)), Some(Ok(minor))) => Ok(Self::new(major, minor)),
+            (Some(Ok(major)), None) => Ok(Self::new(major, 0)),
+            _ => Err(format!("`{}` isn't a version of PHP", version)),
+        }
+    }
+}
+
+impl fmt::Display for PhpVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Visibility {
+    /// What members without a visibility already are, so this changes nothing.
+    #[default]
+    Public,
+    Protected,
+    Private,
+}
+
+impl Visibility {
+    pub fn keyword(self) -> &'static str {
+        match self {
+            Visibility::Public => "public",
+            Visibility::Protected => "protected",
+            Visibility::Private => "private",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ParameterNameHints {
+    /// Only arguments that are literals, whose meaning can't be guessed from a variable name.
+    #[default]
+    Literals,
+    All,
+}
+
+/**
+ * Functions and methods past these get an informational diagnostic. Limits that aren't set
+ * aren't checked, so by default nothing is.
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct MetricLimits {
+    /// Most paths through a function: one, plus one for every branch, loop, `case`, `catch`,
+    /// ternary, `match` arm and short-circuiting operator.
+    pub max_complexity: Option<usize>,
+    pub max_parameters: Option<usize>,
+    /// Most lines a function can span, from its signature to its closing brace.
+    pub max_lines: Option<usize>,
+}
+
+/**
+ * Which naming conventions to check. Rules that aren't set aren't checked, so by default nothing
+ * is.
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct NamingRules {
+    /// Classes, interfaces, traits and enums, which are in StudlyCase unless said otherwise.
+    pub classes: Option<CaseRule>,
+    /// Methods other than magic ones, which are in camelCase unless said otherwise.
+    pub methods: Option<CaseRule>,
+    /// Local variables and parameters, which are in camelCase unless said otherwise.
+    pub variables: Option<CaseRule>,
+    /// Global and class constants, which are in UPPER_SNAKE_CASE unless said otherwise.
+    pub constants: Option<CaseRule>,
+    pub interfaces: Option<SuffixRule>,
+    pub traits: Option<SuffixRule>,
+}
+
+/**
+ * Which language features to report uses of. Rules that aren't set aren't checked, so by default
+ * nothing is.
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct UsageRules {
+    /// `exit` and `die` anywhere but the top level of a script, where entry scripts stop.
+    pub exit: Option<UsageRule>,
+    /// The `@` operator, which hides every error of what it's in front of.
+    pub error_suppression: Option<UsageRule>,
+    pub eval: Option<UsageRule>,
+}
+
+/**
+ * Which blocks get their closing brace, and whatever else has to follow it, added when their `{`
+ * is typed at the end of a line and isn't closed yet. All of them are by default.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AutoClose {
+    /// `match (...) {`, closed with `};` when it ends a statement.
+    pub r#match: bool,
+    /// `try {`, closed along with a `catch` to fill in.
+    pub r#try: bool,
+    /// Headers of functions, methods and closures.
+    pub functions: bool,
+}
+
+impl Default for AutoClose {
+    fn default() -> Self {
+        Self {
+            r#match: true,
+            r#try: true,
+            functions: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct UsageRule {
+    pub severity: RuleSeverity,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CaseRule {
+    pub severity: RuleSeverity,
+    pub case: Option<Case>,
+}
+
+/// A suffix that every name of a kind has to end with, like `Interface`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SuffixRule {
+    #[serde(default)]
+    pub severity: RuleSeverity,
+    pub suffix: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Case {
+    /// `UserProfile`
+    Studly,
+    /// `userProfile`
+    Camel,
+    /// `user_profile`
+    Snake,
+    /// `USER_PROFILE`
+    UpperSnake,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RuleSeverity {
+    Error,
+    Warning,
+    #[default]
+    Information,
+    Hint,
+}
+
+impl From<RuleSeverity> for DiagnosticSeverity {
+    fn from(severity: RuleSeverity) -> Self {
+        match severity {
+            RuleSeverity::Error => DiagnosticSeverity::ERROR,
+            RuleSeverity::Warning => DiagnosticSeverity::WARNING,
+            RuleSeverity::Information => DiagnosticSeverity::INFORMATION,
+            RuleSeverity::Hint => DiagnosticSeverity::HINT,
+        }
+    }
+}
+
+/// What the diagnostics of a rule are reported as, whatever they would be otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RuleSetting {
+    Off,
+    Error,
+    Warning,
+    #[serde(alias = "info")]
+    Information,
+    Hint,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            memory_budget_mb: 512,
+            parameter_name_hints: ParameterNameHints::default(),
+            type_hints: true,
+            metrics: MetricLimits::default(),
+            naming: NamingRules::default(),
+            default_visibility: Visibility::default(),
+            usage: UsageRules::default(),
+            undocumented_overrides: None,
+            missing_override: None,
+            level: None,
+            rules: HashMap::new(),
+            php_version: PhpVersion::default(),
+            entry_points: vec![],
+            auto_close: AutoClose::default(),
+            disabled_functions: vec![],
+            restricted: false,
+        }
+    }
+}
+
+impl Config {
+    pub fn memory_budget(&self) -> usize {
+        self.memory_budget_mb * 1024 * 1024
+    }
+
+    /// Whether a global function, named with or without its leading `\`, is disabled.
+    pub fn is_disabled(&self, function: &str) -> bool {
+        let function = function.trim_start_matches('\\');
+        self.disabled_functions
+            .iter()
+            .flat_map(|entry| entry.split(','))
+            .any(|disabled| disabled.trim().eq_ignore_ascii_case(function))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        Case, Config, ParameterNameHints, PhpVersion, RuleSetting, RuleSeverity, UsageRule,
+        Visibility,
+    };
+
+    #[test]
+    fn test_partial_options() {
+        let config: Config = serde_json::from_value(serde_json::json!({})).unwrap();
+        assert_eq!(Config::default(), config);
+
+        let config: Config =
+            serde_json::from_value(serde_json::json!({ "memoryBudgetMb": 64 })).unwrap();
+        assert_eq!(64 * 1024 * 1024, config.memory_budget());
+
+        let config: Config =
+            serde_json::from_value(serde_json::json!({ "parameterNameHints": "all" })).unwrap();
+        assert_eq!(ParameterNameHints::All, config.parameter_name_hints);
+
+        let config: Config =
+            serde_json::from_value(serde_json::json!({ "metrics": { "maxLines": 50 } })).unwrap();
+        assert_eq!(Some(50), config.metrics.max_lines);
+        assert_eq!(None, config.metrics.max_complexity);
+
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "naming": {
+                "variables": { "case": "snake", "severity": "warning" },
+                "interfaces": { "suffix": "Interface" },
+            }
+        }))
+        .unwrap();
+        let variables = config.naming.variables.unwrap();
+        assert_eq!(Some(Case::Snake), variables.case);
+        assert_eq!(RuleSeverity::Warning, variables.severity);
+        assert_eq!("Interface", config.naming.interfaces.unwrap().suffix);
+        assert_eq!(None, config.naming.classes);
+        assert_eq!(Visibility::Public, config.default_visibility);
+
+        let config: Config =
+            serde_json::from_value(serde_json::json!({ "defaultVisibility": "private" })).unwrap();
+        assert_eq!(Visibility::Private, config.default_visibility);
+
+        let config: Config =
+            serde_json::from_value(serde_json::json!({ "usage": { "errorSuppression": {} } }))
+                .unwrap();
+        assert_eq!(Some(UsageRule::default()), config.usage.error_suppression);
+        assert_eq!(None, config.usage.exit);
+
+        let config: Config = serde_json::from_value(serde_json::json!({ "level": 5 })).unwrap();
+        assert_eq!(Some(5), config.level);
+
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "rules": { "dead-store": "off", "null-access": "info" }
+        }))
+        .unwrap();
+        assert_eq!(Some(&RuleSetting::Off), config.rules.get("dead-store"));
+        assert_eq!(
+            Some(&RuleSetting::Information),
+            config.rules.get("null-access")
+        );
+
+        let config: Config =
+            serde_json::from_value(serde_json::json!({ "phpVersion": "8.2" })).unwrap();
+        assert_eq!(PhpVersion::new(8, 2), config.php_version);
+        assert!(
+            serde_json::from_value::<Config>(serde_json::json!({ "phpVersion": "x" })).is_err()
+        );
+
+        let config: Config =
+            serde_json::from_value(serde_json::json!({ "autoClose": { "try": false } })).unwrap();
+        assert!(!config.auto_close.r#try);
+        assert!(config.auto_close.r#match);
+
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "disabledFunctions": ["exec, shell_exec", "system"]
+        }))
+        .unwrap();
+        assert!(config.is_disabled("\\Shell_Exec"));
+        assert!(config.is_disabled("system"));
+        assert!(!config.is_disabled("App\\exec"));
+    }
+}
@@ -0,0 +1,502 @@
+//! Conditions whose value can be worked out without running the code, from the types and values
+//! of what they check, and the branches they keep from ever running.
+
+use lsp_types::*;
+use tree_sitter::Node;
+
+use crate::dead_stores::has_dynamic_scope;
+use crate::file::to_range;
+use crate::index::WorkspaceIndex;
+use crate::resolve::Resolver;
+use crate::return_types::is_checked;
+use crate::scope::{variables, FUNCTIONS};
+use crate::symbols::FileSymbols;
+use crate::types::{Type, TypeInference};
+
+/// Node kinds of literal values, whose type is plain to see.
+const LITERALS: &[&str] = &[
+    "integer",
+    "float",
+    "string",
+    "encapsed_string",
+    "heredoc",
+    "nowdoc",
+    "boolean",
+    "null",
+    "array_creation_expression",
+];
+
+/// Where a mention of a variable that isn't an assignment can change it anyway.
+const MODIFYING_PARENTS: &[&str] = &[
+    "augmented_assignment_expression",
+    "reference_assignment_expression",
+    "update_expression",
+    "by_ref",
+    "unset_statement",
+    "global_declaration",
+    "static_variable_declaration",
+];
+
+/**
+ * Whether a value of a type passes a type-checking function like `is_string`, if that doesn't
+ * depend on the value, or `None` if the function isn't one of those.
+ */
+fn passes(function: &str, ty: &Type) -> Option<Option<bool>> {
+    let is = |passes: bool| Some(Some(passes));
+    match function.to_ascii_lowercase().as_str() {
+        "is_int" | "is_integer" | "is_long" => is(*ty == Type::Int),
+        "is_float" | "is_double" => is(*ty == Type::Float),
+        "is_string" => is(*ty == Type::String),
+        "is_bool" => is(*ty == Type::Bool),
+        "is_null" => is(*ty == Type::Null),
+        "is_array" => is(matches!(ty, Type::Array(_) | Type::Shape(_))),
+        "is_object" => is(matches!(ty, Type::Class(_))),
+        "is_scalar" => is(matches!(
+            ty,
+            Type::Int | Type::Float | Type::String | Type::Bool
+        )),
+        // strings can be numeric or not, and objects iterable or not
+        "is_numeric" if *ty == Type::String => Some(None),
+        "is_numeric" => is(matches!(ty, Type::Int | Type::Float)),
+        "is_iterable" if matches!(ty, Type::Class(_)) => Some(None),
+        "is_iterable" => is(matches!(ty, Type::Array(_) | Type::Shape(_))),
+        _ => None,
+    }
+}
+
+/// Which values of a type could be identical to values of another, or `None` if we can't tell.
+fn kind(ty: &Type) -> Option<u8> {
+    match ty {
+        Type::Int => Some(0),
+        Type::Float => Some(1),
+        Type::String => Some(2),
+        Type::Bool => Some(3),
+        Type::Null => Some(4),
+        Type::Array(_) | Type::Shape(_) => Some(5),
+        Type::Class(_) => Some(6),
+        _ => None,
+    }
+}
+
+fn members(ty: &Type) -> &[Type] {
+    match ty {
+        Type::Union(members) => members,
+        ty => std::slice::from_ref(ty),
+    }
+}
+
+struct Checker<'a> {
+    contents: &'a str,
+    types: TypeInference<'a>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker<'_> {
+    fn text(&self, node: &Node) -> &str {
+        &self.contents[node.byte_range()]
+    }
+
+    /// Whether a mention of a variable could change it, like `$a++` or `f($a)` by reference.
+    fn is_modifying(&self, mention: &Node) -> bool {
+        let Some(parent) = mention.parent() else {
+            return false;
+        };
+        if MODIFYING_PARENTS.contains(&parent.kind()) {
+            return true;
+        }
+        if parent.kind() != "argument" {
+            return false;
+        }
+        // named arguments could be any parameter
+        if parent.child_by_field_name("name").is_some() {
+            return true;
+        }
+        let Some(arguments) = parent.parent() else {
+            return true;
+        };
+        let Some(call) = arguments.parent() else {
+            return true;
+        };
+        let mut cursor = arguments.walk();
+        let position = arguments
+            .named_children(&mut cursor)
+            .filter(|argument| argument.kind() == "argument")
+            .position(|argument| argument.id() == parent.id());
+        match (self.types.callee(&call), position) {
+            (Some(callee), Some(position)) => {
+                let last = callee.parameters().len().saturating_sub(1);
+                callee.is_by_reference(position.min(last))
+            }
+            _ => true,
+        }
+    }
+
+    /**
+     * The type of a parameter that the function never changes, as its declaration says: PHP
+     * checks that, unlike what a doc comment says.
+     */
+    fn parameter_type(&self, variable: &Node) -> Option<Type> {
+        let name = self.text(variable);
+        let mut function = variable.parent();
+        let (function, parameter) = loop {
+            let current = function?;
+            if FUNCTIONS.contains(&current.kind()) || current.kind() == "arrow_function" {
+                let parameters = current.child_by_field_name("parameters")?;
+                let mut cursor = parameters.walk();
+                let parameter = parameters.named_children(&mut cursor).find(|parameter| {
+                    parameter
+                        .child_by_field_name("name")
+                        .is_some_and(|parameter| self.text(&parameter) == name)
+                });
+                if let Some(parameter) = parameter {
+                    break (current, parameter);
+                }
+                // only arrow functions see the variables of the function they're in
+                if current.kind() != "arrow_function" {
+                    return None;
+                }
+            }
+            function = current.parent();
+        };
+        if parameter.kind() == "variadic_parameter" || has_dynamic_scope(&function, self.contents) {
+            return None;
+        }
+
+        // closures that capture it by reference count as changing it where they capture it
+        let mentions = variables(&function, self.contents)
+            .remove(name.trim_start_matches('$'))
+            .unwrap_or_default();
+        if !mentions.writes.is_empty()
+            || mentions
+                .reads
+                .iter()
+                .any(|mention| self.is_modifying(mention))
+        {
+            return None;
+        }
+
+        let written = parameter.child_by_field_name("type")?;
+        if !is_checked(self.text(&written)) {
+            return None;
+        }
+        let ty = self.types.type_node(&written)?;
+        // `int $a = null` takes `null` too
+        let defaults_to_null = parameter
+            .child_by_field_name("default_value")
+            .is_some_and(|default| self.text(&default).eq_ignore_ascii_case("null"));
+        Some(match defaults_to_null {
+            true => Type::union([ty, Type::Null]),
+            false => ty,
+        })
+    }
+
+    /// The type of an operand, if we can be sure of it, and what to call it when saying so.
+    fn known_type(&self, node: &Node) -> Option<(Type, String)> {
+        let ty = match node.kind() {
+            kind if LITERALS.contains(&kind) => self.types.expression(node)?,
+            "cast_expression" => self.types.expression(node)?,
+            "variable_name" => self.parameter_type(node)?,
+            _ => return None,
+        };
+        if members(&ty).iter().any(|member| kind(member).is_none()) {
+            return None;
+        }
+        let name = match node.kind() {
+            "variable_name" => format!("`{}`", self.text(node)),
+            _ => "the value".to_string(),
+        };
+        Some((ty, name))
+    }
+
+    /// The value an expression always has, and why, if we can work it out.
+    fn decide(&self, node: &Node) -> Option<(bool, String)> {
+        match node.kind() {
+            "parenthesized_expression" => self.decide(&node.named_child(0)?),
+            "unary_op_expression" => {
+                let operator = node.child_by_field_name("operator")?;
+                if self.text(&operator) != "!" {
+                    return None;
+                }
+                let (value, why) = self.decide(&node.child_by_field_name("argument")?)?;
+                Some((!value, why))
+            }
+            "function_call_expression" => {
+                let function = node.child_by_field_name("function")?;
+                let function = self.text(&function).trim_start_matches('\\');
+                let arguments = node.child_by_field_name("arguments")?;
+                if arguments.named_child_count() != 1 {
+                    return None;
+                }
+                let argument = arguments.named_child(0)?;
+                if argument.kind() != "argument" || argument.child_by_field_name("name").is_some() {
+                    return None;
+                }
+                let (ty, name) = self.known_type(&argument.named_child(0)?)?;
+                let results: Vec<Option<bool>> = members(&ty)
+                    .iter()
+                    .map(|member| passes(function, member))
+                    .collect::<Option<_>>()?;
+                let results: Vec<bool> = results.into_iter().collect::<Option<_>>()?;
+                let value = *results.first()?;
+                if results.iter().any(|result| *result != value) {
+                    return None;
+                }
+                Some((
+                    value,
+                    format!(
+                        "`{}()` is always {} here, since {} is `{}`",
+                        function, value, name, ty
+                    ),
+                ))
+            }
+            "binary_expression" => {
+                let operator = node.child_by_field_name("operator")?;
+                let operator = self.text(&operator);
+                let value = match operator {
+                    "===" => false,
+                    "!==" => true,
+                    _ => return None,
+                };
+                let (left, _) = self.known_type(&node.child_by_field_name("left")?)?;
+                let (right, _) = self.known_type(&node.child_by_field_name("right")?)?;
+                let could_be_identical = members(&left).iter().any(|left| {
+                    members(&right)
+                        .iter()
+                        .any(|right| kind(left) == kind(right))
+                });
+                if could_be_identical {
+                    return None;
+                }
+                Some((
+                    value,
+                    format!(
+                        "`{}` is always {} here, since it compares `{}` with `{}`",
+                        operator, value, left, right
+                    ),
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    fn dead(&mut self, branch: &Node, why: &str) {
+        self.diagnostics.push(Diagnostic {
+            range: to_range(&branch.range()),
+            severity: Some(DiagnosticSeverity::HINT),
+            code: Some(NumberOrString::String("constant-condition".to_string())),
+            source: Some("phplsp".to_string()),
+            message: format!("this branch never runs, since {}", why),
+            tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+            ..Diagnostic::default()
+        });
+    }
+
+    /// Mark the branches of an `if`, `elseif` or `while` that its condition keeps from running.
+    fn branches(&mut self, statement: &Node) {
+        let Some(condition) = statement.child_by_field_name("condition") else {
+            return;
+        };
+        let Some((value, _)) = self.decide(&condition) else {
+            return;
+        };
+        if !value {
+            if let Some(body) = statement.child_by_field_name("body") {
+                self.dead(&body, "its condition is always false");
+            }
+            return;
+        }
+
+        // what comes after a condition that's always true
+        let (parent, after) = match statement.kind() {
+            "if_statement" => (*statement, 0),
+            "else_if_clause" => match statement.parent() {
+                Some(parent) => (parent, statement.end_byte()),
+                None => return,
+            },
+            _ => return,
+        };
+        let mut cursor = parent.walk();
+        let alternatives: Vec<Node> = parent
+            .children_by_field_name("alternative", &mut cursor)
+            .filter(|alternative| alternative.start_byte() >= after)
+            .collect();
+        for alternative in alternatives {
+            self.dead(&alternative, "an earlier condition is always true");
+        }
+    }
+
+    fn walk(&mut self, node: &Node) {
+        match node.kind() {
+            "function_call_expression" | "binary_expression" => {
+                if let Some((_, why)) = self.decide(node) {
+                    self.diagnostics.push(Diagnostic {
+                        range: to_range(&node.range()),
+                        severity: Some(DiagnosticSeverity::WARNING),
+                        code: Some(NumberOrString::String("constant-condition".to_string())),
+                        source: Some("phplsp".to_string()),
+                        message: why,
+                        ..Diagnostic::default()
+                    });
+                }
+            }
+            "if_statement" | "else_if_clause" | "while_statement" => self.branches(node),
+            _ => {}
+        }
+
+        let mut cursor = node.walk();
+        for child in node.named_children(&mut cursor) {
+            self.walk(&child);
+        }
+    }
+}
+
+/**
+ * Report type checks like `is_string($a)` and strict comparisons like `$a === null` that always
+ * give the same result, going by literal values and the declared types of parameters that their
+ * function never changes, and mark the branches that they keep from running.
+ */
+pub fn constant_conditions(
+    root_node: &Node,
+    file_contents: &str,
+    symbols: &FileSymbols,
+    index: &WorkspaceIndex,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let resolver = Resolver::new(file_contents, symbols, index);
+    let mut checker = Checker {
+        contents: file_contents,
+        types: TypeInference::new(file_contents, &resolver),
+        diagnostics: vec![],
+    };
+    checker.walk(root_node);
+    diagnostics.append(&mut checker.diagnostics);
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::*;
+
+    use super::constant_conditions;
+    use crate::file::php_parser;
+    use crate::index::WorkspaceIndex;
+    use crate::symbols::file_symbols;
+
+    #[test]
+    fn test_constant_conditions() {
+        let source = "<?php
+function f(int $a, ?string $b, int $c = null, int $d, $e) {
+    if (is_string($a)) {
+        g();
+    } elseif (!is_null($b)) {
+        g();
+    } elseif ($a !== null) {
+        g();
+    } else {
+        g();
+    }
+    if (is_null($c) || $b === 'x' || is_int($e) || 1 === '1') {
+    }
+    $d++;
+    return is_int($d);
+}
+";
+        let stubs = "<?php
+function is_string(mixed $value): bool {}
+function is_int(mixed $value): bool {}
+function is_null(mixed $value): bool {}
+";
+        let mut index = WorkspaceIndex::default();
+        let stubs_tree = php_parser().parse(stubs, None).unwrap();
+        index.update_symbols(
+            &Url::parse("file:///stubs.php").unwrap(),
+            file_symbols(&stubs_tree.root_node(), stubs),
+        );
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let mut diagnostics = vec![];
+        constant_conditions(
+            &tree.root_node(),
+            source,
+            &symbols,
+            &index,
+            &mut diagnostics,
+        );
+
+        let found: Vec<(u32, Option<DiagnosticSeverity>, String)> = diagnostics
+            .into_iter()
+            .map(|diagnostic| {
+                (
+                    diagnostic.range.start.line,
+                    diagnostic.severity,
+                    diagnostic.message,
+                )
+            })
+            .collect();
+        let warning = Some(DiagnosticSeverity::WARNING);
+        let hint = Some(DiagnosticSeverity::HINT);
+        assert_eq!(
+            vec![
+                (
+                    2,
+                    hint,
+                    "this branch never runs, since its condition is always false".to_string()
+                ),
+                (
+                    2,
+                    warning,
+                    "`is_string()` is always false here, since `$a` is `int`".to_string()
+                ),
+                (
+                    8,
+                    hint,
+                    "this branch never runs, since an earlier condition is always true".to_string()
+                ),
+                (
+                    6,
+                    warning,
+                    "`!==` is always true here, since it compares `int` with `null`".to_string()
+                ),
+                (
+                    11,
+                    warning,
+                    "`===` is always false here, since it compares `int` with `string`".to_string()
+                ),
+            ],
+            found
+        );
+    }
+
+    #[test]
+    fn test_arrow_function_parameters() {
+        let source = "<?php
+$checked = fn(int $a) => is_string($a);
+$changed = fn(int $a) => [$a = (string) $a, is_string($a)];
+";
+        let stubs = "<?php
+function is_string(mixed $value): bool {}
+";
+        let mut index = WorkspaceIndex::default();
+        let stubs_tree = php_parser().parse(stubs, None).unwrap();
+        index.update_symbols(
+            &Url::parse("file:///stubs.php").unwrap(),
+            file_symbols(&stubs_tree.root_node(), stubs),
+        );
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let mut diagnostics = vec![];
+        constant_conditions(
+            &tree.root_node(),
+            source,
+            &symbols,
+            &index,
+            &mut diagnostics,
+        );
+
+        // the second parameter is assigned in the body, so it can be a string by then
+        let lines: Vec<u32> = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.range.start.line)
+            .collect();
+        assert_eq!(vec![1], lines);
+    }
+}
@@ -0,0 +1,274 @@
+//! What operators and other syntax of PHP do, for hovers on them, since they're harder to look up
+//! than functions are.
+
+use lsp_types::*;
+use tree_sitter::Node;
+
+use crate::config::PhpVersion;
+use crate::file::{to_point, to_range};
+
+/// An operator or piece of syntax, and what it does.
+struct Construct {
+    /// The token that it's written with.
+    token: &'static str,
+    /// Kinds of the nodes that the token has to be in, closest first, where it means more than
+    /// one thing.
+    within: &'static [&'static str],
+    name: &'static str,
+    explanation: &'static str,
+    since: PhpVersion,
+    /// The page of the manual about it.
+    link: &'static str,
+}
+
+const MANUAL: &str = "https://www.php.net/manual/en/";
+
+const CONSTRUCTS: &[Construct] = &[
+    Construct {
+        token: "<=>",
+        within: &[],
+        name: "Spaceship operator",
+        explanation:
+            "Compares two values, giving `-1`, `0` or `1` when the left one is less than, \
+            equal to or greater than the right one, which is what `usort` callbacks return.",
+        since: PhpVersion::new(7, 0),
+        link: "language.operators.comparison.php",
+    },
+    Construct {
+        token: "??",
+        within: &[],
+        name: "Null coalescing operator",
+        explanation: "The left side, unless it's `null` or not set, without a warning, in which \
+            case the right side.",
+        since: PhpVersion::new(7, 0),
+        link: "language.operators.comparison.php#language.operators.comparison.coalesce",
+    },
+    Construct {
+        token: "??=",
+        within: &[],
+        name: "Null coalescing assignment",
+        explanation: "Assigns the right side only if the left side is `null` or not set: \
+            `$a ??= $b` is `$a = $a ?? $b`.",
+        since: PhpVersion::new(7, 4),
+        link: "language.operators.assignment.php",
+    },
+    Construct {
+        token: "?->",
+        within: &[],
+        name: "Nullsafe operator",
+        explanation: "Reads a property or calls a method like `->`, unless the object is `null`, \
+            in which case the whole chain is `null` and the rest of it isn't evaluated.",
+        since: PhpVersion::new(8, 0),
+        link: "language.oop5.basic.php#language.oop5.basic.nullsafe",
+    },
+    Construct {
+        token: "...",
+        within: &["variadic_unpacking", "argument"],
+        name: "Argument unpacking",
+        explanation: "Passes the elements of an array or `Traversable` as separate arguments, \
+            and ones with string keys as named arguments since PHP 8.1.",
+        since: PhpVersion::new(5, 6),
+        link: "functions.arguments.php#functions.variable-arg-list",
+    },
+    Construct {
+        token: "...",
+        within: &["variadic_unpacking", "array_element_initializer"],
+        name: "Array unpacking",
+        explanation: "Puts the elements of an array or `Traversable` into the array, like \
+            `array_merge`, with string keys since PHP 8.1.",
+        since: PhpVersion::new(7, 4),
+        link: "language.types.array.php#language.types.array.unpacking",
+    },
+    Construct {
+        token: "...",
+        within: &["variadic_parameter"],
+        name: "Variadic parameter",
+        explanation: "Takes the rest of the arguments, as an array.",
+        since: PhpVersion::new(5, 6),
+        link: "functions.arguments.php#functions.variable-arg-list",
+    },
+    Construct {
+        token: "...",
+        within: &["variadic_placeholder"],
+        name: "First-class callable syntax",
+        explanation: "Makes a `Closure` of the function or method instead of calling it: \
+            `strlen(...)` is `Closure::fromCallable('strlen')`.",
+        since: PhpVersion::new(8, 1),
+        link: "functions.first_class_callable_syntax.php",
+    },
+    Construct {
+        token: "match",
+        within: &["match_expression"],
+        name: "`match` expression",
+        explanation: "Gives the value of the arm whose condition is identical (`===`) to the \
+            subject. Arms don't fall through, and an `UnhandledMatchError` is thrown if none \
+            matches and there's no `default`.",
+        since: PhpVersion::new(8, 0),
+        link: "control-structures.match.php",
+    },
+    Construct {
+        token: "fn",
+        within: &["arrow_function"],
+        name: "Arrow function",
+        explanation: "A closure of a single expression, which it returns. It captures the \
+            variables it uses by value, without a `use`.",
+        since: PhpVersion::new(7, 4),
+        link: "functions.arrow.php",
+    },
+    Construct {
+        token: "from",
+        within: &["yield_expression"],
+        name: "`yield from`",
+        explanation: "Yields every key and value of an array, `Traversable` or other generator \
+            in turn, and is what the generator returns, if it's one.",
+        since: PhpVersion::new(7, 0),
+        link: "language.generators.syntax.php#control-structures.yield.from",
+    },
+    Construct {
+        token: "yield",
+        within: &["yield_expression"],
+        name: "`yield`",
+        explanation: "Makes the function a generator, which returns a `Generator` without running \
+            its body until it's iterated over. Each `yield` gives a value, and pauses until the \
+            next one is asked for.",
+        since: PhpVersion::new(5, 5),
+        link: "language.generators.syntax.php",
+    },
+    Construct {
+        token: "#[",
+        within: &["attribute_group"],
+        name: "Attributes",
+        explanation: "Metadata about a declaration that reflection can read, with \
+            `getAttributes()`. Before PHP 8.0 they're comments.",
+        since: PhpVersion::new(8, 0),
+        link: "language.attributes.overview.php",
+    },
+];
+
+/// Whether a token is in nodes of the kinds that a construct needs.
+fn is_within(token: &Node, within: &[&str]) -> bool {
+    let mut node = *token;
+    within.iter().all(|kind| {
+        let Some(parent) = node.parent() else {
+            return false;
+        };
+        node = parent;
+        parent.kind() == *kind
+    })
+}
+
+/**
+ * What the operator or piece of syntax that the cursor is on does, like `<=>`, `??=`, `...` or
+ * `match`, with the version of PHP that it came with and a link to the manual. Where that's newer
+ * than the version the code runs on, the hover says so.
+ */
+pub fn construct_hover(
+    root_node: &Node,
+    file_contents: &str,
+    position: &Position,
+    php_version: PhpVersion,
+) -> Option<Hover> {
+    let point = to_point(position);
+    let mut token = root_node.descendant_for_point_range(point, point)?;
+    if token.is_named() {
+        return None;
+    }
+    // `yield from` is one thing
+    if token.kind() == "yield" {
+        token = token
+            .next_sibling()
+            .filter(|next| next.kind() == "from")
+            .unwrap_or(token);
+    }
+    let text = &file_contents[token.byte_range()];
+    let construct = CONSTRUCTS.iter().find(|construct| {
+        construct.token.eq_ignore_ascii_case(text) && is_within(&token, construct.within)
+    })?;
+
+    let mut value = format!(
+        "**{}** (PHP {}+)\n\n{}",
+        construct.name, construct.since, construct.explanation
+    );
+    if php_version < construct.since {
+        value.push_str(&format!(
+            "\n\nNot in PHP {}, which the code runs on.",
+            php_version
+        ));
+    }
+    value.push_str(&format!(
+        "\n\n[Documentation]({}{})",
+        MANUAL, construct.link
+    ));
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: Some(to_range(&token.range())),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::*;
+
+    use super::construct_hover;
+    use crate::config::PhpVersion;
+    use crate::file::php_parser;
+
+    #[test]
+    fn test_construct_hover() {
+        let source = "<?php
+$order = $a <=> $b;
+f(...$args);
+$all = [...$a, ...$b];
+$f = strlen(...);
+function g() { yield from h(); }
+#[Attribute]
+class A {}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let hover = |line, character| {
+            let hover = construct_hover(
+                &tree.root_node(),
+                source,
+                &Position::new(line, character),
+                PhpVersion::new(7, 4),
+            )?;
+            match hover.contents {
+                HoverContents::Markup(markup) => Some(markup.value),
+                _ => None,
+            }
+        };
+        let name = |line, character| {
+            hover(line, character).map(|value| value.lines().next().unwrap_or("").to_string())
+        };
+
+        assert_eq!(
+            Some("**Spaceship operator** (PHP 7.0+)".to_string()),
+            name(1, 13)
+        );
+        assert_eq!(
+            Some("**Argument unpacking** (PHP 5.6+)".to_string()),
+            name(2, 3)
+        );
+        assert_eq!(
+            Some("**Array unpacking** (PHP 7.4+)".to_string()),
+            name(3, 9)
+        );
+        assert_eq!(
+            Some("**First-class callable syntax** (PHP 8.1+)".to_string()),
+            name(4, 13)
+        );
+        assert_eq!(Some("**`yield from`** (PHP 7.0+)".to_string()), name(5, 16));
+        assert_eq!(Some("**Attributes** (PHP 8.0+)".to_string()), name(6, 0));
+        // the variable isn't syntax
+        assert_eq!(None, name(1, 2));
+
+        let attributes = hover(6, 1).unwrap();
+        assert!(attributes.contains("Not in PHP 7.4, which the code runs on."));
+        assert!(attributes.ends_with(
+            "[Documentation](https://www.php.net/manual/en/language.attributes.overview.php)"
+        ));
+    }
+}
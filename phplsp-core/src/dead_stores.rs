@@ -0,0 +1,474 @@
+use lsp_types::*;
+use tree_sitter::Node;
+
+use std::collections::HashSet;
+
+use crate::code_actions::QuickFix;
+use crate::file::{to_position, to_range};
+use crate::scope::variables;
+
+/// Node kinds that have their own variables, looked at one at a time.
+const FUNCTIONS: &[&str] = &[
+    "function_definition",
+    "method_declaration",
+    "anonymous_function",
+];
+
+/// Functions that read or write the variables of whatever calls them, by name.
+const SCOPE_FUNCTIONS: &[&str] = &["compact", "extract", "get_defined_vars", "eval"];
+
+/// Node kinds that run another file in the scope they're in.
+const INCLUDES: &[&str] = &[
+    "include_expression",
+    "include_once_expression",
+    "require_expression",
+    "require_once_expression",
+];
+
+/// Variables that outlive any function they're assigned in.
+pub const SUPERGLOBALS: &[&str] = &[
+    "this", "GLOBALS", "_SERVER", "_GET", "_POST", "_FILES", "_COOKIE", "_SESSION", "_REQUEST",
+    "_ENV",
+];
+
+/**
+ * Node kinds whose value can be worked out without doing anything else, as long as their children
+ * can. Reading properties and array elements counts, even though `__get` and `offsetGet` could do
+ * anything at all.
+ */
+const PURE: &[&str] = &[
+    "integer",
+    "float",
+    "boolean",
+    "null",
+    "string",
+    "string_content",
+    "escape_sequence",
+    "encapsed_string",
+    "heredoc",
+    "heredoc_start",
+    "heredoc_body",
+    "heredoc_end",
+    "nowdoc",
+    "nowdoc_body",
+    "nowdoc_string",
+    "name",
+    "qualified_name",
+    "namespace_name",
+    "namespace_name_as_prefix",
+    "variable_name",
+    "class_constant_access_expression",
+    "array_creation_expression",
+    "array_element_initializer",
+    "binary_expression",
+    "unary_op_expression",
+    "conditional_expression",
+    "cast_expression",
+    "cast_type",
+    "parenthesized_expression",
+    "member_access_expression",
+    "nullsafe_member_access_expression",
+    "subscript_expression",
+    "arguments",
+    "argument",
+    "comment",
+];
+
+/// Built-in functions that do nothing but return a value, as long as their arguments don't.
+const PURE_FUNCTIONS: &[&str] = &[
+    "abs",
+    "array_key_exists",
+    "array_keys",
+    "array_merge",
+    "array_slice",
+    "array_values",
+    "count",
+    "empty",
+    "explode",
+    "implode",
+    "in_array",
+    "is_array",
+    "is_bool",
+    "is_callable",
+    "is_float",
+    "is_int",
+    "is_null",
+    "is_numeric",
+    "is_object",
+    "is_string",
+    "isset",
+    "json_encode",
+    "max",
+    "min",
+    "sprintf",
+    "str_contains",
+    "str_ends_with",
+    "str_replace",
+    "str_starts_with",
+    "strlen",
+    "strpos",
+    "strtolower",
+    "strtoupper",
+    "substr",
+    "trim",
+];
+
+fn variable_name<'a>(node: &Node, file_contents: &'a str) -> Option<&'a str> {
+    (node.kind() == "variable_name")
+        .then(|| file_contents[node.byte_range()].trim_start_matches('$'))
+}
+
+/// Whether evaluating an expression can't do anything but give its value.
+pub fn is_pure(node: &Node, file_contents: &str) -> bool {
+    match node.kind() {
+        // making a closure doesn't run it
+        "anonymous_function" | "arrow_function" => return true,
+        "function_call_expression" => {
+            let called = node
+                .child_by_field_name("function")
+                .map(|name| file_contents[name.byte_range()].trim_start_matches('\\'));
+            if !called.is_some_and(|called| {
+                PURE_FUNCTIONS
+                    .iter()
+                    .any(|function| function.eq_ignore_ascii_case(called))
+            }) {
+                return false;
+            }
+        }
+        kind if !PURE.contains(&kind) => return false,
+        _ => {}
+    }
+
+    let mut cursor = node.walk();
+    let pure = node
+        .named_children(&mut cursor)
+        .all(|child| is_pure(&child, file_contents));
+    pure
+}
+
+/// Whether anything in a function could read or write its variables without naming them.
+pub fn has_dynamic_scope(node: &Node, file_contents: &str) -> bool {
+    match node.kind() {
+        "dynamic_variable_name" | "goto_statement" => return true,
+        kind if INCLUDES.contains(&kind) => return true,
+        "function_call_expression" => {
+            let called = node
+                .child_by_field_name("function")
+                .map(|name| file_contents[name.byte_range()].trim_start_matches('\\'));
+            if called.is_some_and(|called| {
+                SCOPE_FUNCTIONS
+                    .iter()
+                    .any(|function| function.eq_ignore_ascii_case(called))
+            }) {
+                return true;
+            }
+        }
+        _ => {}
+    }
+
+    let mut cursor = node.walk();
+    let dynamic = node
+        .children(&mut cursor)
+        .any(|child| has_dynamic_scope(&child, file_contents));
+    dynamic
+}
+
+/// Variables that are references, or bound to something outside of the function.
+pub fn bound_variables<'a>(node: &Node, file_contents: &'a str, bound: &mut HashSet<&'a str>) {
+    let mut cursor = node.walk();
+    match node.kind() {
+        "simple_parameter" if node.child_by_field_name("reference_modifier").is_some() => {
+            bound.extend(
+                node.child_by_field_name("name")
+                    .and_then(|name| variable_name(&name, file_contents)),
+            );
+        }
+        "global_declaration" | "by_ref" => {
+            bound.extend(
+                node.named_children(&mut cursor)
+                    .filter_map(|child| variable_name(&child, file_contents)),
+            );
+        }
+        "static_variable_declaration" => {
+            bound.extend(
+                node.child_by_field_name("name")
+                    .and_then(|name| variable_name(&name, file_contents)),
+            );
+        }
+        "reference_assignment_expression" => {
+            for side in ["left", "right"] {
+                bound.extend(
+                    node.child_by_field_name(side)
+                        .and_then(|side| variable_name(&side, file_contents)),
+                );
+            }
+        }
+        _ => {}
+    }
+
+    cursor.reset(*node);
+    for child in node.named_children(&mut cursor) {
+        bound_variables(&child, file_contents, bound);
+    }
+}
+
+/// Whether a node names a variable anywhere in it.
+fn mentions(node: &Node, file_contents: &str, name: &str) -> bool {
+    if variable_name(node, file_contents) == Some(name) {
+        return true;
+    }
+
+    let mut cursor = node.walk();
+    let mentioned = node
+        .named_children(&mut cursor)
+        .any(|child| mentions(&child, file_contents, name));
+    mentioned
+}
+
+/// The variable that a statement like `$a = 1;` assigns to, and the assignment.
+fn assignment<'a, 'tree>(
+    statement: &Node<'tree>,
+    file_contents: &'a str,
+) -> Option<(&'a str, Node<'tree>)> {
+    if statement.kind() != "expression_statement" {
+        return None;
+    }
+    let assignment = statement
+        .named_child(0)
+        .filter(|expression| expression.kind() == "assignment_expression")?;
+    let name = variable_name(&assignment.child_by_field_name("left")?, file_contents)?;
+    Some((name, assignment))
+}
+
+/// The blocks of a function that aren't in its closures, its classes, or a `try`.
+fn blocks<'tree>(node: &Node<'tree>, found: &mut Vec<Node<'tree>>) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        // anything in a `try` could be read by a `catch` or a `finally`
+        if FUNCTIONS.contains(&child.kind())
+            || matches!(
+                child.kind(),
+                "arrow_function" | "class_declaration" | "try_statement"
+            )
+        {
+            continue;
+        }
+        if child.kind() == "compound_statement" {
+            found.push(child);
+        }
+        blocks(&child, found);
+    }
+}
+
+/// What to delete to remove a statement: the lines it's on, if nothing else is on them.
+pub fn removal(statement: &Node, file_contents: &str) -> Range {
+    let start = statement.start_byte();
+    let end = statement.end_byte();
+    let line_start = file_contents[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = file_contents[end..]
+        .find('\n')
+        .map_or(file_contents.len(), |i| end + i);
+    let alone = file_contents[line_start..start].trim().is_empty()
+        && file_contents[end..line_end].trim().is_empty();
+    if !alone {
+        return to_range(&statement.range());
+    }
+
+    let start = Position {
+        line: statement.start_position().row as u32,
+        character: 0,
+    };
+    let end = match line_end < file_contents.len() {
+        true => Position {
+            line: statement.end_position().row as u32 + 1,
+            character: 0,
+        },
+        false => to_position(&statement.end_position()),
+    };
+    Range { start, end }
+}
+
+fn dead_store(
+    statement: &Node,
+    assignment: &Node,
+    file_contents: &str,
+    name: &str,
+    message: String,
+) -> Diagnostic {
+    let mut diagnostic = Diagnostic {
+        range: to_range(&assignment.range()),
+        severity: Some(DiagnosticSeverity::HINT),
+        code: Some(NumberOrString::String("dead-store".to_string())),
+        source: Some("phplsp".to_string()),
+        message,
+        tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+        ..Diagnostic::default()
+    };
+    QuickFix {
+        title: format!("Remove the assignment to `${}`", name),
+        edits: vec![TextEdit {
+            range: removal(statement, file_contents),
+            new_text: String::new(),
+        }],
+        rename: None,
+    }
+    .attach(&mut diagnostic);
+    diagnostic
+}
+
+fn function_dead_stores(
+    function: &Node,
+    body: &Node,
+    file_contents: &str,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    if has_dynamic_scope(function, file_contents) {
+        return;
+    }
+    let mut bound = HashSet::new();
+    bound_variables(function, file_contents, &mut bound);
+    let variables = variables(function, file_contents);
+
+    let mut function_blocks = vec![*body];
+    blocks(body, &mut function_blocks);
+    for block in function_blocks {
+        let mut cursor = block.walk();
+        let statements: Vec<Node> = block
+            .named_children(&mut cursor)
+            .filter(|statement| statement.kind() != "comment")
+            .collect();
+
+        for (i, statement) in statements.iter().enumerate() {
+            let Some((name, store)) = assignment(statement, file_contents) else {
+                continue;
+            };
+            let right = store.child_by_field_name("right");
+            if bound.contains(name)
+                || SUPERGLOBALS.contains(&name)
+                || !right.is_some_and(|right| is_pure(&right, file_contents))
+            {
+                continue;
+            }
+
+            // running off the end of any other block could be going around a loop again, and
+            // variables that are never read at all are reported as unused instead
+            let read = variables
+                .get(name)
+                .is_some_and(|variable| !variable.reads.is_empty());
+            let mut message =
+                (block == *body && read).then(|| format!("`${}` is assigned but never read", name));
+            for later in &statements[i + 1..] {
+                let reassigned = assignment(later, file_contents).is_some_and(|(other, store)| {
+                    other == name
+                        && store
+                            .child_by_field_name("right")
+                            .is_some_and(|right| !mentions(&right, file_contents, name))
+                });
+                if reassigned {
+                    message = Some(format!(
+                        "`${}` is reassigned before this value is read",
+                        name
+                    ));
+                    break;
+                }
+                if mentions(later, file_contents, name) {
+                    message = None;
+                    break;
+                }
+            }
+
+            if let Some(message) = message {
+                diagnostics.push(dead_store(statement, &store, file_contents, name, message));
+            }
+        }
+    }
+}
+
+/**
+ * Report assignments to local variables whose values are never read: they're either assigned
+ * again first, or the function ends. Only statements like `$a = ...;` are looked at, and only
+ * when what they assign can't do anything else, so removing them doesn't change what the code
+ * does.
+ *
+ * Variables that are references, globals or statics are left alone, and so are functions that
+ * could get at their variables by name, like with `compact()` or `$$name`.
+ */
+pub fn dead_stores(node: &Node, file_contents: &str, diagnostics: &mut Vec<Diagnostic>) {
+    if FUNCTIONS.contains(&node.kind()) {
+        if let Some(body) = node
+            .child_by_field_name("body")
+            .filter(|body| body.kind() == "compound_statement")
+        {
+            function_dead_stores(node, &body, file_contents, diagnostics);
+        }
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        dead_stores(&child, file_contents, diagnostics);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::dead_stores;
+    use crate::file::php_parser;
+
+    #[test]
+    fn test_dead_stores() {
+        let source = "<?php
+$top = 1;
+function f(&$out, $in) {
+    $a = 1;
+    $a = 2;
+    $b = [$a, 'x'];
+    $b = $b + [1];
+    $c = foo();
+    $c = 3;
+    $out = 1;
+    $out = 2;
+    if ($in) {
+        $d = 1;
+        $d = 2;
+        $e = $in;
+    }
+    try {
+        $f = 1;
+        $f = bar();
+    } catch (Exception $x) {
+        return $f;
+    }
+    $g = 1;
+    return $c;
+}
+function g() {
+    $a = 1;
+    $vars = compact('a');
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let mut diagnostics = vec![];
+        dead_stores(&tree.root_node(), source, &mut diagnostics);
+
+        // `$g` is never read at all, which is for unused variables to report
+        let mut found: Vec<(u32, String)> = diagnostics
+            .into_iter()
+            .map(|diagnostic| (diagnostic.range.start.line, diagnostic.message))
+            .collect();
+        found.sort();
+        assert_eq!(
+            vec![
+                (
+                    3,
+                    "`$a` is reassigned before this value is read".to_string()
+                ),
+                (6, "`$b` is assigned but never read".to_string()),
+                (
+                    12,
+                    "`$d` is reassigned before this value is read".to_string()
+                ),
+            ],
+            found
+        );
+    }
+}
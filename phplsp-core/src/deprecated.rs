@@ -0,0 +1,170 @@
+//! Uses of what's marked as deprecated.
+
+use lsp_types::*;
+use tree_sitter::Node;
+
+use crate::file::to_point;
+use crate::index::WorkspaceIndex;
+use crate::resolve::Resolver;
+use crate::symbols::{Declaration, FileSymbols, Reference, ReferenceKind};
+use crate::types::TypeInference;
+
+/// Whether the name at a position is in a comment, like `@see OldClass`.
+fn in_comment(root_node: &Node, position: &Position) -> bool {
+    let point = to_point(position);
+    root_node
+        .descendant_for_point_range(point, point)
+        .is_some_and(|node| node.kind() == "comment")
+}
+
+/// The member that a reference names, following variables whose class we know.
+fn member<'a>(
+    root_node: &Node,
+    reference: &Reference,
+    resolver: &Resolver<'a>,
+    types: &TypeInference<'a>,
+) -> Option<&'a Declaration> {
+    let start = to_point(&reference.range.start);
+    let node = root_node.descendant_for_point_range(start, start)?;
+    // properties are declared with their `$`, but referenced without it
+    let property = format!("${}", reference.name);
+
+    // the name is at most a `variable_name` deep in whatever accesses it
+    let mut current = node.parent();
+    for _ in 0..2 {
+        let parent = current?;
+        match parent.kind() {
+            "member_call_expression"
+            | "nullsafe_member_call_expression"
+            | "scoped_call_expression" => return types.callee(&parent),
+            "member_access_expression" | "nullsafe_member_access_expression" => {
+                let class = types.object_class(&parent.child_by_field_name("object")?)?;
+                return resolver.member(&class, &property);
+            }
+            "scoped_property_access_expression" => {
+                let class = types.object_class(&parent.child_by_field_name("scope")?)?;
+                return resolver.member(&class, &property);
+            }
+            "class_constant_access_expression" => {
+                let class = types.object_class(&parent.named_child(0)?)?;
+                return resolver.member(&class, &reference.name);
+            }
+            _ => current = parent.parent(),
+        }
+    }
+
+    None
+}
+
+/**
+ * Report the classes, functions, constants and members used in a file that are marked with
+ * `@deprecated` or `#[Deprecated]`, saying what the deprecation says, like what to use instead.
+ * They're tagged as deprecated so that editors can strike them through. Mentions in comments
+ * aren't uses.
+ */
+pub fn deprecated_usages(
+    root_node: &Node,
+    file_contents: &str,
+    symbols: &FileSymbols,
+    index: &WorkspaceIndex,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let resolver = Resolver::new(file_contents, symbols, index);
+    let types = TypeInference::new(file_contents, &resolver);
+    for reference in &symbols.references {
+        if in_comment(root_node, &reference.range.start) {
+            continue;
+        }
+        let declaration = match reference.kind {
+            ReferenceKind::Member => member(root_node, reference, &resolver, &types),
+            _ => resolver.resolve(reference),
+        };
+        let Some(declaration) = declaration.filter(|declaration| declaration.deprecated) else {
+            continue;
+        };
+
+        let message = match &declaration.deprecation {
+            Some(deprecation) => format!("`{}` is deprecated: {}", declaration.name, deprecation),
+            None => format!("`{}` is deprecated", declaration.name),
+        };
+        diagnostics.push(Diagnostic {
+            range: reference.range,
+            severity: Some(DiagnosticSeverity::HINT),
+            code: Some(NumberOrString::String("deprecated".to_string())),
+            source: Some("phplsp".to_string()),
+            message,
+            tags: Some(vec![DiagnosticTag::DEPRECATED]),
+            ..Diagnostic::default()
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::Url;
+
+    use super::deprecated_usages;
+    use crate::file::php_parser;
+    use crate::index::WorkspaceIndex;
+    use crate::symbols::file_symbols;
+
+    #[test]
+    fn test_deprecated_usages() {
+        let source = "<?php
+namespace App;
+/**
+ * @deprecated 2.0 Use {@see fetch()} instead,
+ *   which caches.
+ */
+function load() {}
+function fetch() {}
+class Client {
+    #[\\JetBrains\\PhpStorm\\Deprecated(reason: 'blocks', replacement: '%class%->sendAsync()')]
+    public function send() {}
+    /** @deprecated */
+    const TIMEOUT = 30;
+}
+/** @see load() */
+function run(Client $client) {
+    load();
+    fetch();
+    $client->send();
+    echo Client::TIMEOUT;
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(&Url::parse("file:///a.php").unwrap(), symbols.clone());
+        let mut diagnostics = vec![];
+        deprecated_usages(
+            &tree.root_node(),
+            source,
+            &symbols,
+            &index,
+            &mut diagnostics,
+        );
+
+        let mut found: Vec<(u32, String)> = diagnostics
+            .into_iter()
+            .map(|diagnostic| (diagnostic.range.start.line, diagnostic.message))
+            .collect();
+        found.sort();
+        assert_eq!(
+            vec![
+                (
+                    16,
+                    "`App\\load` is deprecated: 2.0 Use {@see fetch()} instead, which caches."
+                        .to_string()
+                ),
+                (
+                    18,
+                    "`App\\Client::send` is deprecated: blocks, use `%class%->sendAsync()` instead"
+                        .to_string()
+                ),
+                (19, "`App\\Client::TIMEOUT` is deprecated".to_string()),
+            ],
+            found
+        );
+    }
+}
@@ -0,0 +1,531 @@
+use lsp_types::*;
+use tree_sitter::Node;
+
+use crate::arguments::{argument_counts, argument_types};
+use crate::array_keys::undefined_keys;
+use crate::budget::{truncate_slow_functions, FUNCTION_BUDGET};
+use crate::conditions::{assignments_in_conditions, duplicate_conditions};
+use crate::config::{Config, RuleSetting};
+use crate::constant_conditions::constant_conditions;
+use crate::dead_stores::dead_stores;
+use crate::deprecated::deprecated_usages;
+use crate::duplicates::duplicate_declarations;
+use crate::fallthrough::fallthroughs;
+use crate::file::to_range;
+use crate::foreach::foreach_diagnostics;
+use crate::generators::generator_diagnostics;
+use crate::goto_labels::goto_labels;
+use crate::index::WorkspaceIndex;
+use crate::inheritance::{
+    final_constants, final_overrides, incompatible_overrides, inherited_docs,
+    missing_implementations, overrides,
+};
+use crate::ini::ini_directive_diagnostics;
+use crate::levels::drop_above_level;
+use crate::metrics::metrics;
+use crate::missing_return::missing_returns;
+use crate::naming::naming;
+use crate::no_effect::no_effect;
+use crate::null_access::null_accesses;
+use crate::options::option_diagnostics;
+use crate::php_version::version_diagnostics;
+use crate::preg::regex_diagnostics;
+use crate::printf::format_diagnostics;
+use crate::property_types::property_types;
+use crate::references::reference_diagnostics;
+use crate::return_types::return_types;
+use crate::symbols::FileSymbols;
+use crate::tags::is_set_visibility;
+use crate::undefined::{
+    undefined_classes, undefined_functions, undefined_members, unresolved_imports,
+};
+use crate::unreachable::{unreachable, unreachable_after_never};
+use crate::unused_private::unused_private;
+use crate::unused_variables::unused_variables;
+use crate::usage_rules::{disabled_functions, usage_rules};
+use crate::visibility::visibility;
+
+/// Run every analysis we have over a parsed file.
+pub fn diagnostics(root_node: &Node, file_contents: &str, config: &Config) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    syntax_errors(root_node, file_contents, &mut diagnostics);
+    dead_stores(root_node, file_contents, &mut diagnostics);
+    unused_variables(root_node, file_contents, &mut diagnostics);
+    unused_private(root_node, file_contents, &mut diagnostics);
+    unreachable(root_node, file_contents, &mut diagnostics);
+    no_effect(root_node, file_contents, &mut diagnostics);
+    duplicate_conditions(root_node, file_contents, &mut diagnostics);
+    assignments_in_conditions(root_node, file_contents, &mut diagnostics);
+    fallthroughs(root_node, file_contents, &mut diagnostics);
+    goto_labels(root_node, file_contents, &mut diagnostics);
+    metrics(root_node, file_contents, &config.metrics, &mut diagnostics);
+    naming(root_node, file_contents, &config.naming, &mut diagnostics);
+    usage_rules(root_node, file_contents, &config.usage, &mut diagnostics);
+    disabled_functions(root_node, file_contents, config, &mut diagnostics);
+    regex_diagnostics(root_node, file_contents, &mut diagnostics);
+    visibility(
+        root_node,
+        file_contents,
+        config.default_visibility,
+        &mut diagnostics,
+    );
+    version_diagnostics(
+        root_node,
+        file_contents,
+        config.php_version,
+        &mut diagnostics,
+    );
+
+    drop_above_level(config.level, &mut diagnostics);
+    apply_rule_settings(|code| config.rules.get(code).copied(), &mut diagnostics);
+    diagnostics
+}
+
+/**
+ * Run the analyses that need to know what the rest of the workspace declares, which are only as
+ * right as the index is up to date.
+ */
+pub fn workspace_diagnostics(
+    uri: &Url,
+    root_node: &Node,
+    file_contents: &str,
+    symbols: &FileSymbols,
+    index: &WorkspaceIndex,
+    config: &Config,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    duplicate_declarations(uri, symbols, index, &mut diagnostics);
+    undefined_functions(root_node, symbols, index, &mut diagnostics);
+    undefined_classes(root_node, symbols, index, &mut diagnostics);
+    unresolved_imports(root_node, file_contents, index, &mut diagnostics);
+    undefined_members(root_node, file_contents, symbols, index, &mut diagnostics);
+    final_constants(file_contents, symbols, index, &mut diagnostics);
+    missing_implementations(file_contents, symbols, index, &mut diagnostics);
+    final_overrides(file_contents, symbols, index, &mut diagnostics);
+    incompatible_overrides(file_contents, symbols, index, &mut diagnostics);
+    inherited_docs(file_contents, symbols, index, config, &mut diagnostics);
+    overrides(file_contents, symbols, index, config, &mut diagnostics);
+    ini_directive_diagnostics(root_node, file_contents, index, &mut diagnostics);
+    unreachable_after_never(root_node, file_contents, symbols, index, &mut diagnostics);
+    constant_conditions(root_node, file_contents, symbols, index, &mut diagnostics);
+    option_diagnostics(root_node, file_contents, symbols, index, &mut diagnostics);
+    format_diagnostics(root_node, file_contents, symbols, index, &mut diagnostics);
+    undefined_keys(root_node, file_contents, symbols, index, &mut diagnostics);
+    missing_returns(root_node, file_contents, symbols, index, &mut diagnostics);
+    generator_diagnostics(root_node, file_contents, symbols, index, &mut diagnostics);
+    return_types(root_node, file_contents, symbols, index, &mut diagnostics);
+    argument_counts(root_node, file_contents, symbols, index, &mut diagnostics);
+    argument_types(root_node, file_contents, symbols, index, &mut diagnostics);
+    property_types(root_node, file_contents, symbols, index, &mut diagnostics);
+    reference_diagnostics(root_node, file_contents, symbols, index, &mut diagnostics);
+    foreach_diagnostics(root_node, file_contents, symbols, index, &mut diagnostics);
+    null_accesses(root_node, file_contents, symbols, index, &mut diagnostics);
+    deprecated_usages(root_node, file_contents, symbols, index, &mut diagnostics);
+    truncate_slow_functions(
+        root_node,
+        file_contents,
+        symbols,
+        index,
+        FUNCTION_BUDGET,
+        &mut diagnostics,
+    );
+
+    drop_above_level(config.level, &mut diagnostics);
+    apply_rule_settings(|code| config.rules.get(code).copied(), &mut diagnostics);
+    diagnostics
+}
+
+/// Where every diagnostic code is explained, under a heading of its own.
+pub const RULES_URL: &str = "https://github.com/chuck-sys/phplsp/blob/main/docs/diagnostics.md";
+
+/// What a client can show of the diagnostics we send it, as it says in its capabilities.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DiagnosticSupport {
+    pub tags: Vec<DiagnosticTag>,
+    pub related_information: bool,
+    pub code_description: bool,
+}
+
+impl DiagnosticSupport {
+    pub fn new(capabilities: Option<&PublishDiagnosticsClientCapabilities>) -> Self {
+        let Some(capabilities) = capabilities else {
+            return Self::default();
+        };
+        Self {
+            tags: capabilities
+                .tag_support
+                .as_ref()
+                .map(|tags| tags.value_set.clone())
+                .unwrap_or_default(),
+            related_information: capabilities.related_information == Some(true),
+            code_description: capabilities.code_description_support == Some(true),
+        }
+    }
+
+    /**
+     * Make diagnostics of a file fit what the client can show: tags it doesn't know are dropped,
+     * and hints that it could only have faded or struck through become information, so that they
+     * don't go unseen. Related information it can't show is told in the message instead, and codes
+     * link to where they're explained if it can follow them.
+     */
+    pub fn adapt(&self, uri: &Url, diagnostics: &mut [Diagnostic]) {
+        for diagnostic in diagnostics {
+            if let Some(tags) = diagnostic.tags.take() {
+                let (shown, hidden): (Vec<DiagnosticTag>, Vec<DiagnosticTag>) =
+                    tags.into_iter().partition(|tag| self.tags.contains(tag));
+                if !hidden.is_empty() && diagnostic.severity == Some(DiagnosticSeverity::HINT) {
+                    diagnostic.severity = Some(DiagnosticSeverity::INFORMATION);
+                }
+                diagnostic.tags = (!shown.is_empty()).then_some(shown);
+            }
+
+            if !self.related_information {
+                for related in diagnostic.related_information.take().unwrap_or_default() {
+                    let line = related.location.range.start.line + 1;
+                    let place = match related.location.uri == *uri {
+                        true => format!("line {}", line),
+                        false => {
+                            let path = related.location.uri.path();
+                            let file = path.rsplit('/').next().unwrap_or(path);
+                            format!("{}:{}", file, line)
+                        }
+                    };
+                    diagnostic.message += &format!("\n{} ({})", related.message, place);
+                }
+            }
+
+            if self.code_description {
+                if let Some(NumberOrString::String(code)) = &diagnostic.code {
+                    diagnostic.code_description = Url::parse(&format!("{}#{}", RULES_URL, code))
+                        .ok()
+                        .map(|href| CodeDescription { href });
+                }
+            }
+        }
+    }
+}
+
+/// Drop the diagnostics that users said not to report in a file, in `.phplsp/overrides.json`.
+pub fn drop_ignored(uri: &Url, index: &WorkspaceIndex, diagnostics: &mut Vec<Diagnostic>) {
+    diagnostics.retain(|diagnostic| match &diagnostic.code {
+        Some(NumberOrString::String(code)) => !index.is_ignored(uri, code),
+        _ => true,
+    });
+}
+
+/**
+ * Give the diagnostics of rules that a setting says to report as something else that severity,
+ * and drop those of rules that are `off`.
+ */
+pub fn apply_rule_settings(
+    setting: impl Fn(&str) -> Option<RuleSetting>,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    diagnostics.retain_mut(|diagnostic| {
+        let Some(NumberOrString::String(code)) = &diagnostic.code else {
+            return true;
+        };
+        let severity = match setting(code) {
+            None => return true,
+            Some(RuleSetting::Off) => return false,
+            Some(RuleSetting::Error) => DiagnosticSeverity::ERROR,
+            Some(RuleSetting::Warning) => DiagnosticSeverity::WARNING,
+            Some(RuleSetting::Information) => DiagnosticSeverity::INFORMATION,
+            Some(RuleSetting::Hint) => DiagnosticSeverity::HINT,
+        };
+        diagnostic.severity = Some(severity);
+        true
+    });
+}
+
+/// Apply the `rules` of the `.phplsp/overrides.json` of a file's workspace folder to its diagnostics.
+pub fn apply_project_rules(uri: &Url, index: &WorkspaceIndex, diagnostics: &mut Vec<Diagnostic>) {
+    apply_rule_settings(|code| index.rule_setting(uri, code), diagnostics);
+}
+
+/**
+ * Drop the diagnostics that a file already had when the `.phplsp/baseline.json` of its workspace
+ * folder was made. Run it last, since the baseline counts what's left after the rest.
+ */
+pub fn drop_baselined(uri: &Url, index: &WorkspaceIndex, diagnostics: &mut Vec<Diagnostic>) {
+    let Ok(path) = uri.to_file_path() else {
+        return;
+    };
+    for baseline in index.baselines() {
+        baseline.drop_known(&path, diagnostics);
+    }
+}
+
+/// Brackets that tree-sitter can leave unclosed in an error, and what closes them.
+const BRACKETS: &[(&str, &str)] = &[("{", "}"), ("(", ")"), ("[", "]")];
+
+/// Errors longer than this are too much to quote.
+const QUOTE_LIMIT: usize = 24;
+
+fn syntax_error(range: Range, message: String) -> Diagnostic {
+    Diagnostic {
+        range,
+        severity: Some(DiagnosticSeverity::ERROR),
+        code: Some(NumberOrString::String("syntax-error".to_string())),
+        source: Some("phplsp".to_string()),
+        message: format!("syntax error: {}", message),
+        ..Diagnostic::default()
+    }
+}
+
+/// What went wrong in an `ERROR` node, going by the hints that tree-sitter's recovery leaves.
+fn error_message(node: &Node, file_contents: &str) -> (Range, String) {
+    // an opening bracket whose closing one never came
+    let mut open: Vec<Node> = vec![];
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        if BRACKETS.iter().any(|(opening, _)| *opening == child.kind()) {
+            open.push(child);
+        } else if let Some(i) = open
+            .iter()
+            .rposition(|opening| BRACKETS.contains(&(opening.kind(), child.kind())))
+        {
+            open.truncate(i);
+        }
+    }
+    if let Some(bracket) = open.last() {
+        return (
+            to_range(&bracket.range()),
+            format!("unclosed `{}`", bracket.kind()),
+        );
+    }
+
+    let text = file_contents[node.byte_range()].trim();
+    let message = match text {
+        "" => "unexpected end of file".to_string(),
+        text if text.len() <= QUOTE_LIMIT && !text.contains('\n') => {
+            format!("unexpected `{}`", text)
+        }
+        _ => "unexpected code".to_string(),
+    };
+    (to_range(&node.range()), message)
+}
+
+/**
+ * Report `ERROR` nodes, and `MISSING` ones that tree-sitter made up to recover from something
+ * like a forgotten `;`, saying what was expected.
+ */
+fn syntax_errors(node: &Node, file_contents: &str, diagnostics: &mut Vec<Diagnostic>) {
+    if node.is_missing() {
+        let expected = match node.is_named() {
+            true => format!("a {}", node.kind().replace('_', " ")),
+            false => format!("`{}`", node.kind()),
+        };
+        diagnostics.push(syntax_error(
+            to_range(&node.range()),
+            format!("expected {}", expected),
+        ));
+        return;
+    }
+    if !node.has_error() {
+        return;
+    }
+
+    // what follows `private(set)` is an error to the grammar, but not to PHP 8.4
+    if node.is_error()
+        && node
+            .prev_sibling()
+            .is_some_and(|previous| is_set_visibility(&previous, file_contents))
+    {
+        return;
+    }
+    if node.is_error() {
+        let (range, message) = error_message(node, file_contents);
+        diagnostics.push(syntax_error(range, message));
+        return;
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        syntax_errors(&child, file_contents, diagnostics);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::*;
+
+    use super::{diagnostics, DiagnosticSupport};
+    use crate::config::{Config, RuleSetting};
+    use crate::file::php_parser;
+
+    fn parse(source: &str) -> tree_sitter::Tree {
+        php_parser().parse(source, None).unwrap()
+    }
+
+    #[test]
+    fn test_no_diagnostics_for_valid_file() {
+        let source = "<?php\nclass A { public function b(): void {} }\n";
+        let tree = parse(source);
+        assert!(diagnostics(&tree.root_node(), source, &Config::default()).is_empty());
+    }
+
+    #[test]
+    fn test_rule_settings() {
+        let source = "<?php\nfunction f() {\n    $a = 1;\n    $b = ;\n}\n";
+        let tree = parse(source);
+        let codes = |config: &Config| {
+            diagnostics(&tree.root_node(), source, config)
+                .into_iter()
+                .filter_map(|diagnostic| match diagnostic.code {
+                    Some(NumberOrString::String(code)) => Some((code, diagnostic.severity?)),
+                    _ => None,
+                })
+                .collect::<Vec<(String, DiagnosticSeverity)>>()
+        };
+
+        let mut config = Config::default();
+        config
+            .rules
+            .insert("dead-store".to_string(), RuleSetting::Off);
+        config
+            .rules
+            .insert("unused-variable".to_string(), RuleSetting::Error);
+        assert_eq!(
+            vec![
+                ("syntax-error".to_string(), DiagnosticSeverity::ERROR),
+                ("unused-variable".to_string(), DiagnosticSeverity::ERROR),
+            ],
+            codes(&config)
+        );
+
+        config.level = Some(0);
+        assert_eq!(
+            vec![("syntax-error".to_string(), DiagnosticSeverity::ERROR)],
+            codes(&config)
+        );
+    }
+
+    #[test]
+    fn test_syntax_error() {
+        let source = "<?php\n$a = ;\n";
+        let tree = parse(source);
+        let diags = diagnostics(&tree.root_node(), source, &Config::default());
+        assert!(!diags.is_empty());
+        assert_eq!("syntax error: unexpected `=`", diags[0].message);
+    }
+
+    #[test]
+    fn test_syntax_error_hints() {
+        let messages = |source: &str| {
+            let tree = parse(source);
+            diagnostics(&tree.root_node(), source, &Config::default())
+                .into_iter()
+                .filter(|diagnostic| diagnostic.severity == Some(DiagnosticSeverity::ERROR))
+                .map(|diagnostic| (diagnostic.range.start, diagnostic.message))
+                .collect::<Vec<(Position, String)>>()
+        };
+
+        assert_eq!(
+            vec![(
+                Position::new(1, 6),
+                "syntax error: expected `)`".to_string()
+            )],
+            messages(
+                "<?php
+f(1, 2;
+"
+            )
+        );
+        assert_eq!(
+            vec![(
+                Position::new(1, 13),
+                "syntax error: unclosed `{`".to_string()
+            )],
+            messages(
+                "<?php
+function f() {
+    $a = 1;
+"
+            )
+        );
+        // the grammar doesn't know asymmetric visibility, but PHP 8.4 does
+        assert_eq!(
+            Vec::<(Position, String)>::new(),
+            messages(
+                "<?php
+class A { public private(set) int $b = 1; }
+"
+            )
+        );
+    }
+
+    #[test]
+    fn test_diagnostic_support() {
+        let uri = Url::parse("file:///project/a.php").unwrap();
+        let related = |uri: &str, line: u32| DiagnosticRelatedInformation {
+            location: Location {
+                uri: Url::parse(uri).unwrap(),
+                range: Range::new(Position::new(line, 0), Position::new(line, 4)),
+            },
+            message: "`User` is also declared here".to_string(),
+        };
+        let sent = vec![
+            Diagnostic {
+                severity: Some(DiagnosticSeverity::HINT),
+                code: Some(NumberOrString::String("unused-variable".to_string())),
+                message: "`$a` is never used".to_string(),
+                tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+                ..Diagnostic::default()
+            },
+            Diagnostic {
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(NumberOrString::String("duplicate-declaration".to_string())),
+                message: "`User` is declared more than once".to_string(),
+                related_information: Some(vec![
+                    related("file:///project/a.php", 2),
+                    related("file:///project/src/b.php", 9),
+                ]),
+                ..Diagnostic::default()
+            },
+        ];
+
+        let mut diagnostics = sent.clone();
+        DiagnosticSupport::default().adapt(&uri, &mut diagnostics);
+        assert_eq!(
+            Some(DiagnosticSeverity::INFORMATION),
+            diagnostics[0].severity
+        );
+        assert_eq!(None, diagnostics[0].tags);
+        assert_eq!(None, diagnostics[0].code_description);
+        assert_eq!(None, diagnostics[1].related_information);
+        assert_eq!(
+            "`User` is declared more than once
+`User` is also declared here (line 3)
+`User` is also declared here (b.php:10)",
+            diagnostics[1].message
+        );
+
+        let mut diagnostics = sent.clone();
+        let support = DiagnosticSupport::new(Some(&PublishDiagnosticsClientCapabilities {
+            related_information: Some(true),
+            tag_support: Some(TagSupport {
+                value_set: vec![DiagnosticTag::UNNECESSARY, DiagnosticTag::DEPRECATED],
+            }),
+            code_description_support: Some(true),
+            ..PublishDiagnosticsClientCapabilities::default()
+        }));
+        support.adapt(&uri, &mut diagnostics);
+        assert_eq!(sent[0].severity, diagnostics[0].severity);
+        assert_eq!(sent[0].tags, diagnostics[0].tags);
+        assert_eq!(sent[1].message, diagnostics[1].message);
+        assert_eq!(
+            sent[1].related_information,
+            diagnostics[1].related_information
+        );
+        assert_eq!(
+            "https://github.com/chuck-sys/phplsp/blob/main/docs/diagnostics.md#unused-variable",
+            diagnostics[0]
+                .code_description
+                .as_ref()
+                .unwrap()
+                .href
+                .as_str()
+        );
+    }
+}
@@ -0,0 +1,219 @@
+//! Doc comments of declarations as hovers show them, with what they inherit filled in.
+
+use tree_sitter::Node;
+
+use crate::file::to_range;
+use crate::resolve::Resolver;
+use crate::symbols::Declaration;
+use crate::tags::{definitions, doc_comments, inherits_doc};
+
+/// How far up `@inheritDoc` is followed, in case the supertypes of classes go round in a circle.
+const MAX_DEPTH: usize = 8;
+
+/// The doc comment of a declaration in a file, as it is written.
+pub fn declaration_doc(
+    root_node: &Node,
+    file_contents: &str,
+    declaration: &Declaration,
+) -> Option<String> {
+    let definition = definitions(root_node, file_contents)
+        .into_iter()
+        .find(|definition| {
+            // the members that enums have without declaring them are where the enum is
+            definition.name.is_some_and(|name| {
+                to_range(&name.range()) == declaration.range
+                    && file_contents[name.byte_range()]
+                        .eq_ignore_ascii_case(declaration.short_name())
+            })
+        })?;
+    // constants and properties are documented together with the others they're declared with
+    let documented = match definition.node.kind() {
+        "const_element" | "property_element" => definition.node.parent()?,
+        _ => definition.node,
+    };
+    let comment = *doc_comments(root_node, file_contents).get(&documented.id())?;
+    Some(file_contents[comment.byte_range()].to_string())
+}
+
+/// The text of a doc comment, without its markers and the `*` that its lines start with.
+pub fn doc_text(comment: &str) -> String {
+    let lines: Vec<&str> = comment
+        .trim_start_matches("/**")
+        .trim_end_matches("*/")
+        .lines()
+        .map(|line| {
+            let line = line.trim();
+            let line = line.strip_prefix('*').unwrap_or(line);
+            line.strip_prefix(' ').unwrap_or(line).trim_end()
+        })
+        .collect();
+    lines.join("\n").trim().to_string()
+}
+
+/// The description of a doc comment's text, and each of its tags with the lines that go on with it.
+fn split_tags(text: &str) -> (String, Vec<String>) {
+    let mut description = vec![];
+    let mut tags: Vec<String> = vec![];
+    for line in text.lines() {
+        if line.starts_with('@') {
+            tags.push(line.to_string());
+        } else if let Some(tag) = tags.last_mut() {
+            tag.push('\n');
+            tag.push_str(line);
+        } else {
+            description.push(line);
+        }
+    }
+    (description.join("\n").trim().to_string(), tags)
+}
+
+/// What a tag is about: its name, and the variable it's for, like `@param $name`.
+fn tag_key(tag: &str) -> (String, Option<&str>) {
+    let mut words = tag.split_whitespace();
+    let name = words.next().unwrap_or_default().to_ascii_lowercase();
+    let variable = words.take(2).find(|word| word.starts_with('$'));
+    (name, variable)
+}
+
+/// Replace every `{@inheritDoc}` in a text, in any case.
+fn replace_inline(text: &str, with: &str) -> String {
+    const INLINE: &str = "{@inheritdoc}";
+    let lower = text.to_ascii_lowercase();
+    let mut replaced = String::new();
+    let mut from = 0;
+    while let Some(i) = lower[from..].find(INLINE) {
+        replaced.push_str(&text[from..from + i]);
+        replaced.push_str(with);
+        from += i + INLINE.len();
+    }
+    replaced.push_str(&text[from..]);
+    replaced
+}
+
+/**
+ * The text of a doc comment with what it inherits from its parent's filled in. `{@inheritDoc}`
+ * is replaced with the parent's description, which is also used if there's no description at
+ * all, and the parent's tags are added for what the comment has no tags of its own about.
+ */
+pub fn inherit_doc(text: &str, parent: &str) -> String {
+    let (parent_description, parent_tags) = split_tags(parent);
+    let (description, tags) = split_tags(&replace_inline(text, &parent_description));
+    let mut tags: Vec<String> = tags
+        .into_iter()
+        .filter(|tag| tag_key(tag).0 != "@inheritdoc")
+        .collect();
+    let inherited: Vec<String> = parent_tags
+        .into_iter()
+        .filter(|tag| tags.iter().all(|own| tag_key(own) != tag_key(tag)))
+        .collect();
+    tags.extend(inherited);
+
+    let description = match description.is_empty() {
+        true => parent_description,
+        false => description,
+    };
+    [description, tags.join("\n")]
+        .into_iter()
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+fn resolve(
+    declaration: &Declaration,
+    resolver: &Resolver,
+    doc_comment: &dyn Fn(&Declaration) -> Option<String>,
+    depth: usize,
+) -> Option<String> {
+    let parent = || {
+        resolver
+            .overridden(declaration)
+            .filter(|_| depth < MAX_DEPTH)
+            .and_then(|parent| resolve(parent, resolver, doc_comment, depth + 1))
+    };
+    match doc_comment(declaration) {
+        Some(comment) if !inherits_doc(&comment) => Some(doc_text(&comment)),
+        Some(comment) => Some(match parent() {
+            Some(parent) => inherit_doc(&doc_text(&comment), &parent),
+            None => doc_text(&comment),
+        }),
+        // members that override without documenting go by what they override
+        None if declaration.class_name().is_some() => parent(),
+        None => None,
+    }
+}
+
+/**
+ * The documentation of a declaration, going up to what it overrides for what it inherits.
+ * `doc_comment` finds the doc comment of a declaration, wherever it is.
+ */
+pub fn documentation(
+    declaration: &Declaration,
+    resolver: &Resolver,
+    doc_comment: &dyn Fn(&Declaration) -> Option<String>,
+) -> Option<String> {
+    resolve(declaration, resolver, doc_comment, 0).filter(|text| !text.is_empty())
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::Url;
+
+    use super::{declaration_doc, documentation, inherit_doc};
+    use crate::file::php_parser;
+    use crate::index::WorkspaceIndex;
+    use crate::resolve::Resolver;
+    use crate::symbols::file_symbols;
+
+    #[test]
+    fn test_inherit_doc() {
+        let parent = "Sends a message.\n\n@param string $to Who to send it to.\n@return bool";
+        assert_eq!(
+            "Sends a message.\n\n@param string $to Who to send it to.\n@return bool",
+            inherit_doc("@inheritDoc", parent)
+        );
+        assert_eq!(
+            "Queues it first. Sends a message.\n\n@return bool Whether it was queued.\n\
+             @param string $to Who to send it to.",
+            inherit_doc(
+                "Queues it first. {@inheritdoc}\n\n@return bool Whether it was queued.",
+                parent
+            )
+        );
+    }
+
+    #[test]
+    fn test_documentation() {
+        let source = "<?php
+interface Mailer {
+    /**
+     * Sends a message.
+     *
+     * @param string $to
+     */
+    public function send($to);
+}
+class SmtpMailer implements Mailer {
+    /** {@inheritDoc} Over SMTP. */
+    public function send($to) {}
+}
+class QueueMailer extends SmtpMailer {
+    public function send($to) {}
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(&Url::parse("file:///mail.php").unwrap(), symbols.clone());
+        let resolver = Resolver::new(source, &symbols, &index);
+        let doc_comment = |declaration: &_| declaration_doc(&tree.root_node(), source, declaration);
+
+        let queued = index.find_declaration("QueueMailer::send").unwrap();
+        assert_eq!(
+            Some("Sends a message. Over SMTP.\n\n@param string $to".to_string()),
+            documentation(queued, &resolver, &doc_comment)
+        );
+        let mailer = index.find_declaration("Mailer").unwrap();
+        assert_eq!(None, documentation(mailer, &resolver, &doc_comment));
+    }
+}
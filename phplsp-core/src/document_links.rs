@@ -0,0 +1,391 @@
+use lsp_types::*;
+use tree_sitter::Node;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::file::{is_within, to_point, to_position};
+use crate::index::WorkspaceIndex;
+use crate::php_namespace::PhpNamespace;
+use crate::symbols::{DeclarationKind, FileSymbols, ReferenceKind};
+use crate::tags::{comment_position, doc_urls};
+
+/// Extensions of the files that strings are taken to be paths of templates to.
+const TEMPLATE_EXTENSIONS: &[&str] = &[".php", ".phtml", ".html", ".twig", ".tpl"];
+
+/**
+ * Where the file of a class should be according to the PSR-4 directories of composer, if there
+ * is a file there. Longer, more specific prefixes are tried first.
+ */
+pub fn autoload_path(
+    class: &str,
+    namespace_dirs: &HashMap<PhpNamespace, Vec<PathBuf>>,
+) -> Option<PathBuf> {
+    let Ok(class) = PhpNamespace::from_str(class);
+    let mut candidates: Vec<(usize, PathBuf)> = namespace_dirs
+        .iter()
+        .filter_map(|(prefix, dirs)| {
+            let relative = class.relative_path(prefix)?;
+            Some(dirs.iter().map(move |dir| {
+                let path = dir.join(&relative).with_extension("php");
+                (relative.components().count(), path)
+            }))
+        })
+        .flatten()
+        .collect();
+    candidates.sort();
+
+    candidates
+        .into_iter()
+        .map(|(_, path)| path)
+        .find(|path| path.is_file())
+}
+
+/**
+ * The class that the PSR-4 directories of composer expect a file to declare, from where it is.
+ * The most specific directory that the file is in wins.
+ */
+pub fn autoload_class(
+    path: &Path,
+    namespace_dirs: &HashMap<PhpNamespace, Vec<PathBuf>>,
+) -> Option<String> {
+    if path.extension()? != "php" {
+        return None;
+    }
+
+    let (_, prefix, relative) = namespace_dirs
+        .iter()
+        .flat_map(|(prefix, dirs)| dirs.iter().map(move |dir| (prefix, dir)))
+        .filter_map(|(prefix, dir)| {
+            let relative = path.strip_prefix(dir).ok()?.with_extension("");
+            Some((dir.components().count(), prefix, relative))
+        })
+        .max_by_key(|(depth, _, _)| *depth)?;
+    let segments: Option<Vec<&str>> = relative
+        .components()
+        .map(|component| component.as_os_str().to_str())
+        .collect();
+    let prefix = prefix.to_string();
+    let class = std::iter::once(prefix.trim_matches('\\'))
+        .chain(segments?)
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<&str>>()
+        .join("\\");
+    Some(class)
+}
+
+/// Where a class is declared: at its declaration if it's indexed, or else at its autoloaded file.
+fn class_target(
+    class: &str,
+    index: &WorkspaceIndex,
+    namespace_dirs: &HashMap<PhpNamespace, Vec<PathBuf>>,
+) -> Option<Url> {
+    let declared = index.locate(class).filter(|(_, declaration)| {
+        matches!(
+            declaration.kind,
+            DeclarationKind::Class
+                | DeclarationKind::Interface
+                | DeclarationKind::Trait
+                | DeclarationKind::Enum
+        )
+    });
+    if let Some((uri, declaration)) = declared {
+        let mut target = uri.clone();
+        target.set_fragment(Some(&format!("L{}", declaration.range.start.line + 1)));
+        return Some(target);
+    }
+
+    Url::from_file_path(autoload_path(class, namespace_dirs)?).ok()
+}
+
+/// The contents of a string literal without any interpolation as written, and where they are.
+pub(crate) fn string_contents<'a>(node: &Node, file_contents: &'a str) -> Option<(Range, &'a str)> {
+    let mut cursor = node.walk();
+    let parts: Vec<Node> = node.named_children(&mut cursor).collect();
+    let is_literal = parts
+        .iter()
+        .all(|part| matches!(part.kind(), "string_content" | "escape_sequence"));
+    let (first, last) = (parts.first()?, parts.last()?);
+    if !is_literal {
+        return None;
+    }
+
+    Some((
+        Range {
+            start: to_position(&first.start_position()),
+            end: to_position(&last.end_position()),
+        },
+        &file_contents[first.start_byte()..last.end_byte()],
+    ))
+}
+
+/// Whether a string names a class like `App\Models\User`, as opposed to being any word at all.
+fn is_class_string(text: &str) -> bool {
+    let name = text.trim_start_matches('\\');
+    name.contains('\\')
+        && name.split('\\').all(|part| {
+            part.chars()
+                .next()
+                .is_some_and(|c| c.is_alphabetic() || c == '_')
+                && part.chars().all(|c| c.is_alphanumeric() || c == '_')
+        })
+}
+
+/**
+ * Where the files that strings name are looked for: the PSR-4 directories of composer and the
+ * workspace folders, and, in restricted mode, the only directories that may be read from.
+ */
+pub struct LinkDirs<'a> {
+    pub namespace_dirs: &'a HashMap<PhpNamespace, Vec<PathBuf>>,
+    pub roots: &'a [PathBuf],
+    pub readable: Option<&'a [PathBuf]>,
+}
+
+/**
+ * The file a string names, relative to the directory of this file or to a workspace folder. Only
+ * the readable directories are looked in, if there are any.
+ */
+fn template_target(text: &str, file: Option<&Path>, dirs: &LinkDirs) -> Option<Url> {
+    if text.contains("://")
+        || text.chars().any(char::is_whitespace)
+        || !TEMPLATE_EXTENSIONS.iter().any(|ext| text.ends_with(ext))
+    {
+        return None;
+    }
+
+    let relative = text.trim_start_matches('/');
+    let candidates = file
+        .and_then(Path::parent)
+        .into_iter()
+        .chain(dirs.roots.iter().map(PathBuf::as_path));
+    let path = candidates
+        .map(|dir| dir.join(relative))
+        .filter(|path| {
+            dirs.readable
+                .is_none_or(|readable| is_within(path, readable))
+        })
+        .find(|path| path.is_file())?;
+    Url::from_file_path(path).ok()
+}
+
+struct Linker<'a> {
+    contents: &'a str,
+    file: Option<PathBuf>,
+    index: &'a WorkspaceIndex,
+    dirs: LinkDirs<'a>,
+    links: Vec<DocumentLink>,
+}
+
+impl Linker<'_> {
+    fn link(&mut self, range: Range, target: Url) {
+        self.links.push(DocumentLink {
+            range,
+            target: Some(target),
+            tooltip: None,
+            data: None,
+        });
+    }
+
+    fn walk(&mut self, node: &Node) {
+        match node.kind() {
+            "comment" => {
+                let text = &self.contents[node.byte_range()];
+                if !text.starts_with("/**") {
+                    return;
+                }
+                for (range, url) in doc_urls(text) {
+                    if let Ok(target) = Url::parse(url) {
+                        let range = Range {
+                            start: comment_position(node, text, range.start),
+                            end: comment_position(node, text, range.end),
+                        };
+                        self.link(range, target);
+                    }
+                }
+            }
+            "string" | "encapsed_string" => {
+                // heredocs and strings with variables in them are never names
+                let Some((range, text)) = string_contents(node, self.contents) else {
+                    return;
+                };
+                // single quoted strings may escape their backslashes
+                let unescaped = text.replace("\\\\", "\\");
+                let target = match is_class_string(&unescaped) {
+                    true => class_target(&unescaped, self.index, self.dirs.namespace_dirs),
+                    false => template_target(text, self.file.as_deref(), &self.dirs),
+                };
+                if let Some(target) = target {
+                    self.link(range, target);
+                }
+            }
+            _ => {
+                let mut cursor = node.walk();
+                for child in node.children(&mut cursor) {
+                    self.walk(&child);
+                }
+            }
+        }
+    }
+}
+
+/**
+ * Links in a file: classes named in doc comment tags like `@see`, URLs in `@see` and `@link`,
+ * strings that name classes, and strings that are paths of templates.
+ *
+ * Classes link to where they're declared if they're indexed, or else to where composer would
+ * autoload them from. Template paths are looked for next to the file and in every workspace
+ * folder, but only in the readable directories if there are any, as in restricted mode.
+ */
+pub fn document_links(
+    root_node: &Node,
+    file_contents: &str,
+    uri: &Url,
+    symbols: &FileSymbols,
+    index: &WorkspaceIndex,
+    dirs: LinkDirs,
+) -> Vec<DocumentLink> {
+    let mut linker = Linker {
+        contents: file_contents,
+        file: uri.to_file_path().ok(),
+        index,
+        dirs,
+        links: vec![],
+    };
+    linker.walk(root_node);
+
+    // names in doc comments were already found along with the file's other references
+    for reference in &symbols.references {
+        if reference.kind != ReferenceKind::Class {
+            continue;
+        }
+        let start = to_point(&reference.range.start);
+        let in_comment = root_node
+            .descendant_for_point_range(start, start)
+            .is_some_and(|node| node.kind() == "comment");
+        if in_comment {
+            if let Some(target) = class_target(&reference.name, index, linker.dirs.namespace_dirs) {
+                linker.link(reference.range, target);
+            }
+        }
+    }
+
+    linker
+        .links
+        .sort_by_key(|link| (link.range.start.line, link.range.start.character));
+    linker.links
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::*;
+
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::Path;
+    use std::str::FromStr;
+
+    use super::{autoload_class, document_links, LinkDirs};
+    use crate::file::php_parser;
+    use crate::index::WorkspaceIndex;
+    use crate::php_namespace::PhpNamespace;
+    use crate::symbols::file_symbols;
+
+    #[test]
+    fn test_document_links() {
+        let root = std::env::temp_dir().join("phplsp-test-document-links");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("src/Models")).unwrap();
+        fs::create_dir_all(root.join("templates")).unwrap();
+        fs::write(root.join("src/Models/Post.php"), "<?php").unwrap();
+        fs::write(root.join("templates/list.twig"), "").unwrap();
+
+        let source = "<?php
+namespace App;
+use App\\Models\\Post;
+/**
+ * @see Post
+ * @link https://example.com/docs
+ */
+class User {}
+$a = 'App\\\\User';
+$b = \"App\\\\Models\\\\Post\";
+$c = 'templates/list.twig';
+$d = 'templates/missing.twig';
+$e = 'plain';
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let uri = Url::from_file_path(root.join("src/User.php")).unwrap();
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(&uri, symbols.clone());
+        let namespace_dirs = HashMap::from([(
+            PhpNamespace::from_str("App").unwrap(),
+            vec![root.join("src")],
+        )]);
+
+        let links: Vec<(u32, String)> = document_links(
+            &tree.root_node(),
+            source,
+            &uri,
+            &symbols,
+            &index,
+            LinkDirs {
+                namespace_dirs: &namespace_dirs,
+                roots: std::slice::from_ref(&root),
+                readable: None,
+            },
+        )
+        .into_iter()
+        .map(|link| {
+            let target = link.target.unwrap();
+            let target = match target.scheme() {
+                "file" => target
+                    .as_str()
+                    .trim_start_matches(Url::from_directory_path(&root).unwrap().as_str())
+                    .to_string(),
+                _ => target.to_string(),
+            };
+            (link.range.start.line, target)
+        })
+        .collect();
+        assert_eq!(
+            vec![
+                (4, "src/Models/Post.php".to_string()),
+                (5, "https://example.com/docs".to_string()),
+                (8, "src/User.php#L8".to_string()),
+                (9, "src/Models/Post.php".to_string()),
+                (10, "templates/list.twig".to_string()),
+            ],
+            links
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_autoload_class() {
+        let namespace_dirs = HashMap::from([
+            (
+                PhpNamespace::from_str("App\\").unwrap(),
+                vec![Path::new("/project/src").to_path_buf()],
+            ),
+            (
+                PhpNamespace::from_str("App\\Tests\\").unwrap(),
+                vec![Path::new("/project/src/tests").to_path_buf()],
+            ),
+        ]);
+        let class = |path: &str| autoload_class(Path::new(path), &namespace_dirs);
+
+        assert_eq!(
+            Some("App\\Models\\User".to_string()),
+            class("/project/src/Models/User.php")
+        );
+        assert_eq!(
+            Some("App\\Tests\\UserTest".to_string()),
+            class("/project/src/tests/UserTest.php")
+        );
+        assert_eq!(None, class("/project/lib/User.php"));
+        assert_eq!(None, class("/project/src/Models/README.md"));
+    }
+}
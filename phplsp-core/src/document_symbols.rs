@@ -0,0 +1,537 @@
+use lsp_types::*;
+use tree_sitter::Node;
+
+use std::collections::HashMap;
+
+use crate::file::{range_plaintext, to_position, to_range};
+use crate::tags::{definitions, doc_comments, doc_summary, use_blocks, Definition, DefinitionKind};
+
+#[allow(deprecated)]
+fn document_symbol(
+    name: String,
+    detail: Option<String>,
+    kind: SymbolKind,
+    range: Range,
+    name_node: &Node,
+    children: Option<Vec<DocumentSymbol>>,
+) -> DocumentSymbol {
+    DocumentSymbol {
+        name,
+        detail,
+        kind,
+        tags: None,
+        deprecated: None,
+        range,
+        selection_range: to_range(&name_node.range()),
+        children,
+    }
+}
+
+/// `namespace Foo;` owns everything up until the next namespace, so this is its last statement.
+fn namespace_end<'tree>(namespace_node: &Node<'tree>) -> Node<'tree> {
+    let mut last = *namespace_node;
+    while let Some(next) = last.next_sibling() {
+        if next.kind() == "namespace_definition" {
+            break;
+        }
+        last = next;
+    }
+
+    last
+}
+
+/**
+ * Turn a definition into a symbol.
+ *
+ * Also returns where the definition ends if other symbols can be nested inside of it.
+ */
+fn definition_symbol(
+    definition: &Definition,
+    file_contents: &str,
+    docs: &HashMap<usize, Node>,
+) -> Option<(DocumentSymbol, Option<usize>)> {
+    let node = &definition.node;
+    let text = |node: &Node| range_plaintext(file_contents, node.range());
+    let summary = || {
+        docs.get(&node.id())
+            .and_then(|comment| doc_summary(&text(comment)))
+    };
+
+    if definition.kind == DefinitionKind::Namespace {
+        // `namespace { ... }` is the global namespace
+        let (name, name_node) = match definition.name {
+            Some(name_node) => (text(&name_node), name_node),
+            None => ("\\".to_string(), node.child(0).unwrap_or(*node)),
+        };
+        let end = match node.child_by_field_name("body") {
+            Some(_) => *node,
+            None => namespace_end(node),
+        };
+        let range = Range {
+            start: to_position(&node.start_position()),
+            end: to_position(&end.end_position()),
+        };
+        let symbol = document_symbol(
+            name,
+            None,
+            SymbolKind::NAMESPACE,
+            range,
+            &name_node,
+            Some(vec![]),
+        );
+        return Some((symbol, Some(end.end_byte())));
+    }
+
+    let name_node = definition.name?;
+    let name = text(&name_node);
+    let container = |kind: SymbolKind| {
+        let symbol = document_symbol(
+            name.clone(),
+            summary(),
+            kind,
+            to_range(&node.range()),
+            &name_node,
+            Some(vec![]),
+        );
+        Some((symbol, Some(node.end_byte())))
+    };
+    let leaf = |kind: SymbolKind, detail: Option<String>, declaration: &Node| {
+        let symbol = document_symbol(
+            name.clone(),
+            detail,
+            kind,
+            to_range(&declaration.range()),
+            &name_node,
+            None,
+        );
+        Some((symbol, None))
+    };
+
+    match definition.kind {
+        DefinitionKind::Class | DefinitionKind::Trait => container(SymbolKind::CLASS),
+        DefinitionKind::Interface => container(SymbolKind::INTERFACE),
+        DefinitionKind::Enum => container(SymbolKind::ENUM),
+        DefinitionKind::Function => container(SymbolKind::FUNCTION),
+        DefinitionKind::Method if name.eq_ignore_ascii_case("__construct") => {
+            container(SymbolKind::CONSTRUCTOR)
+        }
+        DefinitionKind::Method => container(SymbolKind::METHOD),
+        DefinitionKind::Property => {
+            let declaration = node.parent().unwrap_or(*node);
+            leaf(SymbolKind::PROPERTY, Some(text(&declaration)), &declaration)
+        }
+        DefinitionKind::Constant => {
+            let declaration = node.parent().unwrap_or(*node);
+            leaf(SymbolKind::CONSTANT, Some(text(node)), &declaration)
+        }
+        DefinitionKind::EnumCase => leaf(SymbolKind::ENUM_MEMBER, None, node),
+        DefinitionKind::Parameter => leaf(SymbolKind::VARIABLE, Some(text(node)), node),
+        DefinitionKind::Namespace => None,
+    }
+}
+
+/// A run of `use` statements as one symbol, with what each of them imports in it.
+fn use_block_symbol(first: &Node, last: &Node, file_contents: &str) -> DocumentSymbol {
+    let text = |node: &Node| range_plaintext(file_contents, node.range());
+    let mut imports = vec![];
+    let mut statement = Some(*first);
+    while let Some(current) = statement.filter(|current| current.start_byte() <= last.start_byte())
+    {
+        statement = current.next_named_sibling();
+        if current.kind() != "namespace_use_declaration" {
+            continue;
+        }
+
+        // group uses look like `use Prefix\{A, B as C};`
+        let mut cursor = current.walk();
+        let prefix = current
+            .named_children(&mut cursor)
+            .find(|child| child.kind() == "namespace_name")
+            .map(|prefix| text(&prefix));
+        let clauses_parent = current.child_by_field_name("body").unwrap_or(current);
+        let mut kind = SymbolKind::CLASS;
+        let mut cursor = clauses_parent.walk();
+        for clause in clauses_parent.named_children(&mut cursor) {
+            if clause.kind() != "namespace_use_clause" {
+                continue;
+            }
+            // `use function` marks its first clause, but it's about all of them
+            match clause.child_by_field_name("type").map(|ty| ty.kind()) {
+                Some("function") => kind = SymbolKind::FUNCTION,
+                Some("const") => kind = SymbolKind::CONSTANT,
+                _ => {}
+            }
+            let alias = clause.child_by_field_name("alias");
+            let Some(name_node) = clause.named_child(0).filter(|name| Some(*name) != alias) else {
+                continue;
+            };
+            let name = match &prefix {
+                Some(prefix) => format!("{}\\{}", prefix, text(&name_node)),
+                None => text(&name_node),
+            };
+            let detail = alias.map(|alias| format!("as {}", text(&alias)));
+            imports.push(document_symbol(
+                name,
+                detail,
+                kind,
+                to_range(&clause.range()),
+                &name_node,
+                None,
+            ));
+        }
+    }
+
+    let range = Range {
+        start: to_position(&first.start_position()),
+        end: to_position(&last.end_position()),
+    };
+    let keyword = first.child(0).unwrap_or(*first);
+    let detail = match imports.len() {
+        1 => "1 import".to_string(),
+        count => format!("{} imports", count),
+    };
+    document_symbol(
+        "use".to_string(),
+        Some(detail),
+        SymbolKind::MODULE,
+        range,
+        &keyword,
+        Some(imports),
+    )
+}
+
+fn add_symbol(
+    containers: &mut [(usize, DocumentSymbol)],
+    symbols: &mut Vec<DocumentSymbol>,
+    symbol: DocumentSymbol,
+) {
+    match containers.last_mut() {
+        Some((_, parent)) => parent.children.get_or_insert_with(Vec::new).push(symbol),
+        None => symbols.push(symbol),
+    }
+}
+
+/// Finish the containers that end before `byte`.
+fn close_containers(
+    containers: &mut Vec<(usize, DocumentSymbol)>,
+    symbols: &mut Vec<DocumentSymbol>,
+    byte: usize,
+) {
+    while containers.last().is_some_and(|(end, _)| *end <= byte) {
+        if let Some((_, symbol)) = containers.pop() {
+            add_symbol(containers, symbols, symbol);
+        }
+    }
+}
+
+/**
+ * Symbols for everything declared in a file, nested the same way as in the source.
+ *
+ * Classes, functions and methods have a summary of their doc comment as detail. `use` statements
+ * that follow each other are one symbol, with what they import in it.
+ */
+pub fn document_symbols(root_node: &Node, file_contents: &str) -> Vec<DocumentSymbol> {
+    let docs = doc_comments(root_node, file_contents);
+    let mut symbols = vec![];
+    // the symbols we are inside of, with where they end
+    let mut containers: Vec<(usize, DocumentSymbol)> = vec![];
+
+    let mut found: Vec<(usize, DocumentSymbol, Option<usize>)> =
+        definitions(root_node, file_contents)
+            .iter()
+            .filter_map(|definition| {
+                let (symbol, end) = definition_symbol(definition, file_contents, &docs)?;
+                Some((definition.node.start_byte(), symbol, end))
+            })
+            .collect();
+    found.extend(use_blocks(root_node).iter().map(|(first, last)| {
+        let symbol = use_block_symbol(first, last, file_contents);
+        (first.start_byte(), symbol, None)
+    }));
+    found.sort_by_key(|(start, _, _)| *start);
+
+    for (start, symbol, end) in found {
+        close_containers(&mut containers, &mut symbols, start);
+        match end {
+            Some(end) => containers.push((end, symbol)),
+            None => add_symbol(&mut containers, &mut symbols, symbol),
+        }
+    }
+    close_containers(&mut containers, &mut symbols, usize::MAX);
+
+    symbols
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::*;
+
+    use super::document_symbols;
+    use crate::file::php_parser;
+
+    const SOURCE: &str = "<?php
+            class Whatever {
+                public int $x = 12;
+                public function foo(int $bar): void
+                {
+                    $this->x = $bar;
+                }
+
+                public function fee(string $sound, ?array $down): int|false
+                {
+                    $this->x = 12;
+                    if (!empty($down)) {
+                        $this->x = ((int) $sound) + ((int) $down[0]);
+                    }
+                }
+            }
+
+            final class Another {
+                private int $y = 3;
+                public function __construct(): void
+                {
+                }
+            }";
+
+    #[test]
+    fn test_get_symbols() {
+        let tree = php_parser().parse(SOURCE, None).unwrap();
+        let root_node = tree.root_node();
+        let actual_symbols = document_symbols(&root_node, SOURCE);
+        assert_eq!(2, actual_symbols.len());
+        assert_eq!("Whatever", &actual_symbols[0].name);
+        assert_eq!("Another", &actual_symbols[1].name);
+        assert_eq!(3, actual_symbols[0].children.as_ref().unwrap().len());
+        assert_eq!("$x", &actual_symbols[0].children.as_ref().unwrap()[0].name);
+        assert_eq!("foo", &actual_symbols[0].children.as_ref().unwrap()[1].name);
+        assert_eq!("fee", &actual_symbols[0].children.as_ref().unwrap()[2].name);
+        assert_eq!(
+            1,
+            actual_symbols[0].children.as_ref().unwrap()[1]
+                .children
+                .as_ref()
+                .unwrap()
+                .len()
+        );
+        assert_eq!(
+            "$bar",
+            &actual_symbols[0].children.as_ref().unwrap()[1]
+                .children
+                .as_ref()
+                .unwrap()[0]
+                .name
+        );
+        assert_eq!(
+            2,
+            actual_symbols[0].children.as_ref().unwrap()[2]
+                .children
+                .as_ref()
+                .unwrap()
+                .len()
+        );
+        assert_eq!(
+            "$sound",
+            &actual_symbols[0].children.as_ref().unwrap()[2]
+                .children
+                .as_ref()
+                .unwrap()[0]
+                .name
+        );
+        assert_eq!(
+            "$down",
+            &actual_symbols[0].children.as_ref().unwrap()[2]
+                .children
+                .as_ref()
+                .unwrap()[1]
+                .name
+        );
+        assert_eq!(
+            "?array $down",
+            actual_symbols[0].children.as_ref().unwrap()[2]
+                .children
+                .as_ref()
+                .unwrap()[1]
+                .detail
+                .as_ref()
+                .unwrap()
+        );
+        assert_eq!(2, actual_symbols[1].children.as_ref().unwrap().len());
+        assert_eq!(
+            "private int $y = 3;",
+            actual_symbols[1].children.as_ref().unwrap()[0]
+                .detail
+                .as_ref()
+                .unwrap()
+        );
+        assert_eq!(
+            SymbolKind::CONSTRUCTOR,
+            actual_symbols[1].children.as_ref().unwrap()[1].kind
+        );
+    }
+
+    #[test]
+    fn test_get_nested_symbols() {
+        let source = "<?php
+namespace App;
+
+const VERSION = '1.0';
+
+interface Shape {
+    const SIDES = 0;
+    public function area(): float;
+}
+
+trait Named {
+    private string $name, $alias;
+}
+
+enum Suit: string {
+    case Hearts = 'H';
+    case Spades = 'S';
+}
+
+if (!function_exists('App\\helper')) {
+    function helper(int ...$values): void {}
+}
+
+namespace Other;
+
+class Point {
+    public function __construct(private int $x) {}
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = document_symbols(&tree.root_node(), source);
+        let summary = |symbols: &Vec<DocumentSymbol>| -> Vec<(String, SymbolKind)> {
+            symbols
+                .iter()
+                .map(|symbol| (symbol.name.clone(), symbol.kind))
+                .collect()
+        };
+
+        assert_eq!(
+            vec![
+                ("App".to_string(), SymbolKind::NAMESPACE),
+                ("Other".to_string(), SymbolKind::NAMESPACE)
+            ],
+            summary(&symbols)
+        );
+
+        let app = symbols[0].children.as_ref().unwrap();
+        assert_eq!(
+            vec![
+                ("VERSION".to_string(), SymbolKind::CONSTANT),
+                ("Shape".to_string(), SymbolKind::INTERFACE),
+                ("Named".to_string(), SymbolKind::CLASS),
+                ("Suit".to_string(), SymbolKind::ENUM),
+                ("helper".to_string(), SymbolKind::FUNCTION),
+            ],
+            summary(app)
+        );
+        assert_eq!(
+            vec![
+                ("SIDES".to_string(), SymbolKind::CONSTANT),
+                ("area".to_string(), SymbolKind::METHOD),
+            ],
+            summary(app[1].children.as_ref().unwrap())
+        );
+        assert_eq!(
+            vec![
+                ("$name".to_string(), SymbolKind::PROPERTY),
+                ("$alias".to_string(), SymbolKind::PROPERTY),
+            ],
+            summary(app[2].children.as_ref().unwrap())
+        );
+        assert_eq!(
+            vec![
+                ("Hearts".to_string(), SymbolKind::ENUM_MEMBER),
+                ("Spades".to_string(), SymbolKind::ENUM_MEMBER),
+            ],
+            summary(app[3].children.as_ref().unwrap())
+        );
+        assert_eq!(
+            vec![("$values".to_string(), SymbolKind::VARIABLE)],
+            summary(app[4].children.as_ref().unwrap())
+        );
+
+        // the namespace runs until the end of its last statement
+        assert_eq!(1, symbols[0].range.start.line);
+        assert_eq!(21, symbols[0].range.end.line);
+
+        // selection ranges are on the name, ranges cover the whole declaration
+        let shape = &app[1];
+        assert_eq!(5, shape.selection_range.start.line);
+        assert_eq!(10, shape.selection_range.start.character);
+        assert_eq!(5, shape.range.start.line);
+        assert_eq!(0, shape.range.start.character);
+        assert_eq!(8, shape.range.end.line);
+
+        let point = &symbols[1].children.as_ref().unwrap()[0];
+        let constructor = &point.children.as_ref().unwrap()[0];
+        assert_eq!(SymbolKind::CONSTRUCTOR, constructor.kind);
+        assert_eq!("$x", constructor.children.as_ref().unwrap()[0].name);
+    }
+
+    #[test]
+    fn test_nested_in_bodies() {
+        let source = "<?php
+/**
+ * Makes things.
+ */
+function factory() {
+    class Made {
+        public function run() {}
+    }
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = document_symbols(&tree.root_node(), source);
+
+        assert_eq!(1, symbols.len());
+        assert_eq!(Some("Makes things.".to_string()), symbols[0].detail);
+
+        let made = &symbols[0].children.as_ref().unwrap()[0];
+        assert_eq!("Made", made.name);
+        assert_eq!("run", made.children.as_ref().unwrap()[0].name);
+    }
+
+    #[test]
+    fn test_use_blocks() {
+        let source = "<?php
+namespace App;
+use Foo\\Bar;
+use function Foo\\baz, Foo\\qux as q;
+use Foo\\{A, B as C};
+class Z {}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = document_symbols(&tree.root_node(), source);
+        let namespace = &symbols[0].children.as_ref().unwrap();
+        assert_eq!(2, namespace.len());
+
+        let block = &namespace[0];
+        assert_eq!(
+            ("use", SymbolKind::MODULE),
+            (block.name.as_str(), block.kind)
+        );
+        assert_eq!(Some("5 imports".to_string()), block.detail);
+        assert_eq!((2, 4), (block.range.start.line, block.range.end.line));
+        let imports: Vec<(&str, SymbolKind, Option<&str>)> = block
+            .children
+            .iter()
+            .flatten()
+            .map(|import| (import.name.as_str(), import.kind, import.detail.as_deref()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("Foo\\Bar", SymbolKind::CLASS, None),
+                ("Foo\\baz", SymbolKind::FUNCTION, None),
+                ("Foo\\qux", SymbolKind::FUNCTION, Some("as q")),
+                ("Foo\\A", SymbolKind::CLASS, None),
+                ("Foo\\B", SymbolKind::CLASS, Some("as C")),
+            ],
+            imports
+        );
+        assert_eq!("Z", namespace[1].name);
+    }
+}
@@ -0,0 +1,206 @@
+//! What's declared more than once, in a file or across the workspace.
+
+use lsp_types::*;
+
+use crate::index::WorkspaceIndex;
+use crate::symbols::{Declaration, DeclarationKind, FileSymbols};
+
+/// Kinds of declarations whose names can't be shared, since they're looked up together.
+fn name_space(kind: DeclarationKind) -> u8 {
+    match kind {
+        DeclarationKind::Class
+        | DeclarationKind::Interface
+        | DeclarationKind::Trait
+        | DeclarationKind::Enum => 0,
+        DeclarationKind::Function => 1,
+        DeclarationKind::Method => 2,
+        DeclarationKind::Property => 3,
+        DeclarationKind::Constant | DeclarationKind::EnumCase => 4,
+    }
+}
+
+/// Whether two declarations have the same name, which ignores case except for constants and properties.
+fn is_same(a: &Declaration, b: &Declaration) -> bool {
+    if name_space(a.kind) != name_space(b.kind) || !a.name.eq_ignore_ascii_case(&b.name) {
+        return false;
+    }
+    match a.kind {
+        DeclarationKind::Property | DeclarationKind::Constant | DeclarationKind::EnumCase => {
+            a.short_name() == b.short_name()
+        }
+        _ => true,
+    }
+}
+
+fn duplicate(declaration: &Declaration, others: Vec<Location>) -> Diagnostic {
+    Diagnostic {
+        range: declaration.range,
+        severity: Some(DiagnosticSeverity::ERROR),
+        code: Some(NumberOrString::String("duplicate-declaration".to_string())),
+        source: Some("phplsp".to_string()),
+        message: format!("`{}` is declared more than once", declaration.name),
+        related_information: Some(
+            others
+                .into_iter()
+                .map(|location| DiagnosticRelatedInformation {
+                    location,
+                    message: format!("`{}` is also declared here", declaration.short_name()),
+                })
+                .collect(),
+        ),
+        ..Diagnostic::default()
+    }
+}
+
+/**
+ * Report classes, functions, constants and members declared again in a file, and classes,
+ * functions and constants that another file of the workspace declares too, pointing at where else
+ * they are. Declarations in an `if` or a function are left out, since that's how polyfills are
+ * declared only where they don't exist yet, and so are vendored files.
+ */
+pub fn duplicate_declarations(
+    uri: &Url,
+    symbols: &FileSymbols,
+    index: &WorkspaceIndex,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let declarations: Vec<&Declaration> = symbols
+        .declarations
+        .iter()
+        .filter(|declaration| !declaration.conditional)
+        .collect();
+    for (i, declaration) in declarations.iter().enumerate() {
+        if let Some(first) = declarations[..i]
+            .iter()
+            .find(|earlier| is_same(earlier, declaration))
+        {
+            let location = Location {
+                uri: uri.clone(),
+                range: first.range,
+            };
+            diagnostics.push(duplicate(declaration, vec![location]));
+            continue;
+        }
+
+        if declaration.class_name().is_some() || index.is_vendor(uri) {
+            continue;
+        }
+        let elsewhere: Vec<Location> = index
+            .declared_elsewhere(&declaration.name, uri)
+            .into_iter()
+            .filter(|(other_uri, other)| {
+                !other.conditional && !index.is_vendor(other_uri) && is_same(other, declaration)
+            })
+            .map(|(other_uri, other)| Location {
+                uri: other_uri.clone(),
+                range: other.range,
+            })
+            .collect();
+        if !elsewhere.is_empty() {
+            diagnostics.push(duplicate(declaration, elsewhere));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::Url;
+
+    use super::duplicate_declarations;
+    use crate::file::php_parser;
+    use crate::index::WorkspaceIndex;
+    use crate::symbols::file_symbols;
+
+    #[test]
+    fn test_duplicate_declarations() {
+        let files = [
+            (
+                "file:///a.php",
+                "<?php
+namespace App;
+class User {
+    const ROLE = 'user';
+    const role = 'user';
+    public function save() {}
+    public function SAVE() {}
+}
+function helper() {}
+function HELPER() {}
+if (!function_exists('App\\polyfill')) {
+    function polyfill() {}
+}
+",
+            ),
+            (
+                "file:///b.php",
+                "<?php
+namespace App;
+interface user {}
+function polyfill() {}
+",
+            ),
+        ];
+        let mut index = WorkspaceIndex::default();
+        for (uri, source) in files {
+            let tree = php_parser().parse(source, None).unwrap();
+            index.update_symbols(
+                &Url::parse(uri).unwrap(),
+                file_symbols(&tree.root_node(), source),
+            );
+        }
+
+        let found = |uri: &str| {
+            let uri = Url::parse(uri).unwrap();
+            let mut diagnostics = vec![];
+            duplicate_declarations(&uri, index.symbols(&uri).unwrap(), &index, &mut diagnostics);
+            diagnostics
+                .into_iter()
+                .map(|diagnostic| {
+                    let related = diagnostic.related_information.unwrap();
+                    (
+                        diagnostic.range.start.line,
+                        diagnostic.message,
+                        related
+                            .iter()
+                            .map(|related| {
+                                (
+                                    related.location.uri.path().to_string(),
+                                    related.location.range.start.line,
+                                )
+                            })
+                            .collect::<Vec<_>>(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            vec![
+                (
+                    2,
+                    "`App\\User` is declared more than once".to_string(),
+                    vec![("/b.php".to_string(), 2)]
+                ),
+                (
+                    6,
+                    "`App\\User::SAVE` is declared more than once".to_string(),
+                    vec![("/a.php".to_string(), 5)]
+                ),
+                (
+                    9,
+                    "`App\\HELPER` is declared more than once".to_string(),
+                    vec![("/a.php".to_string(), 8)]
+                ),
+            ],
+            found("file:///a.php")
+        );
+        assert_eq!(
+            vec![(
+                2,
+                "`App\\user` is declared more than once".to_string(),
+                vec![("/a.php".to_string(), 2)]
+            )],
+            found("file:///b.php")
+        );
+    }
+}
@@ -0,0 +1,358 @@
+//! Putting together the edits of refactorings and quick fixes, which can come from several places
+//! and touch many files, into one workspace edit that's checked before it's sent.
+
+use lsp_types::*;
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::Arc;
+
+use crate::file::LineIndex;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum EditError {
+    /// The file isn't open or indexed, so the edits can't be checked.
+    UnknownFile(Url),
+    /// An edit is past the end of its line or file, or in the middle of a character.
+    OutOfBounds(Url, Range),
+    /// Two edits change some of the same text.
+    Overlap(Url, Range),
+    /// Files are to be moved, but the client can only take edits.
+    ResourceOperations,
+}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let at = |range: &Range| format!("{}:{}", range.start.line + 1, range.start.character + 1);
+        match self {
+            Self::UnknownFile(uri) => write!(f, "`{}` isn't open or indexed", uri),
+            Self::OutOfBounds(uri, range) => {
+                write!(f, "edit at {} is outside of `{}`", at(range), uri)
+            }
+            Self::Overlap(uri, range) => {
+                write!(f, "edits at {} of `{}` overlap", at(range), uri)
+            }
+            Self::ResourceOperations => write!(f, "the client can't move files"),
+        }
+    }
+}
+
+/**
+ * Edits to any number of files, and files to move after they're edited, that are only turned
+ * into a [`WorkspaceEdit`] once they're all in.
+ *
+ * Edits count columns in bytes, like everything else before it's sent. Building checks them
+ * against the current contents of their files: edits that are out of bounds or overlap are
+ * refused rather than sent, identical ones are only made once, and ones that touch end to end are
+ * merged. Where the client takes versioned edits, each file's edits carry the version they were
+ * checked against, so the client refuses them if the file has changed since.
+ *
+ * Edits of refactorings that the client should preview, like renames, can be annotated: each
+ * file's edits are grouped under its name, and guesses among them, like names in strings, are
+ * grouped apart and need to be confirmed before they're made.
+ */
+#[derive(Debug, Default)]
+pub struct WorkspaceEditBuilder {
+    /// The edits of each file, along with whether they're guesses.
+    edits: BTreeMap<Url, Vec<(TextEdit, bool)>>,
+    renames: Vec<(Url, Url)>,
+    /// What the edits do, if they're to be annotated.
+    description: Option<String>,
+}
+
+impl WorkspaceEditBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn edit(&mut self, uri: &Url, edits: impl IntoIterator<Item = TextEdit>) {
+        self.add(uri, edits, false);
+    }
+
+    /// Edits that might not be wanted, like of a name in a string, which are made like the others.
+    pub fn guess(&mut self, uri: &Url, edits: impl IntoIterator<Item = TextEdit>) {
+        self.add(uri, edits, true);
+    }
+
+    fn add(&mut self, uri: &Url, edits: impl IntoIterator<Item = TextEdit>, guess: bool) {
+        let mut edits = edits.into_iter().map(|edit| (edit, guess)).peekable();
+        if edits.peek().is_some() {
+            self.edits.entry(uri.clone()).or_default().extend(edits);
+        }
+    }
+
+    /// Annotate the edits with what they do, where they're versioned, for the client to preview.
+    pub fn annotate(&mut self, description: String) {
+        self.description = Some(description);
+    }
+
+    /// Move a file or directory once every file is edited, since edits use the old paths.
+    pub fn rename(&mut self, old_uri: Url, new_uri: Url) {
+        self.renames.push((old_uri, new_uri));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.edits.is_empty() && self.renames.is_empty()
+    }
+
+    /**
+     * Check the edits and put them together. `file` looks up the current contents of a file, and
+     * its version if the client has it open; `versioned` is whether the client takes
+     * `documentChanges`, which is needed to move files.
+     */
+    pub fn build(
+        self,
+        file: impl Fn(&Url) -> Option<(Option<i32>, Arc<str>)>,
+        versioned: bool,
+    ) -> Result<WorkspaceEdit, EditError> {
+        if !versioned && !self.renames.is_empty() {
+            return Err(EditError::ResourceOperations);
+        }
+
+        let mut files = vec![];
+        for (uri, edits) in self.edits {
+            let (version, contents) =
+                file(&uri).ok_or_else(|| EditError::UnknownFile(uri.clone()))?;
+            let edits = merge(&uri, &contents, edits)?;
+            files.push((uri, version, edits));
+        }
+
+        if !versioned {
+            let changes = files
+                .into_iter()
+                .map(|(uri, _, edits)| (uri, edits.into_iter().map(|(edit, _)| edit).collect()))
+                .collect();
+            return Ok(WorkspaceEdit {
+                changes: Some(changes),
+                ..WorkspaceEdit::default()
+            });
+        }
+
+        let mut annotations = HashMap::new();
+        let mut operations = vec![];
+        for (uri, version, edits) in files {
+            let edits = edits
+                .into_iter()
+                .map(|(edit, guess)| match &self.description {
+                    Some(description) => {
+                        let (id, annotation) = file_annotation(&uri, description, guess);
+                        annotations.entry(id.clone()).or_insert(annotation);
+                        OneOf::Right(AnnotatedTextEdit {
+                            text_edit: edit,
+                            annotation_id: id,
+                        })
+                    }
+                    None => OneOf::Left(edit),
+                })
+                .collect();
+            operations.push(DocumentChangeOperation::Edit(TextDocumentEdit {
+                text_document: OptionalVersionedTextDocumentIdentifier { uri, version },
+                edits,
+            }));
+        }
+        for (old_uri, new_uri) in self.renames {
+            let annotation_id = self.description.as_ref().map(|description| {
+                let id = format!("{} -> {}", old_uri, new_uri);
+                annotations.insert(
+                    id.clone(),
+                    ChangeAnnotation {
+                        label: format!("Move `{}` to `{}`", name(&old_uri), name(&new_uri)),
+                        needs_confirmation: None,
+                        description: Some(description.clone()),
+                    },
+                );
+                id
+            });
+            operations.push(DocumentChangeOperation::Op(ResourceOp::Rename(
+                RenameFile {
+                    old_uri,
+                    new_uri,
+                    options: None,
+                    annotation_id,
+                },
+            )));
+        }
+        Ok(WorkspaceEdit {
+            document_changes: Some(DocumentChanges::Operations(operations)),
+            change_annotations: (!annotations.is_empty()).then_some(annotations),
+            ..WorkspaceEdit::default()
+        })
+    }
+}
+
+/// The last part of the path of a file or directory.
+fn name(uri: &Url) -> &str {
+    uri.path()
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or_default()
+}
+
+/// The annotation that edits of a file are grouped under, one for its guesses and one for the rest.
+fn file_annotation(uri: &Url, description: &str, guess: bool) -> (String, ChangeAnnotation) {
+    match guess {
+        true => (
+            format!("{}#guesses", uri),
+            ChangeAnnotation {
+                label: name(uri).to_string(),
+                needs_confirmation: Some(true),
+                description: Some(format!(
+                    "{}, where it might not be what's meant",
+                    description
+                )),
+            },
+        ),
+        false => (
+            uri.to_string(),
+            ChangeAnnotation {
+                label: name(uri).to_string(),
+                needs_confirmation: None,
+                description: Some(description.to_string()),
+            },
+        ),
+    }
+}
+
+/**
+ * Sort the edits of a file, dropping repeats and merging those that touch end to end. An edit is
+ * only a guess if all of its repeats are, or if a guess is merged into it.
+ */
+fn merge(
+    uri: &Url,
+    contents: &str,
+    mut edits: Vec<(TextEdit, bool)>,
+) -> Result<Vec<(TextEdit, bool)>, EditError> {
+    let lines = LineIndex::new(contents);
+    for (edit, _) in &edits {
+        let range = edit.range;
+        if range.end < range.start || !lines.contains(range.start) || !lines.contains(range.end) {
+            return Err(EditError::OutOfBounds(uri.clone(), range));
+        }
+    }
+
+    // stable, so insertions at the same place stay in the order they were made
+    edits.sort_by_key(|(edit, _)| edit.range.start);
+    edits.dedup_by(|(edit, guess), (kept, kept_guess)| {
+        if edit != kept {
+            return false;
+        }
+        *kept_guess &= *guess;
+        true
+    });
+    let mut merged: Vec<(TextEdit, bool)> = vec![];
+    for (edit, guess) in edits {
+        match merged.last_mut() {
+            Some((last, _)) if last.range.end > edit.range.start => {
+                return Err(EditError::Overlap(uri.clone(), edit.range));
+            }
+            Some((last, last_guess)) if last.range.end == edit.range.start => {
+                last.range.end = edit.range.end;
+                last.new_text.push_str(&edit.new_text);
+                *last_guess |= guess;
+            }
+            _ => merged.push((edit, guess)),
+        }
+    }
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::*;
+
+    use super::{EditError, WorkspaceEditBuilder};
+
+    fn edit(line: u32, start: u32, end: u32, text: &str) -> TextEdit {
+        TextEdit {
+            range: Range::new(Position::new(line, start), Position::new(line, end)),
+            new_text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_build() {
+        let uri = Url::parse("file:///A.php").unwrap();
+        let file = |_: &Url| Some((Some(3), "<?php\n$é = 1;\n".into()));
+
+        let mut builder = WorkspaceEditBuilder::new();
+        builder.edit(&uri, [edit(1, 0, 3, "$e"), edit(1, 3, 3, "x")]);
+        builder.edit(&uri, [edit(1, 0, 3, "$e"), edit(1, 6, 7, "2")]);
+        let built = builder.build(file, true).unwrap();
+        assert_eq!(
+            Some(DocumentChanges::Operations(vec![
+                DocumentChangeOperation::Edit(TextDocumentEdit {
+                    text_document: OptionalVersionedTextDocumentIdentifier {
+                        uri: uri.clone(),
+                        version: Some(3),
+                    },
+                    edits: vec![
+                        OneOf::Left(edit(1, 0, 3, "$ex")),
+                        OneOf::Left(edit(1, 6, 7, "2")),
+                    ],
+                })
+            ])),
+            built.document_changes
+        );
+
+        let refused = |edits: Vec<TextEdit>| {
+            let mut builder = WorkspaceEditBuilder::new();
+            builder.edit(&uri, edits);
+            builder.build(file, false).unwrap_err()
+        };
+        // in the middle of `é`
+        assert_eq!(
+            EditError::OutOfBounds(uri.clone(), edit(1, 2, 3, "").range),
+            refused(vec![edit(1, 2, 3, "")])
+        );
+        assert_eq!(
+            EditError::OutOfBounds(uri.clone(), edit(1, 0, 12, "").range),
+            refused(vec![edit(1, 0, 12, "")])
+        );
+        assert_eq!(
+            EditError::Overlap(uri.clone(), edit(1, 4, 7, "").range),
+            refused(vec![edit(1, 0, 5, "a"), edit(1, 4, 7, "b")])
+        );
+    }
+
+    #[test]
+    fn test_annotate() {
+        let uri = Url::parse("file:///src/A.php").unwrap();
+        let file = |_: &Url| Some((None, "<?php\nnew A('A');\n".into()));
+
+        let mut builder = WorkspaceEditBuilder::new();
+        builder.annotate("Rename `A` to `B`".to_string());
+        builder.edit(&uri, [edit(1, 4, 5, "B")]);
+        builder.guess(&uri, [edit(1, 7, 8, "B"), edit(1, 4, 5, "B")]);
+        let built = builder.build(file, true).unwrap();
+
+        let annotated = |start, end, id: &str| {
+            OneOf::Right(AnnotatedTextEdit {
+                text_edit: edit(1, start, end, "B"),
+                annotation_id: id.to_string(),
+            })
+        };
+        assert_eq!(
+            Some(DocumentChanges::Operations(vec![
+                DocumentChangeOperation::Edit(TextDocumentEdit {
+                    text_document: OptionalVersionedTextDocumentIdentifier {
+                        uri: uri.clone(),
+                        version: None,
+                    },
+                    edits: vec![
+                        annotated(4, 5, "file:///src/A.php"),
+                        annotated(7, 8, "file:///src/A.php#guesses"),
+                    ],
+                })
+            ])),
+            built.document_changes
+        );
+        let annotations = built.change_annotations.unwrap();
+        assert_eq!(2, annotations.len());
+        assert_eq!("A.php", annotations["file:///src/A.php"].label);
+        assert_eq!(
+            Some(true),
+            annotations["file:///src/A.php#guesses"].needs_confirmation
+        );
+    }
+}
@@ -0,0 +1,131 @@
+//! `case`s of a `switch` that carry on into the next one without a `break`.
+
+use lsp_types::*;
+use tree_sitter::Node;
+
+use crate::cfg::ControlFlowGraph;
+use crate::file::to_range;
+use crate::unreachable::functions;
+
+/// What comments saying that a `case` falls through on purpose say, in lowercase.
+const FALLTHROUGH_COMMENTS: &[&str] = &["no break", "fall through", "falls through", "fallthrough"];
+
+fn says_fallthrough(comment: &Node, file_contents: &str) -> bool {
+    let text = file_contents[comment.byte_range()].to_lowercase();
+    FALLTHROUGH_COMMENTS.iter().any(|said| text.contains(said))
+}
+
+/**
+ * The last statement of a `case` that falls through on its own, unless a comment at its end or
+ * before the next `case` says that it's meant to. `case`s without statements, which share the
+ * statements of the next one, and the last one of a `switch` are left alone.
+ */
+fn falls_through<'tree>(case: &Node<'tree>, file_contents: &str) -> Option<Node<'tree>> {
+    let value = case.child_by_field_name("value").map(|value| value.id());
+    let mut cursor = case.walk();
+    let mut last = None;
+    for child in case.named_children(&mut cursor) {
+        match child.kind() {
+            _ if Some(child.id()) == value => {}
+            "comment" if says_fallthrough(&child, file_contents) => return None,
+            "comment" => {}
+            _ => last = Some(child),
+        }
+    }
+    let last = last?;
+
+    let mut next = case.next_named_sibling();
+    while let Some(sibling) = next {
+        match sibling.kind() {
+            "comment" if says_fallthrough(&sibling, file_contents) => return None,
+            "comment" => next = sibling.next_named_sibling(),
+            "case_statement" | "default_statement" => return Some(last),
+            _ => return None,
+        }
+    }
+    None
+}
+
+/**
+ * Report `case`s and `default`s of a `switch` that run into the next one because nothing at
+ * their end jumps away, like `break`, `return`, `continue` or `throw` does, and there's no
+ * `// no break` or `// fall through` comment to say it's meant.
+ */
+pub fn fallthroughs(root_node: &Node, file_contents: &str, diagnostics: &mut Vec<Diagnostic>) {
+    let mut found = vec![];
+    functions(root_node, &mut found);
+    for function in found {
+        let cfg = ControlFlowGraph::new(&function, file_contents);
+        if cfg.cases.is_empty() {
+            continue;
+        }
+        let reachable = cfg.reachable();
+        for (case, end) in &cfg.cases {
+            if !reachable[*end] {
+                continue;
+            }
+            let Some(last) = falls_through(case, file_contents) else {
+                continue;
+            };
+            diagnostics.push(Diagnostic {
+                range: to_range(&last.range()),
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(NumberOrString::String("fallthrough".to_string())),
+                source: Some("phplsp".to_string()),
+                message: "this case falls through to the next one; add `break`, or a `// no break` comment if that's meant".to_string(),
+                ..Diagnostic::default()
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::fallthroughs;
+    use crate::file::php_parser;
+
+    #[test]
+    fn test_fallthroughs() {
+        let source = "<?php
+function f($a) {
+    switch ($a) {
+        case 1:
+            g();
+        case 2:
+            g();
+            // no break
+        case 3:
+        case 4:
+            if ($a) {
+                return 1;
+            } else {
+                throw new Exception();
+            }
+        case 5:
+            if ($a) {
+                break;
+            }
+        default:
+            g();
+    }
+    foreach ([] as $b) {
+        switch ($b) {
+            case 1:
+                continue 2;
+            default:
+                g();
+        }
+    }
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let mut diagnostics = vec![];
+        fallthroughs(&tree.root_node(), source, &mut diagnostics);
+
+        let lines: Vec<u32> = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.range.start.line)
+            .collect();
+        assert_eq!(vec![4, 16], lines);
+    }
+}
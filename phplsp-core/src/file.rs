@@ -0,0 +1,351 @@
+use lsp_types::*;
+use tree_sitter::{Language, Node, Parser, Tree};
+
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+
+/**
+ * The text of a file and its parse tree at one point in time.
+ *
+ * Snapshots are never modified, and cloning one is cheap: the text is shared and tree-sitter
+ * trees are reference counted. Analyses should clone the snapshot they need and let go of any
+ * locks before borrowing from it.
+ */
+#[derive(Clone)]
+pub struct Snapshot {
+    pub contents: Arc<str>,
+    pub tree: Tree,
+}
+
+impl Snapshot {
+    pub fn new(contents: impl Into<Arc<str>>, tree: Tree) -> Self {
+        Self {
+            contents: contents.into(),
+            tree,
+        }
+    }
+
+    pub fn parse(parser: &mut Parser, contents: impl Into<Arc<str>>) -> Option<Self> {
+        let contents = contents.into();
+        let tree = parser.parse(contents.as_bytes(), None)?;
+        Some(Self { contents, tree })
+    }
+
+    pub fn root_node(&self) -> Node<'_> {
+        self.tree.root_node()
+    }
+}
+
+/// The PHP grammar, loaded once and shared by every parser.
+pub fn php_language() -> &'static Language {
+    static PHP: OnceLock<Language> = OnceLock::new();
+    PHP.get_or_init(tree_sitter_php::language_php)
+}
+
+/// A new PHP parser. Prefer one from [`crate::parsers::parsers`] unless it's for a single use.
+pub fn php_parser() -> Parser {
+    let mut parser = Parser::new();
+    parser
+        .set_language(php_language())
+        .expect("error loading PHP grammar");
+    parser
+}
+
+/// A path with `.` and `..` worked out from how it's written, without looking at the file system.
+fn lexically_normal(path: &Path) -> PathBuf {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normal.pop();
+            }
+            component => normal.push(component),
+        }
+    }
+    normal
+}
+
+/**
+ * Whether a path is in one of some directories, going by how it's written, so that `..` can't
+ * get it out of them.
+ */
+pub fn is_within(path: &Path, dirs: &[PathBuf]) -> bool {
+    let path = lexically_normal(path);
+    dirs.iter()
+        .any(|dir| path.starts_with(lexically_normal(dir)))
+}
+
+pub fn range_plaintext(file_contents: &str, range: tree_sitter::Range) -> String {
+    file_contents[range.start_byte..range.end_byte].to_owned()
+}
+
+pub fn to_position(point: &tree_sitter::Point) -> Position {
+    Position {
+        line: point.row as u32,
+        character: point.column as u32,
+    }
+}
+
+pub fn to_point(position: &Position) -> tree_sitter::Point {
+    tree_sitter::Point {
+        row: position.line as usize,
+        column: position.character as usize,
+    }
+}
+
+pub fn to_range(range: &tree_sitter::Range) -> Range {
+    Range {
+        start: to_position(&range.start_point),
+        end: to_position(&range.end_point),
+    }
+}
+
+/**
+ * How the columns of positions are counted. Tree-sitter, and so everything we work out, counts
+ * bytes, but clients count UTF-16 code units unless they say they can count bytes too.
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PositionEncoding {
+    Utf8,
+    #[default]
+    Utf16,
+}
+
+impl PositionEncoding {
+    /// The encoding to use with a client that supports some, preferring the one we use ourselves.
+    pub fn negotiate(supported: &[PositionEncodingKind]) -> Self {
+        match supported.contains(&PositionEncodingKind::UTF8) {
+            true => Self::Utf8,
+            false => Self::Utf16,
+        }
+    }
+
+    pub fn kind(self) -> PositionEncodingKind {
+        match self {
+            Self::Utf8 => PositionEncodingKind::UTF8,
+            Self::Utf16 => PositionEncodingKind::UTF16,
+        }
+    }
+
+    /// How many columns a character takes up in this encoding.
+    fn columns(self, c: char) -> usize {
+        match self {
+            Self::Utf8 => c.len_utf8(),
+            Self::Utf16 => c.len_utf16(),
+        }
+    }
+}
+
+/// Where the lines of a text start, to convert positions between encodings.
+pub struct LineIndex {
+    text: Arc<str>,
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(text: impl Into<Arc<str>>) -> Self {
+        let text = text.into();
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self { text, line_starts }
+    }
+
+    /// The text of a line, without its line break.
+    fn line(&self, line: u32) -> Option<&str> {
+        let start = *self.line_starts.get(line as usize)?;
+        let end = self
+            .line_starts
+            .get(line as usize + 1)
+            .map_or(self.text.len(), |next| next - 1);
+        let text = &self.text[start..end];
+        Some(text.strip_suffix('\r').unwrap_or(text))
+    }
+
+    /**
+     * Convert a position whose column counts bytes, like those of tree-sitter, to one that counts
+     * in an encoding. Columns in the middle of a character are taken to be before it.
+     */
+    pub fn encode(&self, position: Position, encoding: PositionEncoding) -> Position {
+        let Some(line) = self.line(position.line) else {
+            return position;
+        };
+        let mut column = (position.character as usize).min(line.len());
+        while !line.is_char_boundary(column) {
+            column -= 1;
+        }
+
+        Position {
+            line: position.line,
+            character: line[..column]
+                .chars()
+                .map(|c| encoding.columns(c))
+                .sum::<usize>() as u32,
+        }
+    }
+
+    /**
+     * Convert a position whose column counts in an encoding to one that counts bytes. Columns
+     * past the end of their line are at the end of it, as LSP wants.
+     */
+    pub fn decode(&self, position: Position, encoding: PositionEncoding) -> Position {
+        let Some(line) = self.line(position.line) else {
+            return position;
+        };
+        let mut column = 0;
+        let mut bytes = line.len();
+        for (i, c) in line.char_indices() {
+            if column >= position.character as usize {
+                bytes = i;
+                break;
+            }
+            column += encoding.columns(c);
+        }
+
+        Position {
+            line: position.line,
+            character: bytes as u32,
+        }
+    }
+
+    /// Whether a position whose column counts bytes is in the text, and between two characters.
+    pub fn contains(&self, position: Position) -> bool {
+        self.line(position.line).is_some_and(|line| {
+            let column = position.character as usize;
+            column <= line.len() && line.is_char_boundary(column)
+        })
+    }
+
+    /// The byte offset of a position that counts in an encoding.
+    pub fn offset(&self, position: Position, encoding: PositionEncoding) -> usize {
+        match self.line_starts.get(position.line as usize) {
+            Some(start) => start + self.decode(position, encoding).character as usize,
+            None => self.text.len(),
+        }
+    }
+}
+
+/// Get byte offset given some row and column position in a file.
+///
+/// For example, line 1 character 1 should have offset of 0 (0-indexing).
+///
+/// Return None if the position is invalid (i.e. not in the file, out of range of current line,
+/// etc.)
+pub fn byte_offset(text: &str, r: &Position) -> Option<usize> {
+    if r.character == 0 {
+        return None;
+    }
+
+    let line = r.line as usize;
+    // start on the zeroth, not the first, because that's how offsets work
+    let character = r.character as usize - 1;
+    let mut current_offset = 0usize;
+
+    for (line_text, line_num) in text.lines().zip(1..=line) {
+        if line_num == line {
+            if character > line_text.len() {
+                return None;
+            } else {
+                return Some(current_offset + character);
+            }
+        } else {
+            let newline_offset = current_offset + line_text.len();
+            // assume only two types of newlines exist: `\n` and `\r\n`
+            let newline_num_bytes = if text[newline_offset..].starts_with('\n') {
+                1
+            } else {
+                2
+            };
+
+            current_offset += line_text.len() + newline_num_bytes;
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::*;
+
+    use std::path::{Path, PathBuf};
+
+    use super::{byte_offset, is_within, LineIndex, PositionEncoding};
+
+    const SOURCE: &str = "<?php
+            class Whatever {
+                public int $x = 12;
+            }";
+
+    #[test]
+    fn test_valid_byte_offsets() {
+        let valids = [
+            (
+                Position {
+                    line: 1,
+                    character: 1,
+                },
+                0usize,
+            ),
+            (
+                Position {
+                    line: 2,
+                    character: 1,
+                },
+                6usize,
+            ),
+        ];
+
+        for (pos, expected) in valids {
+            assert_eq!(expected, byte_offset(SOURCE, &pos).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_invalid_byte_offsets() {
+        let invalids = [
+            Position {
+                line: 200,
+                character: 10,
+            },
+            Position {
+                line: 1,
+                character: 100,
+            },
+        ];
+
+        for invalid_position in invalids {
+            assert_eq!(None, byte_offset(SOURCE, &invalid_position));
+        }
+    }
+
+    #[test]
+    fn test_line_index() {
+        let lines = LineIndex::new("<?php\r\n$é = '😀';\n$x = 1;");
+        let at = |line, character| Position { line, character };
+
+        // the closing `'` comes after 2 bytes of `é` and 4 of the emoji, but 1 and 2 code units
+        assert_eq!(at(1, 8), lines.encode(at(1, 11), PositionEncoding::Utf16));
+        assert_eq!(at(1, 11), lines.decode(at(1, 8), PositionEncoding::Utf16));
+        assert_eq!(at(1, 11), lines.encode(at(1, 11), PositionEncoding::Utf8));
+        // in the middle of the emoji, and past the end of lines
+        assert_eq!(at(1, 6), lines.encode(at(1, 9), PositionEncoding::Utf16));
+        assert_eq!(at(0, 5), lines.decode(at(0, 20), PositionEncoding::Utf16));
+        assert_eq!(at(1, 10), lines.encode(at(1, 40), PositionEncoding::Utf16));
+
+        assert_eq!(7, lines.offset(at(1, 0), PositionEncoding::Utf16));
+        assert_eq!(22, lines.offset(at(2, 1), PositionEncoding::Utf16));
+        assert_eq!(28, lines.offset(at(9, 0), PositionEncoding::Utf16));
+    }
+
+    #[test]
+    fn test_is_within() {
+        let dirs = [PathBuf::from("/project"), PathBuf::from("/stubs/./php")];
+        assert!(is_within(Path::new("/project/src/A.php"), &dirs));
+        assert!(is_within(Path::new("/stubs/php/core.php"), &dirs));
+        assert!(is_within(Path::new("/project/src/../lib/B.php"), &dirs));
+        assert!(!is_within(Path::new("/project/../etc/passwd"), &dirs));
+        assert!(!is_within(Path::new("/projects/A.php"), &dirs));
+    }
+}
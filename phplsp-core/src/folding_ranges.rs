@@ -0,0 +1,226 @@
+use lsp_types::*;
+use tree_sitter::Node;
+
+use crate::file::range_plaintext;
+use crate::tags::use_blocks;
+
+/// Nodes that can be folded away, as long as they span several lines.
+const FOLDABLE: &[&str] = &[
+    "declaration_list",
+    "enum_declaration_list",
+    "compound_statement",
+    "colon_block",
+    "switch_block",
+    "match_block",
+    "array_creation_expression",
+];
+
+fn folding_range(
+    start_line: usize,
+    end_line: usize,
+    kind: Option<FoldingRangeKind>,
+) -> FoldingRange {
+    FoldingRange {
+        start_line: start_line as u32,
+        start_character: None,
+        end_line: end_line as u32,
+        end_character: None,
+        kind,
+        collapsed_text: None,
+    }
+}
+
+/// Whether a comment is a `#region` or `#endregion` marker, and its label.
+fn region_marker(comment: &str) -> Option<(bool, &str)> {
+    let text = comment
+        .strip_prefix("//")
+        .or_else(|| comment.strip_prefix('#'))?
+        .trim_start();
+    let text = text.strip_prefix('#').unwrap_or(text);
+
+    if let Some(label) = text.strip_prefix("endregion") {
+        Some((false, label.trim()))
+    } else {
+        text.strip_prefix("region")
+            .map(|label| (true, label.trim()))
+    }
+}
+
+struct FoldingWalker<'a> {
+    file_contents: &'a str,
+    ranges: Vec<FoldingRange>,
+    /// Start lines and labels of the `#region`s we are in.
+    regions: Vec<(usize, String)>,
+}
+
+impl FoldingWalker<'_> {
+    fn comment(&mut self, node: &Node) {
+        let text = range_plaintext(self.file_contents, node.range());
+        let start_line = node.start_position().row;
+        let end_line = node.end_position().row;
+
+        match region_marker(&text) {
+            Some((true, label)) => self.regions.push((start_line, label.to_string())),
+            Some((false, _)) => {
+                if let Some((region_start, label)) = self.regions.pop() {
+                    let mut range =
+                        folding_range(region_start, end_line, Some(FoldingRangeKind::Region));
+                    if !label.is_empty() {
+                        range.collapsed_text = Some(label);
+                    }
+                    self.ranges.push(range);
+                }
+            }
+            None => {
+                if text.starts_with("/*") && end_line > start_line {
+                    self.ranges.push(folding_range(
+                        start_line,
+                        end_line,
+                        Some(FoldingRangeKind::Comment),
+                    ));
+                }
+            }
+        }
+    }
+
+    fn walk(&mut self, node: &Node) {
+        if node.kind() == "comment" {
+            self.comment(node);
+            return;
+        }
+
+        if FOLDABLE.contains(&node.kind()) {
+            let start_line = node.start_position().row;
+            let mut end_line = node.end_position().row;
+            // keep the closing bracket visible
+            let closes_with_bracket = node
+                .child(node.child_count().saturating_sub(1))
+                .is_some_and(|last| matches!(last.kind(), "}" | "]" | ")"));
+            if closes_with_bracket {
+                end_line = end_line.saturating_sub(1);
+            }
+
+            if end_line > start_line {
+                self.ranges.push(folding_range(start_line, end_line, None));
+            }
+        }
+
+        let mut cursor = node.walk();
+        for child in node.children(&mut cursor) {
+            self.walk(&child);
+        }
+    }
+}
+
+/**
+ * Folding ranges for bodies, multi-line arrays, doc comments, runs of `use` statements and
+ * `#region`/`#endregion` markers, which are named after the label of their `#region`.
+ *
+ * Unterminated regions are ignored.
+ */
+pub fn folding_ranges(root_node: &Node, file_contents: &str) -> Vec<FoldingRange> {
+    let mut walker = FoldingWalker {
+        file_contents,
+        ranges: vec![],
+        regions: vec![],
+    };
+    walker.walk(root_node);
+    for (first, last) in use_blocks(root_node) {
+        let (start_line, end_line) = (first.start_position().row, last.end_position().row);
+        if end_line > start_line {
+            walker.ranges.push(folding_range(
+                start_line,
+                end_line,
+                Some(FoldingRangeKind::Imports),
+            ));
+        }
+    }
+
+    walker
+        .ranges
+        .sort_by_key(|range| (range.start_line, range.end_line));
+    walker.ranges
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::*;
+
+    use super::{folding_ranges, region_marker};
+    use crate::file::php_parser;
+
+    #[test]
+    fn test_region_markers() {
+        assert_eq!(Some((true, "Helpers")), region_marker("#region Helpers"));
+        assert_eq!(Some((true, "")), region_marker("// #region"));
+        assert_eq!(Some((false, "")), region_marker("// endregion"));
+        assert_eq!(None, region_marker("// the region we are in"));
+        assert_eq!(None, region_marker("/* #region */"));
+    }
+
+    #[test]
+    fn test_folding_ranges() {
+        let source = "<?php
+#region one
+class A {
+    /**
+     * doc
+     */
+    function f() {
+        if ($x):
+            a();
+            b();
+        endif;
+        $a = [
+            1,
+        ];
+        $b = [1, 2];
+    }
+}
+// #endregion
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let folds = folding_ranges(&tree.root_node(), source);
+        assert_eq!(Some("one".to_string()), folds[0].collapsed_text);
+
+        let ranges: Vec<(u32, u32, Option<FoldingRangeKind>)> = folds
+            .into_iter()
+            .map(|range| (range.start_line, range.end_line, range.kind))
+            .collect();
+
+        assert_eq!(
+            vec![
+                (1, 17, Some(FoldingRangeKind::Region)),
+                (2, 15, None),
+                (3, 5, Some(FoldingRangeKind::Comment)),
+                (6, 14, None),
+                (7, 9, None),
+                (11, 12, None),
+            ],
+            ranges
+        );
+    }
+
+    #[test]
+    fn test_use_folding() {
+        let source = "<?php
+namespace App;
+use Foo\\A;
+// the rest
+use Foo\\{
+    B,
+    C,
+};
+function f() {}
+use Foo\\D;
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let ranges: Vec<(u32, u32, Option<FoldingRangeKind>)> =
+            folding_ranges(&tree.root_node(), source)
+                .into_iter()
+                .map(|range| (range.start_line, range.end_line, range.kind))
+                .collect();
+
+        assert_eq!(vec![(2, 7, Some(FoldingRangeKind::Imports))], ranges);
+    }
+}
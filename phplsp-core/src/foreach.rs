@@ -0,0 +1,317 @@
+//! `foreach` loops over what can't be iterated over, which PHP only warns about and skips, and
+//! destructuring of their values with keys those values don't have.
+
+use lsp_types::*;
+use tree_sitter::Node;
+
+use crate::array_keys::literal_key;
+use crate::file::to_range;
+use crate::index::WorkspaceIndex;
+use crate::resolve::Resolver;
+use crate::symbols::{DeclarationKind, FileSymbols};
+use crate::types::{Type, TypeInference};
+
+/// Classes that stand for more than one kind of value: callables can be arrays, say.
+const VAGUE_CLASSES: &[&str] = &["Closure", "stdClass"];
+
+fn warning(node: &Node, code: &str, message: String) -> Diagnostic {
+    Diagnostic {
+        range: to_range(&node.range()),
+        severity: Some(DiagnosticSeverity::WARNING),
+        code: Some(NumberOrString::String(code.to_string())),
+        source: Some("phplsp".to_string()),
+        message,
+        ..Diagnostic::default()
+    }
+}
+
+struct Checker<'a> {
+    contents: &'a str,
+    resolver: &'a Resolver<'a>,
+    types: TypeInference<'a>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker<'_> {
+    fn text(&self, node: &Node) -> &str {
+        &self.contents[node.byte_range()]
+    }
+
+    /**
+     * Why a value of a type can't be iterated over, if it certainly can't. Interfaces and classes
+     * with ancestors we don't know could be `Traversable` after all, and so could `null` along
+     * with something else, since it's usually checked for first.
+     */
+    fn not_iterable(&self, ty: &Type) -> Option<&'static str> {
+        match ty {
+            Type::Int | Type::Float | Type::String | Type::Bool | Type::Null | Type::Void => {
+                Some("so it's skipped")
+            }
+            Type::Union(members) => members
+                .iter()
+                .map(|member| self.not_iterable(member))
+                .collect::<Option<Vec<_>>>()
+                .map(|_| "so it's skipped"),
+            Type::Class(class) => {
+                if VAGUE_CLASSES
+                    .iter()
+                    .any(|vague| vague.eq_ignore_ascii_case(class))
+                {
+                    return None;
+                }
+                let index = self.resolver.index();
+                let declaration = index.find_declaration(class)?;
+                if declaration.kind == DeclarationKind::Interface {
+                    return None;
+                }
+                let ancestors = self.resolver.ancestors(class);
+                let all_known = ancestors
+                    .iter()
+                    .all(|ancestor| index.find_declaration(ancestor).is_some());
+                let traversable = ancestors
+                    .iter()
+                    .any(|ancestor| ancestor.eq_ignore_ascii_case("Traversable"));
+                (all_known && !traversable)
+                    .then_some("so it goes over its public properties instead")
+            }
+            _ => None,
+        }
+    }
+
+    /**
+     * Check the keys of a `[...]` or `list(...)` that destructures a value of a type. Keys that
+     * aren't written count up from 0, skipped elements included.
+     */
+    fn destructure(&mut self, list: &Node, ty: &Type, iterated: &str) {
+        let ty = ty.without_null();
+        if matches!(ty, Type::Int | Type::Float | Type::String | Type::Bool) {
+            self.diagnostics.push(warning(
+                list,
+                "foreach-destructuring",
+                format!(
+                    "the values of `{}` are `{}`, which can't be destructured, so every variable here is `null`",
+                    iterated, ty
+                ),
+            ));
+            return;
+        }
+
+        // each element is its nodes between commas
+        let mut elements: Vec<Vec<Node>> = vec![vec![]];
+        let mut cursor = list.walk();
+        for child in list.children(&mut cursor) {
+            match child.kind() {
+                "," => elements.push(vec![]),
+                "[" | "]" | "list" | "(" | ")" => {}
+                _ => elements.last_mut().into_iter().for_each(|e| e.push(child)),
+            }
+        }
+
+        let mut position = 0;
+        for element in elements {
+            let named: Vec<&Node> = element.iter().filter(|node| node.is_named()).collect();
+            let keyed = element.iter().any(|node| node.kind() == "=>");
+            let (key, written, value) = match (keyed, named.as_slice()) {
+                (true, [key, value]) => {
+                    let Some(literal) = literal_key(key, self.contents) else {
+                        continue;
+                    };
+                    (literal, **key, **value)
+                }
+                (false, [value]) => {
+                    position += 1;
+                    ((position - 1).to_string(), **value, **value)
+                }
+                (false, []) => {
+                    position += 1;
+                    continue;
+                }
+                _ => continue,
+            };
+
+            if let Type::Shape(entries) = &ty {
+                match entries.iter().find(|(known, _)| *known == key) {
+                    Some((_, entry)) if value.kind() == "list_literal" => {
+                        let entry = entry.clone();
+                        self.destructure(&value, &entry, iterated);
+                    }
+                    Some(_) => {}
+                    None => self.diagnostics.push(warning(
+                        &written,
+                        "foreach-destructuring",
+                        format!(
+                            "the values of `{}` have no key `{}`; their keys are {}",
+                            iterated,
+                            key,
+                            entries
+                                .iter()
+                                .map(|(known, _)| format!("`{}`", known))
+                                .collect::<Vec<String>>()
+                                .join(", ")
+                        ),
+                    )),
+                }
+            }
+        }
+    }
+
+    fn foreach(&mut self, foreach: &Node) {
+        let (Some(iterated), Some(binding)) = (foreach.named_child(0), foreach.named_child(1))
+        else {
+            return;
+        };
+        let Some(ty) = self.types.expression(&iterated) else {
+            return;
+        };
+        let text = self.text(&iterated).to_string();
+        if let Some(consequence) = self.not_iterable(&ty) {
+            self.diagnostics.push(warning(
+                &iterated,
+                "foreach-type",
+                format!(
+                    "`{}` is `{}`, which isn't an array or `Traversable`, {}",
+                    text, ty, consequence
+                ),
+            ));
+            return;
+        }
+
+        let value = match binding.kind() {
+            "pair" => binding.named_child(binding.named_child_count().saturating_sub(1)),
+            _ => Some(binding),
+        };
+        let element = ty.without_null().value_type();
+        if let (Some(list), Some(element)) = (
+            value.filter(|value| value.kind() == "list_literal"),
+            element,
+        ) {
+            self.destructure(&list, &element, &text);
+        }
+    }
+
+    fn walk(&mut self, node: &Node) {
+        if node.kind() == "foreach_statement" {
+            self.foreach(node);
+        }
+
+        let mut cursor = node.walk();
+        for child in node.named_children(&mut cursor) {
+            self.walk(&child);
+        }
+    }
+}
+
+/**
+ * Report `foreach` loops over values whose type isn't an array or `Traversable`: scalars and
+ * `null`, which PHP warns about and skips, and objects of classes that don't implement it, whose
+ * public properties are gone over instead. Also report destructuring of the values with keys
+ * that their shape doesn't have, or of values that are scalars.
+ */
+pub fn foreach_diagnostics(
+    root_node: &Node,
+    file_contents: &str,
+    symbols: &FileSymbols,
+    index: &WorkspaceIndex,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let resolver = Resolver::new(file_contents, symbols, index);
+    let mut checker = Checker {
+        contents: file_contents,
+        resolver: &resolver,
+        types: TypeInference::new(file_contents, &resolver),
+        diagnostics: vec![],
+    };
+    checker.walk(root_node);
+    diagnostics.append(&mut checker.diagnostics);
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::*;
+
+    use super::foreach_diagnostics;
+    use crate::file::php_parser;
+    use crate::index::WorkspaceIndex;
+    use crate::symbols::file_symbols;
+
+    #[test]
+    fn test_foreach_diagnostics() {
+        let source = "<?php
+interface Traversable {}
+interface IteratorAggregate extends Traversable {}
+interface Countable {}
+class Point { public $x; }
+class Rows implements IteratorAggregate {}
+class Db {
+    #[ArrayShape(['id' => 'int', 'tags' => 'string[]'])]
+    public function row(): array {}
+    public function f(int $count, ?array $maybe, Countable $c, Point $p, Rows $rows) {
+        foreach ($count as $n) {}
+        foreach ($maybe as $m) {}
+        foreach ($c as $item) {}
+        foreach ($p as $property) {}
+        foreach ($rows as $row) {}
+        foreach ([$this->row()] as $i => ['id' => $id, 'name' => $name, 'tags' => [$tag]]) {}
+        foreach ([$this->row()] as [$first]) {}
+        foreach ([1, 2] as [$a, $b]) {}
+        foreach ([$this->row()] as ['id' => $d, \"\\$id\" => $e]) {}
+    }
+    public function greet(string ...$names) {
+        foreach ($names as $name) {}
+    }
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(&Url::parse("file:///a.php").unwrap(), symbols.clone());
+        let mut diagnostics = vec![];
+        foreach_diagnostics(
+            &tree.root_node(),
+            source,
+            &symbols,
+            &index,
+            &mut diagnostics,
+        );
+
+        let found: Vec<(Position, String)> = diagnostics
+            .into_iter()
+            .map(|diagnostic| (diagnostic.range.start, diagnostic.message))
+            .collect();
+        assert_eq!(
+            vec![
+                (
+                    Position::new(10, 17),
+                    "`$count` is `int`, which isn't an array or `Traversable`, so it's skipped"
+                        .to_string()
+                ),
+                (
+                    Position::new(13, 17),
+                    "`$p` is `Point`, which isn't an array or `Traversable`, so it goes over its public properties instead"
+                        .to_string()
+                ),
+                (
+                    Position::new(15, 55),
+                    "the values of `[$this->row()]` have no key `name`; their keys are `id`, `tags`"
+                        .to_string()
+                ),
+                (
+                    Position::new(16, 36),
+                    "the values of `[$this->row()]` have no key `0`; their keys are `id`, `tags`"
+                        .to_string()
+                ),
+                (
+                    Position::new(17, 27),
+                    "the values of `[1, 2]` are `int`, which can't be destructured, so every variable here is `null`"
+                        .to_string()
+                ),
+                (
+                    Position::new(18, 48),
+                    "the values of `[$this->row()]` have no key `$id`; their keys are `id`, `tags`"
+                        .to_string()
+                ),
+            ],
+            found
+        );
+    }
+}
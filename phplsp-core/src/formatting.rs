@@ -0,0 +1,1246 @@
+use lsp_types::*;
+use tree_sitter::Node;
+
+use std::ops::Range as ByteRange;
+
+use crate::config::AutoClose;
+use crate::file::{to_point, to_position};
+
+/// Nodes that are formatted as if they were one token, since what's in them isn't ours to change.
+const ATOMIC: &[&str] = &[
+    "comment",
+    "string",
+    "encapsed_string",
+    "heredoc",
+    "nowdoc",
+    "shell_command_expression",
+    "variable_name",
+    "qualified_name",
+    "namespace_name",
+    "php_tag",
+    "text",
+];
+
+/// Nodes whose children are statements or members, each of which goes on a line of its own.
+const BLOCKS: &[&str] = &[
+    "program",
+    "compound_statement",
+    "declaration_list",
+    "enum_declaration_list",
+    "switch_block",
+    "case_statement",
+    "default_statement",
+    "colon_block",
+    "use_list",
+];
+
+/// Clauses of statements, and the keywords that end statements written with colons, which line
+/// up with the statement they're in.
+const CLAUSES: &[&str] = &[
+    "else_clause",
+    "else_if_clause",
+    "catch_clause",
+    "finally_clause",
+    "endif",
+    "endwhile",
+    "endfor",
+    "endforeach",
+    "endswitch",
+    "enddeclare",
+];
+
+/// Blocks whose statements come after a colon, and are indented from it.
+const COLON_BODIES: &[&str] = &["case_statement", "default_statement", "colon_block"];
+
+/// Nodes other than blocks whose children can each start a line without continuing another.
+const LISTS: &[&str] = &[
+    "match_block",
+    "arguments",
+    "formal_parameters",
+    "array_creation_expression",
+    "parenthesized_expression",
+    "anonymous_function_use_clause",
+    "namespace_use_group",
+    "attribute_group",
+    "list_literal",
+    "ERROR",
+];
+
+/// Keywords that have a space between them and their parentheses.
+const KEYWORDS_BEFORE_PARENTHESES: &[&str] = &[
+    "if", "elseif", "while", "for", "foreach", "switch", "catch", "match", "use",
+];
+
+/// Keywords that are written like calls, without a space before their parentheses.
+const CALL_KEYWORDS: &[&str] = &[
+    "declare", "array", "list", "isset", "empty", "unset", "exit", "die", "eval",
+];
+
+const OPERATORS: &[&str] = &[
+    "=", "+=", "-=", "*=", "/=", ".=", "%=", "**=", "??=", "&=", "|=", "^=", "<<=", ">>=", "=>",
+    "==", "===", "!=", "!==", "<>", "<", ">", "<=", ">=", "<=>", "&&", "||", "??", "+", "-", "*",
+    "/", "%", "**", ".", "|", "&", "^", "<<", ">>", "?", ":",
+];
+
+const WORD_OPERATORS: &[&str] = &["and", "or", "xor", "instanceof"];
+
+/// Nodes that the operators in them are binary in, as opposed to prefixes.
+const OPERATOR_PARENTS: &[&str] = &[
+    "binary_expression",
+    "assignment_expression",
+    "augmented_assignment_expression",
+    "reference_assignment_expression",
+    "conditional_expression",
+    "array_element_initializer",
+    "pair",
+    "match_conditional_expression",
+    "match_default_expression",
+    "arrow_function",
+    "simple_parameter",
+    "property_promotion_parameter",
+    "property_element",
+    "const_element",
+    "static_variable_declaration",
+    "enum_case",
+    "declare_directive",
+    "union_type",
+    "intersection_type",
+    "disjunctive_normal_form_type",
+    "type_list",
+];
+
+/// Where a function-like's body goes, and whether its opening brace is on a line of its own.
+const FUNCTIONS: &[&str] = &["function_definition", "method_declaration"];
+
+/// Class-likes, whose opening braces are on lines of their own.
+const CLASS_LIKES: &[&str] = &[
+    "class_declaration",
+    "interface_declaration",
+    "trait_declaration",
+    "enum_declaration",
+];
+
+#[derive(Clone, Copy)]
+struct Token<'tree> {
+    node: Node<'tree>,
+    in_error: bool,
+}
+
+/// What the whitespace between two tokens should be.
+enum Gap {
+    /// Whatever it is now.
+    Keep,
+    /// This, on the same line.
+    Inline(&'static str),
+    /// The same lines as now if the tokens are on different lines, or else this.
+    Wrap(&'static str),
+    /// A new line, after at most this many blank lines.
+    Line(usize),
+}
+
+fn tokens<'tree>(node: Node<'tree>, in_error: bool, found: &mut Vec<Token<'tree>>) {
+    let in_error = in_error || node.is_error();
+    if node.is_missing() || node.start_byte() == node.end_byte() {
+        return;
+    }
+    if node.child_count() == 0 || ATOMIC.contains(&node.kind()) {
+        found.push(Token { node, in_error });
+        return;
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        tokens(child, in_error, found);
+    }
+}
+
+fn parent_kind(node: &Node) -> &'static str {
+    node.parent().map_or("", |parent| parent.kind())
+}
+
+/// Whether a child of a block with a colon comes after that colon.
+fn after_colon(block: &Node, child: &Node) -> bool {
+    let mut cursor = block.walk();
+    let colon = block.children(&mut cursor).find(|node| node.kind() == ":");
+    colon.is_some_and(|colon| child.start_byte() >= colon.end_byte())
+}
+
+fn is_item(block: &Node, child: &Node) -> bool {
+    match block.kind() {
+        "program" => !matches!(child.kind(), "php_tag" | "text_interpolation"),
+        kind if COLON_BODIES.contains(&kind) => after_colon(block, child),
+        _ => !matches!(child.kind(), "{" | "}" | ";"),
+    }
+}
+
+/// Whether a token starts a statement or member of a block.
+fn starts_item(token: &Node) -> bool {
+    let mut node = *token;
+    while let Some(parent) = node.parent() {
+        if BLOCKS.contains(&parent.kind()) && is_item(&parent, &node) {
+            return true;
+        }
+        if parent.start_byte() != token.start_byte() {
+            return false;
+        }
+        node = parent;
+    }
+
+    false
+}
+
+/**
+ * How many more levels a line is indented than the bracket it's in: one for statements after
+ * a `case`, and one for lines that continue a statement or an item of a list.
+ */
+fn continuation(token: &Node) -> usize {
+    let continues = |node: &Node| {
+        // attributes go on lines of their own before what they're on
+        let after_attributes = node
+            .child(0)
+            .filter(|first| first.kind() == "attribute_list")
+            .and_then(|attributes| attributes.next_sibling())
+            .is_some_and(|next| next.start_byte() == token.start_byte());
+        (node.start_byte() != token.start_byte() && !after_attributes) as usize
+    };
+    let mut node = *token;
+    while let Some(parent) = node.parent() {
+        if node.start_byte() == token.start_byte() && CLAUSES.contains(&node.kind()) {
+            return 0;
+        }
+        if COLON_BODIES.contains(&parent.kind()) && after_colon(&parent, &node) {
+            return 1 + continues(&node);
+        }
+        // conditions split over lines line their operators up with the first operand
+        if parent.kind() == "parenthesized_expression" {
+            return 0;
+        }
+        if BLOCKS.contains(&parent.kind()) || LISTS.contains(&parent.kind()) {
+            return continues(&node);
+        }
+        node = parent;
+    }
+
+    0
+}
+
+fn closes(open: &str, close: &str) -> bool {
+    matches!((open, close), ("{", "}") | ("(", ")") | ("[" | "#[", "]"))
+}
+
+/// Operators that go between two operands, like `+` and `=>`.
+fn is_operator(node: &Node) -> bool {
+    !node.is_named()
+        && (OPERATORS.contains(&node.kind()) || WORD_OPERATORS.contains(&node.kind()))
+        && OPERATOR_PARENTS.contains(&parent_kind(node))
+}
+
+/// Tokens that the token after them is written right up against, like `!` and `...`.
+fn is_prefix(node: &Node) -> bool {
+    match (node.kind(), parent_kind(node)) {
+        (_, "unary_op_expression") | ("++" | "--", "update_expression") => {
+            node.prev_sibling().is_none()
+        }
+        ("&", "reference_modifier" | "by_ref" | "reference_assignment_expression") => true,
+        ("?", "optional_type") => true,
+        ("...", _) | ("@", _) => true,
+        _ => false,
+    }
+}
+
+/// Whether two tokens would read as something else if they were written together.
+fn would_merge(prev: &str, next: &str) -> bool {
+    let word = |c: char| c.is_alphanumeric() || c == '_' || c == '$';
+    match (prev.chars().last(), next.chars().next()) {
+        (Some(a), Some(b)) => {
+            (word(a) && word(b))
+                || (a == b && matches!(a, '+' | '-' | '&' | '|' | '.' | '?'))
+                || (a == '/' && matches!(b, '/' | '*'))
+        }
+        _ => false,
+    }
+}
+
+/// The gap between two tokens according to PSR-12.
+fn gap(prev: &Node, next: &Node, existing: &str) -> Gap {
+    let (p, n) = (prev.kind(), next.kind());
+    let wraps = existing.contains('\n');
+    let default = match existing.is_empty() {
+        true => Gap::Inline(""),
+        false => Gap::Wrap(" "),
+    };
+
+    if matches!(p, "php_tag" | "heredoc" | "nowdoc" | "?>" | "text") || matches!(n, "?>" | "text") {
+        return Gap::Keep;
+    }
+    if p == "comment" || n == "comment" {
+        return match wraps {
+            true if n == "}" || p == "{" => Gap::Line(0),
+            true => Gap::Line(1),
+            false => Gap::Keep,
+        };
+    }
+
+    if p == "}"
+        && (matches!(n, "else" | "elseif" | "catch" | "finally")
+            || (n == "while" && parent_kind(next) == "do_statement"))
+    {
+        return Gap::Inline(" ");
+    }
+    if n == "{" {
+        let block = next.parent();
+        let owner = block.and_then(|block| block.parent());
+        let owner_kind = owner.map_or("", |owner| owner.kind());
+        match parent_kind(next) {
+            "declaration_list" | "enum_declaration_list" if CLASS_LIKES.contains(&owner_kind) => {
+                return Gap::Line(0)
+            }
+            "compound_statement" if FUNCTIONS.contains(&owner_kind) => {
+                // a closing parenthesis on a line of its own is followed by the brace
+                let split = owner
+                    .and_then(|owner| owner.child_by_field_name("parameters"))
+                    .is_some_and(|parameters| {
+                        parameters.start_position().row != parameters.end_position().row
+                    });
+                return match split {
+                    true => Gap::Inline(" "),
+                    false => Gap::Line(0),
+                };
+            }
+            "compound_statement" | "switch_block" | "match_block" | "declaration_list" => {
+                return Gap::Inline(" ")
+            }
+            _ => {}
+        }
+    }
+    if n == "}" && BLOCKS.contains(&parent_kind(next)) {
+        return match (p, wraps) {
+            ("{", false) => Gap::Inline(""),
+            _ => Gap::Line(0),
+        };
+    }
+    if starts_item(next) {
+        return Gap::Line(if p == "{" { 0 } else { 1 });
+    }
+
+    if matches!(n, ";" | ",") {
+        return Gap::Inline("");
+    }
+    if matches!(n, ")" | "]") || matches!(p, "(" | "[" | "#[") {
+        return Gap::Wrap("");
+    }
+    // semicolons that don't end statements are in `for` loops
+    if matches!(p, "," | ";") {
+        return Gap::Wrap(" ");
+    }
+    if n == "(" {
+        match parent_kind(next) {
+            "arguments" => return Gap::Inline(""),
+            "formal_parameters" if matches!(p, "function" | "fn") => return Gap::Inline(" "),
+            "formal_parameters" => return Gap::Inline(""),
+            _ if KEYWORDS_BEFORE_PARENTHESES.contains(&p) => return Gap::Inline(" "),
+            _ if CALL_KEYWORDS.contains(&p) => return Gap::Inline(""),
+            _ => {}
+        }
+    }
+    if matches!(n, "->" | "?->") {
+        return Gap::Wrap("");
+    }
+    if matches!(p, "->" | "?->" | "::") || n == "::" {
+        return Gap::Inline("");
+    }
+    if is_prefix(prev) || (matches!(n, "++" | "--") && parent_kind(next) == "update_expression") {
+        return Gap::Inline("");
+    }
+
+    if n == ":" && !is_operator(next) {
+        return Gap::Inline("");
+    }
+    if p == ":" && !is_operator(prev) {
+        return match parent_kind(prev) {
+            "argument"
+            | "function_definition"
+            | "method_declaration"
+            | "anonymous_function"
+            | "arrow_function" => Gap::Inline(" "),
+            _ => default,
+        };
+    }
+    if p == "?" && n == ":" && parent_kind(next) == "conditional_expression" {
+        return Gap::Inline("");
+    }
+    if let Some(operator) = [next, prev].into_iter().find(|node| is_operator(node)) {
+        return match parent_kind(operator) {
+            "declare_directive"
+            | "union_type"
+            | "intersection_type"
+            | "disjunctive_normal_form_type"
+            | "type_list" => Gap::Inline(""),
+            _ => Gap::Wrap(" "),
+        };
+    }
+    if p == ")" && parent_kind(prev) == "cast_expression" {
+        return Gap::Inline(" ");
+    }
+    // like `use` after the parameters of a closure
+    if p == ")" && !next.is_named() && n.starts_with(char::is_alphabetic) {
+        return Gap::Inline(" ");
+    }
+
+    default
+}
+
+/// How a `use` statement is sorted: classes, then functions, then constants, each by name.
+fn import_key(statement: &Node, file_contents: &str) -> (u8, String) {
+    let text = &file_contents[statement.byte_range()];
+    let rest = text.trim_start_matches("use").trim_start();
+    let (group, name) = match rest.split_once(char::is_whitespace) {
+        Some(("function", name)) => (1, name),
+        Some(("const", name)) => (2, name),
+        _ => (0, rest),
+    };
+    (group, name.trim().trim_start_matches('\\').to_lowercase())
+}
+
+/// Edits that sort runs of `use` statements that aren't sorted, and what they replace.
+fn sorted_imports(node: &Node, file_contents: &str, edits: &mut Vec<(ByteRange<usize>, TextEdit)>) {
+    let mut cursor = node.walk();
+    let children: Vec<Node> = node.named_children(&mut cursor).collect();
+    for run in children.chunk_by(|a, b| {
+        a.kind() == "namespace_use_declaration" && b.kind() == "namespace_use_declaration"
+    }) {
+        let (Some(first), Some(last)) = (run.first(), run.last()) else {
+            continue;
+        };
+        if run.len() < 2 || run.iter().any(|statement| statement.has_error()) {
+            continue;
+        }
+
+        let mut sorted = run.to_vec();
+        sorted.sort_by_cached_key(|statement| import_key(statement, file_contents));
+        if sorted == run {
+            continue;
+        }
+        let line_start = file_contents[..first.start_byte()]
+            .rfind('\n')
+            .map_or(0, |i| i + 1);
+        let indent = &file_contents[line_start..first.start_byte()];
+        let separator = match indent.trim().is_empty() {
+            true => format!("\n{}", indent),
+            false => "\n".to_string(),
+        };
+        let new_text = sorted
+            .iter()
+            .map(|statement| &file_contents[statement.byte_range()])
+            .collect::<Vec<_>>()
+            .join(&separator);
+        edits.push((
+            first.start_byte()..last.end_byte(),
+            TextEdit {
+                range: Range {
+                    start: to_position(&first.start_position()),
+                    end: to_position(&last.end_position()),
+                },
+                new_text,
+            },
+        ));
+    }
+
+    for child in children {
+        if matches!(child.kind(), "namespace_definition" | "compound_statement") {
+            sorted_imports(&child, file_contents, edits);
+        }
+    }
+}
+
+fn has_inline_html(node: &Node) -> bool {
+    if node.kind() == "text" {
+        return true;
+    }
+
+    let mut cursor = node.walk();
+    let html = node
+        .children(&mut cursor)
+        .any(|child| has_inline_html(&child));
+    html
+}
+
+/**
+ * Format a file according to PSR-12: indentation, where braces go, spaces around operators and
+ * after commas and keywords, and sorted `use` statements.
+ *
+ * Formatting works on the parse tree, so anything that parsed is formatted even if other parts
+ * of the file didn't. Only whitespace between tokens is changed, except for sorting `use`
+ * statements, and whitespace next to syntax errors is left alone. Files with HTML in them aren't
+ * formatted at all, since their indentation belongs to the HTML.
+ */
+pub fn format(root_node: &Node, file_contents: &str, options: &FormattingOptions) -> Vec<TextEdit> {
+    if has_inline_html(root_node) {
+        return vec![];
+    }
+
+    let indent = match options.insert_spaces {
+        true => " ".repeat(options.tab_size as usize),
+        false => "\t".to_string(),
+    };
+    let newline = match file_contents.contains("\r\n") {
+        true => "\r\n",
+        false => "\n",
+    };
+
+    let mut imports = vec![];
+    sorted_imports(root_node, file_contents, &mut imports);
+    let in_imports = |start: usize, end: usize| {
+        imports
+            .iter()
+            .any(|(range, _)| range.start <= start && end <= range.end)
+    };
+    let mut edits: Vec<TextEdit> = imports.iter().map(|(_, edit)| edit.clone()).collect();
+
+    let mut found = vec![];
+    tokens(*root_node, false, &mut found);
+    // opening brackets, and the indentation of the line each one is on
+    let mut brackets: Vec<(&str, usize)> = vec![];
+    let mut level = 0;
+    for (i, token) in found.iter().enumerate() {
+        let node = token.node;
+        let mut reindented = false;
+        if let Some(prev) = i.checked_sub(1).map(|i| found[i]) {
+            let (start, end) = (prev.node.end_byte(), node.start_byte());
+            let existing = &file_contents[start..end];
+            let rule = match existing.chars().all(char::is_whitespace)
+                && !prev.in_error
+                && !token.in_error
+            {
+                true => gap(&prev.node, &node, existing),
+                false => Gap::Keep,
+            };
+
+            let newlines = existing.matches('\n').count();
+            let lines = match rule {
+                Gap::Keep | Gap::Inline(_) => 0,
+                Gap::Wrap(_) => newlines.min(2),
+                Gap::Line(blank) => newlines.clamp(1, blank + 1),
+            };
+            if lines > 0 || (matches!(rule, Gap::Keep) && newlines > 0) {
+                let opening = brackets.iter().rfind(|(open, _)| closes(open, node.kind()));
+                level = match (opening, brackets.last()) {
+                    (Some((_, line_level)), _) => *line_level,
+                    (None, Some((_, line_level))) => line_level + 1 + continuation(&node),
+                    (None, None) => continuation(&node),
+                };
+            }
+
+            let replacement = match rule {
+                Gap::Keep => None,
+                _ if lines > 0 => {
+                    reindented = true;
+                    Some(newline.repeat(lines) + &indent.repeat(level))
+                }
+                Gap::Inline(text) | Gap::Wrap(text) => {
+                    let prev_text = &file_contents[prev.node.byte_range()];
+                    let next_text = &file_contents[node.byte_range()];
+                    match !existing.is_empty() && would_merge(prev_text, next_text) {
+                        true => Some(" ".to_string()),
+                        false => Some(text.to_string()),
+                    }
+                }
+                Gap::Line(_) => None,
+            };
+            if let Some(replacement) = replacement {
+                if replacement != existing && !in_imports(start, end) {
+                    edits.push(TextEdit {
+                        range: Range {
+                            start: to_position(&prev.node.end_position()),
+                            end: to_position(&node.start_position()),
+                        },
+                        new_text: replacement,
+                    });
+                }
+            }
+        }
+
+        // the stars of doc comments line up under the first one
+        if reindented && node.kind() == "comment" && !in_imports(node.start_byte(), node.end_byte())
+        {
+            let star_indent = indent.repeat(level) + " ";
+            let text = &file_contents[node.byte_range()];
+            for (row, line) in text.split('\n').enumerate().skip(1) {
+                let stripped = line.trim_start();
+                let leading = &line[..line.len() - stripped.len()];
+                if stripped.starts_with('*') && leading != star_indent {
+                    let line = (node.start_position().row + row) as u32;
+                    edits.push(TextEdit {
+                        range: Range {
+                            start: Position { line, character: 0 },
+                            end: Position {
+                                line,
+                                character: leading.len() as u32,
+                            },
+                        },
+                        new_text: star_indent.clone(),
+                    });
+                }
+            }
+        }
+
+        match node.kind() {
+            "{" | "(" | "[" | "#[" => brackets.push((node.kind(), level)),
+            // brackets that were never closed are closed along with the ones they're in
+            "}" | ")" | "]" => {
+                if let Some(opening) = brackets
+                    .iter()
+                    .rposition(|(open, _)| closes(open, node.kind()))
+                {
+                    brackets.truncate(opening);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // files end with exactly one newline
+    if let Some(last) = found.last() {
+        let rest = &file_contents[last.node.end_byte()..];
+        if last.node.kind() != "?>"
+            && !last.in_error
+            && rest.chars().all(char::is_whitespace)
+            && rest != newline
+        {
+            let end = file_contents.lines().count().max(1) as u32;
+            edits.push(TextEdit {
+                range: Range {
+                    start: to_position(&last.node.end_position()),
+                    end: Position {
+                        line: end,
+                        character: 0,
+                    },
+                },
+                new_text: newline.to_string(),
+            });
+        }
+    }
+
+    edits.sort_by_key(|edit| (edit.range.start.line, edit.range.start.character));
+    edits
+}
+
+/// The innermost statement or member of a block that a node is in, including the node itself.
+fn item_of<'tree>(node: Node<'tree>) -> Option<Node<'tree>> {
+    let mut node = node;
+    loop {
+        let parent = node.parent()?;
+        if BLOCKS.contains(&parent.kind()) && is_item(&parent, &node) {
+            return Some(node);
+        }
+        node = parent;
+    }
+}
+
+fn depth(node: &Node) -> usize {
+    let mut depth = 0;
+    let mut current = node.parent();
+    while let Some(parent) = current {
+        depth += 1;
+        current = parent.parent();
+    }
+    depth
+}
+
+fn contains(outer: &Node, inner: &Node) -> bool {
+    outer.start_byte() <= inner.start_byte() && inner.end_byte() <= outer.end_byte()
+}
+
+/// The first and last statements or members of one block that a range is in.
+fn items_in<'tree>(root_node: &Node<'tree>, range: &Range) -> Option<(Node<'tree>, Node<'tree>)> {
+    let at = |position: &Position| {
+        let point = to_point(position);
+        root_node
+            .descendant_for_point_range(point, point)
+            .and_then(item_of)
+    };
+    let (mut first, mut last) = (at(&range.start)?, at(&range.end)?);
+    while first.parent() != last.parent() {
+        if contains(&first, &last) {
+            return Some((first, first));
+        }
+        if contains(&last, &first) {
+            return Some((last, last));
+        }
+        match depth(&first) >= depth(&last) {
+            true => first = item_of(first.parent()?)?,
+            false => last = item_of(last.parent()?)?,
+        }
+    }
+
+    Some((first, last))
+}
+
+/**
+ * Format the statements and members that a range is in, as [`format`] would. Edits that end
+ * where the first of them starts are kept, so that its indentation is fixed too.
+ */
+pub fn format_range(
+    root_node: &Node,
+    file_contents: &str,
+    options: &FormattingOptions,
+    range: &Range,
+) -> Vec<TextEdit> {
+    let (start, end) = match items_in(root_node, range) {
+        Some((first, last)) => (
+            to_position(&first.start_position()).min(range.start),
+            to_position(&last.end_position()).max(range.end),
+        ),
+        None => (range.start, range.end),
+    };
+
+    format(root_node, file_contents, options)
+        .into_iter()
+        .filter(|edit| edit.range.end >= start && edit.range.start < end)
+        .collect()
+}
+
+/**
+ * The part of a formatting edit that indents the line it ends on, if that line was already
+ * there. Edits that would add or join lines aren't kept.
+ */
+fn indentation(edit: &TextEdit, file_contents: &str) -> Option<TextEdit> {
+    let end = edit.range.end;
+    let line = file_contents.lines().nth(end.line as usize)?;
+    let leading = line.get(..end.character as usize)?;
+    if !leading.trim().is_empty() {
+        return None;
+    }
+    // the stars of doc comments are reindented on their own already
+    let is_star = edit.range.start.line == end.line && edit.range.start.character == 0;
+    let new_indentation = match edit.new_text.rsplit_once('\n') {
+        Some((_, indentation)) if end.line > edit.range.start.line => indentation,
+        _ if is_star => &edit.new_text,
+        _ => return None,
+    };
+
+    (new_indentation != leading).then(|| TextEdit {
+        range: Range {
+            start: Position {
+                line: end.line,
+                character: 0,
+            },
+            end,
+        },
+        new_text: new_indentation.to_string(),
+    })
+}
+
+/**
+ * How many levels a line with nothing on it yet is indented: one for every line that opens a
+ * block or list that the line is in.
+ */
+fn empty_line_level(root_node: &Node, position: &Position) -> usize {
+    let point = to_point(position);
+    let mut rows = vec![];
+    let mut current = root_node.descendant_for_point_range(point, point);
+    // a `case` with nothing after its colon yet ends before the line does
+    if let Some(node) = current {
+        let mut cursor = node.walk();
+        let before = node
+            .named_children(&mut cursor)
+            .take_while(|child| child.end_position() <= point)
+            .last();
+        if let Some(body) = before.filter(|body| COLON_BODIES.contains(&body.kind())) {
+            rows.push(body.start_position().row);
+        }
+    }
+    while let Some(node) = current {
+        let unclosed = node
+            .child(node.child_count().saturating_sub(1))
+            .is_some_and(|last| last.is_missing());
+        let is_open = node.start_position() < point && (point < node.end_position() || unclosed);
+        let opens = match node.kind() {
+            "program" | "ERROR" => false,
+            // statements after a `case` are indented from it
+            kind if COLON_BODIES.contains(&kind) => {
+                let mut cursor = node.walk();
+                let colon = node.children(&mut cursor).find(|child| child.kind() == ":");
+                colon.is_some_and(|colon| colon.end_position() <= point)
+            }
+            kind => BLOCKS.contains(&kind) || LISTS.contains(&kind),
+        };
+        if opens && is_open && !rows.contains(&node.start_position().row) {
+            rows.push(node.start_position().row);
+        }
+        current = node.parent();
+    }
+
+    rows.len()
+}
+
+/// Modifiers that can come before `function` in the declaration of a function or method.
+const FUNCTION_MODIFIERS: &[&str] = &[
+    "public",
+    "protected",
+    "private",
+    "static",
+    "abstract",
+    "final",
+];
+
+/// Where a keyword last is in some code on its own, rather than as part of a longer name.
+fn find_word(code: &str, word: &str) -> Option<usize> {
+    let is_name = |c: char| c.is_alphanumeric() || c == '_' || c == '$' || c == '\\';
+    code.match_indices(word)
+        .map(|(at, _)| at)
+        .filter(|at| {
+            !code[..*at].ends_with(is_name) && !code[at + word.len()..].starts_with(is_name)
+        })
+        .last()
+}
+
+/// What closes the brackets left open in some code, innermost first.
+fn unclosed(code: &str) -> String {
+    let mut open = vec![];
+    for c in code.chars() {
+        match c {
+            '(' => open.push(')'),
+            '[' => open.push(']'),
+            ')' | ']' if open.last() == Some(&c) => {
+                open.pop();
+            }
+            _ => {}
+        }
+    }
+    open.iter().rev().collect()
+}
+
+/**
+ * What comes after the `}` that closes a block opened at the end of a line, when the line is the
+ * header of one of the blocks we close: a `catch` for `try`, and for `match` and closures, `;` if
+ * they end the statement, or the brackets they're in otherwise.
+ */
+fn block_end(header: &str, auto_close: &AutoClose) -> Option<String> {
+    let expression_end = |start: usize| match unclosed(&header[..start]) {
+        open if open.is_empty() => ";".to_string(),
+        open => open,
+    };
+
+    if header.ends_with("try") && find_word(header, "try") == Some(header.len() - 3) {
+        return auto_close
+            .r#try
+            .then(|| " catch (\\Exception $e) {\n}".to_string());
+    }
+    if let Some(at) = find_word(header, "match") {
+        let condition = header[at + "match".len()..].trim_start();
+        if condition.starts_with('(') && condition.ends_with(')') && unclosed(condition).is_empty()
+        {
+            return auto_close.r#match.then(|| expression_end(at));
+        }
+    }
+    if let Some(at) = find_word(header, "function") {
+        let signature = &header[at..];
+        let has_parameters = signature.rsplit_once(')').is_some_and(|(_, return_type)| {
+            return_type.is_empty() || return_type.trim_start().starts_with(':')
+        });
+        if has_parameters && unclosed(signature).is_empty() {
+            let is_declaration = header[..at]
+                .split_whitespace()
+                .all(|word| FUNCTION_MODIFIERS.contains(&word));
+            return auto_close.functions.then(|| match is_declaration {
+                true => String::new(),
+                false => expression_end(at),
+            });
+        }
+    }
+    None
+}
+
+/**
+ * Reindent as a character is typed: the block that a `}` closes, the statement that a `;` ends,
+ * or the line that a newline starts. Only indentation is changed, so nothing moves around what
+ * is being typed, except when a `{` opens a `match`, `try` or function at the end of a line and
+ * nothing closes it yet. Then its closing brace and what has to follow that are added, as far as
+ * `auto_close` allows.
+ */
+pub fn format_on_type(
+    root_node: &Node,
+    file_contents: &str,
+    options: &FormattingOptions,
+    auto_close: &AutoClose,
+    position: &Position,
+    typed: &str,
+) -> Vec<TextEdit> {
+    let indentations = |edits: Vec<TextEdit>| {
+        edits
+            .iter()
+            .filter_map(|edit| indentation(edit, file_contents))
+            .collect()
+    };
+    let line_start = Position {
+        line: position.line,
+        character: 0,
+    };
+    let indent = match options.insert_spaces {
+        true => " ".repeat(options.tab_size as usize),
+        false => "\t".to_string(),
+    };
+
+    match typed {
+        "{" => {
+            let Some(line) = file_contents.lines().nth(position.line as usize) else {
+                return vec![];
+            };
+            let at = position.character as usize;
+            let (Some(header), Some(rest)) = (
+                line.get(..at).and_then(|before| before.strip_suffix('{')),
+                line.get(at..),
+            ) else {
+                return vec![];
+            };
+            // a file without errors has a `}` for the brace somewhere already
+            if !rest.trim().is_empty() || !root_node.has_error() {
+                return vec![];
+            }
+            let Some(end) = block_end(header.trim_end(), auto_close) else {
+                return vec![];
+            };
+
+            let leading = &line[..line.len() - line.trim_start().len()];
+            vec![TextEdit {
+                range: Range {
+                    start: *position,
+                    end: *position,
+                },
+                new_text: format!(
+                    "\n{}{}\n{}}}{}",
+                    leading,
+                    indent,
+                    leading,
+                    end.replace('\n', &format!("\n{}", leading))
+                ),
+            }]
+        }
+        "}" => {
+            let before = Position {
+                line: position.line,
+                character: position.character.saturating_sub(1),
+            };
+            let point = to_point(&before);
+            let Some(block) = root_node
+                .descendant_for_point_range(point, point)
+                .filter(|node| node.kind() == "}")
+                .and_then(|node| node.parent())
+            else {
+                return vec![];
+            };
+            let range = Range {
+                start: to_position(&block.start_position()),
+                end: *position,
+            };
+            indentations(format_range(root_node, file_contents, options, &range))
+        }
+        ";" => {
+            let range = Range {
+                start: line_start,
+                end: *position,
+            };
+            indentations(format_range(root_node, file_contents, options, &range))
+        }
+        "\n" => {
+            let Some(line) = file_contents.lines().nth(position.line as usize) else {
+                return vec![];
+            };
+            let leading = &line[..line.len() - line.trim_start().len()];
+            let first = Position {
+                line: position.line,
+                character: leading.len() as u32,
+            };
+            if leading.len() < line.trim_end_matches('\r').len() {
+                return format(root_node, file_contents, options)
+                    .iter()
+                    .filter(|edit| edit.range.end == first)
+                    .filter_map(|edit| indentation(edit, file_contents))
+                    .collect();
+            }
+
+            let new_indentation = indent.repeat(empty_line_level(root_node, position));
+            match new_indentation == leading {
+                true => vec![],
+                false => vec![TextEdit {
+                    range: Range {
+                        start: line_start,
+                        end: first,
+                    },
+                    new_text: new_indentation,
+                }],
+            }
+        }
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::*;
+
+    use super::{format, format_on_type, format_range};
+    use crate::config::AutoClose;
+    use crate::file::php_parser;
+
+    fn options() -> FormattingOptions {
+        FormattingOptions {
+            tab_size: 4,
+            insert_spaces: true,
+            ..FormattingOptions::default()
+        }
+    }
+
+    fn offset(text: &str, position: &Position) -> usize {
+        let line_start: usize = text
+            .split_inclusive('\n')
+            .take(position.line as usize)
+            .map(str::len)
+            .sum();
+        (line_start + position.character as usize).min(text.len())
+    }
+
+    fn apply(text: &str, edits: &[TextEdit]) -> String {
+        let mut result = text.to_string();
+        for edit in edits.iter().rev() {
+            let range = offset(text, &edit.range.start)..offset(text, &edit.range.end);
+            result.replace_range(range, &edit.new_text);
+        }
+        result
+    }
+
+    #[test]
+    fn test_format() {
+        let source = "<?php
+namespace App;
+
+use Zed\\Thing;
+use function App\\helper;
+use Alpha\\Other;
+/**
+* Greets.
+*/
+class Greeter extends Base implements Named {
+  const   LOUD=true;
+
+
+  public function greet( string $name,int ...$rest ) : ?string {
+     if($name==='')
+     {
+        return null;
+     } else{ $name = strtoupper( $name ); }
+     $items=[ 'a'=>1,'b'  =>  2 ];
+     foreach($items as $key=>&$value){ $value++; }
+     $f = function($x)use($name){ return !$x; };
+        return $this
+        ->prefix()  .  $name;
+  }
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let edits = format(&tree.root_node(), source, &options());
+        assert_eq!(
+            "<?php
+namespace App;
+
+use Alpha\\Other;
+use Zed\\Thing;
+use function App\\helper;
+/**
+ * Greets.
+ */
+class Greeter extends Base implements Named
+{
+    const LOUD = true;
+
+    public function greet(string $name, int ...$rest): ?string
+    {
+        if ($name === '') {
+            return null;
+        } else {
+            $name = strtoupper($name);
+        }
+        $items = ['a' => 1, 'b' => 2];
+        foreach ($items as $key => &$value) {
+            $value++;
+        }
+        $f = function ($x) use ($name) {
+            return !$x;
+        };
+        return $this
+            ->prefix() . $name;
+    }
+}
+",
+            apply(source, &edits)
+        );
+    }
+
+    #[test]
+    fn test_format_invalid_file() {
+        let source = "<?php
+function f($a){
+  $b=[
+  1,
+  2,
+  ];
+  $c = ;
+    return $a+$b;
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let edits = format(&tree.root_node(), source, &options());
+        for edit in &edits {
+            let range = offset(source, &edit.range.start)..offset(source, &edit.range.end);
+            assert!(source[range].trim().is_empty());
+            assert!(edit.new_text.trim().is_empty());
+        }
+        let formatted = apply(source, &edits);
+        assert!(formatted.contains("\n    $b = [\n        1,\n        2,\n    ];\n"));
+        assert!(formatted.contains("\n    return $a + $b;\n"));
+    }
+
+    #[test]
+    fn test_format_range() {
+        let source = "<?php
+function f($a){
+  $b=$a+1;
+  if($b){ return $b*2; }
+  return  $a;
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        // from inside `$b+1` to inside `return $b*2`
+        let range = Range {
+            start: Position {
+                line: 2,
+                character: 6,
+            },
+            end: Position {
+                line: 3,
+                character: 16,
+            },
+        };
+        let edits = format_range(&tree.root_node(), source, &options(), &range);
+        assert_eq!(
+            "<?php
+function f($a){
+    $b = $a + 1;
+    if ($b) {
+        return $b * 2;
+    }
+  return  $a;
+}
+",
+            apply(source, &edits)
+        );
+    }
+
+    #[test]
+    fn test_format_on_type() {
+        let at = |line, character| Position { line, character };
+        let on_type = |source: &str, position: Position, typed: &str| {
+            let tree = php_parser().parse(source, None).unwrap();
+            let edits = format_on_type(
+                &tree.root_node(),
+                source,
+                &options(),
+                &AutoClose::default(),
+                &position,
+                typed,
+            );
+            apply(source, &edits)
+        };
+
+        assert_eq!(
+            "<?php\nfunction f() {\n    if ($a) {\n        b();\n    }\n}\n",
+            on_type(
+                "<?php\nfunction f() {\n  if ($a) {\nb();\n      }\n}\n",
+                at(4, 7),
+                "}"
+            )
+        );
+        // spacing is left alone
+        assert_eq!(
+            "<?php\nif ($a) {\n    b(1,2);\n}\n",
+            on_type("<?php\nif ($a) {\nb(1,2);\n}\n", at(2, 7), ";")
+        );
+        assert_eq!(
+            "<?php\nswitch ($a) {\n    case 1:\n        \n}\n",
+            on_type("<?php\nswitch ($a) {\n    case 1:\n\n}\n", at(3, 0), "\n")
+        );
+        assert_eq!(
+            "<?php\nclass A {\n    function f() {\n    }\n}\n",
+            on_type(
+                "<?php\nclass A {\n    function f() {\n}\n}\n",
+                at(3, 0),
+                "\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_auto_close() {
+        let at = |line, character| Position { line, character };
+        let on_type = |source: &str, position: Position, auto_close: AutoClose| {
+            let tree = php_parser().parse(source, None).unwrap();
+            let edits = format_on_type(
+                &tree.root_node(),
+                source,
+                &options(),
+                &auto_close,
+                &position,
+                "{",
+            );
+            apply(source, &edits)
+        };
+        let all = AutoClose::default();
+
+        assert_eq!(
+            "<?php\nclass A {\n    public function f(): int {\n        \n    }\n}\n",
+            on_type(
+                "<?php\nclass A {\n    public function f(): int {\n}\n",
+                at(2, 30),
+                all
+            )
+        );
+        assert_eq!(
+            "<?php\ntry {\n    \n} catch (\\Exception $e) {\n}\n",
+            on_type("<?php\ntry {\n", at(1, 5), all)
+        );
+        assert_eq!(
+            "<?php\n$a = match ($b) {\n    \n};\n",
+            on_type("<?php\n$a = match ($b) {\n", at(1, 17), all)
+        );
+        assert_eq!(
+            "<?php\nusort($a, function ($x, $y) use ($z) {\n    \n})\n",
+            on_type(
+                "<?php\nusort($a, function ($x, $y) use ($z) {\n",
+                at(1, 38),
+                all
+            )
+        );
+        // closed already, by the client or by hand
+        assert_eq!(
+            "<?php\nfunction f() {}\n",
+            on_type("<?php\nfunction f() {}\n", at(1, 14), all)
+        );
+        assert_eq!(
+            "<?php\nfunction f() {\n}\n",
+            on_type("<?php\nfunction f() {\n}\n", at(1, 14), all)
+        );
+        assert_eq!(
+            "<?php\nif ($a) {\n",
+            on_type("<?php\nif ($a) {\n", at(1, 9), all)
+        );
+        let no_try = AutoClose {
+            r#try: false,
+            ..AutoClose::default()
+        };
+        assert_eq!(
+            "<?php\ntry {\n",
+            on_type("<?php\ntry {\n", at(1, 5), no_try)
+        );
+    }
+}
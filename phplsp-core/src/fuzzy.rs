@@ -0,0 +1,123 @@
+/// Whether a character starts a new "hump" in a name like `HttpRequestFactory` or `get_user`.
+fn is_word_start(previous: Option<char>, current: char) -> bool {
+    match previous {
+        None => true,
+        Some(previous) => {
+            (previous.is_lowercase() && current.is_uppercase())
+                || (!previous.is_alphanumeric() && current.is_alphanumeric())
+        }
+    }
+}
+
+/**
+ * Score how well a pattern matches a candidate, ignoring case.
+ *
+ * Every pattern character has to appear in the candidate in order. Matches at the start of a
+ * camel-hump or word and runs of consecutive matches are preferred, so `HRF` and `httpreq` both
+ * rank `HttpRequestFactory` highly. Returns `None` if there is no match; higher scores are better.
+ */
+pub fn fuzzy_score(pattern: &str, candidate: &str) -> Option<i64> {
+    let pattern: Vec<char> = pattern.chars().flat_map(char::to_lowercase).collect();
+    if pattern.is_empty() {
+        return Some(0);
+    }
+
+    let mut score = 0;
+    let mut pattern_index = 0;
+    let mut previous = None;
+    let mut previous_matched = false;
+
+    for (i, c) in candidate.chars().enumerate() {
+        if pattern_index < pattern.len() && c.to_lowercase().eq(Some(pattern[pattern_index])) {
+            score += 1;
+            if is_word_start(previous, c) {
+                score += 10;
+            }
+            if previous_matched {
+                score += 5;
+            }
+            if i == 0 {
+                score += 5;
+            }
+
+            pattern_index += 1;
+            previous_matched = true;
+        } else {
+            previous_matched = false;
+        }
+
+        previous = Some(c);
+    }
+
+    if pattern_index < pattern.len() {
+        return None;
+    }
+
+    // shorter candidates are closer to what was asked for
+    Some(score * 100 - candidate.chars().count() as i64)
+}
+
+/**
+ * How many characters have to be inserted, removed or replaced to turn one name into another,
+ * ignoring case, for telling typos of a name apart from other names.
+ */
+pub fn edit_distance(from: &str, to: &str) -> usize {
+    let from: Vec<char> = from.chars().flat_map(char::to_lowercase).collect();
+    let to: Vec<char> = to.chars().flat_map(char::to_lowercase).collect();
+    // distances from the start of `from` to every start of `to`, a row at a time
+    let mut row: Vec<usize> = (0..=to.len()).collect();
+    for (i, a) in from.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, b) in to.iter().enumerate() {
+            let replaced = diagonal + usize::from(a != b);
+            diagonal = row[j + 1];
+            row[j + 1] = replaced.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[to.len()]
+}
+
+#[cfg(test)]
+mod test {
+    use super::{edit_distance, fuzzy_score};
+
+    #[test]
+    fn test_no_match() {
+        assert_eq!(None, fuzzy_score("xyz", "UserController"));
+        assert_eq!(None, fuzzy_score("cu", "UserController"));
+    }
+
+    #[test]
+    fn test_camel_humps_rank_higher() {
+        let humps = fuzzy_score("uc", "UserController").unwrap();
+        let scattered = fuzzy_score("uc", "Launcher").unwrap();
+        assert!(humps > scattered);
+    }
+
+    #[test]
+    fn test_case_insensitive() {
+        assert!(fuzzy_score("USERCON", "UserController").is_some());
+        assert!(fuzzy_score("get_user", "getUser").is_none());
+        assert!(fuzzy_score("getuser", "get_user").is_some());
+    }
+
+    #[test]
+    fn test_exact_prefix_ranks_higher() {
+        let prefix = fuzzy_score("user", "User").unwrap();
+        let longer = fuzzy_score("user", "UserRepository").unwrap();
+        let inside = fuzzy_score("user", "CurrentUser").unwrap();
+        assert!(prefix > longer);
+        assert!(longer > inside);
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(0, edit_distance("Logger", "logger"));
+        // swapping two letters is two replacements
+        assert_eq!(2, edit_distance("App\\Modles", "App\\Models"));
+        assert_eq!(1, edit_distance("Loger", "Logger"));
+        assert_eq!(3, edit_distance("", "abc"));
+        assert_eq!(3, edit_distance("kitten", "sitting"));
+    }
+}
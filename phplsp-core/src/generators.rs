@@ -0,0 +1,204 @@
+//! Calls to functions that yield, made as if they still returned a value: their body only runs as
+//! what they return is iterated over, so a call that's thrown away or used as a value does nothing.
+
+use lsp_types::*;
+use tree_sitter::Node;
+
+use crate::file::to_range;
+use crate::index::WorkspaceIndex;
+use crate::resolve::Resolver;
+use crate::symbols::FileSymbols;
+use crate::types::TypeInference;
+
+const CALLS: &[&str] = &[
+    "function_call_expression",
+    "member_call_expression",
+    "nullsafe_member_call_expression",
+    "scoped_call_expression",
+];
+
+/// Operators that compare or combine a `Generator` as an object, which is what they're for.
+const OBJECT_OPERATORS: &[&str] = &["===", "!==", "??", "instanceof"];
+
+/// The expression a call is part of, past any parentheses around it.
+fn outer<'tree>(call: &Node<'tree>) -> (Node<'tree>, Option<Node<'tree>>) {
+    let mut node = *call;
+    let mut parent = node.parent();
+    while let Some(parenthesized) = parent.filter(|p| p.kind() == "parenthesized_expression") {
+        node = parenthesized;
+        parent = parenthesized.parent();
+    }
+    (node, parent)
+}
+
+/// How a call is used, if it's used in a way that a `Generator` is no good for.
+enum Misuse {
+    Discarded,
+    Value,
+}
+
+fn misuse(call: &Node, file_contents: &str) -> Option<Misuse> {
+    let (node, parent) = outer(call);
+    let parent = parent?;
+    match parent.kind() {
+        "expression_statement" => Some(Misuse::Discarded),
+        "binary_expression" => {
+            let operator = parent.child_by_field_name("operator")?;
+            let operator = &file_contents[operator.byte_range()];
+            (!OBJECT_OPERATORS
+                .iter()
+                .any(|object| object.eq_ignore_ascii_case(operator)))
+            .then_some(Misuse::Value)
+        }
+        "unary_op_expression" | "echo_statement" | "print_intrinsic" | "encapsed_string" => {
+            Some(Misuse::Value)
+        }
+        "subscript_expression" => parent
+            .named_child(0)
+            .is_some_and(|subscripted| subscripted.id() == node.id())
+            .then_some(Misuse::Value),
+        // a `Generator` is an object, so it's always true
+        "if_statement" | "else_if_clause" | "while_statement" | "do_statement" => parent
+            .child_by_field_name("condition")
+            .is_some_and(|condition| condition.id() == node.id())
+            .then_some(Misuse::Value),
+        _ => None,
+    }
+}
+
+fn check(
+    node: &Node,
+    file_contents: &str,
+    types: &TypeInference,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    if CALLS.contains(&node.kind()) {
+        let generator = types.callee(node).filter(|callee| callee.generator);
+        if let Some((callee, misuse)) =
+            generator.and_then(|callee| Some((callee, misuse(node, file_contents)?)))
+        {
+            let message = match misuse {
+                Misuse::Discarded => format!(
+                    "`{}()` yields, so calling it only makes a `Generator` and runs none of its body; iterate over what it returns, with `foreach` or `iterator_to_array()`",
+                    callee.short_name()
+                ),
+                Misuse::Value => format!(
+                    "`{}()` yields, so it returns a `Generator` rather than a value; iterate over it, or use its `getReturn()` once it's done",
+                    callee.short_name()
+                ),
+            };
+            diagnostics.push(Diagnostic {
+                range: to_range(&node.range()),
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(NumberOrString::String("generator".to_string())),
+                source: Some("phplsp".to_string()),
+                message,
+                ..Diagnostic::default()
+            });
+        }
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        check(&child, file_contents, types, diagnostics);
+    }
+}
+
+/**
+ * Report calls to functions and methods whose body yields that throw away what they return, or
+ * use it as a plain value, like in arithmetic, `echo` or a condition. Functions that yield return
+ * a `Generator`, which runs their body only as it's iterated over, so these usually mean the
+ * function became a generator after the call was written.
+ */
+pub fn generator_diagnostics(
+    root_node: &Node,
+    file_contents: &str,
+    symbols: &FileSymbols,
+    index: &WorkspaceIndex,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let resolver = Resolver::new(file_contents, symbols, index);
+    let types = TypeInference::new(file_contents, &resolver);
+    check(root_node, file_contents, &types, diagnostics);
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::*;
+
+    use super::generator_diagnostics;
+    use crate::file::php_parser;
+    use crate::index::WorkspaceIndex;
+    use crate::symbols::file_symbols;
+
+    #[test]
+    fn test_generator_diagnostics() {
+        let source = "<?php
+function rows() {
+    yield 1;
+}
+function total() {
+    return 1;
+}
+class Import {
+    public function run() {
+        $f = fn() => yield 2;
+        return 3;
+    }
+    public function lines() {
+        yield from rows();
+    }
+}
+rows();
+total();
+echo (rows()) . 'x';
+foreach (rows() as $row) {
+}
+$rows = rows();
+if (rows() === $rows) {
+}
+$import = new Import();
+$import->run();
+$import->lines();
+$n = 1 + $import->lines()[0];
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(&Url::parse("file:///a.php").unwrap(), symbols.clone());
+        let mut diagnostics = vec![];
+        generator_diagnostics(
+            &tree.root_node(),
+            source,
+            &symbols,
+            &index,
+            &mut diagnostics,
+        );
+
+        let found: Vec<(Position, String)> = diagnostics
+            .into_iter()
+            .map(|diagnostic| (diagnostic.range.start, diagnostic.message))
+            .collect();
+        let discarded = |name| {
+            format!(
+                "`{}()` yields, so calling it only makes a `Generator` and runs none of its body; iterate over what it returns, with `foreach` or `iterator_to_array()`",
+                name
+            )
+        };
+        let value = |name| {
+            format!(
+                "`{}()` yields, so it returns a `Generator` rather than a value; iterate over it, or use its `getReturn()` once it's done",
+                name
+            )
+        };
+        assert_eq!(
+            vec![
+                (Position::new(16, 0), discarded("rows")),
+                (Position::new(18, 6), value("rows")),
+                (Position::new(26, 0), discarded("lines")),
+                (Position::new(27, 9), value("lines")),
+            ],
+            found
+        );
+    }
+}
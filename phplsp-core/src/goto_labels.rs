@@ -0,0 +1,199 @@
+use lsp_types::*;
+use tree_sitter::Node;
+
+use std::collections::HashMap;
+
+use crate::file::to_range;
+
+/// Node kinds that have their own labels. Code outside of any of them shares the file's.
+const FUNCTIONS: &[&str] = &[
+    "function_definition",
+    "method_declaration",
+    "anonymous_function",
+];
+
+/// Node kinds that `goto` can't jump into from outside.
+const LOOPS_AND_SWITCHES: &[&str] = &[
+    "for_statement",
+    "foreach_statement",
+    "while_statement",
+    "do_statement",
+    "switch_statement",
+];
+
+fn diagnostic(node: &Node, severity: DiagnosticSeverity, message: String) -> Diagnostic {
+    Diagnostic {
+        range: to_range(&node.range()),
+        severity: Some(severity),
+        code: Some(NumberOrString::String("goto".to_string())),
+        source: Some("phplsp".to_string()),
+        message,
+        ..Diagnostic::default()
+    }
+}
+
+/// Labels and `goto`s of one scope, and the functions in it, which are scopes of their own.
+struct Scope<'tree> {
+    labels: Vec<Node<'tree>>,
+    gotos: Vec<Node<'tree>>,
+    functions: Vec<Node<'tree>>,
+}
+
+impl<'tree> Scope<'tree> {
+    fn collect(&mut self, node: &Node<'tree>) {
+        match node.kind() {
+            "named_label_statement" => self.labels.push(*node),
+            "goto_statement" => self.gotos.push(*node),
+            kind if FUNCTIONS.contains(&kind) => {
+                self.functions.push(*node);
+                return;
+            }
+            _ => {}
+        }
+
+        let mut cursor = node.walk();
+        for child in node.named_children(&mut cursor) {
+            self.collect(&child);
+        }
+    }
+}
+
+/// The name of a label or what a `goto` jumps to.
+fn label_name<'tree>(node: &Node<'tree>) -> Option<Node<'tree>> {
+    let mut cursor = node.walk();
+    let name = node
+        .named_children(&mut cursor)
+        .find(|child| child.kind() == "name");
+    name
+}
+
+fn contains(outer: &Node, inner: &Node) -> bool {
+    outer.start_byte() <= inner.start_byte() && inner.end_byte() <= outer.end_byte()
+}
+
+/**
+ * Check the `goto`s of every function, and of the file outside of them, against their labels:
+ * jumps to labels that don't exist or into a loop or `switch` are errors, as are labels declared
+ * twice, and labels that nothing jumps to are hinted at.
+ */
+pub fn goto_labels(root_node: &Node, file_contents: &str, diagnostics: &mut Vec<Diagnostic>) {
+    let mut scopes = vec![*root_node];
+    while let Some(node) = scopes.pop() {
+        let mut scope = Scope {
+            labels: vec![],
+            gotos: vec![],
+            functions: vec![],
+        };
+        let mut cursor = node.walk();
+        for child in node.named_children(&mut cursor) {
+            scope.collect(&child);
+        }
+        scopes.extend(scope.functions);
+
+        let mut labels: HashMap<&str, Node> = HashMap::new();
+        for label in &scope.labels {
+            let Some(name) = label_name(label) else {
+                continue;
+            };
+            let text = &file_contents[name.byte_range()];
+            if labels.insert(text, *label).is_some() {
+                diagnostics.push(diagnostic(
+                    &name,
+                    DiagnosticSeverity::ERROR,
+                    format!("label `{}` is already declared", text),
+                ));
+            }
+        }
+
+        let mut targeted = vec![];
+        for goto in &scope.gotos {
+            let Some(name) = label_name(goto) else {
+                continue;
+            };
+            let text = &file_contents[name.byte_range()];
+            let Some(label) = labels.get(text) else {
+                diagnostics.push(diagnostic(
+                    &name,
+                    DiagnosticSeverity::ERROR,
+                    format!("`goto` to undefined label `{}`", text),
+                ));
+                continue;
+            };
+            targeted.push(text);
+
+            let mut ancestor = label.parent();
+            while let Some(outer) = ancestor.filter(|outer| outer.id() != node.id()) {
+                if LOOPS_AND_SWITCHES.contains(&outer.kind()) && !contains(&outer, goto) {
+                    diagnostics.push(diagnostic(
+                        &name,
+                        DiagnosticSeverity::ERROR,
+                        format!("`goto` can't jump into the loop or `switch` of `{}`", text),
+                    ));
+                    break;
+                }
+                ancestor = outer.parent();
+            }
+        }
+
+        for (text, label) in labels {
+            if targeted.contains(&text) {
+                continue;
+            }
+            let Some(name) = label_name(&label) else {
+                continue;
+            };
+            let mut unused = diagnostic(
+                &name,
+                DiagnosticSeverity::HINT,
+                format!("nothing jumps to label `{}`", text),
+            );
+            unused.tags = Some(vec![DiagnosticTag::UNNECESSARY]);
+            diagnostics.push(unused);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::goto_labels;
+    use crate::file::php_parser;
+
+    #[test]
+    fn test_goto_labels() {
+        let source = "<?php
+goto top;
+top:
+function f() {
+    goto end;
+    for (;;) {
+        inner:
+        goto inner;
+    }
+    goto inner;
+    goto top;
+    end:
+    unused:
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let mut diagnostics = vec![];
+        goto_labels(&tree.root_node(), source, &mut diagnostics);
+
+        let mut found: Vec<(u32, String)> = diagnostics
+            .into_iter()
+            .map(|diagnostic| (diagnostic.range.start.line, diagnostic.message))
+            .collect();
+        found.sort();
+        assert_eq!(
+            vec![
+                (
+                    9,
+                    "`goto` can't jump into the loop or `switch` of `inner`".to_string()
+                ),
+                (10, "`goto` to undefined label `top`".to_string()),
+                (12, "nothing jumps to label `unused`".to_string()),
+            ],
+            found
+        );
+    }
+}
@@ -0,0 +1,1049 @@
+use lsp_types::Url;
+use serde::Serialize;
+
+use tree_sitter::{Parser, Tree};
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::annotations::{Annotations, PinnedType};
+use crate::baseline::Baseline;
+use crate::cache::IndexCache;
+use crate::config::RuleSetting;
+use crate::file::Snapshot;
+use crate::fuzzy::fuzzy_score;
+use crate::ini::{ini_directives, META_FILE};
+use crate::symbols::{
+    file_symbols, Declaration, DeclarationKind, FileSymbols, Reference, ReferenceKind,
+};
+
+/// A PHP file in the workspace that we have read and parsed from disk.
+///
+/// The client does not need to have these open; if it does, the copy in the open documents is
+/// always preferred over this one.
+pub struct IndexedFile {
+    pub snapshot: Snapshot,
+    /// Files under a `vendor` directory are indexed, but we never report problems in them.
+    pub is_vendor: bool,
+}
+
+impl IndexedFile {
+    pub fn read(parser: &mut Parser, path: &Path, is_vendor: bool) -> Option<Self> {
+        let contents = fs::read_to_string(path).ok()?;
+
+        Some(Self {
+            snapshot: Snapshot::parse(parser, contents)?,
+            is_vendor,
+        })
+    }
+}
+
+/// Rough number of bytes tree-sitter needs for every node in a tree.
+const TREE_NODE_BYTES: usize = 64;
+
+pub fn source_memory(contents: &str) -> usize {
+    contents.len()
+}
+
+/// Tree-sitter doesn't tell us how much memory a tree takes, so this is only an estimate.
+pub fn tree_memory(tree: &Tree) -> usize {
+    tree.root_node().descendant_count() * TREE_NODE_BYTES
+}
+
+/// What the source and tree of a loaded file take.
+fn file_memory(file: &IndexedFile) -> usize {
+    source_memory(&file.snapshot.contents) + tree_memory(&file.snapshot.tree)
+}
+
+/// Estimated bytes used, in total and by kind.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemoryUsage {
+    pub sources: usize,
+    pub trees: usize,
+    pub symbols: usize,
+    pub total: usize,
+}
+
+impl MemoryUsage {
+    pub fn add(&mut self, sources: usize, trees: usize, symbols: usize) {
+        self.sources += sources;
+        self.trees += trees;
+        self.symbols += symbols;
+        self.total += sources + trees + symbols;
+    }
+}
+
+struct IndexEntry {
+    /// Gone when evicted to stay under the memory budget, in which case it has to be read from
+    /// disk again before being used.
+    file: Option<IndexedFile>,
+    is_vendor: bool,
+    last_used: AtomicU64,
+}
+
+/// Where references to a symbol and declarations of it meet.
+type DependencyKey = (ReferenceKind, String);
+
+fn dependency_key(kind: ReferenceKind, name: &str) -> DependencyKey {
+    match kind {
+        ReferenceKind::Constant => (kind, name.to_string()),
+        _ => (kind, name.to_lowercase()),
+    }
+}
+
+/**
+ * Keys that files depending on this declaration would have referenced.
+ *
+ * Members are keyed on both their own name and their class, since we can't always tell which
+ * class a member is accessed on.
+ */
+fn declaration_keys(declaration: &Declaration) -> Vec<DependencyKey> {
+    match declaration.kind {
+        DeclarationKind::Class
+        | DeclarationKind::Interface
+        | DeclarationKind::Trait
+        | DeclarationKind::Enum => vec![dependency_key(ReferenceKind::Class, &declaration.name)],
+        DeclarationKind::Function => {
+            vec![dependency_key(ReferenceKind::Function, &declaration.name)]
+        }
+        _ => match (declaration.class_name(), declaration.member_name()) {
+            (Some(class), Some(member)) => vec![
+                dependency_key(ReferenceKind::Member, member),
+                dependency_key(ReferenceKind::Class, class),
+            ],
+            _ => vec![dependency_key(ReferenceKind::Constant, &declaration.name)],
+        },
+    }
+}
+
+/// Declarations that were added, removed, or had their signature changed.
+fn changed_declarations<'a>(
+    old: &'a [Declaration],
+    new: &'a [Declaration],
+) -> Vec<&'a Declaration> {
+    let old_signatures: HashMap<&str, &str> = old
+        .iter()
+        .map(|declaration| (declaration.name.as_str(), declaration.signature.as_str()))
+        .collect();
+    let new_signatures: HashMap<&str, &str> = new
+        .iter()
+        .map(|declaration| (declaration.name.as_str(), declaration.signature.as_str()))
+        .collect();
+
+    let removed = old
+        .iter()
+        .filter(|declaration| !new_signatures.contains_key(declaration.name.as_str()));
+    let added_or_changed = new.iter().filter(|declaration| {
+        old_signatures.get(declaration.name.as_str()) != Some(&declaration.signature.as_str())
+    });
+
+    removed.chain(added_or_changed).collect()
+}
+
+pub struct WorkspaceIndex {
+    files: HashMap<Url, IndexEntry>,
+    /// Symbols of every file we know of, including open files that were never saved. These are
+    /// small enough that they are never evicted.
+    symbols: HashMap<Url, FileSymbols>,
+    /// Files that reference a symbol.
+    dependents: HashMap<DependencyKey, HashSet<Url>>,
+    /// Files that declare a symbol, keyed on its lowercased name.
+    declared_in: HashMap<String, HashSet<Url>>,
+    /// Members that enums have without declaring them, keyed on their lowercased name.
+    enum_members: HashMap<String, (Url, Declaration)>,
+    /// Names of ini directives, as listed by the stubs.
+    ini_directives: HashSet<String>,
+    /// What users say in the `.phplsp/overrides.json` of each workspace folder.
+    annotations: Vec<Annotations>,
+    /// What the `.phplsp/baseline.json` of each workspace folder says it already had.
+    baselines: Vec<Baseline>,
+
+    memory_budget: usize,
+    /// Bytes that loaded files and all symbols take, kept as they change so that checking the
+    /// budget doesn't go over every file.
+    memory: usize,
+    /// Ticks every time a file is used, for finding the least recently used files.
+    clock: AtomicU64,
+}
+
+impl Default for WorkspaceIndex {
+    fn default() -> Self {
+        Self {
+            files: HashMap::new(),
+            symbols: HashMap::new(),
+            dependents: HashMap::new(),
+            declared_in: HashMap::new(),
+            enum_members: HashMap::new(),
+            ini_directives: HashSet::new(),
+            annotations: vec![],
+            baselines: vec![],
+            memory_budget: usize::MAX,
+            memory: 0,
+            clock: AtomicU64::new(0),
+        }
+    }
+}
+
+impl WorkspaceIndex {
+    fn tick(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Add or replace a file, returning the other files that depend on what it changed.
+    pub fn insert(&mut self, uri: Url, file: IndexedFile) -> Vec<Url> {
+        let symbols = file_symbols(&file.snapshot.root_node(), &file.snapshot.contents);
+        let dependents = self.update_symbols(&uri, symbols);
+        self.memory += file_memory(&file);
+        let old = self.files.insert(
+            uri.clone(),
+            IndexEntry {
+                is_vendor: file.is_vendor,
+                file: Some(file),
+                last_used: AtomicU64::new(self.tick()),
+            },
+        );
+        self.forget_file(old);
+        self.evict(&uri);
+        dependents
+    }
+
+    /**
+     * Add a file we only have the symbols of, such as one from the [`IndexCache`]. It is
+     * treated as evicted, and gets read from disk when it's needed.
+     */
+    pub fn insert_symbols(&mut self, uri: Url, is_vendor: bool, symbols: FileSymbols) {
+        self.update_symbols(&uri, symbols);
+        let old = self.files.insert(
+            uri,
+            IndexEntry {
+                file: None,
+                is_vendor,
+                last_used: AtomicU64::new(self.tick()),
+            },
+        );
+        self.forget_file(old);
+    }
+
+    /// Stop counting the memory of a file that was replaced.
+    fn forget_file(&mut self, entry: Option<IndexEntry>) {
+        if let Some(file) = entry.and_then(|entry| entry.file) {
+            self.memory -= file_memory(&file);
+        }
+    }
+
+    /**
+     * Read an evicted file from disk again.
+     *
+     * Returns whether the file is now available.
+     */
+    pub fn reload(&mut self, parser: &mut Parser, uri: &Url) -> bool {
+        let Some(entry) = self.files.get(uri) else {
+            return false;
+        };
+        if entry.file.is_some() {
+            return true;
+        }
+
+        let is_vendor = entry.is_vendor;
+        match uri
+            .to_file_path()
+            .ok()
+            .and_then(|path| IndexedFile::read(parser, &path, is_vendor))
+        {
+            Some(file) => {
+                self.insert(uri.clone(), file);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn add_ini_directives(&mut self, directives: impl IntoIterator<Item = String>) {
+        self.ini_directives.extend(directives);
+    }
+
+    pub fn ini_directives(&self) -> &HashSet<String> {
+        &self.ini_directives
+    }
+
+    /// Use the annotations of a workspace folder, instead of any it had before.
+    pub fn set_annotations(&mut self, annotations: Annotations) {
+        self.remove_annotations(&annotations.root.clone());
+        self.annotations.push(annotations);
+    }
+
+    pub fn remove_annotations(&mut self, root: &Path) {
+        self.annotations
+            .retain(|annotations| annotations.root != root);
+    }
+
+    /// The type that users pinned a function, method or property to, if any did.
+    pub fn pinned_type(&self, name: &str) -> Option<PinnedType<'_>> {
+        self.annotations
+            .iter()
+            .find_map(|annotations| annotations.pinned_type(name))
+    }
+
+    /// Whether users said not to report diagnostics with a code in a file.
+    pub fn is_ignored(&self, uri: &Url, code: &str) -> bool {
+        let Ok(path) = uri.to_file_path() else {
+            return false;
+        };
+        self.annotations
+            .iter()
+            .any(|annotations| annotations.is_ignored(&path, code))
+    }
+
+    /// What users said the diagnostics with a code are reported as in a file, if they did.
+    pub fn rule_setting(&self, uri: &Url, code: &str) -> Option<RuleSetting> {
+        let path = uri.to_file_path().ok()?;
+        self.annotations
+            .iter()
+            .find_map(|annotations| annotations.rule_setting(&path, code))
+    }
+
+    /// Use the baseline of a workspace folder, instead of any it had before.
+    pub fn set_baseline(&mut self, baseline: Baseline) {
+        self.remove_baseline(&baseline.root.clone());
+        self.baselines.push(baseline);
+    }
+
+    pub fn remove_baseline(&mut self, root: &Path) {
+        self.baselines.retain(|baseline| baseline.root != root);
+    }
+
+    pub fn baselines(&self) -> &[Baseline] {
+        &self.baselines
+    }
+
+    pub fn set_memory_budget(&mut self, memory_budget: usize) {
+        self.memory_budget = memory_budget;
+    }
+
+    /// What everything takes, by kind, added up again from every file.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let mut usage = MemoryUsage::default();
+        for entry in self.files.values() {
+            if let Some(file) = &entry.file {
+                usage.add(
+                    source_memory(&file.snapshot.contents),
+                    tree_memory(&file.snapshot.tree),
+                    0,
+                );
+            }
+        }
+        for symbols in self.symbols.values() {
+            usage.add(0, 0, symbols.memory_usage());
+        }
+
+        usage
+    }
+
+    /**
+     * Drop the sources and trees of the least recently used files until we are within budget,
+     * keeping only their symbols.
+     */
+    fn evict(&mut self, keep: &Url) {
+        if self.memory <= self.memory_budget {
+            return;
+        }
+
+        let mut loaded: Vec<(u64, Url)> = self
+            .files
+            .iter()
+            .filter(|(uri, entry)| entry.file.is_some() && *uri != keep)
+            .map(|(uri, entry)| (entry.last_used.load(Ordering::Relaxed), uri.clone()))
+            .collect();
+        loaded.sort();
+
+        for (_, uri) in loaded {
+            if self.memory <= self.memory_budget {
+                break;
+            }
+
+            if let Some(file) = self.files.get_mut(&uri).and_then(|entry| entry.file.take()) {
+                self.memory -= file_memory(&file);
+            }
+        }
+    }
+
+    /**
+     * Replace the symbols of a file.
+     *
+     * Returns the other files that reference something whose signature changed, in a stable
+     * order. Those are the only files whose diagnostics may be affected by the change.
+     */
+    pub fn update_symbols(&mut self, uri: &Url, symbols: FileSymbols) -> Vec<Url> {
+        let old = self.symbols.remove(uri).unwrap_or_default();
+        self.memory = self.memory - old.memory_usage() + symbols.memory_usage();
+        // unqualified functions and constants depend on the global ones they fall back to too
+        let keys = |reference: &Reference| {
+            std::iter::once(&reference.name)
+                .chain(&reference.fallback)
+                .map(|name| dependency_key(reference.kind, name))
+                .collect::<Vec<_>>()
+        };
+        for key in old.references.iter().flat_map(keys) {
+            if let Some(uris) = self.dependents.get_mut(&key) {
+                uris.remove(uri);
+            }
+        }
+        for key in symbols.references.iter().flat_map(keys) {
+            self.dependents.entry(key).or_default().insert(uri.clone());
+        }
+
+        for declaration in &old.declarations {
+            if let Some(uris) = self.declared_in.get_mut(&declaration.name.to_lowercase()) {
+                uris.remove(uri);
+            }
+            for member in declaration.enum_members() {
+                let key = member.name.to_lowercase();
+                if self
+                    .enum_members
+                    .get(&key)
+                    .is_some_and(|(declared_in, _)| declared_in == uri)
+                {
+                    self.enum_members.remove(&key);
+                }
+            }
+        }
+        for declaration in &symbols.declarations {
+            self.declared_in
+                .entry(declaration.name.to_lowercase())
+                .or_default()
+                .insert(uri.clone());
+            for member in declaration.enum_members() {
+                self.enum_members
+                    .insert(member.name.to_lowercase(), (uri.clone(), member));
+            }
+        }
+
+        let mut affected = HashSet::new();
+        for declaration in changed_declarations(&old.declarations, &symbols.declarations) {
+            for key in declaration_keys(declaration) {
+                if let Some(uris) = self.dependents.get(&key) {
+                    affected.extend(uris.iter().filter(|other| *other != uri).cloned());
+                }
+            }
+        }
+
+        self.symbols.insert(uri.clone(), symbols);
+
+        let mut affected: Vec<Url> = affected.into_iter().collect();
+        affected.sort();
+        affected
+    }
+
+    /// Get a file, unless it was evicted.
+    pub fn get(&self, uri: &Url) -> Option<&IndexedFile> {
+        let entry = self.files.get(uri)?;
+        entry.last_used.store(self.tick(), Ordering::Relaxed);
+        entry.file.as_ref()
+    }
+
+    /// The symbols of a file, whether or not it was evicted.
+    pub fn symbols(&self, uri: &Url) -> Option<&FileSymbols> {
+        self.symbols.get(uri)
+    }
+
+    /// Whether a file was indexed from disk, rather than only opened.
+    pub fn contains(&self, uri: &Url) -> bool {
+        self.files.contains_key(uri)
+    }
+
+    pub fn is_vendor(&self, uri: &Url) -> bool {
+        self.files.get(uri).is_some_and(|entry| entry.is_vendor)
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Number of files that haven't been evicted.
+    pub fn loaded_len(&self) -> usize {
+        self.files
+            .values()
+            .filter(|entry| entry.file.is_some())
+            .count()
+    }
+
+    /// Everything declared in the workspace, including vendored files.
+    pub fn declarations(&self) -> impl Iterator<Item = (&Url, &Declaration)> {
+        self.symbols.iter().flat_map(|(uri, symbols)| {
+            symbols
+                .declarations
+                .iter()
+                .map(move |declaration| (uri, declaration))
+        })
+    }
+
+    /**
+     * Find a declaration by its fully qualified name, with members written as `Class::method`,
+     * `Class::$property` and `Class::CONSTANT`.
+     *
+     * Case is ignored, which is only wrong for constants that differ in nothing but case.
+     */
+    pub fn find_declaration(&self, name: &str) -> Option<&Declaration> {
+        self.locate(name).map(|(_, declaration)| declaration)
+    }
+
+    /**
+     * Like [`Self::find_declaration`], along with the file it's declared in.
+     *
+     * The members that enums have without declaring them, like `cases()`, are found too.
+     */
+    pub fn locate(&self, name: &str) -> Option<(&Url, &Declaration)> {
+        let name = name.trim_start_matches('\\');
+        let key = name.to_lowercase();
+        let declared = self.declared_in.get(&key).and_then(|uris| {
+            uris.iter()
+                .filter_map(|uri| Some((uri, self.symbols.get(uri)?)))
+                .flat_map(|(uri, symbols)| {
+                    symbols
+                        .declarations
+                        .iter()
+                        .map(move |declaration| (uri, declaration))
+                })
+                .find(|(_, declaration)| declaration.name.eq_ignore_ascii_case(name))
+        });
+
+        declared.or_else(|| {
+            self.enum_members
+                .get(&key)
+                .map(|(uri, declaration)| (uri, declaration))
+        })
+    }
+
+    /// The lowercased names of everything declared in the workspace, members included.
+    pub fn declared_names(&self) -> impl Iterator<Item = &str> {
+        self.declared_in
+            .iter()
+            .filter(|(_, uris)| !uris.is_empty())
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Declarations in other files than one that have a name, ignoring case, in a stable order.
+    pub fn declared_elsewhere(&self, name: &str, uri: &Url) -> Vec<(&Url, &Declaration)> {
+        let Some(uris) = self.declared_in.get(&name.to_lowercase()) else {
+            return vec![];
+        };
+        let mut found: Vec<(&Url, &Declaration)> = uris
+            .iter()
+            .filter(|other| *other != uri)
+            .filter_map(|other| Some((other, self.symbols.get(other)?)))
+            .flat_map(|(other, symbols)| {
+                symbols
+                    .declarations
+                    .iter()
+                    .filter(|declaration| declaration.name.eq_ignore_ascii_case(name))
+                    .map(move |declaration| (other, declaration))
+            })
+            .collect();
+        found.sort_by_key(|(other, declaration)| (*other, declaration.range.start.line));
+        found
+    }
+
+    /**
+     * Every reference to a declaration, in a stable order.
+     *
+     * Members are matched on their name alone, since we generally don't know the class they are
+     * accessed on, so these may include references to members of other classes.
+     */
+    pub fn references(&self, declaration: &Declaration) -> Vec<(&Url, &Reference)> {
+        let Some(key) = declaration_keys(declaration).into_iter().next() else {
+            return vec![];
+        };
+        let Some(uris) = self.dependents.get(&key) else {
+            return vec![];
+        };
+
+        let mut references: Vec<(&Url, &Reference)> = uris
+            .iter()
+            .filter_map(|uri| Some((uri, self.symbols.get(uri)?)))
+            .flat_map(|(uri, symbols)| {
+                symbols
+                    .references
+                    .iter()
+                    .map(move |reference| (uri, reference))
+            })
+            .filter(|(_, reference)| {
+                dependency_key(reference.kind, &reference.name) == key
+                    || reference
+                        .fallback
+                        .as_ref()
+                        .is_some_and(|fallback| dependency_key(reference.kind, fallback) == key)
+            })
+            .collect();
+        references.sort_by_key(|(uri, reference)| {
+            (
+                *uri,
+                reference.range.start.line,
+                reference.range.start.character,
+            )
+        });
+        references
+    }
+
+    /// The class-likes that directly extend, implement or use a class-like, in a stable order.
+    pub fn subtypes(&self, name: &str) -> Vec<(&Url, &Declaration)> {
+        let name = name.trim_start_matches('\\');
+        let Some(uris) = self
+            .dependents
+            .get(&dependency_key(ReferenceKind::Class, name))
+        else {
+            return vec![];
+        };
+
+        let mut subtypes: Vec<(&Url, &Declaration)> = uris
+            .iter()
+            .filter_map(|uri| Some((uri, self.symbols.get(uri)?)))
+            .flat_map(|(uri, symbols)| {
+                symbols
+                    .declarations
+                    .iter()
+                    .map(move |declaration| (uri, declaration))
+            })
+            .filter(|(_, declaration)| {
+                declaration
+                    .supertypes
+                    .iter()
+                    .any(|supertype| supertype.eq_ignore_ascii_case(name))
+            })
+            .collect();
+        subtypes.sort_by(|(a_uri, a), (b_uri, b)| (a_uri, &a.name).cmp(&(b_uri, &b.name)));
+        subtypes
+    }
+
+    /**
+     * Find declarations whose name fuzzily matches a query, best matches first.
+     *
+     * Queries with a namespace separator or `::` are matched against fully qualified names.
+     */
+    pub fn search(&self, query: &str, limit: usize) -> Vec<(&Url, &Declaration)> {
+        let qualified = query.contains('\\') || query.contains("::");
+        let mut matches: Vec<(i64, &Url, &Declaration)> = self
+            .declarations()
+            .filter_map(|(uri, declaration)| {
+                let name = if qualified {
+                    declaration.name.as_str()
+                } else {
+                    declaration.short_name()
+                };
+                fuzzy_score(query, name).map(|score| (score, uri, declaration))
+            })
+            .collect();
+        matches.sort_by(|(a_score, a_uri, a), (b_score, b_uri, b)| {
+            b_score
+                .cmp(a_score)
+                .then_with(|| a.name.cmp(&b.name))
+                .then_with(|| a_uri.cmp(b_uri))
+        });
+
+        matches
+            .into_iter()
+            .take(limit)
+            .map(|(_, uri, declaration)| (uri, declaration))
+            .collect()
+    }
+
+    /**
+     * What to cache of the files of a workspace folder, as [`index_folder`] would, leaving out the
+     * files that `skip` says may not match what's on disk, like open files with unsaved changes.
+     */
+    pub fn folder_cache(&self, folder: &Path, skip: impl Fn(&Url) -> bool) -> IndexCache {
+        let mut cache = IndexCache::default();
+        for (uri, entry) in &self.files {
+            let Some(path) = uri
+                .to_file_path()
+                .ok()
+                .filter(|path| path.starts_with(folder))
+            else {
+                continue;
+            };
+            let (Some(symbols), false) = (self.symbols.get(uri), skip(uri)) else {
+                continue;
+            };
+            if let Ok(modified) = fs::metadata(&path).and_then(|metadata| metadata.modified()) {
+                cache.insert(uri.clone(), modified, entry.is_vendor, symbols.clone());
+            }
+        }
+
+        cache
+    }
+
+    /// All indexed files that are not vendored, in a stable order.
+    pub fn non_vendor_files(&self) -> Vec<Url> {
+        let mut uris: Vec<Url> = self
+            .files
+            .iter()
+            .filter(|(_, entry)| !entry.is_vendor)
+            .map(|(uri, _)| uri.clone())
+            .collect();
+        uris.sort();
+        uris
+    }
+}
+
+/**
+ * Whether a path belongs to a composer dependency, relative to the workspace root.
+ */
+pub fn is_vendor(root: &Path, path: &Path) -> bool {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .components()
+        .any(|component| component.as_os_str() == "vendor")
+}
+
+/**
+ * Index every PHP file in a workspace folder, only parsing the files that were modified since
+ * they were cached.
+ *
+ * Returns what should be cached for the next run.
+ */
+pub fn index_folder(
+    parser: &mut Parser,
+    folder: &Path,
+    cache: &IndexCache,
+    index: &mut WorkspaceIndex,
+) -> IndexCache {
+    let mut updated = IndexCache::default();
+    for path in find_php_files(folder) {
+        let Ok(uri) = Url::from_file_path(&path) else {
+            continue;
+        };
+        let Ok(modified) = fs::metadata(&path).and_then(|metadata| metadata.modified()) else {
+            continue;
+        };
+        let is_vendor = is_vendor(folder, &path);
+
+        match cache.get(&uri, modified) {
+            Some(symbols) => index.insert_symbols(uri.clone(), is_vendor, symbols.clone()),
+            None => match IndexedFile::read(parser, &path, is_vendor) {
+                Some(file) => {
+                    index.insert(uri.clone(), file);
+                }
+                None => continue,
+            },
+        }
+        if let Some(symbols) = index.symbols(&uri) {
+            updated.insert(uri, modified, is_vendor, symbols.clone());
+        }
+    }
+
+    updated
+}
+
+/**
+ * Index stubs of what PHP and its extensions declare, like `phpstorm-stubs`, as vendor files so
+ * that they're known about but never checked themselves. The ini directives they list are kept
+ * too.
+ */
+pub fn index_stubs(parser: &mut Parser, dir: &Path, index: &mut WorkspaceIndex) {
+    for path in find_php_files(dir) {
+        let Ok(uri) = Url::from_file_path(&path) else {
+            continue;
+        };
+        if let Some(file) = IndexedFile::read(parser, &path, true) {
+            if path.file_name().is_some_and(|name| name == META_FILE) {
+                let snapshot = &file.snapshot;
+                index.add_ini_directives(ini_directives(&snapshot.root_node(), &snapshot.contents));
+            }
+            index.insert(uri, file);
+        }
+    }
+}
+
+/**
+ * Recursively find all PHP files under some directory, skipping hidden directories.
+ */
+pub fn find_php_files(root: &Path) -> Vec<PathBuf> {
+    let mut php_files = vec![];
+    let mut to_visit = vec![root.to_path_buf()];
+
+    while let Some(dir) = to_visit.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+
+            if file_type.is_dir() {
+                if !entry.file_name().to_string_lossy().starts_with('.') {
+                    to_visit.push(path);
+                }
+            } else if file_type.is_file() && path.extension().is_some_and(|ext| ext == "php") {
+                php_files.push(path);
+            }
+        }
+    }
+
+    php_files.sort();
+    php_files
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::path::Path;
+
+    use lsp_types::Url;
+
+    use super::{find_php_files, index_folder, is_vendor, IndexedFile, WorkspaceIndex};
+    use crate::cache::IndexCache;
+    use crate::file::php_parser;
+    use crate::symbols::{file_symbols, FileSymbols};
+
+    fn symbols(source: &str) -> FileSymbols {
+        let tree = php_parser().parse(source, None).unwrap();
+        file_symbols(&tree.root_node(), source)
+    }
+
+    #[test]
+    fn test_dependents_of_changed_signature() {
+        let foo = Url::parse("file:///project/Foo.php").unwrap();
+        let uses_foo = Url::parse("file:///project/UsesFoo.php").unwrap();
+        let calls_bar = Url::parse("file:///project/CallsBar.php").unwrap();
+        let unrelated = Url::parse("file:///project/Unrelated.php").unwrap();
+
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(
+            &foo,
+            symbols("<?php class Foo { public function bar(int $a) { return $a; } }"),
+        );
+        index.update_symbols(&uses_foo, symbols("<?php function f(Foo $foo) {}"));
+        index.update_symbols(&calls_bar, symbols("<?php $x->bar(1);"));
+        index.update_symbols(&unrelated, symbols("<?php $x->baz(1);"));
+
+        // changing only the body doesn't affect anyone
+        let affected = index.update_symbols(
+            &foo,
+            symbols("<?php class Foo { public function bar(int $a) { return $a + 1; } }"),
+        );
+        assert!(affected.is_empty());
+
+        let affected = index.update_symbols(
+            &foo,
+            symbols("<?php class Foo { public function bar(string $a) { return $a; } }"),
+        );
+        assert_eq!(vec![calls_bar.clone(), uses_foo.clone()], affected);
+
+        // `CallsBar.php` stops depending on `bar`
+        index.update_symbols(&calls_bar, symbols("<?php $x->baz(1);"));
+        let affected = index.update_symbols(&foo, symbols("<?php class Foo {}"));
+        assert_eq!(vec![uses_foo], affected);
+    }
+
+    #[test]
+    fn test_fallback_dependents() {
+        let stubs = Url::parse("file:///stubs/standard.php").unwrap();
+        let calls = Url::parse("file:///project/calls.php").unwrap();
+
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(&stubs, symbols("<?php function strlen($string) {}"));
+        index.update_symbols(&calls, symbols("<?php namespace App; strlen('a');"));
+        let affected = index.update_symbols(&stubs, symbols("<?php function strlen(string $s) {}"));
+        assert_eq!(vec![calls.clone()], affected);
+
+        // the call of `App\strlen`, which falls back to `strlen`, is gone
+        index.update_symbols(&calls, symbols("<?php namespace App; count([]);"));
+        let affected = index.update_symbols(&stubs, symbols("<?php function strlen($string) {}"));
+        assert!(affected.is_empty());
+    }
+
+    #[test]
+    fn test_search() {
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(
+            &Url::parse("file:///project/UserController.php").unwrap(),
+            symbols("<?php namespace App; class UserController { function update() {} }"),
+        );
+        index.update_symbols(
+            &Url::parse("file:///project/Launcher.php").unwrap(),
+            symbols("<?php namespace App; class Launcher {}"),
+        );
+
+        let names: Vec<&str> = index
+            .search("uc", 10)
+            .into_iter()
+            .map(|(_, declaration)| declaration.name.as_str())
+            .collect();
+        assert_eq!(vec!["App\\UserController", "App\\Launcher"], names);
+
+        let names: Vec<&str> = index
+            .search("usercon::up", 10)
+            .into_iter()
+            .map(|(_, declaration)| declaration.name.as_str())
+            .collect();
+        assert_eq!(vec!["App\\UserController::update"], names);
+    }
+
+    #[test]
+    fn test_references() {
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(
+            &Url::parse("file:///project/Foo.php").unwrap(),
+            symbols("<?php namespace App; class Foo { function bar() {} } function baz() {}"),
+        );
+        index.update_symbols(
+            &Url::parse("file:///project/index.php").unwrap(),
+            symbols("<?php namespace App; new Foo(); (new \\App\\Foo)->bar(); baz(); $x->bar;"),
+        );
+
+        let count = |name: &str| {
+            index
+                .references(index.find_declaration(name).unwrap())
+                .len()
+        };
+        assert_eq!(2, count("App\\Foo"));
+        assert_eq!(2, count("App\\Foo::bar"));
+        assert_eq!(1, count("App\\baz"));
+    }
+
+    #[test]
+    fn test_find_declaration() {
+        let mut index = WorkspaceIndex::default();
+        let uri = Url::parse("file:///project/User.php").unwrap();
+        index.update_symbols(
+            &uri,
+            symbols("<?php namespace App; class User { /** @deprecated */ public $name; }"),
+        );
+
+        assert!(index.find_declaration("\\app\\user").is_some());
+        assert!(
+            index
+                .find_declaration("App\\User::$name")
+                .unwrap()
+                .deprecated
+        );
+        assert!(index.find_declaration("App\\Other").is_none());
+
+        index.update_symbols(&uri, symbols("<?php namespace App; class Other {}"));
+        assert!(index.find_declaration("App\\User").is_none());
+        assert!(index.find_declaration("App\\Other").is_some());
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let root = std::env::temp_dir().join("phplsp-test-evicts-least-recently-used");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+
+        let mut parser = php_parser();
+        let mut index = WorkspaceIndex::default();
+        let mut uris = vec![];
+        for name in ["A", "B", "C"] {
+            let path = root.join(format!("{}.php", name));
+            fs::write(&path, format!("<?php class {} {{}}", name)).unwrap();
+            uris.push(Url::from_file_path(&path).unwrap());
+            index.insert(
+                uris.last().unwrap().clone(),
+                IndexedFile::read(&mut parser, &path, false).unwrap(),
+            );
+        }
+
+        // just enough room for the symbols and two files
+        let usage = index.memory_usage();
+        let per_file = (usage.sources + usage.trees) / 3;
+        index.set_memory_budget(usage.symbols + 2 * per_file);
+        assert!(index.get(&uris[0]).is_some());
+
+        // `B` is the least recently used, since `A` was just looked at
+        index.insert(
+            uris[2].clone(),
+            IndexedFile::read(&mut parser, &root.join("C.php"), false).unwrap(),
+        );
+        assert_eq!(2, index.loaded_len());
+        assert!(index.get(&uris[1]).is_none());
+        assert!(index.get(&uris[0]).is_some());
+        // what's kept as files come and go is what they add up to
+        assert_eq!(index.memory_usage().total, index.memory);
+
+        // evicted files keep their symbols, and can be brought back
+        assert_eq!(3, index.len());
+        assert!(index.reload(&mut parser, &uris[1]));
+        assert!(index.get(&uris[1]).is_some());
+        assert_eq!(2, index.loaded_len());
+        assert_eq!(index.memory_usage().total, index.memory);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_index_folder_from_cache() {
+        let root = std::env::temp_dir().join("phplsp-test-index-folder-from-cache");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("A.php"), "<?php class A {}").unwrap();
+        fs::write(root.join("B.php"), "<?php class B {}").unwrap();
+
+        let mut parser = php_parser();
+        let mut index = WorkspaceIndex::default();
+        let cache = index_folder(&mut parser, &root, &IndexCache::default(), &mut index);
+        assert_eq!(2, cache.len());
+        assert_eq!(2, index.loaded_len());
+
+        // nothing changed, so nothing needs to be parsed
+        let mut index = WorkspaceIndex::default();
+        let again = index_folder(&mut parser, &root, &cache, &mut index);
+        assert_eq!(cache, again);
+        assert_eq!(0, index.loaded_len());
+        assert!(index.find_declaration("B").is_some());
+
+        // what's indexed can be cached again, without the files that may have changed
+        let uri = Url::from_file_path(root.join("A.php")).unwrap();
+        assert_eq!(cache, index.folder_cache(&root, |_| false));
+        assert_eq!(
+            1,
+            index.folder_cache(&root, |skipped| *skipped == uri).len()
+        );
+
+        // cached files are read when they're needed
+        assert!(index.reload(&mut parser, &uri));
+        assert!(index.get(&uri).is_some());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_is_vendor() {
+        let root = Path::new("/project");
+        assert!(is_vendor(root, Path::new("/project/vendor/a/b/Foo.php")));
+        assert!(!is_vendor(root, Path::new("/project/src/Foo.php")));
+        assert!(!is_vendor(
+            Path::new("/vendor"),
+            Path::new("/vendor/src/Foo.php")
+        ));
+    }
+
+    #[test]
+    fn test_find_php_files() {
+        let root = std::env::temp_dir().join("phplsp-test-find-php-files");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("src/Models")).unwrap();
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::write(root.join("src/Models/User.php"), "<?php").unwrap();
+        fs::write(root.join("src/readme.md"), "").unwrap();
+        fs::write(root.join(".git/hook.php"), "<?php").unwrap();
+        fs::write(root.join("index.php"), "<?php").unwrap();
+
+        let files = find_php_files(&root);
+        assert_eq!(
+            vec![root.join("index.php"), root.join("src/Models/User.php")],
+            files
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
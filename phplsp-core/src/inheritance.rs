@@ -0,0 +1,1022 @@
+//! Declarations that don't agree with what their class inherits.
+
+use lsp_types::*;
+
+use crate::arguments::accepts;
+use crate::attributes::attribute_arguments;
+use crate::code_actions::QuickFix;
+use crate::config::Config;
+use crate::index::WorkspaceIndex;
+use crate::resolve::Resolver;
+use crate::return_types::{is_checked, is_known};
+use crate::symbols::{Declaration, DeclarationKind, Documentation, FileSymbols};
+use crate::types::{Type, TypeInference};
+
+/// How far up inherited documentation is looked for, in case supertypes go round in a circle.
+const MAX_DEPTH: usize = 8;
+
+/**
+ * Report class constants that override a `final` constant of a class they extend or an interface
+ * they implement, which PHP refuses to compile.
+ */
+pub fn final_constants(
+    file_contents: &str,
+    symbols: &FileSymbols,
+    index: &WorkspaceIndex,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let resolver = Resolver::new(file_contents, symbols, index);
+    for constant in &symbols.declarations {
+        let Some(class) = constant
+            .class_name()
+            .filter(|_| constant.kind == DeclarationKind::Constant)
+            .and_then(|class| index.find_declaration(class))
+        else {
+            continue;
+        };
+        let overridden = class
+            .supertypes
+            .iter()
+            .filter_map(|supertype| resolver.member(supertype, constant.short_name()))
+            .find(|overridden| overridden.has_modifier("final"));
+        if let Some(overridden) = overridden {
+            diagnostics.push(Diagnostic {
+                range: constant.range,
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(NumberOrString::String("final-constant".to_string())),
+                source: Some("phplsp".to_string()),
+                message: format!(
+                    "`{}` overrides the final constant of `{}`",
+                    constant.short_name(),
+                    overridden.class_name().unwrap_or_default()
+                ),
+                ..Diagnostic::default()
+            });
+        }
+    }
+}
+
+/// The closest declaration that a declaration inherits documentation from, if any does.
+fn documented_parent<'a>(
+    declaration: &Declaration,
+    resolver: &Resolver<'a>,
+) -> Option<&'a Declaration> {
+    let mut parent = resolver.overridden(declaration)?;
+    for _ in 0..MAX_DEPTH {
+        if parent.documentation == Documentation::Own {
+            return Some(parent);
+        }
+        // members without docs go by what they override, but classes don't
+        if parent.documentation == Documentation::None && parent.class_name().is_none() {
+            return None;
+        }
+        parent = resolver.overridden(parent)?;
+    }
+    None
+}
+
+/**
+ * Report `@inheritDoc` on declarations that have no documentation to inherit, and, if the rule is
+ * set, methods without docs of their own that rename the parameters of the documented method they
+ * override, so that its docs are about parameters that they call something else.
+ */
+pub fn inherited_docs(
+    file_contents: &str,
+    symbols: &FileSymbols,
+    index: &WorkspaceIndex,
+    config: &Config,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let resolver = Resolver::new(file_contents, symbols, index);
+    for declaration in &symbols.declarations {
+        match declaration.documentation {
+            Documentation::Inherited if documented_parent(declaration, &resolver).is_none() => {
+                let message = match resolver.overridden(declaration) {
+                    Some(parent) => format!(
+                        "`@inheritDoc` on `{}`, but `{}` has no documentation to inherit",
+                        declaration.short_name(),
+                        parent.name
+                    ),
+                    None => format!(
+                        "`@inheritDoc` on `{}`, which doesn't override anything",
+                        declaration.short_name()
+                    ),
+                };
+                diagnostics.push(Diagnostic {
+                    range: declaration.range,
+                    severity: Some(DiagnosticSeverity::WARNING),
+                    code: Some(NumberOrString::String("inherit-doc".to_string())),
+                    source: Some("phplsp".to_string()),
+                    message,
+                    ..Diagnostic::default()
+                });
+            }
+            Documentation::None if declaration.kind == DeclarationKind::Method => {
+                let Some(rule) = &config.undocumented_overrides else {
+                    continue;
+                };
+                let Some(parent) = documented_parent(declaration, &resolver) else {
+                    continue;
+                };
+                let renamed = declaration
+                    .parameters()
+                    .into_iter()
+                    .zip(parent.parameters())
+                    .find(|(own, inherited)| own != inherited);
+                if let Some((own, inherited)) = renamed {
+                    diagnostics.push(Diagnostic {
+                        range: declaration.range,
+                        severity: Some(rule.severity.into()),
+                        code: Some(NumberOrString::String("undocumented-override".to_string())),
+                        source: Some("phplsp".to_string()),
+                        message: format!(
+                            "`{}` renames `{}` of `{}` to `{}` without documenting it",
+                            declaration.short_name(),
+                            inherited,
+                            parent.name,
+                            own
+                        ),
+                        ..Diagnostic::default()
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/**
+ * Report methods with `#[\Override]` that don't override a method of a class they extend or an
+ * interface they implement, which PHP refuses to compile, and, if the rule is set, methods that
+ * do override one without saying so. Methods of traits are left alone, since what they override
+ * depends on the class that uses them.
+ */
+pub fn overrides(
+    file_contents: &str,
+    symbols: &FileSymbols,
+    index: &WorkspaceIndex,
+    config: &Config,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let resolver = Resolver::new(file_contents, symbols, index);
+    for method in &symbols.declarations {
+        if method.kind != DeclarationKind::Method {
+            continue;
+        }
+        if in_trait(index, method) {
+            continue;
+        }
+        // private methods can't be overridden, only declared again
+        let overridden = resolver
+            .overridden(method)
+            .filter(|overridden| !overridden.has_modifier("private"));
+        let marked =
+            attribute_arguments(method.attributes.iter().map(String::as_str), "Override").is_some();
+
+        let (severity, code, message) = match (marked, overridden, &config.missing_override) {
+            (true, None, _) => (
+                DiagnosticSeverity::ERROR,
+                "override",
+                format!(
+                    "`{}` has `#[\\Override]`, but doesn't override anything",
+                    method.short_name()
+                ),
+            ),
+            (false, Some(overridden), Some(rule))
+                if !method.short_name().eq_ignore_ascii_case("__construct") =>
+            {
+                (
+                    rule.severity.into(),
+                    "missing-override",
+                    format!(
+                        "`{}` overrides `{}` without `#[\\Override]`",
+                        method.short_name(),
+                        overridden.name
+                    ),
+                )
+            }
+            _ => continue,
+        };
+        diagnostics.push(Diagnostic {
+            range: method.range,
+            severity: Some(severity),
+            code: Some(NumberOrString::String(code.to_string())),
+            source: Some("phplsp".to_string()),
+            message,
+            ..Diagnostic::default()
+        });
+    }
+}
+
+/// The methods that a class-like declares itself.
+fn own_methods<'a>(index: &'a WorkspaceIndex, class: &str) -> Vec<&'a Declaration> {
+    let Some((uri, _)) = index.locate(class) else {
+        return vec![];
+    };
+    index
+        .symbols(uri)
+        .into_iter()
+        .flat_map(|symbols| &symbols.declarations)
+        .filter(|declaration| {
+            declaration.kind == DeclarationKind::Method
+                && declaration
+                    .class_name()
+                    .is_some_and(|owner| owner.eq_ignore_ascii_case(class))
+        })
+        .collect()
+}
+
+/**
+ * Traits that say they're the default implementation of an interface, with `@see` in their doc
+ * comment or by being named after it, like `LoggerTrait` for `LoggerInterface`, along with the
+ * interface, if they implement every method of it in `missing`.
+ */
+fn default_implementations<'a>(
+    index: &'a WorkspaceIndex,
+    interface: &'a Declaration,
+    missing: &[&Declaration],
+) -> Vec<(&'a Declaration, &'a Declaration)> {
+    let missing: Vec<&str> = missing
+        .iter()
+        .filter(|method| {
+            method
+                .class_name()
+                .is_some_and(|owner| owner.eq_ignore_ascii_case(&interface.name))
+        })
+        .map(|method| method.short_name())
+        .collect();
+    if missing.is_empty() {
+        return vec![];
+    }
+
+    let named = format!(
+        "{}Trait",
+        interface
+            .name
+            .strip_suffix("Interface")
+            .unwrap_or(&interface.name)
+    );
+    let mut traits: Vec<&Declaration> = index
+        .declarations()
+        .map(|(_, declaration)| declaration)
+        .filter(|declaration| {
+            declaration.kind == DeclarationKind::Trait
+                && declaration
+                    .see
+                    .iter()
+                    .any(|see| see.eq_ignore_ascii_case(&interface.name))
+        })
+        .collect();
+    if let Some(named) = index
+        .find_declaration(&named)
+        .filter(|declaration| declaration.kind == DeclarationKind::Trait)
+    {
+        if !traits.iter().any(|other| other.name == named.name) {
+            traits.push(named);
+        }
+    }
+
+    traits
+        .into_iter()
+        .filter(|implementation| {
+            let methods = own_methods(index, &implementation.name);
+            missing.iter().all(|name| {
+                methods.iter().any(|method| {
+                    method.short_name().eq_ignore_ascii_case(name)
+                        && !method.has_modifier("abstract")
+                })
+            })
+        })
+        .map(|implementation| (interface, implementation))
+        .collect()
+}
+
+/// A fix that adds a `use` of a trait to the top of the body of a class.
+fn use_trait_fix(
+    file_contents: &str,
+    class: &Declaration,
+    interface: &Declaration,
+    implementation: &Declaration,
+) -> Option<QuickFix> {
+    // the body starts at the first brace after the name
+    let lines: Vec<&str> = file_contents.lines().collect();
+    let (line, column) = (class.range.end.line as usize..lines.len()).find_map(|line| {
+        let from = match line == class.range.end.line as usize {
+            true => class.range.end.character as usize,
+            false => 0,
+        };
+        let column = lines[line].get(from..)?.find('{')?;
+        Some((line, from + column + 1))
+    })?;
+    let text = lines[class.range.start.line as usize];
+    let indentation = &text[..text.len() - text.trim_start().len()];
+    let name = match implementation.container_name() == class.container_name() {
+        true => implementation.short_name().to_string(),
+        false => format!("\\{}", implementation.name),
+    };
+    let closes = lines[line][column..].trim_start().starts_with('}');
+    let position = Position::new(line as u32, column as u32);
+    Some(QuickFix {
+        title: format!(
+            "Use `{}`, which implements `{}`",
+            implementation.short_name(),
+            interface.short_name()
+        ),
+        edits: vec![TextEdit {
+            range: Range::new(position, position),
+            new_text: format!(
+                "\n{}    use {};{}",
+                indentation,
+                name,
+                match closes {
+                    true => format!("\n{}", indentation),
+                    false => String::new(),
+                }
+            ),
+        }],
+        rename: None,
+    })
+}
+
+/**
+ * Report classes and enums that aren't abstract but leave out methods of the interfaces they
+ * implement, or abstract methods of the classes they extend and the traits they use, which PHP
+ * refuses to compile. The message lists the signatures of what's missing. Classes with an
+ * ancestor that isn't in the index are left alone, since it could have the methods.
+ *
+ * Where a trait in the workspace is the default implementation of an interface that's missing
+ * methods, a fix adds a `use` of it.
+ */
+pub fn missing_implementations(
+    file_contents: &str,
+    symbols: &FileSymbols,
+    index: &WorkspaceIndex,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let resolver = Resolver::new(file_contents, symbols, index);
+    for class in &symbols.declarations {
+        if !matches!(class.kind, DeclarationKind::Class | DeclarationKind::Enum)
+            || class.has_modifier("abstract")
+        {
+            continue;
+        }
+        let Some(ancestors) = resolver
+            .ancestors(&class.name)
+            .iter()
+            .map(|ancestor| index.find_declaration(ancestor))
+            .collect::<Option<Vec<&Declaration>>>()
+        else {
+            continue;
+        };
+
+        let mut required: Vec<&Declaration> = vec![];
+        let mut implemented: Vec<&Declaration> = vec![];
+        for ancestor in &ancestors {
+            for method in own_methods(index, &ancestor.name) {
+                if ancestor.kind == DeclarationKind::Interface || method.has_modifier("abstract") {
+                    required.push(method);
+                } else {
+                    implemented.push(method);
+                }
+            }
+        }
+        let mut missing: Vec<&Declaration> = vec![];
+        for method in required {
+            let is_same =
+                |other: &&Declaration| other.short_name().eq_ignore_ascii_case(method.short_name());
+            if !implemented.iter().any(is_same) && !missing.iter().any(is_same) {
+                missing.push(method);
+            }
+        }
+        if missing.is_empty() {
+            continue;
+        }
+
+        let signatures: Vec<String> = missing
+            .iter()
+            .map(|method| {
+                format!(
+                    "- `{}` from `{}`",
+                    method.signature.trim_end_matches(';'),
+                    method.class_name().unwrap_or_default()
+                )
+            })
+            .collect();
+        let mut diagnostic = Diagnostic {
+            range: class.range,
+            severity: Some(DiagnosticSeverity::ERROR),
+            code: Some(NumberOrString::String("missing-implementation".to_string())),
+            source: Some("phplsp".to_string()),
+            message: format!(
+                "`{}` must be abstract or implement:\n{}",
+                class.short_name(),
+                signatures.join("\n")
+            ),
+            ..Diagnostic::default()
+        };
+        let fixes: Vec<QuickFix> = ancestors
+            .iter()
+            .filter(|ancestor| ancestor.kind == DeclarationKind::Interface)
+            .flat_map(|interface| default_implementations(index, interface, &missing))
+            .filter_map(|(interface, implementation)| {
+                use_trait_fix(file_contents, class, interface, implementation)
+            })
+            .collect();
+        if !fixes.is_empty() {
+            QuickFix::attach_all(&fixes, &mut diagnostic);
+        }
+        diagnostics.push(diagnostic);
+    }
+}
+
+fn error(declaration: &Declaration, code: &str, message: String) -> Diagnostic {
+    Diagnostic {
+        range: declaration.range,
+        severity: Some(DiagnosticSeverity::ERROR),
+        code: Some(NumberOrString::String(code.to_string())),
+        source: Some("phplsp".to_string()),
+        message,
+        ..Diagnostic::default()
+    }
+}
+
+/// Whether a method is declared in a trait, so that what it overrides depends on who uses it.
+fn in_trait(index: &WorkspaceIndex, method: &Declaration) -> bool {
+    method
+        .class_name()
+        .and_then(|class| index.find_declaration(class))
+        .is_none_or(|class| class.kind == DeclarationKind::Trait)
+}
+
+/**
+ * Report classes that extend a `final` class and methods that override a `final` method, which PHP
+ * refuses to compile.
+ */
+pub fn final_overrides(
+    file_contents: &str,
+    symbols: &FileSymbols,
+    index: &WorkspaceIndex,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let resolver = Resolver::new(file_contents, symbols, index);
+    for declaration in &symbols.declarations {
+        match declaration.kind {
+            DeclarationKind::Class => {
+                let parent = declaration
+                    .supertypes
+                    .iter()
+                    .filter_map(|supertype| index.find_declaration(supertype))
+                    .find(|supertype| supertype.kind == DeclarationKind::Class);
+                if let Some(parent) = parent.filter(|parent| parent.has_modifier("final")) {
+                    diagnostics.push(error(
+                        declaration,
+                        "final-class",
+                        format!(
+                            "`{}` extends `{}`, which is final",
+                            declaration.short_name(),
+                            parent.short_name()
+                        ),
+                    ));
+                }
+            }
+            DeclarationKind::Method if !in_trait(index, declaration) => {
+                let overridden = resolver.overridden(declaration).filter(|overridden| {
+                    overridden.has_modifier("final") && !overridden.has_modifier("private")
+                });
+                if let Some(overridden) = overridden {
+                    diagnostics.push(error(
+                        declaration,
+                        "final-method",
+                        format!(
+                            "`{}` overrides `{}`, which is final",
+                            declaration.short_name(),
+                            overridden.name
+                        ),
+                    ));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// A type of a declaration as it's written, if it's one we can compare with others.
+fn written_type(index: &WorkspaceIndex, declaration: &Declaration, written: &str) -> Option<Type> {
+    if !is_checked(written) {
+        return None;
+    }
+    Type::parse(written, declaration.namespace(), declaration.class_name())
+        .filter(|ty| is_known(index, ty))
+}
+
+fn count(arity: Option<usize>) -> String {
+    match arity {
+        Some(1) => "1 parameter".to_string(),
+        Some(count) => format!("{} parameters", count),
+        None => "any number of parameters".to_string(),
+    }
+}
+
+/**
+ * Report methods whose signatures aren't compatible with the method they override, which PHP
+ * refuses to compile: they have to take every argument that it takes, require no more of them,
+ * take parameters of the same types or wider ones, and return the same type or a narrower one.
+ * Constructors are left alone, and so are types that aren't written on both sides.
+ */
+pub fn incompatible_overrides(
+    file_contents: &str,
+    symbols: &FileSymbols,
+    index: &WorkspaceIndex,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let resolver = Resolver::new(file_contents, symbols, index);
+    let types = TypeInference::new(file_contents, &resolver);
+    for method in &symbols.declarations {
+        if method.kind != DeclarationKind::Method
+            || method.short_name().eq_ignore_ascii_case("__construct")
+            || in_trait(index, method)
+        {
+            continue;
+        }
+        let Some(overridden) = resolver
+            .overridden(method)
+            .filter(|overridden| !overridden.has_modifier("private"))
+        else {
+            continue;
+        };
+
+        let name = method.short_name();
+        let (required, most) = method.arity();
+        let (overridden_required, overridden_most) = overridden.arity();
+        let takes_fewer = match (most, overridden_most) {
+            (None, _) => false,
+            (Some(_), None) => true,
+            (Some(most), Some(overridden_most)) => most < overridden_most,
+        };
+        if takes_fewer {
+            diagnostics.push(error(
+                method,
+                "incompatible-override",
+                format!(
+                    "`{}` takes {}, but `{}` that it overrides takes {}",
+                    name,
+                    count(most),
+                    overridden.name,
+                    count(overridden_most)
+                ),
+            ));
+            continue;
+        }
+        if required > overridden_required {
+            diagnostics.push(error(
+                method,
+                "incompatible-override",
+                format!(
+                    "`{}` requires {}, but `{}` that it overrides requires {}",
+                    name,
+                    count(Some(required)),
+                    overridden.name,
+                    count(Some(overridden_required))
+                ),
+            ));
+            continue;
+        }
+
+        let parameters = method.parameters();
+        for (i, parameter) in parameters
+            .iter()
+            .enumerate()
+            .take(overridden.parameters().len())
+        {
+            let (Some(own), Some(inherited)) = (
+                method
+                    .parameter_type(i)
+                    .and_then(|written| written_type(index, method, written)),
+                overridden
+                    .parameter_type(i)
+                    .and_then(|written| written_type(index, overridden, written)),
+            ) else {
+                continue;
+            };
+            if !accepts(&types, &own, &inherited, true) {
+                diagnostics.push(error(
+                    method,
+                    "incompatible-override",
+                    format!(
+                        "`{}` of `{}` takes `{}`, but `{}` that it overrides takes `{}` there",
+                        parameter.trim_start_matches("..."),
+                        name,
+                        own,
+                        overridden.name,
+                        inherited
+                    ),
+                ));
+            }
+        }
+
+        let (Some(own), Some(inherited)) = (
+            method
+                .return_type()
+                .and_then(|written| written_type(index, method, written)),
+            overridden
+                .return_type()
+                .and_then(|written| written_type(index, overridden, written)),
+        ) else {
+            continue;
+        };
+        if !accepts(&types, &inherited, &own, true) {
+            diagnostics.push(error(
+                method,
+                "incompatible-override",
+                format!(
+                    "`{}` returns `{}`, but `{}` that it overrides returns `{}`",
+                    name, own, overridden.name, inherited
+                ),
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::*;
+
+    use super::{
+        final_constants, final_overrides, incompatible_overrides, inherited_docs,
+        missing_implementations, overrides,
+    };
+    use crate::code_actions::QuickFix;
+    use crate::config::{Config, UsageRule};
+    use crate::file::php_parser;
+    use crate::index::WorkspaceIndex;
+    use crate::symbols::file_symbols;
+
+    #[test]
+    fn test_final_constants() {
+        let source = "<?php
+interface Versioned { final public const VERSION = 1; const NAME = 'v'; }
+class Base implements Versioned { const VERSION = 2; }
+class Child extends Base { const NAME = 'child'; }
+enum Kind implements Versioned { case On; }
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(&Url::parse("file:///A.php").unwrap(), symbols.clone());
+        let mut diagnostics = vec![];
+        final_constants(source, &symbols, &index, &mut diagnostics);
+
+        let found: Vec<(u32, String)> = diagnostics
+            .into_iter()
+            .map(|diagnostic| (diagnostic.range.start.line, diagnostic.message))
+            .collect();
+        assert_eq!(
+            vec![(
+                2,
+                "`VERSION` overrides the final constant of `Versioned`".to_string()
+            ),],
+            found
+        );
+    }
+
+    #[test]
+    fn test_inherited_docs() {
+        let source = "<?php
+interface Repository {
+    /** Finds an entity by its id. */
+    public function find(int $id);
+    public function all();
+}
+class UserRepository implements Repository {
+    /** @inheritDoc */
+    public function find(int $id) {}
+    /** {@inheritdoc} */
+    public function all() {}
+    /** @inheritDoc */
+    public function extra() {}
+}
+class CachedUserRepository extends UserRepository {
+    public function find(int $userId) {}
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(&Url::parse("file:///A.php").unwrap(), symbols.clone());
+        let config = Config {
+            undocumented_overrides: Some(UsageRule::default()),
+            ..Config::default()
+        };
+        let mut diagnostics = vec![];
+        inherited_docs(source, &symbols, &index, &config, &mut diagnostics);
+
+        let found: Vec<(u32, String)> = diagnostics
+            .into_iter()
+            .map(|diagnostic| (diagnostic.range.start.line, diagnostic.message))
+            .collect();
+        assert_eq!(
+            vec![
+                (
+                    10,
+                    "`@inheritDoc` on `all`, but `Repository::all` has no documentation to inherit"
+                        .to_string()
+                ),
+                (
+                    12,
+                    "`@inheritDoc` on `extra`, which doesn't override anything".to_string()
+                ),
+                (
+                    15,
+                    "`find` renames `$id` of `Repository::find` to `$userId` without documenting it"
+                        .to_string()
+                ),
+            ],
+            found
+        );
+    }
+
+    #[test]
+    fn test_overrides() {
+        let source = "<?php
+interface Shape { public function area(): float; }
+abstract class Base implements Shape {
+    private function secret() {}
+    public function __construct() {}
+}
+class Square extends Base {
+    #[\\Override]
+    public function area(): float {}
+    #[\\Override]
+    public function secret() {}
+    public function __construct() {}
+    public function name() {}
+}
+class Circle extends Base {
+    public function area(): float {}
+}
+trait Named {
+    #[\\Override]
+    public function name() {}
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(&Url::parse("file:///A.php").unwrap(), symbols.clone());
+        let found = |config: &Config| {
+            let mut diagnostics = vec![];
+            overrides(source, &symbols, &index, config, &mut diagnostics);
+            diagnostics
+                .into_iter()
+                .map(|diagnostic| (diagnostic.range.start.line, diagnostic.message))
+                .collect::<Vec<_>>()
+        };
+
+        let secret = (
+            10,
+            "`secret` has `#[\\Override]`, but doesn't override anything".to_string(),
+        );
+        assert_eq!(vec![secret.clone()], found(&Config::default()));
+        let config = Config {
+            missing_override: Some(UsageRule::default()),
+            ..Config::default()
+        };
+        assert_eq!(
+            vec![
+                secret,
+                (
+                    15,
+                    "`area` overrides `Shape::area` without `#[\\Override]`".to_string()
+                ),
+            ],
+            found(&config)
+        );
+    }
+
+    #[test]
+    fn test_missing_implementations() {
+        let source = "<?php
+interface Shape {
+    public function area(): float;
+    public function name(): string;
+}
+trait Named {
+    public function name(): string { return static::class; }
+    abstract protected function describe(string $format = '%s'): string;
+}
+abstract class Base implements Shape {
+    abstract public function scale(float $by): static;
+}
+class Square extends Base {
+    use Named;
+    public function area(): float {}
+}
+class Circle extends Base {
+    use Named;
+    public function area(): float {}
+    public function scale(float $by): static {}
+    protected function describe(string $format = '%s'): string {}
+}
+class Unknown extends Missing implements Shape {}
+enum Kind implements Shape { case On; }
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(&Url::parse("file:///A.php").unwrap(), symbols.clone());
+        let mut diagnostics = vec![];
+        missing_implementations(source, &symbols, &index, &mut diagnostics);
+
+        let found: Vec<(u32, String)> = diagnostics
+            .into_iter()
+            .map(|diagnostic| (diagnostic.range.start.line, diagnostic.message))
+            .collect();
+        assert_eq!(
+            vec![
+                (
+                    12,
+                    "`Square` must be abstract or implement:
+- `abstract public function scale(float $by): static` from `Base`
+- `abstract protected function describe(string $format = '%s'): string` from `Named`"
+                        .to_string()
+                ),
+                (
+                    23,
+                    "`Kind` must be abstract or implement:
+- `public function area(): float` from `Shape`
+- `public function name(): string` from `Shape`"
+                        .to_string()
+                ),
+            ],
+            found
+        );
+    }
+
+    #[test]
+    fn test_default_implementations() {
+        let source = "<?php
+namespace App;
+interface LoggerInterface {
+    public function log($message);
+    public function error($message);
+}
+trait LoggerTrait {
+    public function log($message) {}
+    public function error($message) {}
+}
+interface Cache {
+    public function get($key);
+}
+/**
+ * @see Cache
+ */
+trait Caching {
+    public function get($key) {}
+}
+class Service implements LoggerInterface, Cache {}
+class Job implements LoggerInterface
+{
+    public function log($message) {}
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(&Url::parse("file:///A.php").unwrap(), symbols.clone());
+        let mut diagnostics = vec![];
+        missing_implementations(source, &symbols, &index, &mut diagnostics);
+
+        let fixes: Vec<Vec<(String, Vec<TextEdit>)>> = diagnostics
+            .iter()
+            .map(|diagnostic| {
+                QuickFix::all(diagnostic)
+                    .into_iter()
+                    .map(|fix| (fix.title, fix.edits))
+                    .collect()
+            })
+            .collect();
+        let insert = |line, character, text: &str| {
+            vec![TextEdit {
+                range: Range::new(
+                    Position::new(line, character),
+                    Position::new(line, character),
+                ),
+                new_text: text.to_string(),
+            }]
+        };
+        assert_eq!(
+            vec![
+                vec![
+                    (
+                        "Use `LoggerTrait`, which implements `LoggerInterface`".to_string(),
+                        insert(19, 49, "\n    use LoggerTrait;\n")
+                    ),
+                    (
+                        "Use `Caching`, which implements `Cache`".to_string(),
+                        insert(19, 49, "\n    use Caching;\n")
+                    ),
+                ],
+                vec![(
+                    "Use `LoggerTrait`, which implements `LoggerInterface`".to_string(),
+                    insert(21, 1, "\n    use LoggerTrait;")
+                )],
+            ],
+            fixes
+        );
+    }
+
+    #[test]
+    fn test_final_overrides() {
+        let source = "<?php
+final class Money {}
+class Euro extends Money {}
+class Base {
+    final public function save() {}
+    final private function load() {}
+}
+class Child extends Base {
+    public function save() {}
+    public function load() {}
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(&Url::parse("file:///A.php").unwrap(), symbols.clone());
+        let mut diagnostics = vec![];
+        final_overrides(source, &symbols, &index, &mut diagnostics);
+
+        let found: Vec<(u32, String)> = diagnostics
+            .into_iter()
+            .map(|diagnostic| (diagnostic.range.start.line, diagnostic.message))
+            .collect();
+        assert_eq!(
+            vec![
+                (2, "`Euro` extends `Money`, which is final".to_string()),
+                (
+                    8,
+                    "`save` overrides `Base::save`, which is final".to_string()
+                ),
+            ],
+            found
+        );
+    }
+
+    #[test]
+    fn test_incompatible_overrides() {
+        let source = "<?php
+class User {}
+class Admin extends User {}
+class Base {
+    public function __construct(int $id) {}
+    public function find(int|string $id): User {}
+    public function all(int $limit, int $offset = 0) {}
+    public function save(User $user, ...$options) {}
+    public function load($id): ?User {}
+}
+class Child extends Base {
+    public function __construct() {}
+    public function find(int $id): ?User {}
+    public function all(int $limit, int $offset) {}
+    public function save(User $user) {}
+    public function load($id, $lazy = false): Admin {}
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(&Url::parse("file:///A.php").unwrap(), symbols.clone());
+        let mut diagnostics = vec![];
+        incompatible_overrides(source, &symbols, &index, &mut diagnostics);
+
+        let found: Vec<(u32, String)> = diagnostics
+            .into_iter()
+            .map(|diagnostic| (diagnostic.range.start.line, diagnostic.message))
+            .collect();
+        assert_eq!(
+            vec![
+                (
+                    12,
+                    "`$id` of `find` takes `int`, but `Base::find` that it overrides takes `int|string` there"
+                        .to_string()
+                ),
+                (
+                    12,
+                    "`find` returns `?User`, but `Base::find` that it overrides returns `User`"
+                        .to_string()
+                ),
+                (
+                    13,
+                    "`all` requires 2 parameters, but `Base::all` that it overrides requires 1 parameter"
+                        .to_string()
+                ),
+                (
+                    14,
+                    "`save` takes 1 parameter, but `Base::save` that it overrides takes any number of parameters"
+                        .to_string()
+                ),
+            ],
+            found
+        );
+    }
+}
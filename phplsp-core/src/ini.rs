@@ -0,0 +1,313 @@
+//! The names of ini directives given to `ini_set` and `ini_get`, which PHP only checks by quietly
+//! returning `false`.
+
+use lsp_types::*;
+use tree_sitter::Node;
+
+use crate::document_links::string_contents;
+use crate::file::to_point;
+use crate::index::WorkspaceIndex;
+
+/// The file of `phpstorm-stubs` that lists the arguments that functions expect.
+pub const META_FILE: &str = ".phpstorm.meta.php";
+
+/// The argument set in [`META_FILE`] of every ini directive.
+const INI_VALUES: &str = "ini_values";
+
+/// Functions whose first argument is the name of a directive, and whether they change it.
+const INI_FUNCTIONS: &[(&str, bool)] = &[
+    ("ini_set", true),
+    ("ini_alter", true),
+    ("ini_get", false),
+    ("ini_restore", false),
+];
+
+/// Directives that can only be set in `php.ini` or the web server's configuration
+/// (`PHP_INI_SYSTEM`).
+const SYSTEM_DIRECTIVES: &[&str] = &[
+    "allow_url_fopen",
+    "allow_url_include",
+    "disable_classes",
+    "disable_functions",
+    "enable_dl",
+    "expose_php",
+    "extension",
+    "extension_dir",
+    "file_uploads",
+    "hard_timeout",
+    "max_file_uploads",
+    "opcache.enable_cli",
+    "opcache.file_cache",
+    "opcache.interned_strings_buffer",
+    "opcache.jit_buffer_size",
+    "opcache.max_accelerated_files",
+    "opcache.memory_consumption",
+    "opcache.preload",
+    "realpath_cache_size",
+    "realpath_cache_ttl",
+    "sys_temp_dir",
+    "upload_tmp_dir",
+    "user_ini.cache_ttl",
+    "user_ini.filename",
+    "zend_extension",
+];
+
+/// Directives that can also be set in `.htaccess` and `.user.ini` files, but not by scripts
+/// (`PHP_INI_PERDIR`).
+const PERDIR_DIRECTIVES: &[&str] = &[
+    "auto_append_file",
+    "auto_globals_jit",
+    "auto_prepend_file",
+    "enable_post_data_reading",
+    "mail.add_x_header",
+    "mail.log",
+    "max_input_nesting_level",
+    "max_input_time",
+    "max_input_vars",
+    "output_buffering",
+    "output_handler",
+    "post_max_size",
+    "register_argc_argv",
+    "request_order",
+    "session.auto_start",
+    "short_open_tag",
+    "upload_max_filesize",
+    "variables_order",
+    "zend.multibyte",
+];
+
+/// Where a directive can be set, if it can't be set by scripts.
+fn set_only_in(name: &str) -> Option<&'static str> {
+    if SYSTEM_DIRECTIVES.contains(&name) {
+        Some("`php.ini` or the web server's configuration")
+    } else if PERDIR_DIRECTIVES.contains(&name) {
+        Some("`php.ini`, `.htaccess`, `.user.ini` or the web server's configuration")
+    } else {
+        None
+    }
+}
+
+/// The string arguments of `registerArgumentsSet('ini_values', ...)` calls in [`META_FILE`].
+pub fn ini_directives(root_node: &Node, file_contents: &str) -> Vec<String> {
+    let mut directives = vec![];
+    let mut stack = vec![*root_node];
+    while let Some(node) = stack.pop() {
+        let mut cursor = node.walk();
+        stack.extend(node.named_children(&mut cursor));
+        if node.kind() != "function_call_expression"
+            || node.child_by_field_name("function").is_none_or(|function| {
+                &file_contents[function.byte_range()] != "registerArgumentsSet"
+            })
+        {
+            continue;
+        }
+
+        let Some(arguments) = node.child_by_field_name("arguments") else {
+            continue;
+        };
+        let values: Vec<&str> = arguments
+            .named_children(&mut cursor)
+            .filter_map(|argument| string_contents(&argument.named_child(0)?, file_contents))
+            .map(|(_, value)| value)
+            .collect();
+        if values.first() == Some(&INI_VALUES) {
+            directives.extend(values[1..].iter().map(|value| value.to_string()));
+        }
+    }
+    directives
+}
+
+/**
+ * The directive named by the first argument of a call to `ini_set` or the like, and whether
+ * the function changes it.
+ */
+fn directive_argument<'a>(call: &Node, file_contents: &'a str) -> Option<(Range, &'a str, bool)> {
+    if call.kind() != "function_call_expression" {
+        return None;
+    }
+    let function = call.child_by_field_name("function")?;
+    let name = file_contents[function.byte_range()].trim_start_matches('\\');
+    let (_, sets) = INI_FUNCTIONS
+        .iter()
+        .find(|(function, _)| function.eq_ignore_ascii_case(name))?;
+    let argument = call.child_by_field_name("arguments")?.named_child(0)?;
+    let (range, directive) = string_contents(&argument.named_child(0)?, file_contents)?;
+    Some((range, directive, *sets))
+}
+
+/// Whether a directive is known, or belongs to an extension that the stubs don't list any of.
+fn is_known(directive: &str, index: &WorkspaceIndex) -> bool {
+    let directives = index.ini_directives();
+    directives.contains(directive)
+        || directive.split_once('.').is_some_and(|(extension, _)| {
+            !directives.iter().any(|known| {
+                known
+                    .split_once('.')
+                    .is_some_and(|(known, _)| known == extension)
+            })
+        })
+}
+
+/**
+ * Report directives given to `ini_set` and the like that don't exist, and those that `ini_set`
+ * can't change. Directives of extensions that the stubs know none of are left alone, and so is
+ * everything if the stubs don't list directives at all.
+ */
+pub fn ini_directive_diagnostics(
+    node: &Node,
+    file_contents: &str,
+    index: &WorkspaceIndex,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    if index.ini_directives().is_empty() {
+        return;
+    }
+
+    if let Some((range, directive, sets)) = directive_argument(node, file_contents) {
+        let found = if !is_known(directive, index) {
+            Some((
+                "unknown-ini-directive",
+                format!("`{}` is not an ini directive", directive),
+            ))
+        } else {
+            set_only_in(directive).filter(|_| sets).map(|only_in| {
+                (
+                    "ini-not-runtime",
+                    format!(
+                        "`{}` can't be changed at runtime, only in {}",
+                        directive, only_in
+                    ),
+                )
+            })
+        };
+        if let Some((code, message)) = found {
+            diagnostics.push(Diagnostic {
+                range,
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(NumberOrString::String(code.to_string())),
+                source: Some("phplsp".to_string()),
+                message,
+                ..Diagnostic::default()
+            });
+        }
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        ini_directive_diagnostics(&child, file_contents, index, diagnostics);
+    }
+}
+
+/// Documentation of the directive that the cursor is on, in a call to `ini_set` or the like.
+pub fn ini_directive_hover(
+    root_node: &Node,
+    file_contents: &str,
+    position: &Position,
+    index: &WorkspaceIndex,
+) -> Option<Hover> {
+    let point = to_point(position);
+    let mut node = root_node.descendant_for_point_range(point, point)?;
+    let (range, directive) = loop {
+        if let Some((range, directive, _)) = directive_argument(&node, file_contents) {
+            break (range, directive);
+        }
+        node = node.parent()?;
+    };
+    let on = range.start <= *position && *position <= range.end;
+    if !on || !index.ini_directives().contains(directive) {
+        return None;
+    }
+
+    let changed = match set_only_in(directive) {
+        Some(only_in) => format!("Can only be changed in {}.", only_in),
+        None => "Can be changed at runtime with `ini_set`.".to_string(),
+    };
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: format!(
+                "ini directive `{}`\n\n{}\n\n[Documentation](https://www.php.net/manual/en/ini.list.php)",
+                directive, changed
+            ),
+        }),
+        range: Some(range),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::*;
+
+    use super::{ini_directive_diagnostics, ini_directive_hover, ini_directives};
+    use crate::file::php_parser;
+    use crate::index::WorkspaceIndex;
+
+    fn index() -> WorkspaceIndex {
+        let meta = "<?php
+namespace PHPSTORM_META {
+    registerArgumentsSet('ini_values', 'memory_limit', 'upload_max_filesize', 'session.name');
+    expectedArguments(\\ini_get(), 0, argumentsSet('ini_values'));
+}
+";
+        let tree = php_parser().parse(meta, None).unwrap();
+        let mut index = WorkspaceIndex::default();
+        index.add_ini_directives(ini_directives(&tree.root_node(), meta));
+        index
+    }
+
+    #[test]
+    fn test_ini_directive_diagnostics() {
+        let source = "<?php
+ini_set('memory_limit', '1G');
+ini_set('upload_max_filesize', '8M');
+echo ini_get('upload_max_filesize');
+echo \\ini_get('memory_limt');
+ini_set('session.nme', 'id');
+ini_set('xdebug.mode', 'debug');
+ini_set($name, 1);
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let mut diagnostics = vec![];
+        ini_directive_diagnostics(&tree.root_node(), source, &index(), &mut diagnostics);
+
+        let found: Vec<(u32, String)> = diagnostics
+            .into_iter()
+            .map(|diagnostic| (diagnostic.range.start.line, diagnostic.message))
+            .collect();
+        assert_eq!(
+            vec![
+                (
+                    2,
+                    "`upload_max_filesize` can't be changed at runtime, only in `php.ini`, \
+                     `.htaccess`, `.user.ini` or the web server's configuration"
+                        .to_string()
+                ),
+                (4, "`memory_limt` is not an ini directive".to_string()),
+                (5, "`session.nme` is not an ini directive".to_string()),
+            ],
+            found
+        );
+    }
+
+    #[test]
+    fn test_ini_directive_hover() {
+        let source = "<?php\nini_get('memory_limit');\n";
+        let tree = php_parser().parse(source, None).unwrap();
+        let hover = ini_directive_hover(&tree.root_node(), source, &Position::new(1, 12), &index())
+            .unwrap();
+        assert_eq!(
+            Some(Range::new(Position::new(1, 9), Position::new(1, 21))),
+            hover.range
+        );
+        let HoverContents::Markup(markup) = hover.contents else {
+            panic!("expected markup");
+        };
+        assert!(markup
+            .value
+            .starts_with("ini directive `memory_limit`\n\nCan be changed at runtime"));
+
+        let outside =
+            ini_directive_hover(&tree.root_node(), source, &Position::new(1, 2), &index());
+        assert!(outside.is_none());
+    }
+}
@@ -0,0 +1,342 @@
+use lsp_types::*;
+use tree_sitter::Node;
+
+use crate::config::{Config, ParameterNameHints};
+use crate::file::{to_point, to_position};
+use crate::index::WorkspaceIndex;
+use crate::resolve::Resolver;
+use crate::symbols::FileSymbols;
+use crate::types::{foreach_variable, Type, TypeInference};
+
+/// Node kinds that pass arguments to something we might be able to resolve.
+const CALLS: &[&str] = &[
+    "function_call_expression",
+    "member_call_expression",
+    "nullsafe_member_call_expression",
+    "scoped_call_expression",
+    "object_creation_expression",
+];
+
+/// Arguments whose meaning can't be told without knowing the parameter they're passed to.
+const LITERALS: &[&str] = &[
+    "integer",
+    "float",
+    "string",
+    "encapsed_string",
+    "heredoc",
+    "nowdoc",
+    "boolean",
+    "null",
+    "array_creation_expression",
+];
+
+fn is_literal(node: &Node) -> bool {
+    match node.kind() {
+        // negative numbers
+        "unary_op_expression" => node
+            .child_by_field_name("argument")
+            .is_some_and(|argument| is_literal(&argument)),
+        kind => LITERALS.contains(&kind),
+    }
+}
+
+struct Hinter<'a> {
+    contents: &'a str,
+    types: TypeInference<'a>,
+    parameter_names: ParameterNameHints,
+    type_hints: bool,
+    range: tree_sitter::Range,
+    hints: Vec<InlayHint>,
+}
+
+impl Hinter<'_> {
+    fn call(&mut self, call: &Node) {
+        let mut cursor = call.walk();
+        let Some(arguments) = call
+            .children(&mut cursor)
+            .find(|child| child.kind() == "arguments")
+        else {
+            return;
+        };
+        let Some(callee) = self.types.callee(call) else {
+            return;
+        };
+        let parameters = callee.parameters();
+
+        let mut cursor = arguments.walk();
+        let arguments = arguments
+            .named_children(&mut cursor)
+            .filter(|argument| argument.kind() == "argument");
+        for (i, argument) in arguments.enumerate() {
+            // named arguments already say what they are, and nothing positional can follow them
+            if argument.child_by_field_name("name").is_some() {
+                break;
+            }
+            let Some(value) = argument.named_child(0) else {
+                continue;
+            };
+            // we can't tell which parameters an unpacked array ends up in
+            if value.kind() == "variadic_unpacking" {
+                break;
+            }
+
+            let Some(parameter) = parameters.get(i).copied() else {
+                break;
+            };
+            let name = parameter.trim_start_matches("...").trim_start_matches('$');
+            let wanted = match self.parameter_names {
+                ParameterNameHints::Literals => is_literal(&value),
+                // `$name` passed as `name:` doesn't need a reminder
+                ParameterNameHints::All => {
+                    &self.contents[value.byte_range()] != parameter.trim_start_matches("...")
+                }
+            };
+            if wanted && self.in_range(&argument) {
+                let label = match parameter.starts_with("...") {
+                    true => format!("...{}:", name),
+                    false => format!("{}:", name),
+                };
+                self.hints.push(InlayHint {
+                    position: to_position(&argument.start_position()),
+                    label: InlayHintLabel::String(label),
+                    kind: Some(InlayHintKind::PARAMETER),
+                    text_edits: None,
+                    tooltip: None,
+                    padding_left: None,
+                    padding_right: Some(true),
+                    data: None,
+                });
+            }
+
+            // only the first of the arguments that a variadic parameter takes gets a hint
+            if parameter.starts_with("...") {
+                break;
+            }
+        }
+    }
+
+    /// A `: Type` hint right after a node.
+    fn type_hint(&mut self, after: &Node, ty: Option<Type>) {
+        let Some(ty) = ty else {
+            return;
+        };
+        if !self.in_range(after) {
+            return;
+        }
+
+        self.hints.push(InlayHint {
+            position: to_position(&after.end_position()),
+            label: InlayHintLabel::String(format!(": {}", ty)),
+            kind: Some(InlayHintKind::TYPE),
+            text_edits: None,
+            tooltip: None,
+            padding_left: None,
+            padding_right: None,
+            data: None,
+        });
+    }
+
+    fn types(&mut self, node: &Node) {
+        match node.kind() {
+            "assignment_expression" => {
+                let (Some(left), Some(right)) = (
+                    node.child_by_field_name("left"),
+                    node.child_by_field_name("right"),
+                ) else {
+                    return;
+                };
+                // the type is already written out in these
+                let obvious = matches!(
+                    right.kind(),
+                    "object_creation_expression" | "cast_expression"
+                );
+                if left.kind() == "variable_name" && !obvious {
+                    let ty = self.types.expression(&right);
+                    self.type_hint(&left, ty);
+                }
+            }
+            "anonymous_function" | "arrow_function" => {
+                if node.child_by_field_name("return_type").is_some() {
+                    return;
+                }
+                let mut cursor = node.walk();
+                let before_body = node
+                    .children(&mut cursor)
+                    .filter(|child| {
+                        matches!(
+                            child.kind(),
+                            "formal_parameters" | "anonymous_function_use_clause"
+                        )
+                    })
+                    .last();
+                if let Some(before_body) = before_body {
+                    let ty = self.types.function_return(node);
+                    self.type_hint(&before_body, ty);
+                }
+            }
+            "foreach_statement" => {
+                if let Some(variable) = foreach_variable(node) {
+                    let ty = self.types.foreach_value(node);
+                    self.type_hint(&variable, ty);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn in_range(&self, node: &Node) -> bool {
+        node.start_position() >= self.range.start_point
+            && node.start_position() < self.range.end_point
+    }
+
+    fn walk(&mut self, node: &Node) {
+        if CALLS.contains(&node.kind()) {
+            self.call(node);
+        }
+        if self.type_hints {
+            self.types(node);
+        }
+
+        let mut cursor = node.walk();
+        for child in node.children(&mut cursor) {
+            if child.end_position() >= self.range.start_point
+                && child.start_position() <= self.range.end_point
+            {
+                self.walk(&child);
+            }
+        }
+    }
+}
+
+/**
+ * Hints for a range of a file: the names of the parameters that arguments are passed to, and
+ * the types of variables, closures and `foreach` values that don't have them written out.
+ *
+ * Only calls whose callee we can find get hints, and only what [`TypeInference`] can work out
+ * gets a type.
+ */
+pub fn inlay_hints(
+    root_node: &Node,
+    file_contents: &str,
+    symbols: &FileSymbols,
+    index: &WorkspaceIndex,
+    config: &Config,
+    range: &Range,
+) -> Vec<InlayHint> {
+    let resolver = Resolver::new(file_contents, symbols, index);
+    let mut hinter = Hinter {
+        contents: file_contents,
+        types: TypeInference::new(file_contents, &resolver),
+        parameter_names: config.parameter_name_hints,
+        type_hints: config.type_hints,
+        range: tree_sitter::Range {
+            start_byte: 0,
+            end_byte: 0,
+            start_point: to_point(&range.start),
+            end_point: to_point(&range.end),
+        },
+        hints: vec![],
+    };
+    hinter.walk(root_node);
+
+    hinter
+        .hints
+        .sort_by_key(|hint| (hint.position.line, hint.position.character));
+    hinter.hints
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::*;
+
+    use super::inlay_hints;
+    use crate::config::{Config, ParameterNameHints};
+    use crate::file::php_parser;
+    use crate::index::WorkspaceIndex;
+    use crate::symbols::file_symbols;
+
+    fn labels(source: &str, config: &Config) -> Vec<String> {
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(&Url::parse("file:///A.php").unwrap(), symbols.clone());
+        let range = Range {
+            start: Position::default(),
+            end: Position {
+                line: u32::MAX,
+                character: 0,
+            },
+        };
+
+        inlay_hints(&tree.root_node(), source, &symbols, &index, config, &range)
+            .into_iter()
+            .map(|hint| match hint.label {
+                InlayHintLabel::String(label) => label,
+                InlayHintLabel::LabelParts(_) => panic!("expected a plain label"),
+            })
+            .collect()
+    }
+
+    const SOURCE: &str = "<?php
+function greet(string $name, bool $loud = false, ...$extra) {}
+class A {
+    public function __construct(int $size) {}
+}
+greet('me', true, 1, 2);
+greet($name, $volume);
+greet('me', loud: true);
+new A(-3);
+";
+
+    #[test]
+    fn test_literals_only() {
+        assert_eq!(
+            vec!["name:", "loud:", "...extra:", "name:", "size:"],
+            labels(
+                SOURCE,
+                &Config {
+                    type_hints: false,
+                    ..Config::default()
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn test_all_arguments() {
+        assert_eq!(
+            vec!["name:", "loud:", "...extra:", "loud:", "name:", "size:"],
+            labels(
+                SOURCE,
+                &Config {
+                    parameter_name_hints: ParameterNameHints::All,
+                    type_hints: false,
+                    ..Config::default()
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn test_types() {
+        let source = "<?php
+function names(): array {}
+$count = 1;
+$user = new User();
+$f = function ($a) use ($count) { return $count > 1; };
+$g = fn () => 'x';
+foreach ([1.5, 2.5] as $i => $value) {}
+";
+        assert_eq!(
+            vec![
+                ": int",
+                ": Closure",
+                ": bool",
+                ": Closure",
+                ": string",
+                ": float"
+            ],
+            labels(source, &Config::default())
+        );
+    }
+}
@@ -0,0 +1,135 @@
+//! Levels of strictness, like PHPStan's, that bundle the diagnostics we report, so that code with
+//! many problems can start at a low level and go up as they're fixed.
+
+use lsp_types::*;
+
+/// The strictest level, which reports everything.
+pub const MAX_LEVEL: u8 = 8;
+
+/// The codes that each level adds to those of the levels below it.
+const LEVELS: &[&[&str]] = &[
+    // what PHP refuses to compile or run
+    &[
+        "syntax-error",
+        "php-version",
+        "undefined-function",
+        "undefined-class",
+        "unresolved-import",
+        "duplicate-declaration",
+        "final-constant",
+        "final-class",
+        "final-method",
+        "missing-implementation",
+        "override",
+        "argument-count",
+        "goto",
+        "invalid-regex",
+        "format-string",
+    ],
+    // members and names that don't exist
+    &[
+        "undefined-method",
+        "undefined-property",
+        "invalid-option",
+        "unknown-ini-directive",
+        "ini-not-runtime",
+    ],
+    // signatures, doc comments and keys
+    &[
+        "incompatible-override",
+        "inherit-doc",
+        "deprecated",
+        "undefined-group",
+        "undefined-key",
+    ],
+    // what functions return and properties hold
+    &[
+        "return-type",
+        "missing-return",
+        "generator",
+        "property-type",
+    ],
+    // dead code
+    &[
+        "dead-store",
+        "unused-variable",
+        "unused-private-member",
+        "unreachable",
+        "no-effect",
+        "duplicate-condition",
+        "identical-operands",
+        "assignment-in-condition",
+        "fallthrough",
+        "constant-condition",
+    ],
+    // the types of arguments
+    &["argument-type", "option-type", "format-type"],
+    // iterating and references
+    &[
+        "foreach-type",
+        "foreach-destructuring",
+        "reference-type",
+        "foreach-reference",
+    ],
+    // how declarations are written
+    &["visibility", "var"],
+    // what can be `null`
+    &["null-access"],
+];
+
+/**
+ * The level that a code is first reported at. Codes of rules that are off unless they're set, like
+ * `naming`, aren't in any, since setting them is asking for them.
+ */
+pub fn level_of(code: &str) -> Option<u8> {
+    LEVELS
+        .iter()
+        .position(|codes| codes.contains(&code))
+        .map(|level| level as u8)
+}
+
+/// Drop the diagnostics that a level of strictness doesn't report, if one is set.
+pub fn drop_above_level(level: Option<u8>, diagnostics: &mut Vec<Diagnostic>) {
+    let Some(level) = level else {
+        return;
+    };
+    diagnostics.retain(|diagnostic| match &diagnostic.code {
+        Some(NumberOrString::String(code)) => level_of(code).is_none_or(|of| of <= level),
+        _ => true,
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::*;
+
+    use super::{drop_above_level, level_of, LEVELS, MAX_LEVEL};
+
+    #[test]
+    fn test_levels() {
+        assert_eq!(MAX_LEVEL as usize + 1, LEVELS.len());
+        assert_eq!(Some(0), level_of("syntax-error"));
+        assert_eq!(Some(MAX_LEVEL), level_of("null-access"));
+        assert_eq!(None, level_of("naming"));
+
+        let diagnostic = |code: &str| Diagnostic {
+            code: Some(NumberOrString::String(code.to_string())),
+            ..Diagnostic::default()
+        };
+        let mut diagnostics = vec![
+            diagnostic("undefined-class"),
+            diagnostic("dead-store"),
+            diagnostic("null-access"),
+            diagnostic("naming"),
+        ];
+        drop_above_level(Some(4), &mut diagnostics);
+        assert_eq!(
+            vec![
+                diagnostic("undefined-class"),
+                diagnostic("dead-store"),
+                diagnostic("naming"),
+            ],
+            diagnostics
+        );
+    }
+}
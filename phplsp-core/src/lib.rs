@@ -0,0 +1,84 @@
+//! The analysis behind the PHP language server.
+//!
+//! Nothing in here talks to a language client: everything works on source text and parse trees,
+//! and results are plain [`lsp_types`] values. [`analysis::analyze_file`] is the simplest way in;
+//! [`queries::Database`] memoizes analysis for files that keep changing, and
+//! [`index::WorkspaceIndex`] knows what every file in a workspace declares.
+
+pub mod analysis;
+pub mod annotations;
+mod arguments;
+mod array_keys;
+mod attributes;
+pub mod baseline;
+mod budget;
+pub mod cache;
+pub mod call_hierarchy;
+pub mod cfg;
+pub mod code_actions;
+pub mod code_lens;
+pub mod completion;
+pub mod composer;
+mod conditions;
+pub mod config;
+mod constant_conditions;
+pub mod constructs;
+mod dead_stores;
+mod deprecated;
+pub mod diagnostics;
+pub mod docs;
+pub mod document_links;
+pub mod document_symbols;
+mod duplicates;
+pub mod edits;
+mod fallthrough;
+pub mod file;
+pub mod folding_ranges;
+mod foreach;
+pub mod formatting;
+mod fuzzy;
+mod generators;
+mod goto_labels;
+pub mod index;
+mod inheritance;
+pub mod ini;
+pub mod inlay_hints;
+pub mod levels;
+pub mod linked_editing;
+mod metrics;
+mod missing_return;
+pub mod monikers;
+mod names;
+mod naming;
+mod no_effect;
+mod null_access;
+mod options;
+pub mod parsers;
+pub mod php_namespace;
+mod php_version;
+mod preg;
+mod printf;
+mod property_types;
+pub mod queries;
+mod references;
+pub mod rename;
+pub mod resolve;
+mod return_types;
+mod scope;
+pub mod selection_ranges;
+pub mod semantic_tokens;
+pub mod suppressions;
+pub mod symbols;
+mod tags;
+pub mod type_hierarchy;
+pub mod types;
+mod undefined;
+mod unreachable;
+pub mod unused_api;
+mod unused_private;
+mod unused_variables;
+mod usage_rules;
+mod visibility;
+
+pub use lsp_types;
+pub use tree_sitter;
@@ -0,0 +1,125 @@
+use lsp_types::*;
+use tree_sitter::Node;
+
+use crate::file::{to_point, to_range};
+use crate::tags::{comment_position, doc_comments, doc_params};
+
+/// Node kinds that declare a parameter.
+const PARAMETERS: &[&str] = &[
+    "simple_parameter",
+    "variadic_parameter",
+    "property_promotion_parameter",
+];
+
+/// The parameter of a function whose name is written as `$name`.
+fn parameter_name<'tree>(
+    function: &Node<'tree>,
+    file_contents: &str,
+    name: &str,
+) -> Option<Node<'tree>> {
+    let parameters = function.child_by_field_name("parameters")?;
+    let mut cursor = parameters.walk();
+    let found = parameters
+        .named_children(&mut cursor)
+        .filter(|parameter| PARAMETERS.contains(&parameter.kind()))
+        .filter_map(|parameter| parameter.child_by_field_name("name"))
+        .find(|parameter| &file_contents[parameter.byte_range()] == name);
+    found
+}
+
+/**
+ * The name of a parameter in a function's signature and in the `@param` tag of its doc comment,
+ * so that renaming one renames the other, when the position is on either of them.
+ */
+pub fn linked_editing_ranges(
+    root_node: &Node,
+    file_contents: &str,
+    position: &Position,
+) -> Option<LinkedEditingRanges> {
+    let point = to_point(position);
+    let node = root_node.descendant_for_point_range(point, point)?;
+    let docs = doc_comments(root_node, file_contents);
+    let doc_range = |comment: &Node, text: &str, range: std::ops::Range<usize>| Range {
+        start: comment_position(comment, text, range.start),
+        end: comment_position(comment, text, range.end),
+    };
+
+    let (signature, doc) = match node.kind() {
+        "comment" => {
+            // doc comments come right before what they document
+            let function = node
+                .next_named_sibling()
+                .filter(|function| docs.get(&function.id()) == Some(&node))?;
+            let text = &file_contents[node.byte_range()];
+            let (range, name) = doc_params(text).into_iter().find(|(range, _)| {
+                let range = doc_range(&node, text, range.clone());
+                range.start <= *position && *position <= range.end
+            })?;
+            let parameter = parameter_name(&function, file_contents, name)?;
+            (to_range(&parameter.range()), doc_range(&node, text, range))
+        }
+        _ => {
+            let mut variable = node;
+            while variable.kind() != "variable_name" {
+                variable = variable.parent()?;
+            }
+            let function = variable
+                .parent()
+                .filter(|parameter| PARAMETERS.contains(&parameter.kind()))?
+                .parent()?
+                .parent()?;
+            let comment = docs.get(&function.id())?;
+            let name = &file_contents[variable.byte_range()];
+            let text = &file_contents[comment.byte_range()];
+            let (range, _) = doc_params(text)
+                .into_iter()
+                .find(|(_, param)| *param == name)?;
+            (to_range(&variable.range()), doc_range(comment, text, range))
+        }
+    };
+
+    Some(LinkedEditingRanges {
+        ranges: vec![signature, doc],
+        word_pattern: None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::*;
+
+    use super::linked_editing_ranges;
+    use crate::file::php_parser;
+
+    #[test]
+    fn test_linked_editing_ranges() {
+        let source = "<?php
+class A {
+    /**
+     * @param int $count how many
+     * @param string ...$names
+     */
+    public function f(int $count, string ...$names, $other) {}
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let at = |line, character| Position { line, character };
+        let range = |line, start, end| Range {
+            start: at(line, start),
+            end: at(line, end),
+        };
+        let ranges = |position| {
+            linked_editing_ranges(&tree.root_node(), source, &position).map(|ranges| ranges.ranges)
+        };
+
+        let count = Some(vec![range(6, 26, 32), range(3, 18, 24)]);
+        assert_eq!(count, ranges(at(6, 28)));
+        assert_eq!(count, ranges(at(3, 20)));
+        assert_eq!(
+            Some(vec![range(6, 44, 50), range(4, 24, 30)]),
+            ranges(at(4, 26))
+        );
+        assert_eq!(None, ranges(at(6, 54)));
+        assert_eq!(None, ranges(at(3, 30)));
+    }
+}
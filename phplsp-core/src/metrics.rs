@@ -0,0 +1,171 @@
+use lsp_types::*;
+use tree_sitter::Node;
+
+use crate::config::MetricLimits;
+use crate::file::to_range;
+
+/// Node kinds that are measured, and reported on their name.
+const FUNCTIONS: &[&str] = &["function_definition", "method_declaration"];
+
+/// Node kinds that add a path through a function.
+const DECISIONS: &[&str] = &[
+    "if_statement",
+    "else_if_clause",
+    "for_statement",
+    "foreach_statement",
+    "while_statement",
+    "do_statement",
+    "case_statement",
+    "catch_clause",
+    "conditional_expression",
+    "match_conditional_expression",
+];
+
+/// Binary operators that only evaluate their right side some of the time.
+const SHORT_CIRCUITS: &[&str] = &["&&", "||", "and", "or", "??"];
+
+/// One plus the decisions in a function, including those in its closures.
+fn complexity(node: &Node) -> usize {
+    let mut cursor = node.walk();
+    let mut decisions = 0;
+    for child in node.children(&mut cursor) {
+        let decides = DECISIONS.contains(&child.kind())
+            || (SHORT_CIRCUITS.contains(&child.kind())
+                && node.kind() == "binary_expression"
+                && !child.is_named());
+        if decides {
+            decisions += 1;
+        }
+        // classes in functions are measured on their own
+        if !FUNCTIONS.contains(&child.kind()) && child.kind() != "class_declaration" {
+            decisions += complexity(&child) - 1;
+        }
+    }
+
+    decisions + 1
+}
+
+fn parameters(function: &Node) -> usize {
+    function
+        .child_by_field_name("parameters")
+        .map_or(0, |parameters| {
+            let mut cursor = parameters.walk();
+            let count = parameters
+                .named_children(&mut cursor)
+                .filter(|parameter| parameter.kind() != "comment")
+                .count();
+            count
+        })
+}
+
+fn metric(name: &Node, code: &str, message: String) -> Diagnostic {
+    Diagnostic {
+        range: to_range(&name.range()),
+        severity: Some(DiagnosticSeverity::INFORMATION),
+        code: Some(NumberOrString::String(code.to_string())),
+        source: Some("phplsp".to_string()),
+        message,
+        ..Diagnostic::default()
+    }
+}
+
+/**
+ * Report functions and methods that are more complex, take more parameters, or span more lines
+ * than the configured limits.
+ */
+pub fn metrics(
+    node: &Node,
+    file_contents: &str,
+    limits: &MetricLimits,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    if let (true, Some(name)) = (
+        FUNCTIONS.contains(&node.kind()),
+        node.child_by_field_name("name"),
+    ) {
+        let function = &file_contents[name.byte_range()];
+        let lines = node.end_position().row - node.start_position().row + 1;
+        let measured = [
+            ("complexity", limits.max_complexity, complexity(node)),
+            ("parameters", limits.max_parameters, parameters(node)),
+            ("lines", limits.max_lines, lines),
+        ];
+        for (code, limit, value) in measured {
+            let Some(limit) = limit.filter(|limit| value > *limit) else {
+                continue;
+            };
+            let measure = match code {
+                "complexity" => format!("a cyclomatic complexity of {}", value),
+                _ => format!("{} {}", value, code),
+            };
+            let message = format!(
+                "`{}` has {}, more than the {} allowed",
+                function, measure, limit
+            );
+            diagnostics.push(metric(&name, code, message));
+        }
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        metrics(&child, file_contents, limits, diagnostics);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::metrics;
+    use crate::config::MetricLimits;
+    use crate::file::php_parser;
+
+    #[test]
+    fn test_metrics() {
+        let source = "<?php
+function simple($a) {
+    return $a;
+}
+function branchy($a, $b, $c) {
+    if ($a && $b) {
+        return 1;
+    } elseif ($c ?? false) {
+        return 2;
+    }
+    foreach ($a as $x) {
+        $f = fn($y) => $y ? 1 : 2;
+    }
+    return match ($a) {
+        1, 2 => 3,
+        default => 4,
+    };
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let mut diagnostics = vec![];
+        metrics(
+            &tree.root_node(),
+            source,
+            &MetricLimits::default(),
+            &mut diagnostics,
+        );
+        assert!(diagnostics.is_empty());
+
+        let limits = MetricLimits {
+            max_complexity: Some(5),
+            max_parameters: Some(2),
+            max_lines: Some(10),
+        };
+        metrics(&tree.root_node(), source, &limits, &mut diagnostics);
+        let messages: Vec<String> = diagnostics
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect();
+        assert_eq!(
+            vec![
+                "`branchy` has a cyclomatic complexity of 8, more than the 5 allowed",
+                "`branchy` has 3 parameters, more than the 2 allowed",
+                "`branchy` has 14 lines, more than the 10 allowed",
+            ],
+            messages
+        );
+    }
+}
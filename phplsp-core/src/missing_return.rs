@@ -0,0 +1,204 @@
+//! Functions with a return type that can get to the end of their body without returning a value,
+//! which PHP only finds out about with a `TypeError` when they do.
+
+use lsp_types::*;
+use tree_sitter::Node;
+
+use crate::cfg::ControlFlowGraph;
+use crate::file::to_range;
+use crate::index::WorkspaceIndex;
+use crate::resolve::Resolver;
+use crate::symbols::FileSymbols;
+use crate::types::TypeInference;
+use crate::unreachable::never_returns;
+
+/// Node kinds of functions with a body of statements. Arrow functions always return their expression.
+const FUNCTIONS: &[&str] = &[
+    "function_definition",
+    "method_declaration",
+    "anonymous_function",
+];
+
+/// Whether a function returns values with `yield`, which makes it return a generator instead.
+pub(crate) fn is_generator(node: &Node) -> bool {
+    let mut cursor = node.walk();
+    let found = node
+        .named_children(&mut cursor)
+        .any(|child| match child.kind() {
+            "yield_expression" => true,
+            kind if FUNCTIONS.contains(&kind) || kind == "arrow_function" => false,
+            _ => is_generator(&child),
+        });
+    found
+}
+
+/// What's wrong with getting to the end of a function with a return type, if anything.
+fn falling_off(return_type: &str) -> Option<&'static str> {
+    let return_type = return_type.to_ascii_lowercase();
+    match return_type.as_str() {
+        "void" => None,
+        "never" => Some("can reach the end of its body, but its return type is `never`"),
+        // getting to the end returns `null`
+        _ if return_type.starts_with('?')
+            || return_type
+                .split('|')
+                .any(|member| matches!(member.trim(), "null" | "mixed")) =>
+        {
+            None
+        }
+        _ => Some("can reach the end of its body without returning a value"),
+    }
+}
+
+fn check_function(
+    function: &Node,
+    file_contents: &str,
+    never_returning: &dyn Fn(&Node) -> bool,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let (Some(return_type), Some(body)) = (
+        function.child_by_field_name("return_type"),
+        function.child_by_field_name("body"),
+    ) else {
+        return;
+    };
+    let Some(message) = falling_off(&file_contents[return_type.byte_range()]) else {
+        return;
+    };
+    if is_generator(&body) {
+        return;
+    }
+    let cfg = ControlFlowGraph::with_never_returning(function, file_contents, never_returning);
+    if !cfg.falls_off_end() {
+        return;
+    }
+
+    let name = match function.child_by_field_name("name") {
+        Some(name) => format!("`{}`", &file_contents[name.byte_range()]),
+        None => "closure".to_string(),
+    };
+    // where it falls off: the closing brace
+    let end = body
+        .child(body.child_count().saturating_sub(1))
+        .unwrap_or(body);
+    diagnostics.push(Diagnostic {
+        range: to_range(&end.range()),
+        severity: Some(DiagnosticSeverity::ERROR),
+        code: Some(NumberOrString::String("missing-return".to_string())),
+        source: Some("phplsp".to_string()),
+        message: format!("{} {}", name, message),
+        ..Diagnostic::default()
+    });
+}
+
+fn walk(
+    node: &Node,
+    file_contents: &str,
+    never_returning: &dyn Fn(&Node) -> bool,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    if FUNCTIONS.contains(&node.kind()) {
+        check_function(node, file_contents, never_returning, diagnostics);
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        walk(&child, file_contents, never_returning, diagnostics);
+    }
+}
+
+/**
+ * Report functions, methods and closures with a return type other than `void` that can get to
+ * the end of their body, where PHP returns nothing. Types that take `null` are left alone, since
+ * that's what they return then, and so are generators. Calls of functions whose return type is
+ * `never` count as never returning.
+ */
+pub fn missing_returns(
+    root_node: &Node,
+    file_contents: &str,
+    symbols: &FileSymbols,
+    index: &WorkspaceIndex,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let resolver = Resolver::new(file_contents, symbols, index);
+    let types = TypeInference::new(file_contents, &resolver);
+    let never_returning = |expression: &Node| types.callee(expression).is_some_and(never_returns);
+    walk(root_node, file_contents, &never_returning, diagnostics);
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::Url;
+
+    use super::missing_returns;
+    use crate::file::php_parser;
+    use crate::index::WorkspaceIndex;
+    use crate::symbols::file_symbols;
+
+    #[test]
+    fn test_missing_returns() {
+        let source = "<?php
+function fail(): never { throw new Exception(); }
+function sign(int $n): int {
+    if ($n > 0) {
+        return 1;
+    } elseif ($n < 0) {
+        return -1;
+    }
+}
+function parse(string $s): int {
+    if (is_numeric($s)) {
+        return (int) $s;
+    }
+    fail();
+}
+function first(array $a): int {
+    try {
+        return $a[0];
+    } finally {
+        echo 'done';
+    }
+}
+function find(array $a): ?int { foreach ($a as $x) { return $x; } }
+function items(): iterable { yield 1; }
+function stop(): never { echo 'no'; }
+interface Shape { public function area(): float; }
+$f = function (): string { if (rand()) { return 'a'; } };
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(&Url::parse("file:///a.php").unwrap(), symbols.clone());
+        let mut diagnostics = vec![];
+        missing_returns(
+            &tree.root_node(),
+            source,
+            &symbols,
+            &index,
+            &mut diagnostics,
+        );
+
+        let found: Vec<(u32, String)> = diagnostics
+            .into_iter()
+            .map(|diagnostic| (diagnostic.range.start.line, diagnostic.message))
+            .collect();
+        assert_eq!(
+            vec![
+                (
+                    8,
+                    "`sign` can reach the end of its body without returning a value".to_string()
+                ),
+                (
+                    24,
+                    "`stop` can reach the end of its body, but its return type is `never`"
+                        .to_string()
+                ),
+                (
+                    26,
+                    "closure can reach the end of its body without returning a value".to_string()
+                ),
+            ],
+            found
+        );
+    }
+}
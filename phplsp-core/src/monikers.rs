@@ -0,0 +1,107 @@
+use lsp_types::*;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::file::is_within;
+use crate::symbols::Declaration;
+
+/// The scheme of monikers that name symbols by the composer package that declares them.
+pub const PACKAGIST_SCHEME: &str = "packagist";
+
+/**
+ * The name of the composer package that a file belongs to, like `vendor/package`, from the
+ * closest `composer.json` in the directories it's in. Installed packages have their own, so this
+ * works for files in `vendor` too. Only the `readable` directories are looked in, if there are any.
+ */
+pub fn package_name(file: &Path, readable: Option<&[PathBuf]>) -> Option<String> {
+    let mut dirs = file
+        .ancestors()
+        .skip(1)
+        .take_while(|dir| readable.is_none_or(|readable| is_within(dir, readable)));
+    dirs.find_map(|dir| {
+        let contents = fs::read_to_string(dir.join("composer.json")).ok()?;
+        let composer: serde_json::Value = serde_json::from_str(&contents).ok()?;
+        Some(composer["name"].as_str()?.to_string())
+    })
+}
+
+/**
+ * A moniker like `packagist:vendor/package:App\Models\User::save` for a declaration, so that
+ * tools indexing other repositories can tell they refer to the same symbol. Declarations outside
+ * of any composer package with a name have no moniker.
+ */
+pub fn moniker(
+    uri: &Url,
+    declaration: &Declaration,
+    kind: MonikerKind,
+    readable: Option<&[PathBuf]>,
+) -> Option<Moniker> {
+    let package = package_name(&uri.to_file_path().ok()?, readable)?;
+    Some(Moniker {
+        scheme: PACKAGIST_SCHEME.to_string(),
+        identifier: format!("{}:{}", package, declaration.name),
+        unique: UniquenessLevel::Scheme,
+        kind: Some(kind),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::*;
+
+    use std::fs;
+
+    use super::moniker;
+    use crate::file::php_parser;
+    use crate::symbols::file_symbols;
+
+    #[test]
+    fn test_moniker() {
+        let root = std::env::temp_dir().join("phplsp-test-monikers");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(root.join("vendor/acme/lib/src")).unwrap();
+        fs::write(root.join("composer.json"), r#"{ "name": "acme/app" }"#).unwrap();
+        fs::write(
+            root.join("vendor/acme/lib/composer.json"),
+            r#"{ "name": "acme/lib" }"#,
+        )
+        .unwrap();
+
+        let source = "<?php\nnamespace Acme;\nclass Helper { function run() {} }\n";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let run = symbols
+            .declarations
+            .iter()
+            .find(|declaration| declaration.name == "Acme\\Helper::run")
+            .unwrap();
+
+        let vendored = Url::from_file_path(root.join("vendor/acme/lib/src/Helper.php")).unwrap();
+        assert_eq!(
+            Some(Moniker {
+                scheme: "packagist".to_string(),
+                identifier: "acme/lib:Acme\\Helper::run".to_string(),
+                unique: UniquenessLevel::Scheme,
+                kind: Some(MonikerKind::Import),
+            }),
+            moniker(&vendored, run, MonikerKind::Import, None)
+        );
+        // restricted to its own directory, it can't see the `composer.json` above
+        let readable = [root.join("vendor/acme/lib/src")];
+        assert_eq!(
+            None,
+            moniker(&vendored, run, MonikerKind::Import, Some(&readable))
+        );
+        let own = Url::from_file_path(root.join("src/Helper.php")).unwrap();
+        assert_eq!(
+            "acme/app:Acme\\Helper::run",
+            moniker(&own, run, MonikerKind::Export, None)
+                .unwrap()
+                .identifier
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
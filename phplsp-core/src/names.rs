@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+
+/// What a `use` statement imports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ImportKind {
+    Class,
+    Function,
+    Constant,
+}
+
+/**
+ * The namespace and imports in effect at some point in a file, used to turn names as written in
+ * source into fully qualified names.
+ *
+ * Fully qualified names never have a leading backslash.
+ */
+#[derive(Debug, Default, Clone)]
+pub struct NameContext {
+    namespace: String,
+    imports: HashMap<(ImportKind, String), String>,
+}
+
+fn last_segment(name: &str) -> &str {
+    name.rsplit('\\').next().unwrap_or(name)
+}
+
+impl NameContext {
+    /// Imports only apply to the namespace they are written in, so entering a new namespace
+    /// forgets them.
+    pub fn enter_namespace(&mut self, namespace: &str) {
+        self.namespace = namespace.trim_matches('\\').to_string();
+        self.imports.clear();
+    }
+
+    pub fn import(&mut self, kind: ImportKind, name: &str, alias: Option<&str>) {
+        let name = name.trim_start_matches('\\');
+        let alias = alias.unwrap_or_else(|| last_segment(name));
+        // constants are the only case-sensitive names
+        let alias = match kind {
+            ImportKind::Constant => alias.to_string(),
+            _ => alias.to_lowercase(),
+        };
+        self.imports.insert((kind, alias), name.to_string());
+    }
+
+    /// Fully qualify a name that is being declared in the current namespace.
+    pub fn qualify(&self, name: &str) -> String {
+        if self.namespace.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}\\{}", self.namespace, name)
+        }
+    }
+
+    /// Resolve the qualified or fully qualified parts of a name, i.e. anything that isn't a
+    /// single unqualified segment.
+    fn resolve_qualified(&self, name: &str) -> Option<String> {
+        if let Some(fully_qualified) = name.strip_prefix('\\') {
+            return Some(fully_qualified.to_string());
+        }
+
+        let (first, rest) = name.split_once('\\')?;
+        if first.eq_ignore_ascii_case("namespace") {
+            return Some(self.qualify(rest));
+        }
+
+        match self.imports.get(&(ImportKind::Class, first.to_lowercase())) {
+            Some(imported) => Some(format!("{}\\{}", imported, rest)),
+            None => Some(self.qualify(name)),
+        }
+    }
+
+    pub fn resolve_class(&self, name: &str) -> String {
+        if let Some(resolved) = self.resolve_qualified(name) {
+            return resolved;
+        }
+
+        match self.imports.get(&(ImportKind::Class, name.to_lowercase())) {
+            Some(imported) => imported.clone(),
+            None => self.qualify(name),
+        }
+    }
+
+    /**
+     * Resolve a function or constant name.
+     *
+     * Unqualified names that are not imported fall back to the global namespace at runtime if the
+     * namespaced one doesn't exist, so that fallback is returned as the second candidate.
+     */
+    pub fn resolve_function_or_constant(
+        &self,
+        kind: ImportKind,
+        name: &str,
+    ) -> (String, Option<String>) {
+        if let Some(resolved) = self.resolve_qualified(name) {
+            return (resolved, None);
+        }
+
+        let alias = match kind {
+            ImportKind::Constant => name.to_string(),
+            _ => name.to_lowercase(),
+        };
+        if let Some(imported) = self.imports.get(&(kind, alias)) {
+            return (imported.clone(), None);
+        }
+
+        if self.namespace.is_empty() {
+            (name.to_string(), None)
+        } else {
+            (self.qualify(name), Some(name.to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ImportKind, NameContext};
+
+    #[test]
+    fn test_resolve_class() {
+        let mut names = NameContext::default();
+        names.enter_namespace("App\\Models");
+        names.import(ImportKind::Class, "Foo\\Bar", Some("Baz"));
+        names.import(ImportKind::Class, "\\Other\\Thing", None);
+
+        assert_eq!("Foo\\Bar", names.resolve_class("baz"));
+        assert_eq!("Other\\Thing", names.resolve_class("Thing"));
+        assert_eq!("Other\\Thing\\Inner", names.resolve_class("Thing\\Inner"));
+        assert_eq!("App\\Models\\User", names.resolve_class("User"));
+        assert_eq!("Countable", names.resolve_class("\\Countable"));
+        assert_eq!(
+            "App\\Models\\Sub\\X",
+            names.resolve_class("namespace\\Sub\\X")
+        );
+    }
+
+    #[test]
+    fn test_resolve_function() {
+        let mut names = NameContext::default();
+        assert_eq!(
+            ("strlen".to_string(), None),
+            names.resolve_function_or_constant(ImportKind::Function, "strlen")
+        );
+
+        names.enter_namespace("App");
+        names.import(ImportKind::Function, "Foo\\helper", None);
+        assert_eq!(
+            ("Foo\\helper".to_string(), None),
+            names.resolve_function_or_constant(ImportKind::Function, "helper")
+        );
+        assert_eq!(
+            ("App\\strlen".to_string(), Some("strlen".to_string())),
+            names.resolve_function_or_constant(ImportKind::Function, "strlen")
+        );
+    }
+}
@@ -0,0 +1,404 @@
+use lsp_types::*;
+use tree_sitter::Node;
+
+use crate::code_actions::{QuickFix, SymbolRename};
+use crate::config::{Case, CaseRule, NamingRules, RuleSeverity, SuffixRule};
+use crate::dead_stores::{has_dynamic_scope, SUPERGLOBALS};
+use crate::file::to_range;
+use crate::symbols::{file_symbols, DeclarationKind};
+
+/// Node kinds that have their own variables. Closures are looked at with what they're in.
+const SCOPES: &[&str] = &["function_definition", "method_declaration"];
+
+/// Node kinds whose variables belong to another scope, or aren't variables at all.
+const OTHER_SCOPES: &[&str] = &[
+    "function_definition",
+    "method_declaration",
+    "declaration_list",
+    "scoped_property_access_expression",
+];
+
+impl Case {
+    fn describe(self) -> &'static str {
+        match self {
+            Case::Studly => "StudlyCase",
+            Case::Camel => "camelCase",
+            Case::Snake => "snake_case",
+            Case::UpperSnake => "UPPER_SNAKE_CASE",
+        }
+    }
+
+    /// Whether a name is written in this case. Leading underscores are allowed in any case.
+    fn matches(self, name: &str) -> bool {
+        let name = name.trim_start_matches('_');
+        let first = name.chars().next();
+        match self {
+            Case::Studly => !name.contains('_') && first.is_none_or(|c| !c.is_lowercase()),
+            Case::Camel => !name.contains('_') && first.is_none_or(|c| !c.is_uppercase()),
+            Case::Snake => !name.chars().any(char::is_uppercase),
+            Case::UpperSnake => !name.chars().any(char::is_lowercase),
+        }
+    }
+
+    /// A name written in this case instead, keeping its leading underscores.
+    fn convert(self, name: &str) -> String {
+        let trimmed = name.trim_start_matches('_');
+        let underscores = &name[..name.len() - trimmed.len()];
+        let words = words(trimmed);
+        let capitalize = |word: &String| {
+            let mut chars = word.chars();
+            chars.next().map_or(String::new(), |first| {
+                first
+                    .to_uppercase()
+                    .chain(chars.flat_map(char::to_lowercase))
+                    .collect()
+            })
+        };
+
+        let converted = match self {
+            Case::Studly => words.iter().map(capitalize).collect(),
+            Case::Camel => words
+                .iter()
+                .enumerate()
+                .map(|(i, word)| match i {
+                    0 => word.to_lowercase(),
+                    _ => capitalize(word),
+                })
+                .collect(),
+            Case::Snake => words
+                .iter()
+                .map(|word| word.to_lowercase())
+                .collect::<Vec<_>>()
+                .join("_"),
+            Case::UpperSnake => words
+                .iter()
+                .map(|word| word.to_uppercase())
+                .collect::<Vec<_>>()
+                .join("_"),
+        };
+        format!("{}{}", underscores, converted)
+    }
+}
+
+/**
+ * The words in a name, split at underscores and where the case changes. A run of capitals is one
+ * word, so `HTTPServer` is `HTTP` and `Server`.
+ */
+fn words(name: &str) -> Vec<String> {
+    let chars: Vec<char> = name.chars().collect();
+    let mut words = vec![];
+    let mut word = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if c == '_' {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            continue;
+        }
+
+        let after_lower = i > 0 && (chars[i - 1].is_lowercase() || chars[i - 1].is_numeric());
+        let before_lower = chars.get(i + 1).is_some_and(|next| next.is_lowercase());
+        if c.is_uppercase() && !word.is_empty() && (after_lower || before_lower) {
+            words.push(std::mem::take(&mut word));
+        }
+        word.push(c);
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+
+    words
+}
+
+fn naming_diagnostic(
+    range: Range,
+    severity: RuleSeverity,
+    message: String,
+    fix: QuickFix,
+) -> Diagnostic {
+    let mut diagnostic = Diagnostic {
+        range,
+        severity: Some(severity.into()),
+        code: Some(NumberOrString::String("naming".to_string())),
+        source: Some("phplsp".to_string()),
+        message,
+        ..Diagnostic::default()
+    };
+    fix.attach(&mut diagnostic);
+    diagnostic
+}
+
+fn rename_fix(name: &str, new_name: String) -> QuickFix {
+    QuickFix {
+        title: format!("Rename to `{}`", new_name),
+        edits: vec![],
+        rename: Some(SymbolRename {
+            name: name.to_string(),
+            new_name,
+        }),
+    }
+}
+
+/// The variables named in a scope, in the order they're first named, with everywhere they are.
+fn scope_variables<'a, 'tree>(
+    node: &Node<'tree>,
+    file_contents: &'a str,
+    variables: &mut Vec<(&'a str, Vec<Node<'tree>>)>,
+) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        if OTHER_SCOPES.contains(&child.kind()) {
+            continue;
+        }
+        if child.kind() == "variable_name" {
+            let name = file_contents[child.byte_range()].trim_start_matches('$');
+            match variables.iter_mut().find(|(other, _)| *other == name) {
+                Some((_, nodes)) => nodes.push(child),
+                None => variables.push((name, vec![child])),
+            }
+            continue;
+        }
+        scope_variables(&child, file_contents, variables);
+    }
+}
+
+fn has_kind(node: &Node, kind: &str) -> bool {
+    let mut cursor = node.walk();
+    let found = node.kind() == kind
+        || node
+            .named_children(&mut cursor)
+            .any(|child| has_kind(&child, kind));
+    found
+}
+
+/**
+ * Check the variables of every function. Variables are renamed within their function, unless
+ * that could change what the code does: when they're parameters, which can be passed by name,
+ * globals, or the function could get at them by name.
+ */
+fn variable_names(
+    node: &Node,
+    file_contents: &str,
+    rule: &CaseRule,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    if SCOPES.contains(&node.kind()) {
+        let case = rule.case.unwrap_or(Case::Camel);
+        let mut variables = vec![];
+        scope_variables(node, file_contents, &mut variables);
+        let parameters = node.child_by_field_name("parameters");
+        let renamable =
+            !has_dynamic_scope(node, file_contents) && !has_kind(node, "global_declaration");
+
+        for (name, nodes) in &variables {
+            if case.matches(name) || SUPERGLOBALS.contains(name) {
+                continue;
+            }
+            let new_name = case.convert(name);
+            let is_parameter = parameters.is_some_and(|parameters| {
+                nodes
+                    .iter()
+                    .any(|node| parameters.byte_range().contains(&node.start_byte()))
+            });
+            let taken = variables.iter().any(|(other, _)| *other == new_name);
+            let fix = QuickFix {
+                title: format!("Rename to `${}`", new_name),
+                edits: nodes
+                    .iter()
+                    .map(|node| TextEdit {
+                        range: to_range(&node.range()),
+                        new_text: format!("${}", new_name),
+                    })
+                    .collect(),
+                rename: None,
+            };
+            let mut diagnostic = naming_diagnostic(
+                to_range(&nodes[0].range()),
+                rule.severity,
+                format!("`${}` should be written in {}", name, case.describe()),
+                fix,
+            );
+            if !renamable || is_parameter || taken {
+                diagnostic.data = None;
+            }
+            diagnostics.push(diagnostic);
+        }
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        variable_names(&child, file_contents, rule, diagnostics);
+    }
+}
+
+/**
+ * Report names that don't follow the naming conventions that are turned on, each with a fix that
+ * renames them. Declarations are renamed along with everything that refers to them, while local
+ * variables are renamed within their function.
+ *
+ * Magic methods like `__construct` and superglobals can't be renamed, so they're never reported.
+ */
+pub fn naming(
+    root_node: &Node,
+    file_contents: &str,
+    rules: &NamingRules,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let symbols = file_symbols(root_node, file_contents);
+    for declaration in &symbols.declarations {
+        let name = declaration.short_name();
+        let (case_rule, default_case, suffix_rule) = match declaration.kind {
+            DeclarationKind::Class | DeclarationKind::Enum => (&rules.classes, Case::Studly, &None),
+            DeclarationKind::Interface => (&rules.classes, Case::Studly, &rules.interfaces),
+            DeclarationKind::Trait => (&rules.classes, Case::Studly, &rules.traits),
+            DeclarationKind::Method if !name.starts_with("__") => {
+                (&rules.methods, Case::Camel, &None)
+            }
+            DeclarationKind::Constant => (&rules.constants, Case::UpperSnake, &None),
+            _ => continue,
+        };
+
+        if let Some(rule) = case_rule {
+            let case = rule.case.unwrap_or(default_case);
+            if !case.matches(name) {
+                diagnostics.push(naming_diagnostic(
+                    declaration.range,
+                    rule.severity,
+                    format!("`{}` should be written in {}", name, case.describe()),
+                    rename_fix(&declaration.name, case.convert(name)),
+                ));
+            }
+        }
+        if let Some(SuffixRule { severity, suffix }) = suffix_rule {
+            if !name.ends_with(suffix.as_str()) {
+                diagnostics.push(naming_diagnostic(
+                    declaration.range,
+                    *severity,
+                    format!("`{}` should end with `{}`", name, suffix),
+                    rename_fix(&declaration.name, format!("{}{}", name, suffix)),
+                ));
+            }
+        }
+    }
+
+    if let Some(rule) = &rules.variables {
+        variable_names(root_node, file_contents, rule, diagnostics);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{naming, words};
+    use crate::code_actions::QuickFix;
+    use crate::config::{Case, CaseRule, NamingRules, RuleSeverity, SuffixRule};
+    use crate::file::php_parser;
+
+    #[test]
+    fn test_cases() {
+        assert_eq!(vec!["HTTP", "Server", "v2"], words("HTTPServer_v2"));
+        assert_eq!("UserProfile", Case::Studly.convert("user_profile"));
+        assert_eq!("userId", Case::Camel.convert("UserID"));
+        assert_eq!("_user_id", Case::Snake.convert("_userId"));
+        assert_eq!("MAX_SIZE", Case::UpperSnake.convert("maxSize"));
+        assert!(Case::Studly.matches("HTTPServer"));
+        assert!(!Case::Camel.matches("get_name"));
+    }
+
+    #[test]
+    fn test_naming() {
+        let source = "<?php
+namespace App;
+interface Shape {}
+class user_profile {
+    const maxSize = 1;
+    public function get_name($first_name) {
+        $full_name = $first_name;
+        return $full_name;
+    }
+    public function __construct() {}
+}
+function f() {
+    global $some_global;
+    $local_value = 1;
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let rules = NamingRules {
+            classes: Some(CaseRule::default()),
+            methods: Some(CaseRule::default()),
+            variables: Some(CaseRule {
+                severity: RuleSeverity::Warning,
+                case: None,
+            }),
+            constants: Some(CaseRule::default()),
+            interfaces: Some(SuffixRule {
+                severity: RuleSeverity::Hint,
+                suffix: "Interface".to_string(),
+            }),
+            traits: None,
+        };
+        let mut diagnostics = vec![];
+        naming(&tree.root_node(), source, &rules, &mut diagnostics);
+
+        let found: Vec<(u32, String, Option<String>)> = diagnostics
+            .iter()
+            .map(|diagnostic| {
+                let fix = QuickFix::of(diagnostic).map(|fix| fix.title);
+                (diagnostic.range.start.line, diagnostic.message.clone(), fix)
+            })
+            .collect();
+        let rename = |name: &str| Some(format!("Rename to `{}`", name));
+        assert_eq!(
+            vec![
+                (
+                    2,
+                    "`Shape` should end with `Interface`".to_string(),
+                    rename("ShapeInterface")
+                ),
+                (
+                    3,
+                    "`user_profile` should be written in StudlyCase".to_string(),
+                    rename("UserProfile")
+                ),
+                (
+                    4,
+                    "`maxSize` should be written in UPPER_SNAKE_CASE".to_string(),
+                    rename("MAX_SIZE")
+                ),
+                (
+                    5,
+                    "`get_name` should be written in camelCase".to_string(),
+                    rename("getName")
+                ),
+                (
+                    5,
+                    "`$first_name` should be written in camelCase".to_string(),
+                    None
+                ),
+                (
+                    6,
+                    "`$full_name` should be written in camelCase".to_string(),
+                    rename("$fullName")
+                ),
+                (
+                    12,
+                    "`$some_global` should be written in camelCase".to_string(),
+                    None
+                ),
+                (
+                    13,
+                    "`$local_value` should be written in camelCase".to_string(),
+                    None
+                ),
+            ],
+            found
+        );
+
+        let fix = QuickFix::of(&diagnostics[5]).unwrap();
+        assert_eq!(2, fix.edits.len());
+        assert_eq!(
+            "App\\user_profile::get_name",
+            QuickFix::of(&diagnostics[3]).unwrap().rename.unwrap().name
+        );
+    }
+}
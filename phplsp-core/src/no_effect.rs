@@ -0,0 +1,96 @@
+use lsp_types::*;
+use tree_sitter::Node;
+
+use crate::dead_stores::is_pure;
+use crate::file::to_range;
+
+/// Operators that compare, which are easy to write by mistake instead of assigning.
+const COMPARISONS: &[&str] = &["==", "==="];
+
+/**
+ * Report statements that only work out a value and throw it away, like `$a === $b;`,
+ * `count($x);` or `$this->name;`, which are usually a comparison written instead of an
+ * assignment, or a missing assignment.
+ */
+pub fn no_effect(node: &Node, file_contents: &str, diagnostics: &mut Vec<Diagnostic>) {
+    if node.kind() == "expression_statement" && !node.has_error() {
+        let Some(expression) = node.named_child(0) else {
+            return;
+        };
+        if !is_pure(&expression, file_contents) {
+            return;
+        }
+
+        let operator = expression
+            .child_by_field_name("operator")
+            .filter(|_| expression.kind() == "binary_expression")
+            .map(|operator| &file_contents[operator.byte_range()]);
+        let message = match operator {
+            Some(operator) if COMPARISONS.contains(&operator) => format!(
+                "the result of `{}` is thrown away; did you mean to assign with `=`?",
+                operator
+            ),
+            _ => "this statement does nothing, since its value is thrown away".to_string(),
+        };
+        diagnostics.push(Diagnostic {
+            range: to_range(&expression.range()),
+            severity: Some(DiagnosticSeverity::WARNING),
+            code: Some(NumberOrString::String("no-effect".to_string())),
+            source: Some("phplsp".to_string()),
+            message,
+            ..Diagnostic::default()
+        });
+        return;
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        no_effect(&child, file_contents, diagnostics);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::no_effect;
+    use crate::file::php_parser;
+
+    #[test]
+    fn test_no_effect() {
+        let source = "<?php
+function f($a, $b, $x) {
+    $a === $b;
+    count($x);
+    $this->name;
+    \\strlen($a) + 1;
+    $a = $b;
+    $a++;
+    count(g());
+    foo($x);
+    fn() => $x;
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let mut diagnostics = vec![];
+        no_effect(&tree.root_node(), source, &mut diagnostics);
+
+        let found: Vec<(u32, String)> = diagnostics
+            .into_iter()
+            .map(|diagnostic| (diagnostic.range.start.line, diagnostic.message))
+            .collect();
+        let nothing = "this statement does nothing, since its value is thrown away";
+        assert_eq!(
+            vec![
+                (
+                    2,
+                    "the result of `===` is thrown away; did you mean to assign with `=`?"
+                        .to_string()
+                ),
+                (3, nothing.to_string()),
+                (4, nothing.to_string()),
+                (5, nothing.to_string()),
+                (10, nothing.to_string()),
+            ],
+            found
+        );
+    }
+}
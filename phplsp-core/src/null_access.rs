@@ -0,0 +1,364 @@
+//! Methods called and properties read on what can be `null`.
+
+use lsp_types::*;
+use tree_sitter::Node;
+
+use crate::cfg::is_exit;
+use crate::file::to_range;
+use crate::index::WorkspaceIndex;
+use crate::resolve::Resolver;
+use crate::symbols::FileSymbols;
+use crate::types::{Type, TypeInference};
+
+/// Nodes that have a scope of their own, which guards outside of them don't reach into.
+const SCOPES: &[&str] = &[
+    "function_definition",
+    "method_declaration",
+    "anonymous_function",
+    "arrow_function",
+    "class_declaration",
+];
+
+fn text<'a>(node: &Node, contents: &'a str) -> &'a str {
+    &contents[node.byte_range()]
+}
+
+/// The only argument of a call to one of some functions, like `isset($user)`.
+fn called_with<'tree>(
+    node: &Node<'tree>,
+    contents: &str,
+    functions: &[&str],
+) -> Option<Node<'tree>> {
+    let function = node.child_by_field_name("function")?;
+    if node.kind() != "function_call_expression"
+        || !functions
+            .iter()
+            .any(|name| text(&function, contents).eq_ignore_ascii_case(name))
+    {
+        return None;
+    }
+    let arguments = node.child_by_field_name("arguments")?;
+    let argument = arguments.named_child(0)?;
+    argument.named_child(0)
+}
+
+/**
+ * Whether a condition having a value means that an expression isn't `null`, like `$user !== null`
+ * being true or `is_null($user)` being false.
+ */
+fn guards(condition: &Node, subject: &str, value: bool, contents: &str) -> bool {
+    let is_subject = |node: &Node| text(node, contents) == subject;
+    let is_null = |node: &Node| node.kind() == "null";
+
+    match condition.kind() {
+        "parenthesized_expression" => condition
+            .named_child(0)
+            .is_some_and(|inner| guards(&inner, subject, value, contents)),
+        "unary_op_expression" => {
+            let negates = condition
+                .child_by_field_name("operator")
+                .is_some_and(|operator| text(&operator, contents) == "!");
+            negates
+                && condition
+                    .child_by_field_name("argument")
+                    .is_some_and(|argument| guards(&argument, subject, !value, contents))
+        }
+        "assignment_expression" => {
+            value
+                && condition
+                    .child_by_field_name("left")
+                    .is_some_and(|left| is_subject(&left))
+        }
+        "binary_expression" => {
+            let (Some(left), Some(operator), Some(right)) = (
+                condition.child_by_field_name("left"),
+                condition.child_by_field_name("operator"),
+                condition.child_by_field_name("right"),
+            ) else {
+                return false;
+            };
+            let compares =
+                (is_subject(&left) && is_null(&right)) || (is_null(&left) && is_subject(&right));
+            match text(&operator, contents).to_lowercase().as_str() {
+                "&&" | "and" if value => {
+                    guards(&left, subject, true, contents)
+                        || guards(&right, subject, true, contents)
+                }
+                "||" | "or" if !value => {
+                    guards(&left, subject, false, contents)
+                        || guards(&right, subject, false, contents)
+                }
+                "||" | "or" => {
+                    guards(&left, subject, true, contents)
+                        && guards(&right, subject, true, contents)
+                }
+                "!==" | "!=" | "<>" => compares && value,
+                "===" | "==" => compares && !value,
+                "instanceof" => is_subject(&left) && value,
+                _ => false,
+            }
+        }
+        "function_call_expression" => {
+            if let Some(argument) = called_with(condition, contents, &["isset"]) {
+                return value && is_subject(&argument);
+            }
+            let argument = called_with(condition, contents, &["is_null", "empty"]);
+            !value && argument.is_some_and(|argument| is_subject(&argument))
+        }
+        _ => value && is_subject(condition),
+    }
+}
+
+/// Whether a statement never lets what comes after it run, like `return` or a block that throws.
+fn leaves(statement: &Node, contents: &str) -> bool {
+    match statement.kind() {
+        "return_statement" | "break_statement" | "continue_statement" | "exit_statement" => true,
+        "compound_statement" => {
+            let mut cursor = statement.walk();
+            let last = statement.named_children(&mut cursor).last();
+            last.is_some_and(|last| leaves(&last, contents))
+        }
+        "expression_statement" => statement.named_child(0).is_some_and(|expression| {
+            expression.kind() == "throw_expression" || is_exit(&expression, contents)
+        }),
+        _ => false,
+    }
+}
+
+/// Whether a statement that runs before others makes sure that an expression isn't `null` then.
+fn guards_after(statement: &Node, subject: &str, contents: &str) -> bool {
+    match statement.kind() {
+        // `if ($user === null) { return; }`
+        "if_statement" => {
+            let (Some(condition), Some(body)) = (
+                statement.child_by_field_name("condition"),
+                statement.child_by_field_name("body"),
+            ) else {
+                return false;
+            };
+            statement.child_by_field_name("alternative").is_none()
+                && leaves(&body, contents)
+                && guards(&condition, subject, false, contents)
+        }
+        // `assert($user !== null);`
+        "expression_statement" => statement
+            .named_child(0)
+            .and_then(|expression| called_with(&expression, contents, &["assert"]))
+            .is_some_and(|asserted| guards(&asserted, subject, true, contents)),
+        _ => false,
+    }
+}
+
+/// Whether something around an expression, or a statement before it, makes sure that a subject
+/// isn't `null` there.
+pub(crate) fn is_guarded(node: &Node, subject: &str, contents: &str) -> bool {
+    let mut child = *node;
+    while let Some(parent) = child.parent() {
+        if SCOPES.contains(&parent.kind()) {
+            return false;
+        }
+        let is_field = |field: &str| {
+            parent
+                .child_by_field_name(field)
+                .is_some_and(|node| node.id() == child.id())
+        };
+        let condition = parent.child_by_field_name("condition");
+        let guarded = match parent.kind() {
+            "if_statement" | "else_if_clause" | "while_statement" if is_field("body") => {
+                condition.is_some_and(|condition| guards(&condition, subject, true, contents))
+            }
+            "if_statement" if is_field("alternative") => {
+                condition.is_some_and(|condition| guards(&condition, subject, false, contents))
+            }
+            "conditional_expression" if is_field("body") => {
+                condition.is_some_and(|condition| guards(&condition, subject, true, contents))
+            }
+            "conditional_expression" if is_field("alternative") => {
+                condition.is_some_and(|condition| guards(&condition, subject, false, contents))
+            }
+            "binary_expression" if is_field("right") => {
+                let left = parent.child_by_field_name("left");
+                let operator = parent
+                    .child_by_field_name("operator")
+                    .map(|operator| text(&operator, contents).to_lowercase());
+                match (left, operator.as_deref()) {
+                    (Some(left), Some("&&" | "and")) => guards(&left, subject, true, contents),
+                    (Some(left), Some("||" | "or")) => guards(&left, subject, false, contents),
+                    _ => false,
+                }
+            }
+            "compound_statement" | "program" | "colon_block" => {
+                let mut statement = child.prev_named_sibling();
+                let mut found = false;
+                while let Some(previous) = statement {
+                    if guards_after(&previous, subject, contents) {
+                        found = true;
+                        break;
+                    }
+                    statement = previous.prev_named_sibling();
+                }
+                found
+            }
+            _ => false,
+        };
+        if guarded {
+            return true;
+        }
+        child = parent;
+    }
+
+    false
+}
+
+fn accesses<'tree>(node: &Node<'tree>, found: &mut Vec<Node<'tree>>) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        if matches!(
+            child.kind(),
+            "member_call_expression" | "member_access_expression"
+        ) {
+            found.push(child);
+        }
+        accesses(&child, found);
+    }
+}
+
+/**
+ * Warn about methods called and properties read with `->` on what can be `null`, like the value
+ * of a function that returns `?User`, unless a condition around the access or an early `return`,
+ * `throw` or `assert` before it rules `null` out. `?->` is what to use otherwise.
+ */
+pub fn null_accesses(
+    root_node: &Node,
+    file_contents: &str,
+    symbols: &FileSymbols,
+    index: &WorkspaceIndex,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let resolver = Resolver::new(file_contents, symbols, index);
+    let types = TypeInference::new(file_contents, &resolver);
+
+    let mut found = vec![];
+    accesses(root_node, &mut found);
+    for access in found {
+        let (Some(object), Some(name)) = (
+            access.child_by_field_name("object"),
+            access.child_by_field_name("name"),
+        ) else {
+            continue;
+        };
+        // assigning to a property of `null` is an error that assignments are checked for
+        let is_assigned = access.parent().is_some_and(|parent| {
+            parent.kind() == "assignment_expression"
+                && parent
+                    .child_by_field_name("left")
+                    .is_some_and(|left| left.id() == access.id())
+        });
+        let nullable = match types.expression(&object) {
+            Some(Type::Union(members)) => members.contains(&Type::Null),
+            _ => false,
+        };
+        let subject = text(&object, file_contents);
+        if is_assigned || !nullable || is_guarded(&access, subject, file_contents) {
+            continue;
+        }
+
+        let what = match access.kind() {
+            "member_call_expression" => "calling",
+            _ => "reading",
+        };
+        diagnostics.push(Diagnostic {
+            range: to_range(&name.range()),
+            severity: Some(DiagnosticSeverity::WARNING),
+            code: Some(NumberOrString::String("null-access".to_string())),
+            source: Some("phplsp".to_string()),
+            message: format!(
+                "{} `{}` on `{}`, which can be `null`",
+                what,
+                text(&name, file_contents),
+                subject
+            ),
+            ..Diagnostic::default()
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::Url;
+
+    use super::null_accesses;
+    use crate::file::php_parser;
+    use crate::index::WorkspaceIndex;
+    use crate::symbols::file_symbols;
+
+    #[test]
+    fn test_null_accesses() {
+        let source = "<?php
+class User {
+    public string $name;
+    public function save(): void {}
+}
+function find(int $id): ?User {}
+function show(?User $user, ?User $other) {
+    echo $user->name;
+    find(1)->save();
+    find(1)?->save();
+    if ($user !== null) {
+        $user->save();
+    }
+    $user && $user->save();
+    echo $user === null ? '' : $user->name;
+    if (!$other) {
+        throw new Exception();
+    }
+    $other->save();
+}
+function edit(int $id) {
+    $user = find($id);
+    assert($user instanceof User);
+    $user->save();
+    $found = find($id);
+    if (is_null($found)) {
+        $found->save();
+    } else {
+        $found->save();
+    }
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(&Url::parse("file:///a.php").unwrap(), symbols.clone());
+        let mut diagnostics = vec![];
+        null_accesses(
+            &tree.root_node(),
+            source,
+            &symbols,
+            &index,
+            &mut diagnostics,
+        );
+
+        let found: Vec<(u32, String)> = diagnostics
+            .into_iter()
+            .map(|diagnostic| (diagnostic.range.start.line, diagnostic.message))
+            .collect();
+        assert_eq!(
+            vec![
+                (
+                    7,
+                    "reading `name` on `$user`, which can be `null`".to_string()
+                ),
+                (
+                    8,
+                    "calling `save` on `find(1)`, which can be `null`".to_string()
+                ),
+                (
+                    26,
+                    "calling `save` on `$found`, which can be `null`".to_string()
+                ),
+            ],
+            found
+        );
+    }
+}
@@ -0,0 +1,529 @@
+//! Options of built-in functions that take them as constants or array keys, like `curl_setopt`,
+//! and the types of values they expect, which PHP only checks when the code runs.
+
+use lsp_types::*;
+use tree_sitter::Node;
+
+use crate::document_links::string_contents;
+use crate::file::to_range;
+use crate::index::WorkspaceIndex;
+use crate::resolve::Resolver;
+use crate::symbols::FileSymbols;
+use crate::types::{Type, TypeInference};
+
+/// The type of value that an option expects.
+#[derive(Debug, Clone, Copy)]
+enum Expects {
+    /// A switch, which is usually given as `true` or `1`.
+    Flag,
+    Int,
+    Float,
+    String,
+    Array,
+    StringOrArray,
+    Callable,
+}
+
+impl Expects {
+    fn to_type(self) -> Type {
+        match self {
+            Expects::Flag => Type::union([Type::Bool, Type::Int]),
+            Expects::Int => Type::Int,
+            Expects::Float => Type::Float,
+            Expects::String => Type::String,
+            Expects::Array => Type::Array(None),
+            Expects::StringOrArray => Type::union([Type::String, Type::Array(None)]),
+            // function names and `[$object, 'method']` too
+            Expects::Callable => Type::union([
+                Type::Class("Closure".to_string()),
+                Type::String,
+                Type::Array(None),
+            ]),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Expects::Flag => "bool",
+            Expects::Int => "int",
+            Expects::Float => "float",
+            Expects::String => "string",
+            Expects::Array => "array",
+            Expects::StringOrArray => "string or array",
+            Expects::Callable => "callable",
+        }
+    }
+}
+
+/// Options by name, and the types of their values.
+type Options = &'static [(&'static str, Expects)];
+
+/// Options of `curl_setopt`. Those that take a stream, like `CURLOPT_FILE`, aren't checked.
+const CURL_OPTIONS: Options = &[
+    ("CURLINFO_HEADER_OUT", Expects::Flag),
+    ("CURLOPT_ACCEPT_ENCODING", Expects::String),
+    ("CURLOPT_AUTOREFERER", Expects::Flag),
+    ("CURLOPT_BUFFERSIZE", Expects::Int),
+    ("CURLOPT_CAINFO", Expects::String),
+    ("CURLOPT_CAPATH", Expects::String),
+    ("CURLOPT_CERTINFO", Expects::Flag),
+    ("CURLOPT_CONNECTTIMEOUT", Expects::Int),
+    ("CURLOPT_CONNECTTIMEOUT_MS", Expects::Int),
+    ("CURLOPT_CONNECT_TO", Expects::Array),
+    ("CURLOPT_COOKIE", Expects::String),
+    ("CURLOPT_COOKIEFILE", Expects::String),
+    ("CURLOPT_COOKIEJAR", Expects::String),
+    ("CURLOPT_COOKIESESSION", Expects::Flag),
+    ("CURLOPT_CUSTOMREQUEST", Expects::String),
+    ("CURLOPT_DNS_CACHE_TIMEOUT", Expects::Int),
+    ("CURLOPT_ENCODING", Expects::String),
+    ("CURLOPT_FAILONERROR", Expects::Flag),
+    ("CURLOPT_FOLLOWLOCATION", Expects::Flag),
+    ("CURLOPT_FORBID_REUSE", Expects::Flag),
+    ("CURLOPT_FRESH_CONNECT", Expects::Flag),
+    ("CURLOPT_HEADER", Expects::Flag),
+    ("CURLOPT_HEADERFUNCTION", Expects::Callable),
+    ("CURLOPT_HTTP200ALIASES", Expects::Array),
+    ("CURLOPT_HTTPAUTH", Expects::Int),
+    ("CURLOPT_HTTPGET", Expects::Flag),
+    ("CURLOPT_HTTPHEADER", Expects::Array),
+    ("CURLOPT_HTTPPROXYTUNNEL", Expects::Flag),
+    ("CURLOPT_HTTP_VERSION", Expects::Int),
+    ("CURLOPT_INFILESIZE", Expects::Int),
+    ("CURLOPT_INTERFACE", Expects::String),
+    ("CURLOPT_IPRESOLVE", Expects::Int),
+    ("CURLOPT_LOW_SPEED_LIMIT", Expects::Int),
+    ("CURLOPT_LOW_SPEED_TIME", Expects::Int),
+    ("CURLOPT_MAXCONNECTS", Expects::Int),
+    ("CURLOPT_MAXREDIRS", Expects::Int),
+    ("CURLOPT_NOBODY", Expects::Flag),
+    ("CURLOPT_NOPROGRESS", Expects::Flag),
+    ("CURLOPT_NOSIGNAL", Expects::Flag),
+    ("CURLOPT_PASSWORD", Expects::String),
+    ("CURLOPT_PINNEDPUBLICKEY", Expects::String),
+    ("CURLOPT_PORT", Expects::Int),
+    ("CURLOPT_POST", Expects::Flag),
+    ("CURLOPT_POSTFIELDS", Expects::StringOrArray),
+    ("CURLOPT_POSTQUOTE", Expects::Array),
+    ("CURLOPT_PROGRESSFUNCTION", Expects::Callable),
+    ("CURLOPT_PROTOCOLS", Expects::Int),
+    ("CURLOPT_PROXY", Expects::String),
+    ("CURLOPT_PROXYHEADER", Expects::Array),
+    ("CURLOPT_PROXYPORT", Expects::Int),
+    ("CURLOPT_PROXYTYPE", Expects::Int),
+    ("CURLOPT_PROXYUSERPWD", Expects::String),
+    ("CURLOPT_PUT", Expects::Flag),
+    ("CURLOPT_QUOTE", Expects::Array),
+    ("CURLOPT_RANGE", Expects::String),
+    ("CURLOPT_READFUNCTION", Expects::Callable),
+    ("CURLOPT_REDIR_PROTOCOLS", Expects::Int),
+    ("CURLOPT_REFERER", Expects::String),
+    ("CURLOPT_RESOLVE", Expects::Array),
+    ("CURLOPT_RESUME_FROM", Expects::Int),
+    ("CURLOPT_RETURNTRANSFER", Expects::Flag),
+    ("CURLOPT_SSLCERT", Expects::String),
+    ("CURLOPT_SSLCERTPASSWD", Expects::String),
+    ("CURLOPT_SSLKEY", Expects::String),
+    ("CURLOPT_SSLKEYPASSWD", Expects::String),
+    ("CURLOPT_SSLVERSION", Expects::Int),
+    // `true` used to be taken as 1, which doesn't check the host name
+    ("CURLOPT_SSL_VERIFYHOST", Expects::Int),
+    ("CURLOPT_SSL_VERIFYPEER", Expects::Flag),
+    ("CURLOPT_TCP_NODELAY", Expects::Flag),
+    ("CURLOPT_TIMEOUT", Expects::Int),
+    ("CURLOPT_TIMEOUT_MS", Expects::Int),
+    ("CURLOPT_UNIX_SOCKET_PATH", Expects::String),
+    ("CURLOPT_UNRESTRICTED_AUTH", Expects::Flag),
+    ("CURLOPT_UPLOAD", Expects::Flag),
+    ("CURLOPT_URL", Expects::String),
+    ("CURLOPT_USERAGENT", Expects::String),
+    ("CURLOPT_USERNAME", Expects::String),
+    ("CURLOPT_USERPWD", Expects::String),
+    ("CURLOPT_VERBOSE", Expects::Flag),
+    ("CURLOPT_WRITEFUNCTION", Expects::Callable),
+    ("CURLOPT_XFERINFOFUNCTION", Expects::Callable),
+    ("CURLOPT_XOAUTH2_BEARER", Expects::String),
+];
+
+/// Options of `socket_set_option`.
+const SOCKET_OPTIONS: Options = &[
+    ("IPV6_MULTICAST_HOPS", Expects::Int),
+    ("IPV6_MULTICAST_LOOP", Expects::Flag),
+    ("IPV6_V6ONLY", Expects::Flag),
+    ("IP_MULTICAST_LOOP", Expects::Flag),
+    ("IP_MULTICAST_TTL", Expects::Int),
+    ("MCAST_JOIN_GROUP", Expects::Array),
+    ("MCAST_LEAVE_GROUP", Expects::Array),
+    ("SO_BROADCAST", Expects::Flag),
+    ("SO_DEBUG", Expects::Flag),
+    ("SO_DONTROUTE", Expects::Flag),
+    ("SO_KEEPALIVE", Expects::Flag),
+    ("SO_LINGER", Expects::Array),
+    ("SO_OOBINLINE", Expects::Flag),
+    ("SO_RCVBUF", Expects::Int),
+    ("SO_RCVLOWAT", Expects::Int),
+    ("SO_RCVTIMEO", Expects::Array),
+    ("SO_REUSEADDR", Expects::Flag),
+    ("SO_REUSEPORT", Expects::Flag),
+    ("SO_SNDBUF", Expects::Int),
+    ("SO_SNDLOWAT", Expects::Int),
+    ("SO_SNDTIMEO", Expects::Array),
+    ("TCP_NODELAY", Expects::Flag),
+];
+
+/**
+ * Functions that take an option as a constant: the argument it's at, the prefixes of their
+ * options, and the options whose values we know the types of.
+ */
+const OPTION_FUNCTIONS: &[(&str, usize, &[&str], Options)] = &[
+    ("curl_setopt", 1, &["CURLOPT_"], CURL_OPTIONS),
+    (
+        "socket_set_option",
+        2,
+        &["SO_", "TCP_", "IP_", "IPV6_", "MCAST_"],
+        SOCKET_OPTIONS,
+    ),
+];
+
+/// Prefixes of constants that are options, or levels or results, of one of these functions.
+const OPTION_PREFIXES: &[&str] = &[
+    "CURLOPT_",
+    "CURLINFO_",
+    "CURLMOPT_",
+    "CURLSHOPT_",
+    "SOL_",
+    "SO_",
+    "TCP_",
+    "IPPROTO_",
+    "IP_",
+    "IPV6_",
+    "MCAST_",
+];
+
+/**
+ * Options of stream contexts of PHP's own wrappers, as `stream_context_create` takes them. Other
+ * wrappers can be registered with options of their own, so they aren't checked.
+ */
+const CONTEXT_OPTIONS: &[(&str, Options)] = &[
+    (
+        "ftp",
+        &[
+            ("overwrite", Expects::Flag),
+            ("proxy", Expects::String),
+            ("resume_pos", Expects::Int),
+        ],
+    ),
+    (
+        "http",
+        &[
+            ("content", Expects::String),
+            ("follow_location", Expects::Flag),
+            ("header", Expects::StringOrArray),
+            ("ignore_errors", Expects::Flag),
+            ("max_redirects", Expects::Int),
+            ("method", Expects::String),
+            ("protocol_version", Expects::Float),
+            ("proxy", Expects::String),
+            ("request_fulluri", Expects::Flag),
+            ("timeout", Expects::Float),
+            ("user_agent", Expects::String),
+        ],
+    ),
+    (
+        "phar",
+        &[("compress", Expects::Int), ("metadata", Expects::Array)],
+    ),
+    (
+        "socket",
+        &[
+            ("backlog", Expects::Int),
+            ("bindto", Expects::String),
+            ("ipv6_v6only", Expects::Flag),
+            ("so_broadcast", Expects::Flag),
+            ("so_reuseport", Expects::Flag),
+            ("tcp_nodelay", Expects::Flag),
+        ],
+    ),
+    (
+        "ssl",
+        &[
+            ("SNI_enabled", Expects::Flag),
+            ("allow_self_signed", Expects::Flag),
+            ("cafile", Expects::String),
+            ("capath", Expects::String),
+            ("capture_peer_cert", Expects::Flag),
+            ("capture_peer_cert_chain", Expects::Flag),
+            ("ciphers", Expects::String),
+            ("crypto_method", Expects::Int),
+            ("disable_compression", Expects::Flag),
+            ("local_cert", Expects::String),
+            ("local_pk", Expects::String),
+            ("passphrase", Expects::String),
+            ("peer_fingerprint", Expects::StringOrArray),
+            ("peer_name", Expects::String),
+            ("security_level", Expects::Int),
+            ("verify_depth", Expects::Int),
+            ("verify_peer", Expects::Flag),
+            ("verify_peer_name", Expects::Flag),
+        ],
+    ),
+    ("zip", &[("password", Expects::String)]),
+];
+
+fn diagnostic(node: &Node, code: &str, message: String) -> Diagnostic {
+    Diagnostic {
+        range: to_range(&node.range()),
+        severity: Some(DiagnosticSeverity::WARNING),
+        code: Some(NumberOrString::String(code.to_string())),
+        source: Some("phplsp".to_string()),
+        message,
+        ..Diagnostic::default()
+    }
+}
+
+/// The values passed to a call by position, or nothing if any are named or unpacked.
+pub(crate) fn arguments<'tree>(call: &Node<'tree>) -> Option<Vec<Node<'tree>>> {
+    let arguments = call.child_by_field_name("arguments")?;
+    let mut cursor = arguments.walk();
+    let values = arguments
+        .named_children(&mut cursor)
+        .filter(|argument| argument.kind() == "argument")
+        .map(|argument| {
+            let value = argument.named_child(0)?;
+            let positional = argument.child_by_field_name("name").is_none()
+                && value.kind() != "variadic_unpacking";
+            positional.then_some(value)
+        })
+        .collect();
+    values
+}
+
+struct Checker<'a> {
+    contents: &'a str,
+    types: TypeInference<'a>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker<'_> {
+    fn check_type(&mut self, option: &str, expects: Expects, value: &Node) {
+        let Some(actual) = self.types.expression(value) else {
+            return;
+        };
+        if !self.types.accepts(&expects.to_type(), &actual) {
+            self.diagnostics.push(diagnostic(
+                value,
+                "option-type",
+                format!(
+                    "`{}` expects {}, but gets {}",
+                    option,
+                    expects.name(),
+                    actual
+                ),
+            ));
+        }
+    }
+
+    /// `curl_setopt($ch, CURLOPT_URL, $url)` and the like.
+    fn option_call(
+        &mut self,
+        function: &str,
+        arguments: &[Node],
+        prefixes: &[&str],
+        options: Options,
+    ) {
+        let Some(option) = arguments
+            .first()
+            .filter(|option| matches!(option.kind(), "name" | "qualified_name"))
+        else {
+            return;
+        };
+        let name = self.contents[option.byte_range()].trim_start_matches('\\');
+        match options.iter().find(|(known, _)| *known == name) {
+            Some((_, expects)) => {
+                if let Some(value) = arguments.get(1) {
+                    self.check_type(name, *expects, value);
+                }
+            }
+            None if !prefixes.iter().any(|prefix| name.starts_with(prefix))
+                && OPTION_PREFIXES
+                    .iter()
+                    .any(|prefix| name.starts_with(prefix)) =>
+            {
+                self.diagnostics.push(diagnostic(
+                    option,
+                    "invalid-option",
+                    format!("`{}` is not an option of `{}`", name, function),
+                ));
+            }
+            None => {}
+        }
+    }
+
+    /// `stream_context_create(['http' => ['method' => 'POST']])`.
+    fn context_options(&mut self, options: &Node) {
+        if options.kind() != "array_creation_expression" {
+            return;
+        }
+        let mut cursor = options.walk();
+        for wrapper in options.named_children(&mut cursor) {
+            let (Some(key), Some(value)) = (wrapper.named_child(0), wrapper.named_child(1)) else {
+                continue;
+            };
+            let Some((_, name)) = string_contents(&key, self.contents) else {
+                continue;
+            };
+            let Some((_, known)) = CONTEXT_OPTIONS.iter().find(|(known, _)| *known == name) else {
+                continue;
+            };
+            if value.kind() != "array_creation_expression" {
+                continue;
+            }
+
+            let mut cursor = value.walk();
+            for option in value.named_children(&mut cursor) {
+                let (Some(key), Some(value)) = (option.named_child(0), option.named_child(1))
+                else {
+                    continue;
+                };
+                let Some((_, option)) = string_contents(&key, self.contents) else {
+                    continue;
+                };
+                match known.iter().find(|(known, _)| *known == option) {
+                    Some((_, expects)) => self.check_type(option, *expects, &value),
+                    None => self.diagnostics.push(diagnostic(
+                        &key,
+                        "invalid-option",
+                        format!(
+                            "`{}` is not an option of `{}` stream contexts",
+                            option, name
+                        ),
+                    )),
+                }
+            }
+        }
+    }
+
+    fn walk(&mut self, node: &Node) {
+        if node.kind() == "function_call_expression" {
+            self.call(node);
+        }
+
+        let mut cursor = node.walk();
+        for child in node.named_children(&mut cursor) {
+            self.walk(&child);
+        }
+    }
+
+    fn call(&mut self, call: &Node) {
+        let (Some(function), Some(arguments)) =
+            (call.child_by_field_name("function"), arguments(call))
+        else {
+            return;
+        };
+        let function = self.contents[function.byte_range()].trim_start_matches('\\');
+        if function.eq_ignore_ascii_case("stream_context_create") {
+            if let Some(options) = arguments.first() {
+                self.context_options(options);
+            }
+            return;
+        }
+        if let Some((function, at, prefixes, options)) = OPTION_FUNCTIONS
+            .iter()
+            .find(|(known, ..)| known.eq_ignore_ascii_case(function))
+        {
+            if let Some(arguments) = arguments.get(*at..) {
+                self.option_call(function, arguments, prefixes, options);
+            }
+        }
+    }
+}
+
+/**
+ * Report options given to `curl_setopt`, `socket_set_option` and `stream_context_create` that
+ * aren't theirs, and values of the wrong type for the options we know the types of.
+ */
+pub fn option_diagnostics(
+    root_node: &Node,
+    file_contents: &str,
+    symbols: &FileSymbols,
+    index: &WorkspaceIndex,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let resolver = Resolver::new(file_contents, symbols, index);
+    let mut checker = Checker {
+        contents: file_contents,
+        types: TypeInference::new(file_contents, &resolver),
+        diagnostics: vec![],
+    };
+    checker.walk(root_node);
+    diagnostics.append(&mut checker.diagnostics);
+}
+
+#[cfg(test)]
+mod test {
+    use super::option_diagnostics;
+    use crate::file::php_parser;
+    use crate::index::WorkspaceIndex;
+    use crate::symbols::file_symbols;
+
+    #[test]
+    fn test_option_diagnostics() {
+        let source = "<?php
+$ch = curl_init();
+curl_setopt($ch, CURLOPT_RETURNTRANSFER, 1);
+curl_setopt($ch, CURLOPT_SSL_VERIFYHOST, true);
+curl_setopt($ch, CURLINFO_HTTP_CODE, 1);
+curl_setopt($ch, CURLINFO_HEADER_OUT, true);
+curl_setopt($ch, CURLOPT_HTTPHEADER, 'Accept: */*');
+curl_setopt($ch, CURLOPT_WRITEFUNCTION, fn($ch, $data) => strlen($data));
+socket_set_option($socket, SOL_SOCKET, SO_RCVTIMEO, 5);
+socket_set_option($socket, SOL_SOCKET, SOL_TCP, 1);
+$context = stream_context_create([
+    'http' => ['method' => 'POST', 'timeout' => '5', 'methd' => 'GET'],
+    's3' => ['region' => 'eu'],
+]);
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let mut diagnostics = vec![];
+        option_diagnostics(
+            &tree.root_node(),
+            source,
+            &symbols,
+            &WorkspaceIndex::default(),
+            &mut diagnostics,
+        );
+
+        let found: Vec<(u32, String)> = diagnostics
+            .into_iter()
+            .map(|diagnostic| (diagnostic.range.start.line, diagnostic.message))
+            .collect();
+        assert_eq!(
+            vec![
+                (
+                    3,
+                    "`CURLOPT_SSL_VERIFYHOST` expects int, but gets bool".to_string()
+                ),
+                (
+                    4,
+                    "`CURLINFO_HTTP_CODE` is not an option of `curl_setopt`".to_string()
+                ),
+                (
+                    6,
+                    "`CURLOPT_HTTPHEADER` expects array, but gets string".to_string()
+                ),
+                (8, "`SO_RCVTIMEO` expects array, but gets int".to_string()),
+                (
+                    9,
+                    "`SOL_TCP` is not an option of `socket_set_option`".to_string()
+                ),
+                (11, "`timeout` expects float, but gets string".to_string()),
+                (
+                    11,
+                    "`methd` is not an option of `http` stream contexts".to_string()
+                ),
+            ],
+            found
+        );
+    }
+}
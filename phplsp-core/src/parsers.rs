@@ -0,0 +1,193 @@
+//! Parsers that are set up once and handed out to whatever needs to parse, so that indexing and
+//! requests don't each load the grammar, and don't queue up behind a single parser either.
+
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Condvar, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::{Serialize, Serializer};
+use tree_sitter::Parser;
+
+use crate::file::php_parser;
+
+fn milliseconds<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64() * 1000.0)
+}
+
+/// How long parsers were waited for, and how many there are. Waits are in milliseconds in JSON.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PoolStats {
+    /// Parsers set up so far, which is at most the capacity of the pool.
+    pub created: usize,
+    /// Times a parser was handed out.
+    pub checkouts: usize,
+    /// Times a parser was handed out only after waiting for another to be returned.
+    pub waits: usize,
+    /// How long was spent waiting for parsers in all.
+    #[serde(serialize_with = "milliseconds")]
+    pub total_wait: Duration,
+    /// The longest a parser was waited for.
+    #[serde(serialize_with = "milliseconds")]
+    pub longest_wait: Duration,
+}
+
+impl fmt::Display for PoolStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} parsers, handed out {} times, waited for {} times for {:?} in all and {:?} at most",
+            self.created, self.checkouts, self.waits, self.total_wait, self.longest_wait
+        )
+    }
+}
+
+struct PoolState {
+    idle: Vec<Parser>,
+    stats: PoolStats,
+}
+
+/**
+ * A pool of PHP parsers.
+ *
+ * Parsers are set up when first needed, up to the capacity of the pool; after that, whoever asks
+ * for one waits until another is returned. A parser is returned when what it was handed out as is
+ * dropped, so don't hold on to one across an `.await`.
+ */
+pub struct ParserPool {
+    state: Mutex<PoolState>,
+    returned: Condvar,
+    capacity: usize,
+}
+
+impl ParserPool {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            state: Mutex::new(PoolState {
+                idle: vec![],
+                stats: PoolStats::default(),
+            }),
+            returned: Condvar::new(),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// A parser of the pool, waiting for one to be returned if they're all in use.
+    pub fn get(&self) -> PooledParser<'_> {
+        let start = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        let mut waited = false;
+        let parser = loop {
+            if let Some(parser) = state.idle.pop() {
+                break parser;
+            }
+            if state.stats.created < self.capacity {
+                state.stats.created += 1;
+                // the grammar is shared, so this is cheap enough to do without the lock
+                drop(state);
+                let parser = php_parser();
+                state = self.state.lock().unwrap();
+                break parser;
+            }
+            waited = true;
+            state = self.returned.wait(state).unwrap();
+        };
+
+        let stats = &mut state.stats;
+        stats.checkouts += 1;
+        if waited {
+            let wait = start.elapsed();
+            stats.waits += 1;
+            stats.total_wait += wait;
+            stats.longest_wait = stats.longest_wait.max(wait);
+        }
+        PooledParser {
+            pool: self,
+            parser: Some(parser),
+        }
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        self.state.lock().unwrap().stats
+    }
+
+    fn put(&self, mut parser: Parser) {
+        // a parse that was cut short would otherwise carry on from where it stopped
+        parser.reset();
+        self.state.lock().unwrap().idle.push(parser);
+        self.returned.notify_one();
+    }
+}
+
+/// A parser handed out by a [`ParserPool`], which goes back to it when dropped.
+pub struct PooledParser<'a> {
+    pool: &'a ParserPool,
+    parser: Option<Parser>,
+}
+
+impl Deref for PooledParser<'_> {
+    type Target = Parser;
+
+    fn deref(&self) -> &Parser {
+        self.parser.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledParser<'_> {
+    fn deref_mut(&mut self) -> &mut Parser {
+        self.parser.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledParser<'_> {
+    fn drop(&mut self) {
+        if let Some(parser) = self.parser.take() {
+            self.pool.put(parser);
+        }
+    }
+}
+
+/// The pool that the server parses with, with a parser for every thread that can run at once.
+pub fn parsers() -> &'static ParserPool {
+    static PARSERS: OnceLock<ParserPool> = OnceLock::new();
+    PARSERS.get_or_init(|| {
+        ParserPool::new(thread::available_parallelism().map_or(4, |threads| threads.get()))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::ParserPool;
+
+    #[test]
+    fn test_parser_pool() {
+        let pool = Arc::new(ParserPool::new(1));
+        {
+            let mut parser = pool.get();
+            let tree = parser.parse("<?php echo 1;", None).unwrap();
+            assert!(!tree.root_node().has_error());
+        }
+        drop(pool.get());
+        let stats = pool.stats();
+        assert_eq!((1, 2, 0), (stats.created, stats.checkouts, stats.waits));
+
+        let parser = pool.get();
+        let waiting = {
+            let pool = pool.clone();
+            thread::spawn(move || pool.get().parse("<?php", None).is_some())
+        };
+        thread::sleep(Duration::from_millis(50));
+        drop(parser);
+        assert!(waiting.join().unwrap());
+
+        let stats = pool.stats();
+        assert_eq!((1, 4, 1), (stats.created, stats.checkouts, stats.waits));
+        assert!(stats.longest_wait > Duration::ZERO);
+    }
+}
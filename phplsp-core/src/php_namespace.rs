@@ -1,4 +1,6 @@
 use std::convert::Infallible;
+use std::fmt;
+use std::path::PathBuf;
 use std::str::FromStr;
 
 /**
@@ -7,6 +9,7 @@ use std::str::FromStr;
 #[derive(Debug, PartialEq, Eq, Hash)]
 pub struct PhpNamespace(Vec<String>);
 
+#[allow(dead_code)]
 impl PhpNamespace {
     pub fn is_within(&self, other: &Self) -> bool {
         let zipped = self.0.iter().zip(other.0.iter());
@@ -16,7 +19,16 @@ impl PhpNamespace {
             }
         }
 
-        return true;
+        true
+    }
+
+    /**
+     * The path of this namespace's directory relative to the directory of a namespace it is in,
+     * the way PSR-4 maps them.
+     */
+    pub fn relative_path(&self, within: &Self) -> Option<PathBuf> {
+        let rest = self.0.strip_prefix(within.0.as_slice())?;
+        Some(rest.iter().collect())
     }
 
     pub fn push(&mut self, s: &str) {
@@ -31,11 +43,9 @@ impl PhpNamespace {
     }
 }
 
-impl ToString for PhpNamespace {
-    fn to_string(&self) -> String {
-        let mut joined = self.0.join("\\");
-        joined.insert(0, '\\');
-        joined
+impl fmt::Display for PhpNamespace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\\{}", self.0.join("\\"))
     }
 }
 
@@ -55,6 +65,7 @@ impl FromStr for PhpNamespace {
 #[cfg(test)]
 mod test {
     use super::PhpNamespace;
+    use std::path::PathBuf;
     use std::str::FromStr;
 
     #[test]
@@ -62,7 +73,7 @@ mod test {
         let equivalents = [["\\Abc\\Def", "\\Abc\\Def\\"], ["", "\\"]];
 
         for [a, b] in equivalents {
-            assert_eq!(PhpNamespace::from_str(&a), PhpNamespace::from_str(&b));
+            assert_eq!(PhpNamespace::from_str(a), PhpNamespace::from_str(b));
         }
     }
 
@@ -71,19 +82,30 @@ mod test {
         let subnamespaces = [["Abc\\", "\\Abc\\Def\\"], ["", "Abc\\Def"]];
 
         for [a, b] in subnamespaces {
-            let ns_a = PhpNamespace::from_str(&a).unwrap();
-            let ns_b = PhpNamespace::from_str(&b).unwrap();
+            let ns_a = PhpNamespace::from_str(a).unwrap();
+            let ns_b = PhpNamespace::from_str(b).unwrap();
             assert!(ns_a.is_within(&ns_b));
         }
     }
 
+    #[test]
+    fn test_relative_path() {
+        let app = PhpNamespace::from_str("App\\").unwrap();
+        let models = PhpNamespace::from_str("App\\Models\\User").unwrap();
+        assert_eq!(
+            Some(PathBuf::from("Models/User")),
+            models.relative_path(&app)
+        );
+        assert_eq!(None, app.relative_path(&models));
+    }
+
     #[test]
     fn test_is_not_within() {
         let subnamespaces = [["\\Abc\\", "\\Def\\Abc"]];
 
         for [a, b] in subnamespaces {
-            let ns_a = PhpNamespace::from_str(&a).unwrap();
-            let ns_b = PhpNamespace::from_str(&b).unwrap();
+            let ns_a = PhpNamespace::from_str(a).unwrap();
+            let ns_b = PhpNamespace::from_str(b).unwrap();
             assert!(!ns_a.is_within(&ns_b));
         }
     }
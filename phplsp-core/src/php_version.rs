@@ -0,0 +1,237 @@
+//! Syntax that needs a newer version of PHP than the one the code has to run on.
+
+use lsp_types::*;
+use tree_sitter::Node;
+
+use crate::config::PhpVersion;
+use crate::file::to_range;
+use crate::tags::is_set_visibility;
+
+const TYPED_CONSTANTS: PhpVersion = PhpVersion::new(8, 3);
+const CLONE_READONLY: PhpVersion = PhpVersion::new(8, 3);
+const PROPERTY_HOOKS: PhpVersion = PhpVersion::new(8, 4);
+const ASYMMETRIC_VISIBILITY: PhpVersion = PhpVersion::new(8, 4);
+
+fn has_child(node: &Node, kind: &str) -> bool {
+    let mut cursor = node.walk();
+    let found = node.children(&mut cursor).any(|child| child.kind() == kind);
+    found
+}
+
+/// Names of the readonly properties a class declares, with their `$`.
+fn readonly_properties(class: &Node, file_contents: &str) -> Vec<String> {
+    let readonly_class = has_child(class, "readonly_modifier");
+    let Some(body) = class.child_by_field_name("body") else {
+        return vec![];
+    };
+
+    let mut names = vec![];
+    let mut cursor = body.walk();
+    for member in body.named_children(&mut cursor) {
+        match member.kind() {
+            "property_declaration" if readonly_class || has_child(&member, "readonly_modifier") => {
+                let mut cursor = member.walk();
+                names.extend(
+                    member
+                        .named_children(&mut cursor)
+                        .filter(|element| element.kind() == "property_element")
+                        .filter_map(|element| element.child_by_field_name("name"))
+                        .map(|name| file_contents[name.byte_range()].to_string()),
+                );
+            }
+            "method_declaration" => {
+                let Some(parameters) = member.child_by_field_name("parameters") else {
+                    continue;
+                };
+                let mut cursor = parameters.walk();
+                names.extend(
+                    parameters
+                        .named_children(&mut cursor)
+                        .filter(|parameter| {
+                            parameter.kind() == "property_promotion_parameter"
+                                && (readonly_class
+                                    || parameter.child_by_field_name("readonly").is_some())
+                        })
+                        .filter_map(|parameter| parameter.child_by_field_name("name"))
+                        .map(|name| file_contents[name.byte_range()].to_string()),
+                );
+            }
+            _ => {}
+        }
+    }
+    names
+}
+
+/// Assignments to `$this->property` in a node, leaving out those of functions in it.
+fn this_assignments<'tree>(node: &Node<'tree>, file_contents: &str, found: &mut Vec<Node<'tree>>) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        match child.kind() {
+            "function_definition" | "anonymous_function" | "arrow_function" => continue,
+            "assignment_expression" | "augmented_assignment_expression" => {
+                let left = child.child_by_field_name("left");
+                let object = left
+                    .filter(|left| left.kind() == "member_access_expression")
+                    .and_then(|left| left.child_by_field_name("object"));
+                if object.is_some_and(|object| &file_contents[object.byte_range()] == "$this") {
+                    found.push(left.unwrap_or(child));
+                }
+            }
+            _ => {}
+        }
+        this_assignments(&child, file_contents, found);
+    }
+}
+
+struct Checker<'a> {
+    contents: &'a str,
+    version: PhpVersion,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker<'_> {
+    fn needs(&mut self, node: &Node, needed: PhpVersion, what: &str) {
+        if self.version >= needed {
+            return;
+        }
+        self.diagnostics.push(Diagnostic {
+            range: to_range(&node.range()),
+            severity: Some(DiagnosticSeverity::ERROR),
+            code: Some(NumberOrString::String("php-version".to_string())),
+            source: Some("phplsp".to_string()),
+            message: format!(
+                "{} needs PHP {}, but the code has to run on PHP {}",
+                what, needed, self.version
+            ),
+            ..Diagnostic::default()
+        });
+    }
+
+    /// Readonly properties can only be set again in `__clone` since PHP 8.3.
+    fn clone_method(&mut self, method: &Node) {
+        let Some(class) = method.parent().and_then(|body| body.parent()) else {
+            return;
+        };
+        let readonly = readonly_properties(&class, self.contents);
+        let Some(body) = method.child_by_field_name("body") else {
+            return;
+        };
+        let mut assignments = vec![];
+        this_assignments(&body, self.contents, &mut assignments);
+        for property in assignments {
+            let Some(name) = property.child_by_field_name("name") else {
+                continue;
+            };
+            let name = format!("${}", &self.contents[name.byte_range()]);
+            if readonly.contains(&name) {
+                let what = format!("setting readonly `{}` in `__clone`", name);
+                self.needs(&property, CLONE_READONLY, &what);
+            }
+        }
+    }
+
+    fn walk(&mut self, node: &Node) {
+        match node.kind() {
+            "const_declaration" => {
+                if let Some(ty) = node.child_by_field_name("type") {
+                    self.needs(&ty, TYPED_CONSTANTS, "a typed class constant");
+                }
+            }
+            "property_hook_list" => self.needs(node, PROPERTY_HOOKS, "a property hook"),
+            "disjunctive_normal_form_type" if is_set_visibility(node, self.contents) => {
+                let visibility = node.prev_sibling().unwrap_or(*node);
+                self.needs(&visibility, ASYMMETRIC_VISIBILITY, "asymmetric visibility");
+            }
+            "method_declaration"
+                if node.child_by_field_name("name").is_some_and(|name| {
+                    self.contents[name.byte_range()].eq_ignore_ascii_case("__clone")
+                }) =>
+            {
+                self.clone_method(node)
+            }
+            _ => {}
+        }
+
+        let mut cursor = node.walk();
+        for child in node.named_children(&mut cursor) {
+            self.walk(&child);
+        }
+    }
+}
+
+/**
+ * Report syntax that the configured version of PHP doesn't have yet: typed class constants and
+ * setting readonly properties again in `__clone` from PHP 8.3, and property hooks and asymmetric
+ * visibility from PHP 8.4.
+ */
+pub fn version_diagnostics(
+    root_node: &Node,
+    file_contents: &str,
+    version: PhpVersion,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let mut checker = Checker {
+        contents: file_contents,
+        version,
+        diagnostics: vec![],
+    };
+    checker.walk(root_node);
+    diagnostics.append(&mut checker.diagnostics);
+}
+
+#[cfg(test)]
+mod test {
+    use super::version_diagnostics;
+    use crate::config::PhpVersion;
+    use crate::file::php_parser;
+
+    #[test]
+    fn test_version_diagnostics() {
+        let source = "<?php
+class Money {
+    const string CURRENCY = 'EUR';
+    public function __construct(public readonly int $amount) {}
+    public private(set) int $cents;
+    public string $label { get => 'x'; }
+    public function __clone() {
+        $this->amount = 0;
+        $this->label = 'copy';
+    }
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let found = |version| {
+            let mut diagnostics = vec![];
+            version_diagnostics(&tree.root_node(), source, version, &mut diagnostics);
+            diagnostics
+                .into_iter()
+                .map(|diagnostic| (diagnostic.range.start.line, diagnostic.message))
+                .collect::<Vec<_>>()
+        };
+
+        assert!(found(PhpVersion::new(8, 4)).is_empty());
+        assert_eq!(
+            vec![
+                (
+                    4,
+                    "asymmetric visibility needs PHP 8.4, but the code has to run on PHP 8.3"
+                        .to_string()
+                ),
+                (
+                    5,
+                    "a property hook needs PHP 8.4, but the code has to run on PHP 8.3".to_string()
+                ),
+            ],
+            found(PhpVersion::new(8, 3))
+        );
+        assert_eq!(
+            vec![
+                (2, "a typed class constant needs PHP 8.3, but the code has to run on PHP 8.2".to_string()),
+                (4, "asymmetric visibility needs PHP 8.4, but the code has to run on PHP 8.2".to_string()),
+                (5, "a property hook needs PHP 8.4, but the code has to run on PHP 8.2".to_string()),
+                (7, "setting readonly `$amount` in `__clone` needs PHP 8.3, but the code has to run on PHP 8.2".to_string()),
+            ],
+            found(PhpVersion::new(8, 2))
+        );
+    }
+}
@@ -0,0 +1,652 @@
+//! Patterns given to the `preg_*` functions, which PCRE only compiles when the code runs, and
+//! which fail with no more than a warning and a `false` or `null` when they don't.
+
+use lsp_types::*;
+use tree_sitter::Node;
+
+use crate::file::{to_position, to_range};
+use crate::options::arguments;
+use crate::types::Type;
+
+/// Functions that take a pattern, or an array of them, as their first argument.
+const PATTERN_FUNCTIONS: &[&str] = &[
+    "preg_filter",
+    "preg_grep",
+    "preg_match",
+    "preg_match_all",
+    "preg_replace",
+    "preg_replace_callback",
+    "preg_split",
+];
+
+/// Takes an array whose keys are patterns.
+const CALLBACK_ARRAY: &str = "preg_replace_callback_array";
+
+/// Modifiers that PCRE knows of, as of PHP 8.4.
+const MODIFIERS: &str = "imsxADSUXJunr";
+
+/// A character of a string literal as PHP reads it, and where it's written in the file.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct LiteralChar {
+    pub c: char,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// What's wrong with a pattern, and the characters of it that are at fault.
+#[derive(Debug, PartialEq, Eq)]
+struct PatternError {
+    from: usize,
+    to: usize,
+    message: String,
+}
+
+impl PatternError {
+    fn new(from: usize, to: usize, message: String) -> Self {
+        Self { from, to, message }
+    }
+
+    fn at(at: usize, message: String) -> Self {
+        Self::new(at, at + 1, message)
+    }
+}
+
+/**
+ * The characters of a string literal once PHP has read its escape sequences, or nothing if it
+ * interpolates anything. Escape sequences that PCRE would read the same, like `\n`, are kept as
+ * they're written.
+ */
+pub(crate) fn literal_chars(node: &Node, file_contents: &str) -> Option<Vec<LiteralChar>> {
+    let quote = match node.kind() {
+        "string" => '\'',
+        "encapsed_string" => '"',
+        _ => return None,
+    };
+    let mut chars = vec![];
+    let mut cursor = node.walk();
+    for part in node.named_children(&mut cursor) {
+        let text = &file_contents[part.byte_range()];
+        match part.kind() {
+            "string_content" => {
+                chars.extend(text.char_indices().map(|(i, c)| LiteralChar {
+                    c,
+                    start: part.start_byte() + i,
+                    end: part.start_byte() + i + c.len_utf8(),
+                }));
+            }
+            "escape_sequence" => {
+                let escaped = text.strip_prefix('\\').and_then(|rest| {
+                    let c = rest.chars().next()?;
+                    (rest.len() == c.len_utf8()
+                        && (c == '\\' || c == quote || quote == '"' && c == '$'))
+                        .then_some(c)
+                });
+                match escaped {
+                    Some(c) => chars.push(LiteralChar {
+                        c,
+                        start: part.start_byte(),
+                        end: part.end_byte(),
+                    }),
+                    None => chars.extend(text.char_indices().map(|(i, c)| LiteralChar {
+                        c,
+                        start: part.start_byte() + i,
+                        end: part.start_byte() + i + c.len_utf8(),
+                    })),
+                }
+            }
+            _ => return None,
+        }
+    }
+
+    Some(chars)
+}
+
+/// Where a pattern that starts with a delimiter ends, skipping escaped characters.
+fn closing_delimiter(chars: &[char], delimiter: char) -> Option<usize> {
+    let closing = match delimiter {
+        '(' => ')',
+        '[' => ']',
+        '{' => '}',
+        '<' => '>',
+        c => c,
+    };
+    let mut depth = 0;
+    let mut i = 1;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            c if c == closing && depth == 0 => return Some(i),
+            c if c == closing => depth -= 1,
+            c if c == delimiter => depth += 1,
+            _ => {}
+        }
+        i += 1;
+    }
+
+    None
+}
+
+/**
+ * Find unbalanced groups and character classes in what's between the delimiters, or else where
+ * each group starts, in order.
+ */
+fn check_body(chars: &[char], offset: usize, extended: bool) -> Result<Vec<usize>, PatternError> {
+    let mut starts = vec![];
+    let mut groups = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            // `\Q...\E` is taken as it is
+            '\\' if chars.get(i + 1) == Some(&'Q') => {
+                i += 2;
+                while i < chars.len() && !(chars[i] == '\\' && chars.get(i + 1) == Some(&'E')) {
+                    i += 1;
+                }
+                i += 1;
+            }
+            '\\' => i += 1,
+            '[' => {
+                let start = i;
+                i += 1;
+                if chars.get(i) == Some(&'^') {
+                    i += 1;
+                }
+                // a `]` right at the start is taken as it is
+                if chars.get(i) == Some(&']') {
+                    i += 1;
+                }
+                loop {
+                    match chars.get(i) {
+                        None => {
+                            return Err(PatternError::new(
+                                offset + start,
+                                offset + chars.len(),
+                                "`[` starts a character class that's never closed".to_string(),
+                            ))
+                        }
+                        Some('\\') => i += 1,
+                        Some(']') => break,
+                        // `[:alpha:]` and the like
+                        Some('[') if chars.get(i + 1) == Some(&':') => {
+                            if let Some(end) = (i + 2..chars.len().saturating_sub(1))
+                                .find(|&j| chars[j] == ':' && chars[j + 1] == ']')
+                            {
+                                i = end + 1;
+                            }
+                        }
+                        Some(_) => {}
+                    }
+                    i += 1;
+                }
+            }
+            // `(?#...)` is a comment
+            '(' if chars.get(i + 1) == Some(&'?') && chars.get(i + 2) == Some(&'#') => {
+                while i < chars.len() && chars[i] != ')' {
+                    i += 1;
+                }
+            }
+            '(' => {
+                starts.push(i);
+                groups.push(i);
+            }
+            ')' if groups.pop().is_none() => {
+                return Err(PatternError::at(
+                    offset + i,
+                    "`)` closes no group".to_string(),
+                ));
+            }
+            '#' if extended => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    match groups.first() {
+        Some(&open) => Err(PatternError::at(
+            offset + open,
+            "`(` starts a group that's never closed".to_string(),
+        )),
+        None => Ok(starts),
+    }
+}
+
+/// A pattern that PCRE compiles, split up.
+struct Pattern<'a> {
+    /// What's between the delimiters.
+    body: &'a [char],
+    modifiers: &'a [char],
+    /// Where each group starts in the body, in order.
+    groups: Vec<usize>,
+}
+
+/// What PCRE would make of a pattern, given as what's in the string, delimiters and all.
+fn check_pattern(chars: &[char]) -> Result<Pattern<'_>, PatternError> {
+    // PHP skips whitespace before the delimiter
+    let Some(start) = chars.iter().position(|c| !c.is_whitespace()) else {
+        return Err(PatternError::new(
+            0,
+            chars.len(),
+            "the pattern is empty, without even delimiters".to_string(),
+        ));
+    };
+    let chars = &chars[start..];
+    let delimiter = chars[0];
+    if delimiter.is_alphanumeric() || delimiter == '\\' || delimiter == '\0' {
+        return Err(PatternError::at(
+            start,
+            format!(
+                "`{}` can't delimit a pattern, since it's a letter, digit or backslash",
+                delimiter
+            ),
+        ));
+    }
+    let Some(end) = closing_delimiter(chars, delimiter) else {
+        return Err(PatternError::at(
+            start,
+            format!(
+                "the pattern starts with the delimiter `{}`, but never ends with it",
+                delimiter
+            ),
+        ));
+    };
+
+    let modifiers = &chars[end + 1..];
+    for (i, &modifier) in modifiers.iter().enumerate() {
+        if MODIFIERS.contains(modifier) || modifier == '\n' || modifier == ' ' || modifier == '\r' {
+            continue;
+        }
+        let at = start + end + 1 + i;
+        let message = if modifier == 'e' {
+            "the `e` modifier was removed in PHP 7, use `preg_replace_callback` instead".to_string()
+        } else if modifiers.contains(&delimiter) || chars[1..end].contains(&delimiter) {
+            format!(
+                "unknown modifier `{}`, which probably means a `{}` in the pattern isn't escaped",
+                modifier, delimiter
+            )
+        } else {
+            format!("unknown modifier `{}`", modifier)
+        };
+        return Err(PatternError::at(at, message));
+    }
+
+    let body = &chars[1..end];
+    let groups = check_body(body, start + 1, modifiers.contains(&'x'))?;
+    Ok(Pattern {
+        body,
+        modifiers,
+        groups,
+    })
+}
+
+/**
+ * The capturing groups of a pattern in the order PCRE numbers them, with the names of those that
+ * have one, or nothing if they're numbered in a way we don't follow, like in `(?|...)`.
+ */
+fn capturing_groups(pattern: &Pattern) -> Option<Vec<Option<String>>> {
+    let body = pattern.body;
+    let name = |from: usize, end: char| {
+        let name: String = body[from.min(body.len())..]
+            .iter()
+            .take_while(|&&c| c != end)
+            .collect();
+        name
+    };
+    // `n` makes groups without a name not capture
+    let numbered = !pattern.modifiers.contains(&'n');
+    let mut groups = vec![];
+    for &start in &pattern.groups {
+        let after = |i: usize| body.get(start + i).copied();
+        match (after(1), after(2), after(3)) {
+            (Some('?'), Some('<'), Some('=' | '!')) => {}
+            (Some('?'), Some('<'), _) => groups.push(Some(name(start + 3, '>'))),
+            (Some('?'), Some('P'), Some('<')) => groups.push(Some(name(start + 4, '>'))),
+            (Some('?'), Some('\''), _) => groups.push(Some(name(start + 3, '\''))),
+            (Some('?'), Some('|'), _) => return None,
+            (Some('?' | '*'), _, _) => {}
+            _ if numbered => groups.push(None),
+            _ => {}
+        }
+    }
+    Some(groups)
+}
+
+/// Flags of `preg_match` and `preg_match_all` that change what they give back in `$matches`.
+#[derive(Default)]
+struct MatchFlags {
+    offset_capture: bool,
+    unmatched_as_null: bool,
+    set_order: bool,
+}
+
+/// The flags of a call, if they're given as constants.
+fn match_flags(flags: Option<&Node>, file_contents: &str) -> Option<MatchFlags> {
+    let mut found = MatchFlags::default();
+    let Some(flags) = flags else {
+        return Some(found);
+    };
+    for flag in file_contents[flags.byte_range()].split('|') {
+        match flag.trim().trim_start_matches('\\') {
+            "PREG_OFFSET_CAPTURE" => found.offset_capture = true,
+            "PREG_UNMATCHED_AS_NULL" => found.unmatched_as_null = true,
+            "PREG_SET_ORDER" => found.set_order = true,
+            "PREG_PATTERN_ORDER" | "0" => {}
+            _ => return None,
+        }
+    }
+    Some(found)
+}
+
+/// Whether an argument of a function is where it puts the matches it finds, like `preg_match`.
+pub(crate) fn is_matches_argument(function: &str, argument: usize) -> bool {
+    let function = function.trim_start_matches('\\');
+    argument == 2
+        && (function.eq_ignore_ascii_case("preg_match")
+            || function.eq_ignore_ascii_case("preg_match_all"))
+}
+
+/**
+ * The type of the matches that a call of `preg_match` or `preg_match_all` finds, which has a key
+ * for each group of its pattern, by number and by name, if the pattern is a string literal.
+ */
+pub(crate) fn matches_type(call: &Node, file_contents: &str) -> Option<Type> {
+    let function = call.child_by_field_name("function")?;
+    let function = file_contents[function.byte_range()].trim_start_matches('\\');
+    let all = function.eq_ignore_ascii_case("preg_match_all");
+    let arguments = arguments(call)?;
+    let chars: Vec<char> = literal_chars(arguments.first()?, file_contents)?
+        .iter()
+        .map(|c| c.c)
+        .collect();
+    let pattern = check_pattern(&chars).ok()?;
+    let groups = capturing_groups(&pattern)?;
+    let flags = match_flags(arguments.get(3), file_contents)?;
+
+    let value = |group: bool| {
+        let text = match flags.unmatched_as_null && group {
+            true => Type::union([Type::String, Type::Null]),
+            false => Type::String,
+        };
+        match flags.offset_capture {
+            true => Type::Shape(vec![("0".to_string(), text), ("1".to_string(), Type::Int)]),
+            false => text,
+        }
+    };
+    let mut keys = vec![("0".to_string(), value(false))];
+    for (i, name) in groups.into_iter().enumerate() {
+        if let Some(name) = name {
+            keys.push((name, value(true)));
+        }
+        keys.push(((i + 1).to_string(), value(true)));
+    }
+
+    Some(match (all, flags.set_order) {
+        (false, _) => Type::Shape(keys),
+        (true, false) => Type::Shape(
+            keys.into_iter()
+                .map(|(key, ty)| (key, Type::Array(Some(Box::new(ty)))))
+                .collect(),
+        ),
+        (true, true) => Type::Array(Some(Box::new(Type::Shape(keys)))),
+    })
+}
+
+/// The position of a byte of a node, counting from where the node starts.
+pub(crate) fn position_at(node: &Node, file_contents: &str, byte: usize) -> Position {
+    let mut position = to_position(&node.start_position());
+    let text = &file_contents[node.start_byte()..byte];
+    match text.rfind('\n') {
+        Some(newline) => {
+            position.line += text.matches('\n').count() as u32;
+            position.character = (text.len() - newline - 1) as u32;
+        }
+        None => position.character += text.len() as u32,
+    }
+    position
+}
+
+fn check_literal(node: &Node, file_contents: &str, diagnostics: &mut Vec<Diagnostic>) {
+    let Some(chars) = literal_chars(node, file_contents) else {
+        return;
+    };
+    let text: Vec<char> = chars.iter().map(|c| c.c).collect();
+    let Err(error) = check_pattern(&text) else {
+        return;
+    };
+
+    let range = match (
+        chars.get(error.from),
+        error.to.checked_sub(1).and_then(|to| chars.get(to)),
+    ) {
+        (Some(from), Some(to)) => Range {
+            start: position_at(node, file_contents, from.start),
+            end: position_at(node, file_contents, to.end),
+        },
+        _ => to_range(&node.range()),
+    };
+    diagnostics.push(Diagnostic {
+        range,
+        severity: Some(DiagnosticSeverity::WARNING),
+        code: Some(NumberOrString::String("invalid-regex".to_string())),
+        source: Some("phplsp".to_string()),
+        message: error.message,
+        ..Diagnostic::default()
+    });
+}
+
+/// The patterns of an array literal, as its values or as its keys.
+fn array_patterns<'tree>(array: &Node<'tree>, keys: bool) -> Vec<Node<'tree>> {
+    if array.kind() != "array_creation_expression" {
+        return vec![];
+    }
+    let mut cursor = array.walk();
+    array
+        .named_children(&mut cursor)
+        .filter_map(|element| {
+            let has_key = element.named_child_count() > 1;
+            match (keys, has_key) {
+                (true, true) => element.named_child(0),
+                (false, true) => element.named_child(1),
+                (false, false) => element.named_child(0),
+                (true, false) => None,
+            }
+        })
+        .collect()
+}
+
+fn walk(node: &Node, file_contents: &str, diagnostics: &mut Vec<Diagnostic>) {
+    if node.kind() == "function_call_expression" {
+        let function = node
+            .child_by_field_name("function")
+            .map(|function| file_contents[function.byte_range()].trim_start_matches('\\'));
+        let first = node
+            .child_by_field_name("arguments")
+            .and_then(|arguments| arguments.named_child(0))
+            .filter(|argument| {
+                argument.kind() == "argument" && argument.child_by_field_name("name").is_none()
+            })
+            .and_then(|argument| argument.named_child(0));
+        if let (Some(function), Some(first)) = (function, first) {
+            let patterns = if PATTERN_FUNCTIONS
+                .iter()
+                .any(|known| known.eq_ignore_ascii_case(function))
+            {
+                match first.kind() {
+                    "array_creation_expression" => array_patterns(&first, false),
+                    _ => vec![first],
+                }
+            } else if CALLBACK_ARRAY.eq_ignore_ascii_case(function) {
+                array_patterns(&first, true)
+            } else {
+                vec![]
+            };
+            for pattern in patterns {
+                check_literal(&pattern, file_contents, diagnostics);
+            }
+        }
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        walk(&child, file_contents, diagnostics);
+    }
+}
+
+/**
+ * Report patterns given to the `preg_*` functions as string literals that PCRE won't compile:
+ * those without proper delimiters, with modifiers it doesn't know, or with groups or character
+ * classes that aren't closed, pointing at where in the string that is.
+ */
+pub fn regex_diagnostics(root_node: &Node, file_contents: &str, diagnostics: &mut Vec<Diagnostic>) {
+    walk(root_node, file_contents, diagnostics);
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::*;
+
+    use super::{check_pattern, regex_diagnostics, PatternError};
+    use crate::file::php_parser;
+    use crate::index::WorkspaceIndex;
+    use crate::resolve::Resolver;
+    use crate::symbols::file_symbols;
+    use crate::types::TypeInference;
+
+    fn check(pattern: &str) -> Result<(), PatternError> {
+        check_pattern(&pattern.chars().collect::<Vec<char>>()).map(|_| ())
+    }
+
+    #[test]
+    fn test_check_pattern() {
+        assert_eq!(Ok(()), check("/^a(b|c)+[)(\\]]$/i"));
+        assert_eq!(Ok(()), check(" {a{1,2}}u"));
+        assert_eq!(Ok(()), check("#[[:alpha:]]+# x"));
+        assert_eq!(Ok(()), check("/a # (\n/x"));
+        assert_eq!(Ok(()), check("~\\Q(\\E(?#)~"));
+
+        let message = |pattern| check(pattern).unwrap_err().message;
+        assert_eq!(
+            "`a` can't delimit a pattern, since it's a letter, digit or backslash",
+            message("abca")
+        );
+        assert_eq!(
+            "the pattern starts with the delimiter `/`, but never ends with it",
+            message("/abc\\/")
+        );
+        assert_eq!(
+            "unknown modifier `b`, which probably means a `/` in the pattern isn't escaped",
+            message("/a/b/")
+        );
+        assert_eq!(
+            "the `e` modifier was removed in PHP 7, use `preg_replace_callback` instead",
+            message("/a/e")
+        );
+        assert_eq!(
+            Err(PatternError::at(2, "`)` closes no group".to_string())),
+            check("/a)b/")
+        );
+        assert_eq!(
+            Err(PatternError::at(
+                2,
+                "`(` starts a group that's never closed".to_string()
+            )),
+            check("/a(b(c)/")
+        );
+        assert_eq!(
+            Err(PatternError::new(
+                2,
+                4,
+                "`[` starts a character class that's never closed".to_string()
+            )),
+            check("/a[]/")
+        );
+    }
+
+    #[test]
+    fn test_regex_diagnostics() {
+        let source = "<?php
+preg_match('/\\\\d+(/', $s);
+preg_replace(['/ok/', \"/a/b/x\"], '', $s);
+preg_replace_callback_array(['~[a~' => fn() => ''], $s);
+preg_match(\"/$prefix(/\", $s);
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let mut diagnostics = vec![];
+        regex_diagnostics(&tree.root_node(), source, &mut diagnostics);
+
+        let found: Vec<(Range, String)> = diagnostics
+            .into_iter()
+            .map(|diagnostic| (diagnostic.range, diagnostic.message))
+            .collect();
+        let range = |line, start, end| Range {
+            start: Position::new(line, start),
+            end: Position::new(line, end),
+        };
+        assert_eq!(
+            vec![
+                (
+                    range(1, 17, 18),
+                    "`(` starts a group that's never closed".to_string()
+                ),
+                (
+                    range(2, 26, 27),
+                    "unknown modifier `b`, which probably means a `/` in the pattern isn't escaped"
+                        .to_string()
+                ),
+                (
+                    range(3, 31, 33),
+                    "`[` starts a character class that's never closed".to_string()
+                ),
+            ],
+            found
+        );
+    }
+
+    #[test]
+    fn test_matches_type() {
+        let source = "<?php
+preg_match('/(?<year>\\d{4})-(\\d+)(?:x)(?=y)/', $s, $m);
+$m;
+preg_match('/(a)?/n', $s, $m, PREG_UNMATCHED_AS_NULL);
+$m;
+preg_match_all('~(?P<word>\\w+)~', $s, $m);
+$m;
+preg_match_all('~(?\\'word\\'\\w+)~', $s, $m, PREG_SET_ORDER | PREG_OFFSET_CAPTURE);
+$m;
+preg_match($pattern, $s, $m);
+$m;
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let index = WorkspaceIndex::default();
+        let resolver = Resolver::new(source, &symbols, &index);
+        let types = TypeInference::new(source, &resolver);
+
+        let mut cursor = tree.root_node().walk();
+        let found: Vec<Option<String>> = tree
+            .root_node()
+            .named_children(&mut cursor)
+            .filter(|statement| source[statement.byte_range()].starts_with("$m"))
+            .map(|statement| {
+                let variable = statement.named_child(0).unwrap();
+                types.expression(&variable).map(|ty| ty.to_string())
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                Some("array{0: string, year: string, 1: string, 2: string}".to_string()),
+                Some("array{0: string}".to_string()),
+                Some("array{0: string[], word: string[], 1: string[]}".to_string()),
+                Some(
+                    "array{0: array{0: string, 1: int}, word: array{0: string, 1: int}, 1: array{0: string, 1: int}}[]"
+                        .to_string()
+                ),
+                None,
+            ],
+            found
+        );
+    }
+}
@@ -0,0 +1,475 @@
+//! Format strings of the `printf` family, which PHP only reads when the code runs.
+
+use lsp_types::*;
+use tree_sitter::Node;
+
+use crate::file::to_range;
+use crate::index::WorkspaceIndex;
+use crate::options::arguments;
+use crate::preg::{literal_chars, position_at, LiteralChar};
+use crate::resolve::Resolver;
+use crate::symbols::FileSymbols;
+use crate::types::{Type, TypeInference};
+
+/**
+ * Functions that take a format: the argument it's at, and whether the values come in an array
+ * after it, like `vsprintf`, rather than as the arguments after it.
+ */
+const FORMAT_FUNCTIONS: &[(&str, usize, bool)] = &[
+    ("fprintf", 1, false),
+    ("printf", 0, false),
+    ("sprintf", 0, false),
+    ("vfprintf", 1, true),
+    ("vprintf", 0, true),
+    ("vsprintf", 0, true),
+];
+
+/// Conversions that take a number, which strings are converted to as best PHP can.
+const NUMERIC: &str = "bcdeEfFgGhHouxX";
+
+/// Conversions that take a string, which is anything PHP can convert to one.
+const STRING: &str = "s";
+
+/// A value that a format takes, by the characters of the conversion that takes it.
+#[derive(Debug, PartialEq, Eq)]
+struct Placeholder {
+    from: usize,
+    to: usize,
+    /// The value it takes, counting from 0.
+    value: usize,
+    /// The conversion, or `*` for a width or precision that's given as a value.
+    conversion: char,
+}
+
+/// What's wrong with a format, and the characters of it that are at fault.
+#[derive(Debug, PartialEq, Eq)]
+struct FormatError {
+    from: usize,
+    to: usize,
+    message: String,
+}
+
+/// The values a format takes, or what's wrong with it.
+fn placeholders(chars: &[char]) -> Result<Vec<Placeholder>, FormatError> {
+    let mut placeholders = vec![];
+    let mut next = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] != '%' {
+            i += 1;
+            continue;
+        }
+        let from = i;
+        i += 1;
+        if chars.get(i) == Some(&'%') {
+            i += 1;
+            continue;
+        }
+
+        // `%1$s` takes the first value, wherever it is
+        let digits = chars[i..].iter().take_while(|c| c.is_ascii_digit()).count();
+        let mut position = None;
+        if digits > 0 && chars.get(i + digits) == Some(&'$') {
+            let number: usize = chars[i..i + digits]
+                .iter()
+                .collect::<String>()
+                .parse()
+                .unwrap_or(usize::MAX);
+            if number == 0 {
+                return Err(FormatError {
+                    from,
+                    to: i + digits + 1,
+                    message: "values are counted from 1, so `%0$` takes none".to_string(),
+                });
+            }
+            position = Some(number - 1);
+            i += digits + 1;
+        }
+
+        let mut stars = vec![];
+        loop {
+            match chars.get(i) {
+                Some('-' | '+' | ' ' | '0') => i += 1,
+                // a padding character of our own, like `%'*10s`
+                Some('\'') => i += 2,
+                _ => break,
+            }
+        }
+        for precision in [false, true] {
+            if precision {
+                if chars.get(i) != Some(&'.') {
+                    break;
+                }
+                i += 1;
+            }
+            if chars.get(i) == Some(&'*') {
+                stars.push(i);
+                i += 1;
+            } else {
+                i += chars[i.min(chars.len())..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_digit())
+                    .count();
+            }
+        }
+        // PHP skips the `l` of C's `%ld`
+        if chars.get(i) == Some(&'l') {
+            i += 1;
+        }
+
+        let Some(&conversion) = chars.get(i) else {
+            return Err(FormatError {
+                from,
+                to: chars.len(),
+                message: "the format ends before saying what to convert to".to_string(),
+            });
+        };
+        if !NUMERIC.contains(conversion) && !STRING.contains(conversion) {
+            return Err(FormatError {
+                from,
+                to: i + 1,
+                message: format!("`%{}` is not a conversion", conversion),
+            });
+        }
+        i += 1;
+
+        for star in stars {
+            placeholders.push(Placeholder {
+                from: star,
+                to: star + 1,
+                value: next,
+                conversion: '*',
+            });
+            next += 1;
+        }
+        placeholders.push(Placeholder {
+            from,
+            to: i,
+            value: position.unwrap_or_else(|| {
+                next += 1;
+                next - 1
+            }),
+            conversion,
+        });
+    }
+
+    Ok(placeholders)
+}
+
+fn count(values: usize) -> String {
+    match values {
+        1 => "1 value".to_string(),
+        values => format!("{} values", values),
+    }
+}
+
+/// Whether a node is a string literal that PHP would read as a number.
+fn is_numeric_literal(node: &Node, file_contents: &str) -> bool {
+    literal_chars(node, file_contents).is_some_and(|chars| {
+        let text: String = chars.iter().map(|c| c.c).collect();
+        text.trim().parse::<f64>().is_ok()
+    })
+}
+
+struct Checker<'a> {
+    contents: &'a str,
+    types: TypeInference<'a>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker<'_> {
+    fn push(&mut self, range: Range, severity: DiagnosticSeverity, code: &str, message: String) {
+        self.diagnostics.push(Diagnostic {
+            range,
+            severity: Some(severity),
+            code: Some(NumberOrString::String(code.to_string())),
+            source: Some("phplsp".to_string()),
+            message,
+            ..Diagnostic::default()
+        });
+    }
+
+    /// Where some characters of a format are written.
+    fn format_range(
+        format: &Node,
+        contents: &str,
+        chars: &[LiteralChar],
+        from: usize,
+        to: usize,
+    ) -> Range {
+        match (
+            chars.get(from),
+            to.checked_sub(1).and_then(|to| chars.get(to)),
+        ) {
+            (Some(from), Some(to)) => Range {
+                start: position_at(format, contents, from.start),
+                end: position_at(format, contents, to.end),
+            },
+            _ => to_range(&format.range()),
+        }
+    }
+
+    fn check_type(&mut self, placeholder: &Placeholder, value: &Node) {
+        let Some(actual) = self.types.expression(value) else {
+            return;
+        };
+        let members = match &actual {
+            Type::Union(members) => members.as_slice(),
+            ty => std::slice::from_ref(ty),
+        };
+        let conversion = placeholder.conversion;
+        let problem = if members
+            .iter()
+            .all(|member| matches!(member, Type::Array(_) | Type::Shape(_)))
+        {
+            Some("an array, which it can't convert")
+        } else if conversion != 's'
+            && actual == Type::String
+            && !is_numeric_literal(value, self.contents)
+        {
+            Some("a string")
+        } else {
+            None
+        };
+        let Some(problem) = problem else {
+            return;
+        };
+
+        let message = match conversion {
+            '*' => format!(
+                "a width or precision of `*` takes an int, but gets {}",
+                problem
+            ),
+            's' => format!("`%s` takes a string, but gets {}", problem),
+            _ => format!("`%{}` takes a number, but gets {}", conversion, problem),
+        };
+        self.push(
+            to_range(&value.range()),
+            DiagnosticSeverity::WARNING,
+            "format-type",
+            message,
+        );
+    }
+
+    /// A format, and the values it's given, or nothing if we can't tell what they are.
+    fn check_format(&mut self, format: &Node, values: Option<Vec<Node>>) {
+        let Some(chars) = literal_chars(format, self.contents) else {
+            return;
+        };
+        let text: Vec<char> = chars.iter().map(|c| c.c).collect();
+        let placeholders = match placeholders(&text) {
+            Ok(placeholders) => placeholders,
+            Err(error) => {
+                let range = Self::format_range(format, self.contents, &chars, error.from, error.to);
+                self.push(
+                    range,
+                    DiagnosticSeverity::ERROR,
+                    "format-string",
+                    error.message,
+                );
+                return;
+            }
+        };
+        let Some(values) = values else {
+            return;
+        };
+
+        let taken = placeholders.iter().map(|p| p.value + 1).max().unwrap_or(0);
+        for placeholder in &placeholders {
+            let range = Self::format_range(
+                format,
+                self.contents,
+                &chars,
+                placeholder.from,
+                placeholder.to,
+            );
+            match values.get(placeholder.value) {
+                Some(value) => self.check_type(placeholder, value),
+                None => self.push(
+                    range,
+                    DiagnosticSeverity::ERROR,
+                    "format-string",
+                    format!(
+                        "the format takes {}, but gets {}",
+                        count(taken),
+                        values.len()
+                    ),
+                ),
+            }
+        }
+
+        if let (Some(first), Some(last)) = (values.get(taken), values.last()) {
+            self.push(
+                Range {
+                    start: to_range(&first.range()).start,
+                    end: to_range(&last.range()).end,
+                },
+                DiagnosticSeverity::WARNING,
+                "format-string",
+                format!(
+                    "the format takes {}, so {} of them are never used",
+                    count(taken),
+                    values.len() - taken
+                ),
+            );
+        }
+    }
+
+    fn call(&mut self, call: &Node) {
+        let Some(function) = call.child_by_field_name("function") else {
+            return;
+        };
+        let name = self.contents[function.byte_range()].trim_start_matches('\\');
+        let Some(&(_, at, in_array)) = FORMAT_FUNCTIONS
+            .iter()
+            .find(|(known, ..)| known.eq_ignore_ascii_case(name))
+        else {
+            return;
+        };
+        // named or unpacked arguments could be anything
+        let Some(arguments) = arguments(call) else {
+            return;
+        };
+        let Some(format) = arguments.get(at) else {
+            return;
+        };
+
+        let values = match in_array {
+            false => Some(arguments[at + 1..].to_vec()),
+            true => arguments
+                .get(at + 1)
+                .filter(|array| array.kind() == "array_creation_expression")
+                .and_then(|array| {
+                    let mut cursor = array.walk();
+                    array
+                        .named_children(&mut cursor)
+                        .map(|element| {
+                            let value =
+                                element.named_child(element.named_child_count().checked_sub(1)?)?;
+                            (value.kind() != "variadic_unpacking").then_some(value)
+                        })
+                        .collect()
+                }),
+        };
+        self.check_format(format, values);
+    }
+
+    fn walk(&mut self, node: &Node) {
+        if node.kind() == "function_call_expression" {
+            self.call(node);
+        }
+
+        let mut cursor = node.walk();
+        for child in node.named_children(&mut cursor) {
+            self.walk(&child);
+        }
+    }
+}
+
+/**
+ * Report format strings given to `sprintf`, `printf` and the rest of their family that PHP would
+ * throw on, because they have a conversion it doesn't know or take more values than they're given,
+ * that are given values they never use, or values of types their conversions can't take, like a
+ * string that isn't a number for `%d`.
+ */
+pub fn format_diagnostics(
+    root_node: &Node,
+    file_contents: &str,
+    symbols: &FileSymbols,
+    index: &WorkspaceIndex,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let resolver = Resolver::new(file_contents, symbols, index);
+    let mut checker = Checker {
+        contents: file_contents,
+        types: TypeInference::new(file_contents, &resolver),
+        diagnostics: vec![],
+    };
+    checker.walk(root_node);
+    diagnostics.append(&mut checker.diagnostics);
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::*;
+
+    use super::{format_diagnostics, placeholders};
+    use crate::file::php_parser;
+    use crate::index::WorkspaceIndex;
+    use crate::symbols::file_symbols;
+
+    #[test]
+    fn test_placeholders() {
+        let values = |format: &str| {
+            placeholders(&format.chars().collect::<Vec<char>>()).map(|placeholders| {
+                placeholders
+                    .into_iter()
+                    .map(|placeholder| (placeholder.value, placeholder.conversion))
+                    .collect::<Vec<_>>()
+            })
+        };
+        assert_eq!(
+            Ok(vec![(0, 's'), (1, 'd'), (0, 's')]),
+            values("%s is %05.2d%% %1$s")
+        );
+        assert_eq!(
+            Ok(vec![(0, '*'), (1, '*'), (2, 'f'), (3, 'x')]),
+            values("%*.*f %'#-10lx")
+        );
+        assert_eq!(
+            "`%y` is not a conversion",
+            values("100%y").unwrap_err().message
+        );
+        assert_eq!(
+            "the format ends before saying what to convert to",
+            values("100%").unwrap_err().message
+        );
+    }
+
+    #[test]
+    fn test_format_diagnostics() {
+        let source = "<?php
+$name = 'world';
+printf('%s has %d items', $name, 'many');
+echo sprintf('%s and %s', $name);
+echo sprintf('%d', '42', $name, $name);
+echo vsprintf('%s %q', [$name]);
+echo sprintf($format, $name);
+echo sprintf('%s %s', ...$names);
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let index = WorkspaceIndex::default();
+        let mut diagnostics = vec![];
+        format_diagnostics(
+            &tree.root_node(),
+            source,
+            &symbols,
+            &index,
+            &mut diagnostics,
+        );
+
+        let found: Vec<(Position, String)> = diagnostics
+            .into_iter()
+            .map(|diagnostic| (diagnostic.range.start, diagnostic.message))
+            .collect();
+        assert_eq!(
+            vec![
+                (
+                    Position::new(2, 33),
+                    "`%d` takes a number, but gets a string".to_string()
+                ),
+                (
+                    Position::new(3, 21),
+                    "the format takes 2 values, but gets 1".to_string()
+                ),
+                (
+                    Position::new(4, 25),
+                    "the format takes 1 value, so 2 of them are never used".to_string()
+                ),
+                (Position::new(5, 18), "`%q` is not a conversion".to_string()),
+            ],
+            found
+        );
+    }
+}
@@ -0,0 +1,248 @@
+//! Values assigned to typed properties, or given them as defaults, that aren't of their type.
+
+use lsp_types::*;
+use tree_sitter::Node;
+
+use crate::arguments::{accepts, is_strict};
+use crate::file::to_range;
+use crate::index::WorkspaceIndex;
+use crate::resolve::Resolver;
+use crate::return_types::{is_checked, is_known};
+use crate::symbols::{Declaration, FileSymbols};
+use crate::types::{Type, TypeInference};
+
+/// The property that `$object->name` or `Class::$name` is.
+pub(crate) fn accessed_property<'a>(
+    target: &Node,
+    file_contents: &str,
+    resolver: &'a Resolver,
+    types: &TypeInference,
+) -> Option<&'a Declaration> {
+    let text = |node: Node| &file_contents[node.byte_range()];
+    let (class, name) = match target.kind() {
+        "member_access_expression" => (
+            target.child_by_field_name("object")?,
+            format!("${}", text(target.child_by_field_name("name")?)),
+        ),
+        "scoped_property_access_expression" => (
+            target.child_by_field_name("scope")?,
+            text(target.child_by_field_name("name")?).to_string(),
+        ),
+        _ => return None,
+    };
+    let class = types.object_class(&class)?;
+    resolver.member(&class, &name)
+}
+
+/// The type of a property, if it's one we can check values against.
+pub(crate) fn property_type(index: &WorkspaceIndex, property: &Declaration) -> Option<Type> {
+    let written = property.property_type()?;
+    if !is_checked(written) {
+        return None;
+    }
+    Type::parse(written, property.namespace(), property.class_name())
+        .filter(|ty| is_known(index, ty))
+}
+
+struct Checker<'a> {
+    contents: &'a str,
+    index: &'a WorkspaceIndex,
+    resolver: &'a Resolver<'a>,
+    types: TypeInference<'a>,
+    strict: bool,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker<'_> {
+    fn text(&self, node: &Node) -> &str {
+        &self.contents[node.byte_range()]
+    }
+
+    /// A type written in this file, if it's one we can check values against.
+    fn written_type(&self, node: &Node) -> Option<Type> {
+        if !is_checked(self.text(node)) {
+            return None;
+        }
+        self.types
+            .type_node(node)
+            .filter(|ty| is_known(self.index, ty))
+    }
+
+    fn check(&mut self, name: &str, expected: &Type, value: &Node, strict: bool) {
+        let Some(actual) = self
+            .types
+            .expression(value)
+            .filter(|actual| is_known(self.index, actual))
+        else {
+            return;
+        };
+        if accepts(&self.types, expected, &actual, strict) {
+            return;
+        }
+        self.diagnostics.push(Diagnostic {
+            range: to_range(&value.range()),
+            severity: Some(DiagnosticSeverity::ERROR),
+            code: Some(NumberOrString::String("property-type".to_string())),
+            source: Some("phplsp".to_string()),
+            message: format!(
+                "`{}` is of type `{}`, but is given `{}`",
+                name, expected, actual
+            ),
+            ..Diagnostic::default()
+        });
+    }
+
+    fn walk(&mut self, node: &Node) {
+        match node.kind() {
+            "assignment_expression" => {
+                let (Some(left), Some(right)) = (
+                    node.child_by_field_name("left"),
+                    node.child_by_field_name("right"),
+                ) else {
+                    return;
+                };
+                let expected = accessed_property(&left, self.contents, self.resolver, &self.types)
+                    .and_then(|property| {
+                        Some((
+                            property.short_name().to_string(),
+                            property_type(self.index, property)?,
+                        ))
+                    });
+                if let Some((name, expected)) = expected {
+                    self.check(&name, &expected, &right, self.strict);
+                }
+            }
+            // defaults are never converted, only ints into floats
+            "property_element" => {
+                let ty = node
+                    .parent()
+                    .and_then(|declaration| declaration.child_by_field_name("type"))
+                    .and_then(|ty| self.written_type(&ty));
+                let (Some(name), Some(default), Some(ty)) = (
+                    node.child_by_field_name("name"),
+                    node.child_by_field_name("default_value"),
+                    ty,
+                ) else {
+                    return;
+                };
+                let name = self.text(&name).to_string();
+                self.check(&name, &ty, &default, true);
+            }
+            "property_promotion_parameter" => {
+                let ty = node
+                    .child_by_field_name("type")
+                    .and_then(|ty| self.written_type(&ty));
+                let (Some(name), Some(default), Some(ty)) = (
+                    node.child_by_field_name("name"),
+                    node.child_by_field_name("default_value"),
+                    ty,
+                ) else {
+                    return;
+                };
+                // parameters with a default of `null` take `null`
+                let ty = match default.kind() {
+                    "null" => Type::union([ty, Type::Null]),
+                    _ => ty,
+                };
+                let name = self.text(&name).to_string();
+                self.check(&name, &ty, &default, true);
+            }
+            _ => {}
+        }
+
+        let mut cursor = node.walk();
+        for child in node.named_children(&mut cursor) {
+            self.walk(&child);
+        }
+    }
+}
+
+/**
+ * Report values of types that a typed property doesn't take, whether they're assigned to it, as
+ * in `$this->count = 'none'` or `self::$count = null`, or are its default, including the defaults
+ * of promoted constructor parameters. Assigned scalars are converted unless the file declares
+ * `strict_types`, but defaults never are.
+ */
+pub fn property_types(
+    root_node: &Node,
+    file_contents: &str,
+    symbols: &FileSymbols,
+    index: &WorkspaceIndex,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let resolver = Resolver::new(file_contents, symbols, index);
+    let mut checker = Checker {
+        contents: file_contents,
+        index,
+        resolver: &resolver,
+        types: TypeInference::new(file_contents, &resolver),
+        strict: is_strict(root_node, file_contents),
+        diagnostics: vec![],
+    };
+    checker.walk(root_node);
+    diagnostics.append(&mut checker.diagnostics);
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::Url;
+
+    use super::property_types;
+    use crate::file::php_parser;
+    use crate::index::WorkspaceIndex;
+    use crate::symbols::file_symbols;
+
+    #[test]
+    fn test_property_types() {
+        let source = "<?php
+class Counter {
+    public int $count = 0;
+    public ?string $label = null;
+    public static int $instances = 'none';
+    public float $rate = 1;
+    public function __construct(public array $items = 'all', public int $step = null) {
+        $this->count = [];
+        $this->label = 'counter';
+        self::$instances = 1;
+    }
+}
+$counter = new Counter();
+$counter->rate = '0.5';
+$counter->label = false;
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(&Url::parse("file:///a.php").unwrap(), symbols.clone());
+        let mut diagnostics = vec![];
+        property_types(
+            &tree.root_node(),
+            source,
+            &symbols,
+            &index,
+            &mut diagnostics,
+        );
+
+        let found: Vec<(u32, String)> = diagnostics
+            .into_iter()
+            .map(|diagnostic| (diagnostic.range.start.line, diagnostic.message))
+            .collect();
+        assert_eq!(
+            vec![
+                (
+                    4,
+                    "`$instances` is of type `int`, but is given `string`".to_string()
+                ),
+                (
+                    6,
+                    "`$items` is of type `array`, but is given `string`".to_string()
+                ),
+                (
+                    7,
+                    "`$count` is of type `int`, but is given `array`".to_string()
+                ),
+            ],
+            found
+        );
+    }
+}
@@ -0,0 +1,324 @@
+use lsp_types::{Diagnostic, DocumentSymbol, FoldingRange, Url};
+
+use dashmap::DashMap;
+
+use std::any::Any;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+use crate::config::Config;
+use crate::diagnostics::diagnostics;
+use crate::document_symbols::document_symbols;
+use crate::file::Snapshot;
+use crate::folding_ranges::folding_ranges;
+use crate::symbols::{file_symbols, FileSymbols};
+
+/**
+ * Everything that can be asked of the database. `Text` and `Config` are inputs; the rest are
+ * derived.
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum QueryKey {
+    Text(Url),
+    Config,
+    Symbols(Url),
+    DocumentSymbols(Url),
+    FoldingRanges(Url),
+    Diagnostics(Url),
+}
+
+impl QueryKey {
+    /// The file a query is about, if it's about one.
+    fn uri(&self) -> Option<&Url> {
+        match self {
+            Self::Text(uri)
+            | Self::Symbols(uri)
+            | Self::DocumentSymbols(uri)
+            | Self::FoldingRanges(uri)
+            | Self::Diagnostics(uri) => Some(uri),
+            Self::Config => None,
+        }
+    }
+}
+
+type Value = Arc<dyn Any + Send + Sync>;
+
+fn value_eq<T: PartialEq + 'static>(a: &Value, b: &Value) -> bool {
+    a.downcast_ref::<T>() == b.downcast_ref::<T>()
+}
+
+fn erase<T: PartialEq + Send + Sync + 'static>(value: T) -> (Value, fn(&Value, &Value) -> bool) {
+    (Arc::new(value), value_eq::<T>)
+}
+
+#[derive(Clone)]
+struct Memo {
+    value: Value,
+    /// Revision in which the value last changed. Recomputing a query that produces an equal
+    /// value keeps the old revision, so that queries depending on it don't need to run again.
+    changed_at: u64,
+    /// Revision in which we last checked that the value is still up to date.
+    verified_at: u64,
+    /// Queries read while computing the value.
+    dependencies: Vec<QueryKey>,
+}
+
+struct Input {
+    snapshot: Snapshot,
+    changed_at: u64,
+}
+
+#[derive(Default)]
+struct ConfigInput {
+    config: Arc<Config>,
+    changed_at: u64,
+}
+
+/**
+ * Memoized analysis of open files.
+ *
+ * Every query remembers which other queries it read. Setting a file's text starts a new
+ * revision; a memoized value is only recomputed once it is asked for again and one of its
+ * dependencies changed since it was last verified. This way an edit only invalidates what is
+ * downstream of it, and features never see results computed from older text.
+ *
+ * Queries and inputs can be used from any number of threads at once. A query works against the
+ * revision it started in; if an input changes while it runs, the result is simply recomputed
+ * the next time it's asked for. Two threads may occasionally compute the same value, in which
+ * case whichever finishes last wins.
+ */
+#[derive(Default)]
+pub struct Database {
+    revision: AtomicU64,
+    inputs: DashMap<Url, Input>,
+    config: RwLock<ConfigInput>,
+    memos: DashMap<QueryKey, Memo>,
+}
+
+impl Database {
+    /// Callers have to make sure that the text of one file isn't set from two threads at once.
+    pub fn set_text(&self, uri: Url, snapshot: Snapshot) {
+        // hold on to the entry while moving to the next revision, so that nobody can see the
+        // new revision with the old text
+        let entry = self.inputs.entry(uri);
+        let revision = self.revision.fetch_add(1, Ordering::SeqCst) + 1;
+        entry.insert(Input {
+            snapshot,
+            changed_at: revision,
+        });
+    }
+
+    /// Forget a file and everything worked out from it.
+    pub fn remove_text(&self, uri: &Url) {
+        self.inputs.remove(uri);
+        self.revision.fetch_add(1, Ordering::SeqCst);
+        self.memos.retain(|key, _| key.uri() != Some(uri));
+    }
+
+    /// Settings that change what analyses report, like which diagnostics are enabled.
+    pub fn set_config(&self, config: Config) {
+        let mut input = self.config.write().expect("config lock poisoned");
+        let revision = self.revision.fetch_add(1, Ordering::SeqCst) + 1;
+        *input = ConfigInput {
+            config: Arc::new(config),
+            changed_at: revision,
+        };
+    }
+
+    pub fn symbols(&self, uri: &Url) -> Arc<FileSymbols> {
+        self.fetch(QueryKey::Symbols(uri.clone()), &mut vec![])
+    }
+
+    pub fn document_symbols(&self, uri: &Url) -> Arc<Vec<DocumentSymbol>> {
+        self.fetch(QueryKey::DocumentSymbols(uri.clone()), &mut vec![])
+    }
+
+    pub fn folding_ranges(&self, uri: &Url) -> Arc<Vec<FoldingRange>> {
+        self.fetch(QueryKey::FoldingRanges(uri.clone()), &mut vec![])
+    }
+
+    pub fn diagnostics(&self, uri: &Url) -> Arc<Vec<Diagnostic>> {
+        self.fetch(QueryKey::Diagnostics(uri.clone()), &mut vec![])
+    }
+
+    fn snapshot(&self, uri: &Url, dependencies: &mut Vec<QueryKey>) -> Option<Snapshot> {
+        dependencies.push(QueryKey::Text(uri.clone()));
+        self.inputs.get(uri).map(|input| input.snapshot.clone())
+    }
+
+    fn config(&self, dependencies: &mut Vec<QueryKey>) -> Arc<Config> {
+        dependencies.push(QueryKey::Config);
+        self.config
+            .read()
+            .expect("config lock poisoned")
+            .config
+            .clone()
+    }
+
+    /// Get the value of a query, recording it as a dependency of whatever is asking.
+    fn fetch<T: Send + Sync + 'static>(
+        &self,
+        key: QueryKey,
+        dependencies: &mut Vec<QueryKey>,
+    ) -> Arc<T> {
+        let revision = self.revision.load(Ordering::SeqCst);
+        self.update(&key, revision);
+        let value = self
+            .memos
+            .get(&key)
+            .map(|memo| memo.value.clone())
+            .expect("query was just updated");
+        dependencies.push(key);
+
+        value
+            .downcast::<T>()
+            .expect("query returned the wrong type")
+    }
+
+    /// Bring a query up to date, returning the revision in which its value last changed.
+    fn update(&self, key: &QueryKey, revision: u64) -> u64 {
+        if let QueryKey::Text(uri) = key {
+            return self.inputs.get(uri).map_or(0, |input| input.changed_at);
+        }
+        if let QueryKey::Config = key {
+            return self.config.read().expect("config lock poisoned").changed_at;
+        }
+
+        // never hold on to a memo while updating others, they may be in the same shard
+        let memo = self.memos.get(key).map(|memo| memo.clone());
+        if let Some(memo) = &memo {
+            if memo.verified_at >= revision {
+                return memo.changed_at;
+            }
+
+            let unchanged = memo
+                .dependencies
+                .iter()
+                .all(|dependency| self.update(dependency, revision) <= memo.verified_at);
+            if unchanged {
+                if let Some(mut memo) = self.memos.get_mut(key) {
+                    memo.verified_at = memo.verified_at.max(revision);
+                }
+                return memo.changed_at;
+            }
+        }
+
+        let mut dependencies = vec![];
+        let (value, eq) = self.execute(key, &mut dependencies);
+        let (value, changed_at) = match memo {
+            Some(old) if eq(&old.value, &value) => (old.value, old.changed_at),
+            _ => (value, revision),
+        };
+
+        self.memos.insert(
+            key.clone(),
+            Memo {
+                value,
+                changed_at,
+                verified_at: revision,
+                dependencies,
+            },
+        );
+        changed_at
+    }
+
+    fn execute(
+        &self,
+        key: &QueryKey,
+        dependencies: &mut Vec<QueryKey>,
+    ) -> (Value, fn(&Value, &Value) -> bool) {
+        match key {
+            QueryKey::Text(_) | QueryKey::Config => unreachable!("inputs are never computed"),
+            QueryKey::Symbols(uri) => erase(
+                self.snapshot(uri, dependencies)
+                    .map(|snapshot| file_symbols(&snapshot.root_node(), &snapshot.contents))
+                    .unwrap_or_default(),
+            ),
+            QueryKey::DocumentSymbols(uri) => erase(
+                self.snapshot(uri, dependencies)
+                    .map(|snapshot| document_symbols(&snapshot.root_node(), &snapshot.contents))
+                    .unwrap_or_default(),
+            ),
+            QueryKey::FoldingRanges(uri) => erase(
+                self.snapshot(uri, dependencies)
+                    .map(|snapshot| folding_ranges(&snapshot.root_node(), &snapshot.contents))
+                    .unwrap_or_default(),
+            ),
+            QueryKey::Diagnostics(uri) => {
+                let config = self.config(dependencies);
+                erase(
+                    self.snapshot(uri, dependencies)
+                        .map(|snapshot| {
+                            diagnostics(&snapshot.root_node(), &snapshot.contents, &config)
+                        })
+                        .unwrap_or_default(),
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::Url;
+
+    use std::sync::Arc;
+
+    use super::Database;
+    use crate::config::{Config, MetricLimits};
+    use crate::file::{php_parser, Snapshot};
+
+    fn set_text(db: &Database, uri: &Url, text: &str) {
+        let snapshot = Snapshot::parse(&mut php_parser(), text).unwrap();
+        db.set_text(uri.clone(), snapshot);
+    }
+
+    #[test]
+    fn test_memoized_until_changed() {
+        let db = Database::default();
+        let a = Url::parse("file:///A.php").unwrap();
+        let b = Url::parse("file:///B.php").unwrap();
+        set_text(&db, &a, "<?php class A {}");
+        set_text(&db, &b, "<?php class B {}");
+
+        let a_symbols = db.symbols(&a);
+        let b_symbols = db.symbols(&b);
+        assert!(Arc::ptr_eq(&a_symbols, &db.symbols(&a)));
+
+        set_text(&db, &a, "<?php class C {}");
+        assert_eq!("C", db.symbols(&a).declarations[0].name);
+        // other files are left alone
+        assert!(Arc::ptr_eq(&b_symbols, &db.symbols(&b)));
+    }
+
+    #[test]
+    fn test_equal_results_are_reused() {
+        let db = Database::default();
+        let a = Url::parse("file:///A.php").unwrap();
+        set_text(&db, &a, "<?php $a = ;");
+
+        let diagnostics = db.diagnostics(&a);
+        assert_eq!(1, diagnostics.len());
+
+        // same text, new revision
+        set_text(&db, &a, "<?php $a = ;");
+        assert!(Arc::ptr_eq(&diagnostics, &db.diagnostics(&a)));
+    }
+
+    #[test]
+    fn test_config_changes() {
+        let db = Database::default();
+        let a = Url::parse("file:///A.php").unwrap();
+        set_text(&db, &a, "<?php function f($a, $b) {}");
+        assert!(db.diagnostics(&a).is_empty());
+
+        db.set_config(Config {
+            metrics: MetricLimits {
+                max_parameters: Some(1),
+                ..MetricLimits::default()
+            },
+            ..Config::default()
+        });
+        assert_eq!(1, db.diagnostics(&a).len());
+    }
+}
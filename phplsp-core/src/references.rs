@@ -0,0 +1,378 @@
+//! Variables bound by reference, which go on changing what they're bound to after the binding:
+//! with values that it can't take, or long after it was meant to, like after a `foreach`.
+
+use lsp_types::*;
+use tree_sitter::Node;
+
+use std::collections::HashMap;
+
+use crate::arguments::{accepts, is_strict};
+use crate::file::to_range;
+use crate::index::WorkspaceIndex;
+use crate::property_types::{accessed_property, property_type};
+use crate::resolve::Resolver;
+use crate::return_types::{is_checked, is_known};
+use crate::scope::{aliases, variables, Alias};
+use crate::symbols::FileSymbols;
+use crate::types::{Type, TypeInference};
+use crate::unreachable::functions;
+
+/// Whether a function or method returns by reference, like `function &items()`.
+fn returns_by_reference(signature: &str) -> bool {
+    signature
+        .split_once('(')
+        .is_some_and(|(before, _)| before.contains('&'))
+}
+
+/// Whether a mention of a variable is an `=` that assigns to it, rather than to something in it.
+fn is_assignment(mention: &Node) -> bool {
+    mention.parent().is_some_and(|parent| {
+        parent.kind() == "assignment_expression"
+            && parent
+                .child_by_field_name("left")
+                .is_some_and(|left| left.id() == mention.id())
+    })
+}
+
+/// What a reference is bound to, and what to call it.
+struct Target {
+    ty: Type,
+    name: String,
+    /// Whether PHP checks values given to it, which typed properties do.
+    enforced: bool,
+}
+
+struct Checker<'a> {
+    contents: &'a str,
+    index: &'a WorkspaceIndex,
+    resolver: &'a Resolver<'a>,
+    types: TypeInference<'a>,
+    strict: bool,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker<'_> {
+    fn text(&self, node: &Node) -> &str {
+        &self.contents[node.byte_range()]
+    }
+
+    fn push(&mut self, node: &Node, severity: DiagnosticSeverity, code: &str, message: String) {
+        self.diagnostics.push(Diagnostic {
+            range: to_range(&node.range()),
+            severity: Some(severity),
+            code: Some(NumberOrString::String(code.to_string())),
+            source: Some("phplsp".to_string()),
+            message,
+            ..Diagnostic::default()
+        });
+    }
+
+    /// The type of what a reference is bound to, if it's one that values given to it should be.
+    fn target(&self, target: &Node) -> Option<Target> {
+        if let Some(property) = accessed_property(target, self.contents, self.resolver, &self.types)
+        {
+            return Some(Target {
+                ty: property_type(self.index, property)?,
+                name: format!("`{}`", self.text(target)),
+                enforced: true,
+            });
+        }
+
+        // what a function returns by reference is only checked when it's returned
+        let callee = self.types.callee(target)?;
+        if !returns_by_reference(&callee.signature) {
+            return None;
+        }
+        let written = callee.return_type()?;
+        if !is_checked(written) {
+            return None;
+        }
+        let ty = Type::parse(written, callee.namespace(), callee.class_name())
+            .filter(|ty| is_known(self.index, ty))?;
+        Some(Target {
+            ty,
+            name: format!("what `{}()` returns", callee.short_name()),
+            enforced: false,
+        })
+    }
+
+    /// The type of a value, if it isn't one that a type accepts.
+    fn rejected(&self, expected: &Type, value: &Node) -> Option<Type> {
+        let actual = self
+            .types
+            .expression(value)
+            .filter(|actual| is_known(self.index, actual))?;
+        (!accepts(&self.types, expected, &actual, self.strict)).then_some(actual)
+    }
+
+    /**
+     * The mentions of a variable from a byte on while it stays bound as it is: until it's bound
+     * again by reference or `unset`.
+     */
+    fn bound<'tree>(
+        &self,
+        mentions: &[Node<'tree>],
+        aliases: &[Alias],
+        from: usize,
+    ) -> Vec<Node<'tree>> {
+        let mut bound = vec![];
+        for mention in mentions
+            .iter()
+            .filter(|mention| mention.start_byte() >= from)
+        {
+            let rebound = aliases
+                .iter()
+                .any(|alias| alias.variable.id() == mention.id());
+            let unset = mention
+                .parent()
+                .is_some_and(|parent| parent.kind() == "unset_statement");
+            if rebound || unset {
+                break;
+            }
+            bound.push(*mention);
+        }
+        bound
+    }
+
+    fn function(&mut self, function: &Node) {
+        let aliases = aliases(function);
+        // every mention of each variable, in order
+        let mut mentions: HashMap<&str, Vec<Node>> = HashMap::new();
+        for (name, variable) in variables(function, self.contents) {
+            let mut all = [variable.writes, variable.reads].concat();
+            all.sort_by_key(|mention| mention.start_byte());
+            mentions.insert(name, all);
+        }
+        let mentions = |name: &str| {
+            mentions
+                .get(name.trim_start_matches('$'))
+                .map_or(&[][..], |mentions| mentions.as_slice())
+        };
+
+        for alias in &aliases {
+            let name = self.text(&alias.variable).to_string();
+            let bound = self.bound(mentions(&name), &aliases, alias.binding.end_byte());
+
+            if alias.binding.kind() == "foreach_statement" {
+                if let Some(reused) = bound.first() {
+                    self.push(
+                        reused,
+                        DiagnosticSeverity::WARNING,
+                        "foreach-reference",
+                        format!(
+                            "`{}` is still a reference to the last element of the `foreach` on line {}; `unset({})` after that loop before using it again",
+                            name,
+                            alias.binding.start_position().row + 1,
+                            name
+                        ),
+                    );
+                }
+                continue;
+            }
+
+            let Some(target) = self.target(&alias.target) else {
+                continue;
+            };
+            for mention in bound.iter().filter(|mention| is_assignment(mention)) {
+                let Some(value) = mention
+                    .parent()
+                    .and_then(|assignment| assignment.child_by_field_name("right"))
+                else {
+                    continue;
+                };
+                let Some(actual) = self.rejected(&target.ty, &value) else {
+                    continue;
+                };
+                let severity = match target.enforced {
+                    true => DiagnosticSeverity::ERROR,
+                    false => DiagnosticSeverity::WARNING,
+                };
+                self.push(
+                    &value,
+                    severity,
+                    "reference-type",
+                    format!(
+                        "`{}` is a reference to {}, which is of type `{}`, but is given `{}`",
+                        name, target.name, target.ty, actual
+                    ),
+                );
+            }
+        }
+
+        // what a function gives to a parameter by reference goes back to whoever called it
+        let Some(parameters) = function.child_by_field_name("parameters") else {
+            return;
+        };
+        let mut cursor = parameters.walk();
+        let by_reference: Vec<(Node, Node)> = parameters
+            .named_children(&mut cursor)
+            .filter(|parameter| {
+                parameter
+                    .child_by_field_name("reference_modifier")
+                    .is_some()
+            })
+            .filter_map(|parameter| {
+                Some((
+                    parameter.child_by_field_name("name")?,
+                    parameter.child_by_field_name("type")?,
+                ))
+            })
+            .collect();
+        for (name, written) in by_reference {
+            if !is_checked(self.text(&written)) {
+                continue;
+            }
+            let Some(ty) = self
+                .types
+                .type_node(&written)
+                .filter(|ty| is_known(self.index, ty))
+            else {
+                continue;
+            };
+            let name = self.text(&name).to_string();
+            let bound = self.bound(mentions(&name), &aliases, parameters.end_byte());
+            for mention in bound.iter().filter(|mention| is_assignment(mention)) {
+                let Some(value) = mention
+                    .parent()
+                    .and_then(|assignment| assignment.child_by_field_name("right"))
+                else {
+                    continue;
+                };
+                if let Some(actual) = self.rejected(&ty, &value) {
+                    self.push(
+                        &value,
+                        DiagnosticSeverity::WARNING,
+                        "reference-type",
+                        format!(
+                            "`{}` is passed by reference as `{}`, but is given `{}`, which the caller gets back",
+                            name, ty, actual
+                        ),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/**
+ * Report variables bound by reference that are given values of a type that what they're bound to
+ * doesn't take: typed properties, which PHP checks, what a function returns by reference, and
+ * typed parameters taken by reference, which go back to the caller. Also report variables that a
+ * `foreach` bound by reference and that are used after it without an `unset`, which changes the
+ * last element it went over.
+ */
+pub fn reference_diagnostics(
+    root_node: &Node,
+    file_contents: &str,
+    symbols: &FileSymbols,
+    index: &WorkspaceIndex,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let resolver = Resolver::new(file_contents, symbols, index);
+    let mut checker = Checker {
+        contents: file_contents,
+        index,
+        resolver: &resolver,
+        types: TypeInference::new(file_contents, &resolver),
+        strict: is_strict(root_node, file_contents),
+        diagnostics: vec![],
+    };
+    let mut found = vec![];
+    functions(root_node, &mut found);
+    for function in found {
+        checker.function(&function);
+    }
+    diagnostics.append(&mut checker.diagnostics);
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::*;
+
+    use super::reference_diagnostics;
+    use crate::file::php_parser;
+    use crate::index::WorkspaceIndex;
+    use crate::symbols::file_symbols;
+
+    #[test]
+    fn test_reference_diagnostics() {
+        let source = "<?php
+declare(strict_types=1);
+class Cart {
+    public int $count = 0;
+    public function &items(): array {}
+    public function add(int &$total) {
+        $count = &$this->count;
+        $count = 'many';
+        $items = &$this->items();
+        $items = 1;
+        unset($count);
+        $count = 'few';
+        $total = 'lots';
+    }
+}
+$rows = [1, 2];
+foreach ($rows as &$row) {
+    $row = $row * 2;
+}
+foreach ($rows as $row) {
+}
+foreach ($rows as &$cell) {
+}
+unset($cell);
+echo $cell;
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(&Url::parse("file:///a.php").unwrap(), symbols.clone());
+        let mut diagnostics = vec![];
+        reference_diagnostics(
+            &tree.root_node(),
+            source,
+            &symbols,
+            &index,
+            &mut diagnostics,
+        );
+
+        let found: Vec<(u32, Option<DiagnosticSeverity>, String)> = diagnostics
+            .into_iter()
+            .map(|diagnostic| {
+                (
+                    diagnostic.range.start.line,
+                    diagnostic.severity,
+                    diagnostic.message,
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                (
+                    19,
+                    Some(DiagnosticSeverity::WARNING),
+                    "`$row` is still a reference to the last element of the `foreach` on line 17; `unset($row)` after that loop before using it again"
+                        .to_string()
+                ),
+                (
+                    7,
+                    Some(DiagnosticSeverity::ERROR),
+                    "`$count` is a reference to `$this->count`, which is of type `int`, but is given `string`"
+                        .to_string()
+                ),
+                (
+                    9,
+                    Some(DiagnosticSeverity::WARNING),
+                    "`$items` is a reference to what `items()` returns, which is of type `array`, but is given `int`"
+                        .to_string()
+                ),
+                (
+                    12,
+                    Some(DiagnosticSeverity::WARNING),
+                    "`$total` is passed by reference as `int`, but is given `string`, which the caller gets back"
+                        .to_string()
+                ),
+            ],
+            found
+        );
+    }
+}
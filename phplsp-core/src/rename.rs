@@ -0,0 +1,1041 @@
+use lsp_types::{Position, Range, TextEdit, Url};
+use tree_sitter::Node;
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::call_hierarchy::call_at;
+use crate::file::{to_point, to_position, to_range};
+use crate::index::WorkspaceIndex;
+use crate::php_namespace::PhpNamespace;
+use crate::preg::literal_chars;
+use crate::resolve::{key, Resolver};
+use crate::symbols::{Declaration, FileSymbols, ReferenceKind};
+use crate::types::TypeInference;
+
+/**
+ * What's left of a name after the namespace it's in, or nothing if it isn't in that namespace.
+ *
+ * Case is ignored, like PHP does.
+ */
+fn within<'a>(name: &'a str, namespace: &str) -> Option<&'a str> {
+    if namespace.is_empty() {
+        return Some(name);
+    }
+    let prefix = name.get(..namespace.len())?;
+    if !prefix.eq_ignore_ascii_case(namespace) {
+        return None;
+    }
+    match &name[namespace.len()..] {
+        "" => Some(""),
+        rest => rest.strip_prefix('\\'),
+    }
+}
+
+fn join(namespace: &str, name: &str) -> String {
+    match (namespace, name) {
+        ("", name) => name.to_string(),
+        (namespace, "") => namespace.to_string(),
+        (namespace, name) => format!("{}\\{}", namespace, name),
+    }
+}
+
+/// Whether a namespace can be written in PHP, like `App\Billing`.
+pub fn is_valid_namespace(namespace: &str) -> bool {
+    !namespace.is_empty()
+        && namespace.split('\\').all(|segment| {
+            let mut chars = segment.chars();
+            chars
+                .next()
+                .is_some_and(|c| c.is_alphabetic() || c == '_' || !c.is_ascii())
+                && chars.all(|c| c.is_alphanumeric() || c == '_' || !c.is_ascii())
+        })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Place {
+    /// `namespace App;` or the prefix of a group `use`.
+    Declaration,
+    /// A name imported by a `use`.
+    Import { has_alias: bool },
+    /// A name in code.
+    Code,
+}
+
+/// A name as it's written in a file.
+struct WrittenName<'tree> {
+    /// The name without any leading `\`.
+    node_range: tree_sitter::Range,
+    /// The `name` node of every segment.
+    segments: Vec<Node<'tree>>,
+    /// What the written name is relative to.
+    base: String,
+    text: String,
+    place: Place,
+}
+
+impl WrittenName<'_> {
+    fn resolved(&self) -> String {
+        join(&self.base, &self.text)
+    }
+}
+
+struct Names<'a, 'tree> {
+    contents: &'a str,
+    /// Where references start, for telling relative names from ones that use an import.
+    references: HashMap<(u32, u32), &'a str>,
+    names: Vec<WrittenName<'tree>>,
+}
+
+impl<'tree> Names<'_, 'tree> {
+    /// Record a `namespace_name` or `qualified_name`.
+    fn push(&mut self, node: &Node<'tree>, base: &str, place: Place) {
+        let mut segments = vec![];
+        let mut stack = vec![*node];
+        while let Some(node) = stack.pop() {
+            if node.kind() == "name" {
+                segments.push(node);
+                continue;
+            }
+            let mut cursor = node.walk();
+            let children: Vec<Node> = node.named_children(&mut cursor).collect();
+            stack.extend(children.into_iter().rev());
+        }
+        let (Some(first), Some(last)) = (segments.first(), segments.last()) else {
+            return;
+        };
+
+        let mut node_range = node.range();
+        node_range.start_byte = first.start_byte();
+        node_range.start_point = first.start_position();
+        node_range.end_byte = last.end_byte();
+        node_range.end_point = last.end_position();
+        self.names.push(WrittenName {
+            text: self.contents[node_range.start_byte..node_range.end_byte].to_string(),
+            node_range,
+            segments,
+            base: base.to_string(),
+            place,
+        });
+    }
+
+    fn use_declaration(&mut self, node: &Node<'tree>) {
+        let mut group_prefix = String::new();
+        let mut cursor = node.walk();
+        for child in node.named_children(&mut cursor) {
+            match child.kind() {
+                "namespace_name" => {
+                    group_prefix = self.contents[child.byte_range()]
+                        .trim_start_matches('\\')
+                        .to_string();
+                    self.push(&child, "", Place::Declaration);
+                }
+                "namespace_use_clause" => self.use_clause(&child, ""),
+                "namespace_use_group" => {
+                    let mut cursor = child.walk();
+                    for clause in child.named_children(&mut cursor) {
+                        self.use_clause(&clause, &group_prefix);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn use_clause(&mut self, clause: &Node<'tree>, base: &str) {
+        let mut cursor = clause.walk();
+        let name = clause
+            .named_children(&mut cursor)
+            .find(|child| child.kind() == "qualified_name");
+        if let Some(name) = name {
+            let has_alias = clause.child_by_field_name("alias").is_some();
+            self.push(&name, base, Place::Import { has_alias });
+        }
+    }
+
+    fn qualified_name(&mut self, node: &Node<'tree>, namespace: &str) {
+        let text = &self.contents[node.byte_range()];
+        if text.starts_with('\\') {
+            self.push(node, "", Place::Code);
+            return;
+        }
+
+        // a name that starts with an import is renamed by renaming the import
+        let relative = join(namespace, text);
+        if self
+            .references
+            .get(&key(&to_position(&node.start_position())))
+            .is_some_and(|resolved| resolved.eq_ignore_ascii_case(&relative))
+        {
+            self.push(node, namespace, Place::Code);
+        }
+    }
+
+    fn walk(&mut self, node: &Node<'tree>, namespace: &mut String) {
+        match node.kind() {
+            "namespace_definition" => {
+                let name = node.child_by_field_name("name");
+                let text = name
+                    .map(|name| self.contents[name.byte_range()].to_string())
+                    .unwrap_or_default();
+                if let Some(name) = name {
+                    self.push(&name, "", Place::Declaration);
+                }
+                match node.child_by_field_name("body") {
+                    Some(body) => {
+                        let mut inner = text;
+                        self.walk(&body, &mut inner);
+                    }
+                    // without a body, the namespace applies to the rest of the file
+                    None => *namespace = text,
+                }
+                return;
+            }
+            "namespace_use_declaration" => {
+                self.use_declaration(node);
+                return;
+            }
+            "qualified_name" => {
+                self.qualified_name(node, namespace);
+                return;
+            }
+            _ => {}
+        }
+
+        let mut cursor = node.walk();
+        for child in node.children(&mut cursor) {
+            self.walk(&child, namespace);
+        }
+    }
+}
+
+fn written_names<'tree>(
+    root_node: &Node<'tree>,
+    file_contents: &str,
+    symbols: &FileSymbols,
+) -> Vec<WrittenName<'tree>> {
+    let mut names = Names {
+        contents: file_contents,
+        references: symbols
+            .references
+            .iter()
+            .map(|reference| (key(&reference.range.start), reference.name.as_str()))
+            .collect(),
+        names: vec![],
+    };
+    names.walk(root_node, &mut String::new());
+    names.names
+}
+
+/**
+ * The namespace whose name is at a position, and the range of the part of it that is written
+ * there.
+ *
+ * Only the segments of a name that are certainly namespaces count: the last segment of a name in
+ * code or in a `use` is usually a class.
+ */
+pub fn namespace_at(
+    root_node: &Node,
+    file_contents: &str,
+    symbols: &FileSymbols,
+    position: &Position,
+) -> Option<(Range, String)> {
+    let point = to_point(position);
+    let name = written_names(root_node, file_contents, symbols)
+        .into_iter()
+        .find(|name| name.node_range.start_point <= point && point <= name.node_range.end_point)?;
+
+    let namespace_segments = match name.place {
+        Place::Declaration => name.segments.len(),
+        Place::Import { .. } | Place::Code => name.segments.len() - 1,
+    };
+    let segment = name.segments[..namespace_segments]
+        .iter()
+        .find(|segment| point <= segment.end_position())?;
+
+    let end = segment.end_byte();
+    let written = &file_contents[name.node_range.start_byte..end];
+    Some((
+        Range {
+            start: to_position(&name.node_range.start_point),
+            end: to_position(&segment.end_position()),
+        },
+        join(&name.base, written),
+    ))
+}
+
+/**
+ * The edits of one file for a rename, apart from the guesses among them, which might not be
+ * what's meant and can be confirmed first.
+ */
+#[derive(Debug, Default, PartialEq)]
+pub struct RenameEdits {
+    pub edits: Vec<TextEdit>,
+    pub guesses: Vec<TextEdit>,
+}
+
+/**
+ * Edits of strings that are nothing but a qualified name, like `'App\Models\User'`, that a
+ * rename gives a new name. Backslashes are escaped where the string escaped them, and always in
+ * double quotes, where `\n` and the like are escape sequences. Names without a backslash are left
+ * alone, since those are usually words.
+ */
+fn qualified_strings(
+    node: &Node,
+    file_contents: &str,
+    rename: &impl Fn(&str) -> Option<String>,
+    edits: &mut Vec<TextEdit>,
+) {
+    if matches!(node.kind(), "string" | "encapsed_string") {
+        let Some(chars) = literal_chars(node, file_contents) else {
+            return;
+        };
+        let value: String = chars.iter().map(|c| c.c).collect();
+        let (leading, name) = match value.strip_prefix('\\') {
+            Some(name) => ("\\", name),
+            None => ("", value.as_str()),
+        };
+        if !name.contains('\\')
+            || !is_valid_namespace(name)
+            || node.start_position().row != node.end_position().row
+        {
+            return;
+        }
+        let Some(new) = rename(name) else {
+            return;
+        };
+
+        let written = &file_contents[node.byte_range()];
+        let new_text = format!("{}{}", leading, new);
+        let new_text = match written.contains("\\\\") || node.kind() == "encapsed_string" {
+            true => new_text.replace('\\', "\\\\"),
+            false => new_text,
+        };
+        let mut range = to_range(&node.range());
+        // inside the quotes, which are one byte
+        range.start.character += 1;
+        range.end.character -= 1;
+        edits.push(TextEdit { range, new_text });
+        return;
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        qualified_strings(&child, file_contents, rename, edits);
+    }
+}
+
+/**
+ * Edits that move everything in one namespace to another: namespace declarations, `use`
+ * statements, and names in code that are qualified with the namespace.
+ *
+ * Names that are relative to a namespace that is itself being renamed are left alone, since
+ * renaming that namespace keeps them correct. Names in strings are renamed too, as guesses.
+ */
+pub fn rename_namespace(
+    root_node: &Node,
+    file_contents: &str,
+    symbols: &FileSymbols,
+    old: &str,
+    new: &str,
+) -> RenameEdits {
+    let old_last = old.rsplit('\\').next().unwrap_or(old);
+    let new_last = new.rsplit('\\').next().unwrap_or(new);
+
+    let mut edits = vec![];
+    for name in written_names(root_node, file_contents, symbols) {
+        let resolved = name.resolved();
+        let Some(rest) = within(&resolved, old) else {
+            continue;
+        };
+        if !name.base.is_empty() && within(&name.base, old).is_some() {
+            continue;
+        }
+
+        let renamed = join(new, rest);
+        let mut new_text = match within(&renamed, &name.base) {
+            Some(relative) if !relative.is_empty() => relative.to_string(),
+            // only names in code can be fully qualified
+            _ if name.place == Place::Code => format!("\\{}", renamed),
+            _ => continue,
+        };
+        // `use App\Legacy;` imported `Legacy`, which code still refers to
+        if name.place == (Place::Import { has_alias: false })
+            && rest.is_empty()
+            && old_last != new_last
+        {
+            new_text = format!("{} as {}", new_text, old_last);
+        }
+
+        let mut start = to_position(&name.node_range.start_point);
+        // the name may already be fully qualified
+        if new_text.starts_with('\\') && file_contents[..name.node_range.start_byte].ends_with('\\')
+        {
+            start.character -= 1;
+        }
+        edits.push(TextEdit {
+            range: Range {
+                start,
+                end: to_position(&name.node_range.end_point),
+            },
+            new_text,
+        });
+    }
+
+    let mut guesses = vec![];
+    let rename = |name: &str| within(name, old).map(|rest| join(new, rest));
+    qualified_strings(root_node, file_contents, &rename, &mut guesses);
+    RenameEdits { edits, guesses }
+}
+
+/**
+ * Whether a file declares or references anything in a namespace, and so might need to change
+ * when the namespace is renamed.
+ *
+ * Files that only import something from the namespace without using it are missed.
+ */
+pub fn mentions_namespace(symbols: &FileSymbols, namespace: &str) -> bool {
+    let declarations = symbols.declarations.iter().map(|d| d.name.as_str());
+    let references = symbols.references.iter().map(|r| r.name.as_str());
+    declarations
+        .chain(references)
+        .any(|name| within(name, namespace).is_some_and(|rest| !rest.is_empty()))
+}
+
+/**
+ * Directories that should be renamed along with a namespace, following the PSR-4 mappings of
+ * `composer.json`, as pairs of the old and new directory.
+ *
+ * Only directories whose namespace stays under the same mapping are moved.
+ */
+pub fn namespace_directories(
+    namespace_dirs: &HashMap<PhpNamespace, Vec<PathBuf>>,
+    old: &str,
+    new: &str,
+) -> Vec<(PathBuf, PathBuf)> {
+    let Ok(old) = PhpNamespace::from_str(old);
+    let Ok(new) = PhpNamespace::from_str(new);
+
+    let mut renames = vec![];
+    for (prefix, dirs) in namespace_dirs {
+        let (Some(old_path), Some(new_path)) =
+            (old.relative_path(prefix), new.relative_path(prefix))
+        else {
+            continue;
+        };
+        if old_path.as_os_str().is_empty() || new_path.as_os_str().is_empty() {
+            continue;
+        }
+
+        for dir in dirs {
+            if dir.join(&old_path).is_dir() {
+                renames.push((dir.join(&old_path), dir.join(&new_path)));
+            }
+        }
+    }
+
+    renames.sort();
+    renames
+}
+
+/// The text of a range that's on one line.
+fn text_at<'a>(file_contents: &'a str, range: &Range) -> Option<&'a str> {
+    if range.start.line != range.end.line {
+        return None;
+    }
+    let line = file_contents.lines().nth(range.start.line as usize)?;
+    line.get(range.start.character as usize..range.end.character as usize)
+}
+
+/**
+ * Edits that rename a declaration in one file, along with the imports of it and references to it
+ * there. Only the last segment of a qualified name is changed, and names that don't end with the
+ * old name, like aliases, are left alone.
+ *
+ * Members are referenced by their name alone, so references that we can tell are to a member of
+ * another class are left alone, but references whose class we can't tell are renamed as guesses,
+ * and so are qualified names of what's renamed in strings.
+ */
+pub fn rename_symbol(
+    root_node: &Node,
+    file_contents: &str,
+    uri: &Url,
+    symbols: &FileSymbols,
+    index: &WorkspaceIndex,
+    declaration: &Declaration,
+    new_name: &str,
+) -> RenameEdits {
+    let resolver = Resolver::new(file_contents, symbols, index);
+    let types = TypeInference::new(file_contents, &resolver);
+    let old_name = declaration.short_name().to_lowercase();
+
+    let mut edits: Vec<TextEdit> = symbols
+        .declarations
+        .iter()
+        .filter(|other| other.name == declaration.name)
+        .map(|other| TextEdit {
+            range: other.range,
+            new_text: new_name.to_string(),
+        })
+        .collect();
+    if declaration.class_name().is_none() {
+        // imports aren't references, but have to be renamed for the references to still resolve
+        let imports = written_names(root_node, file_contents, symbols)
+            .into_iter()
+            .filter(|name| {
+                matches!(name.place, Place::Import { .. })
+                    && name.resolved().eq_ignore_ascii_case(&declaration.name)
+            });
+        edits.extend(imports.filter_map(|name| {
+            let last = name.segments.last()?;
+            Some(TextEdit {
+                range: to_range(&last.range()),
+                new_text: new_name.to_string(),
+            })
+        }));
+    }
+    let mut guesses = vec![];
+    let references = index
+        .references(declaration)
+        .into_iter()
+        .filter(|(reference_uri, _)| *reference_uri == uri);
+    for (_, reference) in references {
+        let target = match call_at(root_node, &reference.range.start) {
+            Some(call) => types.callee(&call),
+            None => resolver.target(root_node, &reference.range.start),
+        };
+        let known = match target {
+            Some(target) if !target.name.eq_ignore_ascii_case(&declaration.name) => continue,
+            // only members are referenced by a name that could be something else's
+            target => target.is_some() || declaration.class_name().is_none(),
+        };
+        let Some(text) = text_at(file_contents, &reference.range) else {
+            continue;
+        };
+        let last = text.rsplit('\\').next().unwrap_or(text);
+        if last.to_lowercase() != old_name {
+            continue;
+        }
+
+        let mut range = reference.range;
+        range.start.character = range.end.character - last.len() as u32;
+        let edit = TextEdit {
+            range,
+            new_text: new_name.to_string(),
+        };
+        match known {
+            true => edits.push(edit),
+            false => guesses.push(edit),
+        }
+    }
+    if declaration.class_name().is_none() {
+        let (namespace, _) = split_class(&declaration.name);
+        let rename = |name: &str| {
+            name.eq_ignore_ascii_case(&declaration.name)
+                .then(|| join(namespace, new_name))
+        };
+        qualified_strings(root_node, file_contents, &rename, &mut guesses);
+    }
+
+    edits.sort_by_key(|edit| key(&edit.range.start));
+    edits.dedup();
+    RenameEdits { edits, guesses }
+}
+
+/// Splits a class into its namespace and its own name.
+fn split_class(class: &str) -> (&str, &str) {
+    class.rsplit_once('\\').unwrap_or(("", class))
+}
+
+/**
+ * Edits that move classes to other fully qualified names in one file, as pairs of the old and
+ * new name, for when their files are moved: the `namespace` and the name of their declarations,
+ * `use` statements of them, and references to them.
+ *
+ * Names that end up pointing somewhere else get a `use`, like references to a class from its old
+ * namespace, and references in a class to others of its old namespace that stay. Files are taken
+ * to be in the namespace that they declare first. Names in strings are moved too, as guesses.
+ */
+pub fn move_classes(
+    root_node: &Node,
+    file_contents: &str,
+    symbols: &FileSymbols,
+    moves: &[(String, String)],
+) -> RenameEdits {
+    let moved = |name: &str| {
+        moves
+            .iter()
+            .find(|(old, _)| old.eq_ignore_ascii_case(name))
+            .map(|(_, new)| new.as_str())
+    };
+    let declared = moves.iter().find(|(old, _)| {
+        symbols
+            .declarations
+            .iter()
+            .any(|declaration| declaration.name.eq_ignore_ascii_case(old))
+    });
+
+    let mut cursor = root_node.walk();
+    let top_level: Vec<Node> = root_node.named_children(&mut cursor).collect();
+    let namespace_name = top_level
+        .iter()
+        .find(|node| node.kind() == "namespace_definition")
+        .and_then(|node| node.child_by_field_name("name"));
+    let old_namespace = namespace_name
+        .map(|name| &file_contents[name.byte_range()])
+        .unwrap_or_default();
+    let namespace = match declared {
+        Some((_, new)) => split_class(new).0,
+        None => old_namespace,
+    };
+
+    let mut edits = vec![];
+    let mut imported = vec![];
+    let mut imports = BTreeSet::new();
+    if namespace != old_namespace {
+        match namespace_name {
+            Some(name) => edits.push(TextEdit {
+                range: to_range(&name.range()),
+                new_text: namespace.to_string(),
+            }),
+            None => {
+                if let Some(tag) = top_level.iter().find(|node| node.kind() == "php_tag") {
+                    let end = to_position(&tag.end_position());
+                    edits.push(TextEdit {
+                        range: Range { start: end, end },
+                        new_text: format!("\n\nnamespace {};", namespace),
+                    });
+                }
+            }
+        }
+    }
+    for declaration in &symbols.declarations {
+        let Some(new) = moved(&declaration.name) else {
+            continue;
+        };
+        let new_short = split_class(new).1;
+        if declaration.short_name() != new_short {
+            edits.push(TextEdit {
+                range: declaration.range,
+                new_text: new_short.to_string(),
+            });
+        }
+    }
+
+    for name in written_names(root_node, file_contents, symbols) {
+        if !matches!(name.place, Place::Import { .. }) {
+            continue;
+        }
+        imported.push(
+            name.segments
+                .last()
+                .map(|last| &file_contents[last.byte_range()]),
+        );
+        let Some(new) = moved(&name.resolved()) else {
+            continue;
+        };
+        let Some(new_text) = within(new, &name.base).filter(|relative| !relative.is_empty()) else {
+            continue;
+        };
+        edits.push(TextEdit {
+            range: to_range(&name.node_range),
+            new_text: new_text.to_string(),
+        });
+    }
+    let is_imported = |text: &str| {
+        imported
+            .iter()
+            .flatten()
+            .any(|last| last.eq_ignore_ascii_case(text))
+    };
+
+    let references = symbols
+        .references
+        .iter()
+        .filter(|reference| reference.kind == ReferenceKind::Class);
+    for reference in references {
+        let Some(text) = text_at(file_contents, &reference.range) else {
+            continue;
+        };
+        let qualified = text.contains('\\');
+        let Some(new) = moved(&reference.name) else {
+            // the file used others of its old namespace without importing them
+            if !namespace.eq_ignore_ascii_case(old_namespace)
+                && !qualified
+                && !is_imported(text)
+                && within(&reference.name, old_namespace).is_some_and(|rest| rest == text)
+            {
+                imports.insert(reference.name.clone());
+            }
+            continue;
+        };
+
+        let (new_namespace, new_short) = split_class(new);
+        let new_text = match qualified {
+            true => format!("\\{}", new),
+            // an alias
+            false if !text.eq_ignore_ascii_case(split_class(&reference.name).1) => continue,
+            false => {
+                if !is_imported(text) && !namespace.eq_ignore_ascii_case(new_namespace) {
+                    imports.insert(new.to_string());
+                }
+                new_short.to_string()
+            }
+        };
+        if new_text != text {
+            edits.push(TextEdit {
+                range: reference.range,
+                new_text,
+            });
+        }
+    }
+
+    // new imports go after the others, or else where they can
+    let after = top_level
+        .iter()
+        .rev()
+        .find(|node| node.kind() == "namespace_use_declaration")
+        .or_else(|| {
+            top_level.iter().find(|node| {
+                node.kind() == "namespace_definition" && node.child_by_field_name("body").is_none()
+            })
+        })
+        .or_else(|| top_level.iter().find(|node| node.kind() == "php_tag"));
+    if let Some(after) = after.filter(|_| !imports.is_empty()) {
+        let end = to_position(&after.end_position());
+        let mut new_text = String::new();
+        if after.kind() != "namespace_use_declaration" {
+            new_text.push('\n');
+        }
+        for import in imports {
+            new_text.push_str(&format!("\nuse {};", import));
+        }
+        edits.push(TextEdit {
+            range: Range { start: end, end },
+            new_text,
+        });
+    }
+
+    edits.sort_by_key(|edit| key(&edit.range.start));
+    edits.dedup();
+    let mut guesses = vec![];
+    let rename = |name: &str| moved(name).map(str::to_string);
+    qualified_strings(root_node, file_contents, &rename, &mut guesses);
+    RenameEdits { edits, guesses }
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::{Position, Range, TextEdit, Url};
+
+    use std::collections::HashMap;
+    use std::fs;
+    use std::str::FromStr;
+
+    use super::{
+        is_valid_namespace, move_classes, namespace_at, namespace_directories, rename_namespace,
+        rename_symbol,
+    };
+    use crate::file::php_parser;
+    use crate::index::WorkspaceIndex;
+    use crate::php_namespace::PhpNamespace;
+    use crate::symbols::file_symbols;
+
+    /// Apply edits, which must not overlap, to a source.
+    fn apply(source: &str, edits: &[TextEdit]) -> String {
+        let lines: Vec<&str> = source.split('\n').collect();
+        let mut lines: Vec<String> = lines.into_iter().map(String::from).collect();
+        let mut edits = edits.to_vec();
+        edits.sort_by_key(|edit| (edit.range.start.line, edit.range.start.character));
+        for edit in edits.iter().rev() {
+            assert_eq!(edit.range.start.line, edit.range.end.line);
+            let line = &mut lines[edit.range.start.line as usize];
+            line.replace_range(
+                edit.range.start.character as usize..edit.range.end.character as usize,
+                &edit.new_text,
+            );
+        }
+        lines.join("\n")
+    }
+
+    /// Rename a namespace in a source, guesses and all.
+    fn rename(source: &str, old: &str, new: &str) -> String {
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let edits = rename_namespace(&tree.root_node(), source, &symbols, old, new);
+        apply(source, &[edits.edits, edits.guesses].concat())
+    }
+
+    #[test]
+    fn test_rename_namespace() {
+        assert_eq!(
+            "<?php
+namespace App\\Billing\\Invoices;
+use App\\Billing\\Invoice;
+use App\\Billing as Legacy;
+use App\\Billing\\{Order, Sub\\Line};
+use App\\Other;
+new \\App\\Billing\\Invoice();
+new Legacy\\Invoice();
+$classes = ['App\\Billing\\Invoice', \"\\\\App\\\\Billing\\\\Order\", 'App\\Other', \"App\\Legacy$suffix\"];
+",
+            rename(
+                "<?php
+namespace App\\Legacy\\Invoices;
+use App\\Legacy\\Invoice;
+use App\\Legacy;
+use App\\Legacy\\{Order, Sub\\Line};
+use App\\Other;
+new \\App\\Legacy\\Invoice();
+new Legacy\\Invoice();
+$classes = ['App\\Legacy\\Invoice', \"\\\\App\\\\Legacy\\\\Order\", 'App\\Other', \"App\\Legacy$suffix\"];
+",
+                "App\\Legacy",
+                "App\\Billing"
+            )
+        );
+    }
+
+    #[test]
+    fn test_relative_names() {
+        // relative to a namespace that moves along
+        assert_eq!(
+            "<?php\nnamespace App\\Billing;\nnew Sub\\Thing();\n",
+            rename(
+                "<?php\nnamespace App\\Legacy;\nnew Sub\\Thing();\n",
+                "App\\Legacy",
+                "App\\Billing"
+            )
+        );
+        // relative to a namespace that stays, into one that isn't under it anymore
+        assert_eq!(
+            "<?php\nnamespace App;\nnew \\Billing\\Thing();\n",
+            rename(
+                "<?php\nnamespace App;\nnew Legacy\\Thing();\n",
+                "App\\Legacy",
+                "Billing"
+            )
+        );
+    }
+
+    #[test]
+    fn test_namespace_at() {
+        let source = "<?php\nnamespace App\\Legacy\\Invoices;\nnew \\App\\Legacy\\Invoice();\n";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let at = |line, character| {
+            namespace_at(
+                &tree.root_node(),
+                source,
+                &symbols,
+                &Position { line, character },
+            )
+            .map(|(_, namespace)| namespace)
+        };
+
+        assert_eq!(Some("App\\Legacy".to_string()), at(1, 16));
+        assert_eq!(Some("App\\Legacy\\Invoices".to_string()), at(1, 24));
+        assert_eq!(Some("App".to_string()), at(2, 6));
+        // the class
+        assert_eq!(None, at(2, 20));
+    }
+
+    #[test]
+    fn test_is_valid_namespace() {
+        assert!(is_valid_namespace("App\\Billing"));
+        assert!(!is_valid_namespace("App\\\\Billing"));
+        assert!(!is_valid_namespace("App\\2Billing"));
+        assert!(!is_valid_namespace(""));
+    }
+
+    #[test]
+    fn test_namespace_directories() {
+        let root = std::env::temp_dir().join("phplsp-test-namespace-directories");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("src/Legacy")).unwrap();
+
+        let mut namespace_dirs = HashMap::new();
+        namespace_dirs.insert(
+            PhpNamespace::from_str("App\\").unwrap(),
+            vec![root.join("src")],
+        );
+
+        assert_eq!(
+            vec![(root.join("src/Legacy"), root.join("src/Billing"))],
+            namespace_directories(&namespace_dirs, "App\\Legacy", "App\\Billing")
+        );
+        assert!(namespace_directories(&namespace_dirs, "App\\Legacy", "Other").is_empty());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_rename_symbol() {
+        let declared = "<?php
+namespace App;
+class Foo {
+    public function bar() {}
+}
+class Other {
+    public function bar() {}
+}
+";
+        let used = "<?php
+use App\\Foo;
+use App\\Foo as Alias;
+$foo = new Foo();
+$foo->bar();
+(new \\App\\Other())->bar();
+$unknown->bar();
+Alias::class;
+$class = 'App\\Foo';
+";
+        let declared_uri = Url::parse("file:///project/Foo.php").unwrap();
+        let used_uri = Url::parse("file:///project/used.php").unwrap();
+        let mut index = WorkspaceIndex::default();
+        let mut trees = vec![];
+        for (uri, source) in [(&declared_uri, declared), (&used_uri, used)] {
+            let tree = php_parser().parse(source, None).unwrap();
+            index.update_symbols(uri, file_symbols(&tree.root_node(), source));
+            trees.push(tree);
+        }
+        let rename = |declaration: &str, new_name: &str| {
+            let (_, declaration) = index.locate(declaration).unwrap();
+            [(&declared_uri, declared), (&used_uri, used)]
+                .into_iter()
+                .zip(&trees)
+                .map(|((uri, source), tree)| {
+                    let edits = rename_symbol(
+                        &tree.root_node(),
+                        source,
+                        uri,
+                        index.symbols(uri).unwrap(),
+                        &index,
+                        declaration,
+                        new_name,
+                    );
+                    (apply(source, &edits.edits), edits.guesses)
+                })
+                .collect::<Vec<(String, Vec<TextEdit>)>>()
+        };
+
+        let renamed = rename("App\\Foo", "Bar");
+        assert!(renamed[0].0.contains("class Bar {"));
+        assert_eq!(
+            "<?php
+use App\\Bar;
+use App\\Bar as Alias;
+$foo = new Bar();
+$foo->bar();
+(new \\App\\Other())->bar();
+$unknown->bar();
+Alias::class;
+$class = 'App\\Foo';
+",
+            renamed[1].0
+        );
+        // names in strings might not be meant
+        assert_eq!(
+            vec![TextEdit {
+                range: Range::new(Position::new(8, 10), Position::new(8, 17)),
+                new_text: "App\\Bar".to_string(),
+            }],
+            renamed[1].1
+        );
+
+        let renamed = rename("App\\Foo::bar", "baz");
+        assert!(renamed[0]
+            .0
+            .contains("public function baz() {}\n}\nclass Other {\n    public function bar()"));
+        assert_eq!(
+            "<?php
+use App\\Foo;
+use App\\Foo as Alias;
+$foo = new Foo();
+$foo->baz();
+(new \\App\\Other())->bar();
+$unknown->bar();
+Alias::class;
+$class = 'App\\Foo';
+",
+            renamed[1].0
+        );
+        // nor members of what we can't tell the class of
+        assert_eq!(
+            vec![TextEdit {
+                range: Range::new(Position::new(6, 10), Position::new(6, 13)),
+                new_text: "baz".to_string(),
+            }],
+            renamed[1].1
+        );
+    }
+
+    #[test]
+    fn test_move_classes() {
+        let moved = |source: &str| {
+            let tree = php_parser().parse(source, None).unwrap();
+            let symbols = file_symbols(&tree.root_node(), source);
+            let moves = [
+                ("App\\Models\\User", "App\\Auth\\Account"),
+                ("App\\Models\\Role", "App\\Auth\\Role"),
+            ]
+            .map(|(old, new)| (old.to_string(), new.to_string()));
+            let edits = move_classes(&tree.root_node(), source, &symbols, &moves);
+            apply(source, &[edits.edits, edits.guesses].concat())
+        };
+
+        assert_eq!(
+            "<?php
+namespace App\\Auth;
+
+use App\\Models\\Team;
+class Account extends Team {
+    function role(): Role {}
+}
+",
+            moved(
+                "<?php
+namespace App\\Models;
+class User extends Team {
+    function role(): Role {}
+}
+"
+            )
+        );
+        assert_eq!(
+            "<?php
+namespace App\\Models;
+
+use App\\Auth\\Account;
+new Account();
+",
+            moved(
+                "<?php
+namespace App\\Models;
+new User();
+"
+            )
+        );
+        assert_eq!(
+            "<?php
+use App\\Auth\\Account;
+use App\\Auth\\Account as Member;
+new Account();
+new Member();
+new \\App\\Auth\\Account();
+$class = '\\App\\Auth\\Account';
+",
+            moved(
+                "<?php
+use App\\Models\\User;
+use App\\Models\\User as Member;
+new User();
+new Member();
+new \\App\\Models\\User();
+$class = '\\App\\Models\\User';
+"
+            )
+        );
+    }
+}
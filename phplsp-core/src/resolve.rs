@@ -0,0 +1,421 @@
+use lsp_types::{Position, Range};
+use tree_sitter::Node;
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::file::{to_point, to_position};
+use crate::index::WorkspaceIndex;
+use crate::symbols::{Declaration, FileSymbols, Reference, ReferenceKind};
+
+/// Node kinds that `$this`, `self` and `static` refer to.
+const CLASS_LIKES: &[&str] = &[
+    "class_declaration",
+    "interface_declaration",
+    "trait_declaration",
+    "enum_declaration",
+];
+
+/// Positions aren't hashable, so everything is keyed on their line and character.
+pub(crate) fn key(position: &Position) -> (u32, u32) {
+    (position.line, position.character)
+}
+
+/**
+ * Finds what the names in one file refer to.
+ *
+ * Classes, functions and constants are looked up by the names the file's symbols resolved them
+ * to. Members can only be found when we can tell which class they are accessed on, which is when
+ * they are accessed through `$this`, `self`, `static` or a class name.
+ */
+pub struct Resolver<'a> {
+    contents: &'a str,
+    index: &'a WorkspaceIndex,
+    /// Declarations in this file, by where their name starts.
+    declarations: HashMap<(u32, u32), &'a Declaration>,
+    /// References in this file, by where they start.
+    references: HashMap<(u32, u32), &'a Reference>,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(contents: &'a str, symbols: &'a FileSymbols, index: &'a WorkspaceIndex) -> Self {
+        Self {
+            contents,
+            index,
+            declarations: symbols
+                .declarations
+                .iter()
+                .map(|declaration| (key(&declaration.range.start), declaration))
+                .collect(),
+            references: symbols
+                .references
+                .iter()
+                .map(|reference| (key(&reference.range.start), reference))
+                .collect(),
+        }
+    }
+
+    pub fn index(&self) -> &'a WorkspaceIndex {
+        self.index
+    }
+
+    /// The declaration in this file whose name starts at a position.
+    pub fn declaration_at(&self, start: &Position) -> Option<&'a Declaration> {
+        self.declarations.get(&key(start)).copied()
+    }
+
+    /// The reference in this file that starts at a position.
+    pub fn reference_at(&self, start: &Position) -> Option<&'a Reference> {
+        self.references.get(&key(start)).copied()
+    }
+
+    /// What a class, function or constant reference refers to.
+    pub fn resolve(&self, reference: &Reference) -> Option<&'a Declaration> {
+        self.index.find_declaration(&reference.name).or_else(|| {
+            reference
+                .fallback
+                .as_ref()
+                .and_then(|fallback| self.index.find_declaration(fallback))
+        })
+    }
+
+    /// The fully qualified name of the class that `$this`, `self` or `static` refers to.
+    pub fn enclosing_class(&self, node: &Node) -> Option<&'a str> {
+        let mut current = node.parent();
+        while let Some(parent) = current {
+            if CLASS_LIKES.contains(&parent.kind()) {
+                let name_node = parent.child_by_field_name("name")?;
+                return self
+                    .declaration_at(&to_position(&name_node.start_position()))
+                    .map(|declaration| declaration.name.as_str());
+            }
+            current = parent.parent();
+        }
+
+        None
+    }
+
+    /// The fully qualified name of the class that a member is accessed on, if we can tell.
+    pub fn accessed_class(&self, object: &Node) -> Option<&'a str> {
+        match self.contents[object.byte_range()].to_lowercase().as_str() {
+            "$this" | "self" | "static" => self.enclosing_class(object),
+            _ => self
+                .references
+                .get(&key(&to_position(&object.start_position())))
+                .filter(|reference| reference.kind == ReferenceKind::Class)
+                .map(|reference| reference.name.as_str()),
+        }
+    }
+
+    /**
+     * A class-like, then what it extends, implements and uses, then what those do and so on, each
+     * once.
+     */
+    pub fn ancestors(&self, class: &str) -> Vec<String> {
+        let mut ancestors: Vec<String> = vec![];
+        let mut pending = VecDeque::from([class.to_string()]);
+        while let Some(class) = pending.pop_front() {
+            if ancestors
+                .iter()
+                .any(|seen| seen.eq_ignore_ascii_case(&class))
+            {
+                continue;
+            }
+            if let Some(declaration) = self.index.find_declaration(&class) {
+                pending.extend(declaration.supertypes.iter().cloned());
+            }
+            ancestors.push(class);
+        }
+
+        ancestors
+    }
+
+    /**
+     * A member of a class, or else of the closest of its ancestors that has it, like a constant of
+     * an interface it implements. Properties are named with their `$`.
+     */
+    pub fn member(&self, class: &str, name: &str) -> Option<&'a Declaration> {
+        self.ancestors(class).iter().find_map(|ancestor| {
+            self.index
+                .find_declaration(&format!("{}::{}", ancestor, name))
+        })
+    }
+
+    /**
+     * What a declaration overrides: for a member, the closest ancestor's member of the same name,
+     * and for a class-like, the first of what it extends, implements and uses that's declared.
+     */
+    pub fn overridden(&self, declaration: &Declaration) -> Option<&'a Declaration> {
+        match declaration.class_name() {
+            Some(class) => self.ancestors(class).iter().skip(1).find_map(|ancestor| {
+                self.index
+                    .find_declaration(&format!("{}::{}", ancestor, declaration.short_name()))
+            }),
+            None => declaration
+                .supertypes
+                .iter()
+                .find_map(|supertype| self.index.find_declaration(supertype)),
+        }
+    }
+
+    /// Every member of a class and its ancestors, leaving out those that a closer one overrides.
+    pub fn members(&self, class: &str) -> Vec<&'a Declaration> {
+        let mut members: Vec<&Declaration> = vec![];
+        for ancestor in self.ancestors(class) {
+            let Some((uri, _)) = self.index.locate(&ancestor) else {
+                continue;
+            };
+            let declared = self.index.symbols(uri).into_iter().flat_map(|symbols| {
+                symbols.declarations.iter().filter(|declaration| {
+                    declaration
+                        .class_name()
+                        .is_some_and(|owner| owner.eq_ignore_ascii_case(&ancestor))
+                })
+            });
+            for member in declared {
+                if !members.iter().any(|closer| {
+                    closer
+                        .short_name()
+                        .eq_ignore_ascii_case(member.short_name())
+                }) {
+                    members.push(member);
+                }
+            }
+        }
+
+        members
+    }
+
+    /// Whether a class-like is another one, or extends, implements or uses it somewhere up the line.
+    pub fn is_subtype(&self, class: &str, of: &str) -> bool {
+        self.ancestors(class)
+            .iter()
+            .any(|ancestor| ancestor.eq_ignore_ascii_case(of))
+    }
+
+    /**
+     * The declaration that the name at a position declares or refers to.
+     *
+     * Members can only be found when we can tell which class they belong to, as with
+     * [`Self::accessed_class`], or when a doc comment names them as `Class::member`.
+     */
+    pub fn target(&self, root_node: &Node, position: &Position) -> Option<&'a Declaration> {
+        let contains = |range: &Range| range.start <= *position && *position <= range.end;
+        if let Some(declaration) = self
+            .declarations
+            .values()
+            .find(|declaration| contains(&declaration.range))
+        {
+            return Some(declaration);
+        }
+
+        let reference = self
+            .references
+            .values()
+            .find(|reference| contains(&reference.range))?;
+        match reference.kind {
+            ReferenceKind::Member => self.member_reference(root_node, reference),
+            _ => self.resolve(reference),
+        }
+    }
+
+    fn member_reference(&self, root_node: &Node, reference: &Reference) -> Option<&'a Declaration> {
+        let start = to_point(&reference.range.start);
+        let node = root_node.descendant_for_point_range(start, start)?;
+        // properties are declared with their `$`, but referenced without it
+        let property = format!("${}", reference.name);
+
+        if node.kind() == "comment" {
+            // `Class::member`, right after the reference to the class
+            let class = self.references.values().find(|class| {
+                class.kind == ReferenceKind::Class
+                    && class.range.end.line == reference.range.start.line
+                    && class.range.end.character + 2 == reference.range.start.character
+            })?;
+            return self
+                .member(&class.name, &reference.name)
+                .or_else(|| self.member(&class.name, &property));
+        }
+
+        // the name is at most a `variable_name` deep in whatever accesses it
+        let mut current = node.parent();
+        for _ in 0..2 {
+            let parent = current?;
+            match parent.kind() {
+                "member_call_expression"
+                | "nullsafe_member_call_expression"
+                | "scoped_call_expression" => return self.callee(&parent),
+                "member_access_expression" | "nullsafe_member_access_expression" => {
+                    let class = self.accessed_class(&parent.child_by_field_name("object")?)?;
+                    return self.member(class, &property);
+                }
+                "scoped_property_access_expression" => {
+                    let class = self.accessed_class(&parent.child_by_field_name("scope")?)?;
+                    return self.member(class, &property);
+                }
+                "class_constant_access_expression" => {
+                    let class = self.accessed_class(&parent.named_child(0)?)?;
+                    return self.member(class, &reference.name);
+                }
+                _ => current = parent.parent(),
+            }
+        }
+
+        None
+    }
+
+    /// The function, method or constructor that a call expression calls.
+    pub fn callee(&self, call: &Node) -> Option<&'a Declaration> {
+        match call.kind() {
+            "function_call_expression" => {
+                let function = call.child_by_field_name("function")?;
+                let reference = self
+                    .references
+                    .get(&key(&to_position(&function.start_position())))?;
+                self.resolve(reference)
+            }
+            "member_call_expression" | "nullsafe_member_call_expression" => {
+                let class = self.accessed_class(&call.child_by_field_name("object")?)?;
+                let name = call.child_by_field_name("name")?;
+                self.member(class, &self.contents[name.byte_range()])
+            }
+            "scoped_call_expression" => {
+                let class = self.accessed_class(&call.child_by_field_name("scope")?)?;
+                let name = call.child_by_field_name("name")?;
+                self.member(class, &self.contents[name.byte_range()])
+            }
+            "object_creation_expression" => {
+                let mut cursor = call.walk();
+                let class = call
+                    .named_children(&mut cursor)
+                    .find(|child| matches!(child.kind(), "name" | "qualified_name"))?;
+                let class = self.accessed_class(&class)?;
+                self.member(class, "__construct")
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::{Position, Url};
+    use tree_sitter::Node;
+
+    use super::Resolver;
+    use crate::file::php_parser;
+    use crate::index::WorkspaceIndex;
+    use crate::symbols::file_symbols;
+
+    fn calls<'tree>(node: &Node<'tree>, calls: &mut Vec<Node<'tree>>) {
+        if node.kind().ends_with("call_expression") || node.kind() == "object_creation_expression" {
+            calls.push(*node);
+        }
+
+        let mut cursor = node.walk();
+        for child in node.children(&mut cursor) {
+            self::calls(&child, calls);
+        }
+    }
+
+    #[test]
+    fn test_target() {
+        let source = "<?php
+namespace App;
+class A {
+    public $size;
+    const LIMIT = 1;
+    /** @see A::grow() */
+    public function grow() { $this->size = self::LIMIT; }
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(&Url::parse("file:///A.php").unwrap(), symbols.clone());
+        let resolver = Resolver::new(source, &symbols, &index);
+        let target = |line, character| {
+            resolver
+                .target(&tree.root_node(), &Position { line, character })
+                .map(|declaration| declaration.name.as_str())
+        };
+
+        assert_eq!(Some("App\\A"), target(2, 6));
+        assert_eq!(Some("App\\A"), target(5, 13));
+        assert_eq!(Some("App\\A::grow"), target(5, 16));
+        assert_eq!(Some("App\\A::$size"), target(6, 38));
+        assert_eq!(Some("App\\A::LIMIT"), target(6, 52));
+        assert_eq!(None, target(6, 30));
+    }
+
+    #[test]
+    fn test_callee() {
+        let source = "<?php
+namespace App;
+function helper() {}
+class A {
+    public function __construct() {}
+    public static function make() { return new A(); }
+    public function run() { $this->run(); self::make(); helper(); strlen(''); }
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(&Url::parse("file:///A.php").unwrap(), symbols.clone());
+        let resolver = Resolver::new(source, &symbols, &index);
+
+        let mut found = vec![];
+        calls(&tree.root_node(), &mut found);
+        let callees: Vec<Option<&str>> = found
+            .iter()
+            .map(|call| {
+                resolver
+                    .callee(call)
+                    .map(|declaration| declaration.name.as_str())
+            })
+            .collect();
+
+        assert_eq!(
+            vec![
+                Some("App\\A::__construct"),
+                Some("App\\A::run"),
+                Some("App\\A::make"),
+                Some("App\\helper"),
+                None,
+            ],
+            callees
+        );
+    }
+
+    #[test]
+    fn test_inherited_members() {
+        let source = "<?php
+interface HasLimit { const LIMIT = 10; }
+class Base implements HasLimit { const NAME = 'base'; public function run() {} }
+class Child extends Base { const NAME = 'child'; }
+Child::LIMIT;
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(&Url::parse("file:///A.php").unwrap(), symbols.clone());
+        let resolver = Resolver::new(source, &symbols, &index);
+
+        assert_eq!(
+            vec!["Child", "Base", "HasLimit"],
+            resolver.ancestors("Child")
+        );
+        assert_eq!(
+            Some("HasLimit::LIMIT"),
+            resolver
+                .target(&tree.root_node(), &Position::new(4, 8))
+                .map(|declaration| declaration.name.as_str())
+        );
+        let members: Vec<&str> = resolver
+            .members("Child")
+            .into_iter()
+            .map(|declaration| declaration.name.as_str())
+            .collect();
+        assert_eq!(vec!["Child::NAME", "Base::run", "HasLimit::LIMIT"], members);
+    }
+}
@@ -0,0 +1,327 @@
+//! `return` statements whose value isn't of the type that their function is declared to return,
+//! either by its return type or by the `@return` tag of its doc comment.
+
+use lsp_types::*;
+use tree_sitter::Node;
+
+use std::collections::HashMap;
+
+use crate::arguments::{accepts, is_strict};
+use crate::file::{to_position, to_range};
+use crate::index::WorkspaceIndex;
+use crate::missing_return::is_generator;
+use crate::resolve::Resolver;
+use crate::symbols::FileSymbols;
+use crate::tags::{doc_comments, doc_return};
+use crate::types::{return_statements, Type, TypeInference};
+
+/// Node kinds of functions, whose `return` statements are their own.
+const FUNCTIONS: &[&str] = &[
+    "function_definition",
+    "method_declaration",
+    "anonymous_function",
+    "arrow_function",
+];
+
+/// Types that take values we can't tell apart well enough: any object, strings and arrays that
+/// name callables, and iterators.
+const UNCHECKED: &[&str] = &["object", "callable", "iterable"];
+
+/// Whether a type as it's written is one we can check values against.
+pub(crate) fn is_checked(written: &str) -> bool {
+    let written = written.to_ascii_lowercase();
+    !written.contains(['<', '(', '{'])
+        && written
+            .split(['|', '&', '?'])
+            .all(|member| !UNCHECKED.contains(&member.trim().trim_start_matches('\\')))
+}
+
+/// Whether the classes in a type are ones we know, so that we can tell what extends them.
+pub(crate) fn is_known(index: &WorkspaceIndex, ty: &Type) -> bool {
+    match ty {
+        Type::Class(class) => {
+            class.eq_ignore_ascii_case("Closure") || index.find_declaration(class).is_some()
+        }
+        Type::Array(Some(value)) => is_known(index, value),
+        Type::Shape(entries) => entries.iter().all(|(_, ty)| is_known(index, ty)),
+        Type::Union(members) => members.iter().all(|member| is_known(index, member)),
+        _ => true,
+    }
+}
+
+struct Checker<'a, 'tree> {
+    contents: &'a str,
+    index: &'a WorkspaceIndex,
+    resolver: &'a Resolver<'a>,
+    types: TypeInference<'a>,
+    comments: HashMap<usize, Node<'tree>>,
+    /// Whether the file has `strict_types`, without which returned scalars are converted.
+    strict: bool,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'tree> Checker<'_, 'tree> {
+    fn is_known(&self, ty: &Type) -> bool {
+        is_known(self.index, ty)
+    }
+
+    /// The return type of a function.
+    fn declared(&self, function: &Node) -> Option<Type> {
+        let return_type = function.child_by_field_name("return_type")?;
+        if !is_checked(&self.contents[return_type.byte_range()]) {
+            return None;
+        }
+        self.types.type_node(&return_type)
+    }
+
+    /// The type in the `@return` tag of a function's doc comment.
+    fn documented(&self, function: &Node) -> Option<Type> {
+        let comment = self.comments.get(&function.id())?;
+        let written = doc_return(&self.contents[comment.byte_range()])?;
+        if !is_checked(written) {
+            return None;
+        }
+        // names in doc comments are seen from where the function is declared
+        let name = function.child_by_field_name("name")?;
+        let declaration = self
+            .resolver
+            .declaration_at(&to_position(&name.start_position()))?;
+        Type::parse(written, declaration.namespace(), declaration.class_name())
+    }
+
+    fn check_value(
+        &mut self,
+        function: &Node,
+        value: &Node,
+        declared: Option<&Type>,
+        documented: Option<&Type>,
+    ) {
+        let Some(actual) = self
+            .types
+            .expression(value)
+            .filter(|actual| self.is_known(actual))
+        else {
+            return;
+        };
+        // the return type is what PHP checks, so breaking it is an error, and what PHP converts
+        // to it is what's returned, which the `@return` tag is about
+        let converted = declared.is_some() && !self.strict;
+        let (expected, severity) = match (declared, documented) {
+            (Some(declared), _) if !accepts(&self.types, declared, &actual, self.strict) => {
+                (declared, DiagnosticSeverity::ERROR)
+            }
+            (_, Some(documented)) if !accepts(&self.types, documented, &actual, !converted) => {
+                (documented, DiagnosticSeverity::WARNING)
+            }
+            _ => return,
+        };
+
+        let name = match function.child_by_field_name("name") {
+            Some(name) => format!("`{}`", &self.contents[name.byte_range()]),
+            None => "closure".to_string(),
+        };
+        self.diagnostics.push(Diagnostic {
+            range: to_range(&value.range()),
+            severity: Some(severity),
+            code: Some(NumberOrString::String("return-type".to_string())),
+            source: Some("phplsp".to_string()),
+            message: format!(
+                "{} is declared to return `{}`, but returns `{}` here",
+                name, expected, actual
+            ),
+            ..Diagnostic::default()
+        });
+    }
+
+    fn check_function(&mut self, function: &Node<'tree>) {
+        let Some(body) = function.child_by_field_name("body") else {
+            return;
+        };
+        let declared = self.declared(function).filter(|ty| self.is_known(ty));
+        let documented = self.documented(function).filter(|ty| self.is_known(ty));
+        if declared.is_none() && documented.is_none() {
+            return;
+        }
+
+        if function.kind() == "arrow_function" {
+            self.check_value(function, &body, declared.as_ref(), documented.as_ref());
+            return;
+        }
+        // what generators return is what the generator gives with `getReturn()`
+        if is_generator(&body) {
+            return;
+        }
+        let mut returns = vec![];
+        return_statements(&body, &mut returns);
+        for statement in returns {
+            if let Some(value) = statement.named_child(0) {
+                self.check_value(function, &value, declared.as_ref(), documented.as_ref());
+            }
+        }
+    }
+
+    fn walk(&mut self, node: &Node<'tree>) {
+        if FUNCTIONS.contains(&node.kind()) {
+            self.check_function(node);
+        }
+
+        let mut cursor = node.walk();
+        for child in node.named_children(&mut cursor) {
+            self.walk(&child);
+        }
+    }
+}
+
+/**
+ * Report values returned by functions that aren't of the type the function is declared to
+ * return, like `string|false` from a function that returns `string`. The return type is an
+ * error to break, since PHP throws a `TypeError` then, and the `@return` tag is a warning.
+ *
+ * Scalars are only checked against return types in files with `strict_types`, since PHP converts
+ * them otherwise. Only values whose type we can work out are checked, against types whose classes
+ * are all indexed; `object`, `callable`, `iterable` and generic types in doc comments aren't
+ * checked.
+ */
+pub fn return_types(
+    root_node: &Node,
+    file_contents: &str,
+    symbols: &FileSymbols,
+    index: &WorkspaceIndex,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let resolver = Resolver::new(file_contents, symbols, index);
+    let mut checker = Checker {
+        contents: file_contents,
+        index,
+        resolver: &resolver,
+        types: TypeInference::new(file_contents, &resolver),
+        comments: doc_comments(root_node, file_contents),
+        strict: is_strict(root_node, file_contents),
+        diagnostics: vec![],
+    };
+    checker.walk(root_node);
+    diagnostics.append(&mut checker.diagnostics);
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::{DiagnosticSeverity, Url};
+
+    use super::return_types;
+    use crate::file::php_parser;
+    use crate::index::WorkspaceIndex;
+    use crate::symbols::file_symbols;
+
+    fn check(source: &str) -> Vec<(u32, DiagnosticSeverity, String)> {
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(&Url::parse("file:///a.php").unwrap(), symbols.clone());
+        let mut diagnostics = vec![];
+        return_types(
+            &tree.root_node(),
+            source,
+            &symbols,
+            &index,
+            &mut diagnostics,
+        );
+
+        diagnostics
+            .into_iter()
+            .map(|diagnostic| {
+                (
+                    diagnostic.range.start.line,
+                    diagnostic.severity.unwrap(),
+                    diagnostic.message,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_return_types() {
+        let source = "<?php
+declare(strict_types=1);
+class Model {}
+class User extends Model {}
+function find(string $s): string {
+    if ($s === '') {
+        return false;
+    }
+    return $s;
+}
+function load(): Model { return new User(); }
+/** @return int */
+function count_all() { return 'many'; }
+function nothing(): void { return 1; }
+/** @return array<int> */
+function ids() { return 'no'; }
+function any(): object { return 1; }
+function items(): iterable { yield 1; return 2; }
+$f = fn(): int => 1.5;
+";
+        assert_eq!(
+            vec![
+                (
+                    6,
+                    DiagnosticSeverity::ERROR,
+                    "`find` is declared to return `string`, but returns `bool` here".to_string()
+                ),
+                (
+                    12,
+                    DiagnosticSeverity::WARNING,
+                    "`count_all` is declared to return `int`, but returns `string` here"
+                        .to_string()
+                ),
+                (
+                    13,
+                    DiagnosticSeverity::ERROR,
+                    "`nothing` is declared to return `void`, but returns `int` here".to_string()
+                ),
+                (
+                    18,
+                    DiagnosticSeverity::ERROR,
+                    "closure is declared to return `int`, but returns `float` here".to_string()
+                ),
+            ],
+            check(source)
+        );
+    }
+
+    #[test]
+    fn test_converted_return_types() {
+        // without `strict_types`, PHP converts returned scalars to the return type
+        let source = "<?php
+class Model {}
+function id(): string { return 5; }
+function whole(): int { return 1.0; }
+/** @return string */
+function label(): string { return 1; }
+function load(): Model { return 'model'; }
+function nothing(): void { return 1; }
+/** @return int */
+function count_all() { return 'many'; }
+";
+        assert_eq!(
+            vec![
+                (
+                    6,
+                    DiagnosticSeverity::ERROR,
+                    "`load` is declared to return `Model`, but returns `string` here".to_string()
+                ),
+                (
+                    7,
+                    DiagnosticSeverity::ERROR,
+                    "`nothing` is declared to return `void`, but returns `int` here".to_string()
+                ),
+                (
+                    9,
+                    DiagnosticSeverity::WARNING,
+                    "`count_all` is declared to return `int`, but returns `string` here"
+                        .to_string()
+                ),
+            ],
+            check(source)
+        );
+    }
+}
@@ -0,0 +1,276 @@
+//! The variables of a function, and where each of them is written and read.
+
+use tree_sitter::Node;
+
+use std::collections::HashMap;
+
+/// Node kinds with variables of their own. Arrow functions see the variables around them.
+pub const FUNCTIONS: &[&str] = &[
+    "function_definition",
+    "method_declaration",
+    "anonymous_function",
+];
+
+/// Node kinds in a function whose code isn't part of it.
+const OTHER_SCOPES: &[&str] = &[
+    "function_definition",
+    "method_declaration",
+    "class_declaration",
+    "declaration_list",
+];
+
+/// Where a variable is given a value, and where that value is used.
+#[derive(Debug, Default)]
+pub struct Variable<'tree> {
+    /// The variable names that are assigned to: by `=`, destructuring, `foreach`, or `$a[] =`.
+    pub writes: Vec<Node<'tree>>,
+    /// Every other mention, including `+=`, `++` and capturing the variable in a closure.
+    pub reads: Vec<Node<'tree>>,
+}
+
+/// Whether a node is what an `=` assigns to, maybe through `[]` like `$a[] = 1`.
+fn is_assigned(node: &Node) -> bool {
+    let mut current = *node;
+    while let Some(parent) = current.parent() {
+        match parent.kind() {
+            "assignment_expression" => {
+                return parent
+                    .child_by_field_name("left")
+                    .is_some_and(|left| left.id() == current.id());
+            }
+            "subscript_expression"
+                if parent
+                    .named_child(0)
+                    .is_some_and(|array| array.id() == current.id()) =>
+            {
+                current = parent;
+            }
+            // `[$a, $b] = ...` and `list('key' => $c) = ...`, leaving out the keys
+            "list_literal"
+                if current
+                    .next_sibling()
+                    .is_none_or(|next| next.kind() != "=>") =>
+            {
+                current = parent;
+            }
+            _ => return false,
+        }
+    }
+
+    false
+}
+
+/// Whether a variable name is what a `foreach` binds its keys or values to.
+fn is_foreach_binding(node: &Node) -> bool {
+    let mut binding = *node;
+    if binding
+        .parent()
+        .is_some_and(|parent| parent.kind() == "pair")
+    {
+        binding = binding.parent().unwrap_or(binding);
+    }
+    binding.parent().is_some_and(|foreach| {
+        foreach.kind() == "foreach_statement"
+            && foreach
+                .named_child(1)
+                .is_some_and(|bound| bound.id() == binding.id())
+    })
+}
+
+fn walk<'a, 'tree>(
+    node: &Node<'tree>,
+    file_contents: &'a str,
+    variables: &mut HashMap<&'a str, Variable<'tree>>,
+) {
+    match node.kind() {
+        kind if OTHER_SCOPES.contains(&kind) => return,
+        // a closure only shares the variables it captures
+        "anonymous_function" => {
+            let mut cursor = node.walk();
+            for child in node.named_children(&mut cursor) {
+                if child.kind() == "anonymous_function_use_clause" {
+                    walk(&child, file_contents, variables);
+                }
+            }
+            return;
+        }
+        "variable_name" => {
+            let name = file_contents[node.byte_range()].trim_start_matches('$');
+            let variable = variables.entry(name).or_default();
+            match is_assigned(node) || is_foreach_binding(node) {
+                true => variable.writes.push(*node),
+                false => variable.reads.push(*node),
+            }
+            return;
+        }
+        _ => {}
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        walk(&child, file_contents, variables);
+    }
+}
+
+/// The code of a function, or the file's own statements.
+fn body<'tree>(function: &Node<'tree>) -> Option<Node<'tree>> {
+    match function.kind() {
+        "program" => Some(*function),
+        _ => function.child_by_field_name("body"),
+    }
+}
+
+/**
+ * The variables used in the body of a function, by name without the `$`, with where each of
+ * them is written and read in order, or those of the file's own statements. Parameters only show
+ * up where the body uses them, and variables of closures in it only where they're captured.
+ */
+pub fn variables<'a, 'tree>(
+    function: &Node<'tree>,
+    file_contents: &'a str,
+) -> HashMap<&'a str, Variable<'tree>> {
+    let mut variables = HashMap::new();
+    if let Some(body) = body(function) {
+        walk(&body, file_contents, &mut variables);
+    }
+    variables
+}
+
+/// A variable bound as a reference to something else, by `$a = &$b` or `foreach ($b as &$a)`.
+#[derive(Debug)]
+pub struct Alias<'tree> {
+    /// The variable name that becomes a reference.
+    pub variable: Node<'tree>,
+    /// What it's a reference to, or into: the right of `= &`, or what the `foreach` goes over.
+    pub target: Node<'tree>,
+    /// The `= &` or the `foreach`, after which the variable is a reference.
+    pub binding: Node<'tree>,
+}
+
+fn walk_aliases<'tree>(node: &Node<'tree>, aliases: &mut Vec<Alias<'tree>>) {
+    match node.kind() {
+        kind if OTHER_SCOPES.contains(&kind) => return,
+        "anonymous_function" => return,
+        "reference_assignment_expression" => {
+            let (Some(variable), Some(target)) = (
+                node.child_by_field_name("left"),
+                node.child_by_field_name("right"),
+            ) else {
+                return;
+            };
+            if variable.kind() == "variable_name" {
+                aliases.push(Alias {
+                    variable,
+                    target,
+                    binding: *node,
+                });
+            }
+        }
+        "foreach_statement" => {
+            let bound = node.named_child(1).map(|bound| match bound.kind() {
+                "pair" => bound.named_child(1).unwrap_or(bound),
+                _ => bound,
+            });
+            let variable = bound
+                .filter(|bound| bound.kind() == "by_ref")
+                .and_then(|bound| bound.named_child(0))
+                .filter(|variable| variable.kind() == "variable_name");
+            if let (Some(variable), Some(target)) = (variable, node.named_child(0)) {
+                aliases.push(Alias {
+                    variable,
+                    target,
+                    binding: *node,
+                });
+            }
+        }
+        _ => {}
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        walk_aliases(&child, aliases);
+    }
+}
+
+/**
+ * The variables that the body of a function binds as references, in the order they're bound.
+ * Closures in it are left out: they only share a variable that they capture.
+ */
+pub fn aliases<'tree>(function: &Node<'tree>) -> Vec<Alias<'tree>> {
+    let mut aliases = vec![];
+    if let Some(body) = body(function) {
+        walk_aliases(&body, &mut aliases);
+    }
+    aliases
+}
+
+#[cfg(test)]
+mod test {
+    use super::{aliases, variables};
+    use crate::file::php_parser;
+
+    #[test]
+    fn test_variables() {
+        let source = "<?php
+function f($in) {
+    $a = $in;
+    [$b, 'k' => $c] = $a;
+    $d[] = $b;
+    $e += 1;
+    foreach ($d as $key => $value) {}
+    $g = function () use ($c) { $unseen = 1; };
+    $h = fn() => $e;
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let function = tree.root_node().named_child(1).unwrap();
+        let variables = variables(&function, source);
+
+        let mut found: Vec<(&str, usize, usize)> = variables
+            .iter()
+            .map(|(name, variable)| (*name, variable.writes.len(), variable.reads.len()))
+            .collect();
+        found.sort();
+        assert_eq!(
+            vec![
+                ("a", 1, 1),
+                ("b", 1, 1),
+                ("c", 1, 1),
+                ("d", 1, 1),
+                ("e", 0, 2),
+                ("g", 1, 0),
+                ("h", 1, 0),
+                ("in", 0, 1),
+                ("key", 1, 0),
+                ("value", 1, 0),
+            ],
+            found
+        );
+    }
+
+    #[test]
+    fn test_aliases() {
+        let source = "<?php
+function f($rows) {
+    foreach ($rows as $key => &$row) {}
+    foreach ($rows as $value) {}
+    $first = &$rows[0];
+    $this->items[] = &$first;
+    $g = function () use (&$first) { $inner = &$first; };
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let function = tree.root_node().named_child(1).unwrap();
+
+        let found: Vec<(&str, &str)> = aliases(&function)
+            .iter()
+            .map(|alias| {
+                (
+                    &source[alias.variable.byte_range()],
+                    &source[alias.target.byte_range()],
+                )
+            })
+            .collect();
+        assert_eq!(vec![("$row", "$rows"), ("$first", "$rows[0]")], found);
+    }
+}
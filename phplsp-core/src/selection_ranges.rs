@@ -0,0 +1,98 @@
+use lsp_types::*;
+use tree_sitter::Node;
+
+use crate::file::{to_point, to_range};
+
+/**
+ * Ranges to grow the selection through, starting at the smallest node under the cursor and going
+ * up through its ancestors, e.g. identifier → expression → statement → block → function.
+ *
+ * Ancestors that cover exactly the same text as their child are skipped, since selecting them
+ * wouldn't change anything.
+ */
+pub fn selection_range(root_node: &Node, position: &Position) -> SelectionRange {
+    let point = to_point(position);
+    let mut node = root_node
+        .descendant_for_point_range(point, point)
+        .unwrap_or(*root_node);
+
+    let mut ranges = vec![to_range(&node.range())];
+    while let Some(parent) = node.parent() {
+        let range = to_range(&parent.range());
+        if ranges.last() != Some(&range) {
+            ranges.push(range);
+        }
+        node = parent;
+    }
+
+    let mut selection: Option<SelectionRange> = None;
+    for range in ranges.into_iter().rev() {
+        selection = Some(SelectionRange {
+            range,
+            parent: selection.map(Box::new),
+        });
+    }
+
+    selection.expect("there is always at least one range")
+}
+
+pub fn selection_ranges(root_node: &Node, positions: &[Position]) -> Vec<SelectionRange> {
+    positions
+        .iter()
+        .map(|position| selection_range(root_node, position))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::*;
+
+    use super::selection_range;
+    use crate::file::php_parser;
+
+    #[test]
+    fn test_expand_selection() {
+        let source = "<?php
+function f() {
+    if (true) {
+        $value = strlen($name);
+    }
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let selection = selection_range(&tree.root_node(), &Position::new(3, 26));
+
+        let mut texts = vec![];
+        let mut current = Some(&selection);
+        while let Some(selection) = current {
+            let start = selection.range.start;
+            let end = selection.range.end;
+            let lines: Vec<&str> = source.lines().collect();
+            let text = if start.line == end.line {
+                lines[start.line as usize][start.character as usize..end.character as usize]
+                    .to_string()
+            } else {
+                format!("{}..{}", start.line, end.line)
+            };
+            texts.push(text);
+            current = selection.parent.as_deref();
+        }
+
+        assert_eq!(
+            vec![
+                "name",
+                "$name",
+                "($name)",
+                "strlen($name)",
+                "$value = strlen($name)",
+                "$value = strlen($name);",
+                "2..4",
+                "2..4",
+                "1..5",
+                "1..5",
+                "0..6",
+            ],
+            texts
+        );
+    }
+}
@@ -0,0 +1,579 @@
+use lsp_types::*;
+use tree_sitter::Node;
+
+use dashmap::DashMap;
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::file::{to_point, to_range};
+use crate::index::WorkspaceIndex;
+use crate::resolve::{key, Resolver};
+use crate::symbols::{Declaration, DeclarationKind, FileSymbols, ReferenceKind};
+use crate::tags::{definitions, DefinitionKind};
+
+pub const TOKEN_TYPES: &[SemanticTokenType] = &[
+    SemanticTokenType::NAMESPACE,
+    SemanticTokenType::CLASS,
+    SemanticTokenType::INTERFACE,
+    SemanticTokenType::ENUM,
+    SemanticTokenType::ENUM_MEMBER,
+    SemanticTokenType::FUNCTION,
+    SemanticTokenType::METHOD,
+    SemanticTokenType::PARAMETER,
+    SemanticTokenType::VARIABLE,
+    SemanticTokenType::PROPERTY,
+];
+
+pub const TOKEN_MODIFIERS: &[SemanticTokenModifier] = &[
+    SemanticTokenModifier::DECLARATION,
+    SemanticTokenModifier::READONLY,
+    SemanticTokenModifier::STATIC,
+    SemanticTokenModifier::DEPRECATED,
+];
+
+const DECLARATION: u32 = 1;
+const READONLY: u32 = 1 << 1;
+const STATIC: u32 = 1 << 2;
+const DEPRECATED: u32 = 1 << 3;
+
+pub fn legend() -> SemanticTokensLegend {
+    SemanticTokensLegend {
+        token_types: TOKEN_TYPES.to_vec(),
+        token_modifiers: TOKEN_MODIFIERS.to_vec(),
+    }
+}
+
+/// Node kinds whose parameters are visible inside of them.
+const FUNCTION_LIKES: &[&str] = &[
+    "function_definition",
+    "method_declaration",
+    "anonymous_function",
+    "arrow_function",
+];
+
+fn declaration_type(kind: DeclarationKind) -> SemanticTokenType {
+    match kind {
+        // LSP has no token type for traits
+        DeclarationKind::Class | DeclarationKind::Trait => SemanticTokenType::CLASS,
+        DeclarationKind::Interface => SemanticTokenType::INTERFACE,
+        DeclarationKind::Enum => SemanticTokenType::ENUM,
+        DeclarationKind::Function => SemanticTokenType::FUNCTION,
+        DeclarationKind::Method => SemanticTokenType::METHOD,
+        DeclarationKind::Property => SemanticTokenType::PROPERTY,
+        // LSP has no token type for constants either, so we do what other servers do
+        DeclarationKind::Constant => SemanticTokenType::VARIABLE,
+        DeclarationKind::EnumCase => SemanticTokenType::ENUM_MEMBER,
+    }
+}
+
+fn declaration_modifiers(declaration: &Declaration) -> u32 {
+    let mut modifiers = 0;
+    if declaration.deprecated {
+        modifiers |= DEPRECATED;
+    }
+    if declaration.has_modifier("static") {
+        modifiers |= STATIC;
+    }
+    if declaration.has_modifier("readonly") {
+        modifiers |= READONLY;
+    }
+    if declaration.kind == DeclarationKind::Constant {
+        modifiers |= READONLY;
+        if declaration.class_name().is_some() {
+            modifiers |= STATIC;
+        }
+    }
+
+    modifiers
+}
+
+struct Token {
+    length: u32,
+    token_type: SemanticTokenType,
+    modifiers: u32,
+}
+
+struct Highlighter<'a, 'tree> {
+    root_node: Node<'tree>,
+    contents: &'a str,
+    resolver: Resolver<'a>,
+    /// Tokens by where they start, so they come out sorted.
+    tokens: BTreeMap<(u32, u32), Token>,
+}
+
+impl<'a, 'tree> Highlighter<'a, 'tree> {
+    fn text(&self, node: &Node) -> &'a str {
+        &self.contents[node.byte_range()]
+    }
+
+    fn add(&mut self, range: Range, token_type: SemanticTokenType, modifiers: u32) {
+        // tokens can't span lines
+        if range.start.line != range.end.line {
+            return;
+        }
+
+        self.tokens.insert(
+            key(&range.start),
+            Token {
+                length: range.end.character - range.start.character,
+                token_type,
+                modifiers,
+            },
+        );
+    }
+
+    fn definitions(&mut self) {
+        for definition in definitions(&self.root_node, self.contents) {
+            let Some(name_node) = definition.name else {
+                continue;
+            };
+            let range = to_range(&name_node.range());
+
+            let token_type = match definition.kind {
+                DefinitionKind::Namespace => SemanticTokenType::NAMESPACE,
+                DefinitionKind::Class | DefinitionKind::Trait => SemanticTokenType::CLASS,
+                DefinitionKind::Interface => SemanticTokenType::INTERFACE,
+                DefinitionKind::Enum => SemanticTokenType::ENUM,
+                DefinitionKind::Function => SemanticTokenType::FUNCTION,
+                DefinitionKind::Method => SemanticTokenType::METHOD,
+                DefinitionKind::Property => SemanticTokenType::PROPERTY,
+                DefinitionKind::Constant => SemanticTokenType::VARIABLE,
+                DefinitionKind::EnumCase => SemanticTokenType::ENUM_MEMBER,
+                DefinitionKind::Parameter => SemanticTokenType::PARAMETER,
+            };
+            let mut modifiers = DECLARATION;
+            if definition.kind == DefinitionKind::Constant {
+                modifiers |= READONLY;
+            }
+            // promoted constructor parameters are declared as properties too
+            if let Some(declaration) = self.resolver.declaration_at(&range.start) {
+                modifiers |= declaration_modifiers(declaration);
+            }
+
+            self.add(range, token_type, modifiers);
+        }
+    }
+
+    fn member(&mut self, range: Range) {
+        let Some(node) = self
+            .root_node
+            .descendant_for_point_range(to_point(&range.start), to_point(&range.end))
+        else {
+            return;
+        };
+        let Some(parent) = node.parent() else {
+            return;
+        };
+
+        let (token_type, modifiers, object) = match parent.kind() {
+            "member_call_expression" | "nullsafe_member_call_expression" => (
+                SemanticTokenType::METHOD,
+                0,
+                parent.child_by_field_name("object"),
+            ),
+            "member_access_expression" | "nullsafe_member_access_expression" => (
+                SemanticTokenType::PROPERTY,
+                0,
+                parent.child_by_field_name("object"),
+            ),
+            "scoped_call_expression" => (
+                SemanticTokenType::METHOD,
+                STATIC,
+                parent.child_by_field_name("scope"),
+            ),
+            "scoped_property_access_expression" => (
+                SemanticTokenType::PROPERTY,
+                STATIC,
+                parent.child_by_field_name("scope"),
+            ),
+            "class_constant_access_expression" => (
+                SemanticTokenType::VARIABLE,
+                READONLY | STATIC,
+                parent.named_child(0),
+            ),
+            _ => return,
+        };
+
+        let name = self.text(&node);
+        let name = if token_type == SemanticTokenType::PROPERTY && !name.starts_with('$') {
+            format!("${}", name)
+        } else {
+            name.to_string()
+        };
+        let declaration = object
+            .and_then(|object| self.resolver.accessed_class(&object))
+            .and_then(|class| self.resolver.member(class, &name));
+
+        match declaration {
+            Some(declaration) => self.add(
+                range,
+                declaration_type(declaration.kind),
+                modifiers | declaration_modifiers(declaration),
+            ),
+            None => self.add(range, token_type, modifiers),
+        }
+    }
+
+    fn references(&mut self, symbols: &FileSymbols) {
+        for reference in &symbols.references {
+            if reference.kind == ReferenceKind::Member {
+                self.member(reference.range);
+                continue;
+            }
+
+            let declaration = self.resolver.resolve(reference);
+            let (token_type, mut modifiers) = match reference.kind {
+                ReferenceKind::Function => (SemanticTokenType::FUNCTION, 0),
+                ReferenceKind::Constant => (SemanticTokenType::VARIABLE, READONLY),
+                _ => (SemanticTokenType::CLASS, 0),
+            };
+            let token_type = match declaration {
+                Some(declaration) => {
+                    modifiers |= declaration_modifiers(declaration);
+                    declaration_type(declaration.kind)
+                }
+                None => token_type,
+            };
+
+            self.add(reference.range, token_type, modifiers);
+        }
+    }
+
+    /// Whether a variable is a parameter of the function it is used in.
+    fn is_parameter(&self, variable: &Node) -> bool {
+        let name = self.text(variable);
+        let mut current = variable.parent();
+        while let Some(parent) = current {
+            if FUNCTION_LIKES.contains(&parent.kind()) {
+                let Some(parameters) = parent.child_by_field_name("parameters") else {
+                    return false;
+                };
+
+                let mut cursor = parameters.walk();
+                let is_parameter = parameters.named_children(&mut cursor).any(|parameter| {
+                    parameter
+                        .child_by_field_name("name")
+                        .is_some_and(|parameter_name| self.text(&parameter_name) == name)
+                });
+                // only arrow functions can see the variables of the function they are in
+                if is_parameter || parent.kind() != "arrow_function" {
+                    return is_parameter;
+                }
+            }
+            current = parent.parent();
+        }
+
+        false
+    }
+
+    fn variables(&mut self, node: &Node) {
+        if node.kind() == "variable_name" {
+            let range = to_range(&node.range());
+            if self.text(node) != "$this" && !self.tokens.contains_key(&key(&range.start)) {
+                let token_type = match self.is_parameter(node) {
+                    true => SemanticTokenType::PARAMETER,
+                    false => SemanticTokenType::VARIABLE,
+                };
+                self.add(range, token_type, 0);
+            }
+            return;
+        }
+
+        let mut cursor = node.walk();
+        for child in node.children(&mut cursor) {
+            self.variables(&child);
+        }
+    }
+}
+
+fn classify(
+    root_node: &Node,
+    file_contents: &str,
+    symbols: &FileSymbols,
+    index: &WorkspaceIndex,
+) -> BTreeMap<(u32, u32), Token> {
+    let mut highlighter = Highlighter {
+        root_node: *root_node,
+        contents: file_contents,
+        resolver: Resolver::new(file_contents, symbols, index),
+        tokens: BTreeMap::new(),
+    };
+    highlighter.definitions();
+    highlighter.references(symbols);
+    highlighter.variables(root_node);
+
+    highlighter.tokens
+}
+
+/// Encode tokens relative to the ones before them, the way LSP wants them.
+fn encode(tokens: impl Iterator<Item = ((u32, u32), Token)>) -> Vec<SemanticToken> {
+    let mut data = vec![];
+    let (mut previous_line, mut previous_start) = (0, 0);
+    for ((line, start), token) in tokens {
+        let delta_line = line - previous_line;
+        data.push(SemanticToken {
+            delta_line,
+            delta_start: if delta_line == 0 {
+                start - previous_start
+            } else {
+                start
+            },
+            length: token.length,
+            token_type: TOKEN_TYPES
+                .iter()
+                .position(|token_type| *token_type == token.token_type)
+                .unwrap_or_default() as u32,
+            token_modifiers_bitset: token.modifiers,
+        });
+        previous_line = line;
+        previous_start = start;
+    }
+
+    data
+}
+
+/**
+ * Classify the names in a file so that editors can highlight them.
+ *
+ * References are looked up in the index to tell classes from interfaces and enums, and to find
+ * out whether what they refer to is static, readonly or deprecated.
+ */
+pub fn semantic_tokens(
+    root_node: &Node,
+    file_contents: &str,
+    symbols: &FileSymbols,
+    index: &WorkspaceIndex,
+) -> Vec<SemanticToken> {
+    encode(classify(root_node, file_contents, symbols, index).into_iter())
+}
+
+/// Like [`semantic_tokens`], but only for the tokens that start in a range.
+pub fn semantic_tokens_range(
+    root_node: &Node,
+    file_contents: &str,
+    symbols: &FileSymbols,
+    index: &WorkspaceIndex,
+    range: &Range,
+) -> Vec<SemanticToken> {
+    let tokens = classify(root_node, file_contents, symbols, index);
+    encode(
+        tokens
+            .into_iter()
+            .filter(|(start, _)| *start >= key(&range.start) && *start < key(&range.end)),
+    )
+}
+
+/**
+ * The smallest edit that turns one set of tokens into another.
+ *
+ * Edits index into the flattened array of integers that tokens are sent as, five per token.
+ * Typing usually only changes a few tokens in the middle, so we keep what the old and new sets
+ * start and end with in common and replace the rest.
+ */
+pub fn edits(old: &[SemanticToken], new: &[SemanticToken]) -> Vec<SemanticTokensEdit> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let deleted = old.len() - prefix - suffix;
+    let inserted = &new[prefix..new.len() - suffix];
+    if deleted == 0 && inserted.is_empty() {
+        return vec![];
+    }
+
+    vec![SemanticTokensEdit {
+        start: 5 * prefix as u32,
+        delete_count: 5 * deleted as u32,
+        data: Some(inserted.to_vec()),
+    }]
+}
+
+/**
+ * The last tokens we sent for each file, so that the next request can be answered with a delta.
+ *
+ * Clients only ever ask for a delta against the last result they got, so older results are
+ * dropped.
+ */
+#[derive(Default)]
+pub struct TokenCache {
+    next_id: AtomicU64,
+    results: DashMap<Url, (String, Vec<SemanticToken>)>,
+}
+
+impl TokenCache {
+    /// Remember the tokens for a file, returning the result ID to send with them.
+    pub fn store(&self, uri: Url, data: Vec<SemanticToken>) -> String {
+        let result_id = self.next_id.fetch_add(1, Ordering::Relaxed).to_string();
+        self.results.insert(uri, (result_id.clone(), data));
+        result_id
+    }
+
+    /// The tokens we sent for a file with a result ID, if they were the last ones we sent.
+    pub fn previous(&self, uri: &Url, result_id: &str) -> Option<Vec<SemanticToken>> {
+        self.results
+            .get(uri)
+            .filter(|result| result.0 == result_id)
+            .map(|result| result.1.clone())
+    }
+
+    /// Drop the tokens of a file that was closed.
+    pub fn forget(&self, uri: &Url) {
+        self.results.remove(uri);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::*;
+
+    use super::{
+        edits, semantic_tokens, semantic_tokens_range, DECLARATION, DEPRECATED, READONLY, STATIC,
+        TOKEN_TYPES,
+    };
+    use crate::file::php_parser;
+    use crate::index::WorkspaceIndex;
+    use crate::symbols::file_symbols;
+
+    /// Decode tokens back into their text, type and modifiers.
+    fn decode(source: &str, tokens: &[SemanticToken]) -> Vec<(String, SemanticTokenType, u32)> {
+        let lines: Vec<&str> = source.lines().collect();
+        let (mut line, mut start) = (0, 0);
+        tokens
+            .iter()
+            .map(|token| {
+                if token.delta_line > 0 {
+                    start = 0;
+                }
+                line += token.delta_line;
+                start += token.delta_start;
+                let text = &lines[line as usize][start as usize..(start + token.length) as usize];
+                (
+                    text.to_string(),
+                    TOKEN_TYPES[token.token_type as usize].clone(),
+                    token.token_modifiers_bitset,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_classify() {
+        let source = "<?php
+interface Shape {}
+class Square implements Shape {
+    const SIDES = 4;
+    public readonly int $size;
+    /** @deprecated */
+    public static function make(int $size) {
+        $square = new Square();
+        $f = fn () => $size;
+        return self::SIDES;
+    }
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(
+            &Url::parse("file:///project/Square.php").unwrap(),
+            symbols.clone(),
+        );
+
+        let tokens = semantic_tokens(&tree.root_node(), source, &symbols, &index);
+        let token = |text: &str| -> Vec<(SemanticTokenType, u32)> {
+            decode(source, &tokens)
+                .into_iter()
+                .filter(|(token_text, _, _)| token_text == text)
+                .map(|(_, token_type, modifiers)| (token_type, modifiers))
+                .collect()
+        };
+
+        assert_eq!(
+            vec![
+                (SemanticTokenType::INTERFACE, DECLARATION),
+                (SemanticTokenType::INTERFACE, 0)
+            ],
+            token("Shape")
+        );
+        assert_eq!(
+            vec![
+                (SemanticTokenType::CLASS, DECLARATION),
+                (SemanticTokenType::CLASS, 0)
+            ],
+            token("Square")
+        );
+        assert_eq!(
+            vec![
+                (SemanticTokenType::VARIABLE, DECLARATION | READONLY | STATIC),
+                (SemanticTokenType::VARIABLE, READONLY | STATIC),
+            ],
+            token("SIDES")
+        );
+        assert_eq!(
+            vec![
+                (SemanticTokenType::PROPERTY, DECLARATION | READONLY),
+                (SemanticTokenType::PARAMETER, DECLARATION),
+                (SemanticTokenType::PARAMETER, 0),
+            ],
+            token("$size")
+        );
+        assert_eq!(
+            vec![(SemanticTokenType::METHOD, DECLARATION | STATIC | DEPRECATED)],
+            token("make")
+        );
+        assert_eq!(vec![(SemanticTokenType::VARIABLE, 0)], token("$square"));
+    }
+
+    #[test]
+    fn test_range() {
+        let source = "<?php
+function a($x) {}
+function b($y) {}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let index = WorkspaceIndex::default();
+        let range = Range {
+            start: Position {
+                line: 2,
+                character: 0,
+            },
+            end: Position {
+                line: 3,
+                character: 0,
+            },
+        };
+
+        let tokens = semantic_tokens_range(&tree.root_node(), source, &symbols, &index, &range);
+        let texts: Vec<String> = decode(source, &tokens)
+            .into_iter()
+            .map(|(text, _, _)| text)
+            .collect();
+        assert_eq!(vec!["b", "$y"], texts);
+    }
+
+    #[test]
+    fn test_edits() {
+        let token = |delta_start| SemanticToken {
+            delta_line: 0,
+            delta_start,
+            length: 1,
+            token_type: 0,
+            token_modifiers_bitset: 0,
+        };
+        let old = vec![token(1), token(2), token(3), token(4)];
+        let new = vec![token(1), token(5), token(6), token(4)];
+
+        let edits = edits(&old, &new);
+        assert_eq!(1, edits.len());
+        assert_eq!(5, edits[0].start);
+        assert_eq!(10, edits[0].delete_count);
+        assert_eq!(Some(vec![token(5), token(6)]), edits[0].data);
+        assert!(super::edits(&old, &old).is_empty());
+    }
+}
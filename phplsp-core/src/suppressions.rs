@@ -0,0 +1,135 @@
+//! Comments that say not to report some diagnostics on a line, for false positives that aren't
+//! worth turning a rule off everywhere for.
+
+use lsp_types::*;
+use tree_sitter::Node;
+
+/// Suppresses the diagnostics on the line after the comment, all of them unless codes follow.
+const NEXT_LINE: &str = "@phplsp-ignore-next-line";
+/// Suppresses the diagnostics with the codes that follow, on the line the comment ends, or on
+/// the next one if the comment is on a line of its own.
+const IGNORE: &str = "@phplsp-ignore";
+
+/// Diagnostics that a comment suppresses: on a line, those with some codes, or all of them.
+struct Suppression {
+    line: u32,
+    codes: Option<Vec<String>>,
+}
+
+impl Suppression {
+    fn suppresses(&self, diagnostic: &Diagnostic) -> bool {
+        if diagnostic.range.start.line != self.line {
+            return false;
+        }
+        match (&self.codes, &diagnostic.code) {
+            (None, _) => true,
+            (Some(codes), Some(NumberOrString::String(code))) => codes.contains(code),
+            (Some(_), _) => false,
+        }
+    }
+}
+
+/// The codes after a tag, separated by commas or spaces, up to anything that isn't one.
+fn codes(after: &str) -> Vec<String> {
+    after
+        .split([',', ' ', '\t'])
+        .filter(|word| !word.is_empty())
+        .take_while(|word| {
+            word.chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        })
+        .map(str::to_string)
+        .collect()
+}
+
+fn suppression(comment: &Node, file_contents: &str) -> Option<Suppression> {
+    let text = &file_contents[comment.byte_range()];
+    let next_line = comment.end_position().row as u32 + 1;
+    if let Some((_, after)) = text.split_once(NEXT_LINE) {
+        let codes = codes(after);
+        return Some(Suppression {
+            line: next_line,
+            codes: (!codes.is_empty()).then_some(codes),
+        });
+    }
+
+    let (_, after) = text.split_once(IGNORE)?;
+    let codes = codes(after);
+    if codes.is_empty() {
+        return None;
+    }
+    // a comment after some code is about that code
+    let start = comment.start_position();
+    let line_start = comment.start_byte() - start.column;
+    let trailing = !file_contents[line_start..comment.start_byte()]
+        .trim()
+        .is_empty();
+    Some(Suppression {
+        line: match trailing {
+            true => comment.end_position().row as u32,
+            false => next_line,
+        },
+        codes: Some(codes),
+    })
+}
+
+fn suppressions(node: &Node, file_contents: &str, found: &mut Vec<Suppression>) {
+    if node.kind() == "comment" {
+        found.extend(suppression(node, file_contents));
+        return;
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        suppressions(&child, file_contents, found);
+    }
+}
+
+/**
+ * Drop the diagnostics that comments in a file say not to report: every one on the line after
+ * `// @phplsp-ignore-next-line`, or only those with the codes after it, and those with the codes
+ * after `// @phplsp-ignore`, like `// @phplsp-ignore dead-store, unused-variable`, on the line the
+ * comment is at the end of, or on the next one if it's on a line of its own.
+ */
+pub fn drop_suppressed(root_node: &Node, file_contents: &str, diagnostics: &mut Vec<Diagnostic>) {
+    let mut found = vec![];
+    suppressions(root_node, file_contents, &mut found);
+    diagnostics.retain(|diagnostic| {
+        !found
+            .iter()
+            .any(|suppression| suppression.suppresses(diagnostic))
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::drop_suppressed;
+    use crate::config::Config;
+    use crate::diagnostics::diagnostics;
+    use crate::file::php_parser;
+
+    #[test]
+    fn test_drop_suppressed() {
+        let source = "<?php
+function f() {
+    // @phplsp-ignore-next-line
+    $a = 1;
+    $b = 2; // @phplsp-ignore dead-store, unused-variable
+    /* @phplsp-ignore unused-variable because it's read by eval */
+    $c = 3;
+    # @phplsp-ignore-next-line naming
+    $d = 4;
+    $e = 5; // @phplsp-ignore
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let mut found = diagnostics(&tree.root_node(), source, &Config::default());
+        drop_suppressed(&tree.root_node(), source, &mut found);
+
+        let lines: Vec<u32> = found
+            .iter()
+            .map(|diagnostic| diagnostic.range.start.line)
+            .collect();
+        assert_eq!(vec![8, 9], lines);
+    }
+}
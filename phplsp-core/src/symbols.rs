@@ -0,0 +1,1312 @@
+use lsp_types::{Position, Range, SymbolKind};
+use tree_sitter::Node;
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::{HashMap, HashSet};
+
+use crate::attributes::{
+    array_shape, attribute_arguments, deprecation, expected_values, strip_attributes,
+};
+use crate::file::to_range;
+use crate::missing_return::is_generator;
+use crate::names::{ImportKind, NameContext};
+use crate::tags::{
+    definitions, doc_comments, doc_deprecation, doc_names, doc_see_classes, inherits_doc,
+    references, Definition, DefinitionKind, ReferenceTag,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DeclarationKind {
+    Class,
+    Interface,
+    Trait,
+    Enum,
+    Function,
+    Method,
+    Property,
+    Constant,
+    EnumCase,
+}
+
+/// Something declared in a file that other files can refer to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Declaration {
+    /// Fully qualified name. Members are written as `Class::method`, `Class::$property` and
+    /// `Class::CONSTANT`.
+    pub name: String,
+    pub kind: DeclarationKind,
+    /// Range of the name being declared.
+    pub range: Range,
+    /// The declaration without its body or attributes, with whitespace normalized. Other files
+    /// can only depend on this part, so edits that keep the signature intact cannot break them.
+    pub signature: String,
+    /// The attributes of the declaration as they are written, like `ArrayShape(['id' => 'int'])`.
+    pub attributes: Vec<String>,
+    /// Marked with `@deprecated` in its doc comment or with a `#[Deprecated]` attribute.
+    pub deprecated: bool,
+    /// What the deprecation says about why, or what to use instead.
+    #[serde(default)]
+    pub deprecation: Option<String>,
+    /// Fully qualified names of what a class-like extends, implements and uses, in that order.
+    pub supertypes: Vec<String>,
+    /// A class-like with `@method`, `@property` or `@mixin` in its doc comment, so it has members
+    /// that it doesn't declare.
+    pub magic_members: bool,
+    #[serde(default)]
+    pub documentation: Documentation,
+    /// Declared in an `if`, a function or the like, like a polyfill that's only declared if it
+    /// doesn't exist yet, so it may be declared somewhere else too.
+    #[serde(default)]
+    pub conditional: bool,
+    /// A function or method whose body yields, so calling it returns a `Generator`.
+    #[serde(default)]
+    pub generator: bool,
+    /// For traits, the fully qualified names of the class-likes in the `@see` tags of their doc
+    /// comment, like the interface that the trait is the default implementation of.
+    #[serde(default)]
+    pub see: Vec<String>,
+}
+
+/// Whether a declaration has a doc comment, and whether that says to inherit its parent's.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Documentation {
+    #[default]
+    None,
+    Own,
+    /// `@inheritDoc`, or `{@inheritDoc}` in a comment of its own.
+    Inherited,
+}
+
+impl Documentation {
+    pub fn of(comment: &str) -> Self {
+        match inherits_doc(comment) {
+            true => Self::Inherited,
+            false => Self::Own,
+        }
+    }
+}
+
+impl Declaration {
+    /// The class that declares this member, if this is a member.
+    pub fn class_name(&self) -> Option<&str> {
+        self.name.split_once("::").map(|(class, _)| class)
+    }
+
+    /// The name without its namespace or class.
+    pub fn short_name(&self) -> &str {
+        match self.name.rsplit_once("::") {
+            Some((_, member)) => member,
+            None => self.name.rsplit('\\').next().unwrap_or(&self.name),
+        }
+    }
+
+    /// The namespace or class this is declared in.
+    pub fn container_name(&self) -> Option<&str> {
+        match self.name.rsplit_once("::") {
+            Some((class, _)) => Some(class),
+            None => self.name.rsplit_once('\\').map(|(namespace, _)| namespace),
+        }
+    }
+
+    /// Whether a modifier like `static` or `readonly` is part of the signature.
+    pub fn has_modifier(&self, modifier: &str) -> bool {
+        let before_name = match self.signature.find('$') {
+            Some(end) => &self.signature[..end],
+            None => &self.signature,
+        };
+        before_name.split([' ', '(']).any(|word| word == modifier)
+    }
+
+    /**
+     * The parameters in the signature of a function or method as they are written, and where the
+     * parameter list ends.
+     *
+     * Default values may contain commas and parentheses of their own, so only commas outside of
+     * brackets and strings separate parameters.
+     */
+    fn parameter_list(&self) -> (Vec<&str>, Option<usize>) {
+        let Some(start) = self.signature.find('(') else {
+            return (vec![], None);
+        };
+
+        let mut parameters = vec![];
+        let mut depth = 0;
+        let mut quote = None;
+        let mut escaped = false;
+        let mut parameter_start = start + 1;
+        for (i, c) in self
+            .signature
+            .char_indices()
+            .skip_while(|(i, _)| *i <= start)
+        {
+            if escaped {
+                escaped = false;
+                continue;
+            }
+
+            match (quote, c) {
+                (Some(_), '\\') => escaped = true,
+                (Some(q), c) if c == q => quote = None,
+                (Some(_), _) => {}
+                (None, '\'' | '"') => quote = Some(c),
+                (None, '(' | '[') => depth += 1,
+                (None, ')') if depth == 0 => {
+                    parameters.push(&self.signature[parameter_start..i]);
+                    return (parameters, Some(i));
+                }
+                (None, ')' | ']') => depth -= 1,
+                (None, ',') if depth == 0 => {
+                    parameters.push(&self.signature[parameter_start..i]);
+                    parameter_start = i + 1;
+                }
+                _ => {}
+            }
+        }
+
+        (parameters, None)
+    }
+
+    /// Names of the parameters of a function or method, with their `$`. Variadic parameters keep
+    /// their `...`.
+    pub fn parameters(&self) -> Vec<&str> {
+        self.parameter_list()
+            .0
+            .into_iter()
+            .filter_map(|parameter| {
+                let (_, parameter) = strip_attributes(parameter);
+                let declared = parameter.split('=').next().unwrap_or_default();
+                let name_start = declared.find("...$").or_else(|| declared.find('$'))?;
+                Some(declared[name_start..].trim())
+            })
+            .collect()
+    }
+
+    /**
+     * How many arguments a call of a function or method has to pass, and how many it can pass,
+     * which is any number if it's variadic. Parameters that the stubs mark as only in some
+     * versions of PHP with `#[PhpStormStubsElementAvailable]` are taken to be optional.
+     */
+    pub fn arity(&self) -> (usize, Option<usize>) {
+        let mut required = 0;
+        let mut count = 0;
+        for parameter in self.parameter_list().0 {
+            let (attributes, parameter) = strip_attributes(parameter);
+            let declared = parameter.split('=').next().unwrap_or_default();
+            if !declared.contains('$') {
+                continue;
+            }
+            if declared.contains("...$") {
+                return (required, None);
+            }
+            count += 1;
+            let optional = parameter.contains('=')
+                || attribute_arguments(attributes, "PhpStormStubsElementAvailable").is_some();
+            if !optional {
+                required = count;
+            }
+        }
+
+        (required, Some(count))
+    }
+
+    /// The type of a parameter of a function or method as it is written, if it has one.
+    pub fn parameter_type(&self, parameter: usize) -> Option<&str> {
+        let (_, parameter) = strip_attributes(self.parameter_list().0.get(parameter)?);
+        let declared = parameter.split('=').next().unwrap_or_default();
+        declared[..declared.find('$')?]
+            .trim_end_matches(['.', '&', ' '])
+            .split_whitespace()
+            .next_back()
+            .filter(|word| !PROPERTY_MODIFIERS.contains(&word.to_lowercase().as_str()))
+    }
+
+    /// The default value of a parameter of a function or method as it is written, if it has one.
+    pub fn parameter_default(&self, parameter: usize) -> Option<&str> {
+        let (_, parameter) = strip_attributes(self.parameter_list().0.get(parameter)?);
+        parameter.split_once('=').map(|(_, default)| default.trim())
+    }
+
+    /// Whether a parameter of a function or method is passed by reference, like `&$matches`.
+    pub fn is_by_reference(&self, parameter: usize) -> bool {
+        self.parameter_list()
+            .0
+            .get(parameter)
+            .is_some_and(|parameter| {
+                let (_, parameter) = strip_attributes(parameter);
+                let declared = parameter.split('=').next().unwrap_or_default();
+                declared.contains("&$") || declared.contains("& $") || declared.contains("&...$")
+            })
+    }
+
+    /// Whether this is marked `#[Pure]`, meaning calling it does nothing but return a value.
+    pub fn is_pure(&self) -> bool {
+        attribute_arguments(self.attributes.iter().map(String::as_str), "Pure").is_some()
+    }
+
+    /// The keys and value types of the array this returns or holds, from `#[ArrayShape]`.
+    pub fn array_shape(&self) -> Option<Vec<(String, String)>> {
+        array_shape(attribute_arguments(
+            self.attributes.iter().map(String::as_str),
+            "ArrayShape",
+        )?)
+    }
+
+    /// The values a parameter expects as they are written, from its `#[ExpectedValues]`.
+    pub fn expected_values(&self, parameter: usize) -> Option<Vec<String>> {
+        let (attributes, _) = strip_attributes(self.parameter_list().0.get(parameter)?);
+        expected_values(attribute_arguments(attributes, "ExpectedValues")?)
+    }
+
+    /// The return type of a function or method as it is written, if it has one.
+    pub fn return_type(&self) -> Option<&str> {
+        let end = self.parameter_list().1?;
+        let return_type = self.signature[end + 1..]
+            .trim_start()
+            .strip_prefix(':')?
+            .trim();
+        Some(return_type).filter(|return_type| !return_type.is_empty())
+    }
+
+    /// The namespace this is declared in, empty for the global namespace.
+    pub fn namespace(&self) -> &str {
+        let name = match self.name.split_once("::") {
+            Some((class, _)) => class,
+            None => &self.name,
+        };
+        name.rsplit_once('\\')
+            .map_or("", |(namespace, _)| namespace)
+    }
+
+    /// The name of this member without any `$` sigil, if this is a member.
+    pub fn member_name(&self) -> Option<&str> {
+        self.name
+            .split_once("::")
+            .map(|(_, member)| member.trim_start_matches('$'))
+    }
+
+    /// The type of a property as it is written, if it has one.
+    pub fn property_type(&self) -> Option<&str> {
+        let before_name = &self.signature[..self.signature.find('$')?];
+        before_name
+            .split_whitespace()
+            .next_back()
+            .filter(|word| !PROPERTY_MODIFIERS.contains(&word.to_lowercase().as_str()))
+    }
+
+    /// The type of a class constant as it is written, like `string` in `const string NAME = 'a'`.
+    pub fn constant_type(&self) -> Option<&str> {
+        if self.kind != DeclarationKind::Constant {
+            return None;
+        }
+        let declared = self.signature.split('=').next().unwrap_or_default();
+        let mut words = declared
+            .split_whitespace()
+            .skip_while(|word| !word.eq_ignore_ascii_case("const"))
+            .skip(1);
+        words.next().filter(|word| *word != self.short_name())
+    }
+
+    /// The type of the values of a backed enum, like `string` in `enum Suit: string`.
+    pub fn backing_type(&self) -> Option<&str> {
+        if self.kind != DeclarationKind::Enum {
+            return None;
+        }
+        let after_name = self
+            .signature
+            .split_once(&format!("enum {}", self.short_name()))?
+            .1;
+        after_name
+            .trim_start()
+            .strip_prefix(':')?
+            .split_whitespace()
+            .next()
+    }
+
+    /**
+     * The members that every enum has without declaring them: `cases()` and `$name`, and for
+     * backed enums also `from()`, `tryFrom()` and `$value`.
+     *
+     * They are placed at the name of the enum, and their signatures are what PHP would declare,
+     * except that `cases()` says what its array holds.
+     */
+    pub fn enum_members(&self) -> Vec<Declaration> {
+        if self.kind != DeclarationKind::Enum {
+            return vec![];
+        }
+
+        let mut members = vec![
+            (
+                "cases",
+                "public static function cases(): static[]".to_string(),
+            ),
+            ("$name", "public readonly string $name;".to_string()),
+        ];
+        if let Some(backing_type) = self.backing_type() {
+            members.extend([
+                (
+                    "from",
+                    format!(
+                        "public static function from({} $value): static",
+                        backing_type
+                    ),
+                ),
+                (
+                    "tryFrom",
+                    format!(
+                        "public static function tryFrom({} $value): ?static",
+                        backing_type
+                    ),
+                ),
+                (
+                    "$value",
+                    format!("public readonly {} $value;", backing_type),
+                ),
+            ]);
+        }
+
+        members
+            .into_iter()
+            .map(|(member, signature)| Declaration {
+                name: format!("{}::{}", self.name, member),
+                kind: match member.starts_with('$') {
+                    true => DeclarationKind::Property,
+                    false => DeclarationKind::Method,
+                },
+                range: self.range,
+                signature,
+                attributes: vec![],
+                deprecated: false,
+                deprecation: None,
+                supertypes: vec![],
+                magic_members: false,
+                documentation: Documentation::None,
+                conditional: self.conditional,
+                generator: false,
+                see: vec![],
+            })
+            .collect()
+    }
+}
+
+/// Statements and functions whose declarations may not be declared at all.
+const CONDITIONAL: &[&str] = &[
+    "if_statement",
+    "switch_statement",
+    "try_statement",
+    "while_statement",
+    "function_definition",
+    "method_declaration",
+    "anonymous_function",
+];
+
+fn is_conditional(node: &Node) -> bool {
+    // promoted properties are declared by the class, not the constructor they're written in
+    let mut current = match node.kind() {
+        "property_promotion_parameter" => node
+            .parent()
+            .and_then(|parameters| parameters.parent())
+            .and_then(|constructor| constructor.parent()),
+        _ => node.parent(),
+    };
+    while let Some(parent) = current {
+        if CONDITIONAL.contains(&parent.kind()) {
+            return true;
+        }
+        current = parent.parent();
+    }
+    false
+}
+
+/// Words that can come before the name of a property instead of its type.
+const PROPERTY_MODIFIERS: &[&str] = &[
+    "public",
+    "protected",
+    "private",
+    "static",
+    "readonly",
+    "var",
+    "final",
+    "abstract",
+];
+
+impl From<DeclarationKind> for SymbolKind {
+    fn from(kind: DeclarationKind) -> Self {
+        match kind {
+            DeclarationKind::Class => SymbolKind::CLASS,
+            DeclarationKind::Interface => SymbolKind::INTERFACE,
+            // LSP has no kind for traits
+            DeclarationKind::Trait => SymbolKind::CLASS,
+            DeclarationKind::Enum => SymbolKind::ENUM,
+            DeclarationKind::Function => SymbolKind::FUNCTION,
+            DeclarationKind::Method => SymbolKind::METHOD,
+            DeclarationKind::Property => SymbolKind::PROPERTY,
+            DeclarationKind::Constant => SymbolKind::CONSTANT,
+            DeclarationKind::EnumCase => SymbolKind::ENUM_MEMBER,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ReferenceKind {
+    Class,
+    Function,
+    Constant,
+    Member,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Reference {
+    /// Fully qualified for classes, functions and constants. Members only have their own name
+    /// (without `$`), since we generally don't know the class they are accessed on.
+    pub name: String,
+    /// Unqualified functions and constants fall back to the global namespace when the namespaced
+    /// name doesn't exist.
+    pub fallback: Option<String>,
+    pub kind: ReferenceKind,
+    pub range: Range,
+}
+
+/// A summary of what a file declares and what it uses.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileSymbols {
+    pub declarations: Vec<Declaration>,
+    pub references: Vec<Reference>,
+}
+
+impl FileSymbols {
+    /// Estimated bytes used by this summary.
+    pub fn memory_usage(&self) -> usize {
+        let declarations: usize = self
+            .declarations
+            .iter()
+            .map(|declaration| {
+                size_of::<Declaration>()
+                    + declaration.name.len()
+                    + declaration.signature.len()
+                    + declaration
+                        .attributes
+                        .iter()
+                        .chain(&declaration.supertypes)
+                        .chain(&declaration.see)
+                        .map(|text| size_of::<String>() + text.len())
+                        .sum::<usize>()
+            })
+            .sum();
+        let references: usize = self
+            .references
+            .iter()
+            .map(|reference| {
+                size_of::<Reference>()
+                    + reference.name.len()
+                    + reference
+                        .fallback
+                        .as_ref()
+                        .map_or(0, |fallback| fallback.len())
+            })
+            .sum();
+
+        declarations + references
+    }
+}
+
+fn normalize_whitespace(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Node kinds that methods, properties and class constants can belong to.
+const CLASS_LIKES: &[&str] = &[
+    "class_declaration",
+    "interface_declaration",
+    "trait_declaration",
+    "enum_declaration",
+    "anonymous_class",
+];
+
+fn enclosing_class<'tree>(node: &Node<'tree>) -> Option<Node<'tree>> {
+    let mut current = node.parent();
+    while let Some(parent) = current {
+        if CLASS_LIKES.contains(&parent.kind()) {
+            return Some(parent);
+        }
+        current = parent.parent();
+    }
+
+    None
+}
+
+/// Doc comments and attributes go on the whole `const A = 1, B = 2;`, not on each constant.
+fn attributed_node<'tree>(node: &Node<'tree>) -> Node<'tree> {
+    match node.kind() {
+        "const_element" => node.parent().unwrap_or(*node),
+        _ => *node,
+    }
+}
+
+/// Whether a declaration has an attribute like `#[Deprecated]` or `#[\JetBrains\PhpStorm\Deprecated]`.
+fn has_deprecated_attribute(contents: &str, declaration: &Node) -> bool {
+    let Some(attributes) = declaration.child_by_field_name("attributes") else {
+        return false;
+    };
+
+    let mut groups_cursor = attributes.walk();
+    for group in attributes.named_children(&mut groups_cursor) {
+        let mut cursor = group.walk();
+        for attribute in group.named_children(&mut cursor) {
+            let name = attribute
+                .named_child(0)
+                .map(|name| &contents[name.byte_range()])
+                .unwrap_or("");
+            if name.rsplit('\\').next() == Some("Deprecated") {
+                return true;
+            }
+        }
+    }
+
+    false
+}
+
+struct SymbolWalker<'a> {
+    contents: &'a str,
+    /// Declarations with `@deprecated` in their doc comments.
+    deprecated_docs: HashSet<usize>,
+    /// What the `@deprecated` tags of doc comments say, by the declaration's id.
+    deprecations: HashMap<usize, String>,
+    /// Declarations whose doc comments have tags of members they don't declare.
+    magic_docs: HashSet<usize>,
+    /// What each doc comment says about the declaration it documents, by the declaration's id.
+    documentation: HashMap<usize, Documentation>,
+    /// The class-likes named by the `@see` tags of doc comments, by the declaration's id.
+    see: HashMap<usize, Vec<&'a str>>,
+    names: NameContext,
+    /// The braced namespaces we are in, with where they end and the names in effect outside.
+    namespaces: Vec<(usize, NameContext)>,
+    symbols: FileSymbols,
+}
+
+impl<'a> SymbolWalker<'a> {
+    fn text(&self, node: &Node) -> &'a str {
+        &self.contents[node.byte_range()]
+    }
+
+    fn is_deprecated(&self, node: &Node) -> bool {
+        let declaration = attributed_node(node);
+        self.deprecated_docs.contains(&declaration.id())
+            || has_deprecated_attribute(self.contents, &declaration)
+    }
+
+    fn attributes(&self, node: &Node) -> Vec<String> {
+        let Some(attributes) = attributed_node(node).child_by_field_name("attributes") else {
+            return vec![];
+        };
+
+        let mut groups_cursor = attributes.walk();
+        let mut found = vec![];
+        for group in attributes.named_children(&mut groups_cursor) {
+            let mut cursor = group.walk();
+            found.extend(
+                group
+                    .named_children(&mut cursor)
+                    .map(|attribute| normalize_whitespace(self.text(&attribute))),
+            );
+        }
+        found
+    }
+
+    fn declare(&mut self, name: String, kind: DeclarationKind, name_node: &Node, node: &Node) {
+        // attributes come first, but they aren't what other files depend on
+        let start = match node.child_by_field_name("attributes") {
+            Some(attributes) => attributes.end_byte(),
+            None => node.start_byte(),
+        };
+        // the hooks of a property are its body
+        let body = node.child_by_field_name("body").or_else(|| {
+            let mut cursor = node.walk();
+            let hooks = node
+                .named_children(&mut cursor)
+                .find(|child| child.kind() == "property_hook_list");
+            hooks
+        });
+        let signature = match body {
+            Some(body) => &self.contents[start..body.start_byte()],
+            None => &self.contents[start..node.end_byte()],
+        };
+        let attributes = self.attributes(node);
+        let deprecated = self.is_deprecated(node);
+        let deprecation = self
+            .deprecations
+            .get(&attributed_node(node).id())
+            .cloned()
+            .or_else(|| {
+                attribute_arguments(attributes.iter().map(String::as_str), "Deprecated")
+                    .and_then(deprecation)
+            });
+        let supertypes = self.supertypes(node);
+        let magic_members = self.magic_docs.contains(&attributed_node(node).id());
+        let documentation = self
+            .documentation
+            .get(&attributed_node(node).id())
+            .copied()
+            .unwrap_or_default();
+        let generator = matches!(kind, DeclarationKind::Function | DeclarationKind::Method)
+            && body.is_some_and(|body| is_generator(&body));
+        let see = match kind {
+            DeclarationKind::Trait => self
+                .see
+                .get(&attributed_node(node).id())
+                .into_iter()
+                .flatten()
+                .map(|name| self.names.resolve_class(name))
+                .collect(),
+            _ => vec![],
+        };
+
+        self.symbols.declarations.push(Declaration {
+            name,
+            kind,
+            range: to_range(&name_node.range()),
+            signature: normalize_whitespace(signature),
+            attributes,
+            deprecated,
+            deprecation,
+            supertypes,
+            magic_members,
+            documentation,
+            conditional: is_conditional(node),
+            generator,
+            see,
+        });
+    }
+
+    /// The names in the `extends` and `implements` clauses of a class-like, and the traits it uses.
+    fn supertypes(&self, node: &Node) -> Vec<String> {
+        let mut clauses = vec![];
+        let mut cursor = node.walk();
+        for child in node.children(&mut cursor) {
+            match child.kind() {
+                "base_clause" | "class_interface_clause" => clauses.push(child),
+                "declaration_list" | "enum_declaration_list" => {
+                    let mut body_cursor = child.walk();
+                    clauses.extend(
+                        child
+                            .named_children(&mut body_cursor)
+                            .filter(|member| member.kind() == "use_declaration"),
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        let mut supertypes = vec![];
+        for clause in clauses {
+            let mut cursor = clause.walk();
+            supertypes.extend(
+                clause
+                    .named_children(&mut cursor)
+                    .filter(|name| matches!(name.kind(), "name" | "qualified_name"))
+                    .map(|name| self.names.resolve_class(self.text(&name))),
+            );
+        }
+        supertypes
+    }
+
+    fn reference(
+        &mut self,
+        name: String,
+        fallback: Option<String>,
+        kind: ReferenceKind,
+        node: &Node,
+    ) {
+        self.symbols.references.push(Reference {
+            name,
+            fallback,
+            kind,
+            range: to_range(&node.range()),
+        });
+    }
+
+    /// References to what the tags of a doc comment name.
+    fn doc_references(&mut self, comment: &Node) {
+        let text = self.text(comment);
+        let start = comment.start_position();
+        // where a byte of the comment is in the file
+        let position = |offset: usize| match text[..offset].rfind('\n') {
+            Some(newline) => Position {
+                line: (start.row + text[..offset].matches('\n').count()) as u32,
+                character: (offset - newline - 1) as u32,
+            },
+            None => Position {
+                line: start.row as u32,
+                character: (start.column + offset) as u32,
+            },
+        };
+        let range = |from: usize, length: usize| Range {
+            start: position(from),
+            end: position(from + length),
+        };
+
+        for (written_range, written) in doc_names(text) {
+            let from = written_range.start;
+            let is_call = written.ends_with("()");
+            let written = written.trim_end_matches("()");
+            let reference = match written.split_once("::") {
+                Some((class, member)) => {
+                    if !matches!(class.to_lowercase().as_str(), "self" | "static" | "parent") {
+                        self.symbols.references.push(Reference {
+                            name: self.names.resolve_class(class),
+                            fallback: None,
+                            kind: ReferenceKind::Class,
+                            range: range(from, class.len()),
+                        });
+                    }
+                    Reference {
+                        name: member.trim_start_matches('$').to_string(),
+                        fallback: None,
+                        kind: ReferenceKind::Member,
+                        range: range(from + class.len() + 2, member.len()),
+                    }
+                }
+                None if is_call => {
+                    let (name, fallback) = self
+                        .names
+                        .resolve_function_or_constant(ImportKind::Function, written);
+                    Reference {
+                        name,
+                        fallback,
+                        kind: ReferenceKind::Function,
+                        range: range(from, written.len()),
+                    }
+                }
+                None => Reference {
+                    name: self.names.resolve_class(written),
+                    fallback: None,
+                    kind: ReferenceKind::Class,
+                    range: range(from, written.len()),
+                },
+            };
+            self.symbols.references.push(reference);
+        }
+    }
+
+    /// Restore the names from outside of any braced namespaces that end before `byte`.
+    fn leave_namespaces(&mut self, byte: usize) {
+        while self.namespaces.last().is_some_and(|(end, _)| *end <= byte) {
+            if let Some((_, outer)) = self.namespaces.pop() {
+                self.names = outer;
+            }
+        }
+    }
+
+    /**
+     * The fully qualified name of the class-like that a member belongs to.
+     *
+     * Members of anonymous classes can't be referred to by name, so they have none.
+     */
+    fn class_name(&self, class: &Node) -> Option<String> {
+        let name_node = class.child_by_field_name("name")?;
+        Some(self.names.qualify(self.text(&name_node)))
+    }
+
+    fn declare_member(&mut self, kind: DeclarationKind, name_node: &Node, node: &Node) {
+        let Some(class) = enclosing_class(node).and_then(|class| self.class_name(&class)) else {
+            return;
+        };
+
+        let name = format!("{}::{}", class, self.text(name_node));
+        self.declare(name, kind, name_node, node);
+    }
+
+    /**
+     * Class constants are declared by their element, like `X = 1`, but say whether they're `final`
+     * and what their visibility is in the declaration around it.
+     */
+    fn class_constant_signature(&mut self, name_node: &Node, element: &Node) {
+        let Some(declaration) = element.parent() else {
+            return;
+        };
+        let start = match declaration.child_by_field_name("attributes") {
+            Some(attributes) => attributes.end_byte(),
+            None => declaration.start_byte(),
+        };
+        let mut cursor = declaration.walk();
+        let Some(first) = declaration
+            .named_children(&mut cursor)
+            .find(|child| child.kind() == "const_element")
+        else {
+            return;
+        };
+        let modifiers = normalize_whitespace(&self.contents[start..first.start_byte()]);
+        let constant = self.symbols.declarations.last_mut();
+        if let Some(constant) = constant.filter(|c| c.range == to_range(&name_node.range())) {
+            constant.signature = format!("{} {}", modifiers, constant.signature);
+        }
+    }
+
+    fn definition(&mut self, definition: &Definition) {
+        let node = &definition.node;
+        if definition.kind == DefinitionKind::Namespace {
+            let namespace = definition.name.map(|name| self.text(&name)).unwrap_or("");
+            // without a body, the namespace applies to the rest of the file
+            if node.child_by_field_name("body").is_some() {
+                self.namespaces.push((node.end_byte(), self.names.clone()));
+            }
+            self.names.enter_namespace(namespace);
+            return;
+        }
+
+        let Some(name_node) = &definition.name else {
+            return;
+        };
+        match definition.kind {
+            DefinitionKind::Class
+            | DefinitionKind::Interface
+            | DefinitionKind::Trait
+            | DefinitionKind::Enum
+            | DefinitionKind::Function => {
+                let kind = match definition.kind {
+                    DefinitionKind::Class => DeclarationKind::Class,
+                    DefinitionKind::Interface => DeclarationKind::Interface,
+                    DefinitionKind::Trait => DeclarationKind::Trait,
+                    DefinitionKind::Enum => DeclarationKind::Enum,
+                    _ => DeclarationKind::Function,
+                };
+                let name = self.names.qualify(self.text(name_node));
+                self.declare(name, kind, name_node, node);
+            }
+            DefinitionKind::Method => self.declare_member(DeclarationKind::Method, name_node, node),
+            DefinitionKind::Property => {
+                let declaration = node.parent().unwrap_or(*node);
+                self.declare_member(DeclarationKind::Property, name_node, &declaration);
+            }
+            // promoted constructor parameters also declare properties
+            DefinitionKind::Parameter if node.kind() == "property_promotion_parameter" => {
+                self.declare_member(DeclarationKind::Property, name_node, node);
+            }
+            DefinitionKind::Constant => match enclosing_class(node) {
+                Some(_) => {
+                    self.declare_member(DeclarationKind::Constant, name_node, node);
+                    self.class_constant_signature(name_node, node);
+                }
+                None => {
+                    let name = self.names.qualify(self.text(name_node));
+                    self.declare(name, DeclarationKind::Constant, name_node, node);
+                }
+            },
+            DefinitionKind::EnumCase => {
+                self.declare_member(DeclarationKind::EnumCase, name_node, node)
+            }
+            _ => {}
+        }
+    }
+
+    fn import(&mut self, node: &Node) {
+        let kind_of = |node: &Node| match node.child_by_field_name("type").map(|t| t.kind()) {
+            Some("function") => Some(ImportKind::Function),
+            Some("const") => Some(ImportKind::Constant),
+            _ => None,
+        };
+        let declaration_kind = kind_of(node).unwrap_or(ImportKind::Class);
+
+        // group uses look like `use Prefix\{A, B as C};`
+        let mut cursor = node.walk();
+        let prefix = node
+            .named_children(&mut cursor)
+            .find(|child| child.kind() == "namespace_name")
+            .map(|prefix| self.text(&prefix));
+        let clauses_parent = node.child_by_field_name("body").unwrap_or(*node);
+
+        let mut cursor = clauses_parent.walk();
+        for clause in clauses_parent.named_children(&mut cursor) {
+            if clause.kind() != "namespace_use_clause" {
+                continue;
+            }
+
+            let alias_node = clause.child_by_field_name("alias");
+            let mut clause_cursor = clause.walk();
+            let Some(name_node) = clause
+                .named_children(&mut clause_cursor)
+                .find(|child| Some(*child) != alias_node)
+            else {
+                continue;
+            };
+
+            let name = match prefix {
+                Some(prefix) => format!("{}\\{}", prefix, self.text(&name_node)),
+                None => self.text(&name_node).to_string(),
+            };
+            let alias = alias_node.map(|alias| self.text(&alias));
+            self.names
+                .import(kind_of(&clause).unwrap_or(declaration_kind), &name, alias);
+        }
+    }
+
+    fn name_reference(&mut self, kind: ReferenceKind, node: &Node) {
+        let text = self.text(node);
+        match kind {
+            ReferenceKind::Class => {
+                if !matches!(text.to_lowercase().as_str(), "self" | "static" | "parent") {
+                    let name = self.names.resolve_class(text);
+                    self.reference(name, None, kind, node);
+                }
+            }
+            ReferenceKind::Member => {
+                let name = text.trim_start_matches('$').to_string();
+                self.reference(name, None, kind, node);
+            }
+            ReferenceKind::Function | ReferenceKind::Constant => {
+                let import_kind = match kind {
+                    ReferenceKind::Function => ImportKind::Function,
+                    _ => ImportKind::Constant,
+                };
+                let (name, fallback) = self.names.resolve_function_or_constant(import_kind, text);
+                self.reference(name, fallback, kind, node);
+            }
+        }
+    }
+}
+
+/**
+ * Find everything a file declares and references, with names fully qualified.
+ */
+pub fn file_symbols(root_node: &Node, file_contents: &str) -> FileSymbols {
+    let docs = doc_comments(root_node, file_contents);
+    let tagged = |tags: &[&str]| -> HashSet<usize> {
+        docs.iter()
+            .filter(|(_, comment)| {
+                let comment = &file_contents[comment.byte_range()];
+                tags.iter().any(|tag| comment.contains(tag))
+            })
+            .map(|(declaration, _)| *declaration)
+            .collect()
+    };
+    let mut walker = SymbolWalker {
+        contents: file_contents,
+        deprecated_docs: tagged(&["@deprecated"]),
+        deprecations: docs
+            .iter()
+            .filter_map(|(declaration, comment)| {
+                let comment = &file_contents[comment.byte_range()];
+                Some((*declaration, doc_deprecation(comment)?))
+            })
+            .collect(),
+        magic_docs: tagged(&["@method", "@property", "@mixin"]),
+        documentation: docs
+            .iter()
+            .map(|(declaration, comment)| {
+                let comment = &file_contents[comment.byte_range()];
+                (*declaration, Documentation::of(comment))
+            })
+            .collect(),
+        see: docs
+            .iter()
+            .map(|(declaration, comment)| {
+                let comment = &file_contents[comment.byte_range()];
+                (*declaration, doc_see_classes(comment))
+            })
+            .collect(),
+        names: NameContext::default(),
+        namespaces: vec![],
+        symbols: FileSymbols::default(),
+    };
+
+    // names are resolved against the namespace and imports in effect where they are written, so
+    // everything has to be visited in order
+    let mut definitions = definitions(root_node, file_contents).into_iter().peekable();
+    let mut references = references(root_node, file_contents).into_iter().peekable();
+    loop {
+        let next_definition = definitions
+            .peek()
+            .map(|definition| definition.node.start_byte());
+        let next_reference = references.peek().map(ReferenceTag::start_byte);
+        match (next_definition, next_reference) {
+            (Some(definition_start), reference_start)
+                if reference_start.is_none_or(|start| definition_start <= start) =>
+            {
+                walker.leave_namespaces(definition_start);
+                if let Some(definition) = definitions.next() {
+                    walker.definition(&definition);
+                }
+            }
+            (_, Some(reference_start)) => {
+                walker.leave_namespaces(reference_start);
+                match references.next() {
+                    Some(ReferenceTag::Import(node)) => walker.import(&node),
+                    Some(ReferenceTag::Doc(node)) => walker.doc_references(&node),
+                    Some(ReferenceTag::Name(kind, node)) => walker.name_reference(kind, &node),
+                    None => {}
+                }
+            }
+            _ => break,
+        }
+    }
+
+    walker.symbols
+}
+
+#[cfg(test)]
+mod test {
+    use super::{file_symbols, DeclarationKind, ReferenceKind};
+    use crate::file::php_parser;
+
+    const SOURCE: &str = "<?php
+namespace App\\Models;
+
+use Foo\\Bar as Baz, Other\\Thing;
+use function Foo\\helper;
+
+class User extends Base implements \\Countable {
+    use SomeTrait;
+    const X = 1;
+    public int $age = 0;
+
+    public function __construct(private int $id) {}
+
+    public function go(Baz $b): ?Thing
+    {
+        $x = new Thing();
+        helper();
+        strlen('a');
+        $b->run();
+        Thing::make();
+        return FOO;
+    }
+}
+
+function top(): void {}
+";
+
+    #[test]
+    fn test_declarations() {
+        let tree = php_parser().parse(SOURCE, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), SOURCE);
+        let declarations: Vec<(&str, DeclarationKind)> = symbols
+            .declarations
+            .iter()
+            .map(|declaration| (declaration.name.as_str(), declaration.kind))
+            .collect();
+
+        assert_eq!(
+            vec![
+                ("App\\Models\\User", DeclarationKind::Class),
+                ("App\\Models\\User::X", DeclarationKind::Constant),
+                ("App\\Models\\User::$age", DeclarationKind::Property),
+                ("App\\Models\\User::__construct", DeclarationKind::Method),
+                ("App\\Models\\User::$id", DeclarationKind::Property),
+                ("App\\Models\\User::go", DeclarationKind::Method),
+                ("App\\Models\\top", DeclarationKind::Function),
+            ],
+            declarations
+        );
+        assert_eq!(
+            "public function go(Baz $b): ?Thing",
+            symbols.declarations[5].signature
+        );
+        assert_eq!("public int $age = 0;", symbols.declarations[2].signature);
+        assert_eq!("const X = 1", symbols.declarations[1].signature);
+        assert_eq!(
+            vec!["App\\Models\\Base", "Countable", "App\\Models\\SomeTrait"],
+            symbols.declarations[0].supertypes
+        );
+    }
+
+    #[test]
+    fn test_references() {
+        let tree = php_parser().parse(SOURCE, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), SOURCE);
+        let references: Vec<(&str, Option<&str>, ReferenceKind)> = symbols
+            .references
+            .iter()
+            .map(|reference| {
+                (
+                    reference.name.as_str(),
+                    reference.fallback.as_deref(),
+                    reference.kind,
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            vec![
+                ("App\\Models\\Base", None, ReferenceKind::Class),
+                ("Countable", None, ReferenceKind::Class),
+                ("App\\Models\\SomeTrait", None, ReferenceKind::Class),
+                ("Foo\\Bar", None, ReferenceKind::Class),
+                ("Other\\Thing", None, ReferenceKind::Class),
+                ("Other\\Thing", None, ReferenceKind::Class),
+                ("Foo\\helper", None, ReferenceKind::Function),
+                (
+                    "App\\Models\\strlen",
+                    Some("strlen"),
+                    ReferenceKind::Function
+                ),
+                ("run", None, ReferenceKind::Member),
+                ("Other\\Thing", None, ReferenceKind::Class),
+                ("make", None, ReferenceKind::Member),
+                ("App\\Models\\FOO", Some("FOO"), ReferenceKind::Constant),
+            ],
+            references
+        );
+    }
+
+    #[test]
+    fn test_doc_references() {
+        let source = "<?php
+namespace App;
+use Foo\\Bar;
+/**
+ * @see Bar::run()
+ * @throws \\RuntimeException
+ */
+function f() {}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let references: Vec<(&str, ReferenceKind, &str)> = symbols
+            .references
+            .iter()
+            .map(|reference| {
+                let range = reference.range;
+                let line = source.lines().nth(range.start.line as usize).unwrap();
+                let text = &line[range.start.character as usize..range.end.character as usize];
+                (reference.name.as_str(), reference.kind, text)
+            })
+            .collect();
+
+        assert_eq!(
+            vec![
+                ("Foo\\Bar", ReferenceKind::Class, "Bar"),
+                ("run", ReferenceKind::Member, "run"),
+                (
+                    "RuntimeException",
+                    ReferenceKind::Class,
+                    "\\RuntimeException"
+                ),
+            ],
+            references
+        );
+    }
+
+    #[test]
+    fn test_attributes() {
+        let source = "<?php
+class Repository {
+    #[ArrayShape(['id' => 'int', 'name' => 'string']), Pure]
+    public function find(#[ExpectedValues(values: ['asc', 'desc'])] string $order = 'asc', int $limit = 10): array {}
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let find = &symbols.declarations[1];
+
+        assert!(find.signature.starts_with("public function find("));
+        assert!(find.is_pure());
+        assert_eq!(
+            Some(vec![
+                ("id".to_string(), "int".to_string()),
+                ("name".to_string(), "string".to_string())
+            ]),
+            find.array_shape()
+        );
+        assert_eq!(vec!["$order", "$limit"], find.parameters());
+        assert_eq!(Some("array"), find.return_type());
+        assert_eq!(
+            Some(vec!["'asc'".to_string(), "'desc'".to_string()]),
+            find.expected_values(0)
+        );
+        assert_eq!(None, find.expected_values(1));
+        assert!(!symbols.declarations[0].is_pure());
+    }
+
+    #[test]
+    fn test_enum_members() {
+        let source = "<?php
+namespace App;
+enum Suit: string implements HasLabel { case Hearts = 'H'; }
+enum Status { case Active; }
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let suit = &symbols.declarations[0];
+        let status = &symbols.declarations[2];
+
+        assert_eq!(Some("string"), suit.backing_type());
+        assert_eq!(None, status.backing_type());
+        let members: Vec<String> = suit
+            .enum_members()
+            .into_iter()
+            .map(|member| member.name)
+            .collect();
+        assert_eq!(
+            vec![
+                "App\\Suit::cases",
+                "App\\Suit::$name",
+                "App\\Suit::from",
+                "App\\Suit::tryFrom",
+                "App\\Suit::$value"
+            ],
+            members
+        );
+        assert_eq!(2, status.enum_members().len());
+        assert_eq!(Some("string"), suit.enum_members()[4].property_type());
+        assert_eq!(Some("?static"), suit.enum_members()[3].return_type());
+    }
+
+    #[test]
+    fn test_parameters() {
+        let source = "<?php
+function f(int $a, array $b = [1, 2], string $c = 'x, $y', &$d, int ...$rest): void {}
+class A { public function __construct(private readonly string $name) {} }
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+
+        assert_eq!(
+            vec!["$a", "$b", "$c", "$d", "...$rest"],
+            symbols.declarations[0].parameters()
+        );
+        assert_eq!(Some("void"), symbols.declarations[0].return_type());
+        let types: Vec<Option<&str>> = (0..5)
+            .map(|i| symbols.declarations[0].parameter_type(i))
+            .collect();
+        assert_eq!(
+            vec![
+                Some("int"),
+                Some("array"),
+                Some("string"),
+                None,
+                Some("int")
+            ],
+            types
+        );
+        assert_eq!(vec!["$name"], symbols.declarations[2].parameters());
+        assert_eq!(Some("string"), symbols.declarations[2].parameter_type(0));
+        assert_eq!(None, symbols.declarations[2].return_type());
+        assert_eq!((4, None), symbols.declarations[0].arity());
+        assert_eq!((1, Some(1)), symbols.declarations[2].arity());
+        assert_eq!(
+            Some("'x, $y'"),
+            symbols.declarations[0].parameter_default(2)
+        );
+        assert_eq!(None, symbols.declarations[0].parameter_default(3));
+        assert!(symbols.declarations[0].is_by_reference(3));
+    }
+
+    #[test]
+    fn test_typed_members() {
+        let source = "<?php
+class A {
+    final public const string NAME = 'a';
+    const LIMIT = 1;
+    public string $label { get => strtoupper($this->label); }
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+
+        assert_eq!(Some("string"), symbols.declarations[1].constant_type());
+        assert_eq!(None, symbols.declarations[2].constant_type());
+        assert_eq!("public string $label", symbols.declarations[3].signature);
+    }
+}
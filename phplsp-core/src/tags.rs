@@ -0,0 +1,565 @@
+use lsp_types::Position;
+use tree_sitter::{Node, Query, QueryCursor};
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use crate::file::php_language;
+use crate::symbols::ReferenceKind;
+
+/**
+ * Compiled tree-sitter queries from the `queries` directory.
+ *
+ * These are the one place that knows which node kinds declare or reference something, so that
+ * the index, the document symbols and anything else that needs to find declarations all agree.
+ */
+fn query(cell: &'static OnceLock<Query>, source: &str) -> &'static Query {
+    cell.get_or_init(|| Query::new(php_language(), source).expect("invalid query"))
+}
+
+fn declarations_query() -> &'static Query {
+    static QUERY: OnceLock<Query> = OnceLock::new();
+    query(&QUERY, include_str!("../queries/declarations.scm"))
+}
+
+fn references_query() -> &'static Query {
+    static QUERY: OnceLock<Query> = OnceLock::new();
+    query(&QUERY, include_str!("../queries/references.scm"))
+}
+
+fn doc_comments_query() -> &'static Query {
+    static QUERY: OnceLock<Query> = OnceLock::new();
+    query(&QUERY, include_str!("../queries/doc_comments.scm"))
+}
+
+/// What a `@definition.<kind>` capture declares.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefinitionKind {
+    Namespace,
+    Class,
+    Interface,
+    Trait,
+    Enum,
+    Function,
+    Method,
+    Property,
+    Constant,
+    EnumCase,
+    Parameter,
+}
+
+impl DefinitionKind {
+    fn from_capture(name: &str) -> Option<Self> {
+        Some(match name.strip_prefix("definition.")? {
+            "namespace" => Self::Namespace,
+            "class" => Self::Class,
+            "interface" => Self::Interface,
+            "trait" => Self::Trait,
+            "enum" => Self::Enum,
+            "function" => Self::Function,
+            "method" => Self::Method,
+            "property" => Self::Property,
+            "constant" => Self::Constant,
+            "enum_case" => Self::EnumCase,
+            "parameter" => Self::Parameter,
+            _ => return None,
+        })
+    }
+}
+
+pub struct Definition<'tree> {
+    pub kind: DefinitionKind,
+    pub node: Node<'tree>,
+    /// Only the global namespace has no name.
+    pub name: Option<Node<'tree>>,
+}
+
+pub enum ReferenceTag<'tree> {
+    /// A `use` statement.
+    Import(Node<'tree>),
+    /// A doc comment, which may name things in its tags.
+    Doc(Node<'tree>),
+    Name(ReferenceKind, Node<'tree>),
+}
+
+impl ReferenceTag<'_> {
+    pub fn start_byte(&self) -> usize {
+        match self {
+            Self::Import(node) | Self::Doc(node) | Self::Name(_, node) => node.start_byte(),
+        }
+    }
+}
+
+/// Everything declared in a tree, in the order the declarations start.
+pub fn definitions<'tree>(root_node: &Node<'tree>, file_contents: &str) -> Vec<Definition<'tree>> {
+    let query = declarations_query();
+    let mut cursor = QueryCursor::new();
+    let mut definitions = vec![];
+
+    for m in cursor.matches(query, *root_node, file_contents.as_bytes()) {
+        let mut kind = None;
+        let mut node = None;
+        let mut name = None;
+        for capture in m.captures {
+            let capture_name = query.capture_names()[capture.index as usize];
+            if capture_name == "name" {
+                name = Some(capture.node);
+            } else if let Some(definition_kind) = DefinitionKind::from_capture(capture_name) {
+                kind = Some(definition_kind);
+                node = Some(capture.node);
+            }
+        }
+
+        if let (Some(kind), Some(node)) = (kind, node) {
+            definitions.push(Definition { kind, node, name });
+        }
+    }
+
+    definitions.sort_by_key(|definition| definition.node.start_byte());
+    definitions
+}
+
+/**
+ * Whether a node is the `(set)` of asymmetric visibility, like `private(set)`. The grammar doesn't
+ * know PHP 8.4's asymmetric visibility yet, and parses it as a type with an error after it.
+ */
+pub fn is_set_visibility(node: &Node, file_contents: &str) -> bool {
+    node.kind() == "disjunctive_normal_form_type"
+        && node
+            .prev_sibling()
+            .is_some_and(|previous| previous.kind() == "visibility_modifier")
+        && file_contents[node.byte_range()]
+            .split_whitespace()
+            .collect::<String>()
+            .eq_ignore_ascii_case("(set)")
+}
+
+/// Every reference and `use` statement in a tree, in the order they start.
+pub fn references<'tree>(root_node: &Node<'tree>, file_contents: &str) -> Vec<ReferenceTag<'tree>> {
+    let query = references_query();
+    let mut cursor = QueryCursor::new();
+    // earlier patterns take priority over later ones for the same node
+    let mut tags: HashMap<usize, (usize, ReferenceTag)> = HashMap::new();
+
+    for m in cursor.matches(query, *root_node, file_contents.as_bytes()) {
+        for capture in m.captures {
+            let tag = match query.capture_names()[capture.index as usize] {
+                "import" => ReferenceTag::Import(capture.node),
+                "doc" => ReferenceTag::Doc(capture.node),
+                // the `set` of `private(set)` is no class
+                "reference.class"
+                    if capture
+                        .node
+                        .parent()
+                        .and_then(|named_type| named_type.parent())
+                        .and_then(|intersection| intersection.parent())
+                        .is_some_and(|dnf| is_set_visibility(&dnf, file_contents)) =>
+                {
+                    continue
+                }
+                "reference.class" => ReferenceTag::Name(ReferenceKind::Class, capture.node),
+                "reference.member" => ReferenceTag::Name(ReferenceKind::Member, capture.node),
+                "reference.function" => ReferenceTag::Name(ReferenceKind::Function, capture.node),
+                "reference.constant" => ReferenceTag::Name(ReferenceKind::Constant, capture.node),
+                _ => continue,
+            };
+
+            let is_better = tags
+                .get(&capture.node.id())
+                .is_none_or(|(pattern_index, _)| m.pattern_index < *pattern_index);
+            if is_better {
+                tags.insert(capture.node.id(), (m.pattern_index, tag));
+            }
+        }
+    }
+
+    let mut tags: Vec<ReferenceTag> = tags.into_values().map(|(_, tag)| tag).collect();
+    tags.sort_by_key(ReferenceTag::start_byte);
+    tags
+}
+
+/**
+ * Runs of `use` statements that follow each other, with nothing but comments between them, as
+ * their first and last statement. Those of a namespace's body are found too.
+ */
+pub fn use_blocks<'tree>(root_node: &Node<'tree>) -> Vec<(Node<'tree>, Node<'tree>)> {
+    let mut blocks = vec![];
+    let mut bodies = vec![*root_node];
+    while let Some(body) = bodies.pop() {
+        let mut block: Option<(Node, Node)> = None;
+        let mut cursor = body.walk();
+        for statement in body.named_children(&mut cursor) {
+            match statement.kind() {
+                "namespace_use_declaration" => {
+                    let first = block.map_or(statement, |(first, _)| first);
+                    block = Some((first, statement));
+                }
+                "comment" => {}
+                kind => {
+                    blocks.extend(block.take());
+                    if kind == "namespace_definition" {
+                        bodies.extend(statement.child_by_field_name("body"));
+                    }
+                }
+            }
+        }
+        blocks.extend(block);
+    }
+
+    blocks.sort_by_key(|(first, _)| first.start_byte());
+    blocks
+}
+
+/// Doc comments, keyed by the id of the declaration node they document.
+pub fn doc_comments<'tree>(
+    root_node: &Node<'tree>,
+    file_contents: &str,
+) -> HashMap<usize, Node<'tree>> {
+    let query = doc_comments_query();
+    let mut cursor = QueryCursor::new();
+    let mut comments = HashMap::new();
+
+    for m in cursor.matches(query, *root_node, file_contents.as_bytes()) {
+        let mut doc = None;
+        let mut definition = None;
+        for capture in m.captures {
+            match query.capture_names()[capture.index as usize] {
+                "doc" => doc = Some(capture.node),
+                "definition" => definition = Some(capture.node),
+                _ => {}
+            }
+        }
+
+        if let (Some(doc), Some(definition)) = (doc, definition) {
+            comments.insert(definition.id(), doc);
+        }
+    }
+
+    comments
+}
+
+/// The first line of text in a doc comment, without the comment markers.
+pub fn doc_summary(comment: &str) -> Option<String> {
+    comment
+        .trim_start_matches("/**")
+        .trim_end_matches("*/")
+        .lines()
+        .map(|line| line.trim().trim_start_matches('*').trim())
+        .find(|line| !line.is_empty() && !line.starts_with('@'))
+        .map(str::to_string)
+}
+
+/**
+ * What the `@deprecated` tag of a doc comment says, which can go on over the lines after it until
+ * the next tag. The version it starts with, if any, is left in.
+ */
+pub fn doc_deprecation(comment: &str) -> Option<String> {
+    let mut lines = comment
+        .trim_start_matches("/**")
+        .trim_end_matches("*/")
+        .lines()
+        .map(|line| line.trim().trim_start_matches('*').trim())
+        .skip_while(|line| !line.starts_with("@deprecated"));
+    let first = lines.next()?.trim_start_matches("@deprecated");
+    if !first.is_empty() && !first.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let rest = lines.take_while(|line| !line.is_empty() && !line.starts_with('@'));
+    let text = std::iter::once(first.trim())
+        .chain(rest)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    (!text.is_empty()).then_some(text)
+}
+
+/// Whether a doc comment inherits the documentation of its parent, with `@inheritDoc` or `{@inheritDoc}` in any case.
+pub fn inherits_doc(comment: &str) -> bool {
+    comment.to_ascii_lowercase().contains("@inheritdoc")
+}
+
+/// Tags whose first word can name a class, member or function.
+const NAMING_TAGS: &[&str] = &["@see", "@uses", "@throws", "@link"];
+
+/// The words of a doc comment and where they start, with `{@link ...}` braces as separators.
+fn doc_words(comment: &str) -> Vec<(usize, &str)> {
+    let mut words = vec![];
+    let mut word_start = None;
+    for (i, c) in comment.char_indices() {
+        let is_separator = c.is_whitespace() || c == '{' || c == '}';
+        match (is_separator, word_start) {
+            (true, Some(start)) => {
+                words.push((start, &comment[start..i]));
+                word_start = None;
+            }
+            (false, None) => word_start = Some(i),
+            _ => {}
+        }
+    }
+    if let Some(start) = word_start {
+        words.push((start, &comment[start..]));
+    }
+
+    words
+}
+
+/// URLs in the `@see` and `{@link}` tags of a doc comment, with where they are in the comment.
+pub fn doc_urls(comment: &str) -> Vec<(std::ops::Range<usize>, &str)> {
+    doc_words(comment)
+        .windows(2)
+        .filter_map(|pair| {
+            let [(_, tag), (start, written)] = pair else {
+                return None;
+            };
+            let url = written.trim_end_matches(['.', ',', '*', '/']);
+            (matches!(*tag, "@see" | "@link") && url.contains("://"))
+                .then(|| (*start..start + url.len(), url))
+        })
+        .collect()
+}
+
+/// Class-likes named by the `@see` tags of a doc comment, as written, leaving out members, functions and URLs.
+pub fn doc_see_classes(comment: &str) -> Vec<&str> {
+    doc_words(comment)
+        .windows(2)
+        .filter_map(|pair| {
+            let [(_, tag), (_, written)] = pair else {
+                return None;
+            };
+            let name = written.trim_end_matches(['.', ',', '*', '/']);
+            let is_class = !name.contains("://")
+                && !name.contains("::")
+                && !name.ends_with("()")
+                && name
+                    .chars()
+                    .next()
+                    .is_some_and(|c| c.is_alphabetic() || c == '_' || c == '\\');
+            (*tag == "@see" && is_class).then_some(name)
+        })
+        .collect()
+}
+
+/**
+ * Names in the `@see`, `@uses`, `@throws` and `{@link}` tags of a doc comment, with where they
+ * are in the comment.
+ *
+ * Names are as written: functions and methods may be followed by `()`, and members are written
+ * as `Class::member`. `@throws` may list several classes, like `A|B`. Links to URLs are skipped.
+ */
+pub fn doc_names(comment: &str) -> Vec<(std::ops::Range<usize>, &str)> {
+    let words = doc_words(comment);
+    let mut names = vec![];
+    for pair in words.windows(2) {
+        let [(_, tag), (start, written)] = pair else {
+            continue;
+        };
+        if !NAMING_TAGS.contains(tag) || written.contains("://") {
+            continue;
+        }
+
+        let mut part_start = *start;
+        for part in written.split('|') {
+            let name_start = part_start + part.len() - part.trim_start_matches('?').len();
+            let name = part
+                .trim_start_matches('?')
+                .trim_end_matches(['.', ',', '*', '/']);
+            if name
+                .chars()
+                .next()
+                .is_some_and(|c| c.is_alphabetic() || c == '_' || c == '\\')
+            {
+                names.push((name_start..name_start + name.len(), name));
+            }
+            part_start += part.len() + 1;
+        }
+    }
+
+    names
+}
+
+/**
+ * Names of the parameters in the `@param` tags of a doc comment, with their `$` and where they
+ * are in the comment. The type before the name can be left out, and variadic and by-reference
+ * parameters are written as `...$name` and `&$name`.
+ */
+pub fn doc_params(comment: &str) -> Vec<(std::ops::Range<usize>, &str)> {
+    let words = doc_words(comment);
+    let mut params = vec![];
+    for (i, (_, tag)) in words.iter().enumerate() {
+        if *tag != "@param" {
+            continue;
+        }
+        let name = words[i + 1..].iter().take(2).find_map(|(start, written)| {
+            let name = written.trim_start_matches(['.', '&']);
+            let start = start + written.len() - name.len();
+            name.starts_with('$')
+                .then(|| (start..start + name.len(), name))
+        });
+        params.extend(name);
+    }
+
+    params
+}
+
+/// The type in the first `@return` tag of a doc comment, as it is written.
+pub fn doc_return(comment: &str) -> Option<&str> {
+    let words = doc_words(comment);
+    let i = words.iter().position(|(_, tag)| *tag == "@return")?;
+    words
+        .get(i + 1)
+        .map(|(_, ty)| *ty)
+        .filter(|ty| !ty.starts_with('@') && !ty.starts_with('*'))
+}
+
+/// Where a byte of a comment is in the file.
+pub fn comment_position(comment: &Node, text: &str, offset: usize) -> Position {
+    let start = comment.start_position();
+    match text[..offset].rfind('\n') {
+        Some(newline) => Position {
+            line: (start.row + text[..offset].matches('\n').count()) as u32,
+            character: (offset - newline - 1) as u32,
+        },
+        None => Position {
+            line: start.row as u32,
+            character: (start.column + offset) as u32,
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        definitions, doc_comments, doc_names, doc_params, doc_see_classes, doc_summary, doc_urls,
+        references, DefinitionKind, ReferenceTag,
+    };
+    use crate::file::php_parser;
+    use crate::symbols::ReferenceKind;
+
+    #[test]
+    fn test_queries_compile() {
+        let tree = php_parser().parse("<?php", None).unwrap();
+        assert!(definitions(&tree.root_node(), "<?php").is_empty());
+        assert!(references(&tree.root_node(), "<?php").is_empty());
+        assert!(doc_comments(&tree.root_node(), "<?php").is_empty());
+    }
+
+    #[test]
+    fn test_nested_definitions() {
+        let source = "<?php
+function outer($a) {
+    $f = function ($b) {};
+    class Inner {
+        const X = 1;
+    }
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let found: Vec<(DefinitionKind, &str)> = definitions(&tree.root_node(), source)
+            .iter()
+            .map(|definition| {
+                (
+                    definition.kind,
+                    &source[definition.name.unwrap().byte_range()],
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            vec![
+                (DefinitionKind::Function, "outer"),
+                (DefinitionKind::Parameter, "$a"),
+                (DefinitionKind::Class, "Inner"),
+                (DefinitionKind::Constant, "X"),
+            ],
+            found
+        );
+    }
+
+    #[test]
+    fn test_reference_priority() {
+        let source = "<?php $a instanceof Foo; echo BAR; Baz::class;";
+        let tree = php_parser().parse(source, None).unwrap();
+        let found: Vec<(ReferenceKind, &str)> = references(&tree.root_node(), source)
+            .iter()
+            .filter_map(|tag| match tag {
+                ReferenceTag::Name(kind, node) => Some((*kind, &source[node.byte_range()])),
+                ReferenceTag::Import(_) | ReferenceTag::Doc(_) => None,
+            })
+            .collect();
+
+        assert_eq!(
+            vec![
+                (ReferenceKind::Class, "Foo"),
+                (ReferenceKind::Constant, "BAR"),
+                (ReferenceKind::Class, "Baz"),
+            ],
+            found
+        );
+    }
+
+    #[test]
+    fn test_doc_names() {
+        let comment = "/**
+ * Uses {@link Helper::run()} and https://example.com.
+ *
+ * @see https://example.com
+ * @see format()
+ * @uses \\App\\Models\\User::$name
+ * @throws InvalidArgumentException|?RuntimeException when it fails
+ */";
+        let names: Vec<&str> = doc_names(comment)
+            .into_iter()
+            .map(|(range, name)| {
+                assert_eq!(&comment[range], name);
+                name
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                "Helper::run()",
+                "format()",
+                "\\App\\Models\\User::$name",
+                "InvalidArgumentException",
+                "RuntimeException"
+            ],
+            names
+        );
+
+        let urls: Vec<&str> = doc_urls(comment).into_iter().map(|(_, url)| url).collect();
+        assert_eq!(vec!["https://example.com"], urls);
+        assert!(doc_see_classes(comment).is_empty());
+        assert_eq!(
+            vec!["\\Psr\\Log\\LoggerInterface"],
+            doc_see_classes("/** @see \\Psr\\Log\\LoggerInterface. */")
+        );
+    }
+
+    #[test]
+    fn test_doc_summary() {
+        assert_eq!(
+            Some("Does a thing.".to_string()),
+            doc_summary("/**\n * Does a thing.\n *\n * @return int\n */")
+        );
+        assert_eq!(Some("Short".to_string()), doc_summary("/** Short */"));
+        assert_eq!(None, doc_summary("/** @var int */"));
+    }
+
+    #[test]
+    fn test_doc_params() {
+        let comment = "/**
+ * @param int $a the first
+ * @param $b
+ * @param string ...$rest
+ * @param array &$out
+ * @param int
+ */";
+        let params: Vec<&str> = doc_params(comment)
+            .into_iter()
+            .map(|(range, name)| {
+                assert_eq!(&comment[range], name);
+                name
+            })
+            .collect();
+        assert_eq!(vec!["$a", "$b", "$rest", "$out"], params);
+    }
+}
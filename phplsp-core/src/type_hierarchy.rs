@@ -0,0 +1,143 @@
+use lsp_types::*;
+use serde::{Deserialize, Serialize};
+use tree_sitter::Node;
+
+use crate::index::WorkspaceIndex;
+use crate::resolve::Resolver;
+use crate::symbols::{Declaration, DeclarationKind, FileSymbols};
+
+/// What an item needs to find its declaration again when the client asks for its relatives.
+#[derive(Debug, Serialize, Deserialize)]
+struct TypeHierarchyData {
+    name: String,
+}
+
+fn is_class_like(declaration: &Declaration) -> bool {
+    matches!(
+        declaration.kind,
+        DeclarationKind::Class
+            | DeclarationKind::Interface
+            | DeclarationKind::Trait
+            | DeclarationKind::Enum
+    )
+}
+
+/// An item for a class-like, spanning its name.
+pub fn type_hierarchy_item(uri: &Url, declaration: &Declaration) -> TypeHierarchyItem {
+    TypeHierarchyItem {
+        name: declaration.short_name().to_string(),
+        kind: declaration.kind.into(),
+        tags: declaration.deprecated.then_some(SymbolTag::DEPRECATED),
+        detail: declaration.container_name().map(str::to_string),
+        uri: uri.clone(),
+        range: declaration.range,
+        selection_range: declaration.range,
+        data: serde_json::to_value(TypeHierarchyData {
+            name: declaration.name.clone(),
+        })
+        .ok(),
+    }
+}
+
+/// The class-like an item from [`type_hierarchy_item`] is for.
+pub fn item_declaration<'a>(
+    item: &TypeHierarchyItem,
+    index: &'a WorkspaceIndex,
+) -> Option<&'a Declaration> {
+    let data: TypeHierarchyData = serde_json::from_value(item.data.clone()?).ok()?;
+    index
+        .find_declaration(&data.name)
+        .filter(|declaration| is_class_like(declaration))
+}
+
+/// The item for the class-like that the name at a position declares or refers to.
+pub fn prepare_type_hierarchy(
+    root_node: &Node,
+    file_contents: &str,
+    symbols: &FileSymbols,
+    index: &WorkspaceIndex,
+    position: &Position,
+) -> Option<TypeHierarchyItem> {
+    let resolver = Resolver::new(file_contents, symbols, index);
+    let declaration = resolver.target(root_node, position)?;
+    let (uri, declaration) = index.locate(&declaration.name)?;
+
+    is_class_like(declaration).then(|| type_hierarchy_item(uri, declaration))
+}
+
+/**
+ * What a class-like extends, implements and uses, in that order. Those that aren't in the
+ * workspace, like built-in interfaces without stubs, are left out.
+ */
+pub fn supertypes(declaration: &Declaration, index: &WorkspaceIndex) -> Vec<TypeHierarchyItem> {
+    declaration
+        .supertypes
+        .iter()
+        .filter_map(|name| index.locate(name))
+        .map(|(uri, supertype)| type_hierarchy_item(uri, supertype))
+        .collect()
+}
+
+/// The class-likes in the workspace that directly extend, implement or use a class-like.
+pub fn subtypes(declaration: &Declaration, index: &WorkspaceIndex) -> Vec<TypeHierarchyItem> {
+    index
+        .subtypes(&declaration.name)
+        .into_iter()
+        .map(|(uri, subtype)| type_hierarchy_item(uri, subtype))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::*;
+
+    use super::{item_declaration, prepare_type_hierarchy, subtypes, supertypes};
+    use crate::file::php_parser;
+    use crate::index::WorkspaceIndex;
+    use crate::symbols::file_symbols;
+
+    #[test]
+    fn test_type_hierarchy() {
+        let base = "<?php
+namespace App;
+interface Shape {}
+trait Named {}
+abstract class Base implements Shape, \\Countable { use Named; }
+";
+        let source = "<?php
+namespace App\\Shapes;
+use App\\Base;
+final class Circle extends Base {}
+class Square extends Base {}
+";
+        let mut index = WorkspaceIndex::default();
+        let base_tree = php_parser().parse(base, None).unwrap();
+        index.update_symbols(
+            &Url::parse("file:///project/Base.php").unwrap(),
+            file_symbols(&base_tree.root_node(), base),
+        );
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        index.update_symbols(
+            &Url::parse("file:///project/Shapes.php").unwrap(),
+            symbols.clone(),
+        );
+
+        // on `Base` after `extends`
+        let position = Position {
+            line: 3,
+            character: 28,
+        };
+        let item =
+            prepare_type_hierarchy(&tree.root_node(), source, &symbols, &index, &position).unwrap();
+        assert_eq!("Base", item.name);
+        assert_eq!("file:///project/Base.php", item.uri.as_str());
+
+        let base = item_declaration(&item, &index).unwrap();
+        let names = |items: Vec<TypeHierarchyItem>| -> Vec<String> {
+            items.into_iter().map(|item| item.name).collect()
+        };
+        assert_eq!(vec!["Shape", "Named"], names(supertypes(base, &index)));
+        assert_eq!(vec!["Circle", "Square"], names(subtypes(base, &index)));
+    }
+}
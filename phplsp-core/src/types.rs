@@ -0,0 +1,1300 @@
+use tree_sitter::Node;
+
+use std::fmt;
+use std::time::Instant;
+
+use crate::annotations::PinnedType;
+use crate::attributes::{array_shape, attribute_arguments, strip_attributes};
+use crate::file::to_position;
+use crate::preg::{is_matches_argument, matches_type};
+use crate::resolve::Resolver;
+use crate::symbols::{Declaration, DeclarationKind};
+
+/// Node kinds with parameters and a body of their own.
+const FUNCTION_LIKES: &[&str] = &[
+    "function_definition",
+    "method_declaration",
+    "anonymous_function",
+    "arrow_function",
+];
+
+/// How far we follow variables and calls before giving up.
+const MAX_DEPTH: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Type {
+    Int,
+    Float,
+    String,
+    Bool,
+    Null,
+    Void,
+    Mixed,
+    /// An array, with the type of its values if we know it.
+    Array(Option<Box<Type>>),
+    /// An array with known keys, from `#[ArrayShape]`.
+    Shape(Vec<(String, Type)>),
+    /// A class-like, by its fully qualified name.
+    Class(String),
+    Union(Vec<Type>),
+}
+
+impl Type {
+    /// One type out of several, dropping duplicates. Nested unions are flattened.
+    pub fn union(types: impl IntoIterator<Item = Type>) -> Type {
+        let mut flat: Vec<Type> = vec![];
+        for ty in types {
+            let members = match ty {
+                Type::Union(members) => members,
+                ty => vec![ty],
+            };
+            for member in members {
+                if !flat.contains(&member) {
+                    flat.push(member);
+                }
+            }
+        }
+
+        match flat.len() {
+            1 => flat.remove(0),
+            _ => Type::Union(flat),
+        }
+    }
+
+    /// This type, but never `null`.
+    pub fn without_null(&self) -> Type {
+        match self {
+            Type::Union(members) => Type::union(
+                members
+                    .iter()
+                    .filter(|member| **member != Type::Null)
+                    .cloned(),
+            ),
+            ty => ty.clone(),
+        }
+    }
+
+    /// The type of the values we get when iterating over something of this type.
+    pub fn value_type(&self) -> Option<Type> {
+        match self {
+            Type::Array(value) => value.as_deref().cloned(),
+            Type::Shape(entries) if !entries.is_empty() => {
+                Some(Type::union(entries.iter().map(|(_, ty)| ty.clone())))
+            }
+            _ => None,
+        }
+    }
+
+    /// The type of the value at a key of something of this type.
+    pub fn key_type(&self, key: &str) -> Option<Type> {
+        match self {
+            Type::Shape(entries) => entries
+                .iter()
+                .find(|(entry, _)| entry == key)
+                .map(|(_, ty)| ty.clone()),
+            _ => self.value_type(),
+        }
+    }
+
+    /// The shape from the arguments of an `#[ArrayShape]`, as seen from where it's written.
+    fn shape(arguments: &str, namespace: &str, class: Option<&str>) -> Option<Type> {
+        let entries: Option<Vec<(String, Type)>> = array_shape(arguments)?
+            .into_iter()
+            .map(|(key, ty)| Some((key, Type::parse(&ty, namespace, class)?)))
+            .collect();
+        entries.map(Type::Shape)
+    }
+
+    /**
+     * A type as it is written in a declaration.
+     *
+     * Class names are taken to be in the namespace the declaration is in, since we don't know
+     * what that file imports. `self` and `static` are the class the declaration belongs to.
+     */
+    pub fn parse(text: &str, namespace: &str, class: Option<&str>) -> Option<Type> {
+        let text = text.trim();
+        if let Some(text) = text.strip_prefix('?') {
+            return Some(Type::union([
+                Type::parse(text, namespace, class)?,
+                Type::Null,
+            ]));
+        }
+        if text.contains('|') {
+            let members: Option<Vec<Type>> = text
+                .split('|')
+                .map(|member| Type::parse(member, namespace, class))
+                .collect();
+            return members.map(Type::union);
+        }
+
+        if let Some(value) = text.strip_suffix("[]") {
+            return Some(Type::Array(Some(Box::new(Type::parse(
+                value, namespace, class,
+            )?))));
+        }
+
+        Some(match text.to_lowercase().as_str() {
+            "" => return None,
+            "int" => Type::Int,
+            "float" => Type::Float,
+            "string" => Type::String,
+            "bool" | "true" | "false" => Type::Bool,
+            "null" => Type::Null,
+            "void" | "never" => Type::Void,
+            "mixed" => Type::Mixed,
+            "array" | "iterable" => Type::Array(None),
+            "callable" => Type::Class("Closure".to_string()),
+            "object" => Type::Class("stdClass".to_string()),
+            "self" | "static" | "$this" => Type::Class(class?.to_string()),
+            _ => match text.strip_prefix('\\') {
+                Some(name) => Type::Class(name.to_string()),
+                None if namespace.is_empty() => Type::Class(text.to_string()),
+                None => Type::Class(format!("{}\\{}", namespace, text)),
+            },
+        })
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Float => write!(f, "float"),
+            Type::String => write!(f, "string"),
+            Type::Bool => write!(f, "bool"),
+            Type::Null => write!(f, "null"),
+            Type::Void => write!(f, "void"),
+            Type::Mixed => write!(f, "mixed"),
+            Type::Array(None) => write!(f, "array"),
+            Type::Array(Some(value)) => match **value {
+                Type::Union(_) => write!(f, "({})[]", value),
+                _ => write!(f, "{}[]", value),
+            },
+            Type::Shape(entries) => {
+                let entries: Vec<String> = entries
+                    .iter()
+                    .map(|(key, ty)| format!("{}: {}", key, ty))
+                    .collect();
+                write!(f, "array{{{}}}", entries.join(", "))
+            }
+            Type::Class(name) => write!(f, "{}", name.rsplit('\\').next().unwrap_or(name)),
+            Type::Union(members) => match members.as_slice() {
+                [ty, Type::Null] | [Type::Null, ty] => write!(f, "?{}", ty),
+                _ => {
+                    let members: Vec<String> = members.iter().map(Type::to_string).collect();
+                    write!(f, "{}", members.join("|"))
+                }
+            },
+        }
+    }
+}
+
+/**
+ * Works out the types of expressions in a file.
+ *
+ * This is deliberately simple: variables have the type of the last assignment to them that comes
+ * before where they're used, regardless of branches and loops, and calls have the return type
+ * that their callee declares. Anything we can't tell is `None` rather than a guess.
+ */
+pub struct TypeInference<'a> {
+    contents: &'a str,
+    resolver: &'a Resolver<'a>,
+    /// When to stop working types out and take everything as `mixed`, if ever.
+    deadline: Option<Instant>,
+}
+
+impl<'a> TypeInference<'a> {
+    pub fn new(contents: &'a str, resolver: &'a Resolver<'a>) -> Self {
+        Self {
+            contents,
+            resolver,
+            deadline: None,
+        }
+    }
+
+    /// Take every expression as `mixed` once a deadline has passed.
+    pub fn with_deadline(self, deadline: Instant) -> Self {
+        Self {
+            deadline: Some(deadline),
+            ..self
+        }
+    }
+
+    /// Whether the deadline has passed, which makes every expression `mixed`.
+    pub fn is_out_of_time(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    fn text(&self, node: &Node) -> &'a str {
+        &self.contents[node.byte_range()]
+    }
+
+    /// The type of an expression.
+    pub fn expression(&self, node: &Node) -> Option<Type> {
+        self.expression_at_depth(node, 0)
+    }
+
+    /// What a call expression calls, also following variables whose class we know.
+    pub fn callee(&self, call: &Node) -> Option<&'a Declaration> {
+        self.callee_at_depth(call, 0)
+    }
+
+    /// The class of an object, or the class a scope like `self` or `Foo` names.
+    pub fn object_class(&self, node: &Node) -> Option<String> {
+        self.class_of(node, 0)
+    }
+
+    /// The type a function returns, from its return type or else its `return` statements.
+    pub fn function_return(&self, function: &Node) -> Option<Type> {
+        self.function_return_at_depth(function, 0)
+    }
+
+    /// The type of the values bound to the value variable of a `foreach`.
+    pub fn foreach_value(&self, foreach: &Node) -> Option<Type> {
+        self.foreach_value_at_depth(foreach, 0)
+    }
+
+    /**
+     * Whether a value of a type can be passed where a `callable` or `Closure` is expected: closures
+     * themselves, and objects of classes with an `__invoke` method.
+     *
+     * Strings and arrays can name callables too, but we can't tell whether they do.
+     */
+    pub fn is_callable(&self, ty: &Type) -> bool {
+        match ty {
+            Type::Class(class) => {
+                class.eq_ignore_ascii_case("Closure")
+                    || self.resolver.member(class, "__invoke").is_some()
+            }
+            Type::Union(members) => members.iter().all(|member| self.is_callable(member)),
+            _ => false,
+        }
+    }
+
+    /**
+     * Whether a value of one type can be used where another is expected, as far as we can tell.
+     * Ints can be used as floats, objects as any of their supertypes, and `mixed` goes anywhere.
+     */
+    pub fn accepts(&self, expected: &Type, actual: &Type) -> bool {
+        match (expected, actual) {
+            (Type::Mixed, _) | (_, Type::Mixed) => true,
+            (_, Type::Union(members)) => {
+                members.iter().all(|member| self.accepts(expected, member))
+            }
+            (Type::Union(members), _) => members.iter().any(|member| self.accepts(member, actual)),
+            (Type::Float, Type::Int) => true,
+            (Type::Array(_) | Type::Shape(_), Type::Array(_) | Type::Shape(_)) => true,
+            (Type::Class(class), _)
+                if class.eq_ignore_ascii_case("Closure") && self.is_callable(actual) =>
+            {
+                true
+            }
+            (Type::Class(expected), Type::Class(actual)) => {
+                self.resolver.is_subtype(actual, expected)
+            }
+            _ => expected == actual,
+        }
+    }
+
+    /**
+     * The type that an expression is expected to have where it's written: the type of the
+     * parameter it's passed to, the return type of the function it's returned from, or the type of
+     * the property it's assigned to. `None` where nothing in particular is expected.
+     */
+    pub fn expected(&self, node: &Node) -> Option<Type> {
+        let parent = node.parent()?;
+        match parent.kind() {
+            "parenthesized_expression" => self.expected(&parent),
+            "argument" => {
+                let arguments = parent.parent()?;
+                let call = arguments.parent()?;
+                let index = match parent.child_by_field_name("name") {
+                    Some(name) => {
+                        let name = self.text(&name);
+                        self.callee(&call)?
+                            .parameters()
+                            .iter()
+                            .position(|parameter| {
+                                parameter.trim_start_matches("...").trim_start_matches('$') == name
+                            })?
+                    }
+                    None => {
+                        let mut cursor = arguments.walk();
+                        let index = arguments
+                            .named_children(&mut cursor)
+                            .filter(|argument| argument.kind() == "argument")
+                            .position(|argument| argument == parent)?;
+                        index
+                    }
+                };
+                self.parameter_type(&call, index)
+            }
+            "return_statement" => self.enclosing_return(&parent),
+            "arrow_function" if parent.child_by_field_name("body") == Some(*node) => {
+                self.type_node(&parent.child_by_field_name("return_type")?)
+            }
+            "assignment_expression" if parent.child_by_field_name("right") == Some(*node) => {
+                let left = parent.child_by_field_name("left")?;
+                match left.kind() {
+                    "member_access_expression" => self.expression(&left),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /**
+     * The type of the parameter that the argument at an index of a call is passed to. Arguments
+     * past the last parameter go to it if it's variadic.
+     */
+    pub fn parameter_type(&self, call: &Node, argument: usize) -> Option<Type> {
+        let callee = self.callee(call)?;
+        let parameters = callee.parameters();
+        let index = match parameters.last() {
+            Some(last) if last.starts_with("...") => argument.min(parameters.len() - 1),
+            _ => argument,
+        };
+        Type::parse(
+            callee.parameter_type(index)?,
+            callee.namespace(),
+            callee.class_name(),
+        )
+    }
+
+    /// The return type declared by the function that a node is in.
+    pub fn enclosing_return(&self, node: &Node) -> Option<Type> {
+        let mut current = node.parent();
+        while let Some(parent) = current {
+            if FUNCTION_LIKES.contains(&parent.kind()) {
+                return self.type_node(&parent.child_by_field_name("return_type")?);
+            }
+            current = parent.parent();
+        }
+
+        None
+    }
+
+    /// The type that a declaration returns, as seen from the file the declaration is in.
+    fn declared_return(&self, declaration: &Declaration) -> Option<Type> {
+        if let Some(shape) = self.declared_shape(declaration) {
+            return Some(shape);
+        }
+        Type::parse(
+            declaration.return_type()?,
+            declaration.namespace(),
+            declaration.class_name(),
+        )
+    }
+
+    /**
+     * The type that a call returns: the one that users pinned its callee to, if they did, or else
+     * what the callee declares.
+     */
+    fn returned(&self, callee: &Declaration, call: &Node, depth: usize) -> Option<Type> {
+        match self.resolver.index().pinned_type(&callee.name) {
+            Some(PinnedType::Written(written)) => Type::parse(written, "", None),
+            Some(PinnedType::ArgumentClass(position)) => {
+                let arguments = call.child_by_field_name("arguments")?;
+                let argument = arguments.named_child(position)?.named_child(0)?;
+                self.named_class(&argument, depth)
+            }
+            None => self.declared_return(callee),
+        }
+    }
+
+    /// An object of the class that an expression like `User::class` or `'App\User'` names.
+    fn named_class(&self, node: &Node, depth: usize) -> Option<Type> {
+        match node.kind() {
+            "class_constant_access_expression" => {
+                let name = node.named_child(1)?;
+                if !self.text(&name).eq_ignore_ascii_case("class") {
+                    return None;
+                }
+                Some(Type::Class(self.class_of(&node.named_child(0)?, depth)?))
+            }
+            "string" | "encapsed_string" => {
+                let mut cursor = node.walk();
+                let is_literal = node
+                    .named_children(&mut cursor)
+                    .all(|part| matches!(part.kind(), "string_content" | "escape_sequence"));
+                let quoted = self.text(node);
+                let class = quoted.get(1..quoted.len() - 1)?.replace("\\\\", "\\");
+                is_literal.then(|| Type::Class(class.trim_start_matches('\\').to_string()))
+            }
+            _ => None,
+        }
+    }
+
+    /// The `#[ArrayShape]` of a declaration, which says more than its `array` type.
+    fn declared_shape(&self, declaration: &Declaration) -> Option<Type> {
+        let arguments = attribute_arguments(
+            declaration.attributes.iter().map(String::as_str),
+            "ArrayShape",
+        )?;
+        Type::shape(arguments, declaration.namespace(), declaration.class_name())
+    }
+
+    /// The `#[ArrayShape]` among the attributes of a parameter in this file.
+    fn parameter_shape(&self, parameter: &Node) -> Option<Type> {
+        let attributes = parameter.child_by_field_name("attributes")?;
+        let text = self.text(&attributes);
+        let (attributes, _) = strip_attributes(text);
+        let arguments = attribute_arguments(attributes, "ArrayShape")?;
+        let namespace = self
+            .resolver
+            .enclosing_class(parameter)
+            .and_then(|class| class.rsplit_once('\\'))
+            .map_or("", |(namespace, _)| namespace);
+        Type::shape(
+            arguments,
+            namespace,
+            self.resolver.enclosing_class(parameter),
+        )
+    }
+
+    /// A type written in this file, with class names resolved the way the file's symbols are.
+    pub fn type_node(&self, node: &Node) -> Option<Type> {
+        match node.kind() {
+            "optional_type" => Some(Type::union([
+                self.type_node(&node.named_child(0)?)?,
+                Type::Null,
+            ])),
+            "union_type" => {
+                let mut cursor = node.walk();
+                let members: Option<Vec<Type>> = node
+                    .named_children(&mut cursor)
+                    .map(|member| self.type_node(&member))
+                    .collect();
+                members.map(Type::union)
+            }
+            "named_type" => {
+                let name = node.named_child(0)?;
+                match self
+                    .resolver
+                    .reference_at(&to_position(&name.start_position()))
+                {
+                    Some(reference) => Some(Type::Class(reference.name.clone())),
+                    None => {
+                        let class = self.resolver.enclosing_class(node);
+                        Type::parse(self.text(&name), "", class)
+                    }
+                }
+            }
+            _ => Type::parse(self.text(node), "", self.resolver.enclosing_class(node)),
+        }
+    }
+
+    fn expression_at_depth(&self, node: &Node, depth: usize) -> Option<Type> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        if self.is_out_of_time() {
+            return Some(Type::Mixed);
+        }
+        let depth = depth + 1;
+
+        match node.kind() {
+            "integer" => Some(Type::Int),
+            "float" => Some(Type::Float),
+            "string" | "encapsed_string" | "heredoc" | "nowdoc" => Some(Type::String),
+            "boolean" => Some(Type::Bool),
+            "null" => Some(Type::Null),
+            "array_creation_expression" => {
+                let mut cursor = node.walk();
+                let values: Option<Vec<Type>> = node
+                    .named_children(&mut cursor)
+                    .filter(|element| element.kind() == "array_element_initializer")
+                    .map(|element| {
+                        // the value comes after the key, if there is one
+                        let value = element.named_child(element.named_child_count() - 1)?;
+                        self.expression_at_depth(&value, depth)
+                    })
+                    .collect();
+                let value = values
+                    .filter(|values| !values.is_empty())
+                    .map(Type::union)
+                    .filter(|value| !matches!(value, Type::Union(_)));
+                Some(Type::Array(value.map(Box::new)))
+            }
+            "object_creation_expression" => {
+                let mut cursor = node.walk();
+                let class = node
+                    .named_children(&mut cursor)
+                    .find(|child| matches!(child.kind(), "name" | "qualified_name"))?;
+                match self.text(&class).to_lowercase().as_str() {
+                    "self" | "static" => self
+                        .resolver
+                        .enclosing_class(node)
+                        .map(|class| Type::Class(class.to_string())),
+                    _ => self
+                        .resolver
+                        .reference_at(&to_position(&class.start_position()))
+                        .map(|reference| Type::Class(reference.name.clone())),
+                }
+            }
+            "anonymous_function" | "arrow_function" => Some(Type::Class("Closure".to_string())),
+            "clone_expression" | "parenthesized_expression" => {
+                self.expression_at_depth(&node.named_child(0)?, depth)
+            }
+            "assignment_expression" => {
+                self.expression_at_depth(&node.child_by_field_name("right")?, depth)
+            }
+            "cast_expression" => {
+                let cast = node.child_by_field_name("type")?;
+                match self.text(&cast).to_lowercase().as_str() {
+                    "int" | "integer" => Some(Type::Int),
+                    "float" | "double" | "real" => Some(Type::Float),
+                    "string" | "binary" => Some(Type::String),
+                    "bool" | "boolean" => Some(Type::Bool),
+                    "array" => Some(Type::Array(None)),
+                    "object" => Some(Type::Class("stdClass".to_string())),
+                    "unset" => Some(Type::Null),
+                    _ => None,
+                }
+            }
+            "class_constant_access_expression" => {
+                let name = node.named_child(1)?;
+                if self.text(&name).eq_ignore_ascii_case("class") {
+                    return Some(Type::String);
+                }
+                // enum cases are instances of their enum
+                let class = self.class_of(&node.named_child(0)?, depth)?;
+                let member = self.resolver.member(&class, self.text(&name))?;
+                match member.kind {
+                    DeclarationKind::EnumCase => Some(Type::Class(class)),
+                    DeclarationKind::Constant => Type::parse(
+                        member.constant_type()?,
+                        member.namespace(),
+                        member.class_name(),
+                    ),
+                    _ => None,
+                }
+            }
+            "member_access_expression" | "nullsafe_member_access_expression" => {
+                let class = self.class_of(&node.child_by_field_name("object")?, depth)?;
+                let name = node.child_by_field_name("name")?;
+                let property = self
+                    .resolver
+                    .member(&class, &format!("${}", self.text(&name)))?;
+                let pinned = self.resolver.index().pinned_type(&property.name);
+                let ty = match (pinned, self.declared_shape(property)) {
+                    (Some(PinnedType::Written(written)), _) => Type::parse(written, "", None)?,
+                    (_, Some(shape)) => shape,
+                    _ => Type::parse(
+                        property.property_type()?,
+                        property.namespace(),
+                        property.class_name(),
+                    )?,
+                };
+                match node.kind() {
+                    "nullsafe_member_access_expression" => Some(Type::union([ty, Type::Null])),
+                    _ => Some(ty),
+                }
+            }
+            "unary_op_expression" => {
+                let operator = node.child_by_field_name("operator")?;
+                match self.text(&operator) {
+                    "!" => Some(Type::Bool),
+                    "~" => Some(Type::Int),
+                    _ => self.expression_at_depth(&node.child_by_field_name("argument")?, depth),
+                }
+            }
+            "binary_expression" => self.binary(node, depth),
+            "conditional_expression" => {
+                let body = node
+                    .child_by_field_name("body")
+                    .or_else(|| node.child_by_field_name("condition"))?;
+                let alternative = node.child_by_field_name("alternative")?;
+                Some(Type::union([
+                    self.expression_at_depth(&body, depth)?,
+                    self.expression_at_depth(&alternative, depth)?,
+                ]))
+            }
+            "subscript_expression" => {
+                let array = self.expression_at_depth(&node.named_child(0)?, depth)?;
+                let key = node.named_child(1);
+                let literal = key.and_then(|key| match key.kind() {
+                    "integer" => Some(self.text(&key)),
+                    "string" | "encapsed_string" => {
+                        let content = key.named_child(0)?;
+                        (key.named_child_count() == 1 && content.kind() == "string_content")
+                            .then(|| self.text(&content))
+                    }
+                    _ => None,
+                });
+                match literal {
+                    Some(key) => array.without_null().key_type(key),
+                    None => array.without_null().value_type(),
+                }
+            }
+            "variable_name" => self.variable(node, depth),
+            "function_call_expression" | "member_call_expression" | "scoped_call_expression" => {
+                let callee = self.callee_at_depth(node, depth)?;
+                self.returned(callee, node, depth)
+            }
+            "nullsafe_member_call_expression" => {
+                let callee = self.callee_at_depth(node, depth)?;
+                Some(Type::union([
+                    self.returned(callee, node, depth)?,
+                    Type::Null,
+                ]))
+            }
+            _ => None,
+        }
+    }
+
+    fn binary(&self, node: &Node, depth: usize) -> Option<Type> {
+        let operator = node.child_by_field_name("operator")?;
+        let left = node.child_by_field_name("left")?;
+        let right = node.child_by_field_name("right")?;
+        match self.text(&operator).to_lowercase().as_str() {
+            "." => Some(Type::String),
+            "==" | "!=" | "<>" | "===" | "!==" | "<" | ">" | "<=" | ">=" | "&&" | "||" | "and"
+            | "or" | "xor" | "instanceof" => Some(Type::Bool),
+            "<=>" | "&" | "|" | "^" | "<<" | ">>" | "%" => Some(Type::Int),
+            "??" => Some(Type::union([
+                self.expression_at_depth(&left, depth)?.without_null(),
+                self.expression_at_depth(&right, depth)?,
+            ])),
+            "+" | "-" | "*" | "/" | "**" => {
+                let left = self.expression_at_depth(&left, depth)?;
+                let right = self.expression_at_depth(&right, depth)?;
+                match (left, right) {
+                    (Type::Int, Type::Int) if self.text(&operator) == "/" => {
+                        Some(Type::union([Type::Int, Type::Float]))
+                    }
+                    (Type::Int, Type::Int) => Some(Type::Int),
+                    (Type::Int | Type::Float, Type::Int | Type::Float) => Some(Type::Float),
+                    (Type::Array(_), Type::Array(_)) if self.text(&operator) == "+" => {
+                        Some(Type::Array(None))
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// The class of an object, or the class a scope like `self` or `Foo` names.
+    fn class_of(&self, node: &Node, depth: usize) -> Option<String> {
+        if let Some(class) = self.resolver.accessed_class(node) {
+            return Some(class.to_string());
+        }
+        match self.expression_at_depth(node, depth)?.without_null() {
+            Type::Class(class) => Some(class),
+            _ => None,
+        }
+    }
+
+    fn callee_at_depth(&self, call: &Node, depth: usize) -> Option<&'a Declaration> {
+        if let Some(callee) = self.resolver.callee(call) {
+            return Some(callee);
+        }
+
+        let object = match call.kind() {
+            // calling an object calls its `__invoke`
+            "function_call_expression" => {
+                let function = call.child_by_field_name("function")?;
+                return match self.expression_at_depth(&function, depth)?.without_null() {
+                    Type::Class(class) => self.resolver.member(&class, "__invoke"),
+                    _ => None,
+                };
+            }
+            "member_call_expression" | "nullsafe_member_call_expression" => {
+                call.child_by_field_name("object")?
+            }
+            "scoped_call_expression" => call.child_by_field_name("scope")?,
+            _ => return None,
+        };
+        let name = call.child_by_field_name("name")?;
+        match self.expression_at_depth(&object, depth)?.without_null() {
+            Type::Class(class) => self.resolver.member(&class, self.text(&name)),
+            _ => None,
+        }
+    }
+
+    fn function_return_at_depth(&self, function: &Node, depth: usize) -> Option<Type> {
+        if let Some(return_type) = function.child_by_field_name("return_type") {
+            return self.type_node(&return_type);
+        }
+
+        let body = function.child_by_field_name("body")?;
+        if function.kind() == "arrow_function" {
+            return self.expression_at_depth(&body, depth + 1);
+        }
+
+        let mut returns = vec![];
+        return_statements(&body, &mut returns);
+        if returns.is_empty() {
+            return Some(Type::Void);
+        }
+
+        let types: Option<Vec<Type>> = returns
+            .iter()
+            .map(|statement| match statement.named_child(0) {
+                Some(value) => self.expression_at_depth(&value, depth + 1),
+                None => Some(Type::Void),
+            })
+            .collect();
+        types.map(Type::union)
+    }
+
+    fn foreach_value_at_depth(&self, foreach: &Node, depth: usize) -> Option<Type> {
+        let iterated = foreach.named_child(0)?;
+        self.expression_at_depth(&iterated, depth + 1)?
+            .without_null()
+            .value_type()
+    }
+
+    /**
+     * What gives a variable the value it has where it's used: the latest assignment to it,
+     * `foreach` over values bound to it, or argument it's passed by reference as, or else the
+     * parameter it is.
+     */
+    fn source<'tree>(&self, variable: &Node<'tree>) -> Option<Node<'tree>> {
+        let name = self.text(variable);
+        // the latest thing that gives the variable a value wins
+        let mut latest: Option<(usize, Node)> = None;
+        let mut current = variable.parent();
+        while let Some(parent) = current {
+            if parent.kind() == "foreach_statement" {
+                let body = parent.child_by_field_name("body");
+                let is_value =
+                    foreach_variable(&parent).is_some_and(|value| self.text(&value) == name);
+                let in_body =
+                    body.is_some_and(|body| body.byte_range().contains(&variable.start_byte()));
+                // inner loops start later than the ones around them
+                if is_value && in_body && latest.is_none() {
+                    latest = Some((parent.start_byte(), parent));
+                }
+            }
+
+            if FUNCTION_LIKES.contains(&parent.kind()) || parent.kind() == "program" {
+                let mut assignments = vec![];
+                self.assignments(&parent, name, variable.start_byte(), &mut assignments);
+                if let Some(assignment) = assignments.last() {
+                    if latest.is_none_or(|(start, _)| start < assignment.start_byte()) {
+                        latest = Some((assignment.start_byte(), *assignment));
+                    }
+                }
+
+                if let Some((_, found)) = latest {
+                    return Some(found);
+                }
+                if let Some(parameter) = self.parameter(&parent, name) {
+                    return Some(parameter);
+                }
+
+                // only arrow functions can see the variables of the function they are in
+                if parent.kind() != "arrow_function" {
+                    return None;
+                }
+            }
+            current = parent.parent();
+        }
+
+        None
+    }
+
+    /**
+     * What last gave a variable the value it has where it's used: an assignment, a `foreach`, or
+     * a call it was passed to by reference as an argument. Parameters aren't given theirs in the
+     * function.
+     */
+    pub fn binding<'tree>(&self, variable: &Node<'tree>) -> Option<Node<'tree>> {
+        self.source(variable)
+            .filter(|source| !source.kind().ends_with("parameter"))
+    }
+
+    /// The type a variable has where it's used.
+    fn variable(&self, variable: &Node, depth: usize) -> Option<Type> {
+        if self.text(variable) == "$this" {
+            return self
+                .resolver
+                .enclosing_class(variable)
+                .map(|class| Type::Class(class.to_string()));
+        }
+
+        let source = self.source(variable)?;
+        match source.kind() {
+            "foreach_statement" => self.foreach_value_at_depth(&source, depth),
+            "argument" => self.out_argument(&source),
+            "assignment_expression" => {
+                self.expression_at_depth(&source.child_by_field_name("right")?, depth)
+            }
+            _ => {
+                let declared = match self.parameter_shape(&source) {
+                    Some(shape) => Some(shape),
+                    None => source
+                        .child_by_field_name("type")
+                        .and_then(|ty| self.type_node(&ty)),
+                };
+                match source.kind() {
+                    // a variadic parameter collects the arguments it's given into an array
+                    "variadic_parameter" => Some(Type::Array(declared.map(Box::new))),
+                    _ => declared,
+                }
+            }
+        }
+    }
+
+    /// The call that an argument is given to, and which argument of it it is.
+    fn argument_of<'tree>(&self, argument: &Node<'tree>) -> Option<(Node<'tree>, usize)> {
+        if argument.child_by_field_name("name").is_some() {
+            return None;
+        }
+        let arguments = argument.parent()?;
+        let call = arguments.parent()?;
+        let mut cursor = arguments.walk();
+        let position = arguments
+            .named_children(&mut cursor)
+            .filter(|argument| argument.kind() == "argument")
+            .position(|other| other.id() == argument.id())?;
+        Some((call, position))
+    }
+
+    /// Whether an argument is passed by reference, so that the call can give it a value.
+    fn is_out_argument(&self, argument: &Node) -> bool {
+        let Some((call, position)) = self.argument_of(argument) else {
+            return false;
+        };
+        let is_matches = call
+            .child_by_field_name("function")
+            .filter(|_| call.kind() == "function_call_expression")
+            .is_some_and(|function| is_matches_argument(self.text(&function), position));
+        is_matches
+            || self.resolver.callee(&call).is_some_and(|callee| {
+                let last = callee.parameters().len().saturating_sub(1);
+                callee.is_by_reference(position.min(last))
+            })
+    }
+
+    /**
+     * The type of what a call gives to an argument passed by reference: the matches that
+     * `preg_match` finds, say. What other functions give back isn't known.
+     */
+    fn out_argument(&self, argument: &Node) -> Option<Type> {
+        let (call, position) = self.argument_of(argument)?;
+        let function = call.child_by_field_name("function")?;
+        if call.kind() != "function_call_expression"
+            || !is_matches_argument(self.text(&function), position)
+        {
+            return None;
+        }
+        matches_type(&call, self.contents)
+    }
+
+    fn parameter<'tree>(&self, function: &Node<'tree>, name: &str) -> Option<Node<'tree>> {
+        let parameters = function.child_by_field_name("parameters")?;
+        let mut cursor = parameters.walk();
+        let parameter = parameters.named_children(&mut cursor).find(|parameter| {
+            parameter
+                .child_by_field_name("name")
+                .is_some_and(|parameter_name| self.text(&parameter_name) == name)
+        });
+        parameter
+    }
+
+    /// Assignments to a variable that end before a byte, in the order they start.
+    fn assignments<'tree>(
+        &self,
+        node: &Node<'tree>,
+        name: &str,
+        before: usize,
+        assignments: &mut Vec<Node<'tree>>,
+    ) {
+        let mut cursor = node.walk();
+        for child in node.children(&mut cursor) {
+            if child.start_byte() >= before {
+                break;
+            }
+            // the variables of nested functions are their own
+            if FUNCTION_LIKES.contains(&child.kind()) || child.kind().ends_with("class_declaration")
+            {
+                continue;
+            }
+
+            let is_match = match child.kind() {
+                "assignment_expression" => {
+                    child.end_byte() <= before
+                        && child
+                            .child_by_field_name("left")
+                            .is_some_and(|left| self.text(&left) == name)
+                }
+                "argument" => {
+                    child.end_byte() <= before
+                        && child
+                            .named_child(0)
+                            .is_some_and(|value| self.text(&value) == name)
+                        && self.is_out_argument(&child)
+                }
+                _ => false,
+            };
+            if is_match {
+                assignments.push(child);
+            }
+
+            self.assignments(&child, name, before, assignments);
+        }
+    }
+}
+
+/// The value variable of a `foreach`, if it is a plain variable.
+pub fn foreach_variable<'tree>(foreach: &Node<'tree>) -> Option<Node<'tree>> {
+    let binding = foreach.named_child(1)?;
+    let value = match binding.kind() {
+        "pair" => binding.named_child(binding.named_child_count() - 1)?,
+        _ => binding,
+    };
+    Some(value).filter(|value| value.kind() == "variable_name")
+}
+
+/// The `return` statements of a function, leaving out those of functions and classes in it.
+pub(crate) fn return_statements<'tree>(node: &Node<'tree>, returns: &mut Vec<Node<'tree>>) {
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        if child.kind() == "return_statement" {
+            returns.push(child);
+        } else if !FUNCTION_LIKES.contains(&child.kind()) && child.kind() != "class_declaration" {
+            return_statements(&child, returns);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::Url;
+    use tree_sitter::Node;
+
+    use super::{Type, TypeInference};
+    use crate::annotations::Annotations;
+    use crate::file::php_parser;
+    use crate::index::WorkspaceIndex;
+    use crate::resolve::Resolver;
+    use crate::symbols::file_symbols;
+
+    /// The types of the right-hand side of every assignment, in order.
+    fn assigned_types(source: &str) -> Vec<Option<String>> {
+        assigned_types_with(source, Annotations::default())
+    }
+
+    /// Like [`assigned_types`], with what users say about the workspace.
+    fn assigned_types_with(source: &str, annotations: Annotations) -> Vec<Option<String>> {
+        fn assignments<'tree>(node: &Node<'tree>, found: &mut Vec<Node<'tree>>) {
+            if node.kind() == "assignment_expression" {
+                found.push(*node);
+            }
+            let mut cursor = node.walk();
+            for child in node.children(&mut cursor) {
+                assignments(&child, found);
+            }
+        }
+
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(&Url::parse("file:///A.php").unwrap(), symbols.clone());
+        index.set_annotations(annotations);
+        let resolver = Resolver::new(source, &symbols, &index);
+        let types = TypeInference::new(source, &resolver);
+
+        let mut found = vec![];
+        assignments(&tree.root_node(), &mut found);
+        found
+            .iter()
+            .map(|assignment| {
+                types
+                    .expression(&assignment.child_by_field_name("right").unwrap())
+                    .map(|ty| ty.to_string())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_expressions() {
+        let source = "<?php
+$a = 1;
+$b = $a + 2.5;
+$c = [1, 2];
+$d = $a . 'x';
+$e = $unknown;
+$f = $a > 1 ? 'yes' : null;
+$g = (bool) $e;
+";
+        assert_eq!(
+            vec![
+                Some("int".to_string()),
+                Some("float".to_string()),
+                Some("int[]".to_string()),
+                Some("string".to_string()),
+                None,
+                Some("?string".to_string()),
+                Some("bool".to_string()),
+            ],
+            assigned_types(source)
+        );
+    }
+
+    #[test]
+    fn test_calls_and_variables() {
+        let source = "<?php
+namespace App;
+class User {
+    const int LIMIT = 10;
+    public function name(): string {}
+    public function friend(): ?User {}
+    public static function make(): static {}
+}
+function load(int $id): User {}
+function run(User $user) {
+    $a = $user->name();
+    $b = load(1)->friend();
+    $c = User::make();
+    foreach ([$c] as $u) {
+        $d = $u;
+    }
+    $e = $a;
+    $f = User::LIMIT;
+}
+function total(int ...$nums) {
+    $g = $nums;
+}
+function all(...$values) {
+    $h = $values;
+}
+";
+        assert_eq!(
+            vec![
+                Some("string".to_string()),
+                Some("?User".to_string()),
+                Some("User".to_string()),
+                Some("User".to_string()),
+                Some("string".to_string()),
+                Some("int".to_string()),
+                Some("int[]".to_string()),
+                Some("array".to_string()),
+            ],
+            assigned_types(source)
+        );
+    }
+
+    #[test]
+    fn test_invokable() {
+        let source = "<?php
+namespace App;
+class Handler {
+    public function __invoke(int $request): string {}
+}
+class Plain {}
+$handler = new Handler();
+$a = $handler(1);
+$b = (new Handler)(2);
+";
+        assert_eq!(
+            vec![
+                Some("Handler".to_string()),
+                Some("string".to_string()),
+                Some("string".to_string()),
+            ],
+            assigned_types(source)
+        );
+
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(&Url::parse("file:///A.php").unwrap(), symbols.clone());
+        let resolver = Resolver::new(source, &symbols, &index);
+        let types = TypeInference::new(source, &resolver);
+        assert!(types.is_callable(&Type::Class("App\\Handler".to_string())));
+        assert!(types.is_callable(&Type::Class("Closure".to_string())));
+        assert!(!types.is_callable(&Type::Class("App\\Plain".to_string())));
+        assert!(!types.is_callable(&Type::String));
+    }
+
+    #[test]
+    fn test_array_shapes() {
+        let source = "<?php
+namespace App;
+class User {}
+class Repository {
+    #[ArrayShape(['id' => 'int', 'owner' => User::class])]
+    public function find(): array {}
+    public function save(#[ArrayShape(['name' => 'string'])] array $row) {
+        $a = $row['name'];
+    }
+}
+$row = (new Repository)->find();
+$b = $row;
+$c = $row['id'];
+$d = $row['owner'];
+$e = $row['missing'];
+";
+        assert_eq!(
+            vec![
+                Some("string".to_string()),
+                Some("array{id: int, owner: User}".to_string()),
+                Some("array{id: int, owner: User}".to_string()),
+                Some("int".to_string()),
+                Some("User".to_string()),
+                None,
+            ],
+            assigned_types(source)
+        );
+    }
+
+    #[test]
+    fn test_enums() {
+        let source = "<?php
+namespace App;
+enum Suit: string {
+    case Hearts = 'H';
+}
+$a = Suit::Hearts;
+$b = Suit::tryFrom('H');
+$c = Suit::from('H')->value;
+$d = $a->name;
+$e = Suit::cases();
+$f = $b?->value;
+";
+        assert_eq!(
+            vec![
+                Some("Suit".to_string()),
+                Some("?Suit".to_string()),
+                Some("string".to_string()),
+                Some("string".to_string()),
+                Some("Suit[]".to_string()),
+                Some("?string".to_string()),
+            ],
+            assigned_types(source)
+        );
+    }
+
+    #[test]
+    fn test_expected() {
+        let source = "<?php
+namespace App;
+class Animal {}
+class Cat extends Animal {}
+class Shelter {
+    public ?Animal $pet;
+    public function adopt(Animal $animal, int ...$ids): float {
+        $this->pet = new Cat();
+        take(count: 1, name: 'x');
+        return (1);
+    }
+}
+function take(string $name, int $count) {}
+$shelter = new Shelter();
+$shelter->adopt(new Cat(), 1, 2);
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(&Url::parse("file:///A.php").unwrap(), symbols.clone());
+        let resolver = Resolver::new(source, &symbols, &index);
+        let types = TypeInference::new(source, &resolver);
+
+        fn values<'tree>(node: &Node<'tree>, found: &mut Vec<Node<'tree>>) {
+            let is_value = matches!(
+                node.kind(),
+                "object_creation_expression" | "integer" | "string"
+            );
+            if is_value && node.is_named() {
+                found.push(*node);
+            }
+            let mut cursor = node.walk();
+            for child in node.children(&mut cursor) {
+                values(&child, found);
+            }
+        }
+        let mut found = vec![];
+        values(&tree.root_node(), &mut found);
+        let expected: Vec<Option<String>> = found
+            .iter()
+            .map(|value| types.expected(value).map(|ty| ty.to_string()))
+            .collect();
+        assert_eq!(
+            vec![
+                Some("?Animal".to_string()),
+                Some("int".to_string()),
+                Some("string".to_string()),
+                Some("float".to_string()),
+                None,
+                Some("Animal".to_string()),
+                Some("int".to_string()),
+                Some("int".to_string()),
+            ],
+            expected
+        );
+
+        let animal = Type::Class("App\\Animal".to_string());
+        let cat = Type::Class("App\\Cat".to_string());
+        assert!(types.accepts(&animal, &cat));
+        assert!(!types.accepts(&cat, &animal));
+        assert!(types.accepts(&Type::union([animal, Type::Null]), &Type::Null));
+        assert!(types.accepts(&Type::Float, &Type::Int));
+        assert!(!types.accepts(&Type::Int, &Type::union([Type::Int, Type::String])));
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Some(Type::union([
+                Type::Class("App\\Foo".to_string()),
+                Type::Null
+            ])),
+            Type::parse("?Foo", "App", None)
+        );
+        assert_eq!(
+            Some(Type::Class("Foo".to_string())),
+            Type::parse("\\Foo", "App", None)
+        );
+        assert_eq!(
+            Some(Type::union([Type::Int, Type::String])),
+            Type::parse("int|string", "", None)
+        );
+    }
+
+    #[test]
+    fn test_pinned_types() {
+        let source = "<?php
+namespace App;
+class User {}
+class Container {
+    public array $bindings;
+    public function get(string $id): object {}
+}
+function config(string $key): mixed {}
+$container = new Container();
+$a = $container->get(User::class);
+$b = $container->get('App\\\\User');
+$c = $container->get($id);
+$d = $container->bindings;
+$e = config('app.name');
+";
+        let annotations = Annotations::parse(
+            std::path::Path::new("/"),
+            r#"{
+                "types": {
+                    "App\\Container::get": "class-of-argument:0",
+                    "App\\Container::$bindings": "\\App\\User[]",
+                    "App\\config": "?string"
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            vec![
+                Some("Container".to_string()),
+                Some("User".to_string()),
+                Some("User".to_string()),
+                None,
+                Some("User[]".to_string()),
+                Some("?string".to_string()),
+            ],
+            assigned_types_with(source, annotations)
+        );
+    }
+}
@@ -0,0 +1,704 @@
+//! Names that refer to something that neither the workspace nor PHP declares.
+
+use lsp_types::*;
+use tree_sitter::Node;
+
+use crate::attributes::attribute_arguments;
+use crate::code_actions::QuickFix;
+use crate::file::{to_point, to_range};
+use crate::fuzzy::edit_distance;
+use crate::index::WorkspaceIndex;
+use crate::resolve::Resolver;
+use crate::symbols::{DeclarationKind, FileSymbols, Reference, ReferenceKind};
+use crate::types::TypeInference;
+
+/// What PHP parses like a call to a function, but aren't functions, so stubs don't declare them.
+const LANGUAGE_CONSTRUCTS: &[&str] = &["isset", "empty", "eval", "exit", "die", "list", "unset"];
+
+/// Types that are written like classes but aren't.
+const RESERVED_TYPES: &[&str] = &["object", "mixed", "iterable", "callable", "void", "never"];
+
+/// A function that every PHP has, for telling whether the stubs of built-in functions are indexed.
+const ALWAYS_DECLARED: &str = "strlen";
+
+/// How many characters a declared name can be off from an import for the import to be a typo of it.
+const MAX_TYPO_DISTANCE: usize = 2;
+
+/// Node kinds that access a member, whether that member is a method, and what it's accessed on.
+const MEMBER_ACCESSES: &[(&str, bool, &str)] = &[
+    ("member_call_expression", true, "object"),
+    ("nullsafe_member_call_expression", true, "object"),
+    ("scoped_call_expression", true, "scope"),
+    ("member_access_expression", false, "object"),
+    ("nullsafe_member_access_expression", false, "object"),
+    ("scoped_property_access_expression", false, "scope"),
+];
+
+/**
+ * References of a kind that are in code. Names in doc comments are left out, since they're often
+ * template types or other names that only mean something to tools.
+ */
+fn code_references<'a>(
+    root_node: &'a Node,
+    symbols: &'a FileSymbols,
+    kind: ReferenceKind,
+) -> impl Iterator<Item = &'a Reference> {
+    symbols.references.iter().filter(move |reference| {
+        let point = to_point(&reference.range.start);
+        reference.kind == kind
+            && root_node
+                .descendant_for_point_range(point, point)
+                .is_none_or(|node| node.kind() != "comment")
+    })
+}
+
+/**
+ * Whether a name could be of something built in: a global one. Without stubs, those can't be told
+ * apart from what's undefined, so they're left alone.
+ */
+fn could_be_built_in(name: &str, index: &WorkspaceIndex) -> bool {
+    !name.trim_start_matches('\\').contains('\\') && index.locate(ALWAYS_DECLARED).is_none()
+}
+
+fn diagnostic(range: Range, code: &str, message: String) -> Diagnostic {
+    Diagnostic {
+        range,
+        severity: Some(DiagnosticSeverity::WARNING),
+        code: Some(NumberOrString::String(code.to_string())),
+        source: Some("phplsp".to_string()),
+        message,
+        ..Diagnostic::default()
+    }
+}
+
+/**
+ * Report calls to functions that aren't declared anywhere we know of. Unqualified names count as
+ * declared if the global function they fall back to is.
+ *
+ * Without stubs for built-in functions, those can't be told apart from undefined ones, so only
+ * calls that can't be of a global function are reported.
+ */
+pub fn undefined_functions(
+    root_node: &Node,
+    symbols: &FileSymbols,
+    index: &WorkspaceIndex,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    for call in code_references(root_node, symbols, ReferenceKind::Function) {
+        let written = call.fallback.as_deref().unwrap_or(&call.name);
+        if LANGUAGE_CONSTRUCTS
+            .iter()
+            .any(|construct| construct.eq_ignore_ascii_case(written))
+            || could_be_built_in(written, index)
+        {
+            continue;
+        }
+        let declared = std::iter::once(&call.name)
+            .chain(&call.fallback)
+            .any(|name| index.locate(name).is_some());
+        if !declared {
+            diagnostics.push(diagnostic(
+                call.range,
+                "undefined-function",
+                format!("call to undefined function `{}()`", call.name),
+            ));
+        }
+    }
+}
+
+/**
+ * Report names of classes, interfaces, traits and enums that aren't declared anywhere we know of,
+ * wherever code uses them: `new`, types, `extends`, `implements`, `use` in a class, `instanceof`
+ * and `::`.
+ *
+ * Like with functions, global names aren't reported without stubs.
+ */
+pub fn undefined_classes(
+    root_node: &Node,
+    symbols: &FileSymbols,
+    index: &WorkspaceIndex,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    for reference in code_references(root_node, symbols, ReferenceKind::Class) {
+        let short_name = reference.name.rsplit('\\').next().unwrap_or_default();
+        if RESERVED_TYPES
+            .iter()
+            .any(|reserved| reserved.eq_ignore_ascii_case(short_name))
+            || could_be_built_in(&reference.name, index)
+        {
+            continue;
+        }
+        if index.locate(&reference.name).is_none() {
+            diagnostics.push(diagnostic(
+                reference.range,
+                "undefined-class",
+                format!("undefined class `{}`", reference.name),
+            ));
+        }
+    }
+}
+
+/// What a `use` of a type, like `use function`, imports.
+fn import_kinds(import_type: Option<&str>) -> &'static [DeclarationKind] {
+    match import_type {
+        Some("function") => &[DeclarationKind::Function],
+        Some("const") => &[DeclarationKind::Constant],
+        _ => &[
+            DeclarationKind::Class,
+            DeclarationKind::Interface,
+            DeclarationKind::Trait,
+            DeclarationKind::Enum,
+        ],
+    }
+}
+
+/// Whether anything is declared in a namespace, which `use` can import to qualify names with.
+fn is_namespace(name: &str, index: &WorkspaceIndex) -> bool {
+    let prefix = format!("{}\\", name.to_lowercase());
+    index
+        .declared_names()
+        .any(|declared| declared.starts_with(&prefix))
+}
+
+/**
+ * The declared name of one of some kinds that a name is most likely a typo of, if any is close
+ * enough. Short names are allowed fewer typos, so that they aren't taken for other short names.
+ */
+fn closest_name(name: &str, kinds: &[DeclarationKind], index: &WorkspaceIndex) -> Option<String> {
+    let allowed = MAX_TYPO_DISTANCE.min(name.chars().count() / 4);
+    let lowercase = name.to_lowercase();
+    index
+        .declared_names()
+        .filter(|declared| {
+            !declared.contains("::") && declared.len().abs_diff(lowercase.len()) <= allowed
+        })
+        .map(|declared| (edit_distance(&lowercase, declared), declared))
+        .filter(|(distance, _)| *distance <= allowed)
+        .filter_map(|(distance, declared)| {
+            let declaration = index.find_declaration(declared)?;
+            kinds
+                .contains(&declaration.kind)
+                .then(|| (distance, declaration.name.clone()))
+        })
+        .min()
+        .map(|(_, name)| name)
+}
+
+fn check_import(
+    declaration: &Node,
+    file_contents: &str,
+    index: &WorkspaceIndex,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let import_type = |node: &Node| node.child_by_field_name("type").map(|node| node.kind());
+    // group uses look like `use Prefix\{A, B as C};`
+    let mut cursor = declaration.walk();
+    let prefix = declaration
+        .named_children(&mut cursor)
+        .find(|child| child.kind() == "namespace_name")
+        .map(|prefix| file_contents[prefix.byte_range()].trim_start_matches('\\'));
+    let clauses = declaration
+        .child_by_field_name("body")
+        .unwrap_or(*declaration);
+
+    let mut cursor = clauses.walk();
+    for clause in clauses.named_children(&mut cursor) {
+        if clause.kind() != "namespace_use_clause" {
+            continue;
+        }
+        let alias = clause.child_by_field_name("alias");
+        let mut clause_cursor = clause.walk();
+        let Some(name_node) = clause
+            .named_children(&mut clause_cursor)
+            .find(|child| Some(*child) != alias)
+        else {
+            continue;
+        };
+
+        let written = &file_contents[name_node.byte_range()];
+        let name = match prefix {
+            Some(prefix) => format!("{}\\{}", prefix, written),
+            None => written.trim_start_matches('\\').to_string(),
+        };
+        let kinds = import_kinds(import_type(&clause).or(import_type(declaration)));
+        if could_be_built_in(&name, index)
+            || index.find_declaration(&name).is_some()
+            || (kinds.contains(&DeclarationKind::Class) && is_namespace(&name, index))
+        {
+            continue;
+        }
+
+        let range = to_range(&name_node.range());
+        let Some(closest) = closest_name(&name, kinds, index) else {
+            diagnostics.push(diagnostic(
+                range,
+                "unresolved-import",
+                format!(
+                    "import of undefined `{}`, which isn't in the workspace or `vendor`; is the \
+                    package that declares it installed?",
+                    name
+                ),
+            ));
+            continue;
+        };
+
+        let mut found = diagnostic(
+            range,
+            "unresolved-import",
+            format!(
+                "import of undefined `{}`; did you mean `{}`?",
+                name, closest
+            ),
+        );
+        // what's written in a group leaves out its prefix
+        let replacement = match prefix {
+            Some(prefix) => {
+                let prefix = format!("{}\\", prefix);
+                closest
+                    .get(..prefix.len())
+                    .filter(|start| start.eq_ignore_ascii_case(&prefix))
+                    .map(|_| closest[prefix.len()..].to_string())
+            }
+            None if written.starts_with('\\') => Some(format!("\\{}", closest)),
+            None => Some(closest.clone()),
+        };
+        if let Some(replacement) = replacement {
+            QuickFix {
+                title: format!("Change to `{}`", closest),
+                edits: vec![TextEdit::new(range, replacement)],
+                rename: None,
+            }
+            .attach(&mut found);
+        }
+        diagnostics.push(found);
+    }
+}
+
+fn imports(
+    node: &Node,
+    file_contents: &str,
+    index: &WorkspaceIndex,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    if node.kind() == "namespace_use_declaration" {
+        check_import(node, file_contents, index, diagnostics);
+        return;
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        imports(&child, file_contents, index, diagnostics);
+    }
+}
+
+/**
+ * Report `use` statements that import what isn't declared anywhere we know of, which includes
+ * what composer autoloads from `vendor`. Where something of the same kind has a close enough name,
+ * the import is likely a typo of it, which a fix corrects; otherwise the package that declares it
+ * likely isn't installed. Classes can be imported as namespaces too, for `Models\User` after
+ * `use App\Models;`, so namespaces that something is declared in are left alone.
+ *
+ * Like with functions, imports of global names aren't reported without stubs.
+ */
+pub fn unresolved_imports(
+    root_node: &Node,
+    file_contents: &str,
+    index: &WorkspaceIndex,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    imports(root_node, file_contents, index, diagnostics);
+}
+
+fn member_accesses<'tree>(node: &Node<'tree>, accesses: &mut Vec<Node<'tree>>) {
+    if MEMBER_ACCESSES
+        .iter()
+        .any(|(kind, ..)| *kind == node.kind())
+    {
+        accesses.push(*node);
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        member_accesses(&child, accesses);
+    }
+}
+
+/**
+ * Report methods and properties used on objects and classes whose class we know, but that neither
+ * it nor its ancestors declare.
+ *
+ * Nothing is reported when something up the line isn't indexed, or could have the member without
+ * declaring it: by `__call`, `__callStatic`, `__get` or `__set`, or by `@method`, `@property` or
+ * `@mixin` in its doc comment. Traits are left alone, since the classes using them declare what
+ * they use. Properties aren't reported on `stdClass`, which `(object)` casts make, or on classes
+ * marked `#[AllowDynamicProperties]`.
+ */
+pub fn undefined_members(
+    root_node: &Node,
+    file_contents: &str,
+    symbols: &FileSymbols,
+    index: &WorkspaceIndex,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let resolver = Resolver::new(file_contents, symbols, index);
+    let types = TypeInference::new(file_contents, &resolver);
+    let mut accesses = vec![];
+    member_accesses(root_node, &mut accesses);
+
+    for access in accesses {
+        let Some(&(_, is_method, receiver)) = MEMBER_ACCESSES
+            .iter()
+            .find(|(kind, ..)| *kind == access.kind())
+        else {
+            continue;
+        };
+        let (Some(object), Some(name)) = (
+            access.child_by_field_name(receiver),
+            access.child_by_field_name("name"),
+        ) else {
+            continue;
+        };
+        let text = &file_contents[name.byte_range()];
+        // properties are declared with their `$`, and only accessed with it after `::`
+        let member = match (is_method, name.kind()) {
+            (true, "name") => text.to_string(),
+            (false, "name") => format!("${}", text),
+            (false, "variable_name") if receiver == "scope" => text.to_string(),
+            _ => continue,
+        };
+
+        let Some(class) = types.object_class(&object) else {
+            continue;
+        };
+        if resolver.member(&class, &member).is_some() {
+            continue;
+        }
+        let declarations: Option<Vec<_>> = resolver
+            .ancestors(&class)
+            .iter()
+            .map(|ancestor| index.find_declaration(ancestor))
+            .collect();
+        let Some(declarations) = declarations else {
+            continue;
+        };
+        if declarations
+            .first()
+            .is_none_or(|declaration| declaration.kind == DeclarationKind::Trait)
+            || declarations
+                .iter()
+                .any(|declaration| declaration.magic_members)
+        {
+            continue;
+        }
+        // any property can be set on these, and read back
+        let dynamic = class.eq_ignore_ascii_case("stdClass")
+            || declarations.iter().any(|declaration| {
+                attribute_arguments(
+                    declaration.attributes.iter().map(String::as_str),
+                    "AllowDynamicProperties",
+                )
+                .is_some()
+            });
+        if dynamic && !is_method {
+            continue;
+        }
+        let magic: &[&str] = match (is_method, receiver) {
+            (true, "object") => &["__call"],
+            (true, _) => &["__call", "__callStatic"],
+            (false, "object") => &["__get", "__set"],
+            (false, _) => &[],
+        };
+        if magic
+            .iter()
+            .any(|magic| resolver.member(&class, magic).is_some())
+        {
+            continue;
+        }
+
+        let (code, message) = match is_method {
+            true => (
+                "undefined-method",
+                format!("undefined method `{}::{}()`", class, member),
+            ),
+            false => (
+                "undefined-property",
+                format!("undefined property `{}::{}`", class, member),
+            ),
+        };
+        diagnostics.push(diagnostic(to_range(&name.range()), code, message));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::*;
+
+    use super::{undefined_classes, undefined_functions, undefined_members, unresolved_imports};
+    use crate::code_actions::QuickFix;
+    use crate::file::php_parser;
+    use crate::index::WorkspaceIndex;
+    use crate::symbols::file_symbols;
+
+    #[test]
+    fn test_undefined_functions() {
+        let stubs = "<?php\nfunction strlen($string) {}\n";
+        let source = "<?php
+namespace App;
+function helper() {}
+helper();
+strlen('a');
+missing();
+\\Other\\missing();
+isset($a);
+/** @see documented() */
+";
+        let check = |files: &[(&str, &str)]| {
+            let mut index = WorkspaceIndex::default();
+            for (uri, source) in files {
+                let tree = php_parser().parse(source, None).unwrap();
+                let uri = Url::parse(uri).unwrap();
+                index.update_symbols(&uri, file_symbols(&tree.root_node(), source));
+            }
+            let tree = php_parser().parse(source, None).unwrap();
+            let symbols = file_symbols(&tree.root_node(), source);
+            let mut diagnostics = vec![];
+            undefined_functions(&tree.root_node(), &symbols, &index, &mut diagnostics);
+            diagnostics
+                .into_iter()
+                .map(|diagnostic| (diagnostic.range.start.line, diagnostic.message))
+                .collect::<Vec<(u32, String)>>()
+        };
+
+        assert_eq!(
+            vec![
+                (5, "call to undefined function `App\\missing()`".to_string()),
+                (
+                    6,
+                    "call to undefined function `Other\\missing()`".to_string()
+                ),
+            ],
+            check(&[("file:///stubs.php", stubs), ("file:///app.php", source)])
+        );
+        // without stubs, only what can't be global is reported
+        assert_eq!(
+            vec![(
+                6,
+                "call to undefined function `Other\\missing()`".to_string()
+            )],
+            check(&[("file:///app.php", source)])
+        );
+    }
+
+    #[test]
+    fn test_undefined_classes() {
+        let stubs = "<?php\nfunction strlen($string) {}\ninterface Countable {}\n";
+        let source = "<?php
+namespace App;
+trait Helpers {}
+/** @param T $item */
+class User extends Model implements \\Countable {
+    use Helpers, Missing;
+    function f(object $o, Group $g) {
+        return $o instanceof \\Countable ? new User() : Role::admin();
+    }
+}
+";
+        let check = |files: &[(&str, &str)]| {
+            let mut index = WorkspaceIndex::default();
+            for (uri, source) in files {
+                let tree = php_parser().parse(source, None).unwrap();
+                let uri = Url::parse(uri).unwrap();
+                index.update_symbols(&uri, file_symbols(&tree.root_node(), source));
+            }
+            let tree = php_parser().parse(source, None).unwrap();
+            let symbols = file_symbols(&tree.root_node(), source);
+            let mut diagnostics = vec![];
+            undefined_classes(&tree.root_node(), &symbols, &index, &mut diagnostics);
+            diagnostics
+                .into_iter()
+                .map(|diagnostic| (diagnostic.range.start.line, diagnostic.message))
+                .collect::<Vec<(u32, String)>>()
+        };
+
+        let found = vec![
+            (4, "undefined class `App\\Model`".to_string()),
+            (5, "undefined class `App\\Missing`".to_string()),
+            (6, "undefined class `App\\Group`".to_string()),
+            (7, "undefined class `App\\Role`".to_string()),
+        ];
+        assert_eq!(
+            found,
+            check(&[("file:///stubs.php", stubs), ("file:///app.php", source)])
+        );
+        // without stubs, `\\Countable` could be built in, but what's in `App` can't
+        assert_eq!(found, check(&[("file:///app.php", source)]));
+    }
+
+    #[test]
+    fn test_unresolved_imports() {
+        let stubs = "<?php\nfunction strlen($string) {}\n";
+        let library = "<?php
+namespace App\\Models;
+class User {}
+function helper() {}
+";
+        let source = "<?php
+namespace App;
+use App\\Modles\\User;
+use App\\Models;
+use Monolog\\Logger as Log;
+use function App\\Models\\helpr;
+use App\\Models\\{User as Member, Usr};
+use App\\Models\\Users;
+use Missing;
+";
+        let check = |files: &[(&str, &str)]| {
+            let mut index = WorkspaceIndex::default();
+            for (uri, source) in files {
+                let tree = php_parser().parse(source, None).unwrap();
+                let uri = Url::parse(uri).unwrap();
+                index.update_symbols(&uri, file_symbols(&tree.root_node(), source));
+            }
+            let tree = php_parser().parse(source, None).unwrap();
+            let mut diagnostics = vec![];
+            unresolved_imports(&tree.root_node(), source, &index, &mut diagnostics);
+            diagnostics
+                .into_iter()
+                .map(|diagnostic| {
+                    let fixes = QuickFix::all(&diagnostic)
+                        .into_iter()
+                        .flat_map(|fix| fix.edits)
+                        .map(|edit| edit.new_text)
+                        .collect();
+                    (diagnostic.range.start.line, diagnostic.message, fixes)
+                })
+                .collect::<Vec<(u32, String, Vec<String>)>>()
+        };
+
+        let mut found = check(&[("file:///stubs.php", stubs), ("file:///lib.php", library)]);
+        assert_eq!(
+            vec![
+                (
+                    2,
+                    "import of undefined `App\\Modles\\User`; did you mean `App\\Models\\User`?"
+                        .to_string(),
+                    vec!["App\\Models\\User".to_string()]
+                ),
+                (
+                    4,
+                    "import of undefined `Monolog\\Logger`, which isn't in the workspace or \
+                    `vendor`; is the package that declares it installed?"
+                        .to_string(),
+                    vec![]
+                ),
+                (
+                    5,
+                    "import of undefined `App\\Models\\helpr`; did you mean \
+                    `App\\Models\\helper`?"
+                        .to_string(),
+                    vec!["App\\Models\\helper".to_string()]
+                ),
+                (
+                    6,
+                    "import of undefined `App\\Models\\Usr`; did you mean `App\\Models\\User`?"
+                        .to_string(),
+                    vec!["User".to_string()]
+                ),
+                (
+                    7,
+                    "import of undefined `App\\Models\\Users`; did you mean `App\\Models\\User`?"
+                        .to_string(),
+                    vec!["App\\Models\\User".to_string()]
+                ),
+                (
+                    8,
+                    "import of undefined `Missing`, which isn't in the workspace or `vendor`; is \
+                    the package that declares it installed?"
+                        .to_string(),
+                    vec![]
+                ),
+            ],
+            found
+        );
+        // without stubs, what's global could be built in
+        found.pop();
+        assert_eq!(found, check(&[("file:///lib.php", library)]));
+    }
+
+    #[test]
+    fn test_undefined_members() {
+        let source = "<?php
+class Model {
+    public $id;
+    public function __get($name) {}
+    public static function find($id): static {}
+}
+/** @method void publish() */
+class Post extends Model {
+    public function save() {
+        $this->id;
+        $this->title;
+        $this->publish();
+        $this->delete();
+    }
+}
+class Comment {
+    public function save() {
+        $this->save();
+        $this->remove();
+        self::create();
+        $this->body;
+        Comment::$count;
+    }
+}
+function f(Comment $comment) {
+    $comment->update();
+    $comment?->author;
+    Post::find(1)->archive();
+    $unknown->anything();
+}
+class stdClass {}
+#[AllowDynamicProperties]
+class Bag {}
+class Sack extends Bag {}
+function g(Sack $sack) {
+    $o = new \\stdClass();
+    $o->x = 1;
+    echo $o->x;
+    $a = (object) ['k' => 1];
+    echo $a->k;
+    echo $sack->anything;
+    $sack->missing();
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(&Url::parse("file:///app.php").unwrap(), symbols.clone());
+        let mut diagnostics = vec![];
+        undefined_members(
+            &tree.root_node(),
+            source,
+            &symbols,
+            &index,
+            &mut diagnostics,
+        );
+
+        let found: Vec<(u32, String)> = diagnostics
+            .into_iter()
+            .map(|diagnostic| (diagnostic.range.start.line, diagnostic.message))
+            .collect();
+        assert_eq!(
+            vec![
+                (18, "undefined method `Comment::remove()`".to_string()),
+                (19, "undefined method `Comment::create()`".to_string()),
+                (20, "undefined property `Comment::$body`".to_string()),
+                (21, "undefined property `Comment::$count`".to_string()),
+                (25, "undefined method `Comment::update()`".to_string()),
+                (26, "undefined property `Comment::$author`".to_string()),
+                (41, "undefined method `Sack::missing()`".to_string()),
+            ],
+            found
+        );
+    }
+}
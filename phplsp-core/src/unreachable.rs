@@ -0,0 +1,298 @@
+//! Statements that can never run, because everything before them jumps away or stops.
+
+use lsp_types::*;
+use tree_sitter::Node;
+
+use std::ops::Range as Span;
+
+use crate::cfg::ControlFlowGraph;
+use crate::file::to_position;
+use crate::index::WorkspaceIndex;
+use crate::resolve::Resolver;
+use crate::symbols::{Declaration, FileSymbols};
+use crate::types::TypeInference;
+
+/// Node kinds with a control flow graph of their own. Arrow functions are only an expression.
+const FUNCTIONS: &[&str] = &[
+    "function_definition",
+    "method_declaration",
+    "anonymous_function",
+];
+
+/// Node kinds whose statements run one after another.
+const STATEMENT_LISTS: &[&str] = &[
+    "program",
+    "compound_statement",
+    "colon_block",
+    "case_statement",
+    "default_statement",
+];
+
+/// Statements that are never unreachable: declarations, which PHP hoists, and what isn't code.
+const NOT_RUN: &[&str] = &[
+    "function_definition",
+    "class_declaration",
+    "interface_declaration",
+    "trait_declaration",
+    "enum_declaration",
+    "namespace_definition",
+    "namespace_use_declaration",
+    "php_tag",
+    "comment",
+    "ERROR",
+];
+
+/// Where each statement and condition of a graph is, and whether it can run.
+fn reachability(cfg: &ControlFlowGraph) -> Vec<(Span<usize>, bool)> {
+    cfg.blocks
+        .iter()
+        .zip(cfg.reachable())
+        .flat_map(|(block, reachable)| {
+            block
+                .statements
+                .iter()
+                .map(move |statement| (statement.byte_range(), reachable))
+        })
+        .collect()
+}
+
+/// Whether a statement can run, going by what runs first in it, if anything in it is in the graph.
+fn can_run(statement: &Node, reachability: &[(Span<usize>, bool)]) -> Option<bool> {
+    reachability
+        .iter()
+        .filter(|(span, _)| {
+            statement.start_byte() <= span.start && span.end <= statement.end_byte()
+        })
+        .min_by_key(|(span, _)| span.start)
+        .map(|(_, reachable)| *reachable)
+}
+
+/**
+ * Find the runs of statements in a list that `unreachable` says can't run, and look for more in
+ * those that can. Functions in the code are left for their own graph.
+ */
+fn walk(
+    node: &Node,
+    unreachable: &dyn Fn(&Node) -> bool,
+    message: &str,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    if !STATEMENT_LISTS.contains(&node.kind()) {
+        let mut cursor = node.walk();
+        for child in node.named_children(&mut cursor) {
+            if !FUNCTIONS.contains(&child.kind()) {
+                walk(&child, unreachable, message, diagnostics);
+            }
+        }
+        return;
+    }
+
+    let value = node.child_by_field_name("value").map(|value| value.id());
+    let mut run: Option<(Node, Node)> = None;
+    let mut cursor = node.walk();
+    let statements = node
+        .named_children(&mut cursor)
+        .filter(|child| Some(child.id()) != value && !NOT_RUN.contains(&child.kind()));
+    for statement in statements {
+        if unreachable(&statement) {
+            run = Some((run.map_or(statement, |(first, _)| first), statement));
+            continue;
+        }
+        if let Some((first, last)) = run.take() {
+            diagnostics.push(diagnostic(&first, &last, message));
+        }
+        walk(&statement, unreachable, message, diagnostics);
+    }
+    if let Some((first, last)) = run {
+        diagnostics.push(diagnostic(&first, &last, message));
+    }
+}
+
+fn diagnostic(first: &Node, last: &Node, message: &str) -> Diagnostic {
+    Diagnostic {
+        range: Range {
+            start: to_position(&first.start_position()),
+            end: to_position(&last.end_position()),
+        },
+        severity: Some(DiagnosticSeverity::HINT),
+        code: Some(NumberOrString::String("unreachable".to_string())),
+        source: Some("phplsp".to_string()),
+        message: message.to_string(),
+        tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+        ..Diagnostic::default()
+    }
+}
+
+/// The file's own statements, and every function in it.
+pub(crate) fn functions<'tree>(node: &Node<'tree>, functions: &mut Vec<Node<'tree>>) {
+    if node.kind() == "program" || FUNCTIONS.contains(&node.kind()) {
+        functions.push(*node);
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        self::functions(&child, functions);
+    }
+}
+
+/// The code of a function, or the file's own statements.
+fn body<'tree>(function: &Node<'tree>) -> Option<Node<'tree>> {
+    match function.kind() {
+        "program" => Some(*function),
+        _ => function.child_by_field_name("body"),
+    }
+}
+
+/**
+ * Report statements that can't run: those after a `return`, `throw`, `exit`, `break`,
+ * `continue` or `goto` in the same block, and after branches that all do that.
+ */
+pub fn unreachable(root_node: &Node, file_contents: &str, diagnostics: &mut Vec<Diagnostic>) {
+    let mut found = vec![];
+    functions(root_node, &mut found);
+    for function in found {
+        let Some(body) = body(&function) else {
+            continue;
+        };
+        let reachability = reachability(&ControlFlowGraph::new(&function, file_contents));
+        let unreachable = |statement: &Node| can_run(statement, &reachability) == Some(false);
+        walk(&body, &unreachable, "unreachable code", diagnostics);
+    }
+}
+
+/// Whether a function or method says it never returns, like `function fail(): never`.
+pub(crate) fn never_returns(declaration: &Declaration) -> bool {
+    declaration
+        .signature
+        .rsplit_once(')')
+        .and_then(|(_, returns)| returns.trim_start().strip_prefix(':'))
+        .is_some_and(|returns| returns.trim().eq_ignore_ascii_case("never"))
+}
+
+/**
+ * Report statements that can't run because they come after a call of a function or method whose
+ * return type is `never`. Those that can't run anyway are left to [`unreachable`].
+ */
+pub fn unreachable_after_never(
+    root_node: &Node,
+    file_contents: &str,
+    symbols: &FileSymbols,
+    index: &WorkspaceIndex,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let resolver = Resolver::new(file_contents, symbols, index);
+    let types = TypeInference::new(file_contents, &resolver);
+    let calls_never = |expression: &Node| types.callee(expression).is_some_and(never_returns);
+
+    let mut found = vec![];
+    functions(root_node, &mut found);
+    for function in found {
+        let Some(body) = body(&function) else {
+            continue;
+        };
+        let with_never = reachability(&ControlFlowGraph::with_never_returning(
+            &function,
+            file_contents,
+            &calls_never,
+        ));
+        // only calls that never return make a difference
+        if with_never.iter().all(|(_, reachable)| *reachable) {
+            continue;
+        }
+        let without = reachability(&ControlFlowGraph::new(&function, file_contents));
+        let unreachable = |statement: &Node| {
+            can_run(statement, &with_never) == Some(false)
+                && can_run(statement, &without) == Some(true)
+        };
+        walk(
+            &body,
+            &unreachable,
+            "unreachable code, after a call that never returns",
+            diagnostics,
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::Url;
+
+    use super::{unreachable, unreachable_after_never};
+    use crate::file::php_parser;
+    use crate::index::WorkspaceIndex;
+    use crate::symbols::file_symbols;
+
+    #[test]
+    fn test_unreachable() {
+        let source = "<?php
+function f($items) {
+    foreach ($items as $item) {
+        if ($item) {
+            continue;
+            echo 'skipped';
+        }
+        break;
+        echo 'never';
+        echo 'either';
+    }
+    switch ($items) {
+        case 1:
+            return 1;
+            echo 'after return';
+        default:
+            throw new Exception();
+    }
+    echo 'after switch';
+}
+exit(0);
+echo 'done';
+function declared() {}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let mut diagnostics = vec![];
+        unreachable(&tree.root_node(), source, &mut diagnostics);
+
+        let found: Vec<(u32, u32)> = diagnostics
+            .into_iter()
+            .map(|diagnostic| (diagnostic.range.start.line, diagnostic.range.end.line))
+            .collect();
+        assert_eq!(vec![(21, 21), (5, 5), (8, 9), (14, 14), (18, 18)], found);
+    }
+
+    #[test]
+    fn test_unreachable_after_never() {
+        let source = "<?php
+function fail(string $message): never { throw new Exception($message); }
+class Guard {
+    public function deny(): never { fail('denied'); }
+    public function check($allowed) {
+        if (!$allowed) {
+            $this->deny();
+            echo 'denied';
+        }
+        fail('checked');
+        return true;
+        return false;
+    }
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let symbols = file_symbols(&tree.root_node(), source);
+        let mut index = WorkspaceIndex::default();
+        index.update_symbols(&Url::parse("file:///guard.php").unwrap(), symbols.clone());
+        let mut diagnostics = vec![];
+        unreachable_after_never(
+            &tree.root_node(),
+            source,
+            &symbols,
+            &index,
+            &mut diagnostics,
+        );
+
+        let found: Vec<(u32, u32)> = diagnostics
+            .into_iter()
+            .map(|diagnostic| (diagnostic.range.start.line, diagnostic.range.end.line))
+            .collect();
+        assert_eq!(vec![(7, 7), (10, 10)], found);
+    }
+}
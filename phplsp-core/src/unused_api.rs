@@ -0,0 +1,189 @@
+//! Public declarations that nothing outside their own file uses.
+
+use lsp_types::Url;
+
+use crate::index::WorkspaceIndex;
+use crate::resolve::Resolver;
+use crate::symbols::{Declaration, DeclarationKind, FileSymbols};
+
+/// Whether a name matches a pattern where `*` stands for anything, ignoring case.
+pub(crate) fn matches(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.trim_start_matches('\\').to_lowercase();
+    let name = name.to_lowercase();
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+        // no `*` at all
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+/// Whether a declaration can be used from another file at all.
+fn is_public(declaration: &Declaration) -> bool {
+    match declaration.kind {
+        DeclarationKind::Class
+        | DeclarationKind::Interface
+        | DeclarationKind::Trait
+        | DeclarationKind::Enum
+        | DeclarationKind::Function => true,
+        DeclarationKind::Method => {
+            !declaration.has_modifier("private") && !declaration.has_modifier("protected")
+        }
+        _ => false,
+    }
+}
+
+fn is_used_elsewhere(index: &WorkspaceIndex, uri: &Url, declaration: &Declaration) -> bool {
+    index
+        .references(declaration)
+        .iter()
+        .any(|(reference_uri, _)| *reference_uri != uri)
+}
+
+/**
+ * Public classes, functions and methods of the workspace that no other file refers to, in a stable
+ * order, leaving out vendored files.
+ *
+ * Some are used in ways that we can't see, so these aren't reported:
+ * - what matches one of the `entry_points`, like `App\Controller\*` for the controllers that
+ *   routes name in strings or config files
+ * - magic methods, which PHP calls itself
+ * - methods that override or implement another, which are called through it
+ * - members of a class that's reported already
+ *
+ * Members are matched on their name alone, so a method isn't reported while another with the same
+ * name is used elsewhere.
+ */
+pub fn unused_public_api<'a>(
+    index: &'a WorkspaceIndex,
+    entry_points: &[String],
+) -> Vec<(&'a Url, &'a Declaration)> {
+    let no_symbols = FileSymbols::default();
+    let resolver = Resolver::new("", &no_symbols, index);
+
+    // a file's declarations stay in order, so classes come before their members
+    let mut declarations: Vec<(&Url, &Declaration)> = index
+        .declarations()
+        .filter(|(uri, _)| !index.is_vendor(uri))
+        .collect();
+    declarations.sort_by_key(|(uri, _)| *uri);
+
+    let mut unused: Vec<(&Url, &Declaration)> = vec![];
+    for (uri, declaration) in declarations {
+        if !is_public(declaration)
+            || entry_points
+                .iter()
+                .any(|pattern| matches(pattern, &declaration.name))
+        {
+            continue;
+        }
+        if let Some(class) = declaration.class_name() {
+            let class_unused = unused
+                .iter()
+                .any(|(_, unused)| unused.name.eq_ignore_ascii_case(class));
+            if class_unused
+                || declaration.short_name().starts_with("__")
+                || resolver.overridden(declaration).is_some()
+            {
+                continue;
+            }
+        }
+        if !is_used_elsewhere(index, uri, declaration) {
+            unused.push((uri, declaration));
+        }
+    }
+
+    unused
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::Url;
+
+    use super::{matches, unused_public_api};
+    use crate::file::php_parser;
+    use crate::index::WorkspaceIndex;
+    use crate::symbols::file_symbols;
+
+    #[test]
+    fn test_matches() {
+        assert!(matches(
+            "App\\Controller\\*",
+            "App\\Controller\\Home::index"
+        ));
+        assert!(matches("*::handle", "App\\Kernel::handle"));
+        assert!(matches("\\app\\kernel", "App\\Kernel"));
+        assert!(!matches("App\\Kernel", "App\\Kernel::handle"));
+        assert!(!matches("*::handle", "App\\Kernel::handler"));
+    }
+
+    #[test]
+    fn test_unused_public_api() {
+        let files = [
+            (
+                "file:///lib.php",
+                "<?php
+namespace Lib;
+interface Shape { public function area(): float; }
+class Square implements Shape {
+    public function __construct(private float $side) {}
+    public function area(): float { return $this->side ** 2; }
+    public function perimeter(): float { return $this->side * 4; }
+    public function scale(float $by): static { return new static($this->side * $by); }
+    private function unused() {}
+}
+class Circle { public function radius() {} }
+function helper() { return (new Square(1))->perimeter(); }
+function used() {}
+",
+            ),
+            (
+                "file:///app.php",
+                "<?php
+use Lib\\Square;
+$square = new Square(2);
+echo $square->area();
+\\Lib\\used();
+",
+            ),
+        ];
+        let mut index = WorkspaceIndex::default();
+        for (uri, source) in files {
+            let tree = php_parser().parse(source, None).unwrap();
+            let symbols = file_symbols(&tree.root_node(), source);
+            index.update_symbols(&Url::parse(uri).unwrap(), symbols);
+        }
+
+        let unused = |entry_points: &[String]| {
+            unused_public_api(&index, entry_points)
+                .into_iter()
+                .map(|(_, declaration)| declaration.name.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec![
+                "Lib\\Shape",
+                "Lib\\Square::perimeter",
+                "Lib\\Square::scale",
+                "Lib\\Circle",
+                "Lib\\helper",
+            ],
+            unused(&[])
+        );
+        assert_eq!(
+            vec!["Lib\\Shape", "Lib\\Square::perimeter", "Lib\\Square::scale"],
+            unused(&["lib\\circle*".to_string(), "Lib\\help*".to_string()])
+        );
+    }
+}
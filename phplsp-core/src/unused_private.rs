@@ -0,0 +1,248 @@
+use lsp_types::*;
+use tree_sitter::Node;
+
+use std::collections::HashSet;
+
+use crate::file::to_range;
+
+/// Class-likes whose private members can only be used by their own code.
+const CLASSES: &[&str] = &["class_declaration", "enum_declaration"];
+
+/// Node kinds that access a member by a name written after `->` or `::`.
+const MEMBER_ACCESSES: &[&str] = &[
+    "member_access_expression",
+    "nullsafe_member_access_expression",
+    "member_call_expression",
+    "nullsafe_member_call_expression",
+    "scoped_call_expression",
+    "scoped_property_access_expression",
+];
+
+/// The names used in a class, and whether it uses a member whose name isn't written out.
+#[derive(Default)]
+struct Uses<'a> {
+    names: HashSet<&'a str>,
+    dynamic: bool,
+}
+
+impl<'a> Uses<'a> {
+    fn collect(&mut self, node: &Node, file_contents: &'a str) {
+        match node.kind() {
+            kind if MEMBER_ACCESSES.contains(&kind) => match node.child_by_field_name("name") {
+                Some(name) if name.kind() == "name" => {
+                    self.names.insert(&file_contents[name.byte_range()]);
+                }
+                Some(name) if name.kind() == "variable_name" && kind.starts_with("scoped") => {
+                    let name = &file_contents[name.byte_range()];
+                    self.names.insert(name.trim_start_matches('$'));
+                }
+                // `$this->{'name'}` is counted like any other string
+                Some(name) if name.kind() == "string" => {}
+                _ => self.dynamic = true,
+            },
+            "class_constant_access_expression" => {
+                if let Some(name) = node.named_child(1).filter(|name| name.kind() == "name") {
+                    self.names.insert(&file_contents[name.byte_range()]);
+                }
+            }
+            // callables like `[$this, 'method']`, and names given to attributes
+            "string_content" => {
+                self.names.insert(&file_contents[node.byte_range()]);
+            }
+            _ => {}
+        }
+
+        let mut cursor = node.walk();
+        for child in node.named_children(&mut cursor) {
+            self.collect(&child, file_contents);
+        }
+    }
+
+    fn uses(&self, name: &str, is_method: bool) -> bool {
+        match is_method {
+            true => self
+                .names
+                .iter()
+                .any(|used| used.eq_ignore_ascii_case(name)),
+            false => self.names.contains(name),
+        }
+    }
+}
+
+fn has_child(node: &Node, kind: &str) -> bool {
+    let mut cursor = node.walk();
+    let found = node.children(&mut cursor).any(|child| child.kind() == kind);
+    found
+}
+
+fn is_private(member: &Node, file_contents: &str) -> bool {
+    let mut cursor = member.walk();
+    let private = member.children(&mut cursor).any(|child| {
+        child.kind() == "visibility_modifier" && &file_contents[child.byte_range()] == "private"
+    });
+    private && !has_child(member, "attribute_list")
+}
+
+/// The private members of a class that have no attributes, as what they are and their name nodes.
+fn private_members<'tree>(
+    body: &Node<'tree>,
+    file_contents: &str,
+) -> Vec<(&'static str, Node<'tree>)> {
+    let mut members = vec![];
+    let mut cursor = body.walk();
+    for member in body.named_children(&mut cursor) {
+        match member.kind() {
+            "method_declaration" => {
+                if let Some(name) = member.child_by_field_name("name") {
+                    if is_private(&member, file_contents) {
+                        members.push(("method", name));
+                    }
+                }
+                // properties promoted from the constructor's parameters
+                let Some(parameters) = member.child_by_field_name("parameters") else {
+                    continue;
+                };
+                let mut cursor = parameters.walk();
+                for parameter in parameters.named_children(&mut cursor) {
+                    if parameter.kind() == "property_promotion_parameter"
+                        && is_private(&parameter, file_contents)
+                    {
+                        if let Some(name) = parameter.child_by_field_name("name") {
+                            members.push(("property", name));
+                        }
+                    }
+                }
+            }
+            "property_declaration" | "const_declaration" if is_private(&member, file_contents) => {
+                let mut cursor = member.walk();
+                for element in member.named_children(&mut cursor) {
+                    match element.kind() {
+                        "property_element" => {
+                            members.extend(
+                                element
+                                    .child_by_field_name("name")
+                                    .map(|name| ("property", name)),
+                            );
+                        }
+                        "const_element" => {
+                            members.extend(element.named_child(0).map(|name| ("constant", name)));
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    members
+}
+
+fn class_unused_private(class: &Node, file_contents: &str, diagnostics: &mut Vec<Diagnostic>) {
+    let Some(body) = class.child_by_field_name("body") else {
+        return;
+    };
+    // code of the traits it uses can use its private members too
+    if has_child(&body, "use_declaration") {
+        return;
+    }
+    let members = private_members(&body, file_contents);
+    if members.is_empty() {
+        return;
+    }
+
+    let mut uses = Uses::default();
+    uses.collect(&body, file_contents);
+    for (what, node) in members {
+        let written = &file_contents[node.byte_range()];
+        let name = written.trim_start_matches('$');
+        // magic methods are called by PHP, and dynamic accesses could be of anything else
+        if name.starts_with("__")
+            || (uses.dynamic && what != "constant")
+            || uses.uses(name, what == "method")
+        {
+            continue;
+        }
+        diagnostics.push(Diagnostic {
+            range: to_range(&node.range()),
+            severity: Some(DiagnosticSeverity::HINT),
+            code: Some(NumberOrString::String("unused-private-member".to_string())),
+            source: Some("phplsp".to_string()),
+            message: format!("private {} `{}` is never used", what, written),
+            tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+            ..Diagnostic::default()
+        });
+    }
+}
+
+/**
+ * Report private methods, properties and constants of classes and enums that their class never
+ * uses. Magic methods and members with attributes, which frameworks tend to use through
+ * reflection, are left alone, and so are classes that use traits, since the traits' code can use
+ * them. Names in strings count as uses, for callables like `[$this, 'method']` and names given to
+ * attributes.
+ */
+pub fn unused_private(node: &Node, file_contents: &str, diagnostics: &mut Vec<Diagnostic>) {
+    if CLASSES.contains(&node.kind()) {
+        class_unused_private(node, file_contents, diagnostics);
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        unused_private(&child, file_contents, diagnostics);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::unused_private;
+    use crate::file::php_parser;
+
+    #[test]
+    fn test_unused_private() {
+        let source = "<?php
+class Service {
+    private const USED = 1;
+    private const UNUSED = 2;
+    private static $cache;
+    private $unread, $sorted;
+    #[Inject]
+    private $logger;
+
+    public function __construct(private int $retries, private Clock $clock) {}
+
+    public function run() {
+        usort($this->sorted, [$this, 'compare']);
+        return self::USED + static::$cache + $this->retries + $this->Helper();
+    }
+
+    private function helper() {}
+    private function compare($a, $b) {}
+    private function unused() {}
+    private function __clone() {}
+}
+
+#[Attribute]
+class Route {
+    public function __construct(private $path) {}
+    private function dynamic($name) { return $this->$name; }
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let mut diagnostics = vec![];
+        unused_private(&tree.root_node(), source, &mut diagnostics);
+
+        let found: Vec<(u32, String)> = diagnostics
+            .into_iter()
+            .map(|diagnostic| (diagnostic.range.start.line, diagnostic.message))
+            .collect();
+        assert_eq!(
+            vec![
+                (3, "private constant `UNUSED` is never used".to_string()),
+                (5, "private property `$unread` is never used".to_string()),
+                (9, "private property `$clock` is never used".to_string()),
+                (18, "private method `unused` is never used".to_string()),
+            ],
+            found
+        );
+    }
+}
@@ -0,0 +1,154 @@
+use lsp_types::*;
+use tree_sitter::Node;
+
+use std::collections::HashSet;
+
+use crate::code_actions::QuickFix;
+use crate::dead_stores::{bound_variables, has_dynamic_scope, is_pure, removal, SUPERGLOBALS};
+use crate::file::to_range;
+use crate::scope::{variables, Variable, FUNCTIONS};
+
+/// The name that says a variable is there on purpose, like in `foreach ($xs as $_ => $x)`.
+const IGNORED: &str = "_";
+
+/**
+ * The statements to delete to remove a variable, if every one of its writes is a statement like
+ * `$a = ...;` that can't do anything but assign.
+ */
+fn removals(variable: &Variable, file_contents: &str) -> Option<Vec<TextEdit>> {
+    variable
+        .writes
+        .iter()
+        .map(|write| {
+            let assignment = write
+                .parent()
+                .filter(|parent| parent.kind() == "assignment_expression")?;
+            let statement = assignment
+                .parent()
+                .filter(|parent| parent.kind() == "expression_statement")?;
+            let right = assignment.child_by_field_name("right")?;
+            is_pure(&right, file_contents).then(|| TextEdit {
+                range: removal(&statement, file_contents),
+                new_text: String::new(),
+            })
+        })
+        .collect()
+}
+
+fn function_unused_variables(
+    function: &Node,
+    file_contents: &str,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    if has_dynamic_scope(function, file_contents) {
+        return;
+    }
+    let mut bound = HashSet::new();
+    bound_variables(function, file_contents, &mut bound);
+
+    let mut unused: Vec<(&str, Variable)> = variables(function, file_contents)
+        .into_iter()
+        .filter(|(name, variable)| {
+            variable.reads.is_empty()
+                && *name != IGNORED
+                && !bound.contains(name)
+                && !SUPERGLOBALS.contains(name)
+        })
+        .collect();
+    unused.sort_by_key(|(_, variable)| variable.writes.first().map(Node::start_byte));
+
+    for (name, variable) in unused {
+        let Some(first) = variable.writes.first() else {
+            continue;
+        };
+        let mut diagnostic = Diagnostic {
+            range: to_range(&first.range()),
+            severity: Some(DiagnosticSeverity::HINT),
+            code: Some(NumberOrString::String("unused-variable".to_string())),
+            source: Some("phplsp".to_string()),
+            message: format!("`${}` is assigned but never used", name),
+            tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+            ..Diagnostic::default()
+        };
+        if let Some(edits) = removals(&variable, file_contents) {
+            QuickFix {
+                title: format!("Remove `${}`", name),
+                edits,
+                rename: None,
+            }
+            .attach(&mut diagnostic);
+        }
+        diagnostics.push(diagnostic);
+    }
+}
+
+/**
+ * Report variables of functions that are assigned but never read. Variables named `$_`, those
+ * that are references, globals or statics, and those that a closure captures are left alone, and
+ * so are functions that could get at their variables by name.
+ */
+pub fn unused_variables(node: &Node, file_contents: &str, diagnostics: &mut Vec<Diagnostic>) {
+    if FUNCTIONS.contains(&node.kind()) {
+        function_unused_variables(node, file_contents, diagnostics);
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        unused_variables(&child, file_contents, diagnostics);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::unused_variables;
+    use crate::file::php_parser;
+
+    #[test]
+    fn test_unused_variables() {
+        let source = "<?php
+$top = 1;
+function f(&$out, $in) {
+    $out = 1;
+    $unused = 1;
+    $unused = bar();
+    foreach ($in as $_ => $item) {
+        $seen[] = $item;
+    }
+    $total = 0;
+    $total += 1;
+    $captured = 2;
+    $callback = function () use ($captured) {
+        $inner = 3;
+    };
+    static $count = 0;
+    return $callback;
+}
+function g() {
+    $a = 1;
+    extract(['a' => 2]);
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let mut diagnostics = vec![];
+        unused_variables(&tree.root_node(), source, &mut diagnostics);
+
+        let found: Vec<(u32, String, bool)> = diagnostics
+            .into_iter()
+            .map(|diagnostic| {
+                (
+                    diagnostic.range.start.line,
+                    diagnostic.message,
+                    diagnostic.data.is_some(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                (4, "`$unused` is assigned but never used".to_string(), false),
+                (7, "`$seen` is assigned but never used".to_string(), false),
+                (13, "`$inner` is assigned but never used".to_string(), true),
+            ],
+            found
+        );
+    }
+}
@@ -0,0 +1,244 @@
+use lsp_types::*;
+use tree_sitter::Node;
+
+use crate::config::{Config, UsageRule, UsageRules};
+use crate::file::to_range;
+
+/// Node kinds whose code runs when they're called, rather than where they're written.
+const FUNCTIONS: &[&str] = &[
+    "function_definition",
+    "method_declaration",
+    "anonymous_function",
+    "arrow_function",
+];
+
+/// Names that stop the script when they're called, or written on their own.
+const EXITS: &[&str] = &["exit", "die"];
+
+/// Node kinds that a name can be in as an expression of its own, like `or die`.
+const BARE_NAME_PARENTS: &[&str] = &[
+    "expression_statement",
+    "binary_expression",
+    "conditional_expression",
+];
+
+fn diagnostic(node: &Node, rule: &UsageRule, code: &str, message: &str) -> Diagnostic {
+    Diagnostic {
+        range: to_range(&node.range()),
+        severity: Some(rule.severity.into()),
+        code: Some(NumberOrString::String(code.to_string())),
+        source: Some("phplsp".to_string()),
+        message: message.to_string(),
+        ..Diagnostic::default()
+    }
+}
+
+/// Whether a node is a call of a function with one of some names.
+fn calls(node: &Node, file_contents: &str, names: &[&str]) -> bool {
+    let called = match node.kind() {
+        "function_call_expression" => node.child_by_field_name("function"),
+        // `die` without parentheses
+        "name"
+            if node
+                .parent()
+                .is_some_and(|parent| BARE_NAME_PARENTS.contains(&parent.kind())) =>
+        {
+            Some(*node)
+        }
+        _ => None,
+    };
+    called.is_some_and(|called| {
+        let called = file_contents[called.byte_range()].trim_start_matches('\\');
+        names.iter().any(|name| name.eq_ignore_ascii_case(called))
+    })
+}
+
+fn walk(
+    node: &Node,
+    file_contents: &str,
+    rules: &UsageRules,
+    in_function: bool,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    if let Some(rule) = &rules.exit {
+        let exits = node.kind() == "exit_statement" || calls(node, file_contents, EXITS);
+        if exits && in_function {
+            diagnostics.push(diagnostic(
+                node,
+                rule,
+                "exit",
+                "only entry scripts should stop with `exit` or `die`; throw an exception instead",
+            ));
+        }
+    }
+    if let Some(rule) = &rules.error_suppression {
+        if node.kind() == "error_suppression_expression" {
+            let at = node.child(0).unwrap_or(*node);
+            diagnostics.push(diagnostic(
+                &at,
+                rule,
+                "error-suppression",
+                "`@` hides every error of what it's in front of",
+            ));
+        }
+    }
+    if let Some(rule) = &rules.eval {
+        if calls(node, file_contents, &["eval"]) {
+            diagnostics.push(diagnostic(
+                node,
+                rule,
+                "eval",
+                "`eval` runs code that can't be checked",
+            ));
+        }
+    }
+
+    let in_function = in_function || FUNCTIONS.contains(&node.kind());
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        walk(&child, file_contents, rules, in_function, diagnostics);
+    }
+}
+
+/**
+ * Report uses of the language features that the rules that are turned on don't allow: `exit` and
+ * `die` in functions, the `@` operator, and `eval`.
+ */
+pub fn usage_rules(
+    root_node: &Node,
+    file_contents: &str,
+    rules: &UsageRules,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    walk(root_node, file_contents, rules, false, diagnostics);
+}
+
+/**
+ * Warn about calls of the functions that `disabledFunctions` says the environment the code runs in
+ * turns off. Unqualified calls in a namespace are taken to call the global function, as they do
+ * unless the namespace declares one of the same name.
+ */
+pub fn disabled_functions(
+    node: &Node,
+    file_contents: &str,
+    config: &Config,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    if config.disabled_functions.is_empty() {
+        return;
+    }
+    if node.kind() == "function_call_expression" {
+        let function = node
+            .child_by_field_name("function")
+            .filter(|function| matches!(function.kind(), "name" | "qualified_name"));
+        if let Some(function) = function {
+            let name = &file_contents[function.byte_range()];
+            if config.is_disabled(name) {
+                diagnostics.push(Diagnostic {
+                    range: to_range(&function.range()),
+                    severity: Some(DiagnosticSeverity::WARNING),
+                    code: Some(NumberOrString::String("disabled-function".to_string())),
+                    source: Some("phplsp".to_string()),
+                    message: format!(
+                        "`{}` is disabled by `disable_functions` where the code runs",
+                        name.trim_start_matches('\\')
+                    ),
+                    ..Diagnostic::default()
+                });
+            }
+        }
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        disabled_functions(&child, file_contents, config, diagnostics);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::*;
+
+    use super::{disabled_functions, usage_rules};
+    use crate::config::{Config, UsageRule, UsageRules};
+    use crate::file::php_parser;
+
+    #[test]
+    fn test_usage_rules() {
+        let source = "<?php
+$config = @include 'config.php';
+exit(main());
+function main() {
+    connect() or die('no database');
+    eval('return 1;');
+    exit;
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let found = |rules: &UsageRules| {
+            let mut diagnostics = vec![];
+            usage_rules(&tree.root_node(), source, rules, &mut diagnostics);
+            diagnostics
+                .into_iter()
+                .map(|diagnostic| {
+                    let code = match diagnostic.code {
+                        Some(NumberOrString::String(code)) => code,
+                        _ => String::new(),
+                    };
+                    (diagnostic.range.start.line, code)
+                })
+                .collect::<Vec<(u32, String)>>()
+        };
+
+        assert!(found(&UsageRules::default()).is_empty());
+        let all = UsageRules {
+            exit: Some(UsageRule::default()),
+            error_suppression: Some(UsageRule::default()),
+            eval: Some(UsageRule::default()),
+        };
+        assert_eq!(
+            vec![
+                (1, "error-suppression".to_string()),
+                (4, "exit".to_string()),
+                (5, "eval".to_string()),
+                (6, "exit".to_string()),
+            ],
+            found(&all)
+        );
+    }
+
+    #[test]
+    fn test_disabled_functions() {
+        let source = "<?php
+namespace App;
+exec('ls');
+\\SYSTEM('ls');
+Shell\\exec('ls');
+$exec = 'exec';
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let config = Config {
+            disabled_functions: vec!["exec,system".to_string()],
+            ..Config::default()
+        };
+        let mut diagnostics = vec![];
+        disabled_functions(&tree.root_node(), source, &config, &mut diagnostics);
+
+        assert_eq!(
+            vec![
+                (
+                    2,
+                    "`exec` is disabled by `disable_functions` where the code runs".to_string()
+                ),
+                (
+                    3,
+                    "`SYSTEM` is disabled by `disable_functions` where the code runs".to_string()
+                ),
+            ],
+            diagnostics
+                .into_iter()
+                .map(|diagnostic| (diagnostic.range.start.line, diagnostic.message))
+                .collect::<Vec<_>>()
+        );
+    }
+}
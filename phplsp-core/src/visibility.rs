@@ -0,0 +1,214 @@
+use lsp_types::*;
+use tree_sitter::Node;
+
+use crate::code_actions::QuickFix;
+use crate::config::Visibility;
+use crate::file::{to_position, to_range};
+
+/// Modifiers that PSR-12 puts before the visibility. Anything else goes after it.
+const LEADING_MODIFIERS: &[&str] = &["abstract_modifier", "final_modifier"];
+
+fn child_of_kind<'tree>(node: &Node<'tree>, kind: &str) -> Option<Node<'tree>> {
+    let mut cursor = node.walk();
+    let found = node
+        .children(&mut cursor)
+        .find(|child| child.kind() == kind);
+    found
+}
+
+/// Where the visibility of a member goes: after its attributes, `abstract` and `final`.
+fn visibility_position(member: &Node) -> Position {
+    let mut cursor = member.walk();
+    let after = member.children(&mut cursor).find(|child| {
+        child.kind() != "attribute_list" && !LEADING_MODIFIERS.contains(&child.kind())
+    });
+    to_position(&after.unwrap_or(*member).start_position())
+}
+
+fn diagnostic(range: Range, code: &str, message: String, fix: QuickFix) -> Diagnostic {
+    let mut diagnostic = Diagnostic {
+        range,
+        severity: Some(DiagnosticSeverity::INFORMATION),
+        code: Some(NumberOrString::String(code.to_string())),
+        source: Some("phplsp".to_string()),
+        message,
+        ..Diagnostic::default()
+    };
+    fix.attach(&mut diagnostic);
+    diagnostic
+}
+
+fn in_interface(member: &Node) -> bool {
+    member
+        .parent()
+        .and_then(|body| body.parent())
+        .is_some_and(|declaration| declaration.kind() == "interface_declaration")
+}
+
+/**
+ * Report methods and properties that don't say what their visibility is, and properties declared
+ * with `var`, as PSR-12 asks. Fixes declare them with the default visibility, except where only
+ * some visibilities are allowed: methods of interfaces are public, and abstract methods can't be
+ * private.
+ */
+pub fn visibility(
+    node: &Node,
+    file_contents: &str,
+    default: Visibility,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    match node.kind() {
+        "method_declaration" if child_of_kind(node, "visibility_modifier").is_none() => {
+            let visibility = match default {
+                _ if in_interface(node) => Visibility::Public,
+                Visibility::Private if child_of_kind(node, "abstract_modifier").is_some() => {
+                    Visibility::Protected
+                }
+                default => default,
+            };
+            let Some(name) = node.child_by_field_name("name") else {
+                return;
+            };
+            let position = visibility_position(node);
+            diagnostics.push(diagnostic(
+                to_range(&name.range()),
+                "visibility",
+                format!(
+                    "`{}` should say what its visibility is",
+                    &file_contents[name.byte_range()]
+                ),
+                QuickFix {
+                    title: format!("Add `{}`", visibility.keyword()),
+                    edits: vec![TextEdit {
+                        range: Range {
+                            start: position,
+                            end: position,
+                        },
+                        new_text: format!("{} ", visibility.keyword()),
+                    }],
+                    rename: None,
+                },
+            ));
+            return;
+        }
+        "property_declaration" if child_of_kind(node, "visibility_modifier").is_none() => {
+            let keyword = default.keyword();
+            if let Some(var) = child_of_kind(node, "var_modifier") {
+                diagnostics.push(diagnostic(
+                    to_range(&var.range()),
+                    "var",
+                    "properties should be declared with their visibility instead of `var`"
+                        .to_string(),
+                    QuickFix {
+                        title: format!("Replace `var` with `{}`", keyword),
+                        edits: vec![TextEdit {
+                            range: to_range(&var.range()),
+                            new_text: keyword.to_string(),
+                        }],
+                        rename: None,
+                    },
+                ));
+                return;
+            }
+
+            let Some(name) = child_of_kind(node, "property_element")
+                .and_then(|property| property.child_by_field_name("name"))
+            else {
+                return;
+            };
+            let position = visibility_position(node);
+            diagnostics.push(diagnostic(
+                to_range(&name.range()),
+                "visibility",
+                format!(
+                    "`{}` should say what its visibility is",
+                    &file_contents[name.byte_range()]
+                ),
+                QuickFix {
+                    title: format!("Add `{}`", keyword),
+                    edits: vec![TextEdit {
+                        range: Range {
+                            start: position,
+                            end: position,
+                        },
+                        new_text: format!("{} ", keyword),
+                    }],
+                    rename: None,
+                },
+            ));
+            return;
+        }
+        _ => {}
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        visibility(&child, file_contents, default, diagnostics);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::*;
+
+    use super::visibility;
+    use crate::code_actions::QuickFix;
+    use crate::config::Visibility;
+    use crate::file::php_parser;
+
+    #[test]
+    fn test_visibility() {
+        let source = "<?php
+class A {
+    var $a;
+    static $b = 1;
+    #[Pure]
+    function f() {}
+    public function ok() {}
+    abstract static function g();
+}
+interface I {
+    function h();
+}
+";
+        let tree = php_parser().parse(source, None).unwrap();
+        let mut diagnostics = vec![];
+        visibility(
+            &tree.root_node(),
+            source,
+            Visibility::Private,
+            &mut diagnostics,
+        );
+
+        let found: Vec<(u32, String, String, Position)> = diagnostics
+            .iter()
+            .map(|diagnostic| {
+                let fix = QuickFix::of(diagnostic).unwrap();
+                (
+                    diagnostic.range.start.line,
+                    diagnostic.message.clone(),
+                    fix.edits[0].new_text.clone(),
+                    fix.edits[0].range.start,
+                )
+            })
+            .collect();
+        let at = |line, character| Position { line, character };
+        let missing = |name: &str| format!("`{}` should say what its visibility is", name);
+        assert_eq!(
+            vec![
+                (
+                    2,
+                    "properties should be declared with their visibility instead of `var`"
+                        .to_string(),
+                    "private".to_string(),
+                    at(2, 4)
+                ),
+                (3, missing("$b"), "private ".to_string(), at(3, 4)),
+                (5, missing("f"), "private ".to_string(), at(5, 4)),
+                (7, missing("g"), "protected ".to_string(), at(7, 13)),
+                (10, missing("h"), "public ".to_string(), at(10, 4)),
+            ],
+            found
+        );
+    }
+}
@@ -0,0 +1,47 @@
+//! Golden files for `analyze_file`.
+//!
+//! Every `tests/golden/*.php` is analyzed and compared to the `.json` file next to it. Run with
+//! `UPDATE_GOLDEN=1` to write the current output instead, and review the diff before committing.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use phplsp_core::analysis::analyze_file;
+use phplsp_core::config::Config;
+
+#[test]
+fn test_golden_files() {
+    let update = env::var_os("UPDATE_GOLDEN").is_some();
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let mut sources: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "php"))
+        .collect();
+    sources.sort();
+    assert!(!sources.is_empty(), "no golden files in {}", dir.display());
+
+    let mut mismatches = vec![];
+    for source in sources {
+        let result = analyze_file(&fs::read_to_string(&source).unwrap(), &Config::default());
+        let actual = serde_json::to_string_pretty(&result).unwrap() + "\n";
+        let golden = source.with_extension("json");
+        if update {
+            fs::write(&golden, actual).unwrap();
+            continue;
+        }
+
+        match fs::read_to_string(&golden) {
+            Ok(expected) if expected == actual => {}
+            Ok(_) => mismatches.push(format!("{} does not match", golden.display())),
+            Err(_) => mismatches.push(format!("{} is missing", golden.display())),
+        }
+    }
+
+    assert!(
+        mismatches.is_empty(),
+        "{}\nrerun with UPDATE_GOLDEN=1 to accept the new output",
+        mismatches.join("\n")
+    );
+}
@@ -0,0 +1,28 @@
+<?php
+
+namespace App\Models;
+
+use App\Contracts\HasName;
+
+/**
+ * A person.
+ */
+class User implements HasName
+{
+    public const TABLE = 'users';
+
+    public function __construct(private string $name)
+    {
+    }
+
+    public function getName(): string
+    {
+        return $this->name;
+    }
+}
+
+enum Status
+{
+    case Active;
+    case Banned;
+}
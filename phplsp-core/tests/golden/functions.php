@@ -0,0 +1,10 @@
+<?php
+
+function greet(string $name): string
+{
+    return sprintf('Hello, %s', strtoupper($name));
+}
+
+echo greet(PHP_EOL);
+
+$broken = ;
@@ -0,0 +1,339 @@
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, Url};
+
+use serde::Serialize;
+
+use std::env;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use phplsp_core::annotations::Annotations;
+use phplsp_core::baseline::{Baseline, BASELINE_FILE};
+use phplsp_core::cache::{cache_path, IndexCache};
+use phplsp_core::config::Config;
+use phplsp_core::diagnostics::{
+    apply_project_rules, diagnostics, drop_baselined, drop_ignored, workspace_diagnostics,
+};
+use phplsp_core::file::Snapshot;
+use phplsp_core::index::{index_folder, index_stubs, WorkspaceIndex};
+use phplsp_core::parsers::parsers;
+use phplsp_core::suppressions::drop_suppressed;
+use phplsp_core::symbols::{file_symbols, FileSymbols};
+
+use crate::backend::STUBS_DIR;
+
+const USAGE: &str =
+    "usage: phplsp analyze [--stdin] [--config <options.json>] --filename <file.php>";
+const BASELINE_USAGE: &str = "usage: phplsp --generate-baseline [<dir>] [--config <options.json>]";
+
+/// What `phplsp analyze` prints.
+#[derive(Debug, Serialize)]
+struct Output {
+    uri: Url,
+    diagnostics: Vec<Diagnostic>,
+}
+
+/**
+ * The workspace a file belongs to: the closest directory above it with a `composer.json`, or the
+ * current directory if there is none.
+ */
+fn workspace_root(file: &Path, current_dir: &Path) -> PathBuf {
+    file.ancestors()
+        .skip(1)
+        .find(|dir| dir.join("composer.json").is_file())
+        .unwrap_or(current_dir)
+        .to_path_buf()
+}
+
+/**
+ * The settings to analyze with: those in a file of the same options that clients pass as
+ * `initializationOptions`, so that what's reported is what the editor reports, or the defaults.
+ */
+fn load_config(path: Option<&Path>) -> Result<Config, String> {
+    let Some(path) = path else {
+        return Ok(Config::default());
+    };
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("could not read `{}`: {}", path.display(), e))?;
+    serde_json::from_str(&contents).map_err(|e| format!("invalid `{}`: {}", path.display(), e))
+}
+
+/// Index the stubs of PHP and the workspace, reusing and then updating the cache of the workspace.
+fn index_workspace(root: &Path, config: &Config) -> WorkspaceIndex {
+    let mut index = WorkspaceIndex::default();
+    index.set_memory_budget(config.memory_budget());
+    match Annotations::load(root) {
+        Ok(Some(annotations)) => index.set_annotations(annotations),
+        Ok(None) => {}
+        Err(e) => eprintln!("{}", e),
+    }
+    match Baseline::load(root) {
+        Ok(Some(baseline)) => index.set_baseline(baseline),
+        Ok(None) => {}
+        Err(e) => eprintln!("{}", e),
+    }
+    index_stubs(&mut parsers().get(), Path::new(STUBS_DIR), &mut index);
+    let path = cache_path(root);
+    let cache = path.as_deref().map(IndexCache::load).unwrap_or_default();
+    let updated = index_folder(&mut parsers().get(), root, &cache, &mut index);
+    if let Some(path) = path {
+        if let Err(e) = updated.save(&path) {
+            eprintln!("could not save `{}`: {}", path.display(), e);
+        }
+    }
+
+    index
+}
+
+fn analyze(filename: &Path, source: String, config: &Config) -> Result<Output, String> {
+    let current_dir = env::current_dir().map_err(|e| e.to_string())?;
+    let filename = current_dir.join(filename);
+    let uri = Url::from_file_path(&filename)
+        .map_err(|_| format!("`{}` is not a valid path", filename.display()))?;
+
+    let mut index = index_workspace(&workspace_root(&filename, &current_dir), config);
+    let snapshot = Snapshot::parse(&mut parsers().get(), source)
+        .ok_or_else(|| format!("could not parse `{}`", filename.display()))?;
+    let root_node = snapshot.root_node();
+    // what was piped in replaces whatever is on disk
+    let symbols = file_symbols(&root_node, &snapshot.contents);
+    index.update_symbols(&uri, symbols.clone());
+
+    let mut diagnostics = reported(&uri, &snapshot, &symbols, &index, config);
+    drop_baselined(&uri, &index, &mut diagnostics);
+    Ok(Output { uri, diagnostics })
+}
+
+/**
+ * The diagnostics of a file, less those that users said not to report, but before the baseline,
+ * as the server works them out for a file that isn't open.
+ */
+fn reported(
+    uri: &Url,
+    snapshot: &Snapshot,
+    symbols: &FileSymbols,
+    index: &WorkspaceIndex,
+    config: &Config,
+) -> Vec<Diagnostic> {
+    let root_node = snapshot.root_node();
+    let mut diagnostics = diagnostics(&root_node, &snapshot.contents, config);
+    diagnostics.extend(workspace_diagnostics(
+        uri,
+        &root_node,
+        &snapshot.contents,
+        symbols,
+        index,
+        config,
+    ));
+    drop_ignored(uri, index, &mut diagnostics);
+    apply_project_rules(uri, index, &mut diagnostics);
+    drop_suppressed(&root_node, &snapshot.contents, &mut diagnostics);
+    diagnostics
+}
+
+/**
+ * `phplsp analyze`: analyze one file of a workspace without a language client, and print its
+ * diagnostics as JSON.
+ *
+ * With `--stdin` the file is read from stdin instead of from disk, so that unsaved or staged
+ * contents can be checked; `--filename` then only says where in the workspace it belongs. With
+ * `--config`, the settings are read from a file of `initializationOptions`. Exits with 1 if there
+ * are any errors.
+ */
+pub fn run(args: impl Iterator<Item = String>) -> ExitCode {
+    let mut stdin = false;
+    let mut filename = None;
+    let mut config = None;
+    let mut args = args;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--stdin" => stdin = true,
+            "--filename" => filename = args.next().map(PathBuf::from),
+            "--config" => config = args.next().map(PathBuf::from),
+            _ => {
+                eprintln!("unknown argument `{}`\n{}", arg, USAGE);
+                return ExitCode::from(2);
+            }
+        }
+    }
+    let Some(filename) = filename else {
+        eprintln!("{}", USAGE);
+        return ExitCode::from(2);
+    };
+    let config = match load_config(config.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::from(2);
+        }
+    };
+
+    let source = match stdin {
+        true => {
+            let mut source = String::new();
+            io::stdin().read_to_string(&mut source).map(|_| source)
+        }
+        false => fs::read_to_string(&filename),
+    };
+    let output = source
+        .map_err(|e| e.to_string())
+        .and_then(|source| analyze(&filename, source, &config));
+
+    match output {
+        Ok(output) => {
+            println!(
+                "{}",
+                serde_json::to_string(&output).expect("diagnostics are always serializable")
+            );
+            let has_errors = output
+                .diagnostics
+                .iter()
+                .any(|diagnostic| diagnostic.severity == Some(DiagnosticSeverity::ERROR));
+            match has_errors {
+                true => ExitCode::FAILURE,
+                false => ExitCode::SUCCESS,
+            }
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::from(2)
+        }
+    }
+}
+
+/**
+ * `phplsp --generate-baseline [<dir>]`: write the `.phplsp/baseline.json` of a workspace, the
+ * current directory unless another is given, with what every non-vendor file of it has now, so
+ * that only diagnostics that come after are reported. Any baseline it had is replaced. It takes
+ * `--config` like `phplsp analyze`, so that it counts what that reports.
+ */
+pub fn generate_baseline(args: impl Iterator<Item = String>) -> ExitCode {
+    let mut dir = None;
+    let mut config = None;
+    let mut args = args;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => config = args.next().map(PathBuf::from),
+            _ if dir.is_none() && !arg.starts_with("--") => dir = Some(arg),
+            _ => {
+                eprintln!("unknown argument `{}`\n{}", arg, BASELINE_USAGE);
+                return ExitCode::from(2);
+            }
+        }
+    }
+    let (root, config) =
+        match env::current_dir()
+            .map_err(|e| e.to_string())
+            .and_then(|current_dir| {
+                let config = load_config(config.as_deref())?;
+                Ok((current_dir.join(dir.unwrap_or_default()), config))
+            }) {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                eprintln!("{}", e);
+                return ExitCode::from(2);
+            }
+        };
+    let index = index_workspace(&root, &config);
+
+    let mut baseline = Baseline::new(&root);
+    let mut count = 0;
+    for uri in index.non_vendor_files() {
+        let Ok(path) = uri.to_file_path() else {
+            continue;
+        };
+        if !path.starts_with(&root) {
+            continue;
+        }
+        let Some(snapshot) = fs::read_to_string(&path)
+            .ok()
+            .and_then(|source| Snapshot::parse(&mut parsers().get(), source))
+        else {
+            continue;
+        };
+        let symbols = file_symbols(&snapshot.root_node(), &snapshot.contents);
+        let diagnostics = reported(&uri, &snapshot, &symbols, &index, &config);
+        count += diagnostics.len();
+        baseline.add(&path, &diagnostics);
+    }
+
+    match baseline.save() {
+        Ok(()) => {
+            println!(
+                "wrote {} diagnostics of {} files to `{}`",
+                count,
+                baseline.files.len(),
+                root.join(BASELINE_FILE).display()
+            );
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!(
+                "could not save `{}`: {}",
+                root.join(BASELINE_FILE).display(),
+                e
+            );
+            ExitCode::from(2)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tower_lsp::lsp_types::Url;
+
+    use std::fs;
+    use std::path::Path;
+
+    use super::{load_config, reported, workspace_root};
+    use phplsp_core::config::Config;
+    use phplsp_core::file::Snapshot;
+    use phplsp_core::index::WorkspaceIndex;
+    use phplsp_core::parsers::parsers;
+    use phplsp_core::symbols::file_symbols;
+
+    #[test]
+    fn test_workspace_root() {
+        let root = std::env::temp_dir().join("phplsp-test-workspace-root");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("project/src")).unwrap();
+        fs::write(root.join("project/composer.json"), "{}").unwrap();
+
+        assert_eq!(
+            root.join("project"),
+            workspace_root(&root.join("project/src/A.php"), Path::new("/elsewhere"))
+        );
+        assert_eq!(
+            Path::new("/elsewhere"),
+            workspace_root(&root.join("B.php"), Path::new("/elsewhere"))
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_load_config() {
+        let root = std::env::temp_dir().join("phplsp-test-load-config");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let path = root.join("options.json");
+        fs::write(&path, r#"{ "metrics": { "maxParameters": 1 } }"#).unwrap();
+
+        let source = "<?php function f($a, $b) {}";
+        let snapshot = Snapshot::parse(&mut parsers().get(), source.to_string()).unwrap();
+        let symbols = file_symbols(&snapshot.root_node(), &snapshot.contents);
+        let uri = Url::from_file_path(root.join("A.php")).unwrap();
+        let index = WorkspaceIndex::default();
+        let count = |config: &Config| reported(&uri, &snapshot, &symbols, &index, config).len();
+
+        let config = load_config(Some(&path)).unwrap();
+        assert_eq!(Some(1), config.metrics.max_parameters);
+        assert_eq!(count(&Config::default()) + 1, count(&config));
+        assert_eq!(Config::default(), load_config(None).unwrap());
+        fs::write(&path, "{").unwrap();
+        assert!(load_config(Some(&path)).is_err());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
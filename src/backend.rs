@@ -1,349 +1,922 @@
-use tower_lsp::jsonrpc::Result as LspResult;
+use tower_lsp::jsonrpc::{self, Result as LspResult};
+use tower_lsp::lsp_types::notification::Progress;
+use tower_lsp::lsp_types::request::WorkDoneProgressCreate;
 use tower_lsp::lsp_types::*;
 use tower_lsp::{Client, LanguageServer};
 
-use tree_sitter::{InputEdit, Node, Parser, Tree};
+use serde::Serialize;
 
 use tokio::sync::RwLock;
+use tokio::time;
 
 use std::collections::HashMap;
 use std::error::Error;
 use std::fs::File;
 use std::io::BufReader;
-use std::path::PathBuf;
-use std::str::FromStr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use phplsp_core::annotations::{Annotations, ANNOTATIONS_FILE};
+use phplsp_core::baseline::{Baseline, BASELINE_FILE};
+use phplsp_core::cache::{cache_path, clear_cache, IndexCache};
+use phplsp_core::call_hierarchy::{
+    incoming_calls, item_declaration, outgoing_calls, prepare_call_hierarchy,
+};
+use phplsp_core::code_actions::{quick_fixes, SymbolRename};
+use phplsp_core::code_lens::{code_lenses, resolve_code_lens};
+use phplsp_core::completion::completions;
+use phplsp_core::composer::{
+    autoload_namespaces, composer_diagnostics, is_composer_file, locked_packages,
+    package_completions, LOCK_FILE,
+};
+use phplsp_core::config::Config;
+use phplsp_core::constructs::construct_hover;
+use phplsp_core::diagnostics::{
+    apply_project_rules, diagnostics, drop_baselined, drop_ignored, workspace_diagnostics,
+    DiagnosticSupport,
+};
+use phplsp_core::docs::{declaration_doc, documentation};
+use phplsp_core::document_links::{autoload_class, document_links, LinkDirs};
+use phplsp_core::edits::{EditError, WorkspaceEditBuilder};
+use phplsp_core::file::{is_within, PositionEncoding, Snapshot};
+use phplsp_core::formatting::{format, format_on_type, format_range};
+use phplsp_core::index::{index_folder, index_stubs, IndexedFile, MemoryUsage, WorkspaceIndex};
+use phplsp_core::ini::ini_directive_hover;
+use phplsp_core::inlay_hints::inlay_hints;
+use phplsp_core::linked_editing::linked_editing_ranges;
+use phplsp_core::monikers::moniker;
+use phplsp_core::parsers::{parsers, PoolStats};
+use phplsp_core::php_namespace::PhpNamespace;
+use phplsp_core::rename::{
+    is_valid_namespace, mentions_namespace, move_classes, namespace_at, namespace_directories,
+    rename_namespace, rename_symbol,
+};
+use phplsp_core::resolve::Resolver;
+use phplsp_core::selection_ranges::selection_ranges;
+use phplsp_core::semantic_tokens::{self, TokenCache};
+use phplsp_core::suppressions::drop_suppressed;
+use phplsp_core::symbols::{Declaration, DeclarationKind, FileSymbols, ReferenceKind};
+use phplsp_core::type_hierarchy::{self, prepare_type_hierarchy, subtypes, supertypes};
+use phplsp_core::unused_api::unused_public_api;
+
+use crate::composer_files::ComposerFiles;
+use crate::documents::Documents;
+use crate::encoding::{Encode, Encoder};
+use crate::lifecycle::{Lifecycle, SHUTDOWN_TIMEOUT};
+use crate::notebooks::{
+    cell_diagnostics, DidChangeNotebookDocumentParams, DidCloseNotebookDocumentParams,
+    DidOpenNotebookDocumentParams, Notebooks,
+};
+
+pub const CHECK_WORKSPACE_COMMAND: &str = "phplsp.checkWorkspace";
+pub const CLEAR_CACHE_COMMAND: &str = "phplsp.clearCache";
+pub const UNUSED_API_COMMAND: &str = "phplsp.unusedPublicApi";
+
+/// Stubs of what PHP and its extensions declare, from the `phpstorm-stubs` submodule.
+pub const STUBS_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/phpstorm-stubs");
+
+/// Most symbols we send back for a `workspace/symbol` query.
+const WORKSPACE_SYMBOL_LIMIT: usize = 256;
 
-use crate::php_namespace::PhpNamespace;
+struct BackendData {
+    /// Shared with [`Backend`], so that requests on open files don't need the lock.
+    documents: Arc<Documents>,
+    ns_to_dir: HashMap<PhpNamespace, Vec<PathBuf>>,
 
-struct FileData {
-    contents: String,
-    tree: Tree,
-    version: i32,
+    config: Config,
+    workspace_folders: Vec<PathBuf>,
+    index: WorkspaceIndex,
+    /// The client asks for diagnostics with `textDocument/diagnostic` and `workspace/diagnostic`
+    /// instead of waiting for us to publish them.
+    pull_diagnostics: bool,
+    /// The client lets us register type hierarchy support once initialized. `lsp-types` has no
+    /// server capability for it, so that's the only way to tell clients we have it.
+    register_type_hierarchy: bool,
+    /// The client lets us register how documents are synced once initialized, which is the only
+    /// way to ask for notebooks, since `lsp-types` has no server capability for them either.
+    register_notebooks: bool,
+    /// The client takes versioned edits and can move files, as `documentChanges` of workspace
+    /// edits.
+    document_changes: bool,
+    /// The client can preview annotated edits, and ask before making those that need confirming.
+    change_annotations: bool,
+    /// The client lets us register which files to watch once initialized.
+    register_file_watchers: bool,
+    /// What the client can show of diagnostics, which they are made to fit.
+    diagnostic_support: DiagnosticSupport,
+    /// The workspace folders have been indexed, so the index has all their files.
+    indexed: bool,
 }
 
-fn range_plaintext(file_contents: &String, range: tree_sitter::Range) -> String {
-    file_contents[range.start_byte..range.end_byte].to_owned()
-}
+impl BackendData {
+    fn new(documents: Arc<Documents>) -> Self {
+        Self {
+            documents,
+            ns_to_dir: HashMap::new(),
+            config: Config::default(),
+            workspace_folders: vec![],
+            index: WorkspaceIndex::default(),
+            pull_diagnostics: false,
+            register_type_hierarchy: false,
+            register_notebooks: false,
+            document_changes: false,
+            change_annotations: false,
+            register_file_watchers: false,
+            diagnostic_support: DiagnosticSupport::default(),
+            indexed: false,
+        }
+    }
 
-fn to_position(point: &tree_sitter::Point) -> Position {
-    Position {
-        line: point.row as u32,
-        character: point.column as u32,
+    /**
+     * The directories that files may be read from, which in restricted mode are only the
+     * workspace folders and the stubs. Outside of it, any file may be read.
+     */
+    fn readable_dirs(&self) -> Option<Vec<PathBuf>> {
+        self.config.restricted.then(|| {
+            let mut dirs = self.workspace_folders.clone();
+            dirs.push(PathBuf::from(STUBS_DIR));
+            dirs
+        })
     }
-}
 
-fn to_point(position: &Position) -> tree_sitter::Point {
-    tree_sitter::Point {
-        row: position.line as usize,
-        column: position.character as usize,
+    fn may_read(&self, path: &Path) -> bool {
+        self.readable_dirs()
+            .is_none_or(|readable| is_within(path, &readable))
     }
-}
 
-fn to_range(range: &tree_sitter::Range) -> Range {
-    Range {
-        start: to_position(&range.start_point),
-        end: to_position(&range.end_point),
+    /// The current snapshot of a file, preferring the open copy over the indexed copy.
+    fn snapshot(&self, uri: &Url) -> Option<Snapshot> {
+        if let Some(snapshot) = self.documents.snapshot(uri) {
+            return Some(snapshot);
+        }
+
+        self.index.get(uri).map(|file| file.snapshot.clone())
     }
-}
 
-fn document_symbols_property_decl(
-    property_node: &Node,
-    file_contents: &String,
-) -> Option<DocumentSymbol> {
-    let mut cursor = property_node.walk();
-    if !cursor.goto_first_child() {
-        return None;
-    }
-
-    loop {
-        let kind = cursor.node().kind();
-        if kind == "property_element" {
-            cursor.goto_first_child();
-
-            return Some(DocumentSymbol {
-                name: range_plaintext(file_contents, cursor.node().range()),
-                detail: Some(range_plaintext(file_contents, property_node.range())),
-                kind: SymbolKind::PROPERTY,
-                tags: None,
-                deprecated: None,
-                range: to_range(&cursor.node().range()),
-                selection_range: to_range(&property_node.range()),
-                children: None,
-            });
+    /// Converts positions in any file to and from how the client counts columns.
+    fn encoder(&self) -> Encoder<'_> {
+        Encoder::new(self.documents.encoding(), |uri| {
+            self.snapshot(uri).map(|snapshot| snapshot.contents)
+        })
+    }
+
+    /**
+     * Diagnostics for a file. Those of open files that only need the file are memoized, so asking
+     * again is cheap.
+     */
+    fn file_diagnostics(&self, uri: &Url) -> Option<Vec<Diagnostic>> {
+        let snapshot = self.snapshot(uri)?;
+        let mut diagnostics = match self.documents.contains(uri) {
+            true => {
+                let mut diagnostics = self.documents.queries().diagnostics(uri).to_vec();
+                let symbols = self.documents.queries().symbols(uri);
+                diagnostics.extend(workspace_diagnostics(
+                    uri,
+                    &snapshot.root_node(),
+                    &snapshot.contents,
+                    &symbols,
+                    &self.index,
+                    &self.config,
+                ));
+                diagnostics
+            }
+            false => {
+                let mut diagnostics =
+                    diagnostics(&snapshot.root_node(), &snapshot.contents, &self.config);
+                if let Some(symbols) = self.index.symbols(uri) {
+                    diagnostics.extend(workspace_diagnostics(
+                        uri,
+                        &snapshot.root_node(),
+                        &snapshot.contents,
+                        symbols,
+                        &self.index,
+                        &self.config,
+                    ));
+                }
+                diagnostics
+            }
+        };
+        drop_ignored(uri, &self.index, &mut diagnostics);
+        apply_project_rules(uri, &self.index, &mut diagnostics);
+        drop_suppressed(&snapshot.root_node(), &snapshot.contents, &mut diagnostics);
+        drop_baselined(uri, &self.index, &mut diagnostics);
+        diagnostics.encode(uri, &mut self.encoder());
+        self.diagnostic_support.adapt(uri, &mut diagnostics);
+        Some(diagnostics)
+    }
+
+    /// The doc comment of a declaration, read from disk if its file was evicted from the index.
+    fn doc_comment(&self, declaration: &Declaration) -> Option<String> {
+        let (uri, _) = self.index.locate(&declaration.name)?;
+        let snapshot = match self.snapshot(uri) {
+            Some(snapshot) => snapshot,
+            None => {
+                let path = uri.to_file_path().ok().filter(|path| self.may_read(path))?;
+                let contents = std::fs::read_to_string(path).ok()?;
+                Snapshot::parse(&mut parsers().get(), contents)?
+            }
+        };
+        declaration_doc(&snapshot.root_node(), &snapshot.contents, declaration)
+    }
+
+    /// Make sure a closed file that was evicted from the index can be looked at again.
+    fn load(&mut self, uri: &Url) {
+        let readable = uri.to_file_path().is_ok_and(|path| self.may_read(&path));
+        if readable && !self.documents.contains(uri) {
+            self.index.reload(&mut parsers().get(), uri);
         }
+    }
 
-        if !cursor.goto_next_sibling() {
-            return None;
+    /// Edits in every file that rename a declaration and what refers to it.
+    fn rename_symbol(&mut self, symbol: &SymbolRename, builder: &mut WorkspaceEditBuilder) {
+        let Some((uri, declaration)) = self.index.locate(&symbol.name) else {
+            return;
+        };
+        let declaration = declaration.clone();
+        let mut uris: Vec<Url> = self
+            .index
+            .references(&declaration)
+            .into_iter()
+            .map(|(uri, _)| uri.clone())
+            .collect();
+        uris.push(uri.clone());
+        uris.sort();
+        uris.dedup();
+
+        for uri in uris {
+            self.load(&uri);
+            let (Some(snapshot), Some(symbols)) = (self.snapshot(&uri), self.index.symbols(&uri))
+            else {
+                continue;
+            };
+            let edits = rename_symbol(
+                &snapshot.root_node(),
+                &snapshot.contents,
+                &uri,
+                symbols,
+                &self.index,
+                &declaration,
+                &symbol.new_name,
+            );
+            builder.edit(&uri, edits.edits);
+            builder.guess(&uri, edits.guesses);
         }
     }
-}
 
-fn document_symbols_method_params_decl(
-    params: &Node,
-    file_contents: &String,
-) -> Vec<DocumentSymbol> {
-    let mut symbols = vec![];
-    let mut cursor = params.walk();
-    if !cursor.goto_first_child() {
-        return symbols;
-    }
-
-    loop {
-        let kind = cursor.node().kind();
-        if kind == "simple_parameter" {
-            if let Some(name_node) = cursor.node().child_by_field_name("name") {
-                symbols.push(DocumentSymbol {
-                    name: range_plaintext(file_contents, name_node.range()),
-                    detail: Some(range_plaintext(file_contents, cursor.node().range())),
-                    kind: SymbolKind::VARIABLE,
-                    tags: None,
-                    deprecated: None,
-                    range: to_range(&name_node.range()),
-                    selection_range: to_range(&cursor.node().range()),
-                    children: None,
-                });
-            }
+    /// A builder for the edits of a refactoring, annotated with what it does if the client can
+    /// preview them.
+    fn refactoring(&self, description: String) -> WorkspaceEditBuilder {
+        let mut builder = WorkspaceEditBuilder::new();
+        if self.change_annotations {
+            builder.annotate(description);
         }
+        builder
+    }
+
+    /**
+     * Check edits against the files as they are now and put them together, converted to how the
+     * client counts columns. Edits of open files carry their version when the client takes that.
+     */
+    fn build_edit(&self, builder: WorkspaceEditBuilder) -> Result<WorkspaceEdit, EditError> {
+        let mut edit = builder.build(
+            |uri| {
+                let snapshot = self.snapshot(uri)?;
+                Some((self.documents.version(uri), snapshot.contents))
+            },
+            self.document_changes,
+        )?;
+        self.encoder().workspace_edit(&mut edit);
+        Ok(edit)
+    }
 
-        if !cursor.goto_next_sibling() {
-            return symbols;
+    fn status(&self) -> Status {
+        Status {
+            indexed_files: self.index.len(),
+            loaded_files: self.index.loaded_len(),
+            open_files: self.documents.len(),
+            index_memory: self.index.memory_usage(),
+            open_file_memory: self.documents.memory_usage(),
+            memory_budget: self.config.memory_budget(),
+            parsers: parsers().stats(),
         }
     }
 }
 
-fn document_symbols_method_decl(method_node: &Node, file_contents: &String) -> Vec<DocumentSymbol> {
-    let mut symbols = vec![];
+/// Response to `phplsp/status`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Status {
+    indexed_files: usize,
+    /// Indexed files that still have their source and tree in memory.
+    loaded_files: usize,
+    open_files: usize,
+    /// Open files don't count towards the memory budget, since they can't be evicted.
+    index_memory: MemoryUsage,
+    open_file_memory: MemoryUsage,
+    memory_budget: usize,
+    parsers: PoolStats,
+}
 
-    if let Some(method_parameters_node) = method_node.child_by_field_name("parameters") {
-        symbols.extend(document_symbols_method_params_decl(
-            &method_parameters_node,
-            file_contents,
-        ));
-    }
+pub struct Backend {
+    client: Client,
 
-    symbols
+    documents: Arc<Documents>,
+    notebooks: Notebooks,
+    composer_files: ComposerFiles,
+    /// The last semantic tokens we sent for each open file.
+    semantic_tokens: TokenCache,
+    data: Arc<RwLock<BackendData>>,
+    lifecycle: Arc<Lifecycle>,
 }
 
-fn document_symbols_class_decl(class_node: &Node, file_contents: &String) -> Vec<DocumentSymbol> {
-    let mut symbols = vec![];
+impl Backend {
+    pub fn new(client: Client, lifecycle: Arc<Lifecycle>) -> Self {
+        let documents = Arc::new(Documents::default());
+        Self {
+            client,
+
+            documents: documents.clone(),
+            notebooks: Notebooks::default(),
+            composer_files: ComposerFiles::default(),
+            semantic_tokens: TokenCache::default(),
+            data: Arc::new(RwLock::new(BackendData::new(documents))),
+            lifecycle,
+        }
+    }
+
+    async fn read_composer_file(
+        &self,
+        composer_file: PathBuf,
+    ) -> Result<(), Box<dyn Error + Send>> {
+        let file = File::open(&composer_file).map_err(|e| Box::new(e) as Box<dyn Error + Send>)?;
+        // directories are relative to `composer.json`
+        let root = composer_file.parent().unwrap_or(&composer_file);
+        let reader = BufReader::new(file);
+
+        let v: serde_json::Value =
+            serde_json::from_reader(reader).map_err(|e| Box::new(e) as Box<dyn Error + Send>)?;
+        let mut data_guard = self.data.write().await;
+        for (namespace, mut paths) in autoload_namespaces(root, &v) {
+            paths.retain(|path| data_guard.may_read(path));
+            if !paths.is_empty() {
+                data_guard
+                    .ns_to_dir
+                    .entry(namespace)
+                    .or_default()
+                    .extend(paths);
+            }
+        }
+
+        Ok(())
+    }
 
-    if let Some(decl_list) = class_node.child_by_field_name("body") {
-        let mut cursor = decl_list.walk();
-        if !cursor.goto_first_child() {
-            return symbols;
+    async fn read_composer_files(&self, composer_files: Vec<PathBuf>) {
+        for path in composer_files {
+            if let Err(e) = self.read_composer_file(path).await {
+                self.client.log_message(MessageType::ERROR, e).await;
+            }
         }
+    }
 
-        loop {
-            let kind = cursor.node().kind();
-            if kind == "property_declaration" {
-                if let Some(prop_docsym) =
-                    document_symbols_property_decl(&cursor.node(), file_contents)
-                {
-                    symbols.push(prop_docsym);
+    /**
+     * Parse every PHP file in the workspace folders.
+     *
+     * Parsing happens off the async runtime and without holding the lock, so that requests on
+     * open files can still be answered while a large workspace is being indexed.
+     */
+    async fn index_workspace(&self) {
+        let (folders, memory_budget, restricted) = {
+            let data_guard = self.data.read().await;
+            (
+                data_guard.workspace_folders.clone(),
+                data_guard.config.memory_budget(),
+                data_guard.config.restricted,
+            )
+        };
+        let lifecycle = self.lifecycle.clone();
+        let indexed = tokio::task::spawn_blocking(move || {
+            let mut parser = parsers().get();
+            let mut index = WorkspaceIndex::default();
+            index.set_memory_budget(memory_budget);
+            let mut errors = vec![];
+            index_stubs(&mut parser, Path::new(STUBS_DIR), &mut index);
+            for folder in folders {
+                // what's left isn't worth waiting for, and would be saved half done
+                if lifecycle.is_shut_down() {
+                    break;
+                }
+                match Annotations::load(&folder) {
+                    Ok(Some(annotations)) => index.set_annotations(annotations),
+                    Ok(None) => {}
+                    Err(e) => errors.push(e),
+                }
+                match Baseline::load(&folder) {
+                    Ok(Some(baseline)) => index.set_baseline(baseline),
+                    Ok(None) => {}
+                    Err(e) => errors.push(e),
                 }
-            } else if kind == "{" || kind == "}" || kind == "comment" {
-                // ignore these
-            } else if kind == "method_declaration" {
-                if let Some(name_node) = cursor.node().child_by_field_name("name") {
-                    let children = document_symbols_method_decl(&cursor.node(), file_contents);
-                    let method_name = range_plaintext(file_contents, name_node.range());
-                    let kind = if &method_name == "__constructor" {
-                        SymbolKind::CONSTRUCTOR
-                    } else {
-                        SymbolKind::METHOD
-                    };
-                    symbols.push(DocumentSymbol {
-                        name: method_name,
-                        detail: None,
-                        kind,
-                        tags: None,
-                        deprecated: None,
-                        range: to_range(&name_node.range()),
-                        selection_range: to_range(&cursor.node().range()),
-                        children: Some(children),
-                    });
+                // the cache is outside of the workspace, so restricted mode goes without
+                let path = cache_path(&folder).filter(|_| !restricted);
+                let cache = path.as_deref().map(IndexCache::load).unwrap_or_default();
+                let updated = index_folder(&mut parser, &folder, &cache, &mut index);
+                if let Some(path) = path {
+                    if let Err(e) = updated.save(&path) {
+                        errors.push(format!("could not save `{}`: {}", path.display(), e));
+                    }
                 }
-            } else {
-                unimplemented!("{}", kind);
             }
 
-            if !cursor.goto_next_sibling() {
-                break;
+            (index, errors)
+        })
+        .await;
+
+        match indexed {
+            Ok((index, errors)) => {
+                for error in errors {
+                    self.client.log_message(MessageType::WARNING, error).await;
+                }
+
+                let data_guard = &mut *self.data.write().await;
+                data_guard.index = index;
+                data_guard.indexed = !self.lifecycle.is_shut_down();
+                // files opened while we were indexing are more recent than what's on disk
+                for uri in self.documents.uris() {
+                    let symbols = self.documents.queries().symbols(&uri);
+                    data_guard
+                        .index
+                        .update_symbols(&uri, FileSymbols::clone(&symbols));
+                }
+
+                self.client
+                    .log_message(
+                        MessageType::LOG,
+                        format!(
+                            "indexed {} files with {}",
+                            data_guard.index.len(),
+                            parsers().stats()
+                        ),
+                    )
+                    .await;
+            }
+            Err(e) => {
+                self.client
+                    .log_message(
+                        MessageType::ERROR,
+                        format!("could not index workspace: {}", e),
+                    )
+                    .await;
             }
         }
     }
 
-    symbols
-}
+    /**
+     * Save the index of every workspace folder, so that the next run only parses what changes
+     * from now on. Open files are left out, since they may not be saved.
+     */
+    async fn save_caches(&self) {
+        let data = self.data.clone();
+        let saved = tokio::task::spawn_blocking(move || {
+            let data_guard = data.blocking_read();
+            let mut errors = vec![];
+            // an index that isn't done would throw away what the cache has of the rest
+            if !data_guard.indexed || data_guard.config.restricted {
+                return errors;
+            }
+            for folder in &data_guard.workspace_folders {
+                let Some(path) = cache_path(folder) else {
+                    continue;
+                };
+                let cache = data_guard
+                    .index
+                    .folder_cache(folder, |uri| data_guard.documents.contains(uri));
+                if let Err(e) = cache.save(&path) {
+                    errors.push(format!("could not save `{}`: {}", path.display(), e));
+                }
+            }
 
-fn document_symbols(root_node: &Node, file_contents: &String) -> Vec<DocumentSymbol> {
-    let mut ret = Vec::new();
-    let mut cursor = root_node.walk();
-
-    if !cursor.goto_first_child() {
-        return ret;
-    }
-
-    loop {
-        let kind = cursor.node().kind();
-        // DFS
-        if kind == "class_declaration" {
-            if let Some(name_node) = cursor.node().child_by_field_name("name") {
-                let children = document_symbols_class_decl(&cursor.node(), file_contents);
-                ret.push(DocumentSymbol {
-                    name: range_plaintext(file_contents, name_node.range()),
-                    detail: None,
-                    kind: SymbolKind::CLASS,
-                    tags: None,
-                    deprecated: None,
-                    range: to_range(&name_node.range()),
-                    selection_range: to_range(&cursor.node().range()),
-                    children: Some(children),
-                });
+            errors
+        });
+
+        match time::timeout(SHUTDOWN_TIMEOUT, saved).await {
+            Ok(Ok(errors)) => {
+                for error in errors {
+                    self.client.log_message(MessageType::WARNING, error).await;
+                }
+            }
+            Ok(Err(e)) => {
+                self.client
+                    .log_message(
+                        MessageType::ERROR,
+                        format!("could not save the index: {}", e),
+                    )
+                    .await;
+            }
+            Err(_) => {
+                self.client
+                    .log_message(
+                        MessageType::WARNING,
+                        format!("gave up saving the index after {:?}", SHUTDOWN_TIMEOUT),
+                    )
+                    .await;
             }
         }
+    }
 
-        if !cursor.goto_next_sibling() {
-            break;
+    async fn publish_file_diagnostics(&self, uri: &Url) {
+        let data_guard = self.data.read().await;
+        if data_guard.pull_diagnostics {
+            return;
+        }
+
+        let version = self.documents.version(uri);
+        let diagnostics = data_guard.file_diagnostics(uri);
+        drop(data_guard);
+
+        let Some(diagnostics) = diagnostics else {
+            return;
+        };
+        match self.notebooks.cells(uri) {
+            Some(cells) => {
+                for (cell, lines) in cells {
+                    self.client
+                        .publish_diagnostics(cell, cell_diagnostics(&diagnostics, &lines), None)
+                        .await;
+                }
+            }
+            None => {
+                self.client
+                    .publish_diagnostics(uri.clone(), diagnostics, version)
+                    .await;
+            }
         }
     }
 
-    ret
-}
+    /// Problems in an open `composer.json`, with positions in the client's encoding.
+    fn composer_file_diagnostics(
+        &self,
+        uri: &Url,
+        support: &DiagnosticSupport,
+        readable: Option<&[PathBuf]>,
+    ) -> Option<Vec<Diagnostic>> {
+        let contents = self.composer_files.contents(uri)?;
+        let path = uri.to_file_path().ok()?;
+        let mut diagnostics = composer_diagnostics(&contents, path.parent()?, readable);
+        diagnostics.encode(
+            uri,
+            &mut Encoder::new(self.documents.encoding(), |_| Some(contents.clone())),
+        );
+        support.adapt(uri, &mut diagnostics);
+        Some(diagnostics)
+    }
 
-/// Get byte offset given some row and column position in a file.
-///
-/// For example, line 1 character 1 should have offset of 0 (0-indexing).
-///
-/// Return None if the position is invalid (i.e. not in the file, out of range of current line,
-/// etc.)
-fn byte_offset(text: &String, r: &Position) -> Option<usize> {
-    if r.character == 0 {
-        return None;
-    }
-
-    let line = r.line as usize;
-    // start on the zeroth, not the first, because that's how offsets work
-    let character = r.character as usize - 1;
-    let mut current_offset = 0usize;
-
-    for (line_text, line_num) in text.lines().zip(1..=line) {
-        if line_num == line {
-            if character > line_text.len() {
-                return None;
-            } else {
-                return Some(current_offset + character);
+    async fn publish_composer_diagnostics(&self, uri: &Url) {
+        let (support, readable) = {
+            let data_guard = self.data.read().await;
+            if data_guard.pull_diagnostics {
+                return;
             }
-        } else {
-            let newline_offset = current_offset + line_text.len();
-            // assume only two types of newlines exist: `\n` and `\r\n`
-            let newline_num_bytes = if text[newline_offset..].starts_with("\n") {
-                1
-            } else {
-                2
-            };
+            (
+                data_guard.diagnostic_support.clone(),
+                data_guard.readable_dirs(),
+            )
+        };
+        if let Some(diagnostics) =
+            self.composer_file_diagnostics(uri, &support, readable.as_deref())
+        {
+            self.client
+                .publish_diagnostics(uri.clone(), diagnostics, self.composer_files.version(uri))
+                .await;
+        }
+    }
 
-            current_offset += line_text.len() + newline_num_bytes;
+    /// Packages of the `composer.lock` next to an open `composer.json`, to complete in it.
+    fn composer_completions(
+        &self,
+        uri: &Url,
+        position: &Position,
+        readable: Option<&[PathBuf]>,
+    ) -> Option<Vec<CompletionItem>> {
+        let contents = self.composer_files.contents(uri)?;
+        let mut encoder = Encoder::new(self.documents.encoding(), |_| Some(contents.clone()));
+        let lock = uri.to_file_path().ok()?.with_file_name(LOCK_FILE);
+        let lock = match readable.is_none_or(|readable| is_within(&lock, readable)) {
+            true => std::fs::read_to_string(lock).unwrap_or_default(),
+            false => String::new(),
+        };
+        let mut completions = package_completions(
+            &contents,
+            &encoder.decode(uri, position),
+            &locked_packages(&lock),
+        );
+        completions.encode(uri, &mut encoder);
+        Some(completions)
+    }
+
+    /// Where a position in a notebook cell is in the file of its notebook. Others stay where they are.
+    fn notebook_position(&self, uri: &Url, position: &Position) -> (Url, Position) {
+        match self.notebooks.locate(uri) {
+            Some((notebook, lines)) => (
+                notebook,
+                Position::new(position.line + lines.start, position.character),
+            ),
+            None => (uri.clone(), *position),
         }
     }
 
-    None
-}
+    /// Open a notebook's file, and index and check it like any other open file.
+    async fn open_notebook(&self, uri: Url, contents: String, version: i32) {
+        let Some(snapshot) = Snapshot::parse(&mut parsers().get(), contents) else {
+            return;
+        };
+        self.documents.open(uri.clone(), snapshot, version);
+        self.notebook_changed(&uri).await;
+    }
 
-struct BackendData {
-    parser: Parser,
+    async fn notebook_changed(&self, uri: &Url) {
+        let dependents = {
+            let mut data_guard = self.data.write().await;
+            let symbols = self.documents.queries().symbols(uri);
+            data_guard
+                .index
+                .update_symbols(uri, FileSymbols::clone(&symbols))
+        };
+        self.publish_file_diagnostics(uri).await;
+        self.revalidate(dependents).await;
+    }
 
-    file_trees: HashMap<Url, FileData>,
-    ns_to_dir: HashMap<PhpNamespace, Vec<PathBuf>>,
-}
+    pub async fn notebook_did_open(&self, params: DidOpenNotebookDocumentParams) {
+        let uri = params.notebook_document.uri.clone();
+        let version = params.notebook_document.version;
+        let contents = self.notebooks.open(params);
+        self.open_notebook(uri, contents, version).await;
+    }
 
-impl BackendData {
-    fn new() -> Self {
-        let mut parser = Parser::new();
-        parser
-            .set_language(&tree_sitter_php::language_php())
-            .expect("error loading PHP grammar");
+    pub async fn notebook_did_change(&self, params: DidChangeNotebookDocumentParams) {
+        let uri = &params.notebook_document.uri;
+        let Some(contents) = self
+            .notebooks
+            .change(uri, params.change, self.documents.encoding())
+        else {
+            return;
+        };
+        let changes = vec![TextDocumentContentChangeEvent {
+            range: None,
+            range_length: None,
+            text: contents,
+        }];
+        if let Err(e) = self
+            .documents
+            .change(uri, params.notebook_document.version, changes)
+        {
+            self.client
+                .log_message(
+                    MessageType::ERROR,
+                    format!("notebookDocument/didChange `{}`: {}", uri, e),
+                )
+                .await;
+            return;
+        }
+        self.notebook_changed(uri).await;
+    }
 
-        Self {
-            parser,
-            file_trees: HashMap::new(),
-            ns_to_dir: HashMap::new(),
+    pub async fn notebook_did_close(&self, params: DidCloseNotebookDocumentParams) {
+        let uri = &params.notebook_document.uri;
+        for cell in self.notebooks.close(uri) {
+            self.client.publish_diagnostics(cell, vec![], None).await;
         }
+        self.documents.close(uri);
+        self.semantic_tokens.forget(uri);
+        // what the notebook declared is gone with it
+        let dependents = self
+            .data
+            .write()
+            .await
+            .index
+            .update_symbols(uri, FileSymbols::default());
+        self.revalidate(dependents).await;
     }
-}
 
-pub struct Backend {
-    client: Client,
+    /**
+     * Report problems again in files that depend on something that was just changed.
+     */
+    async fn revalidate(&self, uris: Vec<Url>) {
+        if uris.is_empty() {
+            return;
+        }
 
-    data: RwLock<BackendData>,
-}
+        if self.data.read().await.pull_diagnostics {
+            let _ = self.client.workspace_diagnostic_refresh().await;
+            return;
+        }
 
-impl Backend {
-    pub fn new(client: Client) -> Self {
-        Self {
-            client,
+        for uri in uris {
+            if self.data.read().await.index.is_vendor(&uri) {
+                continue;
+            }
 
-            data: RwLock::new(BackendData::new()),
+            self.data.write().await.load(&uri);
+            self.publish_file_diagnostics(&uri).await;
         }
     }
 
-    async fn read_composer_file(
+    /// Converts positions in open files to and from how the client counts columns.
+    fn encoder(&self) -> Encoder<'_> {
+        Encoder::new(self.documents.encoding(), |uri| {
+            self.documents
+                .snapshot(uri)
+                .map(|snapshot| snapshot.contents)
+        })
+    }
+
+    /// Semantic tokens of an open file, or only those in a range of it.
+    async fn file_semantic_tokens(
         &self,
-        composer_file: PathBuf,
-    ) -> Result<(), Box<dyn Error + Send>> {
-        let file = File::open(composer_file).map_err(|e| Box::new(e) as Box<dyn Error + Send>)?;
-        let reader = BufReader::new(file);
+        uri: &Url,
+        range: Option<&Range>,
+    ) -> Option<Vec<SemanticToken>> {
+        let snapshot = self.documents.snapshot(uri)?;
+        let symbols = self.documents.queries().symbols(uri);
+        let index = &self.data.read().await.index;
+        let mut encoder = self.encoder();
+        let mut tokens = match range {
+            Some(range) => semantic_tokens::semantic_tokens_range(
+                &snapshot.root_node(),
+                &snapshot.contents,
+                &symbols,
+                index,
+                &encoder.decode_range(uri, range),
+            ),
+            None => semantic_tokens::semantic_tokens(
+                &snapshot.root_node(),
+                &snapshot.contents,
+                &symbols,
+                index,
+            ),
+        };
+        encoder.semantic_tokens(uri, &mut tokens);
+        Some(tokens)
+    }
 
-        let v: serde_json::Value =
-            serde_json::from_reader(reader).map_err(|e| Box::new(e) as Box<dyn Error + Send>)?;
-        if let serde_json::Value::Object(autoload) = &v["autoload"] {
-            if let serde_json::Value::Object(psr4) = &autoload["psr-4"] {
-                let mut data_guard = self.data.write().await;
-                for (ns, dir) in psr4 {
-                    let namespace = PhpNamespace::from_str(ns).unwrap();
-                    match dir {
-                        serde_json::Value::Array(dirs) => {
-                            let mut paths = vec![];
-                            for x in dirs {
-                                if let serde_json::Value::String(dir) = x {
-                                    if let Ok(path) = PathBuf::from_str(dir) {
-                                        paths.push(path);
-                                    }
-                                }
-                            }
-
-                            if paths.len() > 0 {
-                                data_guard.ns_to_dir.insert(namespace, paths);
-                            }
-                        }
-                        serde_json::Value::String(dir) => {
-                            let dir = PathBuf::from_str(dir)
-                                .map_err(|e| Box::new(e) as Box<dyn Error + Send>)?;
-                            data_guard.ns_to_dir.insert(namespace, vec![dir]);
-                        }
-                        _ => {}
-                    }
-                }
-            }
+    /// The declaration that the name at a position in an open file declares or refers to.
+    async fn target(&self, uri: &Url, position: &Position) -> Option<(Url, Declaration)> {
+        let snapshot = self.documents.snapshot(uri)?;
+        let symbols = self.documents.queries().symbols(uri);
+        let data_guard = self.data.read().await;
+        let resolver = Resolver::new(&snapshot.contents, &symbols, &data_guard.index);
+        let position = self.encoder().decode(uri, position);
+        let declaration = resolver.target(&snapshot.root_node(), &position)?;
+        let (uri, declaration) = data_guard.index.locate(&declaration.name)?;
+
+        Some((uri.clone(), declaration.clone()))
+    }
+
+    pub async fn status(&self) -> LspResult<Status> {
+        Ok(self.data.read().await.status())
+    }
+}
+
+async fn report_progress(client: &Client, token: &NumberOrString, progress: WorkDoneProgress) {
+    client
+        .send_notification::<Progress>(ProgressParams {
+            token: token.clone(),
+            value: ProgressParamsValue::WorkDone(progress),
+        })
+        .await;
+}
+
+/**
+ * Re-read every closed, non-vendor file in the index from disk and report its problems.
+ *
+ * Clients that pull diagnostics are asked to refresh instead of having the results pushed.
+ */
+async fn check_workspace(
+    client: Client,
+    data: Arc<RwLock<BackendData>>,
+    lifecycle: Arc<Lifecycle>,
+) {
+    // checks can overlap, and each needs a token of its own
+    static RUNS: AtomicU64 = AtomicU64::new(0);
+    let token = NumberOrString::String(format!(
+        "{}/{}",
+        CHECK_WORKSPACE_COMMAND,
+        RUNS.fetch_add(1, Ordering::Relaxed)
+    ));
+    let has_progress = client
+        .send_request::<WorkDoneProgressCreate>(WorkDoneProgressCreateParams {
+            token: token.clone(),
+        })
+        .await
+        .is_ok();
+
+    let uris = data.read().await.index.non_vendor_files();
+    if has_progress {
+        report_progress(
+            &client,
+            &token,
+            WorkDoneProgress::Begin(WorkDoneProgressBegin {
+                title: "Checking workspace".to_string(),
+                cancellable: Some(false),
+                message: Some(format!("0/{} files", uris.len())),
+                percentage: Some(0),
+            }),
+        )
+        .await;
+    }
 
-            if let serde_json::Value::Object(psr0) = &autoload["psr-0"] {
-                unimplemented!("composer autoload psr-0");
+    let mut problems = 0;
+    for (i, uri) in uris.iter().enumerate() {
+        if lifecycle.is_shut_down() {
+            break;
+        }
+        let is_open = data.read().await.documents.contains(uri);
+        if !is_open {
+            if let Some(file) = uri
+                .to_file_path()
+                .ok()
+                .and_then(|path| IndexedFile::read(&mut parsers().get(), &path, false))
+            {
+                data.write().await.index.insert(uri.clone(), file);
             }
+        }
 
-            if let serde_json::Value::Array(files) = &autoload["files"] {
-                unimplemented!("composer autoload files");
+        let (pull_diagnostics, diagnostics) = {
+            let data_guard = data.read().await;
+            (
+                data_guard.pull_diagnostics,
+                data_guard.file_diagnostics(uri),
+            )
+        };
+        if let Some(diagnostics) = diagnostics {
+            problems += diagnostics.len();
+            if !pull_diagnostics && !is_open {
+                client
+                    .publish_diagnostics(uri.clone(), diagnostics, None)
+                    .await;
             }
         }
 
-        Ok(())
+        if has_progress {
+            report_progress(
+                &client,
+                &token,
+                WorkDoneProgress::Report(WorkDoneProgressReport {
+                    cancellable: Some(false),
+                    message: Some(format!("{}/{} files", i + 1, uris.len())),
+                    percentage: Some(((i + 1) * 100 / uris.len()) as u32),
+                }),
+            )
+            .await;
+        }
     }
 
-    async fn read_composer_files(&self, composer_files: Vec<PathBuf>) {
-        for path in composer_files {
-            if let Err(e) = self.read_composer_file(path).await {
-                self.client.log_message(MessageType::ERROR, e).await;
-            }
-        }
+    if data.read().await.pull_diagnostics {
+        let _ = client.workspace_diagnostic_refresh().await;
+    }
+
+    if has_progress {
+        report_progress(
+            &client,
+            &token,
+            WorkDoneProgress::End(WorkDoneProgressEnd {
+                message: Some(format!(
+                    "found {} problems in {} files",
+                    problems,
+                    uris.len()
+                )),
+            }),
+        )
+        .await;
+    }
+}
+
+/// A declaration as a symbol of the workspace.
+fn symbol_information(
+    encoder: &mut Encoder,
+    uri: &Url,
+    declaration: &Declaration,
+) -> SymbolInformation {
+    let mut range = declaration.range;
+    encoder.range(uri, &mut range);
+    #[allow(deprecated)]
+    SymbolInformation {
+        name: declaration.short_name().to_string(),
+        kind: declaration.kind.into(),
+        tags: None,
+        deprecated: None,
+        location: Location {
+            uri: uri.clone(),
+            range,
+        },
+        container_name: declaration.container_name().map(str::to_string),
+    }
+}
+
+/// Edits that don't fit the files anymore mean that they changed while the edits were made.
+fn edit_error(error: EditError) -> jsonrpc::Error {
+    jsonrpc::Error {
+        message: error.to_string().into(),
+        ..jsonrpc::Error::content_modified()
     }
 }
 
@@ -373,8 +946,8 @@ fn get_composer_files(workspace_folders: &Vec<WorkspaceFolder>) -> LspResult<Vec
 #[tower_lsp::async_trait]
 impl LanguageServer for Backend {
     async fn initialize(&self, params: InitializeParams) -> LspResult<InitializeResult> {
-        let mut workspace_folders = params.workspace_folders.unwrap_or(vec![]);
-        if workspace_folders.len() == 0 {
+        let mut workspace_folders = params.workspace_folders.unwrap_or_default();
+        if workspace_folders.is_empty() {
             if let Some(root_uri) = params.root_uri {
                 workspace_folders.push(WorkspaceFolder {
                     uri: root_uri.clone(),
@@ -383,7 +956,7 @@ impl LanguageServer for Backend {
             }
         }
 
-        if workspace_folders.len() == 0 {
+        if workspace_folders.is_empty() {
             self.client
                 .log_message(
                     MessageType::LOG,
@@ -403,17 +976,199 @@ impl LanguageServer for Backend {
                 .await;
         }
 
+        let config = match params
+            .initialization_options
+            .map(serde_json::from_value::<Config>)
+        {
+            Some(Ok(config)) => config,
+            Some(Err(e)) => {
+                self.client
+                    .log_message(
+                        MessageType::ERROR,
+                        format!("invalid initialization options, using defaults: {}", e),
+                    )
+                    .await;
+                Config::default()
+            }
+            None => Config::default(),
+        };
+
+        {
+            let mut data_guard = self.data.write().await;
+            data_guard.index.set_memory_budget(config.memory_budget());
+            self.documents.queries().set_config(config.clone());
+            data_guard.config = config;
+            data_guard.workspace_folders = workspace_folders
+                .iter()
+                .filter_map(|folder| folder.uri.to_file_path().ok())
+                .collect();
+            data_guard.pull_diagnostics = params
+                .capabilities
+                .text_document
+                .as_ref()
+                .is_some_and(|text_document| text_document.diagnostic.is_some());
+            data_guard.register_type_hierarchy = params
+                .capabilities
+                .text_document
+                .as_ref()
+                .and_then(|text_document| text_document.type_hierarchy.as_ref())
+                .is_some_and(|type_hierarchy| type_hierarchy.dynamic_registration == Some(true));
+            data_guard.document_changes = params
+                .capabilities
+                .workspace
+                .as_ref()
+                .and_then(|workspace| workspace.workspace_edit.as_ref())
+                .is_some_and(|workspace_edit| workspace_edit.document_changes == Some(true));
+            data_guard.change_annotations = params
+                .capabilities
+                .workspace
+                .as_ref()
+                .and_then(|workspace| workspace.workspace_edit.as_ref())
+                .is_some_and(|workspace_edit| workspace_edit.change_annotation_support.is_some());
+            data_guard.diagnostic_support = DiagnosticSupport::new(
+                params
+                    .capabilities
+                    .text_document
+                    .as_ref()
+                    .and_then(|text_document| text_document.publish_diagnostics.as_ref()),
+            );
+            data_guard.register_file_watchers = params
+                .capabilities
+                .workspace
+                .as_ref()
+                .and_then(|workspace| workspace.did_change_watched_files.as_ref())
+                .is_some_and(|watched_files| watched_files.dynamic_registration == Some(true));
+            data_guard.register_notebooks = params
+                .capabilities
+                .text_document
+                .as_ref()
+                .and_then(|text_document| text_document.synchronization.as_ref())
+                .is_some_and(|synchronization| synchronization.dynamic_registration == Some(true));
+        }
+
+        let encoding = PositionEncoding::negotiate(
+            params
+                .capabilities
+                .general
+                .as_ref()
+                .and_then(|general| general.position_encodings.as_deref())
+                .unwrap_or_default(),
+        );
+        self.documents.set_encoding(encoding);
+
         // TODO check workspace folders for `composer.json` and read namespaces with PSR-4 and
         // PSR-0 (maybe support it??)
         let composer_files = get_composer_files(&workspace_folders)?;
         self.read_composer_files(composer_files).await;
 
+        let mut commands = vec![
+            CHECK_WORKSPACE_COMMAND.to_string(),
+            CLEAR_CACHE_COMMAND.to_string(),
+            UNUSED_API_COMMAND.to_string(),
+        ];
+        // commands that write files are turned off in restricted mode
+        if self.data.read().await.config.restricted {
+            commands.retain(|command| command != CLEAR_CACHE_COMMAND);
+        }
+
         Ok(InitializeResult {
             capabilities: ServerCapabilities {
+                position_encoding: Some(encoding.kind()),
                 text_document_sync: Some(TextDocumentSyncCapability::Kind(
                     TextDocumentSyncKind::INCREMENTAL,
                 )),
                 document_symbol_provider: Some(OneOf::Left(true)),
+                completion_provider: Some(CompletionOptions {
+                    trigger_characters: Some(vec!["$".to_string(), ":".to_string()]),
+                    ..CompletionOptions::default()
+                }),
+                folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+                selection_range_provider: Some(SelectionRangeProviderCapability::Simple(true)),
+                inlay_hint_provider: Some(OneOf::Left(true)),
+                hover_provider: Some(HoverProviderCapability::Simple(true)),
+                definition_provider: Some(OneOf::Left(true)),
+                references_provider: Some(OneOf::Left(true)),
+                call_hierarchy_provider: Some(CallHierarchyServerCapability::Simple(true)),
+                moniker_provider: Some(OneOf::Left(true)),
+                document_link_provider: Some(DocumentLinkOptions {
+                    resolve_provider: Some(false),
+                    work_done_progress_options: WorkDoneProgressOptions::default(),
+                }),
+                document_formatting_provider: Some(OneOf::Left(true)),
+                document_range_formatting_provider: Some(OneOf::Left(true)),
+                linked_editing_range_provider: Some(LinkedEditingRangeServerCapabilities::Simple(
+                    true,
+                )),
+                document_on_type_formatting_provider: Some(DocumentOnTypeFormattingOptions {
+                    first_trigger_character: "}".to_string(),
+                    more_trigger_character: Some(vec![
+                        ";".to_string(),
+                        "\n".to_string(),
+                        "{".to_string(),
+                    ]),
+                }),
+                code_action_provider: Some(CodeActionProviderCapability::Options(
+                    CodeActionOptions {
+                        code_action_kinds: Some(vec![CodeActionKind::QUICKFIX]),
+                        ..CodeActionOptions::default()
+                    },
+                )),
+                code_lens_provider: Some(CodeLensOptions {
+                    resolve_provider: Some(true),
+                }),
+                rename_provider: Some(OneOf::Right(RenameOptions {
+                    prepare_provider: Some(true),
+                    work_done_progress_options: WorkDoneProgressOptions::default(),
+                })),
+                semantic_tokens_provider: Some(
+                    SemanticTokensServerCapabilities::SemanticTokensOptions(
+                        SemanticTokensOptions {
+                            legend: semantic_tokens::legend(),
+                            full: Some(SemanticTokensFullOptions::Delta { delta: Some(true) }),
+                            range: Some(true),
+                            ..SemanticTokensOptions::default()
+                        },
+                    ),
+                ),
+                workspace_symbol_provider: Some(OneOf::Left(true)),
+                workspace: Some(WorkspaceServerCapabilities {
+                    file_operations: Some(WorkspaceFileOperationsServerCapabilities {
+                        will_rename: Some(FileOperationRegistrationOptions {
+                            filters: vec![
+                                FileOperationFilter {
+                                    scheme: Some("file".to_string()),
+                                    pattern: FileOperationPattern {
+                                        glob: "**/*.php".to_string(),
+                                        matches: Some(FileOperationPatternKind::File),
+                                        options: None,
+                                    },
+                                },
+                                FileOperationFilter {
+                                    scheme: Some("file".to_string()),
+                                    pattern: FileOperationPattern {
+                                        glob: "**".to_string(),
+                                        matches: Some(FileOperationPatternKind::Folder),
+                                        options: None,
+                                    },
+                                },
+                            ],
+                        }),
+                        ..WorkspaceFileOperationsServerCapabilities::default()
+                    }),
+                    ..WorkspaceServerCapabilities::default()
+                }),
+                diagnostic_provider: Some(DiagnosticServerCapabilities::Options(
+                    DiagnosticOptions {
+                        identifier: Some(env!("CARGO_PKG_NAME").to_string()),
+                        inter_file_dependencies: true,
+                        workspace_diagnostics: true,
+                        ..DiagnosticOptions::default()
+                    },
+                )),
+                execute_command_provider: Some(ExecuteCommandOptions {
+                    commands,
+                    ..ExecuteCommandOptions::default()
+                }),
                 ..ServerCapabilities::default()
             },
             server_info: Some(ServerInfo {
@@ -427,9 +1182,94 @@ impl LanguageServer for Backend {
         self.client
             .log_message(MessageType::INFO, "server initialized")
             .await;
+
+        if self.data.read().await.register_type_hierarchy {
+            let registration = Registration {
+                id: "textDocument/prepareTypeHierarchy".to_string(),
+                method: "textDocument/prepareTypeHierarchy".to_string(),
+                register_options: serde_json::to_value(TypeHierarchyRegistrationOptions {
+                    text_document_registration_options: TextDocumentRegistrationOptions {
+                        document_selector: Some(vec![DocumentFilter {
+                            language: Some("php".to_string()),
+                            scheme: None,
+                            pattern: None,
+                        }]),
+                    },
+                    ..TypeHierarchyRegistrationOptions::default()
+                })
+                .ok(),
+            };
+            if let Err(e) = self.client.register_capability(vec![registration]).await {
+                self.client
+                    .log_message(
+                        MessageType::WARNING,
+                        format!("could not register type hierarchy: {}", e),
+                    )
+                    .await;
+            }
+        }
+
+        if self.data.read().await.register_notebooks {
+            // every notebook, as long as its cells are PHP
+            let registration = Registration {
+                id: "notebookDocument/sync".to_string(),
+                method: "notebookDocument/sync".to_string(),
+                register_options: Some(serde_json::json!({
+                    "notebookSelector": [{ "cells": [{ "language": "php" }] }],
+                })),
+            };
+            if let Err(e) = self.client.register_capability(vec![registration]).await {
+                // clients from before notebooks refuse what they don't know
+                self.client
+                    .log_message(
+                        MessageType::LOG,
+                        format!("could not register notebooks: {}", e),
+                    )
+                    .await;
+            }
+        }
+
+        if self.data.read().await.register_file_watchers {
+            let registration = Registration {
+                id: "workspace/didChangeWatchedFiles".to_string(),
+                method: "workspace/didChangeWatchedFiles".to_string(),
+                register_options: serde_json::to_value(DidChangeWatchedFilesRegistrationOptions {
+                    watchers: [ANNOTATIONS_FILE, BASELINE_FILE]
+                        .map(|file| FileSystemWatcher {
+                            glob_pattern: GlobPattern::String(format!("**/{}", file)),
+                            kind: None,
+                        })
+                        .to_vec(),
+                })
+                .ok(),
+            };
+            if let Err(e) = self.client.register_capability(vec![registration]).await {
+                self.client
+                    .log_message(
+                        MessageType::WARNING,
+                        format!(
+                            "could not watch `{}` and `{}`: {}",
+                            ANNOTATIONS_FILE, BASELINE_FILE, e
+                        ),
+                    )
+                    .await;
+            }
+        }
+
+        self.index_workspace().await;
     }
 
     async fn shutdown(&self) -> LspResult<()> {
+        if !self.lifecycle.shut_down(SHUTDOWN_TIMEOUT).await {
+            self.client
+                .log_message(
+                    MessageType::WARNING,
+                    "background work was cancelled before it finished",
+                )
+                .await;
+        }
+        self.save_caches().await;
+
         self.client
             .log_message(MessageType::LOG, "server thread has shutdown")
             .await;
@@ -437,23 +1277,37 @@ impl LanguageServer for Backend {
     }
 
     async fn did_open(&self, data: DidOpenTextDocumentParams) {
-        let mut data_guard = self.data.write().await;
-        match data_guard.parser.parse(&data.text_document.text, None) {
-            Some(tree) => {
-                data_guard.file_trees.insert(
-                    data.text_document.uri,
-                    FileData {
-                        contents: data.text_document.text,
-                        tree,
-                        version: data.text_document.version,
-                    },
-                );
+        let uri = data.text_document.uri;
+        if is_composer_file(&uri) {
+            self.composer_files.open(
+                uri.clone(),
+                data.text_document.text,
+                data.text_document.version,
+            );
+            self.publish_composer_diagnostics(&uri).await;
+            return;
+        }
+
+        let snapshot = Snapshot::parse(&mut parsers().get(), data.text_document.text);
+        match snapshot {
+            Some(snapshot) => {
+                self.documents
+                    .open(uri.clone(), snapshot, data.text_document.version);
+                let symbols = self.documents.queries().symbols(&uri);
+                let dependents = self
+                    .data
+                    .write()
+                    .await
+                    .index
+                    .update_symbols(&uri, FileSymbols::clone(&symbols));
+                self.publish_file_diagnostics(&uri).await;
+                self.revalidate(dependents).await;
             }
             None => {
                 self.client
                     .log_message(
                         MessageType::ERROR,
-                        format!("could not parse file `{}`", &data.text_document.uri),
+                        format!("could not parse file `{}`", &uri),
                     )
                     .await
             }
@@ -461,86 +1315,141 @@ impl LanguageServer for Backend {
     }
 
     async fn did_change(&self, data: DidChangeTextDocumentParams) {
-        // https://users.rust-lang.org/t/rwlock-is-confusing-me-and-or-mutable-borrow-counting/120492/2
-        // we gently nudge the borrow checker to give us the actual &mut BackendData instead of
-        // going through a DerefMut.
-        let data_guard = &mut *self.data.write().await;
-        match data_guard.file_trees.get_mut(&data.text_document.uri) {
-            Some(entry) => {
-                if entry.version >= data.text_document.version {
+        let uri = &data.text_document.uri;
+        if self.composer_files.contains(uri) {
+            let changed = self.composer_files.change(
+                uri,
+                data.text_document.version,
+                data.content_changes,
+                self.documents.encoding(),
+            );
+            match changed {
+                Ok(()) => self.publish_composer_diagnostics(uri).await,
+                Err(e) => {
                     self.client
-                        .log_message(
-                            MessageType::LOG,
-                            format!(
-                                "didChange tried to change same version for file `{}`",
-                                &data.text_document.uri
-                            ),
-                        )
-                        .await;
-                    return;
+                        .log_message(MessageType::ERROR, format!("didChange `{}`: {}", uri, e))
+                        .await
                 }
+            }
+            return;
+        }
 
-                entry.version = data.text_document.version;
-                for change in data.content_changes {
-                    if let Some(r) = change.range {
-                        if let (Some(start_byte), Some(end_byte)) = (
-                            byte_offset(&change.text, &r.start),
-                            byte_offset(&change.text, &r.end),
-                        ) {
-                            let input_edit = InputEdit {
-                                start_byte,
-                                old_end_byte: end_byte,
-                                new_end_byte: change.text.len(),
-                                start_position: to_point(&r.start),
-                                old_end_position: to_point(&r.end),
-                                new_end_position: {
-                                    let mut row = r.start.line as usize;
-                                    let mut column = r.start.character as usize;
-
-                                    for c in change.text.chars() {
-                                        if c == '\n' {
-                                            row += 1;
-                                            column = 0;
-                                        } else {
-                                            column += 1;
-                                        }
-                                    }
-
-                                    tree_sitter::Point { row, column }
-                                },
-                            };
-                            entry.tree.edit(&input_edit);
-                            entry
-                                .contents
-                                .replace_range(start_byte..end_byte, &change.text);
-                        }
-                    } else {
-                        entry.contents = change.text.clone();
-                    }
+        if let Err(e) = self
+            .documents
+            .change(uri, data.text_document.version, data.content_changes)
+        {
+            self.client
+                .log_message(MessageType::ERROR, format!("didChange `{}`: {}", uri, e))
+                .await;
+            return;
+        }
 
-                    match data_guard.parser.parse(&entry.contents, None) {
-                        Some(tree) => {
-                            entry.tree = tree;
-                        }
-                        None => {
-                            self.client
-                                .log_message(MessageType::ERROR, "could not parse change")
-                                .await;
-                        }
-                    }
-                }
+        // read the symbols under the lock, in case another change to the file overtook this one
+        let dependents = {
+            let mut data_guard = self.data.write().await;
+            let symbols = self.documents.queries().symbols(uri);
+            data_guard
+                .index
+                .update_symbols(uri, FileSymbols::clone(&symbols))
+        };
+
+        self.publish_file_diagnostics(uri).await;
+        self.revalidate(dependents).await;
+    }
+
+    /**
+     * What's on disk is what the rest of the workspace sees again once a file is closed, so it's
+     * indexed from there and its problems are reported as for any other closed file.
+     */
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        let uri = params.text_document.uri;
+        if self.composer_files.close(&uri) {
+            if !self.data.read().await.pull_diagnostics {
+                self.client.publish_diagnostics(uri, vec![], None).await;
             }
-            None => {
-                self.client
-                    .log_message(
-                        MessageType::ERROR,
-                        format!(
-                            "didChange event triggered without didOpen for file `{}`",
-                            &data.text_document.uri,
-                        ),
-                    )
-                    .await;
+            return;
+        }
+        if !self.documents.close(&uri) {
+            return;
+        }
+        self.semantic_tokens.forget(&uri);
+
+        // files that were only ever open, like new ones, aren't part of the workspace
+        let (indexed, is_vendor) = {
+            let data_guard = self.data.read().await;
+            (
+                data_guard.index.contains(&uri)
+                    && uri
+                        .to_file_path()
+                        .is_ok_and(|path| data_guard.may_read(&path)),
+                data_guard.index.is_vendor(&uri),
+            )
+        };
+        let file = uri
+            .to_file_path()
+            .ok()
+            .filter(|_| indexed)
+            .and_then(|path| IndexedFile::read(&mut parsers().get(), &path, is_vendor));
+        let checked = file.is_some() && !is_vendor;
+        let mut dependents = {
+            let mut data_guard = self.data.write().await;
+            match file {
+                Some(file) => data_guard.index.insert(uri.clone(), file),
+                None => data_guard
+                    .index
+                    .update_symbols(&uri, FileSymbols::default()),
+            }
+        };
+
+        if checked {
+            dependents.push(uri);
+            dependents.sort();
+            dependents.dedup();
+        } else if !self.data.read().await.pull_diagnostics {
+            self.client.publish_diagnostics(uri, vec![], None).await;
+        }
+        self.revalidate(dependents).await;
+    }
+
+    /**
+     * `.phplsp/overrides.json` changes what we infer and report, and `.phplsp/baseline.json` what
+     * we report, so open files are checked again.
+     */
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        let mut changed = false;
+        for change in params.changes {
+            let Ok(path) = change.uri.to_file_path() else {
+                continue;
+            };
+            if !path.ends_with(ANNOTATIONS_FILE) && !path.ends_with(BASELINE_FILE) {
+                continue;
+            }
+            // the file is in a directory of the workspace folder
+            let Some(root) = path.parent().and_then(Path::parent) else {
+                continue;
+            };
+
+            let mut data_guard = self.data.write().await;
+            let loaded = match path.ends_with(ANNOTATIONS_FILE) {
+                true => Annotations::load(root).map(|loaded| match loaded {
+                    Some(annotations) => data_guard.index.set_annotations(annotations),
+                    None => data_guard.index.remove_annotations(root),
+                }),
+                false => Baseline::load(root).map(|loaded| match loaded {
+                    Some(baseline) => data_guard.index.set_baseline(baseline),
+                    None => data_guard.index.remove_baseline(root),
+                }),
+            };
+            if let Err(e) = loaded {
+                drop(data_guard);
+                self.client.log_message(MessageType::ERROR, e).await;
+                continue;
             }
+            changed = true;
+        }
+
+        if changed {
+            self.revalidate(self.documents.uris()).await;
         }
     }
 
@@ -548,174 +1457,1313 @@ impl LanguageServer for Backend {
         &self,
         data: DocumentSymbolParams,
     ) -> LspResult<Option<DocumentSymbolResponse>> {
-        let data_guard = self.data.read().await;
-        if let Some(FileData { contents, tree, .. }) =
-            data_guard.file_trees.get(&data.text_document.uri)
-        {
-            Ok(Some(DocumentSymbolResponse::Nested(document_symbols(
-                &tree.root_node(),
-                contents,
-            ))))
-        } else {
-            Ok(None)
+        if !self.documents.contains(&data.text_document.uri) {
+            return Ok(None);
         }
+
+        let uri = &data.text_document.uri;
+        let mut symbols = self.documents.queries().document_symbols(uri).to_vec();
+        symbols.encode(uri, &mut self.encoder());
+        Ok(Some(DocumentSymbolResponse::Nested(symbols)))
     }
-}
 
-#[cfg(test)]
-mod test {
-    use tower_lsp::lsp_types::*;
-    use tree_sitter::Parser;
+    async fn folding_range(
+        &self,
+        params: FoldingRangeParams,
+    ) -> LspResult<Option<Vec<FoldingRange>>> {
+        if !self.documents.contains(&params.text_document.uri) {
+            return Ok(None);
+        }
 
-    use super::byte_offset;
-    use super::document_symbols;
+        Ok(Some(
+            self.documents
+                .queries()
+                .folding_ranges(&params.text_document.uri)
+                .to_vec(),
+        ))
+    }
 
-    const SOURCE: &'static str = "<?php
-            class Whatever {
-                public int $x = 12;
-                public function foo(int $bar): void
-                {
-                    $this->x = $bar;
-                }
+    async fn selection_range(
+        &self,
+        params: SelectionRangeParams,
+    ) -> LspResult<Option<Vec<SelectionRange>>> {
+        let uri = &params.text_document.uri;
+        let Some(snapshot) = self.documents.snapshot(uri) else {
+            return Ok(None);
+        };
+
+        let mut encoder = self.encoder();
+        let positions: Vec<Position> = params
+            .positions
+            .iter()
+            .map(|position| encoder.decode(uri, position))
+            .collect();
+        let mut ranges = selection_ranges(&snapshot.root_node(), &positions);
+        ranges.encode(uri, &mut encoder);
+        Ok(Some(ranges))
+    }
 
-                public function fee(string $sound, ?array $down): int|false
-                {
-                    $this->x = 12;
-                    if (!empty($down)) {
-                        $this->x = ((int) $sound) + ((int) $down[0]);
-                    }
+    async fn semantic_tokens_full(
+        &self,
+        params: SemanticTokensParams,
+    ) -> LspResult<Option<SemanticTokensResult>> {
+        let uri = params.text_document.uri;
+        let Some(data) = self.file_semantic_tokens(&uri, None).await else {
+            return Ok(None);
+        };
+
+        let result_id = self.semantic_tokens.store(uri, data.clone());
+        Ok(Some(SemanticTokensResult::Tokens(SemanticTokens {
+            result_id: Some(result_id),
+            data,
+        })))
+    }
+
+    async fn semantic_tokens_full_delta(
+        &self,
+        params: SemanticTokensDeltaParams,
+    ) -> LspResult<Option<SemanticTokensFullDeltaResult>> {
+        let uri = params.text_document.uri;
+        let Some(data) = self.file_semantic_tokens(&uri, None).await else {
+            return Ok(None);
+        };
+
+        let previous = self
+            .semantic_tokens
+            .previous(&uri, &params.previous_result_id);
+        let result_id = self.semantic_tokens.store(uri, data.clone());
+        Ok(Some(match previous {
+            Some(previous) => SemanticTokensFullDeltaResult::TokensDelta(SemanticTokensDelta {
+                result_id: Some(result_id),
+                edits: semantic_tokens::edits(&previous, &data),
+            }),
+            // we no longer have what the client has, so it gets everything
+            None => SemanticTokensFullDeltaResult::Tokens(SemanticTokens {
+                result_id: Some(result_id),
+                data,
+            }),
+        }))
+    }
+
+    async fn semantic_tokens_range(
+        &self,
+        params: SemanticTokensRangeParams,
+    ) -> LspResult<Option<SemanticTokensRangeResult>> {
+        let data = self
+            .file_semantic_tokens(&params.text_document.uri, Some(&params.range))
+            .await;
+
+        Ok(data.map(|data| {
+            SemanticTokensRangeResult::Tokens(SemanticTokens {
+                result_id: None,
+                data,
+            })
+        }))
+    }
+
+    async fn inlay_hint(&self, params: InlayHintParams) -> LspResult<Option<Vec<InlayHint>>> {
+        let uri = &params.text_document.uri;
+        let Some(snapshot) = self.documents.snapshot(uri) else {
+            return Ok(None);
+        };
+
+        let symbols = self.documents.queries().symbols(uri);
+        let data_guard = self.data.read().await;
+        let mut encoder = self.encoder();
+        let mut hints = inlay_hints(
+            &snapshot.root_node(),
+            &snapshot.contents,
+            &symbols,
+            &data_guard.index,
+            &data_guard.config,
+            &encoder.decode_range(uri, &params.range),
+        );
+        hints.encode(uri, &mut encoder);
+        Ok(Some(hints))
+    }
+
+    async fn completion(&self, params: CompletionParams) -> LspResult<Option<CompletionResponse>> {
+        let position = &params.text_document_position;
+        if self.composer_files.contains(&position.text_document.uri) {
+            let readable = self.data.read().await.readable_dirs();
+            return Ok(self
+                .composer_completions(
+                    &position.text_document.uri,
+                    &position.position,
+                    readable.as_deref(),
+                )
+                .map(CompletionResponse::Array));
+        }
+
+        let (uri, position) = self.notebook_position(
+            &params.text_document_position.text_document.uri,
+            &params.text_document_position.position,
+        );
+        let Some(snapshot) = self.documents.snapshot(&uri) else {
+            return Ok(None);
+        };
+
+        let symbols = self.documents.queries().symbols(&uri);
+        let data_guard = self.data.read().await;
+        Ok(Some(CompletionResponse::Array(completions(
+            &snapshot.root_node(),
+            &snapshot.contents,
+            &symbols,
+            &data_guard.index,
+            &self.encoder().decode(&uri, &position),
+        ))))
+    }
+
+    async fn hover(&self, params: HoverParams) -> LspResult<Option<Hover>> {
+        let cell = &params.text_document_position_params;
+        let (uri, position) = self.notebook_position(&cell.text_document.uri, &cell.position);
+        if let Some(snapshot) = self.documents.snapshot(&uri) {
+            let data_guard = self.data.read().await;
+            let decoded = self.encoder().decode(&uri, &position);
+            let hover = ini_directive_hover(
+                &snapshot.root_node(),
+                &snapshot.contents,
+                &decoded,
+                &data_guard.index,
+            )
+            .or_else(|| {
+                construct_hover(
+                    &snapshot.root_node(),
+                    &snapshot.contents,
+                    &decoded,
+                    data_guard.config.php_version,
+                )
+            });
+            if let Some(mut hover) = hover {
+                match uri == cell.text_document.uri {
+                    true => hover.encode(&uri, &mut data_guard.encoder()),
+                    // the range is in the notebook's file rather than the cell
+                    false => hover.range = None,
                 }
+                return Ok(Some(hover));
             }
+        }
+        let Some((_, declaration)) = self.target(&uri, &position).await else {
+            return Ok(None);
+        };
 
-            final class Another {
-                private int $y = 3;
-                public function __constructor(): void
-                {
-                }
-            }";
+        let mut value = format!("```php\n{}\n```", declaration.signature);
+        let data_guard = self.data.read().await;
+        if declaration.kind == DeclarationKind::Function
+            && data_guard.config.is_disabled(&declaration.name)
+        {
+            value.insert_str(
+                0,
+                "**Disabled** by `disable_functions` where the code runs, so calling it fails\n\n",
+            );
+        }
+        let docs = self.documents.snapshot(&uri).and_then(|snapshot| {
+            let symbols = self.documents.queries().symbols(&uri);
+            let resolver = Resolver::new(&snapshot.contents, &symbols, &data_guard.index);
+            documentation(&declaration, &resolver, &|declaration| {
+                data_guard.doc_comment(declaration)
+            })
+        });
+        if let Some(docs) = docs {
+            value.push_str("\n\n");
+            value.push_str(&docs);
+        }
+        Ok(Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value,
+            }),
+            range: None,
+        }))
+    }
 
-    #[test]
-    fn test_valid_byte_offsets() {
-        let valids = [
-            (
-                Position {
-                    line: 1,
-                    character: 1,
-                },
-                0usize,
-            ),
-            (
-                Position {
-                    line: 2,
-                    character: 1,
-                },
-                6usize,
-            ),
-        ];
+    async fn goto_definition(
+        &self,
+        params: GotoDefinitionParams,
+    ) -> LspResult<Option<GotoDefinitionResponse>> {
+        let position = &params.text_document_position_params;
+        let target = self
+            .target(&position.text_document.uri, &position.position)
+            .await;
 
-        let s = SOURCE.to_string();
-        for (pos, expected) in valids {
-            assert_eq!(expected, byte_offset(&s, &pos).unwrap());
-        }
+        let Some((uri, declaration)) = target else {
+            return Ok(None);
+        };
+
+        let mut location = Location {
+            uri,
+            range: declaration.range,
+        };
+        location.encode(
+            &position.text_document.uri,
+            &mut self.data.read().await.encoder(),
+        );
+        Ok(Some(GotoDefinitionResponse::Scalar(location)))
     }
 
-    #[test]
-    fn test_invalid_byte_offsets() {
-        let invalids = [
-            Position {
-                line: 200,
-                character: 10,
-            },
-            Position {
-                line: 1,
-                character: 100,
+    async fn references(&self, params: ReferenceParams) -> LspResult<Option<Vec<Location>>> {
+        let position = &params.text_document_position;
+        let Some((uri, declaration)) = self
+            .target(&position.text_document.uri, &position.position)
+            .await
+        else {
+            return Ok(None);
+        };
+
+        let mut locations = vec![];
+        if params.context.include_declaration {
+            locations.push(Location {
+                uri,
+                range: declaration.range,
+            });
+        }
+        let data_guard = self.data.read().await;
+        locations.extend(data_guard.index.references(&declaration).into_iter().map(
+            |(uri, reference)| Location {
+                uri: uri.clone(),
+                range: reference.range,
             },
-        ];
+        ));
+
+        locations.encode(&position.text_document.uri, &mut data_guard.encoder());
+        Ok(Some(locations))
+    }
+
+    /// Symbols declared in the file are exported, and anything else is imported.
+    async fn moniker(&self, params: MonikerParams) -> LspResult<Option<Vec<Moniker>>> {
+        let position = &params.text_document_position_params;
+        let Some((uri, declaration)) = self
+            .target(&position.text_document.uri, &position.position)
+            .await
+        else {
+            return Ok(None);
+        };
+
+        let kind = match uri == position.text_document.uri {
+            true => MonikerKind::Export,
+            false => MonikerKind::Import,
+        };
+        let readable = self.data.read().await.readable_dirs();
+        Ok(Some(
+            moniker(&uri, &declaration, kind, readable.as_deref())
+                .into_iter()
+                .collect(),
+        ))
+    }
+
+    async fn prepare_call_hierarchy(
+        &self,
+        params: CallHierarchyPrepareParams,
+    ) -> LspResult<Option<Vec<CallHierarchyItem>>> {
+        let position = &params.text_document_position_params;
+        let uri = &position.text_document.uri;
+        let Some(snapshot) = self.documents.snapshot(uri) else {
+            return Ok(None);
+        };
+
+        let symbols = self.documents.queries().symbols(uri);
+        let data_guard = self.data.read().await;
+        let mut encoder = data_guard.encoder();
+        let mut item = prepare_call_hierarchy(
+            &snapshot.root_node(),
+            &snapshot.contents,
+            &symbols,
+            &data_guard.index,
+            &encoder.decode(uri, &position.position),
+        );
+        item.encode(uri, &mut encoder);
+        Ok(item.map(|item| vec![item]))
+    }
 
-        let s = SOURCE.to_string();
-        for invalid_position in invalids {
-            assert_eq!(None, byte_offset(&s, &invalid_position));
+    async fn incoming_calls(
+        &self,
+        params: CallHierarchyIncomingCallsParams,
+    ) -> LspResult<Option<Vec<CallHierarchyIncomingCall>>> {
+        let data_guard = &mut *self.data.write().await;
+        let Some((_, callee)) = item_declaration(&params.item, &data_guard.index) else {
+            return Ok(None);
+        };
+        let callee = callee.clone();
+        let mut uris: Vec<Url> = data_guard
+            .index
+            .references(&callee)
+            .into_iter()
+            .map(|(uri, _)| uri.clone())
+            .collect();
+        uris.sort();
+        uris.dedup();
+
+        let mut calls = vec![];
+        for uri in uris {
+            data_guard.load(&uri);
+            let (Some(snapshot), Some(symbols)) =
+                (data_guard.snapshot(&uri), data_guard.index.symbols(&uri))
+            else {
+                continue;
+            };
+            calls.extend(incoming_calls(
+                &snapshot.root_node(),
+                &snapshot.contents,
+                &uri,
+                symbols,
+                &data_guard.index,
+                &callee,
+            ));
         }
+
+        calls.encode(&params.item.uri, &mut data_guard.encoder());
+        Ok(Some(calls))
     }
 
-    #[test]
-    fn test_get_symbols() {
-        let mut parser = Parser::new();
-        parser
-            .set_language(&tree_sitter_php::language_php())
-            .expect("error loading PHP grammar");
-
-        let tree = parser.parse(SOURCE, None).unwrap();
-        let root_node = tree.root_node();
-        let actual_symbols = document_symbols(&root_node, &SOURCE.to_string());
-        assert_eq!(2, actual_symbols.len());
-        assert_eq!("Whatever", &actual_symbols[0].name);
-        assert_eq!("Another", &actual_symbols[1].name);
-        assert_eq!(3, actual_symbols[0].children.as_ref().unwrap().len());
-        assert_eq!("$x", &actual_symbols[0].children.as_ref().unwrap()[0].name);
-        assert_eq!("foo", &actual_symbols[0].children.as_ref().unwrap()[1].name);
-        assert_eq!("fee", &actual_symbols[0].children.as_ref().unwrap()[2].name);
-        assert_eq!(
-            1,
-            actual_symbols[0].children.as_ref().unwrap()[1]
-                .children
-                .as_ref()
-                .unwrap()
-                .len()
+    async fn outgoing_calls(
+        &self,
+        params: CallHierarchyOutgoingCallsParams,
+    ) -> LspResult<Option<Vec<CallHierarchyOutgoingCall>>> {
+        let data_guard = &mut *self.data.write().await;
+        let Some((uri, caller)) = item_declaration(&params.item, &data_guard.index) else {
+            return Ok(None);
+        };
+        let (uri, caller) = (uri.clone(), caller.clone());
+
+        data_guard.load(&uri);
+        let (Some(snapshot), Some(symbols)) =
+            (data_guard.snapshot(&uri), data_guard.index.symbols(&uri))
+        else {
+            return Ok(None);
+        };
+        let mut calls = outgoing_calls(
+            &snapshot.root_node(),
+            &snapshot.contents,
+            symbols,
+            &data_guard.index,
+            &caller,
         );
-        assert_eq!(
-            "$bar",
-            &actual_symbols[0].children.as_ref().unwrap()[1]
-                .children
-                .as_ref()
-                .unwrap()[0]
-                .name
+        calls.encode(&uri, &mut data_guard.encoder());
+        Ok(Some(calls))
+    }
+
+    async fn prepare_type_hierarchy(
+        &self,
+        params: TypeHierarchyPrepareParams,
+    ) -> LspResult<Option<Vec<TypeHierarchyItem>>> {
+        let position = &params.text_document_position_params;
+        let uri = &position.text_document.uri;
+        let Some(snapshot) = self.documents.snapshot(uri) else {
+            return Ok(None);
+        };
+
+        let symbols = self.documents.queries().symbols(uri);
+        let data_guard = self.data.read().await;
+        let mut encoder = data_guard.encoder();
+        let mut item = prepare_type_hierarchy(
+            &snapshot.root_node(),
+            &snapshot.contents,
+            &symbols,
+            &data_guard.index,
+            &encoder.decode(uri, &position.position),
         );
-        assert_eq!(
-            2,
-            actual_symbols[0].children.as_ref().unwrap()[2]
-                .children
-                .as_ref()
-                .unwrap()
-                .len()
+        item.encode(uri, &mut encoder);
+        Ok(item.map(|item| vec![item]))
+    }
+
+    async fn supertypes(
+        &self,
+        params: TypeHierarchySupertypesParams,
+    ) -> LspResult<Option<Vec<TypeHierarchyItem>>> {
+        let data_guard = self.data.read().await;
+        let mut items = type_hierarchy::item_declaration(&params.item, &data_guard.index)
+            .map(|declaration| supertypes(declaration, &data_guard.index));
+        items.encode(&params.item.uri, &mut data_guard.encoder());
+        Ok(items)
+    }
+
+    async fn subtypes(
+        &self,
+        params: TypeHierarchySubtypesParams,
+    ) -> LspResult<Option<Vec<TypeHierarchyItem>>> {
+        let data_guard = self.data.read().await;
+        let mut items = type_hierarchy::item_declaration(&params.item, &data_guard.index)
+            .map(|declaration| subtypes(declaration, &data_guard.index));
+        items.encode(&params.item.uri, &mut data_guard.encoder());
+        Ok(items)
+    }
+
+    async fn document_link(
+        &self,
+        params: DocumentLinkParams,
+    ) -> LspResult<Option<Vec<DocumentLink>>> {
+        let uri = &params.text_document.uri;
+        let Some(snapshot) = self.documents.snapshot(uri) else {
+            return Ok(None);
+        };
+
+        let symbols = self.documents.queries().symbols(uri);
+        let data_guard = self.data.read().await;
+        let readable = data_guard.readable_dirs();
+        let mut links = document_links(
+            &snapshot.root_node(),
+            &snapshot.contents,
+            uri,
+            &symbols,
+            &data_guard.index,
+            LinkDirs {
+                namespace_dirs: &data_guard.ns_to_dir,
+                roots: &data_guard.workspace_folders,
+                readable: readable.as_deref(),
+            },
         );
-        assert_eq!(
-            "$sound",
-            &actual_symbols[0].children.as_ref().unwrap()[2]
-                .children
-                .as_ref()
-                .unwrap()[0]
-                .name
+        links.encode(uri, &mut self.encoder());
+        Ok(Some(links))
+    }
+
+    async fn formatting(
+        &self,
+        params: DocumentFormattingParams,
+    ) -> LspResult<Option<Vec<TextEdit>>> {
+        let uri = &params.text_document.uri;
+        let Some(snapshot) = self.documents.snapshot(uri) else {
+            return Ok(None);
+        };
+
+        let mut edits = format(&snapshot.root_node(), &snapshot.contents, &params.options);
+        edits.encode(uri, &mut self.encoder());
+        Ok(Some(edits))
+    }
+
+    async fn range_formatting(
+        &self,
+        params: DocumentRangeFormattingParams,
+    ) -> LspResult<Option<Vec<TextEdit>>> {
+        let uri = &params.text_document.uri;
+        let Some(snapshot) = self.documents.snapshot(uri) else {
+            return Ok(None);
+        };
+
+        let mut encoder = self.encoder();
+        let mut edits = format_range(
+            &snapshot.root_node(),
+            &snapshot.contents,
+            &params.options,
+            &encoder.decode_range(uri, &params.range),
         );
-        assert_eq!(
-            "$down",
-            &actual_symbols[0].children.as_ref().unwrap()[2]
-                .children
-                .as_ref()
-                .unwrap()[1]
-                .name
+        edits.encode(uri, &mut encoder);
+        Ok(Some(edits))
+    }
+
+    async fn on_type_formatting(
+        &self,
+        params: DocumentOnTypeFormattingParams,
+    ) -> LspResult<Option<Vec<TextEdit>>> {
+        let position = &params.text_document_position;
+        let uri = &position.text_document.uri;
+        let Some(snapshot) = self.documents.snapshot(uri) else {
+            return Ok(None);
+        };
+
+        let auto_close = self.data.read().await.config.auto_close;
+        let mut encoder = self.encoder();
+        let mut edits = format_on_type(
+            &snapshot.root_node(),
+            &snapshot.contents,
+            &params.options,
+            &auto_close,
+            &encoder.decode(uri, &position.position),
+            &params.ch,
         );
+        edits.encode(uri, &mut encoder);
+        Ok(Some(edits))
+    }
+
+    async fn linked_editing_range(
+        &self,
+        params: LinkedEditingRangeParams,
+    ) -> LspResult<Option<LinkedEditingRanges>> {
+        let position = &params.text_document_position_params;
+        let uri = &position.text_document.uri;
+        let Some(snapshot) = self.documents.snapshot(uri) else {
+            return Ok(None);
+        };
+
+        let mut encoder = self.encoder();
+        let mut ranges = linked_editing_ranges(
+            &snapshot.root_node(),
+            &snapshot.contents,
+            &encoder.decode(uri, &position.position),
+        );
+        ranges.encode(uri, &mut encoder);
+        Ok(ranges)
+    }
+
+    async fn code_action(&self, params: CodeActionParams) -> LspResult<Option<CodeActionResponse>> {
+        let wants_quick_fixes = params.context.only.as_ref().is_none_or(|only| {
+            only.iter()
+                .any(|kind| CodeActionKind::QUICKFIX.as_str().starts_with(kind.as_str()))
+        });
+        if !wants_quick_fixes {
+            return Ok(None);
+        }
+
+        let uri = &params.text_document.uri;
+        let data_guard = &mut *self.data.write().await;
+        let mut errors = vec![];
+        let actions = quick_fixes(&params.context.diagnostics, |fix| {
+            // the diagnostic was sent with its fix, so its edits count columns like the client
+            let mut builder = match &fix.rename {
+                Some(_) => data_guard.refactoring(fix.title.clone()),
+                None => WorkspaceEditBuilder::new(),
+            };
+            let mut encoder = data_guard.encoder();
+            builder.edit(
+                uri,
+                fix.edits.iter().map(|edit| TextEdit {
+                    range: encoder.decode_range(uri, &edit.range),
+                    new_text: edit.new_text.clone(),
+                }),
+            );
+            drop(encoder);
+            if let Some(symbol) = &fix.rename {
+                data_guard.rename_symbol(symbol, &mut builder);
+            }
+            if builder.is_empty() {
+                return None;
+            }
+            data_guard
+                .build_edit(builder)
+                .map_err(|error| errors.push(format!("can't {}: {}", fix.title, error)))
+                .ok()
+        });
+        for error in errors {
+            self.client.log_message(MessageType::WARNING, error).await;
+        }
+        Ok(Some(actions))
+    }
+
+    async fn code_lens(&self, params: CodeLensParams) -> LspResult<Option<Vec<CodeLens>>> {
+        let uri = &params.text_document.uri;
+        let Some(snapshot) = self.documents.snapshot(uri) else {
+            return Ok(None);
+        };
+
+        let symbols = self.documents.queries().symbols(uri);
+        let mut lenses = code_lenses(&snapshot.root_node(), &snapshot.contents, uri, &symbols);
+        lenses.encode(uri, &mut self.encoder());
+        Ok(Some(lenses))
+    }
+
+    async fn code_lens_resolve(&self, lens: CodeLens) -> LspResult<CodeLens> {
+        Ok(resolve_code_lens(lens, &self.data.read().await.index))
+    }
+
+    async fn prepare_rename(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> LspResult<Option<PrepareRenameResponse>> {
+        let uri = &params.text_document.uri;
+        let Some(snapshot) = self.documents.snapshot(uri) else {
+            return Ok(None);
+        };
+
+        let symbols = self.documents.queries().symbols(uri);
+        let mut encoder = self.encoder();
+        let mut response = namespace_at(
+            &snapshot.root_node(),
+            &snapshot.contents,
+            &symbols,
+            &encoder.decode(uri, &params.position),
+        )
+        .map(
+            |(range, placeholder)| PrepareRenameResponse::RangeWithPlaceholder {
+                range,
+                placeholder,
+            },
+        );
+        response.encode(uri, &mut encoder);
+        Ok(response)
+    }
+
+    /// Only namespaces can be renamed, to any other namespace.
+    async fn rename(&self, params: RenameParams) -> LspResult<Option<WorkspaceEdit>> {
+        let position = params.text_document_position;
+        let uri = &position.text_document.uri;
+        let Some(snapshot) = self.documents.snapshot(uri) else {
+            return Ok(None);
+        };
+        let symbols = self.documents.queries().symbols(uri);
+        let Some((_, old)) = namespace_at(
+            &snapshot.root_node(),
+            &snapshot.contents,
+            &symbols,
+            &self.encoder().decode(uri, &position.position),
+        ) else {
+            return Ok(None);
+        };
+        let new = params.new_name.trim_matches('\\');
+        if !is_valid_namespace(new) {
+            return Err(jsonrpc::Error::invalid_params(format!(
+                "`{}` is not a valid namespace",
+                params.new_name
+            )));
+        }
+
+        let data_guard = &mut *self.data.write().await;
+        let mut uris = data_guard.index.non_vendor_files();
+        uris.extend(self.documents.uris());
+        uris.sort();
+        uris.dedup();
+
+        let mut builder =
+            data_guard.refactoring(format!("Rename namespace `{}` to `{}`", old, new));
+        for uri in uris {
+            let symbols = match self.documents.contains(&uri) {
+                true => FileSymbols::clone(&self.documents.queries().symbols(&uri)),
+                false => match data_guard.index.symbols(&uri) {
+                    Some(symbols) => symbols.clone(),
+                    None => continue,
+                },
+            };
+            if !mentions_namespace(&symbols, &old) {
+                continue;
+            }
+
+            data_guard.load(&uri);
+            let Some(snapshot) = data_guard.snapshot(&uri) else {
+                continue;
+            };
+            let edits = rename_namespace(
+                &snapshot.root_node(),
+                &snapshot.contents,
+                &symbols,
+                &old,
+                new,
+            );
+            builder.edit(&uri, edits.edits);
+            builder.guess(&uri, edits.guesses);
+        }
+
+        for (old_dir, new_dir) in namespace_directories(&data_guard.ns_to_dir, &old, new) {
+            if let (Ok(old_uri), Ok(new_uri)) = (
+                Url::from_directory_path(old_dir),
+                Url::from_directory_path(new_dir),
+            ) {
+                builder.rename(old_uri, new_uri);
+            }
+        }
+
+        data_guard.build_edit(builder).map(Some).map_err(edit_error)
+    }
+
+    /**
+     * Moving a file moves the class it declares to the namespace that composer expects of where
+     * it ends up, and renames it after the file. Moving a directory moves every file in it.
+     */
+    async fn will_rename_files(
+        &self,
+        params: RenameFilesParams,
+    ) -> LspResult<Option<WorkspaceEdit>> {
+        let data_guard = &mut *self.data.write().await;
+        let mut uris = data_guard.index.non_vendor_files();
+        uris.extend(self.documents.uris());
+        uris.sort();
+        uris.dedup();
+
+        let mut moves = vec![];
+        for file in &params.files {
+            let (Ok(old_uri), Ok(new_uri)) = (Url::parse(&file.old_uri), Url::parse(&file.new_uri))
+            else {
+                continue;
+            };
+            let (Ok(old_path), Ok(new_path)) = (old_uri.to_file_path(), new_uri.to_file_path())
+            else {
+                continue;
+            };
+            for uri in &uris {
+                let Ok(path) = uri.to_file_path() else {
+                    continue;
+                };
+                let Ok(relative) = path.strip_prefix(&old_path) else {
+                    continue;
+                };
+                let moved = match relative.as_os_str().is_empty() {
+                    true => new_path.clone(),
+                    false => new_path.join(relative),
+                };
+                let (Some(old), Some(new)) = (
+                    autoload_class(&path, &data_guard.ns_to_dir),
+                    autoload_class(&moved, &data_guard.ns_to_dir),
+                ) else {
+                    continue;
+                };
+                let declares = data_guard.index.symbols(uri).is_some_and(|symbols| {
+                    symbols
+                        .declarations
+                        .iter()
+                        .any(|declaration| declaration.name.eq_ignore_ascii_case(&old))
+                });
+                if declares && !old.eq_ignore_ascii_case(&new) {
+                    moves.push((old, new));
+                }
+            }
+        }
+
+        if moves.is_empty() {
+            return Ok(None);
+        }
+
+        let description = match moves.as_slice() {
+            [(old, new)] => format!("Move `{}` to `{}`", old, new),
+            _ => format!("Move {} classes", moves.len()),
+        };
+        let mut builder = data_guard.refactoring(description);
+        for uri in &uris {
+            let symbols = match self.documents.contains(uri) {
+                true => FileSymbols::clone(&self.documents.queries().symbols(uri)),
+                false => match data_guard.index.symbols(uri) {
+                    Some(symbols) => symbols.clone(),
+                    None => continue,
+                },
+            };
+            let declarations = symbols.declarations.iter().map(|d| &d.name);
+            let references = symbols
+                .references
+                .iter()
+                .filter(|r| r.kind == ReferenceKind::Class)
+                .map(|r| &r.name);
+            if !declarations
+                .chain(references)
+                .any(|name| moves.iter().any(|(old, _)| name.eq_ignore_ascii_case(old)))
+            {
+                continue;
+            }
+
+            data_guard.load(uri);
+            let Some(snapshot) = data_guard.snapshot(uri) else {
+                continue;
+            };
+            let edits = move_classes(&snapshot.root_node(), &snapshot.contents, &symbols, &moves);
+            builder.edit(uri, edits.edits);
+            builder.guess(uri, edits.guesses);
+        }
+
+        if builder.is_empty() {
+            return Ok(None);
+        }
+        data_guard.build_edit(builder).map(Some).map_err(edit_error)
+    }
+
+    async fn symbol(
+        &self,
+        params: WorkspaceSymbolParams,
+    ) -> LspResult<Option<Vec<SymbolInformation>>> {
+        let data_guard = self.data.read().await;
+        let mut encoder = data_guard.encoder();
+        let symbols = data_guard
+            .index
+            .search(&params.query, WORKSPACE_SYMBOL_LIMIT)
+            .into_iter()
+            .map(|(uri, declaration)| symbol_information(&mut encoder, uri, declaration))
+            .collect();
+
+        Ok(Some(symbols))
+    }
+
+    async fn diagnostic(
+        &self,
+        params: DocumentDiagnosticParams,
+    ) -> LspResult<DocumentDiagnosticReportResult> {
+        let uri = &params.text_document.uri;
+        let items = match self.notebooks.locate(uri) {
+            _ if self.composer_files.contains(uri) => {
+                let (support, readable) = {
+                    let data_guard = self.data.read().await;
+                    (
+                        data_guard.diagnostic_support.clone(),
+                        data_guard.readable_dirs(),
+                    )
+                };
+                self.composer_file_diagnostics(uri, &support, readable.as_deref())
+            }
+            Some((notebook, lines)) => self
+                .data
+                .read()
+                .await
+                .file_diagnostics(&notebook)
+                .map(|diagnostics| cell_diagnostics(&diagnostics, &lines)),
+            None => self.data.read().await.file_diagnostics(uri),
+        }
+        .unwrap_or_default();
+
+        Ok(DocumentDiagnosticReportResult::Report(
+            DocumentDiagnosticReport::Full(RelatedFullDocumentDiagnosticReport {
+                related_documents: None,
+                full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                    result_id: None,
+                    items,
+                },
+            }),
+        ))
+    }
+
+    async fn workspace_diagnostic(
+        &self,
+        _: WorkspaceDiagnosticParams,
+    ) -> LspResult<WorkspaceDiagnosticReportResult> {
+        let uris = self.data.read().await.index.non_vendor_files();
+        let mut items = vec![];
+        for uri in uris {
+            if self.documents.contains(&uri) {
+                continue;
+            }
+
+            // one at a time, so that loading evicted files doesn't blow the memory budget, and
+            // parsed without a lock, so that other requests don't wait for the whole workspace
+            let evicted = {
+                let data_guard = self.data.read().await;
+                data_guard.index.get(&uri).is_none()
+                    && uri
+                        .to_file_path()
+                        .is_ok_and(|path| data_guard.may_read(&path))
+            };
+            if let Some(file) = uri
+                .to_file_path()
+                .ok()
+                .filter(|_| evicted)
+                .and_then(|path| IndexedFile::read(&mut parsers().get(), &path, false))
+            {
+                self.data.write().await.index.insert(uri.clone(), file);
+            }
+            let diagnostics = self.data.read().await.file_diagnostics(&uri);
+            if let Some(diagnostics) = diagnostics {
+                items.push(WorkspaceDocumentDiagnosticReport::Full(
+                    WorkspaceFullDocumentDiagnosticReport {
+                        uri,
+                        version: None,
+                        full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                            result_id: None,
+                            items: diagnostics,
+                        },
+                    },
+                ));
+            }
+        }
+
+        Ok(WorkspaceDiagnosticReportResult::Report(
+            WorkspaceDiagnosticReport { items },
+        ))
+    }
+
+    async fn execute_command(
+        &self,
+        params: ExecuteCommandParams,
+    ) -> LspResult<Option<serde_json::Value>> {
+        match params.command.as_str() {
+            CHECK_WORKSPACE_COMMAND => {
+                self.lifecycle.spawn(check_workspace(
+                    self.client.clone(),
+                    self.data.clone(),
+                    self.lifecycle.clone(),
+                ));
+                Ok(None)
+            }
+            CLEAR_CACHE_COMMAND => {
+                let (folders, restricted) = {
+                    let data_guard = self.data.read().await;
+                    (
+                        data_guard.workspace_folders.clone(),
+                        data_guard.config.restricted,
+                    )
+                };
+                if restricted {
+                    return Err(jsonrpc::Error::invalid_params(format!(
+                        "`{}` writes files, which restricted mode doesn't allow",
+                        CLEAR_CACHE_COMMAND
+                    )));
+                }
+                for folder in folders {
+                    if let Err(e) = clear_cache(&folder) {
+                        return Err(jsonrpc::Error {
+                            message: format!("could not clear the cache: {}", e).into(),
+                            ..jsonrpc::Error::internal_error()
+                        });
+                    }
+                }
+                // index from scratch, which saves new caches
+                self.index_workspace().await;
+                Ok(None)
+            }
+            UNUSED_API_COMMAND => {
+                let data_guard = self.data.read().await;
+                let mut encoder = data_guard.encoder();
+                let unused: Vec<SymbolInformation> =
+                    unused_public_api(&data_guard.index, &data_guard.config.entry_points)
+                        .into_iter()
+                        .map(|(uri, declaration)| {
+                            symbol_information(&mut encoder, uri, declaration)
+                        })
+                        .collect();
+                serde_json::to_value(unused)
+                    .map(Some)
+                    .map_err(|_| jsonrpc::Error::internal_error())
+            }
+            _ => Err(jsonrpc::Error::invalid_params(format!(
+                "unknown command `{}`",
+                params.command
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use tower_lsp::lsp_types::*;
+    use tower_lsp::{LanguageServer, LspService};
+
+    use super::{Backend, BackendData, STUBS_DIR};
+    use crate::notebooks::{DidOpenNotebookDocumentParams, NotebookCell, NotebookDocument};
+    use phplsp_core::index::IndexedFile;
+    use phplsp_core::parsers::parsers;
+    use phplsp_core::php_namespace::PhpNamespace;
+
+    #[tokio::test]
+    async fn test_edit_open_file() {
+        let (service, _) = LspService::new(|client| Backend::new(client, Arc::default()));
+        let backend = service.inner();
+        let uri = Url::parse("file:///project/A.php").unwrap();
+
+        let edits = async {
+            backend
+                .did_open(DidOpenTextDocumentParams {
+                    text_document: TextDocumentItem {
+                        uri: uri.clone(),
+                        language_id: "php".to_string(),
+                        version: 1,
+                        text: "<?php class A {}".to_string(),
+                    },
+                })
+                .await;
+            backend
+                .did_change(DidChangeTextDocumentParams {
+                    text_document: VersionedTextDocumentIdentifier {
+                        uri: uri.clone(),
+                        version: 2,
+                    },
+                    content_changes: vec![TextDocumentContentChangeEvent {
+                        range: None,
+                        range_length: None,
+                        text: "<?php class B {}".to_string(),
+                    }],
+                })
+                .await;
+        };
+        tokio::time::timeout(Duration::from_secs(5), edits)
+            .await
+            .expect("editing a file should not deadlock");
+
+        let response = backend
+            .document_symbol(DocumentSymbolParams {
+                text_document: TextDocumentIdentifier { uri },
+                work_done_progress_params: WorkDoneProgressParams::default(),
+                partial_result_params: PartialResultParams::default(),
+            })
+            .await
+            .unwrap();
+        let Some(DocumentSymbolResponse::Nested(symbols)) = response else {
+            panic!("expected nested document symbols");
+        };
+        assert_eq!("B", symbols[0].name);
+    }
+
+    #[tokio::test]
+    async fn test_close_file() {
+        let (service, _) = LspService::new(|client| Backend::new(client, Arc::default()));
+        let backend = service.inner();
+        let root = std::env::temp_dir().join("phplsp-test-close-file");
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        let path = root.join("A.php");
+        std::fs::write(&path, "<?php class A {}").unwrap();
+        let uri = Url::from_file_path(&path).unwrap();
+        let file = IndexedFile::read(&mut parsers().get(), &path, false).unwrap();
+        backend.data.write().await.index.insert(uri.clone(), file);
+
+        let checked = || async {
+            let report = backend
+                .workspace_diagnostic(WorkspaceDiagnosticParams {
+                    identifier: None,
+                    previous_result_ids: vec![],
+                    work_done_progress_params: WorkDoneProgressParams::default(),
+                    partial_result_params: PartialResultParams::default(),
+                })
+                .await
+                .unwrap();
+            let WorkspaceDiagnosticReportResult::Report(report) = report else {
+                panic!("expected a full report");
+            };
+            report.items.iter().any(|item| match item {
+                WorkspaceDocumentDiagnosticReport::Full(report) => report.uri == uri,
+                WorkspaceDocumentDiagnosticReport::Unchanged(report) => report.uri == uri,
+            })
+        };
+        let declared = || async {
+            let data_guard = backend.data.read().await;
+            let symbols = data_guard.index.symbols(&uri).unwrap();
+            symbols.declarations[0].name.clone()
+        };
+        assert!(checked().await);
+
+        backend
+            .did_open(DidOpenTextDocumentParams {
+                text_document: TextDocumentItem {
+                    uri: uri.clone(),
+                    language_id: "php".to_string(),
+                    version: 1,
+                    text: "<?php class A {}".to_string(),
+                },
+            })
+            .await;
+        backend
+            .did_change(DidChangeTextDocumentParams {
+                text_document: VersionedTextDocumentIdentifier {
+                    uri: uri.clone(),
+                    version: 2,
+                },
+                content_changes: vec![TextDocumentContentChangeEvent {
+                    range: None,
+                    range_length: None,
+                    text: "<?php class B {}".to_string(),
+                }],
+            })
+            .await;
+        // open files are checked as documents instead
+        assert!(!checked().await);
+        assert_eq!("B", declared().await);
+
+        backend
+            .did_close(DidCloseTextDocumentParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+            })
+            .await;
+        assert!(!backend.documents.contains(&uri));
+        assert!(checked().await);
+        // the edit was never saved
+        assert_eq!("A", declared().await);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_composer_file() {
+        let (service, _) = LspService::new(|client| Backend::new(client, Arc::default()));
+        let backend = service.inner();
+        let uri = Url::parse("file:///project/composer.json").unwrap();
+
+        backend
+            .did_open(DidOpenTextDocumentParams {
+                text_document: TextDocumentItem {
+                    uri: uri.clone(),
+                    language_id: "json".to_string(),
+                    version: 1,
+                    text: "{ \"name\": \"acme/app\" }".to_string(),
+                },
+            })
+            .await;
+        backend
+            .did_change(DidChangeTextDocumentParams {
+                text_document: VersionedTextDocumentIdentifier {
+                    uri: uri.clone(),
+                    version: 2,
+                },
+                content_changes: vec![TextDocumentContentChangeEvent {
+                    range: Some(Range::new(Position::new(0, 21), Position::new(0, 23))),
+                    range_length: None,
+                    text: String::new(),
+                }],
+            })
+            .await;
+        // it isn't PHP, so it's not indexed
+        assert!(!backend.documents.contains(&uri));
+
+        let report = backend
+            .diagnostic(DocumentDiagnosticParams {
+                text_document: TextDocumentIdentifier { uri },
+                identifier: None,
+                previous_result_id: None,
+                work_done_progress_params: WorkDoneProgressParams::default(),
+                partial_result_params: PartialResultParams::default(),
+            })
+            .await
+            .unwrap();
+        let DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Full(report)) = report
+        else {
+            panic!("expected a full report");
+        };
+        let items = report.full_document_diagnostic_report.items;
+        assert_eq!(1, items.len());
         assert_eq!(
-            "?array $down",
-            actual_symbols[0].children.as_ref().unwrap()[2]
-                .children
-                .as_ref()
-                .unwrap()[1]
-                .detail
-                .as_ref()
-                .unwrap()
+            Some(NumberOrString::String("invalid-json".to_string())),
+            items[0].code
         );
-        assert_eq!(2, actual_symbols[1].children.as_ref().unwrap().len());
+    }
+
+    #[tokio::test]
+    async fn test_read_composer_file() {
+        let (service, _) = LspService::new(|client| Backend::new(client, Arc::default()));
+        let backend = service.inner();
+        let root = std::env::temp_dir().join("phplsp-test-read-composer-file");
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        let composer_file = root.join("composer.json");
+        std::fs::write(
+            &composer_file,
+            r#"{
+    "autoload": {
+        "psr-4": { "App\\": "src/" },
+        "psr-0": { "Legacy\\": "lib/" },
+        "files": ["helpers.php"]
+    }
+}"#,
+        )
+        .unwrap();
+
+        assert!(backend.read_composer_file(composer_file).await.is_ok());
+        let data_guard = backend.data.read().await;
+        let Ok(legacy) = "Legacy".parse::<PhpNamespace>();
         assert_eq!(
-            "private int $y = 3;",
-            actual_symbols[1].children.as_ref().unwrap()[0]
-                .detail
-                .as_ref()
-                .unwrap()
+            Some(&vec![root.join("lib/").join("Legacy")]),
+            data_guard.ns_to_dir.get(&legacy)
         );
+        assert_eq!(2, data_guard.ns_to_dir.len());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_snapshots_outlive_edits() {
+        let (service, _) = LspService::new(|client| Backend::new(client, Arc::default()));
+        let backend = service.inner();
+        let uri = Url::parse("file:///project/A.php").unwrap();
+
+        backend
+            .did_open(DidOpenTextDocumentParams {
+                text_document: TextDocumentItem {
+                    uri: uri.clone(),
+                    language_id: "php".to_string(),
+                    version: 1,
+                    text: "<?php class A {}".to_string(),
+                },
+            })
+            .await;
+        let before = backend.data.read().await.snapshot(&uri).unwrap();
+        let shared = backend.data.read().await.snapshot(&uri).unwrap();
+        assert!(Arc::ptr_eq(&before.contents, &shared.contents));
+
+        backend
+            .did_change(DidChangeTextDocumentParams {
+                text_document: VersionedTextDocumentIdentifier {
+                    uri: uri.clone(),
+                    version: 2,
+                },
+                content_changes: vec![TextDocumentContentChangeEvent {
+                    range: None,
+                    range_length: None,
+                    text: "<?php class B {}".to_string(),
+                }],
+            })
+            .await;
+        let after = backend.data.read().await.snapshot(&uri).unwrap();
+
+        assert_eq!("<?php class A {}", &*before.contents);
         assert_eq!(
-            SymbolKind::CONSTRUCTOR,
-            actual_symbols[1].children.as_ref().unwrap()[1].kind
+            "class_declaration",
+            before.root_node().child(1).unwrap().kind()
         );
+        assert_eq!("<?php class B {}", &*after.contents);
+    }
+
+    #[tokio::test]
+    async fn test_notebook_cells_share_scope() {
+        let (service, _) = LspService::new(|client| Backend::new(client, Arc::default()));
+        let backend = service.inner();
+        let cells = [
+            "$greeting = 'hello';\nfunction greet(string $name): string {}",
+            "echo greet($greeting);\n$",
+        ];
+        let uris: Vec<Url> = (0..cells.len())
+            .map(|i| Url::parse(&format!("vscode-notebook-cell:/walkthrough.ipynb#{}", i)).unwrap())
+            .collect();
+        backend
+            .notebook_did_open(DidOpenNotebookDocumentParams {
+                notebook_document: NotebookDocument {
+                    uri: Url::parse("file:///project/walkthrough.ipynb").unwrap(),
+                    version: 1,
+                    cells: uris
+                        .iter()
+                        .map(|uri| NotebookCell {
+                            document: uri.clone(),
+                        })
+                        .collect(),
+                },
+                cell_text_documents: uris
+                    .iter()
+                    .zip(cells)
+                    .map(|(uri, text)| TextDocumentItem {
+                        uri: uri.clone(),
+                        language_id: "php".to_string(),
+                        version: 1,
+                        text: text.to_string(),
+                    })
+                    .collect(),
+            })
+            .await;
+
+        let at = |line, character| TextDocumentPositionParams {
+            text_document: TextDocumentIdentifier {
+                uri: uris[1].clone(),
+            },
+            position: Position::new(line, character),
+        };
+        let Some(CompletionResponse::Array(items)) = backend
+            .completion(CompletionParams {
+                text_document_position: at(1, 1),
+                work_done_progress_params: WorkDoneProgressParams::default(),
+                partial_result_params: PartialResultParams::default(),
+                context: None,
+            })
+            .await
+            .unwrap()
+        else {
+            panic!("expected completions");
+        };
+        assert!(items.iter().any(|item| item.label == "$greeting"));
+
+        let hover = backend
+            .hover(HoverParams {
+                text_document_position_params: at(0, 6),
+                work_done_progress_params: WorkDoneProgressParams::default(),
+            })
+            .await
+            .unwrap();
+        let Some(HoverContents::Markup(markup)) = hover.map(|hover| hover.contents) else {
+            panic!("expected a hover");
+        };
+        assert!(markup
+            .value
+            .contains("function greet(string $name): string"));
+    }
+
+    #[test]
+    fn test_restricted_reads() {
+        let mut data = BackendData::new(Arc::default());
+        data.workspace_folders = vec![PathBuf::from("/project")];
+        let outside = Path::new("/project/../home/user/.ssh/id_rsa");
+        assert!(data.may_read(outside));
+
+        data.config.restricted = true;
+        assert!(data.may_read(Path::new("/project/src/A.php")));
+        assert!(data.may_read(&Path::new(STUBS_DIR).join("standard/basic.php")));
+        assert!(!data.may_read(outside));
     }
 }
@@ -0,0 +1,70 @@
+use tower_lsp::lsp_types::{TextDocumentContentChangeEvent, Url};
+
+use dashmap::DashMap;
+
+use std::sync::Arc;
+
+use phplsp_core::file::{LineIndex, PositionEncoding};
+
+use crate::documents::ChangeError;
+
+/**
+ * The `composer.json` files that the client has open. They aren't PHP, so they're kept apart from
+ * [`crate::documents::Documents`], as their text and version only.
+ */
+#[derive(Default)]
+pub struct ComposerFiles {
+    files: DashMap<Url, (Arc<str>, i32)>,
+}
+
+impl ComposerFiles {
+    pub fn open(&self, uri: Url, contents: String, version: i32) {
+        self.files.insert(uri, (contents.into(), version));
+    }
+
+    /// Apply changes sent with `textDocument/didChange`.
+    pub fn change(
+        &self,
+        uri: &Url,
+        version: i32,
+        changes: Vec<TextDocumentContentChangeEvent>,
+        encoding: PositionEncoding,
+    ) -> Result<(), ChangeError> {
+        let mut file = self.files.get_mut(uri).ok_or(ChangeError::NotOpen)?;
+        if file.1 >= version {
+            return Err(ChangeError::StaleVersion);
+        }
+
+        let mut contents = file.0.to_string();
+        for change in changes {
+            match change.range {
+                Some(range) => {
+                    let lines = LineIndex::new(contents.as_str());
+                    let start = lines.offset(range.start, encoding);
+                    let end = lines.offset(range.end, encoding).max(start);
+                    contents.replace_range(start..end, &change.text);
+                }
+                None => contents = change.text,
+            }
+        }
+        *file = (contents.into(), version);
+        Ok(())
+    }
+
+    /// Returns whether the file was open.
+    pub fn close(&self, uri: &Url) -> bool {
+        self.files.remove(uri).is_some()
+    }
+
+    pub fn contains(&self, uri: &Url) -> bool {
+        self.files.contains_key(uri)
+    }
+
+    pub fn contents(&self, uri: &Url) -> Option<Arc<str>> {
+        self.files.get(uri).map(|file| file.0.clone())
+    }
+
+    pub fn version(&self, uri: &Url) -> Option<i32> {
+        self.files.get(uri).map(|file| file.1)
+    }
+}
@@ -0,0 +1,312 @@
+use tower_lsp::lsp_types::{TextDocumentContentChangeEvent, Url};
+
+use tree_sitter::{InputEdit, Point};
+
+use dashmap::DashMap;
+
+use std::fmt;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use phplsp_core::file::{to_point, LineIndex, PositionEncoding, Snapshot};
+use phplsp_core::index::{source_memory, tree_memory, MemoryUsage};
+use phplsp_core::parsers::parsers;
+use phplsp_core::queries::Database;
+
+/// A file that the client has open.
+pub struct FileData {
+    pub snapshot: Snapshot,
+    pub version: i32,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ChangeError {
+    NotOpen,
+    /// The change is for a version we already have.
+    StaleVersion,
+}
+
+impl fmt::Display for ChangeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotOpen => write!(f, "file was changed without being opened"),
+            Self::StaleVersion => write!(f, "file was changed to a version we already have"),
+        }
+    }
+}
+
+/**
+ * Files that the client has open, and the analysis of them.
+ *
+ * Every file has its own lock, so that editing one file doesn't hold up requests on another.
+ * The lock is only held while a change is applied; requests clone the snapshot they need and
+ * work on that, so they never wait for an edit to be parsed.
+ */
+#[derive(Default)]
+pub struct Documents {
+    files: DashMap<Url, Arc<Mutex<FileData>>>,
+    queries: Database,
+    encoding: OnceLock<PositionEncoding>,
+}
+
+impl Documents {
+    pub fn open(&self, uri: Url, snapshot: Snapshot, version: i32) {
+        let file = Arc::new(Mutex::new(FileData {
+            snapshot: snapshot.clone(),
+            version,
+        }));
+        // changes that come in before we're done here wait for the new file
+        let _guard = file.lock().unwrap();
+        self.files.insert(uri.clone(), file.clone());
+        self.queries.set_text(uri, snapshot);
+    }
+
+    /// Use the encoding negotiated with the client for the positions of changes.
+    pub fn set_encoding(&self, encoding: PositionEncoding) {
+        let _ = self.encoding.set(encoding);
+    }
+
+    /// How the client counts the columns of positions.
+    pub fn encoding(&self) -> PositionEncoding {
+        self.encoding.get().copied().unwrap_or_default()
+    }
+
+    /// Apply changes sent with `textDocument/didChange`.
+    pub fn change(
+        &self,
+        uri: &Url,
+        version: i32,
+        changes: Vec<TextDocumentContentChangeEvent>,
+    ) -> Result<(), ChangeError> {
+        // don't hold on to the map while parsing, other files may be in the same shard
+        let file = self
+            .files
+            .get(uri)
+            .map(|file| file.clone())
+            .ok_or(ChangeError::NotOpen)?;
+        let mut file = file.lock().unwrap();
+        if file.version >= version {
+            return Err(ChangeError::StaleVersion);
+        }
+
+        // older snapshots may still be in use, so edit copies of them
+        let mut contents = file.snapshot.contents.to_string();
+        let mut tree = file.snapshot.tree.clone();
+        let mut parser = parsers().get();
+        for change in changes {
+            // parsing reuses what an edit leaves of the tree, unless it replaces everything
+            let reusable = change.range.is_some();
+            if let Some(r) = change.range {
+                let lines = LineIndex::new(contents.as_str());
+                let start_byte = lines.offset(r.start, self.encoding());
+                let end_byte = lines.offset(r.end, self.encoding()).max(start_byte);
+                let start = lines.decode(r.start, self.encoding());
+                let input_edit = InputEdit {
+                    start_byte,
+                    old_end_byte: end_byte,
+                    new_end_byte: start_byte + change.text.len(),
+                    start_position: to_point(&start),
+                    old_end_position: to_point(&lines.decode(r.end, self.encoding())),
+                    new_end_position: match change.text.rfind('\n') {
+                        Some(i) => Point {
+                            row: start.line as usize + change.text.matches('\n').count(),
+                            column: change.text.len() - i - 1,
+                        },
+                        None => Point {
+                            row: start.line as usize,
+                            column: start.character as usize + change.text.len(),
+                        },
+                    },
+                };
+                tree.edit(&input_edit);
+                contents.replace_range(start_byte..end_byte, &change.text);
+            } else {
+                contents = change.text;
+            }
+
+            // parsing only fails when cancelled or timed out, and we do neither
+            if let Some(new_tree) = parser.parse(&contents, reusable.then_some(&tree)) {
+                tree = new_tree;
+            }
+        }
+
+        file.version = version;
+        file.snapshot = Snapshot::new(contents, tree);
+        self.queries.set_text(uri.clone(), file.snapshot.clone());
+        Ok(())
+    }
+
+    /// Stop tracking a file sent with `textDocument/didClose`, returning whether it was open.
+    pub fn close(&self, uri: &Url) -> bool {
+        let Some((_, file)) = self.files.remove(uri) else {
+            return false;
+        };
+        // a change that's being applied would set the text again after we remove it
+        let _guard = file.lock().unwrap();
+        self.queries.remove_text(uri);
+        true
+    }
+
+    pub fn contains(&self, uri: &Url) -> bool {
+        self.files.contains_key(uri)
+    }
+
+    pub fn snapshot(&self, uri: &Url) -> Option<Snapshot> {
+        let file = self.files.get(uri)?.clone();
+        let snapshot = file.lock().unwrap().snapshot.clone();
+        Some(snapshot)
+    }
+
+    pub fn version(&self, uri: &Url) -> Option<i32> {
+        let file = self.files.get(uri)?.clone();
+        let version = file.lock().unwrap().version;
+        Some(version)
+    }
+
+    pub fn uris(&self) -> Vec<Url> {
+        self.files.iter().map(|file| file.key().clone()).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let mut usage = MemoryUsage::default();
+        for uri in self.uris() {
+            if let Some(snapshot) = self.snapshot(&uri) {
+                usage.add(
+                    source_memory(&snapshot.contents),
+                    tree_memory(&snapshot.tree),
+                    0,
+                );
+            }
+        }
+
+        usage
+    }
+
+    /// Memoized analysis of the open files.
+    pub fn queries(&self) -> &Database {
+        &self.queries
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tower_lsp::lsp_types::{Position, Range, TextDocumentContentChangeEvent, Url};
+
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::{ChangeError, Documents};
+    use phplsp_core::file::{php_parser, Snapshot};
+
+    fn replace_all(text: &str) -> Vec<TextDocumentContentChangeEvent> {
+        vec![TextDocumentContentChangeEvent {
+            range: None,
+            range_length: None,
+            text: text.to_string(),
+        }]
+    }
+
+    #[test]
+    fn test_change_versions() {
+        let documents = Documents::default();
+        let uri = Url::parse("file:///A.php").unwrap();
+        assert_eq!(
+            Err(ChangeError::NotOpen),
+            documents.change(&uri, 1, replace_all("<?php"))
+        );
+
+        let snapshot = Snapshot::parse(&mut php_parser(), "<?php class A {}").unwrap();
+        documents.open(uri.clone(), snapshot, 1);
+        assert_eq!(
+            Err(ChangeError::StaleVersion),
+            documents.change(&uri, 1, replace_all("<?php class B {}"))
+        );
+        assert_eq!(
+            Ok(()),
+            documents.change(&uri, 2, replace_all("<?php class B {}"))
+        );
+
+        assert_eq!(Some(2), documents.version(&uri));
+        assert_eq!("B", documents.queries().symbols(&uri).declarations[0].name);
+    }
+
+    #[test]
+    fn test_incremental_changes() {
+        let documents = Documents::default();
+        let uri = Url::parse("file:///A.php").unwrap();
+        let snapshot = Snapshot::parse(&mut php_parser(), "<?php\n$a = 'é';\n$b = 1;").unwrap();
+        documents.open(uri.clone(), snapshot, 1);
+
+        // UTF-16 columns, so `;` is 1 column after `é` and not 2
+        let change = |start: (u32, u32), end: (u32, u32), text: &str| {
+            vec![TextDocumentContentChangeEvent {
+                range: Some(Range {
+                    start: Position::new(start.0, start.1),
+                    end: Position::new(end.0, end.1),
+                }),
+                range_length: None,
+                text: text.to_string(),
+            }]
+        };
+        documents
+            .change(&uri, 2, change((1, 8), (1, 9), ";\nclass C {}"))
+            .unwrap();
+        documents
+            .change(&uri, 3, change((3, 6), (3, 6), "2"))
+            .unwrap();
+
+        let snapshot = documents.snapshot(&uri).unwrap();
+        assert_eq!(
+            "<?php\n$a = 'é';\nclass C {}\n$b = 12;",
+            &*snapshot.contents
+        );
+        // reusing the edited tree gives the same tree as parsing from scratch
+        let parsed = php_parser().parse(&*snapshot.contents, None).unwrap();
+        assert_eq!(
+            parsed.root_node().to_sexp(),
+            snapshot.tree.root_node().to_sexp()
+        );
+        assert_eq!("C", documents.queries().symbols(&uri).declarations[0].name);
+    }
+
+    #[test]
+    fn test_files_change_in_parallel() {
+        let documents = Arc::new(Documents::default());
+        let uris: Vec<Url> = (0..8)
+            .map(|i| Url::parse(&format!("file:///F{}.php", i)).unwrap())
+            .collect();
+        for uri in &uris {
+            let snapshot = Snapshot::parse(&mut php_parser(), "<?php").unwrap();
+            documents.open(uri.clone(), snapshot, 0);
+        }
+
+        let threads: Vec<_> = uris
+            .iter()
+            .cloned()
+            .enumerate()
+            .map(|(i, uri)| {
+                let documents = documents.clone();
+                thread::spawn(move || {
+                    for version in 1..=10 {
+                        let text = format!("<?php class C{}_{} {{}}", i, version);
+                        documents.change(&uri, version, replace_all(&text)).unwrap();
+                        documents.queries().symbols(&uri);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        for (i, uri) in uris.iter().enumerate() {
+            assert_eq!(
+                format!("C{}_10", i),
+                documents.queries().symbols(uri).declarations[0].name
+            );
+        }
+    }
+}
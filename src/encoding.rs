@@ -0,0 +1,354 @@
+//! Converting the positions we send and receive to and from how the client counts columns.
+
+use tower_lsp::lsp_types::*;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use phplsp_core::code_actions::QuickFix;
+use phplsp_core::file::{LineIndex, PositionEncoding};
+
+/// Looks up the contents of a file.
+type Contents<'a> = Box<dyn Fn(&Url) -> Option<Arc<str>> + 'a>;
+
+/**
+ * Converts positions in the files that a result is about, indexing the lines of each file the
+ * first time one of its positions comes up. Files it can't find the contents of are left alone.
+ */
+pub struct Encoder<'a> {
+    encoding: PositionEncoding,
+    contents: Contents<'a>,
+    lines: HashMap<Url, Option<LineIndex>>,
+}
+
+impl<'a> Encoder<'a> {
+    pub fn new(
+        encoding: PositionEncoding,
+        contents: impl Fn(&Url) -> Option<Arc<str>> + 'a,
+    ) -> Self {
+        Self {
+            encoding,
+            contents: Box::new(contents),
+            lines: HashMap::new(),
+        }
+    }
+
+    fn lines(&mut self, uri: &Url) -> Option<&LineIndex> {
+        let contents = &self.contents;
+        self.lines
+            .entry(uri.clone())
+            .or_insert_with(|| contents(uri).map(LineIndex::new))
+            .as_ref()
+    }
+
+    /// Convert a position in a file from counting bytes to counting in the client's encoding.
+    pub fn position(&mut self, uri: &Url, position: &mut Position) {
+        if self.encoding == PositionEncoding::Utf8 {
+            return;
+        }
+        let encoding = self.encoding;
+        if let Some(lines) = self.lines(uri) {
+            *position = lines.encode(*position, encoding);
+        }
+    }
+
+    pub fn range(&mut self, uri: &Url, range: &mut Range) {
+        self.position(uri, &mut range.start);
+        self.position(uri, &mut range.end);
+    }
+
+    /// Convert a position that the client sent to one that counts bytes.
+    pub fn decode(&mut self, uri: &Url, position: &Position) -> Position {
+        if self.encoding == PositionEncoding::Utf8 {
+            return *position;
+        }
+        let encoding = self.encoding;
+        match self.lines(uri) {
+            Some(lines) => lines.decode(*position, encoding),
+            None => *position,
+        }
+    }
+
+    pub fn decode_range(&mut self, uri: &Url, range: &Range) -> Range {
+        Range {
+            start: self.decode(uri, &range.start),
+            end: self.decode(uri, &range.end),
+        }
+    }
+
+    /**
+     * Convert semantic tokens, which are relative to each other, so they have to be made absolute
+     * to find the lines they're on first.
+     */
+    pub fn semantic_tokens(&mut self, uri: &Url, tokens: &mut [SemanticToken]) {
+        let (mut line, mut start) = (0, 0);
+        let (mut previous_line, mut previous_start) = (0, 0);
+        for token in tokens {
+            if token.delta_line > 0 {
+                start = 0;
+            }
+            line += token.delta_line;
+            start += token.delta_start;
+
+            let mut range = Range {
+                start: Position::new(line, start),
+                end: Position::new(line, start + token.length),
+            };
+            self.range(uri, &mut range);
+            token.delta_start = match line == previous_line {
+                true => range.start.character - previous_start,
+                false => range.start.character,
+            };
+            token.length = range.end.character - range.start.character;
+            (previous_line, previous_start) = (line, range.start.character);
+        }
+    }
+
+    /// Convert the edits of a workspace edit, in whichever files they are.
+    pub fn workspace_edit(&mut self, edit: &mut WorkspaceEdit) {
+        for (uri, edits) in edit.changes.iter_mut().flatten() {
+            edits.encode(uri, self);
+        }
+        let document_edits: Vec<&mut TextDocumentEdit> = match &mut edit.document_changes {
+            Some(DocumentChanges::Edits(edits)) => edits.iter_mut().collect(),
+            Some(DocumentChanges::Operations(operations)) => operations
+                .iter_mut()
+                .filter_map(|operation| match operation {
+                    DocumentChangeOperation::Edit(edit) => Some(edit),
+                    DocumentChangeOperation::Op(_) => None,
+                })
+                .collect(),
+            None => vec![],
+        };
+        for document_edit in document_edits {
+            let uri = &document_edit.text_document.uri;
+            for edit in &mut document_edit.edits {
+                match edit {
+                    OneOf::Left(edit) => edit.encode(uri, self),
+                    OneOf::Right(edit) => edit.text_edit.encode(uri, self),
+                }
+            }
+        }
+    }
+}
+
+/// Results with positions to convert, about the file they were asked for unless they say otherwise.
+pub trait Encode {
+    fn encode(&mut self, uri: &Url, encoder: &mut Encoder);
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode(&mut self, uri: &Url, encoder: &mut Encoder) {
+        if let Some(value) = self {
+            value.encode(uri, encoder);
+        }
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode(&mut self, uri: &Url, encoder: &mut Encoder) {
+        for value in self {
+            value.encode(uri, encoder);
+        }
+    }
+}
+
+impl Encode for Range {
+    fn encode(&mut self, uri: &Url, encoder: &mut Encoder) {
+        encoder.range(uri, self);
+    }
+}
+
+impl Encode for TextEdit {
+    fn encode(&mut self, uri: &Url, encoder: &mut Encoder) {
+        encoder.range(uri, &mut self.range);
+    }
+}
+
+impl Encode for Location {
+    fn encode(&mut self, _: &Url, encoder: &mut Encoder) {
+        encoder.range(&self.uri, &mut self.range);
+    }
+}
+
+/// The edits of quick fixes are converted too, so that they come back the way clients expect.
+impl Encode for Diagnostic {
+    fn encode(&mut self, uri: &Url, encoder: &mut Encoder) {
+        encoder.range(uri, &mut self.range);
+        for related in self.related_information.iter_mut().flatten() {
+            related.location.encode(uri, encoder);
+        }
+        if let Some(mut fix) = QuickFix::of(self) {
+            fix.edits.encode(uri, encoder);
+            fix.attach(self);
+        }
+    }
+}
+
+impl Encode for DocumentSymbol {
+    fn encode(&mut self, uri: &Url, encoder: &mut Encoder) {
+        encoder.range(uri, &mut self.range);
+        encoder.range(uri, &mut self.selection_range);
+        self.children.encode(uri, encoder);
+    }
+}
+
+impl Encode for SelectionRange {
+    fn encode(&mut self, uri: &Url, encoder: &mut Encoder) {
+        encoder.range(uri, &mut self.range);
+        if let Some(parent) = &mut self.parent {
+            parent.encode(uri, encoder);
+        }
+    }
+}
+
+impl Encode for Hover {
+    fn encode(&mut self, uri: &Url, encoder: &mut Encoder) {
+        self.range.encode(uri, encoder);
+    }
+}
+
+impl Encode for CompletionItem {
+    fn encode(&mut self, uri: &Url, encoder: &mut Encoder) {
+        match &mut self.text_edit {
+            Some(CompletionTextEdit::Edit(edit)) => edit.encode(uri, encoder),
+            Some(CompletionTextEdit::InsertAndReplace(edit)) => {
+                encoder.range(uri, &mut edit.insert);
+                encoder.range(uri, &mut edit.replace);
+            }
+            None => {}
+        }
+        self.additional_text_edits.encode(uri, encoder);
+    }
+}
+
+impl Encode for InlayHint {
+    fn encode(&mut self, uri: &Url, encoder: &mut Encoder) {
+        encoder.position(uri, &mut self.position);
+        self.text_edits.encode(uri, encoder);
+    }
+}
+
+impl Encode for CodeLens {
+    fn encode(&mut self, uri: &Url, encoder: &mut Encoder) {
+        encoder.range(uri, &mut self.range);
+    }
+}
+
+impl Encode for DocumentLink {
+    fn encode(&mut self, uri: &Url, encoder: &mut Encoder) {
+        encoder.range(uri, &mut self.range);
+    }
+}
+
+impl Encode for LinkedEditingRanges {
+    fn encode(&mut self, uri: &Url, encoder: &mut Encoder) {
+        self.ranges.encode(uri, encoder);
+    }
+}
+
+impl Encode for PrepareRenameResponse {
+    fn encode(&mut self, uri: &Url, encoder: &mut Encoder) {
+        match self {
+            Self::Range(range) | Self::RangeWithPlaceholder { range, .. } => {
+                encoder.range(uri, range)
+            }
+            Self::DefaultBehavior { .. } => {}
+        }
+    }
+}
+
+impl Encode for CallHierarchyItem {
+    fn encode(&mut self, _: &Url, encoder: &mut Encoder) {
+        encoder.range(&self.uri, &mut self.range);
+        encoder.range(&self.uri, &mut self.selection_range);
+    }
+}
+
+/// The calls are in the file of the caller.
+impl Encode for CallHierarchyIncomingCall {
+    fn encode(&mut self, uri: &Url, encoder: &mut Encoder) {
+        self.from.encode(uri, encoder);
+        self.from_ranges.encode(&self.from.uri, encoder);
+    }
+}
+
+/// The calls are in the file of the item that was asked about.
+impl Encode for CallHierarchyOutgoingCall {
+    fn encode(&mut self, uri: &Url, encoder: &mut Encoder) {
+        self.to.encode(uri, encoder);
+        self.from_ranges.encode(uri, encoder);
+    }
+}
+
+impl Encode for TypeHierarchyItem {
+    fn encode(&mut self, _: &Url, encoder: &mut Encoder) {
+        encoder.range(&self.uri, &mut self.range);
+        encoder.range(&self.uri, &mut self.selection_range);
+    }
+}
+
+/// Workspace edits say which file each of their edits is in.
+impl Encode for WorkspaceEdit {
+    fn encode(&mut self, _: &Url, encoder: &mut Encoder) {
+        encoder.workspace_edit(self);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tower_lsp::lsp_types::*;
+
+    use super::{Encode, Encoder};
+    use phplsp_core::file::PositionEncoding;
+
+    #[test]
+    fn test_encode() {
+        let a = Url::parse("file:///a.php").unwrap();
+        let b = Url::parse("file:///b.php").unwrap();
+        let mut encoder = Encoder::new(PositionEncoding::Utf16, |uri| match uri.path() {
+            "/a.php" => Some("<?php\n$é = 'é';".into()),
+            _ => None,
+        });
+        let range = |line, start, end| Range {
+            start: Position::new(line, start),
+            end: Position::new(line, end),
+        };
+
+        let mut locations = vec![
+            Location::new(a.clone(), range(1, 6, 10)),
+            Location::new(b.clone(), range(1, 6, 10)),
+        ];
+        locations.encode(&a, &mut encoder);
+        assert_eq!(range(1, 5, 8), locations[0].range);
+        assert_eq!(range(1, 6, 10), locations[1].range);
+        assert_eq!(
+            Position::new(1, 6),
+            encoder.decode(&a, &Position::new(1, 5))
+        );
+
+        // `$é` and `'é'`, with lengths in bytes
+        let mut tokens = vec![
+            SemanticToken {
+                delta_line: 1,
+                delta_start: 0,
+                length: 3,
+                token_type: 0,
+                token_modifiers_bitset: 0,
+            },
+            SemanticToken {
+                delta_line: 0,
+                delta_start: 6,
+                length: 4,
+                token_type: 0,
+                token_modifiers_bitset: 0,
+            },
+        ];
+        encoder.semantic_tokens(&a, &mut tokens);
+        let columns: Vec<(u32, u32)> = tokens
+            .iter()
+            .map(|token| (token.delta_start, token.length))
+            .collect();
+        assert_eq!(vec![(0, 2), (5, 3)], columns);
+    }
+}
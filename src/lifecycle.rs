@@ -0,0 +1,87 @@
+use tokio::task::JoinHandle;
+use tokio::time::{self, Instant};
+
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The longest we wait for background work and for state to be saved when shutting down.
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/**
+ * Whether the client asked us to shut down, and the work that carries on after the request that
+ * started it, like checking the workspace.
+ *
+ * Shutting down cancels that work: it should stop as soon as it sees [`Lifecycle::is_shut_down`],
+ * and whatever hasn't stopped by the timeout is aborted.
+ */
+#[derive(Default)]
+pub struct Lifecycle {
+    shut_down: AtomicBool,
+    tasks: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl Lifecycle {
+    pub fn is_shut_down(&self) -> bool {
+        self.shut_down.load(Ordering::Relaxed)
+    }
+
+    /// Run work in the background, unless we're shutting down.
+    pub fn spawn(&self, work: impl Future<Output = ()> + Send + 'static) {
+        if self.is_shut_down() {
+            return;
+        }
+        let mut tasks = self.tasks.lock().unwrap();
+        tasks.retain(|task| !task.is_finished());
+        tasks.push(tokio::spawn(work));
+    }
+
+    /**
+     * Cancel background work and wait for it to stop, for at most `timeout`. Returns whether it
+     * all stopped in time; what didn't is aborted.
+     */
+    pub async fn shut_down(&self, timeout: Duration) -> bool {
+        self.shut_down.store(true, Ordering::Relaxed);
+        let tasks = std::mem::take(&mut *self.tasks.lock().unwrap());
+        let deadline = Instant::now() + timeout;
+        let mut stopped = true;
+        for mut task in tasks {
+            if time::timeout_at(deadline, &mut task).await.is_err() {
+                task.abort();
+                stopped = false;
+            }
+        }
+
+        stopped
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::Lifecycle;
+
+    #[tokio::test]
+    async fn test_shut_down() {
+        let lifecycle = Arc::new(Lifecycle::default());
+        let cancelled = lifecycle.clone();
+        lifecycle.spawn(async move {
+            while !cancelled.is_shut_down() {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        });
+        lifecycle.spawn(async {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+        });
+
+        assert!(!lifecycle.is_shut_down());
+        assert!(!lifecycle.shut_down(Duration::from_millis(50)).await);
+        assert!(lifecycle.is_shut_down());
+        // nothing starts once we're shutting down
+        lifecycle.spawn(async { unreachable!() });
+        assert!(lifecycle.shut_down(Duration::ZERO).await);
+    }
+}
@@ -1,21 +1,63 @@
 use std::env;
+use std::process::ExitCode;
+use std::sync::Arc;
 
+use tokio::runtime::Runtime;
 use tower_lsp::{LspService, Server};
 
+mod analyze;
 mod backend;
-mod php_namespace;
+mod composer_files;
+mod documents;
+mod encoding;
+mod lifecycle;
+mod notebooks;
 
-#[tokio::main]
-async fn main() {
-    if let Some(first_arg) = env::args().nth(1) {
-        if &first_arg == "--version" {
-            println!("PHP LSP version {}", env!("CARGO_PKG_VERSION"));
-            return;
-        }
-    }
+use lifecycle::{Lifecycle, SHUTDOWN_TIMEOUT};
 
+async fn serve(lifecycle: Arc<Lifecycle>) {
     let stdin = tokio::io::stdin();
     let stdout = tokio::io::stdout();
-    let (service, socket) = LspService::new(backend::Backend::new);
+    let (service, socket) = LspService::build(|client| backend::Backend::new(client, lifecycle))
+        .custom_method("phplsp/status", backend::Backend::status)
+        .custom_method(
+            "notebookDocument/didOpen",
+            backend::Backend::notebook_did_open,
+        )
+        .custom_method(
+            "notebookDocument/didChange",
+            backend::Backend::notebook_did_change,
+        )
+        .custom_method(
+            "notebookDocument/didClose",
+            backend::Backend::notebook_did_close,
+        )
+        .finish();
+    // returns once the client sends `exit`, or goes away
     Server::new(stdin, stdout, socket).serve(service).await;
 }
+
+fn main() -> ExitCode {
+    match env::args().nth(1).as_deref() {
+        Some("--version") => {
+            println!("PHP LSP version {}", env!("CARGO_PKG_VERSION"));
+            return ExitCode::SUCCESS;
+        }
+        Some("analyze") => return analyze::run(env::args().skip(2)),
+        Some("--generate-baseline") => return analyze::generate_baseline(env::args().skip(2)),
+        _ => {}
+    }
+
+    let runtime = Runtime::new().expect("could not start the async runtime");
+    let lifecycle = Arc::new(Lifecycle::default());
+    runtime.block_on(serve(lifecycle.clone()));
+    // indexing can't be cancelled halfway through a folder, so don't wait for it for long
+    runtime.shutdown_timeout(SHUTDOWN_TIMEOUT);
+
+    // exiting without being asked to shut down first is an error, as the protocol says
+    if lifecycle.is_shut_down() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
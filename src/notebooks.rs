@@ -0,0 +1,369 @@
+//! Notebooks whose cells are PHP, analyzed as one file so that later cells see what earlier ones
+//! declare and assign, like a kernel running them in order would.
+//!
+//! `lsp-types` has nothing for notebooks yet, so the parameters of their notifications are
+//! declared here, with only the parts we use.
+
+use tower_lsp::lsp_types::*;
+
+use serde::Deserialize;
+
+use std::collections::HashMap;
+use std::ops::Range as Span;
+use std::sync::Mutex;
+
+use phplsp_core::file::{LineIndex, PositionEncoding};
+
+/// What the file of a notebook starts with, on a line of its own, since cells usually don't.
+const OPEN_TAG: &str = "<?php";
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotebookCell {
+    pub document: Url,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotebookDocument {
+    pub uri: Url,
+    pub version: i32,
+    pub cells: Vec<NotebookCell>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DidOpenNotebookDocumentParams {
+    pub notebook_document: NotebookDocument,
+    pub cell_text_documents: Vec<TextDocumentItem>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotebookCellArrayChange {
+    pub start: usize,
+    pub delete_count: usize,
+    pub cells: Option<Vec<NotebookCell>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotebookCellStructure {
+    pub array: NotebookCellArrayChange,
+    pub did_open: Option<Vec<TextDocumentItem>>,
+    pub did_close: Option<Vec<TextDocumentIdentifier>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotebookCellTextContent {
+    pub document: VersionedTextDocumentIdentifier,
+    pub changes: Vec<TextDocumentContentChangeEvent>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotebookCellsChange {
+    pub structure: Option<NotebookCellStructure>,
+    pub text_content: Option<Vec<NotebookCellTextContent>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotebookDocumentChangeEvent {
+    pub cells: Option<NotebookCellsChange>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VersionedNotebookDocumentIdentifier {
+    pub uri: Url,
+    pub version: i32,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DidChangeNotebookDocumentParams {
+    pub notebook_document: VersionedNotebookDocumentIdentifier,
+    pub change: NotebookDocumentChangeEvent,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotebookDocumentIdentifier {
+    pub uri: Url,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DidCloseNotebookDocumentParams {
+    pub notebook_document: NotebookDocumentIdentifier,
+}
+
+/// A notebook's cells in order, and the text of those that are PHP. Markup cells have none.
+#[derive(Debug, Default)]
+struct Notebook {
+    cells: Vec<Url>,
+    texts: HashMap<Url, String>,
+}
+
+impl Notebook {
+    fn open_cells(&mut self, items: Vec<TextDocumentItem>) {
+        for item in items {
+            if item.language_id == "php" {
+                self.texts.insert(item.uri, item.text);
+            }
+        }
+    }
+
+    /// The cells with text, and the lines of the notebook's file that each of them takes.
+    fn spans(&self) -> Vec<(&Url, &str, Span<u32>)> {
+        let mut start = 1;
+        let mut spans = vec![];
+        for cell in &self.cells {
+            let Some(text) = self.texts.get(cell) else {
+                continue;
+            };
+            let end = start + text.split('\n').count() as u32;
+            spans.push((cell, text.as_str(), start..end));
+            start = end;
+        }
+        spans
+    }
+
+    /**
+     * The file that the notebook's cells make up. Every cell starts on a line of its own, and an
+     * opening tag at the start of one is blanked out, so positions in the file are positions in
+     * a cell moved down some lines.
+     */
+    fn contents(&self) -> String {
+        let mut contents = format!("{}\n", OPEN_TAG);
+        for (_, text, _) in self.spans() {
+            match text.strip_prefix(OPEN_TAG) {
+                Some(rest) => {
+                    contents.push_str(&" ".repeat(OPEN_TAG.len()));
+                    contents.push_str(rest);
+                }
+                None => contents.push_str(text),
+            }
+            contents.push('\n');
+        }
+        contents
+    }
+}
+
+/**
+ * The notebooks that the client has open. Each of them is opened as a file of its own in
+ * [`crate::documents::Documents`], under the notebook's URI, and its cells are mapped to lines of
+ * that file.
+ */
+#[derive(Default)]
+pub struct Notebooks {
+    notebooks: Mutex<HashMap<Url, Notebook>>,
+}
+
+impl Notebooks {
+    /// Open a notebook, returning the contents of its file.
+    pub fn open(&self, params: DidOpenNotebookDocumentParams) -> String {
+        let mut notebook = Notebook {
+            cells: params
+                .notebook_document
+                .cells
+                .into_iter()
+                .map(|cell| cell.document)
+                .collect(),
+            texts: HashMap::new(),
+        };
+        notebook.open_cells(params.cell_text_documents);
+        let contents = notebook.contents();
+        self.notebooks
+            .lock()
+            .unwrap()
+            .insert(params.notebook_document.uri, notebook);
+        contents
+    }
+
+    /// Apply changes to the cells of a notebook, returning the new contents of its file.
+    pub fn change(
+        &self,
+        uri: &Url,
+        change: NotebookDocumentChangeEvent,
+        encoding: PositionEncoding,
+    ) -> Option<String> {
+        let mut notebooks = self.notebooks.lock().unwrap();
+        let notebook = notebooks.get_mut(uri)?;
+        let Some(cells) = change.cells else {
+            return Some(notebook.contents());
+        };
+
+        if let Some(structure) = cells.structure {
+            let array = structure.array;
+            let start = array.start.min(notebook.cells.len());
+            let end = (start + array.delete_count).min(notebook.cells.len());
+            let inserted = array
+                .cells
+                .unwrap_or_default()
+                .into_iter()
+                .map(|cell| cell.document);
+            notebook.cells.splice(start..end, inserted);
+            for closed in structure.did_close.unwrap_or_default() {
+                notebook.texts.remove(&closed.uri);
+            }
+            notebook.open_cells(structure.did_open.unwrap_or_default());
+        }
+
+        for content in cells.text_content.unwrap_or_default() {
+            let Some(text) = notebook.texts.get_mut(&content.document.uri) else {
+                continue;
+            };
+            for change in content.changes {
+                match change.range {
+                    Some(range) => {
+                        let lines = LineIndex::new(text.as_str());
+                        let start = lines.offset(range.start, encoding);
+                        let end = lines.offset(range.end, encoding).max(start);
+                        text.replace_range(start..end, &change.text);
+                    }
+                    None => *text = change.text,
+                }
+            }
+        }
+
+        Some(notebook.contents())
+    }
+
+    /// Close a notebook, returning its cells.
+    pub fn close(&self, uri: &Url) -> Vec<Url> {
+        self.notebooks
+            .lock()
+            .unwrap()
+            .remove(uri)
+            .map(|notebook| notebook.texts.into_keys().collect())
+            .unwrap_or_default()
+    }
+
+    /// The notebook that a cell is in, and the lines of the notebook's file that the cell takes.
+    pub fn locate(&self, cell: &Url) -> Option<(Url, Span<u32>)> {
+        let notebooks = self.notebooks.lock().unwrap();
+        notebooks.iter().find_map(|(uri, notebook)| {
+            notebook
+                .spans()
+                .into_iter()
+                .find(|(uri, ..)| *uri == cell)
+                .map(|(_, _, lines)| (uri.clone(), lines))
+        })
+    }
+
+    /// The cells of a notebook, with the lines of the notebook's file that each of them takes.
+    pub fn cells(&self, uri: &Url) -> Option<Vec<(Url, Span<u32>)>> {
+        let notebooks = self.notebooks.lock().unwrap();
+        let notebook = notebooks.get(uri)?;
+        Some(
+            notebook
+                .spans()
+                .into_iter()
+                .map(|(cell, _, lines)| (cell.clone(), lines))
+                .collect(),
+        )
+    }
+}
+
+/// The diagnostics of a notebook's file that are in a cell, moved to where they are in the cell.
+pub fn cell_diagnostics(diagnostics: &[Diagnostic], lines: &Span<u32>) -> Vec<Diagnostic> {
+    diagnostics
+        .iter()
+        .filter(|diagnostic| lines.contains(&diagnostic.range.start.line))
+        .map(|diagnostic| {
+            let mut diagnostic = diagnostic.clone();
+            diagnostic.range.start.line -= lines.start;
+            diagnostic.range.end.line = diagnostic.range.end.line.saturating_sub(lines.start);
+            diagnostic
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use tower_lsp::lsp_types::*;
+
+    use super::*;
+
+    fn cell(uri: &str, text: &str) -> (NotebookCell, TextDocumentItem) {
+        let uri = Url::parse(uri).unwrap();
+        (
+            NotebookCell {
+                document: uri.clone(),
+            },
+            TextDocumentItem {
+                uri,
+                language_id: "php".to_string(),
+                version: 1,
+                text: text.to_string(),
+            },
+        )
+    }
+
+    #[test]
+    fn test_cells() {
+        let notebooks = Notebooks::default();
+        let uri = Url::parse("file:///walkthrough.ipynb").unwrap();
+        let (first, first_text) = cell("cell:///1", "<?php $a = 1;\n$b = 2;");
+        let (second, second_text) = cell("cell:///2", "echo $a;");
+        let contents = notebooks.open(DidOpenNotebookDocumentParams {
+            notebook_document: NotebookDocument {
+                uri: uri.clone(),
+                version: 1,
+                cells: vec![first, second],
+            },
+            cell_text_documents: vec![first_text, second_text],
+        });
+        assert_eq!("<?php\n      $a = 1;\n$b = 2;\necho $a;\n", contents);
+        assert_eq!(
+            Some((uri.clone(), 3..4)),
+            notebooks.locate(&Url::parse("cell:///2").unwrap())
+        );
+
+        // a cell is added between the two, and the first one is edited
+        let (inserted, inserted_text) = cell("cell:///3", "$c = 3;");
+        let contents = notebooks.change(
+            &uri,
+            NotebookDocumentChangeEvent {
+                cells: Some(NotebookCellsChange {
+                    structure: Some(NotebookCellStructure {
+                        array: NotebookCellArrayChange {
+                            start: 1,
+                            delete_count: 0,
+                            cells: Some(vec![inserted]),
+                        },
+                        did_open: Some(vec![inserted_text]),
+                        did_close: None,
+                    }),
+                    text_content: Some(vec![NotebookCellTextContent {
+                        document: VersionedTextDocumentIdentifier {
+                            uri: Url::parse("cell:///1").unwrap(),
+                            version: 2,
+                        },
+                        changes: vec![TextDocumentContentChangeEvent {
+                            range: Some(Range::new(Position::new(1, 0), Position::new(1, 8))),
+                            range_length: None,
+                            text: String::new(),
+                        }],
+                    }]),
+                }),
+            },
+            PositionEncoding::Utf16,
+        );
+        assert_eq!(
+            Some("<?php\n      $a = 1;\n\n$c = 3;\necho $a;\n".to_string()),
+            contents
+        );
+        assert_eq!(
+            Some(vec![
+                (Url::parse("cell:///1").unwrap(), 1..3),
+                (Url::parse("cell:///3").unwrap(), 3..4),
+                (Url::parse("cell:///2").unwrap(), 4..5),
+            ]),
+            notebooks.cells(&uri)
+        );
+    }
+}